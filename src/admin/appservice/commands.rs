@@ -1,3 +1,6 @@
+use std::{fmt::Write, time::SystemTime};
+
+use conduwuit::utils::time;
 use ruma::{api::appservice::Registration, events::room::message::RoomMessageEventContent};
 
 use crate::{Result, admin_command};
@@ -82,3 +85,98 @@ pub(super) async fn list_registered(&self) -> Result<RoomMessageEventContent> {
 	let output = format!("Appservices ({}): {}", appservices.len(), appservices.join(", "));
 	Ok(RoomMessageEventContent::text_plain(output))
 }
+
+#[admin_command]
+pub(super) async fn status(&self) -> Result<RoomMessageEventContent> {
+	let health = self.services.appservice.health();
+	let appservices = self.services.appservice.iter_ids().await;
+	let ago = |ts: Option<SystemTime>| {
+		ts.and_then(|ts| ts.elapsed().ok())
+			.map_or_else(|| "never".to_owned(), |d| format!("{} ago", time::pretty(d)))
+	};
+
+	let mut out = String::new();
+	writeln!(out, "| Appservice | Status | Latency | Last check | Last success |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- |")?;
+	for id in &appservices {
+		let Some(health) = health.get(id) else {
+			writeln!(out, "| {id} | unknown | | never | never |")?;
+			continue;
+		};
+
+		let status = match &health.error {
+			| None => "healthy".to_owned(),
+			| Some(e) => format!("failing ({}x): {e}", health.failures),
+		};
+
+		let latency = health.latency.map(time::pretty).unwrap_or_default();
+
+		writeln!(
+			out,
+			"| {id} | {status} | {latency} | {} | {} |",
+			ago(health.last_check),
+			ago(health.last_success),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn reload(&self, dry_run: bool) -> Result<RoomMessageEventContent> {
+	let report = self
+		.services
+		.appservice
+		.reload_registrations(dry_run)
+		.await?;
+
+	let mut out = if dry_run {
+		"Appservice registrations which would be reloaded:\n".to_owned()
+	} else {
+		"Reloaded appservice registrations:\n".to_owned()
+	};
+
+	for (label, ids) in [
+		("Added", &report.added),
+		("Changed", &report.changed),
+		("Removed", &report.removed),
+		("Unchanged", &report.unchanged),
+	] {
+		writeln!(out, "{label} ({}): {}", ids.len(), ids.join(", "))?;
+	}
+
+	for (label, problems) in [("Errors", &report.errors), ("Warnings", &report.warnings)] {
+		if !problems.is_empty() {
+			writeln!(out, "\n{label}:")?;
+			for problem in problems {
+				writeln!(out, "- {problem}")?;
+			}
+		}
+	}
+
+	Ok(RoomMessageEventContent::text_plain(out))
+}
+
+#[admin_command]
+pub(super) async fn ping_appservice(
+	&self,
+	appservice_identifier: String,
+) -> Result<RoomMessageEventContent> {
+	let Some(registration) = self
+		.services
+		.appservice
+		.get_registration(&appservice_identifier)
+		.await
+	else {
+		return Ok(RoomMessageEventContent::text_plain("Appservice does not exist."));
+	};
+
+	match self.services.appservice.ping(&registration, None).await {
+		| Ok(latency) => Ok(RoomMessageEventContent::text_plain(format!(
+			"Appservice responded in {}",
+			time::pretty(latency)
+		))),
+		| Err(e) =>
+			Ok(RoomMessageEventContent::text_plain(format!("Failed to ping appservice: {e}"))),
+	}
+}
@@ -37,4 +37,28 @@ pub(super) enum AppserviceCommand {
 	/// - List all the currently registered appservices
 	#[clap(alias("list"))]
 	ListRegistered,
+
+	/// - Show the connectivity status of all registered appservices
+	///
+	/// Appservices are pinged periodically according to the
+	/// `appservice_health_interval` config option.
+	Status,
+
+	/// - Reload appservice registrations from `appservice_registration_dir`
+	///
+	/// Registrations in the directory are linted, and appservices are added,
+	/// updated or unregistered to match it without a restart. Appservices
+	/// registered with `register` are left alone.
+	Reload {
+		/// Only show what would change
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Ping an appservice using its ID and show the round-trip time
+	#[clap(alias("ping"))]
+	PingAppservice {
+		/// The appservice to ping
+		appservice_identifier: String,
+	},
 }
@@ -973,6 +973,23 @@ pub(super) async fn database_stats(
 	Ok(RoomMessageEventContent::notice_plain(""))
 }
 
+#[admin_command]
+pub(super) async fn database_slow(&self) -> Result<RoomMessageEventContent> {
+	let slow = self.services.db.db.slow_operations();
+	if slow.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No slow database operations."));
+	}
+
+	self.write_str("| column | slow operations |\n| --- | --- |\n")
+		.await?;
+
+	for (column, count) in slow {
+		writeln!(self, "| {column} | {count} |").await?;
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(""))
+}
+
 #[admin_command]
 pub(super) async fn database_files(
 	&self,
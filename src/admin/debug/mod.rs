@@ -223,6 +223,11 @@ pub(super) enum DebugCommand {
 		map: Option<String>,
 	},
 
+	/// - Count of slow database operations on each column since startup
+	///
+	/// Operations are slow when they take longer than `db_slow_query_ms`.
+	DatabaseSlow,
+
 	/// - Trim memory usage
 	TrimMemory,
 
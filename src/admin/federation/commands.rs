@@ -1,6 +1,6 @@
-use std::fmt::Write;
+use std::{fmt::Write, time::Instant};
 
-use conduwuit::Result;
+use conduwuit::{Result, utils::time::pretty};
 use futures::StreamExt;
 use ruma::{
 	OwnedRoomId, RoomId, ServerName, UserId, events::room::message::RoomMessageEventContent,
@@ -133,3 +133,45 @@ pub(super) async fn remote_user_in_rooms(
 
 	Ok(RoomMessageEventContent::text_markdown(output))
 }
+
+#[admin_command]
+pub(super) async fn health(&self, limit: usize) -> Result<RoomMessageEventContent> {
+	let health = self.services.sending.health();
+	if health.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No transactions sent yet."));
+	}
+
+	self.write_str(
+		"| server | latency | sent | failed | failure rate | consecutive failures | backlog age \
+		 | last error |\n| --- | --- | --- | --- | --- | --- | --- | --- |\n",
+	)
+	.await?;
+
+	for (server, health) in health.iter().take(limit) {
+		let backlog = health
+			.backlog_since
+			.as_ref()
+			.map(Instant::elapsed)
+			.map(pretty)
+			.unwrap_or_default();
+
+		let last_error = health
+			.last_error
+			.as_deref()
+			.unwrap_or_default()
+			.replace(['|', '\n'], " ");
+
+		writeln!(
+			self,
+			"| {server} | {:?} | {} | {} | {}% | {} | {backlog} | {last_error} |",
+			health.latency,
+			health.sent,
+			health.failed,
+			health.failure_rate(),
+			health.consecutive_failures,
+		)
+		.await?;
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(""))
+}
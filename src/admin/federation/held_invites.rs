@@ -0,0 +1,66 @@
+use std::fmt::Write;
+
+use clap::Subcommand;
+use conduwuit::Result;
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum HeldInvitesCommand {
+	/// - List invites from strangers held back from their recipients
+	List,
+
+	/// - Deliver a held invite to its recipient
+	Release {
+		/// ID of the held invite
+		id: u64,
+	},
+
+	/// - Drop a held invite without delivering it
+	Discard {
+		/// ID of the held invite
+		id: u64,
+	},
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let invites: Vec<_> = self.services.strangers.held().collect().await;
+	if invites.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No invites are held."));
+	}
+
+	let mut out = format!("Held invites ({}):\n", invites.len());
+	for (id, invite) in &invites {
+		writeln!(
+			out,
+			"- {id}: {} invited {} into {}",
+			invite.sender, invite.recipient, invite.room_id
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn release(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let invite = self.services.strangers.release(id).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Delivered the invite from {} to {} into {}.",
+		invite.sender, invite.recipient, invite.room_id
+	)))
+}
+
+#[admin_command]
+async fn discard(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let invite = self.services.strangers.discard(id).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Discarded the invite from {} to {} into {}.",
+		invite.sender, invite.recipient, invite.room_id
+	)))
+}
@@ -1,9 +1,11 @@
 mod commands;
+mod held_invites;
 
 use clap::Subcommand;
 use conduwuit::Result;
 use ruma::{RoomId, ServerName, UserId};
 
+use self::held_invites::HeldInvitesCommand;
 use crate::admin_command_dispatch;
 
 #[admin_command_dispatch]
@@ -39,4 +41,18 @@ pub(super) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - Delivery health of the servers we send to, worst first
+	///
+	/// Shows the average time taken by a transaction, the failure rate, the
+	/// failures since the last delivery and how long the oldest undelivered
+	/// event has been waiting, since startup.
+	Health {
+		#[arg(short, long, default_value("20"))]
+		limit: usize,
+	},
+
+	#[command(subcommand)]
+	/// - Invites from strangers held back for review
+	HeldInvites(HeldInvitesCommand),
 }
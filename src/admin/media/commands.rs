@@ -1,7 +1,8 @@
 use std::time::Duration;
 
 use conduwuit::{
-	Result, debug, debug_info, debug_warn, error, info, trace, utils::time::parse_timepoint_ago,
+	Result, debug, debug_info, debug_warn, error, info, trace,
+	utils::{bytes, time::parse_timepoint_ago},
 };
 use conduwuit_service::media::Dim;
 use ruma::{
@@ -397,3 +398,53 @@ pub(super) async fn get_remote_thumbnail(
 	let out = format!("```\n{result:#?}\nreceived {len} bytes for file content.\n```");
 	Ok(RoomMessageEventContent::notice_markdown(out))
 }
+
+#[admin_command]
+pub(super) async fn dedup_report(&self) -> Result<RoomMessageEventContent> {
+	let report = self.services.media.dedup_report().await?;
+	let size = |len: u64| bytes::pretty(usize::try_from(len).unwrap_or(usize::MAX));
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Media records: {} ({} files missing)\nDistinct contents: {}\nRecords sharing their \
+		 content with another record: {}\nStored size: {}\nStoring identical content once would \
+		 save: {}",
+		report.records,
+		report.missing,
+		report.contents,
+		report.shared_records,
+		size(report.total_size),
+		size(report.duplicate_size),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn gc(&self, dry_run: bool) -> Result<RoomMessageEventContent> {
+	let (deleted, size) = self.services.media.delete_orphaned_files(dry_run).await?;
+
+	let size = bytes::pretty(usize::try_from(size).unwrap_or(usize::MAX));
+	let message = if dry_run {
+		format!("{deleted} orphaned media files of {size} would be deleted.")
+	} else {
+		format!("Deleted {deleted} orphaned media files of {size}.")
+	};
+
+	Ok(RoomMessageEventContent::text_plain(message))
+}
+
+#[admin_command]
+pub(super) async fn migrate_to_s3(&self) -> Result<RoomMessageEventContent> {
+	let moved = self.services.media.migrate_to_s3().await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Moved {moved} media files to object storage."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn pregenerate_thumbnails(&self) -> Result<RoomMessageEventContent> {
+	let generated = self.services.media.pregenerate_all_thumbnails().await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Generated {generated} thumbnails of local media."
+	)))
+}
@@ -1,10 +1,17 @@
 #![allow(rustdoc::broken_intra_doc_links)]
 mod commands;
+mod remote_policy;
+mod retention;
+mod url_preview;
 
 use clap::Subcommand;
 use conduwuit::Result;
 use ruma::{EventId, MxcUri, OwnedMxcUri, OwnedServerName, ServerName};
 
+use self::{
+	remote_policy::RemotePolicyCommand, retention::RetentionCommand,
+	url_preview::UrlPreviewCommand,
+};
 use crate::admin_command_dispatch;
 
 #[admin_command_dispatch]
@@ -96,4 +103,38 @@ pub(super) enum MediaCommand {
 		#[arg(short, long, default_value("800"))]
 		height: u32,
 	},
+
+	/// - Shows how much space storing identical media content only once would
+	///   save. This reads every stored media file.
+	DedupReport,
+
+	/// - Deletes the media files no media record refers to any more, e.g. files
+	///   left behind by failed deletions
+	Gc {
+		/// Only count the files which would be deleted
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Moves the files in the media directory to object storage
+	///
+	/// Requires `media_storage.backend` to be set to "s3". Until a file is
+	/// moved it is still served from the media directory.
+	MigrateToS3,
+
+	/// - Generates the missing thumbnails of local media in the sizes of
+	///   `media_thumbnail_pregenerate`
+	PregenerateThumbnails,
+
+	#[command(subcommand)]
+	/// - Manage the URL preview cache and per-domain preview policies
+	UrlPreview(UrlPreviewCommand),
+
+	#[command(subcommand)]
+	/// - Manage media retention policies
+	Retention(RetentionCommand),
+
+	#[command(subcommand)]
+	/// - Manage the caching policies for media from remote servers
+	RemotePolicy(RemotePolicyCommand),
 }
@@ -0,0 +1,118 @@
+use std::{fmt::Write, time::Duration};
+
+use clap::Subcommand;
+use conduwuit::{
+	Result,
+	config::RemoteMediaPolicy,
+	utils::{bytes, time},
+};
+use ruma::{OwnedServerName, events::room::message::RoomMessageEventContent};
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RemotePolicyCommand {
+	/// - Set the caching policy for media from a remote server, replacing any
+	///   policy for the server set in the config or before
+	Set {
+		server: OwnedServerName,
+
+		/// Serve media from the server without caching it
+		#[arg(long)]
+		no_cache: bool,
+
+		/// Time to keep cached media from the server for (e.g. "1h", "7d")
+		#[arg(long)]
+		ttl: Option<String>,
+
+		/// Size above which media from the server is served without caching
+		/// it (e.g. "10MiB")
+		#[arg(long)]
+		max_size: Option<String>,
+	},
+
+	/// - Remove the caching policy for media from a remote server set with
+	///   `set`
+	Remove {
+		server: OwnedServerName,
+	},
+
+	/// - List the caching policies for media from remote servers, from the
+	///   config and set with `set`
+	List,
+}
+
+#[admin_command]
+async fn set(
+	&self,
+	server: OwnedServerName,
+	no_cache: bool,
+	ttl: Option<String>,
+	max_size: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let ttl = ttl
+		.as_deref()
+		.map(time::parse_duration)
+		.transpose()?
+		.map(|ttl| ttl.as_secs());
+
+	let max_size = max_size.as_deref().map(bytes::from_str).transpose()?;
+
+	let policy = RemoteMediaPolicy { no_cache, ttl, max_size };
+	self.services
+		.media
+		.set_remote_media_policy(&server, &policy);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Set remote media policy for `{server}` ({}). Media already cached is deleted under the \
+		 policy on the next periodic run.",
+		describe(&policy)
+	)))
+}
+
+#[admin_command]
+async fn remove(&self, server: OwnedServerName) -> Result<RoomMessageEventContent> {
+	self.services
+		.media
+		.remove_remote_media_policy(&server)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Removed remote media policy for `{server}`."
+	)))
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let policies = self.services.media.remote_media_policies().await;
+
+	if policies.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No remote media policies set."));
+	}
+
+	let mut out = format!("Remote media policies ({}):\n", policies.len());
+	for (server, policy) in &policies {
+		writeln!(out, "- `{server}`: {}", describe(policy))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+fn describe(policy: &RemoteMediaPolicy) -> String {
+	if policy.no_cache {
+		return "not cached".to_owned();
+	}
+
+	let ttl = policy.ttl.map_or_else(
+		|| "kept indefinitely".to_owned(),
+		|ttl| format!("kept for {}", time::pretty(Duration::from_secs(ttl))),
+	);
+
+	let max_size = policy.max_size.map_or_else(
+		|| "any size".to_owned(),
+		|max_size| format!("up to {}", bytes::pretty(max_size)),
+	);
+
+	format!("{ttl}, {max_size}")
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use clap::Subcommand;
+use conduwuit::{Result, utils::time};
+use conduwuit_service::media::RetentionScope;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, admin_command_dispatch};
+
+/// Candidates listed by `preview`
+const PREVIEW_LIMIT: usize = 100;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RetentionCommand {
+	/// - Set a media retention policy, replacing any existing policy of the
+	///   scope
+	///
+	/// The scope is either "unreferenced", for local media not uploaded,
+	/// sent in an event or downloaded within the retention, a room ID, for
+	/// media sent to the room longer than the retention ago, or a user ID,
+	/// for media uploaded by the user longer than the retention ago.
+	SetPolicy {
+		scope: String,
+
+		/// Retention of the media, e.g. "30d"
+		retention: String,
+	},
+
+	/// - Remove a media retention policy
+	RemovePolicy {
+		scope: String,
+	},
+
+	/// - List the media retention policies
+	ListPolicies,
+
+	/// - List the media the retention policies currently apply to, without
+	///   deleting it
+	Preview,
+
+	/// - Delete the media the retention policies apply to now instead of
+	///   waiting for it to be reported and deleted by the periodic runs
+	Run,
+}
+
+#[admin_command]
+async fn set_policy(&self, scope: String, retention: String) -> Result<RoomMessageEventContent> {
+	let scope: RetentionScope = scope.parse()?;
+	let retention = time::parse_duration(&retention)?;
+	self.services.media.set_retention_policy(&scope, retention);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Set media retention policy for {scope} to {}. Preview the media it applies to with \
+		 `media retention preview`.",
+		time::pretty(retention)
+	)))
+}
+
+#[admin_command]
+async fn remove_policy(&self, scope: String) -> Result<RoomMessageEventContent> {
+	let scope: RetentionScope = scope.parse()?;
+	self.services.media.remove_retention_policy(&scope).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Removed media retention policy for {scope}."
+	)))
+}
+
+#[admin_command]
+async fn list_policies(&self) -> Result<RoomMessageEventContent> {
+	let policies = self.services.media.retention_policies().await;
+
+	if policies.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No media retention policies set."));
+	}
+
+	let mut out = format!("Media retention policies ({}):\n", policies.len());
+	for (scope, retention) in &policies {
+		writeln!(out, "- {scope}: {}", time::pretty(*retention))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn preview(&self) -> Result<RoomMessageEventContent> {
+	let candidates = self.services.media.retention_candidates().await?;
+
+	if candidates.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain(
+			"No media falls under the retention policies.",
+		));
+	}
+
+	let mut out =
+		format!("{} media files fall under the retention policies:\n```\n", candidates.len());
+	for candidate in candidates.iter().take(PREVIEW_LIMIT) {
+		writeln!(out, "{}\t{}", candidate.mxc, candidate.scope)?;
+	}
+
+	if candidates.len() > PREVIEW_LIMIT {
+		writeln!(out, "...")?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn run(&self) -> Result<RoomMessageEventContent> {
+	let deleted = self.services.media.enforce_retention_now().await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Deleted {deleted} media files under the retention policies."
+	)))
+}
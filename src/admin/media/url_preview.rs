@@ -0,0 +1,131 @@
+use std::{fmt::Write, time::Duration};
+
+use clap::Subcommand;
+use conduwuit::{Result, utils::time};
+use conduwuit_service::media::UrlPreviewPolicy;
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum UrlPreviewCommand {
+	/// - Set the URL preview policy for a domain and its subdomains, replacing
+	///   any existing policy for the domain
+	SetDomainPolicy {
+		domain: String,
+
+		/// Never preview URLs on the domain
+		#[arg(long)]
+		deny: bool,
+
+		/// Don't download images for previews of URLs on the domain
+		#[arg(long)]
+		no_images: bool,
+
+		/// Time to cache previews for instead of `url_preview_cache_ttl` (e.g.
+		/// "1h", "7d")
+		#[arg(long)]
+		ttl: Option<String>,
+	},
+
+	/// - Remove the URL preview policy for a domain
+	RemoveDomainPolicy {
+		domain: String,
+	},
+
+	/// - List the URL preview policies for all domains
+	ListDomainPolicies,
+
+	/// - Remove cached URL previews, only for a domain and its subdomains if
+	///   specified
+	ClearCache {
+		domain: Option<String>,
+	},
+}
+
+#[admin_command]
+async fn set_domain_policy(
+	&self,
+	domain: String,
+	deny: bool,
+	no_images: bool,
+	ttl: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let ttl = ttl
+		.as_deref()
+		.map(time::parse_duration)
+		.transpose()?
+		.map(|ttl| ttl.as_secs());
+
+	let policy = UrlPreviewPolicy { deny, no_images, ttl };
+	self.services.media.set_url_preview_policy(&domain, &policy);
+
+	// cached previews may not conform to the new policy
+	let cleared = self
+		.services
+		.media
+		.clear_url_previews(Some(&domain))
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Set URL preview policy for `{domain}` ({}) and cleared {cleared} cached previews.",
+		describe(&policy)
+	)))
+}
+
+#[admin_command]
+async fn remove_domain_policy(&self, domain: String) -> Result<RoomMessageEventContent> {
+	self.services
+		.media
+		.remove_url_preview_policy(&domain)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Removed URL preview policy for `{domain}`."
+	)))
+}
+
+#[admin_command]
+async fn list_domain_policies(&self) -> Result<RoomMessageEventContent> {
+	let policies: Vec<_> = self.services.media.url_preview_policies().collect().await;
+
+	if policies.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No URL preview policies set."));
+	}
+
+	let mut out = format!("URL preview policies ({}):\n", policies.len());
+	for (domain, policy) in &policies {
+		writeln!(out, "- `{domain}`: {}", describe(policy))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn clear_cache(&self, domain: Option<String>) -> Result<RoomMessageEventContent> {
+	let cleared = self
+		.services
+		.media
+		.clear_url_previews(domain.as_deref())
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Cleared {cleared} cached URL previews."
+	)))
+}
+
+fn describe(policy: &UrlPreviewPolicy) -> String {
+	if policy.deny {
+		return "denied".to_owned();
+	}
+
+	let images = if policy.no_images { "no images" } else { "images" };
+	let ttl = policy.ttl.map_or_else(
+		|| "default TTL".to_owned(),
+		|ttl| format!("TTL {}", time::pretty(Duration::from_secs(ttl))),
+	);
+
+	format!("{images}, {ttl}")
+}
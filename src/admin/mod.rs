@@ -14,6 +14,7 @@
 pub(crate) mod debug;
 pub(crate) mod federation;
 pub(crate) mod media;
+pub(crate) mod policy_lists;
 pub(crate) mod query;
 pub(crate) mod room;
 pub(crate) mod server;
@@ -0,0 +1,118 @@
+use std::fmt::Write;
+
+use conduwuit::Result;
+use futures::StreamExt;
+use ruma::{OwnedRoomOrAliasId, events::room::message::RoomMessageEventContent};
+use service::policy_lists::{Action, PendingAction};
+
+use crate::{PAGE_SIZE, admin_command};
+
+#[admin_command]
+pub(super) async fn subscribe(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	self.services.policy_lists.subscribe(&room_id).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Subscribed to policy list {room_id}. Its rules will be enforced from the next check."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unsubscribe(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	self.services.policy_lists.unsubscribe(&room_id).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Unsubscribed from policy list {room_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn subscriptions(&self) -> Result<RoomMessageEventContent> {
+	let policy_lists = self.services.policy_lists.subscriptions().await;
+	if policy_lists.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("Not subscribed to any policy lists."));
+	}
+
+	let mut out = format!("Policy lists ({}):\n", policy_lists.len());
+	for room_id in &policy_lists {
+		writeln!(out, "- {room_id}")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn check(&self) -> Result<RoomMessageEventContent> {
+	let count = self.services.policy_lists.check().await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Checked policy lists; found {count} new actions."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn pending(&self) -> Result<RoomMessageEventContent> {
+	let actions: Vec<_> = self
+		.services
+		.policy_lists
+		.pending_actions()
+		.take(PAGE_SIZE)
+		.collect()
+		.await;
+
+	if actions.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No actions are pending review."));
+	}
+
+	let mut out = format!("Pending actions ({}):\n```\n", actions.len());
+	for (id, pending) in &actions {
+		writeln!(out, "#{id}\t{}", describe(pending))?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn apply(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let pending = self.services.policy_lists.apply_pending_action(id).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Applied: {}",
+		describe(&pending)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn dismiss(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let pending = self
+		.services
+		.policy_lists
+		.dismiss_pending_action(id)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Dismissed: {}",
+		describe(&pending)
+	)))
+}
+
+fn describe(pending: &PendingAction) -> String {
+	let action = match &pending.action {
+		| Action::BanUser { room_id, user_id } => format!("Ban {user_id} from {room_id}"),
+		| Action::DenyServer { room_id, server } => format!("Deny {server} in {room_id}"),
+		| Action::BanRoom { room_id } => format!("Ban room {room_id}"),
+	};
+
+	format!(
+		"{action}\tRule: {} in {}\tReason: {}",
+		pending.entity, pending.policy_list, pending.reason
+	)
+}
@@ -0,0 +1,42 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::OwnedRoomOrAliasId;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum PolicyListsCommand {
+	/// - Subscribe to a policy list (ban list) room
+	///
+	/// The server must already be joined to the room.
+	Subscribe {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Unsubscribe from a policy list subscribed to with `subscribe`
+	Unsubscribe {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - List subscribed policy lists, including those from the config
+	Subscriptions,
+
+	/// - Match the policy list rules against the rooms on this server now
+	Check,
+
+	/// - List actions from policy lists pending review
+	Pending,
+
+	/// - Apply a pending action
+	Apply {
+		id: u64,
+	},
+
+	/// - Dismiss a pending action; it will not be queued again
+	Dismiss {
+		id: u64,
+	},
+}
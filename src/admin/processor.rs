@@ -29,12 +29,16 @@
 use service::{
 	Services,
 	admin::{CommandInput, CommandOutput, ProcessorFuture, ProcessorResult},
+	audit::Kind,
 };
 use tracing::Level;
 use tracing_subscriber::{EnvFilter, filter::LevelFilter};
 
 use crate::{Command, admin, admin::AdminCommand};
 
+/// Arguments named like these have their values left out of the audit log.
+const SECRET_ARGS: &[&str] = &["password", "secret", "token"];
+
 #[must_use]
 pub(super) fn complete(line: &str) -> String { complete_command(AdminCommand::command(), line) }
 
@@ -45,11 +49,11 @@ pub(super) fn dispatch(services: Arc<Services>, command: CommandInput) -> Proces
 
 #[tracing::instrument(skip_all, name = "admin")]
 async fn handle_command(services: Arc<Services>, command: CommandInput) -> ProcessorResult {
-	AssertUnwindSafe(Box::pin(process_command(services, &command)))
+	AssertUnwindSafe(Box::pin(process_command(services.clone(), &command)))
 		.catch_unwind()
 		.await
 		.map_err(Error::from_panic)
-		.unwrap_or_else(|error| handle_panic(&error, &command))
+		.unwrap_or_else(|error| handle_panic(&services, &error, &command))
 }
 
 async fn process_command(services: Arc<Services>, input: &CommandInput) -> ProcessorResult {
@@ -68,6 +72,13 @@ async fn process_command(services: Arc<Services>, input: &CommandInput) -> Proce
 
 	let (result, mut logs) = process(&context, command, &args).await;
 
+	services.audit.record(
+		Kind::Command,
+		input.sender.as_deref(),
+		audit_line(&args),
+		result.as_ref().err().map(ToString::to_string),
+	);
+
 	let output = &mut context.output.lock().await;
 	output.flush().await.expect("final flush of output stream");
 
@@ -92,7 +103,15 @@ async fn process_command(services: Arc<Services>, input: &CommandInput) -> Proce
 }
 
 #[allow(clippy::result_large_err)]
-fn handle_panic(error: &Error, command: &CommandInput) -> ProcessorResult {
+fn handle_panic(services: &Services, error: &Error, command: &CommandInput) -> ProcessorResult {
+	let line = command.command.lines().next().unwrap_or_default();
+	services.audit.record(
+		Kind::Command,
+		command.sender.as_deref(),
+		audit_line(&parse_line(line)),
+		Some(format!("panic: {error}")),
+	);
+
 	let link =
 		"Please submit a [bug report](https://github.com/girlbossceo/conduwuit/issues/new). 🥺";
 	let msg = format!("Panic occurred while processing command:\n```\n{error:#?}\n```\n{link}");
@@ -132,6 +151,42 @@ async fn process(
 	(result, output)
 }
 
+/// The command line for the audit log, with the values of arguments named like
+/// secrets replaced.
+pub(super) fn audit_line(args: &[String]) -> String {
+	let mut secrets = Vec::new();
+	if let Ok(matches) = AdminCommand::command().try_get_matches_from(args) {
+		let mut matches = Some(&matches);
+		while let Some(current) = matches {
+			for id in current.ids() {
+				if !SECRET_ARGS
+					.iter()
+					.any(|secret| id.as_str().contains(secret))
+				{
+					continue;
+				}
+
+				if let Ok(Some(values)) = current.try_get_raw(id.as_str()) {
+					secrets.extend(values.map(ToOwned::to_owned));
+				}
+			}
+
+			matches = current.subcommand().map(|(_, sub)| sub);
+		}
+	}
+
+	args.iter()
+		.map(|arg| {
+			if secrets.iter().any(|secret| secret == arg.as_str()) {
+				"<redacted>"
+			} else {
+				arg.as_str()
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
 fn capture_create(context: &Command<'_>) -> (Arc<Capture>, Arc<Mutex<String>>) {
 	let env_config = &context.services.server.config.admin_log_capture;
 	let env_filter = EnvFilter::try_new(env_config).unwrap_or_else(|e| {
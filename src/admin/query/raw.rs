@@ -157,6 +157,11 @@ pub(crate) enum RawCommand {
 
 		#[arg(long, default_value("false"))]
 		exhaustive: bool,
+
+		/// Queue the compaction until the server is quiet, see
+		/// maintenance_quiet_hours.
+		#[arg(long)]
+		defer: bool,
 	},
 }
 
@@ -170,6 +175,7 @@ pub(super) async fn compact(
 	into: Option<usize>,
 	parallelism: Option<usize>,
 	exhaustive: bool,
+	defer: bool,
 ) -> Result<RoomMessageEventContent> {
 	use conduwuit_database::compact::Options;
 
@@ -206,6 +212,13 @@ pub(super) async fn compact(
 		exhaustive,
 	};
 
+	if defer {
+		let id = self.services.maintenance.compact(maps, options);
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"Queued compaction #{id} until the server is quiet."
+		)));
+	}
+
 	let runtime = self.services.server.runtime().clone();
 	let parallelism = parallelism.unwrap_or(1);
 	let results = maps
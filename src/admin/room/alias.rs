@@ -42,6 +42,13 @@ pub(crate) enum RoomAliasCommand {
 		/// If set, only list the aliases for this room
 		room_id: Option<Box<RoomId>>,
 	},
+
+	/// - Remove all local aliases matching `forbidden_alias_names`
+	RemoveForbidden {
+		/// Only list the matching aliases without removing them
+		#[arg(long)]
+		report_only: bool,
+	},
 }
 
 pub(super) async fn process(command: RoomAliasCommand, context: &Command<'_>) -> Result {
@@ -137,8 +144,59 @@ pub(super) async fn reprocess(
 							Ok(RoomMessageEventContent::text_plain("Alias isn't in use.")),
 					}
 				},
-				| RoomAliasCommand::List { .. } => unreachable!(),
+				| RoomAliasCommand::List { .. } | RoomAliasCommand::RemoveForbidden { .. } =>
+					unreachable!(),
+			}
+		},
+		| RoomAliasCommand::RemoveForbidden { report_only } => {
+			let aliases = services
+				.rooms
+				.alias
+				.forbidden_local_aliases()
+				.map(|(room_id, localpart)| (room_id.to_owned(), localpart.to_owned()))
+				.collect::<Vec<(OwnedRoomId, String)>>()
+				.await;
+
+			if aliases.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain(
+					"No local aliases match `forbidden_alias_names`.",
+				));
+			}
+
+			let server_name = services.globals.server_name();
+			let mut output = String::new();
+			let mut removed: usize = 0;
+			for (room_id, localpart) in &aliases {
+				let room_alias = OwnedRoomAliasId::parse(format!("#{localpart}:{server_name}"))?;
+				if report_only {
+					writeln!(output, "- {room_alias} -> {room_id}")?;
+					continue;
+				}
+
+				match services
+					.rooms
+					.alias
+					.remove_alias(&room_alias, server_user)
+					.await
+				{
+					| Ok(()) => {
+						removed = removed.saturating_add(1);
+						writeln!(output, "- {room_alias} -> {room_id}: removed")?;
+					},
+					| Err(e) => writeln!(output, "- {room_alias} -> {room_id}: failed: {e}")?,
+				}
 			}
+
+			let summary = if report_only {
+				format!("{} local alias(es) match `forbidden_alias_names`:", aliases.len())
+			} else {
+				format!(
+					"Removed {removed} of {} local alias(es) matching `forbidden_alias_names`:",
+					aliases.len()
+				)
+			};
+
+			Ok(RoomMessageEventContent::text_plain(format!("{summary}\n{output}")))
 		},
 		| RoomAliasCommand::List { room_id } =>
 			if let Some(room_id) = room_id {
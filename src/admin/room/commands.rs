@@ -1,6 +1,26 @@
-use conduwuit::Result;
+use std::{borrow::Cow, fmt::Write};
+
+use api::client::leave_room;
+use conduwuit::{
+	Result, at, err,
+	matrix::pdu::{BorrowedPdu, PduBuilder, PduEvent},
+	utils::{stream::TryIgnore, time},
+	warn,
+};
+use conduwuit_service::{
+	reports::{Direction, Report},
+	users::glob_matches,
+};
 use futures::StreamExt;
-use ruma::{OwnedRoomId, events::room::message::RoomMessageEventContent};
+use regex::Regex;
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedUserId,
+	events::{
+		TimelineEventType,
+		room::{message::RoomMessageEventContent, redaction::RoomRedactionEventContent},
+	},
+};
+use serde::Deserialize;
 
 use crate::{PAGE_SIZE, admin_command, get_room_info};
 
@@ -67,3 +87,390 @@ pub(super) async fn exists(&self, room_id: OwnedRoomId) -> Result<RoomMessageEve
 
 	Ok(RoomMessageEventContent::notice_markdown(format!("{result}")))
 }
+
+#[admin_command]
+pub(super) async fn quarantine(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
+	if self.services.admin.is_admin_room(&room_id).await {
+		return Ok(RoomMessageEventContent::notice_plain(
+			"Not allowed to quarantine the admin room.",
+		));
+	}
+
+	if self.services.rooms.metadata.is_quarantined(&room_id).await {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{room_id} is already quarantined."
+		)));
+	}
+
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"We do not know about {room_id}."
+		)));
+	}
+
+	self.services.rooms.metadata.quarantine_room(&room_id, true);
+
+	let local_members = self
+		.services
+		.rooms
+		.state_cache
+		.local_users_in_room(&room_id)
+		.count()
+		.await;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Quarantined {room_id}. Its {local_members} local members can no longer send events to \
+		 it, and no local user can join it."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unquarantine(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
+	if !self.services.rooms.metadata.is_quarantined(&room_id).await {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{room_id} is not quarantined."
+		)));
+	}
+
+	self.services
+		.rooms
+		.metadata
+		.quarantine_room(&room_id, false);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Lifted the quarantine of {room_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list_quarantined(&self) -> Result<RoomMessageEventContent> {
+	let rooms: Vec<_> = self
+		.services
+		.rooms
+		.metadata
+		.list_quarantined_rooms()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if rooms.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No rooms are quarantined."));
+	}
+
+	let mut out = format!("Quarantined rooms ({}):\n```\n", rooms.len());
+	for room_id in &rooms {
+		let name = get_room_info(self.services, room_id).await.2;
+		writeln!(out, "{room_id}\tName: {name}")?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn reject_invites(
+	&self,
+	from_server: String,
+	dry_run: bool,
+) -> Result<RoomMessageEventContent> {
+	let users: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut invites = Vec::new();
+	for user_id in users {
+		let rooms: Vec<OwnedRoomId> = self
+			.services
+			.rooms
+			.state_cache
+			.rooms_invited(&user_id)
+			.map(|(room_id, _)| room_id)
+			.collect()
+			.await;
+
+		for room_id in rooms {
+			let Ok(inviter) = self
+				.services
+				.rooms
+				.state_cache
+				.invite_sender(&user_id, &room_id)
+				.await
+			else {
+				continue;
+			};
+
+			if glob_matches(&from_server, inviter.server_name().as_str()) {
+				invites.push((user_id.clone(), room_id, inviter));
+			}
+		}
+	}
+
+	if invites.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No matching invites found."));
+	}
+
+	let mut rejected: usize = 0;
+	let mut out = String::new();
+	for (user_id, room_id, inviter) in &invites {
+		writeln!(out, "{user_id}\t{room_id}\tInvited by: {inviter}")?;
+		if dry_run {
+			continue;
+		}
+
+		if let Err(e) = leave_room(self.services, user_id, room_id, None).await {
+			warn!(%user_id, "Failed to reject invite to {room_id}: {e}");
+			continue;
+		}
+
+		rejected = rejected.saturating_add(1);
+	}
+
+	let summary = if dry_run {
+		format!("Would reject {} invites:", invites.len())
+	} else {
+		format!("Rejected {rejected} of {} invites:", invites.len())
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("{summary}\n```\n{out}```")))
+}
+
+#[admin_command]
+pub(super) async fn list_reports(
+	&self,
+	room: Option<OwnedRoomId>,
+	before: Option<u64>,
+	after: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let direction = before
+		.map(Direction::Before)
+		.or(after.map(Direction::After));
+
+	let reports: Vec<_> = self
+		.services
+		.reports
+		.reports(room.as_deref(), direction)
+		.take(PAGE_SIZE)
+		.collect()
+		.await;
+
+	if reports.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No reports found."));
+	}
+
+	let mut out = format!("Reports ({}):\n```\n", reports.len());
+	for (id, report) in &reports {
+		let status = if report.resolved.is_some() { "resolved" } else { "open" };
+		let subject = match (&report.room_id, &report.event_id) {
+			| (Some(room_id), Some(event_id)) => format!("{room_id}\t{event_id}"),
+			| _ => format!("User {}", report.reported_user),
+		};
+
+		writeln!(
+			out,
+			"#{id}\t{status}\t{subject}\tReported by: {}\tReason: {}",
+			report.reporter,
+			report.reason.as_deref().unwrap_or_default(),
+		)?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn get_report(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let report = self.services.reports.get_report(id).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(describe_report(id, &report)))
+}
+
+#[admin_command]
+pub(super) async fn resolve_report(
+	&self,
+	id: u64,
+	note: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let report = self.services.reports.resolve_report(id, note).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Resolved report.\n\n{}",
+		describe_report(id, &report)
+	)))
+}
+
+/// Text of a message event matched by `redact-matching`.
+#[derive(Deserialize)]
+struct MessageText<'a> {
+	#[serde(borrow)]
+	body: Option<Cow<'a, str>>,
+
+	#[serde(borrow)]
+	formatted_body: Option<Cow<'a, str>>,
+}
+
+#[admin_command]
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn redact_matching(
+	&self,
+	room_id: OwnedRoomId,
+	regex: String,
+	sender: Option<OwnedUserId>,
+	since: Option<String>,
+	until: Option<String>,
+	reason: Option<String>,
+	dry_run: bool,
+) -> Result<RoomMessageEventContent> {
+	let regex = Regex::new(&regex).map_err(|e| err!("Invalid regex: {e}"))?;
+	let parse_ago = |ago: Option<String>| -> Result<Option<MilliSecondsSinceUnixEpoch>> {
+		Ok(ago
+			.as_deref()
+			.map(time::parse_timepoint_ago)
+			.transpose()?
+			.and_then(MilliSecondsSinceUnixEpoch::from_system_time))
+	};
+
+	let since = parse_ago(since)?;
+	let until = parse_ago(until)?;
+
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Ok(RoomMessageEventContent::notice_plain("Room does not exist."));
+	}
+
+	let filter = move |pdu: &BorrowedPdu<'_>| {
+		pdu.is_kind(&TimelineEventType::RoomMessage)
+			&& !pdu.is_state()
+			&& sender
+				.as_ref()
+				.is_none_or(|sender| pdu.sender == sender.as_str())
+			&& since.is_none_or(|since| pdu.origin_server_ts >= since.get())
+			&& until.is_none_or(|until| pdu.origin_server_ts <= until.get())
+			&& serde_json::from_str::<MessageText<'_>>(pdu.content.get()).is_ok_and(|text| {
+				[text.body, text.formatted_body]
+					.iter()
+					.flatten()
+					.any(|text| regex.is_match(text))
+			})
+	};
+
+	let events: Vec<PduEvent> = self
+		.services
+		.rooms
+		.timeline
+		.pdus_filtered(None, &room_id, None, filter)
+		.ignore_err()
+		.map(at!(1))
+		.collect()
+		.await;
+
+	if events.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No messages match."));
+	}
+
+	if dry_run {
+		let mut out = format!("{} message(s) would be redacted:\n```\n", events.len());
+		for pdu in events.iter().take(PAGE_SIZE) {
+			writeln!(out, "{}\t{}\t{}", pdu.event_id, pdu.sender, preview(pdu))?;
+		}
+
+		if events.len() > PAGE_SIZE {
+			writeln!(out, "... and {} more", events.len().saturating_sub(PAGE_SIZE))?;
+		}
+		out.push_str("```");
+
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	}
+
+	let reason = reason.unwrap_or_else(|| {
+		format!(
+			"The administrator(s) of {} removed this message.",
+			self.services.globals.server_name()
+		)
+	});
+
+	let mut redacted: usize = 0;
+	let mut failed = Vec::new();
+	for pdu in &events {
+		// the server user needs the power to redact the events of remote users
+		let redactor = if self.services.globals.user_is_local(&pdu.sender) {
+			&pdu.sender
+		} else {
+			&self.services.globals.server_user
+		};
+
+		let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+		let result = self
+			.services
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					redacts: Some(pdu.event_id.clone()),
+					..PduBuilder::timeline(&RoomRedactionEventContent {
+						redacts: Some(pdu.event_id.clone()),
+						reason: Some(reason.clone()),
+					})
+				},
+				redactor,
+				&room_id,
+				&state_lock,
+			)
+			.await;
+
+		match result {
+			| Ok(_) => redacted = redacted.saturating_add(1),
+			| Err(e) => failed.push((&pdu.event_id, e)),
+		}
+	}
+
+	let mut out = format!("Redacted {redacted} of {} matching message(s).", events.len());
+	if !failed.is_empty() {
+		out.push_str("\n\nFailed to redact:\n```\n");
+		for (event_id, e) in failed.iter().take(PAGE_SIZE) {
+			writeln!(out, "{event_id}: {e}")?;
+		}
+		out.push_str("```");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+/// Start of the body of a message, on one line.
+fn preview(pdu: &PduEvent) -> String {
+	serde_json::from_str::<MessageText<'_>>(pdu.content.get())
+		.ok()
+		.and_then(|text| text.body)
+		.map(|body| body.chars().take(80).collect::<String>().replace('\n', " "))
+		.unwrap_or_default()
+}
+
+fn describe_report(id: u64, report: &Report) -> String {
+	let received = time::rfc2822_from_seconds(report.received_ts.as_secs().into());
+	let resolved = report.resolved.as_ref().map_or_else(
+		|| "no".to_owned(),
+		|resolution| {
+			let resolved = time::rfc2822_from_seconds(resolution.resolved_ts.as_secs().into());
+			match &resolution.note {
+				| Some(note) => format!("{resolved} ({note})"),
+				| None => resolved,
+			}
+		},
+	);
+
+	let subject = match (&report.room_id, &report.event_id) {
+		| (Some(room_id), Some(event_id)) =>
+			format!("Room ID: {room_id}\nEvent ID: {event_id}\nSent By: {}", report.reported_user),
+		| _ => format!("Reported User: {}", report.reported_user),
+	};
+
+	format!(
+		"Report #{id}\n\n{subject}\nReported By: {}\nReceived: {received}\n\nReport Score: \
+		 {}\nReport Reason: {}\n\nResolved: {resolved}",
+		report.reporter,
+		report.score.unwrap_or_default(),
+		report.reason.as_deref().unwrap_or_default(),
+	)
+}
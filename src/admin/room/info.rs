@@ -40,7 +40,10 @@ async fn list_joined_members(
 		.await
 		.unwrap_or_else(|_| room_id.to_string());
 
-	let member_info: Vec<_> = self
+	writeln!(self, "Members in Room \"{room_name}\":\n```").await?;
+
+	let mut count: usize = 0;
+	let mut members = self
 		.services
 		.rooms
 		.state_cache
@@ -50,32 +53,23 @@ async fn list_joined_members(
 				.then(|| self.services.globals.user_is_local(user_id))
 				.unwrap_or(true)
 		})
-		.map(ToOwned::to_owned)
-		.filter_map(|user_id| async move {
-			Some((
-				self.services
-					.users
-					.displayname(&user_id)
-					.await
-					.unwrap_or_else(|_| user_id.to_string()),
-				user_id,
-			))
-		})
-		.collect()
-		.await;
+		.boxed();
+
+	while let Some(user_id) = members.next().await {
+		let displayname = self
+			.services
+			.users
+			.displayname(user_id)
+			.await
+			.unwrap_or_else(|_| user_id.to_string());
+
+		writeln!(self, "{user_id} | {displayname}").await?;
+		count = count.saturating_add(1);
+	}
 
-	let output_plain = format!(
-		"{} Members in Room \"{}\":\n```\n{}\n```",
-		member_info.len(),
-		room_name,
-		member_info
-			.into_iter()
-			.map(|(displayname, mxid)| format!("{mxid} | {displayname}"))
-			.collect::<Vec<_>>()
-			.join("\n")
-	);
+	writeln!(self, "```\n{count} members.").await?;
 
-	Ok(RoomMessageEventContent::notice_markdown(output_plain))
+	Ok(RoomMessageEventContent::notice_markdown(""))
 }
 
 #[admin_command]
@@ -6,7 +6,7 @@
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::OwnedRoomId;
+use ruma::{OwnedRoomId, OwnedUserId};
 
 use self::{
 	alias::RoomAliasCommand, directory::RoomDirectoryCommand, info::RoomInfoCommand,
@@ -56,4 +56,101 @@ pub(super) enum RoomCommand {
 	Exists {
 		room_id: OwnedRoomId,
 	},
+
+	/// - Quarantine a room
+	///
+	/// Local users can no longer join, knock on or send events to the room,
+	/// but may still leave it, and admins may still redact events in it.
+	/// Events from other servers are still received and stored, so the room
+	/// can be reviewed. A softer alternative to banning the room. The admin
+	/// room cannot be quarantined.
+	Quarantine {
+		room_id: OwnedRoomId,
+	},
+
+	/// - Lift the quarantine of a room
+	Unquarantine {
+		room_id: OwnedRoomId,
+	},
+
+	/// - List the quarantined rooms
+	ListQuarantined,
+
+	/// - Redact the message events of a room whose text matches a regex
+	///
+	/// Events of local users are redacted by their sender, events of remote
+	/// users by the server user, which must have the power to redact them.
+	/// Use `--dry-run` first to preview which events would be redacted.
+	RedactMatching {
+		room_id: OwnedRoomId,
+
+		/// Regex matched against the body and formatted body of the messages
+		#[arg(long)]
+		regex: String,
+
+		/// Only redact messages of this user
+		#[arg(long)]
+		sender: Option<OwnedUserId>,
+
+		/// Only redact messages sent within this long ago, e.g. `2h`
+		#[arg(long)]
+		since: Option<String>,
+
+		/// Only redact messages sent at least this long ago, e.g. `30m`
+		#[arg(long)]
+		until: Option<String>,
+
+		/// Reason given in the redactions
+		#[arg(long)]
+		reason: Option<String>,
+
+		/// Only list the matching messages without redacting them
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Reject the pending invites of local users sent from matching servers
+	///
+	/// Useful to clean up after a wave of invite spam. Use `--dry-run` first
+	/// to preview which invites would be rejected.
+	RejectInvites {
+		/// Glob matched against the server name of the inviters, in which `*`
+		/// matches any number of characters and `?` any single character, e.g.
+		/// `*.spam.example`
+		#[arg(long)]
+		from_server: String,
+
+		/// Only list the matching invites without rejecting them
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - List event and user reports submitted by local users, newest first
+	ListReports {
+		/// Only list reports of events in this room
+		#[arg(long)]
+		room: Option<OwnedRoomId>,
+
+		/// Only list reports received before the report with this ID
+		#[arg(long, conflicts_with = "after")]
+		before: Option<u64>,
+
+		/// Only list reports received after the report with this ID, oldest
+		/// first
+		#[arg(long)]
+		after: Option<u64>,
+	},
+
+	/// - Show the details of a report
+	GetReport {
+		id: u64,
+	},
+
+	/// - Mark a report as resolved
+	ResolveReport {
+		id: u64,
+
+		/// Note to keep with the report
+		note: Option<String>,
+	},
 }
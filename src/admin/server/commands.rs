@@ -1,10 +1,28 @@
-use std::{fmt::Write, path::PathBuf, sync::Arc};
+use std::{
+	cmp::Reverse, collections::BTreeSet, fmt::Write, path::PathBuf, sync::Arc, time::Duration,
+};
 
-use conduwuit::{Err, Result, info, utils::time, warn};
-use ruma::events::room::message::RoomMessageEventContent;
+use conduwuit::{
+	Err, Result, info,
+	utils::{
+		bytes::pretty,
+		stream::{ReadyExt, TryIgnore},
+		time,
+	},
+	warn,
+};
+use futures::{StreamExt, future, stream};
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedRoomId, UInt, events::room::message::RoomMessageEventContent,
+};
+use service::{pruning::Table, rooms::state_compressor::Recompressed};
 
+use super::Switch;
 use crate::admin_command;
 
+const MAINTENANCE_MESSAGE: &str =
+	"The server is undergoing maintenance; no messages can be sent until it is over.";
+
 #[admin_command]
 pub(super) async fn uptime(&self) -> Result<RoomMessageEventContent> {
 	let elapsed = self
@@ -33,9 +51,23 @@ pub(super) async fn reload_config(
 	path: Option<PathBuf>,
 ) -> Result<RoomMessageEventContent> {
 	let path = path.as_deref().into_iter();
-	self.services.config.reload(path)?;
+	let changes = self.services.config.reload(path)?;
+	if changes.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Successfully reconfigured; no settings changed.",
+		));
+	}
+
+	let mut out = "Successfully reconfigured.\n".to_owned();
+	if !changes.applied.is_empty() {
+		writeln!(out, "\nApplied: `{}`", changes.applied.join("`, `"))?;
+	}
 
-	Ok(RoomMessageEventContent::text_plain("Successfully reconfigured."))
+	if !changes.restart.is_empty() {
+		writeln!(out, "\nRequire a restart: `{}`", changes.restart.join("`, `"))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
 }
 
 #[admin_command]
@@ -83,6 +115,75 @@ pub(super) async fn memory_usage(&self) -> Result<RoomMessageEventContent> {
 	)))
 }
 
+#[admin_command]
+pub(super) async fn memory(&self, purge: bool) -> Result<RoomMessageEventContent> {
+	let Some(before) = conduwuit::alloc::resident() else {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Allocator statistics are not available; they require the jemalloc_stats feature.",
+		));
+	};
+
+	if purge {
+		conduwuit::alloc::trim(None)?;
+		let after = conduwuit::alloc::resident().unwrap_or(before);
+		let released = pretty(before.saturating_sub(after));
+		writeln!(self, "Released {released} of dirty pages.\n").await?;
+	}
+
+	let usage = conduwuit::alloc::memory_usage().unwrap_or_default();
+	let stats = conduwuit::alloc::arena_stats().unwrap_or_default();
+	self.write_str(&format!("```\n{usage}```\n\n{stats}"))
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(""))
+}
+
+#[admin_command]
+pub(super) async fn request_stats(&self, minutes: u64) -> Result<RoomMessageEventContent> {
+	let stats = self.services.server.metrics.requests.stats(minutes);
+	if stats.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No requests recorded."));
+	}
+
+	let percent = |count: u64, requests: u64| {
+		count
+			.saturating_mul(100)
+			.checked_div(requests)
+			.unwrap_or_default()
+	};
+
+	self.write_str(
+		"| endpoint | kind | requests | p50 | p95 | p99 | 4xx | 5xx |\n| --- | --- | --- | --- \
+		 | --- | --- | --- | --- |\n",
+	)
+	.await?;
+
+	for stat in stats {
+		let kind = if stat.endpoint.contains("/_matrix/federation/")
+			|| stat.endpoint.contains("/_matrix/key/")
+		{
+			"federation"
+		} else {
+			"client"
+		};
+
+		writeln!(
+			self,
+			"| {} | {kind} | {} | {:?} | {:?} | {:?} | {}% | {}% |",
+			stat.endpoint,
+			stat.requests,
+			stat.p50,
+			stat.p95,
+			stat.p99,
+			percent(stat.client_errors, stat.requests),
+			percent(stat.server_errors, stat.requests),
+		)
+		.await?;
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(""))
+}
+
 #[admin_command]
 pub(super) async fn clear_caches(&self) -> Result<RoomMessageEventContent> {
 	self.services.clear_cache().await;
@@ -90,6 +191,19 @@ pub(super) async fn clear_caches(&self) -> Result<RoomMessageEventContent> {
 	Ok(RoomMessageEventContent::text_plain("Done."))
 }
 
+#[admin_command]
+pub(super) async fn set_cache(
+	&self,
+	name: String,
+	entries: usize,
+) -> Result<RoomMessageEventContent> {
+	self.services.caches.set_capacity(&name, entries).await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Capacity of the {name} cache set to {entries} entries."
+	)))
+}
+
 #[admin_command]
 pub(super) async fn list_backups(&self) -> Result<RoomMessageEventContent> {
 	let result = self.services.db.db.backup_list()?;
@@ -102,13 +216,339 @@ pub(super) async fn list_backups(&self) -> Result<RoomMessageEventContent> {
 }
 
 #[admin_command]
-pub(super) async fn backup_database(&self) -> Result<RoomMessageEventContent> {
+pub(super) async fn db_usage(&self, top: usize) -> Result<RoomMessageEventContent> {
+	const MXC: &[u8] = b"mxc://";
+
+	let size = |bytes: u64| pretty(bytes.try_into().unwrap_or(usize::MAX));
+
+	let mut columns: Vec<_> = self
+		.services
+		.db
+		.iter()
+		.map(|(name, map)| {
+			let bytes = map
+				.property_integer(c"rocksdb.total-sst-files-size")
+				.unwrap_or(0);
+
+			let keys = map
+				.property_integer(c"rocksdb.estimate-num-keys")
+				.unwrap_or(0);
+
+			(name, bytes, keys)
+		})
+		.collect();
+
+	columns.sort_unstable_by_key(|&(_, bytes, _)| Reverse(bytes));
+	let total = columns
+		.iter()
+		.fold(0_u64, |total, &(_, bytes, _)| total.saturating_add(bytes));
+
+	let mut out = format!(
+		"Database size on disk: {}\n\n| Column | Size | Keys (estimate) |\n| --- | --- | --- |\n",
+		size(total)
+	);
+
+	for (name, bytes, keys) in &columns {
+		writeln!(out, "| {name} | {} | {keys} |", size(*bytes))?;
+	}
+
+	let pduid_pdu = &self.services.db["pduid_pdu"];
+	let mut rooms: Vec<(OwnedRoomId, u64, u64)> = self
+		.services
+		.rooms
+		.metadata
+		.iter_ids()
+		.filter_map(|room_id| async move {
+			let shortroomid = self
+				.services
+				.rooms
+				.short
+				.get_shortroomid(room_id)
+				.await
+				.ok()?;
+
+			let bytes = pduid_pdu.raw_approximate_size_prefix(&shortroomid.to_be_bytes());
+			Some((room_id.to_owned(), shortroomid, bytes))
+		})
+		.collect()
+		.await;
+
+	rooms.sort_unstable_by_key(|&(_, _, bytes)| Reverse(bytes));
+	rooms.truncate(top);
+
+	writeln!(
+		out,
+		"\n| Room | Events on disk | Events | Event JSON | State entries | Media references \
+		 |\n| --- | --- | --- | --- | --- | --- |"
+	)?;
+
+	for (room_id, shortroomid, bytes) in rooms {
+		let (events, json, media) = pduid_pdu
+			.raw_stream_prefix(&shortroomid.to_be_bytes())
+			.ignore_err()
+			.ready_fold((0_usize, 0_usize, 0_usize), |(events, json, media), (_, pdu)| {
+				let refs = pdu.windows(MXC.len()).filter(|&w| w == MXC).count();
+				(
+					events.saturating_add(1),
+					json.saturating_add(pdu.len()),
+					media.saturating_add(refs),
+				)
+			})
+			.await;
+
+		let state = match self
+			.services
+			.rooms
+			.state
+			.get_room_shortstatehash(&room_id)
+			.await
+		{
+			| Ok(shortstatehash) =>
+				self.services
+					.rooms
+					.state_accessor
+					.state_full_shortids(shortstatehash)
+					.count()
+					.await,
+			| Err(_) => 0,
+		};
+
+		writeln!(
+			out,
+			"| {room_id} | {} | {events} | {} | {state} | {media} |",
+			size(bytes),
+			pretty(json),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn recompress_state(
+	&self,
+	room: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let rooms: Vec<OwnedRoomId> = match room {
+		| Some(room_id) => vec![room_id],
+		| None =>
+			self.services
+				.rooms
+				.metadata
+				.iter_ids()
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+	};
+
+	let mut totals = Recompressed::default();
+	for room_id in &rooms {
+		let Ok(current) = self
+			.services
+			.rooms
+			.state
+			.get_room_shortstatehash(room_id)
+			.await
+		else {
+			continue;
+		};
+
+		let shortstatehashes: BTreeSet<_> = self
+			.services
+			.rooms
+			.timeline
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.filter_map(|(_, pdu)| async move {
+				self.services
+					.rooms
+					.state_accessor
+					.pdu_shortstatehash(&pdu.event_id)
+					.await
+					.ok()
+			})
+			.chain(stream::once(future::ready(current)))
+			.collect()
+			.await;
+
+		self.services
+			.rooms
+			.state_compressor
+			.recompress(&shortstatehashes, &mut totals)
+			.await?;
+	}
+
+	let saved = totals.bytes_before.saturating_sub(totals.bytes_after);
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Recompressed {} states of {} rooms, {} of them deduplicated. State diffs take {} \
+		 instead of {}, saving {}.",
+		totals.states,
+		rooms.len(),
+		totals.deduplicated,
+		pretty(totals.bytes_after),
+		pretty(totals.bytes_before),
+		pretty(saved),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn prune_status(&self, now: bool) -> Result<RoomMessageEventContent> {
+	let pruning = &self.services.pruning;
+	if now {
+		pruning.prune().await;
+	}
+
+	let stats = pruning.stats();
+	let mut out =
+		"| Table | Retention | Pruned | Last run |\n| --- | --- | --- | --- |\n".to_owned();
+	for table in Table::ALL {
+		let retention = match pruning.retention(table) {
+			| None => "disabled".to_owned(),
+			| Some(0) => "superseded".to_owned(),
+			| Some(secs) => time::pretty(Duration::from_secs(secs)),
+		};
+
+		let Some(stats) = stats.get(&table) else {
+			writeln!(out, "| {table} | {retention} | 0 | never |")?;
+			continue;
+		};
+
+		let ago = stats
+			.last_run
+			.and_then(|last_run| last_run.elapsed().ok())
+			.unwrap_or_default();
+
+		writeln!(
+			out,
+			"| {table} | {retention} | {} | {} ago, {} entries in {:?} |",
+			stats.pruned,
+			time::pretty(ago),
+			stats.last_pruned,
+			stats.last_duration,
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn audit_log(
+	&self,
+	since: Option<String>,
+	limit: usize,
+) -> Result<RoomMessageEventContent> {
+	let since = since
+		.as_deref()
+		.map(time::parse_timepoint_ago)
+		.transpose()?
+		.and_then(MilliSecondsSinceUnixEpoch::from_system_time)
+		.unwrap_or(MilliSecondsSinceUnixEpoch(UInt::MIN));
+
+	let entries: Vec<_> = self
+		.services
+		.audit
+		.entries_since(since)
+		.take(limit)
+		.collect()
+		.await;
+
+	if entries.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No audit log entries found."));
+	}
+
+	let mut out =
+		"| Time | User | Kind | Action | Result |\n| --- | --- | --- | --- | --- |\n".to_owned();
+	for entry in &entries {
+		let user = entry
+			.user
+			.as_ref()
+			.map_or_else(|| "console".to_owned(), ToString::to_string);
+
+		let result = entry.error.as_deref().unwrap_or("ok");
+		writeln!(
+			out,
+			"| {} | {user} | {} | `{}` | {} |",
+			time::rfc2822_from_seconds(entry.ts.as_secs().into()),
+			entry.kind,
+			entry.action.replace('|', "\\|"),
+			result.replace('|', "\\|").replace('\n', " "),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn maintenance(
+	&self,
+	state: Switch,
+	message: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let globals = &self.services.globals;
+	let out = match state {
+		| Switch::On => {
+			let message = message.unwrap_or_else(|| MAINTENANCE_MESSAGE.to_owned());
+			globals.set_maintenance(Some(message.clone()));
+			warn!("Maintenance mode turned on: {message}");
+			format!("Maintenance mode is on. Users other than admins are told: {message}")
+		},
+		| Switch::Off => {
+			if globals.maintenance().is_none() {
+				return Err!("Maintenance mode is not on.");
+			}
+
+			globals.set_maintenance(None);
+			info!("Maintenance mode turned off");
+			"Maintenance mode is off.".to_owned()
+		},
+	};
+
+	Ok(RoomMessageEventContent::notice_plain(out))
+}
+
+#[admin_command]
+pub(super) async fn maintenance_queue(&self) -> Result<RoomMessageEventContent> {
+	let maintenance = &self.services.maintenance;
+	let load = maintenance
+		.load()
+		.map_or_else(|| "not measured yet".to_owned(), |load| format!("{load} requests/min"));
+
+	let mut out = format!(
+		"Server is {}quiet ({load}).\n",
+		if maintenance.is_quiet() { "" } else { "not " }
+	);
+
+	let queued = maintenance.queued();
+	if queued.is_empty() {
+		writeln!(out, "No maintenance queued.")?;
+	}
+
+	for job in queued {
+		let waited = job.queued_at.elapsed().unwrap_or_default();
+		writeln!(out, "#{} {} (queued {} ago)", job.id, job.name, time::pretty(waited))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn maintenance_run(&self, id: Option<u64>) -> Result<RoomMessageEventContent> {
+	let count = self.services.maintenance.run_now(id).await;
+	if count == 0 {
+		return Err!("No matching maintenance queued.");
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!("Ran {count} maintenance jobs.")))
+}
+
+#[admin_command]
+pub(super) async fn backup_database(&self, incremental: bool) -> Result<RoomMessageEventContent> {
 	let db = Arc::clone(&self.services.db);
 	let mut result = self
 		.services
 		.server
 		.runtime()
-		.spawn_blocking(move || match db.db.backup() {
+		.spawn_blocking(move || match db.db.backup(incremental) {
 			| Ok(()) => String::new(),
 			| Err(e) => e.to_string(),
 		})
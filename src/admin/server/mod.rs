@@ -1,10 +1,14 @@
 mod commands;
+mod rate_limits;
+mod registration_tokens;
 
 use std::path::PathBuf;
 
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
 use conduwuit::Result;
+use ruma::OwnedRoomId;
 
+use self::{rate_limits::RateLimitsCommand, registration_tokens::RegistrationTokensCommand};
 use crate::admin_command_dispatch;
 
 #[admin_command_dispatch]
@@ -17,6 +21,9 @@ pub(super) enum ServerCommand {
 	ShowConfig,
 
 	/// - Reload configuration values
+	///
+	/// Lists the settings which changed, split into those which took effect
+	/// and those which require a restart.
 	ReloadConfig {
 		path: Option<PathBuf>,
 	},
@@ -36,16 +43,122 @@ pub(super) enum ServerCommand {
 	/// - Print database memory usage statistics
 	MemoryUsage,
 
+	/// - Show latency percentiles and error rates of each client and federation
+	///   endpoint over the last minutes (at most 60)
+	RequestStats {
+		#[arg(short, long, default_value("5"))]
+		minutes: u64,
+	},
+
+	/// - Print allocator statistics: totals, pages held by each arena and the
+	///   fragmentation of each size class
+	///
+	/// With --purge, dirty pages are released to the system first.
+	Memory {
+		#[arg(long)]
+		purge: bool,
+	},
+
 	/// - Clears all of Conduwuit's caches
 	ClearCaches,
 
+	/// - Set the capacity of a cache in entries, until the next restart
+	///
+	/// The name is one of auth_chain, stateinfo or spacehierarchy, or a
+	/// database column with a cache, e.g. pduid_pdu for events.
+	SetCache {
+		name: String,
+		entries: usize,
+	},
+
 	/// - Performs an online backup of the database (only available for RocksDB
 	///   at the moment)
-	BackupDatabase,
+	///
+	/// With --incremental, the memtables are not flushed first and the
+	/// write-ahead log is copied instead, so the backup copies no table files
+	/// written just for it.
+	BackupDatabase {
+		#[arg(long)]
+		incremental: bool,
+	},
 
 	/// - List database backups
 	ListBackups,
 
+	/// - Report the on-disk size of each database column and estimate the
+	///   storage used by the largest rooms
+	///
+	/// The events of the largest rooms are read in full to count them and
+	/// their media references, which may take a while for large rooms.
+	DbUsage {
+		/// Number of rooms to report on.
+		#[arg(short, long, default_value("10"))]
+		top: usize,
+	},
+
+	/// - Rebuild the state diff chains of rooms and deduplicate identical
+	///   states
+	///
+	/// The states of each room are re-layered oldest first, shortening the
+	/// chains long-lived rooms accumulate. Reports the bytes saved.
+	RecompressState {
+		/// Only this room; all rooms without.
+		#[arg(long)]
+		room: Option<OwnedRoomId>,
+	},
+
+	/// - List database maintenance waiting for a quiet period
+	MaintenanceQueue,
+
+	/// - Turn maintenance mode on or off
+	///
+	/// While on, users other than admins may not send events to rooms: their
+	/// messages, state changes, membership and profile changes are refused
+	/// with M_RESOURCE_LIMIT_EXCEEDED and the message, which clients show
+	/// them. Federation and appservices are not affected. Stays on across
+	/// restarts.
+	Maintenance {
+		state: Switch,
+
+		/// Message shown to users.
+		#[arg(long)]
+		message: Option<String>,
+	},
+
+	/// - Run queued database maintenance now regardless of load
+	MaintenanceRun {
+		/// Job to run; all queued jobs without.
+		id: Option<u64>,
+	},
+
+	/// - Show the retention of ephemeral data and how much was pruned
+	PruneStatus {
+		/// Prune the ephemeral data past its retention now rather than at
+		/// the next hourly run, before showing the status.
+		#[arg(long)]
+		now: bool,
+	},
+
+	/// - List the admin commands executed and the API calls which used admin
+	///   privileges, newest first
+	AuditLog {
+		/// Only entries from this long ago (e.g. "1d", "12h"); all without.
+		#[arg(long)]
+		since: Option<String>,
+
+		/// Number of entries to list.
+		#[arg(short, long, default_value("50"))]
+		limit: usize,
+	},
+
+	#[command(subcommand)]
+	/// - Manage registration tokens (MSC3231)
+	RegistrationTokens(RegistrationTokensCommand),
+
+	#[command(subcommand)]
+	/// - Inspect and reset rate limit buckets
+	RateLimits(RateLimitsCommand),
+
 	/// - Send a message to the admin room.
 	AdminNotice {
 		message: Vec<String>,
@@ -65,3 +178,9 @@ pub(super) enum ServerCommand {
 	/// - Shutdown the server
 	Shutdown,
 }
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(super) enum Switch {
+	On,
+	Off,
+}
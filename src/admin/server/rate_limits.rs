@@ -0,0 +1,66 @@
+use std::fmt::Write;
+
+use clap::Subcommand;
+use conduwuit::{Result, utils::time};
+use ruma::events::room::message::RoomMessageEventContent;
+use service::ratelimit::Class;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RateLimitsCommand {
+	/// - List rate limit buckets which are not full
+	List {
+		/// Only list buckets of this endpoint class (login, registration,
+		/// message, join, invite, media, federation)
+		#[arg(short, long)]
+		class: Option<Class>,
+	},
+
+	/// - Reset rate limit buckets
+	Reset {
+		/// The user ID, server name or IP address to reset the buckets of; all
+		/// buckets are reset if omitted
+		subject: Option<String>,
+
+		/// Only reset buckets of this endpoint class
+		#[arg(short, long)]
+		class: Option<Class>,
+	},
+}
+
+#[admin_command]
+async fn list(&self, class: Option<Class>) -> Result<RoomMessageEventContent> {
+	let buckets = self.services.ratelimit.buckets(class);
+	if buckets.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("All rate limit buckets are full."));
+	}
+
+	let mut out = format!("Rate limit buckets ({}):\n", buckets.len());
+	for bucket in &buckets {
+		writeln!(
+			out,
+			"- {} `{}` ({}): full again in {}",
+			bucket.class,
+			bucket.subject,
+			bucket.dimension,
+			time::pretty(bucket.refills_in)
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn reset(
+	&self,
+	subject: Option<String>,
+	class: Option<Class>,
+) -> Result<RoomMessageEventContent> {
+	let count = self.services.ratelimit.reset(class, subject.as_deref());
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Reset {count} rate limit buckets."
+	)))
+}
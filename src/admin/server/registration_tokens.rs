@@ -0,0 +1,132 @@
+use std::fmt::Write;
+
+use clap::Subcommand;
+use conduwuit::{
+	Result,
+	utils::{ReadyExt, time},
+};
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+use service::registration_tokens::TokenInfo;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RegistrationTokensCommand {
+	/// - Issue a new registration token
+	Create {
+		/// The token to issue; a random token is generated if omitted
+		token: Option<String>,
+
+		/// Number of registrations the token may be used for
+		#[arg(short, long)]
+		uses_allowed: Option<u64>,
+
+		/// Time after which the token expires (e.g. "7d", "12h")
+		#[arg(short, long)]
+		expires_in: Option<String>,
+	},
+
+	/// - List issued registration tokens
+	List {
+		/// Only list tokens which can still be used
+		#[arg(long)]
+		valid_only: bool,
+	},
+
+	/// - Delete a registration token
+	Revoke {
+		token: String,
+	},
+
+	/// - Expire a registration token now while keeping its record
+	Expire {
+		token: String,
+	},
+}
+
+#[admin_command]
+async fn create(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expires_in: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let expires_in = expires_in
+		.as_deref()
+		.map(time::parse_duration)
+		.transpose()?;
+
+	let (token, info) = self
+		.services
+		.registration_tokens
+		.issue_token(token, uses_allowed, expires_in)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Issued registration token `{token}` ({}).",
+		describe(&info)
+	)))
+}
+
+#[admin_command]
+async fn list(&self, valid_only: bool) -> Result<RoomMessageEventContent> {
+	let tokens: Vec<_> = self
+		.services
+		.registration_tokens
+		.tokens()
+		.ready_filter(|(_, info)| !valid_only || info.is_valid())
+		.collect()
+		.await;
+
+	if tokens.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No registration tokens found."));
+	}
+
+	let mut out = format!("Registration tokens ({}):\n", tokens.len());
+	for (token, info) in &tokens {
+		writeln!(out, "- `{token}`: {}", describe(info))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn revoke(&self, token: String) -> Result<RoomMessageEventContent> {
+	self.services
+		.registration_tokens
+		.revoke_token(&token)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Revoked registration token {token:?}."
+	)))
+}
+
+#[admin_command]
+async fn expire(&self, token: String) -> Result<RoomMessageEventContent> {
+	self.services
+		.registration_tokens
+		.expire_token(&token)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Expired registration token {token:?}."
+	)))
+}
+
+fn describe(info: &TokenInfo) -> String {
+	let uses = info
+		.uses_allowed
+		.map_or_else(|| "unlimited".to_owned(), |allowed| allowed.to_string());
+
+	let expiry = info.expiry_time.map_or_else(
+		|| "never".to_owned(),
+		|expiry| time::rfc2822_from_seconds((expiry / 1000).try_into().unwrap_or(i64::MAX)),
+	);
+
+	let status = if info.is_valid() { "valid" } else { "invalid" };
+
+	format!("{status}, used {}/{uses}, expires {expiry}", info.completed)
+}
@@ -24,3 +24,14 @@ fn get_help_inner(input: &str) {
 	assert!(error.contains("Commands:"));
 	assert!(error.contains("Options:"));
 }
+
+#[test]
+fn audit_line_redacts_secrets() {
+	use crate::processor::audit_line;
+
+	let args = ["admin", "users", "reset-password", "alice", "hunter2"].map(ToOwned::to_owned);
+	assert_eq!(audit_line(&args), "admin users reset-password alice <redacted>");
+
+	let args = ["admin", "users", "list-users"].map(ToOwned::to_owned);
+	assert_eq!(audit_line(&args), "admin users list-users");
+}
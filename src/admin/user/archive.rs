@@ -0,0 +1,445 @@
+//! Portable account archive
+//!
+//! An archive is a directory holding the manifest `archive.json` and the
+//! media uploaded by the user under `media/`. The format is described in
+//! docs/maintenance.md; readers refuse manifests of a newer version.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	ffi::OsStr,
+	path::{Component, Path},
+};
+
+use api::client::join_room_by_id_helper;
+use conduwuit::{
+	Err, Result, debug_warn, err,
+	utils::{self, content_disposition::make_content_disposition},
+};
+use futures::StreamExt;
+use ruma::{
+	Mxc, OwnedDeviceId, OwnedMxcUri, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, UserId,
+	api::client::account_data::AnyRawAccountDataEvent,
+	encryption::{CrossSigningKey, DeviceKeys},
+	serde::Raw,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use service::{Services, media::MXC_LENGTH};
+use tokio::fs;
+
+const FORMAT: &str = "org.conduwuit.account_archive";
+const VERSION: u32 = 1;
+const MANIFEST: &str = "archive.json";
+const MEDIA_DIR: &str = "media";
+
+/// Number of servers recorded to rejoin each room through
+const VIA_SERVERS: usize = 3;
+
+const REJOIN_REASON: &str = "Imported from an account archive";
+
+#[derive(Deserialize, Serialize)]
+pub(super) struct Archive {
+	format: String,
+	version: u32,
+	user_id: OwnedUserId,
+	exported_at: u64,
+
+	#[serde(default)]
+	profile: Profile,
+
+	#[serde(default)]
+	account_data: AccountData,
+
+	#[serde(default)]
+	rooms: Vec<Membership>,
+
+	#[serde(default)]
+	media: Vec<Media>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	keys: Option<Keys>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Profile {
+	displayname: Option<String>,
+	avatar_url: Option<OwnedMxcUri>,
+	blurhash: Option<String>,
+
+	/// Custom profile fields (MSC4133)
+	#[serde(default)]
+	fields: BTreeMap<String, JsonValue>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct AccountData {
+	#[serde(default)]
+	global: Vec<JsonValue>,
+
+	#[serde(default)]
+	rooms: BTreeMap<OwnedRoomId, Vec<JsonValue>>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Membership {
+	room_id: OwnedRoomId,
+
+	/// Servers to join the room through
+	#[serde(default)]
+	via: Vec<OwnedServerName>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Media {
+	mxc: OwnedMxcUri,
+	content_type: Option<String>,
+	filename: Option<String>,
+
+	/// Path of the file relative to the archive
+	file: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Keys {
+	master: Option<Raw<CrossSigningKey>>,
+	self_signing: Option<Raw<CrossSigningKey>>,
+	user_signing: Option<Raw<CrossSigningKey>>,
+
+	#[serde(default)]
+	devices: BTreeMap<OwnedDeviceId, Raw<DeviceKeys>>,
+}
+
+/// Counts of what was restored from an archive
+#[derive(Default)]
+pub(super) struct Imported {
+	pub(super) account_data: usize,
+	pub(super) media: usize,
+	pub(super) rooms_joined: usize,
+	pub(super) rooms_failed: usize,
+	pub(super) keys: bool,
+}
+
+impl Archive {
+	pub(super) fn media_count(&self) -> usize { self.media.len() }
+
+	pub(super) fn room_count(&self) -> usize { self.rooms.len() }
+}
+
+/// Write the archive of a local user into the directory `dir`, which must
+/// not hold an archive already.
+pub(super) async fn export(
+	services: &Services,
+	user_id: &UserId,
+	dir: &Path,
+	include_keys: bool,
+) -> Result<Archive> {
+	let manifest = dir.join(MANIFEST);
+	if fs::try_exists(&manifest).await? {
+		return Err!("{manifest:?} already exists.");
+	}
+
+	fs::create_dir_all(dir.join(MEDIA_DIR)).await?;
+
+	let users = &services.users;
+	let profile = Profile {
+		displayname: users.displayname(user_id).await.ok(),
+		avatar_url: users.avatar_url(user_id).await.ok(),
+		blurhash: users.blurhash(user_id).await.ok(),
+		fields: users.all_profile_keys(user_id).collect().await,
+	};
+
+	let global = services
+		.account_data
+		.changes_since(None, user_id, 0, None)
+		.filter_map(|event| async move {
+			match event {
+				| AnyRawAccountDataEvent::Global(event) => event.deserialize_as().ok(),
+				| AnyRawAccountDataEvent::Room(_) => None,
+			}
+		})
+		.collect()
+		.await;
+
+	let room_ids: Vec<OwnedRoomId> = services
+		.rooms
+		.state_cache
+		.rooms_joined(user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut room_account_data = BTreeMap::new();
+	let mut rooms = Vec::with_capacity(room_ids.len());
+	for room_id in room_ids {
+		let events: Vec<JsonValue> = services
+			.account_data
+			.changes_since(Some(&room_id), user_id, 0, None)
+			.filter_map(|event| async move {
+				match event {
+					| AnyRawAccountDataEvent::Room(event) => event.deserialize_as().ok(),
+					| AnyRawAccountDataEvent::Global(_) => None,
+				}
+			})
+			.collect()
+			.await;
+
+		if !events.is_empty() {
+			room_account_data.insert(room_id.clone(), events);
+		}
+
+		let via = services
+			.rooms
+			.state_cache
+			.room_servers(&room_id)
+			.take(VIA_SERVERS)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		rooms.push(Membership { room_id, via });
+	}
+
+	let mut media = Vec::new();
+	for mxc in services.media.get_user_mxcs(user_id).await {
+		let Ok(parts) = mxc.parts() else {
+			continue;
+		};
+
+		let Ok(Some(meta)) = services.media.get(&parts).await else {
+			debug_warn!(%mxc, "Skipping media missing from the media store");
+			continue;
+		};
+
+		let file = format!("{MEDIA_DIR}/{}", parts.media_id);
+		fs::write(dir.join(&file), meta.content.unwrap_or_default()).await?;
+		media.push(Media {
+			mxc: mxc.clone(),
+			content_type: meta.content_type,
+			filename: meta
+				.content_disposition
+				.and_then(|content_disposition| content_disposition.filename),
+			file,
+		});
+	}
+
+	let keys = if include_keys {
+		let allowed_signatures = |_: &UserId| true;
+		let mut keys = Keys {
+			master: users
+				.get_master_key(None, user_id, &allowed_signatures)
+				.await
+				.ok(),
+			self_signing: users
+				.get_self_signing_key(None, user_id, &allowed_signatures)
+				.await
+				.ok(),
+			user_signing: users.get_user_signing_key(user_id).await.ok(),
+			devices: BTreeMap::new(),
+		};
+
+		let device_ids: Vec<OwnedDeviceId> = users
+			.all_device_ids(user_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for device_id in device_ids {
+			if let Ok(device_keys) = users.get_device_keys(user_id, &device_id).await {
+				keys.devices.insert(device_id, device_keys);
+			}
+		}
+
+		Some(keys)
+	} else {
+		None
+	};
+
+	let archive = Archive {
+		format: FORMAT.to_owned(),
+		version: VERSION,
+		user_id: user_id.to_owned(),
+		exported_at: utils::millis_since_unix_epoch(),
+		profile,
+		account_data: AccountData { global, rooms: room_account_data },
+		rooms,
+		media,
+		keys,
+	};
+
+	fs::write(&manifest, serde_json::to_vec_pretty(&archive)?).await?;
+
+	Ok(archive)
+}
+
+/// Restore the archive in the directory `dir` into an existing local user.
+/// Media is uploaded under new MXC URIs; keys are only restored into the user
+/// they were exported from.
+pub(super) async fn import(
+	services: &Services,
+	user_id: &UserId,
+	dir: &Path,
+	join_rooms: bool,
+) -> Result<Imported> {
+	let manifest = fs::read(dir.join(MANIFEST)).await?;
+	let archive: Archive = serde_json::from_slice(&manifest)
+		.map_err(|e| err!("Invalid account archive manifest: {e}"))?;
+
+	if archive.format != FORMAT {
+		return Err!("Not an account archive: format is {:?}", archive.format);
+	}
+
+	if archive.version > VERSION {
+		return Err!(
+			"Account archive version {} is newer than the supported version {VERSION}.",
+			archive.version
+		);
+	}
+
+	let mut imported = Imported::default();
+	let mut mxcs: HashMap<OwnedMxcUri, OwnedMxcUri> = HashMap::new();
+	for media in &archive.media {
+		if !is_media_path(&media.file) {
+			return Err!(
+				"Media file {:?} is outside of the archive's media directory.",
+				media.file
+			);
+		}
+
+		let file = fs::read(dir.join(&media.file)).await?;
+		let media_id = utils::random_string(MXC_LENGTH);
+		let mxc = Mxc {
+			server_name: services.globals.server_name(),
+			media_id: &media_id,
+		};
+
+		let content_disposition = make_content_disposition(
+			None,
+			media.content_type.as_deref(),
+			media.filename.as_deref(),
+		);
+
+		services
+			.media
+			.create(
+				&mxc,
+				Some(user_id),
+				Some(&content_disposition),
+				media.content_type.as_deref(),
+				&file,
+			)
+			.await?;
+
+		mxcs.insert(media.mxc.clone(), mxc.to_string().into());
+		imported.media = imported.media.saturating_add(1);
+	}
+
+	let Profile {
+		displayname,
+		avatar_url,
+		blurhash,
+		fields,
+	} = archive.profile;
+	let avatar_url = avatar_url.map(|mxc| mxcs.get(&mxc).cloned().unwrap_or(mxc));
+	services.users.set_displayname(user_id, displayname);
+	services.users.set_avatar_url(user_id, avatar_url);
+	services.users.set_blurhash(user_id, blurhash);
+	for (key, value) in fields {
+		services.users.set_profile_key(user_id, &key, Some(value));
+	}
+
+	let global = archive
+		.account_data
+		.global
+		.iter()
+		.map(|event| (None, event));
+
+	let rooms = archive
+		.account_data
+		.rooms
+		.iter()
+		.flat_map(|(room_id, events)| {
+			let room_id: &RoomId = room_id;
+			events.iter().map(move |event| (Some(room_id), event))
+		});
+
+	for (room_id, event) in global.chain(rooms) {
+		let Some(kind) = event.get("type").and_then(JsonValue::as_str) else {
+			continue;
+		};
+
+		services
+			.account_data
+			.update(room_id, user_id, kind.into(), event)
+			.await?;
+
+		imported.account_data = imported.account_data.saturating_add(1);
+	}
+
+	if let Some(keys) = archive.keys.filter(|_| &*archive.user_id == user_id) {
+		services
+			.users
+			.add_cross_signing_keys(
+				user_id,
+				&keys.master,
+				&keys.self_signing,
+				&keys.user_signing,
+				true,
+			)
+			.await?;
+
+		for (device_id, device_keys) in &keys.devices {
+			if services
+				.users
+				.get_device_metadata(user_id, device_id)
+				.await
+				.is_ok()
+			{
+				services
+					.users
+					.add_device_keys(user_id, device_id, device_keys)
+					.await;
+			}
+		}
+
+		imported.keys = true;
+	}
+
+	if !join_rooms {
+		return Ok(imported);
+	}
+
+	for Membership { room_id, via } in &archive.rooms {
+		match join_room_by_id_helper(
+			services,
+			user_id,
+			room_id,
+			Some(REJOIN_REASON.to_owned()),
+			via,
+			None,
+			&None,
+		)
+		.await
+		{
+			| Ok(_) => imported.rooms_joined = imported.rooms_joined.saturating_add(1),
+			| Err(e) => {
+				debug_warn!(%room_id, "Failed to rejoin room from archive: {e}");
+				imported.rooms_failed = imported.rooms_failed.saturating_add(1);
+			},
+		}
+	}
+
+	Ok(imported)
+}
+
+/// Whether `file` is a plain relative path below the media directory of the
+/// archive, without any root, prefix or parent directory components.
+fn is_media_path(file: &str) -> bool {
+	let components: Vec<_> = Path::new(file).components().collect();
+	match components.as_slice() {
+		| [Component::Normal(dir), rest @ ..]
+			if *dir == OsStr::new(MEDIA_DIR) && !rest.is_empty() =>
+			rest.iter()
+				.all(|component| matches!(component, Component::Normal(_))),
+		| _ => false,
+	}
+}
@@ -1,16 +1,20 @@
-use std::{collections::BTreeMap, fmt::Write as _};
+use std::{collections::BTreeMap, fmt::Write as _, path::PathBuf};
 
 use api::client::{full_user_deactivate, join_room_by_id_helper, leave_room};
 use conduwuit::{
-	Result, debug, debug_warn, error, info, is_equal_to,
+	Result,
+	config::ForbiddenUsernameAction,
+	debug, debug_warn, info, is_equal_to,
 	matrix::pdu::PduBuilder,
-	utils::{self, ReadyExt},
+	utils::{self, ReadyExt, time},
 	warn,
 };
 use conduwuit_api::client::{leave_all_rooms, update_avatar_url, update_displayname};
 use futures::StreamExt;
 use ruma::{
-	EventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId, UserId,
+	EventId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedRoomId, OwnedRoomOrAliasId,
+	OwnedUserId, RoomId, UserId,
+	api::client::device::Device,
 	events::{
 		RoomAccountDataEventType, StateEventType,
 		room::{
@@ -22,6 +26,7 @@
 	},
 };
 
+use super::archive;
 use crate::{
 	admin_command, get_room_info,
 	utils::{parse_active_local_user_id, parse_local_user_id},
@@ -32,19 +37,16 @@
 
 #[admin_command]
 pub(super) async fn list_users(&self) -> Result<RoomMessageEventContent> {
-	let users: Vec<_> = self
-		.services
-		.users
-		.list_local_users()
-		.map(ToString::to_string)
-		.collect()
-		.await;
+	self.write_str("Local user accounts:\n```\n").await?;
 
-	let mut plain_msg = format!("Found {} local user account(s):\n```\n", users.len());
-	plain_msg += users.join("\n").as_str();
-	plain_msg += "\n```";
+	let mut count: usize = 0;
+	let mut users = self.services.users.list_local_users().boxed();
+	while let Some(user_id) = users.next().await {
+		writeln!(self, "{user_id}").await?;
+		count = count.saturating_add(1);
+	}
 
-	self.write_str(plain_msg.as_str()).await?;
+	writeln!(self, "```\nFound {count} local user account(s).").await?;
 
 	Ok(RoomMessageEventContent::text_plain(""))
 }
@@ -116,60 +118,7 @@ pub(super) async fn create_user(
 		.await?;
 
 	if !self.services.server.config.auto_join_rooms.is_empty() {
-		for room in &self.services.server.config.auto_join_rooms {
-			let Ok(room_id) = self.services.rooms.alias.resolve(room).await else {
-				error!(%user_id, "Failed to resolve room alias to room ID when attempting to auto join {room}, skipping");
-				continue;
-			};
-
-			if !self
-				.services
-				.rooms
-				.state_cache
-				.server_in_room(self.services.globals.server_name(), &room_id)
-				.await
-			{
-				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
-				);
-				continue;
-			}
-
-			if let Some(room_server_name) = room.server_name() {
-				match join_room_by_id_helper(
-					self.services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[
-						self.services.globals.server_name().to_owned(),
-						room_server_name.to_owned(),
-					],
-					None,
-					&None,
-				)
-				.await
-				{
-					| Ok(_response) => {
-						info!("Automatically joined room {room} for user {user_id}");
-					},
-					| Err(e) => {
-						self.services
-							.admin
-							.send_message(RoomMessageEventContent::text_plain(format!(
-								"Failed to automatically join room {room} for user {user_id}: \
-								 {e}"
-							)))
-							.await
-							.ok();
-						// don't return this error so we don't fail registrations
-						error!(
-							"Failed to automatically join room {room} for user {user_id}: {e}"
-						);
-					},
-				}
-			}
-		}
+		self.services.auto_join.join(&user_id).await;
 	}
 
 	// we dont add a device since we're not the user, just the creator
@@ -259,6 +208,18 @@ pub(super) async fn reset_password(
 		));
 	}
 
+	if let Some(password) = &password {
+		if let Err(e) = self
+			.services
+			.users
+			.check_password_policy(&user_id, password)
+		{
+			return Ok(RoomMessageEventContent::text_plain(format!(
+				"Password does not satisfy the password policy: {e}"
+			)));
+		}
+	}
+
 	let new_password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
 	match self
@@ -275,6 +236,321 @@ pub(super) async fn reset_password(
 	}
 }
 
+#[admin_command]
+pub(super) async fn list_pending(&self) -> Result<RoomMessageEventContent> {
+	let users: Vec<_> = self
+		.services
+		.users
+		.list_pending_approval()
+		.map(ToString::to_string)
+		.collect()
+		.await;
+
+	if users.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No accounts are awaiting approval."));
+	}
+
+	let mut plain_msg = format!("Accounts awaiting approval ({}):\n```\n", users.len());
+	plain_msg += users.join("\n").as_str();
+	plain_msg += "\n```";
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
+#[admin_command]
+pub(super) async fn approve(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.is_pending_approval(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is not awaiting approval."
+		)));
+	}
+
+	self.services.users.set_pending_approval(&user_id, false);
+	info!("Approved account {user_id}");
+
+	// auto-joining was skipped when the account was registered
+	if !self.services.server.config.auto_join_rooms.is_empty() {
+		self.services.auto_join.join(&user_id).await;
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} has been approved and can now log in."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn deny(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.is_pending_approval(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is not awaiting approval."
+		)));
+	}
+
+	full_user_deactivate(self.services, &user_id, &[]).await?;
+	self.services.users.set_pending_approval(&user_id, false);
+	info!("Denied and deactivated account {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} has been denied and deactivated."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn lock(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to lock the server service account.",
+		));
+	}
+
+	if self.services.users.is_locked(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is already locked.")));
+	}
+
+	self.services.users.set_locked(&user_id, true);
+	info!("Locked account {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} has been locked. Their sessions are kept but can only be used to log out."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn rename_device(
+	&self,
+	user_id: String,
+	device_id: OwnedDeviceId,
+	display_name: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let display_name = display_name.join(" ");
+	if display_name.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("The display name is empty."));
+	}
+
+	let Ok(mut device) = self
+		.services
+		.users
+		.get_device_metadata(&user_id, &device_id)
+		.await
+	else {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} has no device {device_id}."
+		)));
+	};
+
+	device.display_name = Some(display_name.clone());
+	self.services
+		.users
+		.update_device_metadata(&user_id, &device_id, &device)
+		.await?;
+
+	info!("Renamed device {device_id} of {user_id} to {display_name:?}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Renamed device {device_id} of {user_id} to {display_name:?}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn device_info(
+	&self,
+	user_id: String,
+	device_id: Option<OwnedDeviceId>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let devices: Vec<Device> = match device_id {
+		| Some(device_id) => {
+			let Ok(device) = self
+				.services
+				.users
+				.get_device_metadata(&user_id, &device_id)
+				.await
+			else {
+				return Ok(RoomMessageEventContent::text_plain(format!(
+					"{user_id} has no device {device_id}."
+				)));
+			};
+
+			vec![device]
+		},
+		| None =>
+			self.services
+				.users
+				.all_devices_metadata(&user_id)
+				.collect()
+				.await,
+	};
+
+	if devices.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(format!("{user_id} has no devices.")));
+	}
+
+	let mut pushers = Vec::new();
+	for pusher in self.services.pusher.get_pushers(&user_id).await {
+		let device_id = self
+			.services
+			.pusher
+			.get_pusher_device(&pusher.ids.pushkey)
+			.await
+			.ok();
+
+		pushers.push((device_id, pusher));
+	}
+
+	let timestamp = |ts: Option<MilliSecondsSinceUnixEpoch>| {
+		ts.map_or_else(
+			|| "unknown".to_owned(),
+			|ts| time::rfc2822_from_seconds(ts.as_secs().into()),
+		)
+	};
+
+	let mut out = format!("Devices of {user_id} ({}):\n", devices.len());
+	for device in &devices {
+		let created = self
+			.services
+			.users
+			.get_device_created(&user_id, &device.device_id)
+			.await
+			.ok();
+
+		writeln!(out, "\n{}", device.device_id)?;
+		writeln!(out, "  Display name: {}", device.display_name.as_deref().unwrap_or(""))?;
+		writeln!(out, "  Created: {}", timestamp(created))?;
+		writeln!(out, "  Last seen: {}", timestamp(device.last_seen_ts))?;
+		writeln!(out, "  Last seen IP: {}", device.last_seen_ip.as_deref().unwrap_or("unknown"))?;
+
+		let device_pushers: Vec<_> = pushers
+			.iter()
+			.filter(|(pusher_device, _)| pusher_device.as_ref() == Some(&device.device_id))
+			.map(|(_, pusher)| format!("{} ({})", pusher.ids.app_id, pusher.app_display_name))
+			.collect();
+
+		if device_pushers.is_empty() {
+			writeln!(out, "  Pushers: none")?;
+		} else {
+			writeln!(out, "  Pushers: {}", device_pushers.join(", "))?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(out))
+}
+
+#[admin_command]
+pub(super) async fn enforce_forbidden_usernames(
+	&self,
+	report_only: bool,
+) -> Result<RoomMessageEventContent> {
+	let action = self.services.server.config.forbidden_usernames_enforcement;
+	let users = if report_only {
+		self.services.users.forbidden_users().await
+	} else {
+		self.services
+			.users
+			.enforce_forbidden_usernames(action)
+			.await
+	};
+
+	if !report_only && action == ForbiddenUsernameAction::Deactivate {
+		for user in users.iter().filter(|user| !user.is_admin) {
+			let all_joined_rooms: Vec<OwnedRoomId> = self
+				.services
+				.rooms
+				.state_cache
+				.rooms_joined(&user.user_id)
+				.map(Into::into)
+				.collect()
+				.await;
+
+			full_user_deactivate(self.services, &user.user_id, &all_joined_rooms).await?;
+		}
+	}
+
+	if users.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"No active local users match `forbidden_usernames`.",
+		));
+	}
+
+	let mut msg = format!("{} local user(s) match `forbidden_usernames`:\n```\n", users.len());
+	for user in &users {
+		let admin = if user.is_admin { " (admin)" } else { "" };
+		writeln!(msg, "{}{admin}: {}", user.user_id, user.patterns.join(", "))?;
+	}
+	msg.push_str("```\n");
+
+	let outcome = match action {
+		| _ if report_only => "Nothing was changed. Without `--report-only` the non-admin users \
+		                       are handled according to `forbidden_usernames_enforcement`."
+			.to_owned(),
+		| ForbiddenUsernameAction::Warn =>
+			"Nothing was changed as `forbidden_usernames_enforcement` is \"warn\"; set it to \
+			 \"lock\" or \"deactivate\" to act on these users."
+				.to_owned(),
+		| ForbiddenUsernameAction::Lock => "The non-admin users were locked.".to_owned(),
+		| ForbiddenUsernameAction::Deactivate =>
+			"The non-admin users were deactivated.".to_owned(),
+	};
+	msg.push_str(&outcome);
+
+	Ok(RoomMessageEventContent::text_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn unlock(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.is_locked(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is not locked.")));
+	}
+
+	self.services.users.set_locked(&user_id, false);
+	info!("Unlocked account {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!("{user_id} has been unlocked.")))
+}
+
+#[admin_command]
+pub(super) async fn renew(
+	&self,
+	user_id: String,
+	days: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let expires = self.services.users.renew_account(&user_id, days).await?;
+	info!("Renewed account {user_id}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} has been renewed until {}.",
+		utils::time::format(expires, "%Y-%m-%d %H:%M UTC"),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn set_email(
+	&self,
+	username: String,
+	email: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &username)?;
+
+	self.services
+		.email
+		.set_email(&user_id, email.as_deref())
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(match email {
+		| Some(email) => format!("Bound {email} to {user_id}."),
+		| None => format!("Unbound the email of {user_id}."),
+	}))
+}
+
 #[admin_command]
 pub(super) async fn deactivate_all(
 	&self,
@@ -390,6 +666,48 @@ pub(super) async fn deactivate_all(
 	}
 }
 
+#[admin_command]
+pub(super) async fn export_archive(
+	&self,
+	include_keys: bool,
+	user_id: String,
+	path: PathBuf,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if !self.services.users.exists(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("User {user_id} does not exist")));
+	}
+
+	let archive = archive::export(self.services, &user_id, &path, include_keys).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Exported {user_id} to {path:?}: {} rooms and {} media files.",
+		archive.room_count(),
+		archive.media_count(),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn import_archive(
+	&self,
+	no_join_rooms: bool,
+	user_id: String,
+	path: PathBuf,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+	let imported = archive::import(self.services, &user_id, &path, !no_join_rooms).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Imported {path:?} into {user_id}: {} account data events, {} media files, {} rooms \
+		 joined ({} failed), keys {}.",
+		imported.account_data,
+		imported.media,
+		imported.rooms_joined,
+		imported.rooms_failed,
+		if imported.keys { "restored" } else { "not restored" },
+	)))
+}
+
 #[admin_command]
 pub(super) async fn list_joined_rooms(&self, user_id: String) -> Result<RoomMessageEventContent> {
 	// Validate user id
@@ -424,6 +742,40 @@ pub(super) async fn list_joined_rooms(&self, user_id: String) -> Result<RoomMess
 	Ok(RoomMessageEventContent::notice_markdown(output_plain))
 }
 
+#[admin_command]
+pub(super) async fn list_invites(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.rooms
+		.state_cache
+		.rooms_invited(&user_id)
+		.map(|(room_id, _)| room_id)
+		.collect()
+		.await;
+
+	if rooms.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("User has no pending invites."));
+	}
+
+	let mut out = format!("Pending invites of {user_id} ({}):\n```\n", rooms.len());
+	for room_id in &rooms {
+		let inviter = self
+			.services
+			.rooms
+			.state_cache
+			.invite_sender(&user_id, room_id)
+			.await
+			.map_or_else(|_| "unknown".to_owned(), |sender| sender.to_string());
+
+		writeln!(out, "{room_id}\tInvited by: {inviter}")?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn force_join_list_of_local_users(
 	&self,
@@ -955,3 +1307,26 @@ pub(super) async fn redact_event(
 
 	Ok(RoomMessageEventContent::text_plain(""))
 }
+
+#[admin_command]
+pub(super) async fn send_notice(
+	&self,
+	user_id: String,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+	let message = message.join(" ");
+	if message.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("The notice message is empty."));
+	}
+
+	let event_id = self
+		.services
+		.server_notices
+		.send(&user_id, &RoomMessageEventContent::notice_markdown(message))
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Sent server notice {event_id} to {user_id}"
+	)))
+}
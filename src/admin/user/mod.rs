@@ -1,8 +1,11 @@
+mod archive;
 mod commands;
 
+use std::path::PathBuf;
+
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, OwnedRoomOrAliasId, RoomId};
+use ruma::{EventId, OwnedDeviceId, OwnedRoomOrAliasId, RoomId};
 
 use crate::admin_command_dispatch;
 
@@ -26,6 +29,84 @@ pub(super) enum UserCommand {
 		password: Option<String>,
 	},
 
+	/// - List accounts awaiting approval by an admin (MSC3866)
+	ListPending,
+
+	/// - Approve an account awaiting approval so it can log in
+	Approve {
+		/// Username of the user to approve
+		user_id: String,
+	},
+
+	/// - Reject an account awaiting approval and deactivate it
+	Deny {
+		/// Username of the user to reject
+		user_id: String,
+	},
+
+	/// - Lock a user's account (MSC3939)
+	///
+	/// Locked users keep their sessions but can't use them for anything but
+	/// logging out until they are unlocked. Unlike deactivation, locking is
+	/// fully reversible.
+	Lock {
+		/// Username of the user to lock
+		user_id: String,
+	},
+
+	/// - Unlock a locked user's account
+	Unlock {
+		/// Username of the user to unlock
+		user_id: String,
+	},
+
+	/// - Renew a user's account when account validity is enabled
+	///
+	/// Expired accounts can be used again once renewed.
+	Renew {
+		/// Username of the user to renew
+		user_id: String,
+
+		/// Days to renew the account for, `account_validity.period_days` by
+		/// default
+		#[arg(long)]
+		days: Option<u64>,
+	},
+
+	/// - Apply `forbidden_usernames_enforcement` to the local users whose names
+	///   match `forbidden_usernames`
+	///
+	/// This is also done at startup. Admins are only listed, never acted on.
+	EnforceForbiddenUsernames {
+		/// Only list the matching users without acting on them
+		#[arg(long)]
+		report_only: bool,
+	},
+
+	/// - Bind an email address to a user for password reset, or unbind it
+	SetEmail {
+		/// Username of the user
+		username: String,
+		/// Email address to bind; the current one is unbound if unspecified
+		email: Option<String>,
+	},
+
+	/// - Rename a device of a local user
+	RenameDevice {
+		user_id: String,
+		device_id: OwnedDeviceId,
+		/// New display name of the device
+		display_name: Vec<String>,
+	},
+
+	/// - Show when a local user's devices were created and last seen, and their
+	///   pushers
+	DeviceInfo {
+		user_id: String,
+		/// The device to show; all devices of the user if unspecified
+		device_id: Option<OwnedDeviceId>,
+	},
+
 	/// - Deactivate a user
 	///
 	/// User will be removed from all rooms by default.
@@ -63,12 +144,45 @@ pub(super) enum UserCommand {
 	#[clap(alias = "list")]
 	ListUsers,
 
+	/// - Export a local user's account to a portable archive directory
+	///
+	/// The archive holds the profile, account data, joined rooms and uploaded
+	/// media of the user. Cross-signing and device keys are only included with
+	/// --include-keys.
+	ExportArchive {
+		#[arg(long)]
+		/// Also export cross-signing and device keys
+		include_keys: bool,
+		user_id: String,
+		/// Directory to write the archive to
+		path: PathBuf,
+	},
+
+	/// - Import an account archive into an existing local user
+	///
+	/// Media is uploaded again under new MXC URIs. Keys are only restored
+	/// into the user they were exported from. Rooms are rejoined unless
+	/// --no-join-rooms is given.
+	ImportArchive {
+		#[arg(long)]
+		/// Does not rejoin the rooms of the archive
+		no_join_rooms: bool,
+		user_id: String,
+		/// Directory holding the archive
+		path: PathBuf,
+	},
+
 	/// - Lists all the rooms (local and remote) that the specified user is
 	///   joined in
 	ListJoinedRooms {
 		user_id: String,
 	},
 
+	/// - List the pending invites of a local user
+	ListInvites {
+		user_id: String,
+	},
+
 	/// - Manually join a local user to a room.
 	ForceJoinRoom {
 		user_id: String,
@@ -153,4 +267,14 @@ pub(super) enum UserCommand {
 		#[arg(long)]
 		yes_i_want_to_do_this: bool,
 	},
+
+	/// - Send a server notice to a local user
+	///
+	/// The notice is sent by the server user into the user's server notices
+	/// room, which is created and tagged `m.server_notice` on the first
+	/// notice. The message is formatted as markdown.
+	SendNotice {
+		user_id: String,
+		message: Vec<String>,
+	},
 }
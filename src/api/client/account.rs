@@ -1,28 +1,31 @@
-use std::fmt::Write;
+use std::{fmt::Write, net::IpAddr};
 
-use axum::extract::State;
-use axum_client_ip::InsecureClientIp;
+use axum::{
+	extract::{RawQuery, State},
+	response::{IntoResponse, Redirect},
+};
+use axum_client_ip::{InsecureClientIp, SecureClientIp};
 use conduwuit::{
-	Err, Error, Result, debug_info, err, error, info, is_equal_to,
+	Err, Error, Result, debug_info, err, info, is_equal_to,
 	matrix::pdu::PduBuilder,
 	utils,
 	utils::{ReadyExt, stream::BroadbandExt},
 	warn,
 };
-use conduwuit_service::Services;
-use futures::{FutureExt, StreamExt};
+use conduwuit_service::{Services, spam_checker::Check, users};
+use futures::StreamExt;
 use register::RegistrationKind;
 use ruma::{
-	OwnedRoomId, UserId,
+	OwnedClientSecret, OwnedRoomId, OwnedSessionId, UserId,
 	api::client::{
 		account::{
 			ThirdPartyIdRemovalStatus, change_password, check_registration_token_validity,
 			deactivate, get_3pids, get_username_availability,
 			register::{self, LoginType},
 			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
-			whoami,
+			request_password_change_token_via_email, whoami,
 		},
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, UiaaInfo},
 	},
 	events::{
 		GlobalAccountDataEventType, StateEventType,
@@ -33,8 +36,9 @@
 	},
 	push,
 };
+use serde::Deserialize;
 
-use super::{DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH, join_room_by_id_helper};
+use super::{DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::Ruma;
 
 const RANDOM_USER_ID_LENGTH: usize = 10;
@@ -63,14 +67,6 @@ pub(crate) async fn get_register_available_route(
 			|| appservice.registration.id.contains("matrix_appservice_irc")
 	});
 
-	if services
-		.globals
-		.forbidden_usernames()
-		.is_match(&body.username)
-	{
-		return Err!(Request(Forbidden("Username is forbidden")));
-	}
-
 	// don't force the username lowercase if it's from matrix-appservice-irc
 	let body_username = if is_matrix_appservice_irc {
 		body.username.clone()
@@ -78,6 +74,14 @@ pub(crate) async fn get_register_available_route(
 		body.username.to_lowercase()
 	};
 
+	if services
+		.globals
+		.forbidden_usernames()
+		.is_match(&body_username)
+	{
+		return Err!(Request(Forbidden("Username is forbidden")));
+	}
+
 	// Validate user id
 	let user_id =
 		match UserId::parse_with_server_name(&body_username, services.globals.server_name()) {
@@ -143,11 +147,18 @@ pub(crate) async fn get_register_available_route(
 pub(crate) async fn register_route(
 	State(services): State<crate::State>,
 	InsecureClientIp(client): InsecureClientIp,
+	SecureClientIp(trusted_client): SecureClientIp,
 	body: Ruma<register::v3::Request>,
 ) -> Result<register::v3::Response> {
 	let is_guest = body.kind == RegistrationKind::Guest;
 	let emergency_mode_enabled = services.config.emergency_password.is_some();
 
+	if services.oidc.enabled() && body.appservice_info.is_none() {
+		return Err!(Request(Forbidden(
+			"Registration is delegated to the OpenID Connect provider."
+		)));
+	}
+
 	if !services.config.allow_registration && body.appservice_info.is_none() {
 		match (body.username.as_ref(), body.initial_device_display_name.as_ref()) {
 			| (Some(username), Some(device_display_name)) => {
@@ -170,7 +181,7 @@ pub(crate) async fn register_route(
 	if is_guest
 		&& (!services.config.allow_guest_registration
 			|| (services.config.allow_registration
-				&& services.globals.registration_token.is_some()))
+				&& services.registration_tokens.tokens_required()))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, \
@@ -180,6 +191,10 @@ pub(crate) async fn register_route(
 		return Err!(Request(GuestAccessForbidden("Guest registration is disabled.")));
 	}
 
+	if body.appservice_info.is_none() {
+		services.registration_limits.check(trusted_client).await?;
+	}
+
 	// forbid guests from registering if there is not a real admin user yet. give
 	// generic user error.
 	if is_guest && services.users.count().await < 2 {
@@ -201,12 +216,6 @@ pub(crate) async fn register_route(
 						|| appservice.registration.id.contains("matrix_appservice_irc")
 				});
 
-			if services.globals.forbidden_usernames().is_match(username)
-				&& !emergency_mode_enabled
-			{
-				return Err!(Request(Forbidden("Username is forbidden")));
-			}
-
 			// don't force the username lowercase if it's from matrix-appservice-irc
 			let body_username = if is_matrix_appservice_irc {
 				username.clone()
@@ -214,6 +223,15 @@ pub(crate) async fn register_route(
 				username.to_lowercase()
 			};
 
+			if services
+				.globals
+				.forbidden_usernames()
+				.is_match(&body_username)
+				&& !emergency_mode_enabled
+			{
+				return Err!(Request(Forbidden("Username is forbidden")));
+			}
+
 			let proposed_user_id = match UserId::parse_with_server_name(
 				&body_username,
 				services.globals.server_name(),
@@ -275,9 +293,18 @@ pub(crate) async fn register_route(
 		return Err!(Request(Exclusive("Username is reserved by an appservice.")));
 	}
 
+	// appservices manage the passwords of their users themselves
+	if let Some(password) = body
+		.password
+		.as_deref()
+		.filter(|_| !is_guest && body.appservice_info.is_none())
+	{
+		services.users.check_password_policy(&user_id, password)?;
+	}
+
 	// UIAA
 	let mut uiaainfo;
-	let skip_auth = if services.globals.registration_token.is_some() {
+	let skip_auth = if services.registration_tokens.tokens_required() {
 		// Registration token required
 		uiaainfo = UiaaInfo {
 			flows: vec![AuthFlow {
@@ -338,10 +365,67 @@ pub(crate) async fn register_route(
 		}
 	}
 
+	if body.appservice_info.is_none() {
+		services
+			.spam_checker
+			.check_allowed(&Check::Registration { user_id: &user_id, guest: is_guest })
+			.await?;
+	}
+
+	// If this is the first real user, they are granted admin privileges below
+	// Note: the server user is generated first
+	let is_first_user = !is_guest
+		&& match services.admin.get_admin_room().await {
+			| Ok(admin_room) => services
+				.rooms
+				.state_cache
+				.room_joined_count(&admin_room)
+				.await
+				.is_ok_and(is_equal_to!(1)),
+			| Err(_) => false,
+		};
+
+	let awaiting_approval = services.server.config.registration_requires_approval
+		&& body.appservice_info.is_none()
+		&& !is_guest
+		&& !is_first_user;
+
 	let password = if is_guest { None } else { body.password.as_deref() };
 
+	// an issued registration token is used right before the account is created,
+	// so concurrent registrations can't use it more often than allowed; the use
+	// is given back if the account can't be created
+	let used_token = match &body.auth {
+		| Some(AuthData::RegistrationToken(auth)) if !skip_auth => {
+			let token = auth.token.trim();
+			if services.registration_tokens.get_token(token).await.is_ok() {
+				services.registration_tokens.use_token(token).await?;
+				Some(token)
+			} else {
+				None
+			}
+		},
+		| _ => None,
+	};
+
 	// Create user
-	services.users.create(&user_id, password)?;
+	if let Err(e) = services.users.create(&user_id, password) {
+		if let Some(token) = used_token {
+			if let Err(e) = services.registration_tokens.release_token(token).await {
+				warn!(%user_id, "Failed to give back registration token use: {e}");
+			}
+		}
+
+		return Err(e);
+	}
+
+	if body.appservice_info.is_none() {
+		services.registration_limits.record(trusted_client);
+	}
+
+	if is_guest {
+		services.users.mark_as_guest(&user_id);
+	}
 
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
@@ -375,6 +459,24 @@ pub(crate) async fn register_route(
 		)
 		.await?;
 
+	// Accounts awaiting approval are created without a device (MSC3866)
+	if awaiting_approval {
+		services.users.set_pending_approval(&user_id, true);
+		info!("New user \"{user_id}\" registered on this server and is awaiting approval.");
+
+		services
+			.admin
+			.send_message(RoomMessageEventContent::notice_markdown(format!(
+				"New user {user_id} registered on this server from IP {client} and is awaiting \
+				 approval. Approve them with `!admin users approve {user_id}` or reject them \
+				 with `!admin users deny {user_id}`."
+			)))
+			.await
+			.ok();
+
+		return Err(users::awaiting_approval_error());
+	}
+
 	if (!is_guest && body.inhibit_login)
 		|| body
 			.appservice_info
@@ -476,74 +578,16 @@ pub(crate) async fn register_route(
 		}
 	}
 
-	// If this is the first real user, grant them admin privileges except for guest
-	// users
-	// Note: the server user is generated first
-	if !is_guest {
-		if let Ok(admin_room) = services.admin.get_admin_room().await {
-			if services
-				.rooms
-				.state_cache
-				.room_joined_count(&admin_room)
-				.await
-				.is_ok_and(is_equal_to!(1))
-			{
-				services.admin.make_user_admin(&user_id).await?;
-				warn!("Granting {user_id} admin privileges as the first user");
-			}
-		}
+	if is_first_user {
+		services.admin.make_user_admin(&user_id).await?;
+		warn!("Granting {user_id} admin privileges as the first user");
 	}
 
 	if body.appservice_info.is_none()
 		&& !services.server.config.auto_join_rooms.is_empty()
 		&& (services.config.allow_guests_auto_join_rooms || !is_guest)
 	{
-		for room in &services.server.config.auto_join_rooms {
-			let Ok(room_id) = services.rooms.alias.resolve(room).await else {
-				error!(
-					"Failed to resolve room alias to room ID when attempting to auto join \
-					 {room}, skipping"
-				);
-				continue;
-			};
-
-			if !services
-				.rooms
-				.state_cache
-				.server_in_room(services.globals.server_name(), &room_id)
-				.await
-			{
-				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
-				);
-				continue;
-			}
-
-			if let Some(room_server_name) = room.server_name() {
-				match join_room_by_id_helper(
-					&services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[services.globals.server_name().to_owned(), room_server_name.to_owned()],
-					None,
-					&body.appservice_info,
-				)
-				.boxed()
-				.await
-				{
-					| Err(e) => {
-						// don't return this error so we don't fail registrations
-						error!(
-							"Failed to automatically join room {room} for user {user_id}: {e}"
-						);
-					},
-					| _ => {
-						info!("Automatically joined room {room} for user {user_id}");
-					},
-				}
-			}
-		}
+		services.auto_join.join(&user_id).await;
 	}
 
 	Ok(register::v3::Response {
@@ -578,14 +622,23 @@ pub(crate) async fn change_password_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<change_password::v3::Request>,
 ) -> Result<change_password::v3::Response> {
-	// Authentication for this endpoint was made optional, but we need
-	// authentication currently
-	let sender_user = body
-		.sender_user
-		.as_ref()
-		.ok_or_else(|| err!(Request(MissingToken("Missing access token."))))?;
+	if services.oidc.enabled() {
+		return Err!(Request(Forbidden(
+			"Password changes are delegated to the OpenID Connect provider."
+		)));
+	}
+
+	// Authentication for this endpoint was made optional; without it the
+	// password can only be reset through an email bound to the account
+	let Some(sender_user) = body.sender_user.as_ref() else {
+		return reset_password_via_email(&services, client, &body).await;
+	};
 	let sender_device = body.sender_device();
 
+	services
+		.users
+		.check_password_policy(sender_user, &body.new_password)?;
+
 	let mut uiaainfo = UiaaInfo {
 		flows: vec![AuthFlow { stages: vec![AuthType::Password] }],
 		completed: Vec::new(),
@@ -671,6 +724,71 @@ pub(crate) async fn change_password_route(
 	Ok(change_password::v3::Response {})
 }
 
+/// Resets the password of the account an email was validated for, as the
+/// unauthenticated variant of `POST /_matrix/client/r0/account/password`.
+/// All devices of the account are logged out.
+async fn reset_password_via_email(
+	services: &Services,
+	client: IpAddr,
+	body: &Ruma<change_password::v3::Request>,
+) -> Result<change_password::v3::Response> {
+	if !services.email.password_reset_enabled() {
+		return Err!(Request(MissingToken("Missing access token.")));
+	}
+
+	let Some(AuthData::EmailIdentity(EmailIdentity { thirdparty_id_creds, .. })) = &body.auth
+	else {
+		return Err(Error::Uiaa(UiaaInfo {
+			flows: vec![AuthFlow { stages: vec![AuthType::EmailIdentity] }],
+			completed: Vec::new(),
+			params: Box::default(),
+			session: Some(utils::random_string(SESSION_ID_LENGTH)),
+			auth_error: None,
+		}));
+	};
+
+	let user_id = &services
+		.email
+		.take_validated(&thirdparty_id_creds.sid, &thirdparty_id_creds.client_secret)?;
+
+	services
+		.users
+		.check_password_policy(user_id, &body.new_password)?;
+
+	services
+		.users
+		.set_password(user_id, Some(&body.new_password))?;
+
+	services
+		.users
+		.all_device_ids(user_id)
+		.for_each(|id| services.users.remove_device(user_id, id))
+		.await;
+
+	services
+		.pusher
+		.get_pushkeys(user_id)
+		.map(ToOwned::to_owned)
+		.for_each(|pushkey| async move {
+			services.pusher.delete_pusher(user_id, &pushkey).await;
+		})
+		.await;
+
+	info!("User {user_id} reset their password via email.");
+
+	if services.server.config.admin_room_notices {
+		services
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"User {user_id} reset their password via email from {client}."
+			)))
+			.await
+			.ok();
+	}
+
+	Ok(change_password::v3::Response {})
+}
+
 /// # `GET _matrix/client/r0/account/whoami`
 ///
 /// Get `user_id` of the sender user.
@@ -708,6 +826,12 @@ pub(crate) async fn deactivate_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<deactivate::v3::Request>,
 ) -> Result<deactivate::v3::Response> {
+	if services.oidc.enabled() {
+		return Err!(Request(Forbidden(
+			"Account deactivation is delegated to the OpenID Connect provider."
+		)));
+	}
+
 	// Authentication for this endpoint was made optional, but we need
 	// authentication currently
 	let sender_user = body
@@ -808,6 +932,102 @@ pub(crate) async fn request_3pid_management_token_via_email_route(
 	Err!(Request(ThreepidDenied("Third party identifiers are not implemented")))
 }
 
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a link to an email bound to an account so its password can be reset
+/// without being logged in. The returned session is used to authenticate the
+/// subsequent `POST /_matrix/client/v3/account/password` request once the
+/// link has been followed.
+///
+/// - 403 signals that password reset via email is not enabled
+/// - 400 signals that the email is not bound to any account
+pub(crate) async fn request_password_change_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	let sid = services
+		.email
+		.request_password_reset(
+			&body.email,
+			&body.client_secret,
+			body.send_attempt,
+			body.next_link.clone(),
+		)
+		.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response::new(sid))
+}
+
+#[derive(Deserialize)]
+struct ValidateEmailQuery {
+	sid: OwnedSessionId,
+	client_secret: OwnedClientSecret,
+	token: String,
+}
+
+/// # `GET /_conduwuit/email/validate`
+///
+/// Target of the links sent by email. Marks the session as validated and
+/// redirects to the `next_link` given when the email was requested, if any.
+pub(crate) async fn validate_email_route(
+	State(services): State<crate::State>,
+	RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+	let query: ValidateEmailQuery = serde_html_form::from_str(query.as_deref().unwrap_or(""))
+		.map_err(|e| err!(Request(InvalidParam("Invalid query: {e}"))))?;
+
+	let next_link = services
+		.email
+		.validate(&query.sid, &query.client_secret, &query.token)?;
+
+	Ok(match next_link {
+		| Some(next_link) => Redirect::to(&next_link).into_response(),
+		| None =>
+			"Your email has been validated. You can now return to your client.".into_response(),
+	})
+}
+
+#[derive(Deserialize)]
+struct RenewAccountQuery {
+	token: String,
+}
+
+/// # `GET /_conduwuit/account/renew`
+///
+/// Target of the account renewal links sent by email. Renews the account the
+/// link was sent for by `account_validity.period_days`.
+pub(crate) async fn renew_account_route(
+	State(services): State<crate::State>,
+	RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+	let query: RenewAccountQuery = serde_html_form::from_str(query.as_deref().unwrap_or(""))
+		.map_err(|e| err!(Request(InvalidParam("Invalid query: {e}"))))?;
+
+	let (user_id, expires) = services.users.renew_account_by_token(&query.token).await?;
+
+	Ok(format!(
+		"Your account {user_id} has been renewed until {}. You can now return to your client.",
+		utils::time::format(expires, "%Y-%m-%d %H:%M UTC"),
+	))
+}
+
+/// # `POST /_conduwuit/account/renew/send`
+///
+/// Emails a new renewal link to the user, replacing the one sent before. Also
+/// available while the account is expired, so links that were lost or expired
+/// can be replaced.
+pub(crate) async fn send_renewal_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_renewal_email::Request>,
+) -> Result<send_renewal_email::Response> {
+	services
+		.users
+		.resend_renewal_email(body.sender_user())
+		.await?;
+
+	Ok(send_renewal_email::Response {})
+}
+
 /// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
 ///
 /// "This API should be used to request validation tokens when adding an phone
@@ -823,19 +1043,24 @@ pub(crate) async fn request_3pid_management_token_via_msisdn_route(
 
 /// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
 ///
-/// Checks if the provided registration token is valid at the time of checking
+/// Checks if the provided registration token is valid at the time of checking.
+/// Both the configured tokens and tokens issued through the admin commands are
+/// accepted; checking a token does not use it up.
 ///
-/// Currently does not have any ratelimiting, and this isn't very practical as
-/// there is only one registration token allowed.
+/// Currently does not have any ratelimiting.
 pub(crate) async fn check_registration_token_validity(
 	State(services): State<crate::State>,
 	body: Ruma<check_registration_token_validity::v1::Request>,
 ) -> Result<check_registration_token_validity::v1::Response> {
-	let Some(reg_token) = services.globals.registration_token.clone() else {
+	if !services.registration_tokens.tokens_required() {
 		return Err!(Request(Forbidden("Server does not allow token registration")));
-	};
+	}
+
+	let token = body.token.trim();
+	let valid = services.uiaa.read_tokens().await?.contains(token)
+		|| services.registration_tokens.is_token_valid(token).await;
 
-	Ok(check_registration_token_validity::v1::Response { valid: reg_token == body.token })
+	Ok(check_registration_token_validity::v1::Response { valid })
 }
 
 /// Runs through all the deactivation steps:
@@ -851,6 +1076,7 @@ pub async fn full_user_deactivate(
 	all_joined_rooms: &[OwnedRoomId],
 ) -> Result<()> {
 	services.users.deactivate_account(user_id).await.ok();
+	services.email.set_email(user_id, None).await.ok();
 	super::update_displayname(services, user_id, None, all_joined_rooms).await;
 	super::update_avatar_url(services, user_id, None, None, all_joined_rooms).await;
 
@@ -919,3 +1145,24 @@ pub async fn full_user_deactivate(
 
 	Ok(())
 }
+
+/// `POST /_conduwuit/account/renew/send`, also served at Synapse's path.
+pub(crate) mod send_renewal_email {
+	use ruma::api::{Metadata, metadata, request, response};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: true,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_conduwuit/account/renew/send",
+			unstable => "/_matrix/client/unstable/account_validity/send_mail",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {}
+}
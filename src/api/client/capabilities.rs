@@ -10,6 +10,7 @@
 	},
 };
 use serde_json::json;
+use service::users::PASSWORD_POLICY_CAPABILITY;
 
 use crate::Ruma;
 
@@ -34,7 +35,7 @@ pub(crate) async fn get_capabilities_route(
 	capabilities.thirdparty_id_changes = ThirdPartyIdChangesCapability { enabled: false };
 
 	capabilities.get_login_token = GetLoginTokenCapability {
-		enabled: services.server.config.login_via_existing_session,
+		enabled: services.server.config.login_via_existing_session && !services.oidc.enabled(),
 	};
 
 	// MSC4133 capability
@@ -49,5 +50,12 @@ pub(crate) async fn get_capabilities_route(
 		)
 		.expect("valid JSON we created");
 
+	// MSC2000 capability
+	if let Some(policy) = services.users.password_policy() {
+		capabilities
+			.set(PASSWORD_POLICY_CAPABILITY, policy)
+			.expect("valid JSON we created");
+	}
+
 	Ok(get_capabilities::v3::Response { capabilities })
 }
@@ -28,6 +28,8 @@
 ///
 /// Allows loading room history around an event.
 ///
+/// - The filter applies to the returned state as well as the events, and
+///   lazy-loading only returns the members of the senders of the events
 /// - Only works if the user is joined (TODO: always allow, but only show events
 ///   if the user was joined, depending on history_visibility)
 pub(crate) async fn get_context_route(
@@ -179,6 +181,7 @@ pub(crate) async fn get_context_route(
 		.broad_filter_map(|event_id: &OwnedEventId| {
 			services.rooms.timeline.get_pdu(event_id.as_ref()).ok()
 		})
+		.ready_filter(|pdu| pdu.matches(filter))
 		.map(PduEvent::into_state_event)
 		.collect()
 		.await;
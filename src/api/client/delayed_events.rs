@@ -0,0 +1,204 @@
+use std::{ops::Deref, time::Duration};
+
+use axum::extract::State;
+use bytes::BufMut;
+use conduwuit::{Err, Result};
+use futures::StreamExt;
+use ruma::{
+	api::{
+		IncomingRequest, Metadata, OutgoingResponse,
+		error::{FromHttpRequestError, IntoHttpError},
+	},
+	serde::json_to_buf,
+};
+use serde::Deserialize;
+use serde_json::json;
+use service::delayed_events::{DelayedEvent, UpdateAction};
+
+use crate::Ruma;
+
+/// Request of an endpoint events can also be scheduled through, by passing a
+/// delay in the `org.matrix.msc4140.delay` query parameter (MSC4140).
+pub(crate) struct MaybeDelayed<T> {
+	pub(crate) request: T,
+
+	/// Delay after which the event is sent; sent immediately if None
+	pub(crate) delay: Option<Duration>,
+}
+
+/// Response to a [`MaybeDelayed`] request: the response of the endpoint if
+/// the event was sent, or the ID of the scheduled delayed event.
+pub(crate) enum MaybeDelayedResponse<R> {
+	Sent(R),
+	Delayed(String),
+}
+
+#[derive(Deserialize)]
+struct DelayQuery {
+	#[serde(rename = "org.matrix.msc4140.delay")]
+	delay: Option<u64>,
+}
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4140/delayed_events/{delayId}`
+///
+/// Restart the delay of a scheduled delayed event, send it now, or cancel it.
+pub(crate) async fn update_delayed_event_route(
+	State(services): State<crate::State>,
+	body: Ruma<update_delayed_event::Request>,
+) -> Result<update_delayed_event::Response> {
+	let action = match body.action.as_str() {
+		| "restart" => UpdateAction::Restart,
+		| "send" => UpdateAction::Send,
+		| "cancel" => UpdateAction::Cancel,
+		| action => return Err!(Request(InvalidParam("Unknown action {action:?}."))),
+	};
+
+	services
+		.delayed_events
+		.update(body.sender_user(), &body.delay_id, action)
+		.await?;
+
+	Ok(update_delayed_event::Response {})
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4140/delayed_events`
+///
+/// List the delayed events scheduled by the user.
+pub(crate) async fn get_delayed_events_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_delayed_events::Request>,
+) -> Result<get_delayed_events::Response> {
+	let delayed_events = services
+		.delayed_events
+		.delayed_events_for(body.sender_user())
+		.map(|event| format_delayed_event(&event))
+		.collect()
+		.await;
+
+	Ok(get_delayed_events::Response { delayed_events })
+}
+
+fn format_delayed_event(event: &DelayedEvent) -> serde_json::Value {
+	let mut value = json!({
+		"delay_id": event.delay_id,
+		"room_id": event.room_id,
+		"type": event.event_type,
+		"delay": event.delay,
+		"running_since": event.running_since,
+		"content": event.content,
+	});
+
+	if let Some(state_key) = &event.state_key {
+		value["state_key"] = state_key.as_str().into();
+	}
+
+	value
+}
+
+impl<T> IncomingRequest for MaybeDelayed<T>
+where
+	T: IncomingRequest,
+{
+	type EndpointError = T::EndpointError;
+	type OutgoingResponse = MaybeDelayedResponse<T::OutgoingResponse>;
+
+	const METADATA: Metadata = T::METADATA;
+
+	fn try_from_http_request<B, S>(
+		request: http::Request<B>,
+		path_args: &[S],
+	) -> Result<Self, FromHttpRequestError>
+	where
+		B: AsRef<[u8]>,
+		S: AsRef<str>,
+	{
+		let query: DelayQuery =
+			serde_html_form::from_str(request.uri().query().unwrap_or_default())?;
+
+		Ok(Self {
+			request: T::try_from_http_request(request, path_args)?,
+			delay: query.delay.map(Duration::from_millis),
+		})
+	}
+}
+
+impl<T> Deref for MaybeDelayed<T> {
+	type Target = T;
+
+	fn deref(&self) -> &Self::Target { &self.request }
+}
+
+impl<R> OutgoingResponse for MaybeDelayedResponse<R>
+where
+	R: OutgoingResponse,
+{
+	fn try_into_http_response<T: Default + BufMut>(
+		self,
+	) -> Result<http::Response<T>, IntoHttpError> {
+		match self {
+			| Self::Sent(response) => response.try_into_http_response(),
+			| Self::Delayed(delay_id) => Ok(http::Response::builder()
+				.header(http::header::CONTENT_TYPE, "application/json")
+				.body(json_to_buf(&json!({ "delay_id": delay_id }))?)?),
+		}
+	}
+}
+
+impl<R> From<R> for MaybeDelayedResponse<R> {
+	fn from(response: R) -> Self { Self::Sent(response) }
+}
+
+/// `POST /_matrix/client/unstable/org.matrix.msc4140/delayed_events/{delayId}`
+/// (MSC4140), which ruma does not provide yet.
+pub(crate) mod update_delayed_event {
+	use ruma::api::{Metadata, metadata, request, response};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: true,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_matrix/client/unstable/org.matrix.msc4140/delayed_events/:delay_id",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The ID of the delayed event.
+		#[ruma_api(path)]
+		pub delay_id: String,
+
+		/// One of `restart`, `send` or `cancel`.
+		pub action: String,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	#[derive(Default)]
+	pub struct Response {}
+}
+
+/// `GET /_matrix/client/unstable/org.matrix.msc4140/delayed_events` (MSC4140),
+/// which ruma does not provide yet.
+pub(crate) mod get_delayed_events {
+	use ruma::api::{Metadata, metadata, request, response};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_matrix/client/unstable/org.matrix.msc4140/delayed_events",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	#[derive(Default)]
+	pub struct Request {}
+
+	#[response(error = ruma::api::client::Error)]
+	#[derive(Default)]
+	pub struct Response {
+		/// The delayed events scheduled by the user.
+		pub delayed_events: Vec<serde_json::Value>,
+	}
+}
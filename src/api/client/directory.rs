@@ -1,30 +1,28 @@
+use std::collections::HashSet;
+
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
-	Err, Result, err, info,
+	Err, Result, debug_warn, err, info,
 	utils::{
-		TryFutureExtExt,
+		IterStream, TryFutureExtExt,
 		math::Expected,
 		result::FlatOk,
-		stream::{ReadyExt, WidebandExt},
+		stream::{BroadbandExt, ReadyExt, WidebandExt},
 	},
 };
-use conduwuit_service::Services;
+use conduwuit_service::{Services, audit::Kind};
 use futures::{
 	FutureExt, StreamExt, TryFutureExt,
 	future::{join, join4, join5},
 };
 use ruma::{
 	OwnedRoomId, RoomId, ServerName, UInt, UserId,
-	api::{
-		client::{
-			directory::{
-				get_public_rooms, get_public_rooms_filtered, get_room_visibility,
-				set_room_visibility,
-			},
-			room,
+	api::client::{
+		directory::{
+			get_public_rooms, get_public_rooms_filtered, get_room_visibility, set_room_visibility,
 		},
-		federation,
+		room,
 	},
 	directory::{Filter, PublicRoomJoinRule, PublicRoomsChunk, RoomNetwork, RoomTypeFilter},
 	events::{
@@ -39,11 +37,25 @@
 
 use crate::Ruma;
 
+/// Number of rooms fetched from each server whose directory is merged into
+/// ours
+const REMOTE_MERGE_LIMIT: UInt = uint!(100);
+
+/// Room listed in the directory while it is sorted. Only the member count and
+/// ID of local rooms are kept; their chunk is built again for the requested
+/// page.
+enum Listed {
+	Local(UInt, OwnedRoomId),
+	Remote(Box<PublicRoomsChunk>),
+}
+
 /// # `POST /_matrix/client/v3/publicRooms`
 ///
-/// Lists the public rooms on this server.
+/// Lists the public rooms on this server, or on the remote `server`.
 ///
 /// - Rooms are ordered by the number of joined members
+/// - Rooms of the servers in `public_room_directory_servers` are merged into
+///   our own
 #[tracing::instrument(skip_all, fields(%client), name = "publicrooms")]
 pub(crate) async fn get_public_rooms_filtered_route(
 	State(services): State<crate::State>,
@@ -71,6 +83,7 @@ pub(crate) async fn get_public_rooms_filtered_route(
 		body.since.as_deref(),
 		&body.filter,
 		&body.room_network,
+		true,
 	)
 	.await
 	.map_err(|e| {
@@ -82,9 +95,11 @@ pub(crate) async fn get_public_rooms_filtered_route(
 
 /// # `GET /_matrix/client/v3/publicRooms`
 ///
-/// Lists the public rooms on this server.
+/// Lists the public rooms on this server, or on the remote `server`.
 ///
 /// - Rooms are ordered by the number of joined members
+/// - Rooms of the servers in `public_room_directory_servers` are merged into
+///   our own
 #[tracing::instrument(skip_all, fields(%client), name = "publicrooms")]
 pub(crate) async fn get_public_rooms_route(
 	State(services): State<crate::State>,
@@ -112,6 +127,7 @@ pub(crate) async fn get_public_rooms_route(
 		body.since.as_deref(),
 		&Filter::default(),
 		&RoomNetwork::Matrix,
+		true,
 	)
 	.await
 	.map_err(|e| {
@@ -184,8 +200,39 @@ pub(crate) async fn set_room_visibility_route(
 				)));
 			}
 
+			if services
+				.rooms
+				.alias
+				.room_has_forbidden_alias(&body.room_id)
+				.await
+			{
+				info!(
+					"{sender_user} tried to publish {0} to the room directory with a forbidden \
+					 alias",
+					body.room_id
+				);
+
+				return Err!(Request(Forbidden(
+					"Rooms with a forbidden alias can't be published to the room directory",
+				)));
+			}
+
 			services.rooms.directory.set_public(&body.room_id);
 
+			if services.server.config.lockdown_public_room_directory
+				&& body.appservice_info.is_none()
+			{
+				services.audit.record(
+					Kind::Api,
+					Some(sender_user),
+					format!(
+						"setRoomVisibility: published {} to the locked down room directory",
+						body.room_id
+					),
+					None,
+				);
+			}
+
 			if services.server.config.admin_room_notices {
 				services
 					.admin
@@ -234,30 +281,21 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 	since: Option<&str>,
 	filter: &Filter,
 	_network: &RoomNetwork,
+	merge_remote: bool,
 ) -> Result<get_public_rooms_filtered::v3::Response> {
 	if let Some(other_server) =
 		server.filter(|server_name| !services.globals.server_is_ours(server_name))
 	{
 		let response = services
-			.sending
-			.send_federation_request(
-				other_server,
-				federation::directory::get_public_rooms_filtered::v1::Request {
-					limit,
-					since: since.map(ToOwned::to_owned),
-					filter: Filter {
-						generic_search_term: filter.generic_search_term.clone(),
-						room_types: filter.room_types.clone(),
-					},
-					room_network: RoomNetwork::Matrix,
-				},
-			)
+			.rooms
+			.directory
+			.remote_public_rooms(other_server, limit, since, filter)
 			.await?;
 
 		return Ok(get_public_rooms_filtered::v3::Response {
-			chunk: response.chunk,
-			prev_batch: response.prev_batch,
-			next_batch: response.next_batch,
+			chunk: response.chunk.clone(),
+			prev_batch: response.prev_batch.clone(),
+			next_batch: response.next_batch.clone(),
 			total_room_count_estimate: response.total_room_count_estimate,
 		});
 	}
@@ -286,53 +324,41 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 		}
 	}
 
-	let mut all_rooms: Vec<PublicRoomsChunk> = services
+	let mut all_rooms: Vec<Listed> = services
 		.rooms
 		.directory
 		.public_rooms()
 		.map(ToOwned::to_owned)
 		.wide_then(|room_id| public_rooms_chunk(services, room_id))
-		.ready_filter_map(|chunk| {
-			if !filter.room_types.is_empty() && !filter.room_types.contains(&RoomTypeFilter::from(chunk.room_type.clone())) {
-				return None;
-			}
-
-			if let Some(query) = filter.generic_search_term.as_ref().map(|q| q.to_lowercase()) {
-				if let Some(name) = &chunk.name {
-					if name.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(topic) = &chunk.topic {
-					if topic.to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(canonical_alias) = &chunk.canonical_alias {
-					if canonical_alias.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				return None;
-			}
-
-			// No search term
-			Some(chunk)
-		})
+		.ready_filter(|chunk| matches_filter(chunk, filter))
+		.map(|chunk| Listed::Local(chunk.num_joined_members, chunk.room_id))
 		// We need to collect all, so we can sort by member count
 		.collect()
 		.await;
 
-	all_rooms.sort_by(|l, r| r.num_joined_members.cmp(&l.num_joined_members));
+	if merge_remote {
+		merge_remote_public_rooms(services, filter, &mut all_rooms).await;
+	}
+
+	all_rooms.sort_by(|l, r| r.num_joined_members().cmp(&l.num_joined_members()));
 
 	let total_room_count_estimate = UInt::try_from(all_rooms.len())
 		.unwrap_or_else(|_| uint!(0))
 		.into();
 
-	let chunk: Vec<_> = all_rooms.into_iter().skip(num_since).take(limit).collect();
+	let chunk: Vec<_> = all_rooms
+		.into_iter()
+		.skip(num_since)
+		.take(limit)
+		.stream()
+		.then(|listed| async move {
+			match listed {
+				| Listed::Local(_, room_id) => public_rooms_chunk(services, room_id).await,
+				| Listed::Remote(chunk) => *chunk,
+			}
+		})
+		.collect()
+		.await;
 
 	let prev_batch = num_since.ne(&0).then_some(format!("p{num_since}"));
 
@@ -349,6 +375,105 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 	})
 }
 
+/// Add the public rooms of the servers in `public_room_directory_servers` to
+/// our own, skipping rooms which are already listed.
+async fn merge_remote_public_rooms(
+	services: &Services,
+	filter: &Filter,
+	all_rooms: &mut Vec<Listed>,
+) {
+	let mut listed: HashSet<_> = all_rooms.iter().map(Listed::room_id).cloned().collect();
+
+	let remote_rooms: Vec<_> = services
+		.config
+		.public_room_directory_servers
+		.iter()
+		.filter(|server| {
+			!services.globals.server_is_ours(server)
+				&& !services
+					.config
+					.forbidden_remote_room_directory_server_names
+					.is_match(server.host())
+				&& !services
+					.config
+					.forbidden_remote_server_names
+					.is_match(server.host())
+		})
+		.stream()
+		.broad_filter_map(|server| async move {
+			services
+				.rooms
+				.directory
+				.remote_public_rooms(server, Some(REMOTE_MERGE_LIMIT), None, filter)
+				.await
+				.inspect_err(|e| {
+					debug_warn!(%server, "Failed to fetch public rooms to merge: {e}");
+				})
+				.ok()
+		})
+		.collect()
+		.await;
+
+	all_rooms.extend(
+		remote_rooms
+			.iter()
+			.flat_map(|directory| directory.chunk.iter())
+			.filter(|chunk| listed.insert(chunk.room_id.clone()))
+			.cloned()
+			.map(Box::new)
+			.map(Listed::Remote),
+	);
+}
+
+/// Whether the room matches the room types and search term of the filter.
+fn matches_filter(chunk: &PublicRoomsChunk, filter: &Filter) -> bool {
+	if !filter.room_types.is_empty()
+		&& !filter
+			.room_types
+			.contains(&RoomTypeFilter::from(chunk.room_type.clone()))
+	{
+		return false;
+	}
+
+	let Some(query) = filter
+		.generic_search_term
+		.as_ref()
+		.map(|q| q.to_lowercase())
+	else {
+		// No search term
+		return true;
+	};
+
+	chunk
+		.name
+		.as_deref()
+		.is_some_and(|name| name.to_lowercase().contains(&query))
+		|| chunk
+			.topic
+			.as_deref()
+			.is_some_and(|topic| topic.to_lowercase().contains(&query))
+		|| chunk
+			.canonical_alias
+			.as_deref()
+			.is_some_and(|alias| alias.as_str().to_lowercase().contains(&query))
+}
+
+impl Listed {
+	fn num_joined_members(&self) -> UInt {
+		match self {
+			| Self::Local(num_joined_members, _) => *num_joined_members,
+			| Self::Remote(chunk) => chunk.num_joined_members,
+		}
+	}
+
+	fn room_id(&self) -> &OwnedRoomId {
+		match self {
+			| Self::Local(_, room_id) => room_id,
+			| Self::Remote(chunk) => &chunk.room_id,
+		}
+	}
+}
+
 /// Check whether the user can publish to the room directory via power levels of
 /// room history visibility event or room creator
 async fn user_can_publish_room(
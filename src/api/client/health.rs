@@ -0,0 +1,103 @@
+use std::{sync::atomic::Ordering, time::Instant};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use conduwuit::{Err, Result};
+use http::StatusCode;
+use serde_json::{Map, Value, json};
+
+/// # `GET /_conduwuit/health/live`
+///
+/// Liveness probe: fails only once the server is shutting down. Nothing else
+/// is checked so a server busy with a slow dependency is not restarted.
+pub(crate) async fn health_live_route(State(services): State<crate::State>) -> impl IntoResponse {
+	if services.server.running() {
+		(StatusCode::OK, Json(json!({ "status": "ok" })))
+	} else {
+		(StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "status": "stopping" })))
+	}
+}
+
+/// # `GET /_conduwuit/health/ready`
+///
+/// Readiness probe: checks the database can be read and is accepting writes,
+/// the signing key is loaded, the federation senders are running and the
+/// listeners are up. Responds with 503 if the server is shutting down or any
+/// check fails, along with the result of each check.
+pub(crate) async fn health_ready_route(
+	State(services): State<crate::State>,
+) -> impl IntoResponse {
+	let checks = [
+		("database", check_database(&services).await),
+		("signing_keys", check_signing_keys(&services).await),
+		("federation_sender", check_federation_sender(&services)),
+		("listeners", check_listeners(&services)),
+	];
+
+	let ready = services.server.running() && checks.iter().all(|(_, check)| check.is_ok());
+	let checks: Map<String, Value> = checks
+		.into_iter()
+		.map(|(name, check)| {
+			let check = match check {
+				| Ok(detail) => json!({ "status": "ok", "detail": detail }),
+				| Err(error) => json!({ "status": "fail", "error": error.sanitized_message() }),
+			};
+
+			(name.to_owned(), check)
+		})
+		.collect();
+
+	let (status, body) = if ready {
+		(StatusCode::OK, "ok")
+	} else {
+		(StatusCode::SERVICE_UNAVAILABLE, "fail")
+	};
+
+	(status, Json(json!({ "status": body, "checks": checks })))
+}
+
+/// Read the database version and check writes are not stalled. Nothing is
+/// written so frequent probing does not add to the write load.
+async fn check_database(services: &crate::State) -> Result<Value> {
+	let started = Instant::now();
+	services.db["global"].get(b"version").await?;
+	if !services.db.is_read_only() && services.db.db.is_write_stalled()? {
+		return Err!("Writes are stalled while flushes and compactions catch up.");
+	}
+
+	let elapsed = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+	Ok(json!({ "read_only": services.db.is_read_only(), "latency_ms": elapsed }))
+}
+
+/// The active signing key is loaded and stored with our verify keys, which
+/// are served to other servers.
+async fn check_signing_keys(services: &crate::State) -> Result<Value> {
+	let key_id = services.server_keys.active_key_id();
+	if !services
+		.server_keys
+		.verify_key_exists(services.globals.server_name(), key_id)
+		.await
+	{
+		return Err!("Signing key {key_id} is not among our verify keys.");
+	}
+
+	Ok(json!({ "key_id": key_id }))
+}
+
+fn check_federation_sender(services: &crate::State) -> Result<Value> {
+	let (running, started) = services.sending.senders();
+	if running < started {
+		return Err!("{running} of {started} federation sender workers are running.");
+	}
+
+	Ok(json!({ "workers": running }))
+}
+
+fn check_listeners(services: &crate::State) -> Result<Value> {
+	let listening = services.server.config.listening;
+	let listeners = services.server.metrics.listeners.load(Ordering::Relaxed);
+	if listening && listeners == 0 {
+		return Err!("No listeners are serving connections.");
+	}
+
+	Ok(json!({ "listeners": listeners }))
+}
@@ -1,9 +1,12 @@
 use std::time::Duration;
 
-use axum::extract::State;
+use axum::{
+	extract::State,
+	response::{IntoResponse, Redirect, Response},
+};
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
-	Err, Result, err,
+	Err, Result, debug_warn, err,
 	utils::{self, content_disposition::make_content_disposition, math::ruma_from_usize},
 };
 use conduwuit_service::{
@@ -22,7 +25,7 @@
 	},
 };
 
-use crate::Ruma;
+use crate::{Ruma, RumaResponse};
 
 /// # `GET /_matrix/client/v1/media/config`
 pub(crate) async fn get_media_config_route(
@@ -61,22 +64,56 @@ pub(crate) async fn create_content_route(
 		media_id: &utils::random_string(MXC_LENGTH),
 	};
 
+	services
+		.media_scanner
+		.check_upload(user, &body.file)
+		.await?;
+
+	let stripped = if services.media.strip_metadata_enabled(user).await {
+		services.media.strip_metadata(&body.file).await?
+	} else {
+		None
+	};
+
+	let file = stripped.as_deref().unwrap_or(&body.file);
 	services
 		.media
-		.create(mxc, Some(user), Some(&content_disposition), content_type, &body.file)
+		.create(mxc, Some(user), Some(&content_disposition), content_type, file)
 		.await?;
 
-	let blurhash = body.generate_blurhash.then(|| {
+	if !services
+		.server
+		.config
+		.media_thumbnail_pregenerate
+		.is_empty()
+	{
+		let media_id = mxc.media_id.to_owned();
+		services.server.spawn("media:thumbnails", async move {
+			let mxc = Mxc {
+				server_name: services.globals.server_name(),
+				media_id: &media_id,
+			};
+
+			if let Err(e) = services.media.pregenerate_thumbnails(&mxc).await {
+				debug_warn!(%mxc, "Failed to generate thumbnails of uploaded media: {e}");
+			}
+		});
+	}
+
+	let blurhash = if body.generate_blurhash || services.media.blurhash_on_upload() {
 		services
 			.media
-			.create_blurhash(&body.file, content_type, filename)
+			.create_blurhash(file, content_type, filename)
+			.await
 			.ok()
 			.flatten()
-	});
+	} else {
+		None
+	};
 
 	Ok(create_content::v3::Response {
 		content_uri: mxc.to_string().into(),
-		blurhash: blurhash.flatten(),
+		blurhash,
 	})
 }
 
@@ -96,7 +133,7 @@ pub(crate) async fn get_content_thumbnail_route(
 ) -> Result<get_content_thumbnail::v1::Response> {
 	let user = body.sender_user.as_ref().expect("user is authenticated");
 
-	let dim = Dim::from_ruma(body.width, body.height, body.method.clone())?;
+	let dim = Dim::from_ruma(body.width, body.height, body.method.clone(), body.animated)?;
 	let mxc = Mxc {
 		server_name: &body.server_name,
 		media_id: &body.media_id,
@@ -120,6 +157,9 @@ pub(crate) async fn get_content_thumbnail_route(
 /// # `GET /_matrix/client/v1/media/download/{serverName}/{mediaId}`
 ///
 /// Load media from our server or over federation.
+///
+/// - Redirects to object storage if `media_redirect` is enabled for the media's
+///   origin
 #[tracing::instrument(
 	name = "media_get",
 	level = "debug",
@@ -130,7 +170,7 @@ pub(crate) async fn get_content_route(
 	State(services): State<crate::State>,
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<get_content::v1::Request>,
-) -> Result<get_content::v1::Response> {
+) -> Result<Response> {
 	let user = body.sender_user.as_ref().expect("user is authenticated");
 
 	let mxc = Mxc {
@@ -138,24 +178,32 @@ pub(crate) async fn get_content_route(
 		media_id: &body.media_id,
 	};
 
+	if let Some(location) = services.media.get_redirect(&mxc, None).await? {
+		return Ok(Redirect::temporary(&location).into_response());
+	}
+
 	let FileMeta {
 		content,
 		content_type,
 		content_disposition,
 	} = fetch_file(&services, &mxc, user, body.timeout_ms, None).await?;
 
-	Ok(get_content::v1::Response {
+	Ok(RumaResponse(get_content::v1::Response {
 		file: content.expect("entire file contents"),
 		content_type: content_type.map(Into::into),
 		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.into()),
 		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
 		content_disposition,
 	})
+	.into_response())
 }
 
 /// # `GET /_matrix/client/v1/media/download/{serverName}/{mediaId}/{fileName}`
 ///
 /// Load media from our server or over federation as fileName.
+///
+/// - Redirects to object storage if `media_redirect` is enabled for the media's
+///   origin
 #[tracing::instrument(
 	name = "media_get_af",
 	level = "debug",
@@ -166,7 +214,7 @@ pub(crate) async fn get_content_as_filename_route(
 	State(services): State<crate::State>,
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<get_content_as_filename::v1::Request>,
-) -> Result<get_content_as_filename::v1::Response> {
+) -> Result<Response> {
 	let user = body.sender_user.as_ref().expect("user is authenticated");
 
 	let mxc = Mxc {
@@ -174,19 +222,28 @@ pub(crate) async fn get_content_as_filename_route(
 		media_id: &body.media_id,
 	};
 
+	if let Some(location) = services
+		.media
+		.get_redirect(&mxc, Some(&body.filename))
+		.await?
+	{
+		return Ok(Redirect::temporary(&location).into_response());
+	}
+
 	let FileMeta {
 		content,
 		content_type,
 		content_disposition,
 	} = fetch_file(&services, &mxc, user, body.timeout_ms, Some(&body.filename)).await?;
 
-	Ok(get_content_as_filename::v1::Response {
+	Ok(RumaResponse(get_content_as_filename::v1::Response {
 		file: content.expect("entire file contents"),
 		content_type: content_type.map(Into::into),
 		cross_origin_resource_policy: Some(CORP_CROSS_ORIGIN.into()),
 		cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
 		content_disposition,
 	})
+	.into_response())
 }
 
 /// # `GET /_matrix/client/v1/media/preview_url`
@@ -12,23 +12,27 @@
 	Err, Result, at, debug, debug_info, debug_warn, err, error, info,
 	matrix::{
 		StateKey,
-		pdu::{PduBuilder, PduEvent, gen_event_id, gen_event_id_canonical_json},
+		pdu::{PduBuilder, PduCount, PduEvent, gen_event_id, gen_event_id_canonical_json},
 		state_res,
 	},
 	result::{FlatOk, NotFound},
 	trace,
-	utils::{self, IterStream, ReadyExt, shuffle},
+	utils::{self, IterStream, ReadyExt, shuffle, stream::TryIgnore},
 	warn,
 };
 use conduwuit_service::{
 	Services,
 	appservice::RegistrationInfo,
+	ratelimit::JoinKind,
 	rooms::{
+		short::ShortStateHash,
 		state::RoomMutexGuard,
 		state_compressor::{CompressedState, HashSetCompressStateEvent},
 	},
+	spam_checker::Check,
+	users::InvitePermission,
 };
-use futures::{FutureExt, StreamExt, TryFutureExt, future::join4, join};
+use futures::{FutureExt, StreamExt, TryFutureExt, future::join4, join, pin_mut};
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, OwnedEventId, OwnedRoomId, OwnedServerName,
 	OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
@@ -57,7 +61,7 @@
 	},
 };
 
-use crate::{Ruma, client::full_user_deactivate};
+use crate::{JsonStream, Ruma, client::full_user_deactivate};
 
 /// Checks if the room is banned in any way possible and the sender user is not
 /// an admin.
@@ -820,17 +824,19 @@ fn membership_filter(
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/members`
 ///
-/// Lists all joined users in a room (TODO: at a specific point in time, with a
-/// specific membership).
+/// Lists the members of a room with the given membership.
 ///
+/// - With `at`, lists the members as of the last event before that pagination
+///   token instead of the current members
 /// - Only works if the user is currently joined
+/// - The members are streamed to the client as they are read
 pub(crate) async fn get_member_events_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_member_events::v3::Request>,
-) -> Result<get_member_events::v3::Response> {
+) -> Result<JsonStream> {
 	let sender_user = body.sender_user();
-	let membership = body.membership.as_ref();
-	let not_membership = body.not_membership.as_ref();
+	let membership = body.membership.clone();
+	let not_membership = body.not_membership.clone();
 
 	if !services
 		.rooms
@@ -841,19 +847,58 @@ pub(crate) async fn get_member_events_route(
 		return Err!(Request(Forbidden("You don't have permission to view this room.")));
 	}
 
-	Ok(get_member_events::v3::Response {
-		chunk: services
+	let shortstatehash = match body.at.as_deref() {
+		| Some(at) => state_at_token(&services, &body.room_id, at).await?,
+		| None => services
+			.rooms
+			.state
+			.get_room_shortstatehash(&body.room_id)
+			.await
+			.map_err(|e| err!(Database("Missing state for {}: {e}", body.room_id)))?,
+	};
+
+	Ok(JsonStream::object(services, "chunk", move |services| {
+		services
 			.rooms
 			.state_accessor
-			.room_state_full(&body.room_id)
-			.ready_filter_map(Result::ok)
+			.state_full(shortstatehash)
 			.ready_filter(|((ty, _), _)| *ty == StateEventType::RoomMember)
 			.map(at!(1))
-			.ready_filter_map(|pdu| membership_filter(pdu, membership, not_membership))
+			.ready_filter_map(move |pdu| {
+				membership_filter(pdu, membership.as_ref(), not_membership.as_ref())
+			})
 			.map(PduEvent::into_member_event)
-			.collect()
-			.await,
-	})
+			.boxed()
+	}))
+}
+
+/// The state of the room at the last event before the pagination token `at`.
+async fn state_at_token(
+	services: &Services,
+	room_id: &RoomId,
+	at: &str,
+) -> Result<ShortStateHash> {
+	let at: PduCount = at
+		.parse()
+		.map_err(|_| err!(Request(InvalidParam("Invalid `at` token."))))?;
+
+	let pdus = services
+		.rooms
+		.timeline
+		.pdus_rev(None, room_id, Some(at))
+		.ignore_err();
+
+	pin_mut!(pdus);
+	let Some((_, pdu)) = pdus.next().await else {
+		return Err!(Request(NotFound("No events before the `at` token.")));
+	};
+
+	services
+		.rooms
+		.state_accessor
+		.pdu_shortstatehash(&pdu.event_id)
+		.await
+		.map_err(|e| err!(Database("Missing state for {}: {e}", pdu.event_id)))
 }
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/joined_members`
@@ -905,17 +950,18 @@ pub async fn join_room_by_id_helper(
 ) -> Result<join_room_by_id::v3::Response> {
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
-	let user_is_guest = services
-		.users
-		.is_deactivated(sender_user)
-		.await
-		.unwrap_or(false)
-		&& appservice_info.is_none();
+	let user_is_guest = appservice_info.is_none() && services.users.is_guest(sender_user).await;
 
 	if user_is_guest && !services.rooms.state_accessor.guest_can_join(room_id).await {
 		return Err!(Request(Forbidden("Guests are not allowed to join this room")));
 	}
 
+	if services.rooms.metadata.is_quarantined(room_id).await {
+		return Err!(Request(Forbidden(
+			"This room is quarantined by the server administrators and cannot be joined."
+		)));
+	}
+
 	if services
 		.rooms
 		.state_cache
@@ -948,6 +994,14 @@ pub async fn join_room_by_id_helper(
 		|| servers.is_empty()
 		|| (servers.len() == 1 && services.globals.server_is_ours(&servers[0]));
 
+	if appservice_info
+		.as_ref()
+		.is_none_or(RegistrationInfo::is_rate_limited)
+	{
+		let kind = if local_join { JoinKind::Local } else { JoinKind::Remote };
+		services.ratelimit.check_join(sender_user, kind)?;
+	}
+
 	if local_join {
 		join_room_by_id_helper_local(
 			services,
@@ -1737,6 +1791,24 @@ pub(crate) async fn invite_helper(
 		)));
 	}
 
+	services
+		.spam_checker
+		.check_allowed(&Check::Invite {
+			room_id,
+			inviter: sender_user,
+			invitee: user_id,
+		})
+		.await?;
+
+	match services.users.invite_permission(sender_user, user_id).await {
+		| InvitePermission::Allow => {},
+		// silently drop the invite, pretend it worked
+		| InvitePermission::Ignore => return Ok(()),
+		| InvitePermission::Block => {
+			return Err!(Request(Forbidden("{user_id} does not accept invites from you.")));
+		},
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
 	let content = RoomMemberEventContent {
@@ -2126,6 +2198,12 @@ async fn knock_room_by_id_helper(
 		return Err!(Request(Forbidden("You cannot knock on a room you are already joined in.")));
 	}
 
+	if services.rooms.metadata.is_quarantined(room_id).await {
+		return Err!(Request(Forbidden(
+			"This room is quarantined by the server administrators and cannot be knocked on."
+		)));
+	}
+
 	if services
 		.rooms
 		.state_cache
@@ -16,10 +16,15 @@
 	rooms::{
 		lazy_loading,
 		lazy_loading::{Options, Witness},
+		short::ShortStateHash,
 		timeline::PdusIterItem,
 	},
 };
-use futures::{FutureExt, StreamExt, TryFutureExt, future::OptionFuture, pin_mut};
+use futures::{
+	FutureExt, StreamExt, TryFutureExt,
+	future::{OptionFuture, join},
+	pin_mut,
+};
 use ruma::{
 	RoomId, UserId,
 	api::{
@@ -60,6 +65,8 @@
 ///
 /// Allows paginating through room history.
 ///
+/// - With lazy-loading, only the members of the senders of the returned events
+///   are sent, as of the last returned event
 /// - Only works if the user is joined (TODO: always allow, but only show events
 ///   where the user was joined, depending on `history_visibility`)
 pub(crate) async fn get_message_events_route(
@@ -144,14 +151,27 @@ pub(crate) async fn get_message_events_route(
 		.then(|| lazy_loading_witness(&services, &lazy_loading_context, events.iter()))
 		.into();
 
+	// Members are sent as of the last event of the chunk, which is the state
+	// clients need to display it.
+	let shortstatehash: OptionFuture<_> = events
+		.last()
+		.map(|(_, pdu)| {
+			services
+				.rooms
+				.state_accessor
+				.pdu_shortstatehash(&pdu.event_id)
+		})
+		.into();
+
+	let (witness, shortstatehash) = join(witness, shortstatehash).await;
+	let shortstatehash = shortstatehash.flat_ok();
+
 	let state = witness
-		.map(Option::into_iter)
-		.map(|option| option.flat_map(Witness::into_iter))
-		.map(IterStream::stream)
-		.into_stream()
-		.flatten()
+		.into_iter()
+		.flat_map(Witness::into_iter)
+		.stream()
 		.broad_filter_map(|user_id| async move {
-			get_member_event(&services, room_id, &user_id).await
+			get_member_event(&services, room_id, shortstatehash, &user_id).await
 		})
 		.collect()
 		.await;
@@ -221,15 +241,20 @@ pub(crate) async fn lazy_loading_witness<'a, I>(
 async fn get_member_event(
 	services: &Services,
 	room_id: &RoomId,
+	shortstatehash: Option<ShortStateHash>,
 	user_id: &UserId,
 ) -> Option<Raw<AnyStateEvent>> {
-	services
-		.rooms
-		.state_accessor
-		.room_state_get(room_id, &StateEventType::RoomMember, user_id.as_str())
-		.map_ok(PduEvent::into_state_event)
-		.await
-		.ok()
+	let state_accessor = &services.rooms.state_accessor;
+	let member = match shortstatehash {
+		| Some(shortstatehash) => state_accessor
+			.state_get(shortstatehash, &StateEventType::RoomMember, user_id.as_str())
+			.boxed(),
+		| None => state_accessor
+			.room_state_get(room_id, &StateEventType::RoomMember, user_id.as_str())
+			.boxed(),
+	};
+
+	member.map_ok(PduEvent::into_state_event).await.ok()
 }
 
 #[inline]
@@ -265,13 +290,13 @@ pub(crate) async fn is_ignored_pdu(
 		.forbidden_remote_server_names
 		.is_match(pdu.sender().server_name().host());
 
-	if ignored_type
-		&& (ignored_server || services.users.user_is_ignored(&pdu.sender, user_id).await)
-	{
+	if ignored_type && ignored_server {
 		return true;
 	}
 
-	false
+	// Events of users the requester ignores never reach them, except for state
+	// events which are needed to make sense of the room.
+	pdu.state_key.is_none() && services.users.user_is_ignored(&pdu.sender, user_id).await
 }
 
 #[inline]
@@ -0,0 +1,33 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use conduwuit::{Err, Result};
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc2965/auth_issuer`
+///
+/// Returns the OpenID Connect provider authentication is delegated to
+/// (MSC2965), otherwise returns 404.
+pub(crate) async fn auth_issuer_route(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let config = &services.server.config.oidc;
+	let Some(issuer) = config.issuer.as_ref().filter(|_| config.enable) else {
+		return Err!(Request(NotFound("Authentication is not delegated.")));
+	};
+
+	Ok(Json(serde_json::json!({
+		"issuer": issuer,
+	})))
+}
+
+/// # `GET /_matrix/client/v1/auth_metadata`
+///
+/// Returns the OAuth 2.0 authorization server metadata of the OpenID Connect
+/// provider authentication is delegated to (MSC2965), otherwise returns 404.
+pub(crate) async fn auth_metadata_route(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	if !services.oidc.enabled() {
+		return Err!(Request(NotFound("Authentication is not delegated.")));
+	}
+
+	Ok(Json(services.oidc.auth_metadata().await?))
+}
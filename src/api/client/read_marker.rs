@@ -2,6 +2,7 @@
 
 use axum::extract::State;
 use conduwuit::{Err, PduCount, Result, err};
+use database::Batch;
 use ruma::{
 	MilliSecondsSinceUnixEpoch,
 	api::client::{read_marker::set_read_marker, receipt::create_receipt},
@@ -43,10 +44,12 @@ pub(crate) async fn set_read_marker_route(
 	}
 
 	if body.private_read_receipt.is_some() || body.read_receipt.is_some() {
+		let mut batch = Batch::default();
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_notification_counts(&mut batch, sender_user, &body.room_id);
+		batch.write();
 	}
 
 	// ping presence
@@ -97,10 +100,14 @@ pub(crate) async fn set_read_marker_route(
 			)));
 		};
 
-		services
-			.rooms
-			.read_receipt
-			.private_read_set(&body.room_id, sender_user, count);
+		let mut batch = Batch::default();
+		services.rooms.read_receipt.private_read_set(
+			&mut batch,
+			&body.room_id,
+			sender_user,
+			count,
+		);
+		batch.write();
 	}
 
 	Ok(set_read_marker::v3::Response {})
@@ -119,10 +126,12 @@ pub(crate) async fn create_receipt_route(
 		&body.receipt_type,
 		create_receipt::v3::ReceiptType::Read | create_receipt::v3::ReceiptType::ReadPrivate
 	) {
+		let mut batch = Batch::default();
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_notification_counts(&mut batch, sender_user, &body.room_id);
+		batch.write();
 	}
 
 	// ping presence
@@ -192,10 +201,14 @@ pub(crate) async fn create_receipt_route(
 				)));
 			};
 
-			services
-				.rooms
-				.read_receipt
-				.private_read_set(&body.room_id, sender_user, count);
+			let mut batch = Batch::default();
+			services.rooms.read_receipt.private_read_set(
+				&mut batch,
+				&body.room_id,
+				sender_user,
+				count,
+			);
+			batch.write();
 		},
 		| _ => {
 			return Err!(Request(InvalidParam(warn!(
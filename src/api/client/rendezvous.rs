@@ -0,0 +1,172 @@
+use axum::{
+	Json,
+	body::Bytes,
+	extract::{Path, State},
+	http::{HeaderMap, HeaderValue, StatusCode, header},
+	response::IntoResponse,
+};
+use axum_client_ip::SecureClientIp;
+use axum_extra::{
+	TypedHeader,
+	headers::{CacheControl, ETag, Expires, LastModified},
+};
+use conduwuit::{Err, Result, err};
+use conduwuit_service::{Services, rendezvous::Session};
+
+const RENDEZVOUS_PATH: &str = "/_matrix/client/unstable/org.matrix.msc4108/rendezvous";
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4108/rendezvous`
+///
+/// Create a rendezvous session for QR code login (MSC4108) holding the
+/// request body. Returns the URL of the session.
+pub(crate) async fn create_rendezvous_route(
+	State(services): State<crate::State>,
+	SecureClientIp(client): SecureClientIp,
+	headers: HeaderMap,
+	body: Bytes,
+) -> Result<impl IntoResponse> {
+	check_enabled(&services)?;
+
+	let (id, session) = services
+		.rendezvous
+		.create(client, content_type(&headers), body)?;
+
+	let base = services
+		.server
+		.config
+		.well_known
+		.client
+		.as_ref()
+		.map_or_else(
+			|| format!("https://{}", services.server.config.server_name),
+			|url| url.as_str().trim_end_matches('/').to_owned(),
+		);
+
+	let url = format!("{base}{RENDEZVOUS_PATH}/{id}");
+
+	Ok((
+		StatusCode::CREATED,
+		session_headers(&session)?,
+		Json(serde_json::json!({ "url": url })),
+	))
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Get the current content of a rendezvous session. Responds with 304 Not
+/// Modified if the content matches the `If-None-Match` header.
+pub(crate) async fn get_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+	check_enabled(&services)?;
+
+	let session = services.rendezvous.get(&id)?;
+	if headers
+		.get(header::IF_NONE_MATCH)
+		.is_some_and(|value| etag_matches(value, &session.etag))
+	{
+		return Ok((StatusCode::NOT_MODIFIED, session_headers(&session)?).into_response());
+	}
+
+	let content_type = HeaderValue::try_from(&session.content_type)
+		.map_err(|e| err!(Request(InvalidParam("Invalid content type: {e}"))))?;
+
+	Ok((
+		StatusCode::OK,
+		session_headers(&session)?,
+		[(header::CONTENT_TYPE, content_type)],
+		session.content,
+	)
+		.into_response())
+}
+
+/// # `PUT /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Replace the content of a rendezvous session. The `If-Match` header must
+/// carry the entity tag of the current content.
+pub(crate) async fn update_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	headers: HeaderMap,
+	body: Bytes,
+) -> Result<impl IntoResponse> {
+	check_enabled(&services)?;
+
+	let Some(if_match) = headers.get(header::IF_MATCH) else {
+		return Err!(Request(MissingParam("Missing If-Match header.")));
+	};
+
+	let if_match = if_match
+		.to_str()
+		.map_err(|e| err!(Request(InvalidParam("Invalid If-Match header: {e}"))))?;
+
+	let session =
+		services
+			.rendezvous
+			.update(&id, unquote(if_match), content_type(&headers), body)?;
+
+	Ok((StatusCode::ACCEPTED, session_headers(&session)?))
+}
+
+/// # `DELETE /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Delete a rendezvous session.
+pub(crate) async fn delete_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+	check_enabled(&services)?;
+
+	services.rendezvous.delete(&id)?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+fn check_enabled(services: &Services) -> Result {
+	if !services.server.config.rendezvous_enable {
+		return Err!(Request(NotFound("Rendezvous sessions are not enabled.")));
+	}
+
+	Ok(())
+}
+
+fn session_headers(
+	session: &Session,
+) -> Result<(
+	TypedHeader<ETag>,
+	TypedHeader<Expires>,
+	TypedHeader<LastModified>,
+	TypedHeader<CacheControl>,
+)> {
+	let etag: ETag = format!("\"{}\"", session.etag)
+		.parse()
+		.map_err(|_| err!("Invalid rendezvous entity tag"))?;
+
+	Ok((
+		TypedHeader(etag),
+		TypedHeader(Expires::from(session.expires)),
+		TypedHeader(LastModified::from(session.last_modified)),
+		TypedHeader(CacheControl::new().with_no_store()),
+	))
+}
+
+fn content_type(headers: &HeaderMap) -> String {
+	headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.unwrap_or("application/octet-stream")
+		.to_owned()
+}
+
+fn etag_matches(value: &HeaderValue, etag: &str) -> bool {
+	value.to_str().is_ok_and(|value| {
+		value
+			.split(',')
+			.map(str::trim)
+			.any(|tag| tag == "*" || unquote(tag) == etag)
+	})
+}
+
+fn unquote(tag: &str) -> &str { tag.trim_start_matches("W/").trim_matches('"') }
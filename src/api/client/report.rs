@@ -3,10 +3,10 @@
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{Err, Error, Result, debug_info, info, matrix::pdu::PduEvent, utils::ReadyExt};
-use conduwuit_service::Services;
+use conduwuit_service::{Services, reports::Report};
 use rand::Rng;
 use ruma::{
-	EventId, RoomId, UserId,
+	EventId, MilliSecondsSinceUnixEpoch, RoomId, UserId,
 	api::client::{
 		error::ErrorKind,
 		room::{report_content, report_room},
@@ -75,6 +75,9 @@ pub(crate) async fn report_room_route(
 /// # `POST /_matrix/client/v3/rooms/{roomId}/report/{eventId}`
 ///
 /// Reports an inappropriate event to homeserver admins
+///
+/// - The report is stored for review with the `rooms list-reports` admin
+///   command
 #[tracing::instrument(skip_all, fields(%client), name = "report_event")]
 pub(crate) async fn report_event_route(
 	State(services): State<crate::State>,
@@ -110,13 +113,25 @@ pub(crate) async fn report_event_route(
 	)
 	.await?;
 
-	// send admin room message that we received the report with an @room ping for
+	let report_id = services.reports.add_report(&Report {
+		reporter: sender_user.to_owned(),
+		room_id: Some(pdu.room_id.clone()),
+		event_id: Some(pdu.event_id.clone()),
+		reported_user: pdu.sender.clone(),
+		reason: body.reason.clone(),
+		score: body.score,
+		received_ts: MilliSecondsSinceUnixEpoch::now(),
+		resolved: None,
+	})?;
+
+	// send admin room notice that we received the report with an @room ping for
 	// urgency
 	services
 		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Event report received from {} -\n\nEvent ID: {}\nRoom ID: {}\nSent By: \
-			 {}\n\nReport Score: {}\nReport Reason: {}",
+		.send_message(message::RoomMessageEventContent::notice_markdown(format!(
+			"@room Event report #{report_id} received from {} -\n\nEvent ID: {}\nRoom ID: \
+			 {}\nSent By: {}\n\nReport Score: {}\nReport Reason: {}\n\nResolve it with `!admin \
+			 rooms resolve-report {report_id}`.",
 			sender_user.to_owned(),
 			pdu.event_id,
 			pdu.room_id,
@@ -130,6 +145,78 @@ pub(crate) async fn report_event_route(
 	Ok(report_content::v3::Response {})
 }
 
+/// # `POST /_matrix/client/v3/users/{userId}/report`
+///
+/// Reports an abusive user to homeserver admins (MSC4260)
+///
+/// - The report is stored for review with the `rooms list-reports` admin
+///   command
+#[tracing::instrument(skip_all, fields(%client), name = "report_user")]
+pub(crate) async fn report_user_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<report_user::Request>,
+) -> Result<report_user::Response> {
+	// user authentication
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	info!(
+		"Received user report by user {sender_user} for user {}, with reason: \"{}\"",
+		body.user_id, body.reason
+	);
+
+	if body.reason.len() > 750 {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Reason too long, should be 750 characters or fewer",
+		));
+	}
+
+	delay_response().await;
+
+	// only accept reports of users we know and the reporter could have seen
+	let known = if services.globals.user_is_local(&body.user_id) {
+		services.users.exists(&body.user_id).await
+	} else {
+		services
+			.rooms
+			.state_cache
+			.user_sees_user(sender_user, &body.user_id)
+			.await
+	};
+
+	if !known {
+		return Err!(Request(NotFound("User is not known to us or User ID is invalid")));
+	}
+
+	let report_id = services.reports.add_report(&Report {
+		reporter: sender_user.to_owned(),
+		room_id: None,
+		event_id: None,
+		reported_user: body.user_id.clone(),
+		reason: Some(body.reason.clone()),
+		score: None,
+		received_ts: MilliSecondsSinceUnixEpoch::now(),
+		resolved: None,
+	})?;
+
+	// send admin room notice that we received the report with an @room ping for
+	// urgency
+	services
+		.admin
+		.send_message(message::RoomMessageEventContent::notice_markdown(format!(
+			"@room User report #{report_id} received from {} -\n\nReported User: {}\n\nReport \
+			 Reason: {}\n\nResolve it with `!admin rooms resolve-report {report_id}`.",
+			sender_user.to_owned(),
+			body.user_id,
+			body.reason,
+		)))
+		.await
+		.ok();
+
+	Ok(report_user::Response {})
+}
+
 /// in the following order:
 ///
 /// check if the room ID from the URI matches the PDU's room ID
@@ -198,3 +285,37 @@ async fn delay_response() {
 	);
 	sleep(Duration::from_secs(time_to_wait)).await;
 }
+
+/// `POST /_matrix/client/v3/users/{userId}/report` (MSC4260), which ruma does
+/// not provide yet.
+pub(crate) mod report_user {
+	use ruma::{
+		OwnedUserId,
+		api::{Metadata, metadata, request, response},
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: true,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_matrix/client/unstable/org.matrix.msc4260/users/:user_id/report",
+			unstable => "/_matrix/client/v3/users/:user_id/report",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The user being reported.
+		#[ruma_api(path)]
+		pub user_id: OwnedUserId,
+
+		/// The reason the user is being reported.
+		#[serde(default)]
+		pub reason: String,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	#[derive(Default)]
+	pub struct Response {}
+}
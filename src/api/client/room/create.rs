@@ -6,10 +6,13 @@
 	matrix::{StateKey, pdu::PduBuilder},
 	warn,
 };
-use conduwuit_service::{Services, appservice::RegistrationInfo};
+use conduwuit_service::{
+	Services, appservice::RegistrationInfo, audit::Kind, media::FileMeta, spam_checker::Check,
+};
 use futures::FutureExt;
 use ruma::{
-	CanonicalJsonObject, Int, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+	CanonicalJsonObject, Int, Mxc, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId,
+	RoomVersionId,
 	api::client::{
 		error::ErrorKind,
 		room::{self, create_room},
@@ -31,10 +34,13 @@
 	int,
 	serde::{JsonObject, Raw},
 };
-use serde_json::{json, value::to_raw_value};
+use serde_json::{Value as JsonValue, json, value::to_raw_value};
 
 use crate::{Ruma, client::invite_helper};
 
+/// Unstable field of image info holding its blurhash (MSC2448)
+const BLURHASH_FIELD: &str = "xyz.amorgan.blurhash";
+
 /// # `POST /_matrix/client/v3/createRoom`
 ///
 /// Creates a new room.
@@ -48,7 +54,8 @@
 /// - Send join rules
 /// - Send history visibility
 /// - Send guest access
-/// - Send events listed in initial state
+/// - Send events listed in initial state, blurhashing a room avatar stored on
+///   this server if enabled
 /// - Send events implied by `name` and `topic`
 /// - Send invite events
 #[allow(clippy::large_stack_frames)]
@@ -60,16 +67,21 @@ pub(crate) async fn create_room_route(
 
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	if !services.globals.allow_room_creation()
-		&& body.appservice_info.is_none()
-		&& !services.users.is_admin(sender_user).await
-	{
+	let creation_disabled =
+		!services.globals.allow_room_creation() && body.appservice_info.is_none();
+
+	if creation_disabled && !services.users.is_admin(sender_user).await {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
 			"Room creation has been disabled.",
 		));
 	}
 
+	services
+		.spam_checker
+		.check_allowed(&Check::RoomCreation { user_id: sender_user })
+		.await?;
+
 	let room_id: OwnedRoomId = match &body.room_id {
 		| Some(custom_room_id) => custom_room_id_check(&services, custom_room_id)?,
 		| _ => RoomId::new(&services.server.name),
@@ -379,6 +391,15 @@ pub(crate) async fn create_room_route(
 			continue;
 		}
 
+		if pdu_builder.event_type == TimelineEventType::RoomAvatar
+			&& services.media.blurhash_on_upload()
+		{
+			blurhash_room_avatar(&services, &mut pdu_builder)
+				.await
+				.inspect_err(|e| debug_warn!("Failed to blurhash room avatar: {e}"))
+				.ok();
+		}
+
 		services
 			.rooms
 			.timeline
@@ -457,6 +478,25 @@ pub(crate) async fn create_room_route(
 				.await;
 		}
 		info!("{sender_user} made {0} public to the room directory", &room_id);
+
+		if services.server.config.lockdown_public_room_directory && body.appservice_info.is_none()
+		{
+			services.audit.record(
+				Kind::Api,
+				Some(sender_user),
+				format!("createRoom: published {room_id} to the locked down room directory"),
+				None,
+			);
+		}
+	}
+
+	if creation_disabled {
+		services.audit.record(
+			Kind::Api,
+			Some(sender_user),
+			format!("createRoom: created {room_id} while room creation is disabled"),
+			None,
+		);
 	}
 
 	info!("{sender_user} created a room with room ID {room_id}");
@@ -595,6 +635,51 @@ async fn room_alias_check(
 	Ok(full_room_alias)
 }
 
+/// Adds a blurhash to the info of a room avatar stored on this server, unless
+/// the client gave one already (MSC2448).
+async fn blurhash_room_avatar(services: &Services, pdu_builder: &mut PduBuilder) -> Result {
+	let mut content: JsonObject = serde_json::from_str(pdu_builder.content.get())?;
+	let has_blurhash = content
+		.get("info")
+		.and_then(|info| info.get(BLURHASH_FIELD))
+		.is_some();
+
+	let Some(url) = content.get("url").and_then(JsonValue::as_str) else {
+		return Ok(());
+	};
+
+	let mxc: Mxc<'_> = url.try_into()?;
+	if has_blurhash || !services.globals.server_is_ours(mxc.server_name) {
+		return Ok(());
+	}
+
+	let Some(FileMeta { content: Some(file), content_type, .. }) =
+		services.media.get(&mxc).await?
+	else {
+		return Ok(());
+	};
+
+	let Some(blurhash) = services
+		.media
+		.create_blurhash(&file, content_type.as_deref(), None)
+		.await?
+	else {
+		return Ok(());
+	};
+
+	if let Some(info) = content
+		.entry("info")
+		.or_insert_with(|| json!({}))
+		.as_object_mut()
+	{
+		info.insert(BLURHASH_FIELD.to_owned(), blurhash.into());
+	}
+
+	pdu_builder.content = to_raw_value(&content)?;
+
+	Ok(())
+}
+
 /// if a room is being created with a custom room ID, run our checks against it
 fn custom_room_id_check(services: &Services, custom_room_id: &str) -> Result<OwnedRoomId> {
 	// apply forbidden room alias checks to custom room IDs too
@@ -1,17 +1,17 @@
 use axum::extract::State;
-use conduwuit::{Err, Event, Result, err};
+use conduwuit::{Err, Event, PduEvent, Result, err};
 use futures::{FutureExt, TryFutureExt, future::try_join};
-use ruma::api::client::room::get_room_event;
 
 use crate::{Ruma, client::is_ignored_pdu};
 
 /// # `GET /_matrix/client/r0/rooms/{roomId}/event/{eventId}`
 ///
-/// Gets a single event.
+/// Gets a single event. Users allowed to redact the event may request the
+/// original content of a redacted event (MSC2815).
 pub(crate) async fn get_room_event_route(
 	State(ref services): State<crate::State>,
-	ref body: Ruma<get_room_event::v3::Request>,
-) -> Result<get_room_event::v3::Response> {
+	ref body: Ruma<get_room_event::Request>,
+) -> Result<get_room_event::Response> {
 	let event_id = &body.event_id;
 	let room_id = &body.room_id;
 
@@ -38,7 +38,89 @@ pub(crate) async fn get_room_event_route(
 		"Fetched PDU must match requested"
 	);
 
+	if body.include_unredacted_content && event.is_redacted() {
+		event = unredacted_event(services, body, event).await?;
+	}
+
 	event.add_age().ok();
 
-	Ok(get_room_event::v3::Response { event: event.into_room_event() })
+	Ok(get_room_event::Response { event: event.into_room_event() })
+}
+
+async fn unredacted_event(
+	services: &crate::State,
+	body: &Ruma<get_room_event::Request>,
+	redacted: PduEvent,
+) -> Result<PduEvent> {
+	if !services.rooms.unredacted.enabled() {
+		return Err!(Request(Unrecognized(
+			"Viewing redacted content is not enabled on this server."
+		)));
+	}
+
+	if !services
+		.rooms
+		.state_accessor
+		.user_can_redact(&body.event_id, body.sender_user(), &body.room_id, false)
+		.await?
+	{
+		return Err!(Request(Forbidden(
+			"You don't have permission to view the redacted content of this event."
+		)));
+	}
+
+	let original = services
+		.rooms
+		.unredacted
+		.get(&body.event_id)
+		.await
+		.map_err(|_| {
+			err!(Request(NotFound("The redacted content of this event was deleted.")))
+		})?;
+
+	let mut event = PduEvent::from_id_val(&body.event_id, original)?;
+	event.unsigned = redacted.unsigned;
+
+	Ok(event)
+}
+
+/// `GET /_matrix/client/v3/rooms/{roomId}/event/{eventId}` with the
+/// `fi.mau.msc2815.include_unredacted_content` query parameter (MSC2815),
+/// which ruma does not provide yet.
+pub(crate) mod get_room_event {
+	use ruma::{
+		OwnedEventId, OwnedRoomId,
+		api::{Metadata, client::room::get_room_event::v3, request, response},
+		events::AnyTimelineEvent,
+		serde::Raw,
+	};
+
+	const METADATA: Metadata = v3::Request::METADATA;
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The ID of the room the event is in.
+		#[ruma_api(path)]
+		pub room_id: OwnedRoomId,
+
+		/// The ID of the event.
+		#[ruma_api(path)]
+		pub event_id: OwnedEventId,
+
+		/// Whether to return the original content of a redacted event.
+		#[ruma_api(query)]
+		#[serde(
+			default,
+			rename = "fi.mau.msc2815.include_unredacted_content",
+			skip_serializing_if = "ruma::serde::is_default"
+		)]
+		pub include_unredacted_content: bool,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {
+		/// The event.
+		#[ruma_api(body)]
+		pub event: Raw<AnyTimelineEvent>,
+	}
 }
@@ -2,9 +2,18 @@
 
 use axum::extract::State;
 use conduwuit::{Err, Result, err, matrix::pdu::PduBuilder, utils};
-use ruma::{api::client::message::send_message_event, events::MessageLikeEventType};
+use ruma::{
+	DeviceId, OwnedEventId, TransactionId, UserId,
+	api::client::message::send_message_event,
+	events::{MessageLikeEventType, TimelineEventType},
+};
 use serde_json::from_str;
+use service::{
+	Services,
+	spam_checker::{Check, Verdict},
+};
 
+use super::{MaybeDelayed, MaybeDelayedResponse};
 use crate::Ruma;
 
 /// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
@@ -18,8 +27,8 @@
 ///   allowed
 pub(crate) async fn send_message_event_route(
 	State(services): State<crate::State>,
-	body: Ruma<send_message_event::v3::Request>,
-) -> Result<send_message_event::v3::Response> {
+	body: Ruma<MaybeDelayed<send_message_event::v3::Request>>,
+) -> Result<MaybeDelayedResponse<send_message_event::v3::Response>> {
 	let sender_user = body.sender_user();
 	let sender_device = body.sender_device.as_deref();
 	let appservice_info = body.appservice_info.as_ref();
@@ -30,6 +39,78 @@ pub(crate) async fn send_message_event_route(
 		return Err!(Request(Forbidden("Encryption has been disabled")));
 	}
 
+	if body.event_type != MessageLikeEventType::RoomMessage
+		&& appservice_info.is_none()
+		&& services.users.is_guest(sender_user).await
+	{
+		return Err!(Request(GuestAccessForbidden("Guests may only send m.room.message events")));
+	}
+
+	if let Some(delay) = body.delay {
+		// a retried request gets the delay ID the event was scheduled with
+		if let Ok(response) = services
+			.transaction_ids
+			.existing_txnid(sender_user, sender_device, &body.txn_id)
+			.await
+		{
+			return match utils::string_from_bytes(&response) {
+				| Ok(delay_id) if !delay_id.is_empty() && !delay_id.starts_with('$') =>
+					Ok(MaybeDelayedResponse::Delayed(delay_id)),
+				| _ => Err!(Request(InvalidParam(
+					"Tried to use txn id already used for an incompatible endpoint."
+				))),
+			};
+		}
+
+		let delay_id = services
+			.delayed_events
+			.schedule(
+				sender_user,
+				&body.room_id,
+				body.event_type.clone().into(),
+				None,
+				body.body.body.json(),
+				delay,
+			)
+			.await?;
+
+		services.transaction_ids.add_txnid(
+			sender_user,
+			sender_device,
+			&body.txn_id,
+			delay_id.as_bytes(),
+		);
+
+		return Ok(MaybeDelayedResponse::Delayed(delay_id));
+	}
+
+	// A retried transaction gets the event it sent before without being checked
+	// again
+	if let Some(event_id) =
+		existing_event_id(&services, sender_user, sender_device, &body.txn_id).await?
+	{
+		return Ok(send_message_event::v3::Response { event_id }.into());
+	}
+
+	// The spam checker may call out to a webhook, so it is consulted before the
+	// room state is locked
+	let event_type: TimelineEventType = body.event_type.clone().into();
+	let soft_failed = match services
+		.spam_checker
+		.check(&Check::Event {
+			event_id: None,
+			room_id: &body.room_id,
+			sender: sender_user,
+			kind: &event_type,
+			state_key: None,
+			content: body.body.body.json(),
+		})
+		.await
+	{
+		| Verdict::SoftFail => true,
+		| verdict => verdict.allowed().map(|()| false)?,
+	};
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	if body.event_type == MessageLikeEventType::CallInvite
@@ -38,25 +119,12 @@ pub(crate) async fn send_message_event_route(
 		return Err!(Request(Forbidden("Room call invites are not allowed in public rooms")));
 	}
 
-	// Check if this is a new transaction id
-	if let Ok(response) = services
-		.transaction_ids
-		.existing_txnid(sender_user, sender_device, &body.txn_id)
-		.await
+	// Check again now that the state is locked, as a concurrent retry of the
+	// transaction may have completed meanwhile
+	if let Some(event_id) =
+		existing_event_id(&services, sender_user, sender_device, &body.txn_id).await?
 	{
-		// The client might have sent a txnid of the /sendToDevice endpoint
-		// This txnid has no response associated with it
-		if response.is_empty() {
-			return Err!(Request(InvalidParam(
-				"Tried to use txn id already used for an incompatible endpoint."
-			)));
-		}
-
-		return Ok(send_message_event::v3::Response {
-			event_id: utils::string_from_bytes(&response)
-				.map(TryInto::try_into)
-				.map_err(|e| err!(Database("Invalid event_id in txnid data: {e:?}")))??,
-		});
+		return Ok(send_message_event::v3::Response { event_id }.into());
 	}
 
 	let mut unsigned = BTreeMap::new();
@@ -65,22 +133,31 @@ pub(crate) async fn send_message_event_route(
 	let content = from_str(body.body.body.json().get())
 		.map_err(|e| err!(Request(BadJson("Invalid JSON body: {e}"))))?;
 
-	let event_id = services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder {
-				event_type: body.event_type.clone().into(),
-				content,
-				unsigned: Some(unsigned),
-				timestamp: appservice_info.and(body.timestamp),
-				..Default::default()
-			},
-			sender_user,
-			&body.room_id,
-			&state_lock,
-		)
-		.await?;
+	let pdu_builder = PduBuilder {
+		event_type,
+		content,
+		unsigned: Some(unsigned),
+		timestamp: appservice_info.and(body.timestamp),
+		..Default::default()
+	};
+
+	let event_id = if soft_failed {
+		// the event is created for its ID but never sent, so the sender can't
+		// tell it was soft failed
+		services
+			.rooms
+			.timeline
+			.create_hash_and_sign_event(pdu_builder, sender_user, &body.room_id, &state_lock)
+			.await?
+			.0
+			.event_id
+	} else {
+		services
+			.rooms
+			.timeline
+			.build_and_append_pdu(pdu_builder, sender_user, &body.room_id, &state_lock)
+			.await?
+	};
 
 	services.transaction_ids.add_txnid(
 		sender_user,
@@ -91,5 +168,36 @@ pub(crate) async fn send_message_event_route(
 
 	drop(state_lock);
 
-	Ok(send_message_event::v3::Response { event_id })
+	Ok(send_message_event::v3::Response { event_id }.into())
+}
+
+/// The event sent by an earlier request with the same transaction ID, if any.
+async fn existing_event_id(
+	services: &Services,
+	sender_user: &UserId,
+	sender_device: Option<&DeviceId>,
+	txn_id: &TransactionId,
+) -> Result<Option<OwnedEventId>> {
+	let Ok(response) = services
+		.transaction_ids
+		.existing_txnid(sender_user, sender_device, txn_id)
+		.await
+	else {
+		return Ok(None);
+	};
+
+	// The client might have sent a txnid of the /sendToDevice endpoint, which
+	// has no response associated with it, or of a delayed event, whose response
+	// is its delay ID
+	if response.is_empty() || !response.starts_with(b"$") {
+		return Err!(Request(InvalidParam(
+			"Tried to use txn id already used for an incompatible endpoint."
+		)));
+	}
+
+	let event_id = utils::string_from_bytes(&response)
+		.map_err(|e| err!(Database("Invalid event_id in txnid data: {e:?}")))?
+		.try_into()?;
+
+	Ok(Some(event_id))
 }
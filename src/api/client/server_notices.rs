@@ -0,0 +1,65 @@
+use axum::extract::State;
+use conduwuit::{Err, Result, info};
+
+use crate::Ruma;
+
+/// # `POST /_conduwuit/admin/v1/send_server_notice`
+///
+/// Sends a server notice to a local user as a server admin. Also served at
+/// Synapse's path so existing tooling works.
+///
+/// - The notices room of the user is created and the user invited to it on the
+///   first notice
+pub(crate) async fn send_server_notice_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_server_notice::Request>,
+) -> Result<send_server_notice::Response> {
+	let sender_user = body.sender_user();
+	if !services.admin.user_is_admin(sender_user).await {
+		return Err!(Request(Forbidden("Only server admins can send server notices.")));
+	}
+
+	let event_id = services
+		.server_notices
+		.send(&body.user_id, &body.content)
+		.await?;
+
+	info!("{sender_user} sent server notice {event_id} to {}", body.user_id);
+
+	Ok(send_server_notice::Response { event_id })
+}
+
+/// `POST /_conduwuit/admin/v1/send_server_notice`, compatible with Synapse's
+/// admin API.
+pub(crate) mod send_server_notice {
+	use ruma::{
+		OwnedEventId, OwnedUserId,
+		api::{Metadata, metadata, request, response},
+		events::room::message::RoomMessageEventContent,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_conduwuit/admin/v1/send_server_notice",
+			unstable => "/_synapse/admin/v1/send_server_notice",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The local user to send the notice to.
+		pub user_id: OwnedUserId,
+
+		/// Content of the `m.room.message` event of the notice.
+		pub content: RoomMessageEventContent,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {
+		/// ID of the notice event.
+		pub event_id: OwnedEventId,
+	}
+}
@@ -6,16 +6,19 @@
 	Err, Error, Result, debug, err, info, utils,
 	utils::{ReadyExt, hash},
 };
-use conduwuit_service::uiaa::SESSION_ID_LENGTH;
+use conduwuit_service::{jwt, uiaa::SESSION_ID_LENGTH, users};
 use futures::StreamExt;
 use ruma::{
-	UserId,
+	CanonicalJsonValue, UserId,
 	api::client::{
 		session::{
 			get_login_token,
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType, TokenLoginType},
+				v3::{
+					ApplicationServiceLoginType, IdentityProvider, PasswordLoginType,
+					SsoLoginType, TokenLoginType,
+				},
 			},
 			login::{
 				self,
@@ -25,6 +28,7 @@
 		},
 		uiaa,
 	},
+	serde::JsonObject,
 };
 
 use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
@@ -40,13 +44,45 @@ pub(crate) async fn get_login_types_route(
 	InsecureClientIp(client): InsecureClientIp,
 	_body: Ruma<get_login_types::v3::Request>,
 ) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
+	// Clients authenticate against the OpenID Connect provider instead
+	if services.oidc.enabled() {
+		return Ok(get_login_types::v3::Response::new(vec![
+			get_login_types::v3::LoginType::ApplicationService(
+				ApplicationServiceLoginType::default(),
+			),
+		]));
+	}
+
+	let mut flows = vec![
 		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
 		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
 		get_login_types::v3::LoginType::Token(TokenLoginType {
 			get_login_token: services.server.config.login_via_existing_session,
 		}),
-	]))
+	];
+
+	if services.sso.enabled() {
+		let identity_providers = services
+			.sso
+			.providers()
+			.iter()
+			.map(|provider| IdentityProvider {
+				icon: provider.icon.clone(),
+				brand: provider.brand.as_deref().map(Into::into),
+				..IdentityProvider::new(provider.id.clone(), provider.name.clone())
+			})
+			.collect();
+
+		flows.push(get_login_types::v3::LoginType::Sso(SsoLoginType { identity_providers }));
+	}
+
+	if services.jwt.enabled() {
+		for login_type in [jwt::LOGIN_TYPE, jwt::LOGIN_TYPE_STABLE] {
+			flows.push(get_login_types::v3::LoginType::new(login_type, JsonObject::new())?);
+		}
+	}
+
+	Ok(get_login_types::v3::Response::new(flows))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -71,6 +107,12 @@ pub(crate) async fn login_route(
 ) -> Result<login::v3::Response> {
 	let emergency_mode_enabled = services.config.emergency_password.is_some();
 
+	if services.oidc.enabled()
+		&& !matches!(body.login_info, login::v3::LoginInfo::ApplicationService(_))
+	{
+		return Err!(Request(Unknown("Login is delegated to the OpenID Connect provider.")));
+	}
+
 	// Validate login method
 	// TODO: Other login methods
 	let user_id = match &body.login_info {
@@ -105,48 +147,66 @@ pub(crate) async fn login_route(
 				return Err!(Request(Unknown("User ID does not belong to this homeserver")));
 			}
 
-			// first try the username as-is
-			let hash = services
-				.users
-				.password_hash(&user_id)
-				.await
-				.inspect_err(|e| debug!("{e}"));
-
-			match hash {
-				| Ok(hash) => {
-					if hash.is_empty() {
-						return Err!(Request(UserDeactivated("The user has been deactivated")));
-					}
-
-					hash::verify_password(password, &hash)
-						.inspect_err(|e| debug!("{e}"))
-						.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
-
-					user_id
-				},
-				| Err(_e) => {
-					let hash_lowercased_user_id = services
-						.users
-						.password_hash(&lowercased_user_id)
-						.await
-						.inspect_err(|e| debug!("{e}"))
-						.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
-
-					if hash_lowercased_user_id.is_empty() {
-						return Err!(Request(UserDeactivated("The user has been deactivated")));
-					}
-
-					hash::verify_password(password, &hash_lowercased_user_id)
-						.inspect_err(|e| debug!("{e}"))
-						.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
-
-					lowercased_user_id
-				},
+			// accounts in the LDAP directory authenticate against it; other accounts fall
+			// back to their local password
+			if services.ldap.enabled()
+				&& services.ldap.login(&lowercased_user_id, password).await?
+			{
+				lowercased_user_id
+			} else {
+				// first try the username as-is
+				let hash = services
+					.users
+					.password_hash(&user_id)
+					.await
+					.inspect_err(|e| debug!("{e}"));
+
+				match hash {
+					| Ok(hash) => {
+						if hash.is_empty() {
+							return Err!(Request(UserDeactivated(
+								"The user has been deactivated"
+							)));
+						}
+
+						hash::verify_password(password, &hash)
+							.inspect_err(|e| debug!("{e}"))
+							.map_err(|_| {
+								err!(Request(Forbidden("Wrong username or password.")))
+							})?;
+
+						user_id
+					},
+					| Err(_e) => {
+						let hash_lowercased_user_id = services
+							.users
+							.password_hash(&lowercased_user_id)
+							.await
+							.inspect_err(|e| debug!("{e}"))
+							.map_err(|_| {
+								err!(Request(Forbidden("Wrong username or password.")))
+							})?;
+
+						if hash_lowercased_user_id.is_empty() {
+							return Err!(Request(UserDeactivated(
+								"The user has been deactivated"
+							)));
+						}
+
+						hash::verify_password(password, &hash_lowercased_user_id)
+							.inspect_err(|e| debug!("{e}"))
+							.map_err(|_| {
+								err!(Request(Forbidden("Wrong username or password.")))
+							})?;
+
+						lowercased_user_id
+					},
+				}
 			}
 		},
 		| login::v3::LoginInfo::Token(login::v3::Token { token }) => {
 			debug!("Got token login type");
-			if !services.server.config.login_via_existing_session {
+			if !services.server.config.login_via_existing_session && !services.sso.enabled() {
 				return Err!(Request(Unknown("Token login is not enabled.")));
 			}
 			services.users.find_from_login_token(token).await?
@@ -182,16 +242,41 @@ pub(crate) async fn login_route(
 				return Err!(Request(Exclusive("Username is not in an appservice namespace.")));
 			}
 
+			if info.registration.device_management {
+				return Err!(Request(Forbidden(
+					"Appservices managing their own devices (MSC4190) must create devices \
+					 instead of logging in."
+				)));
+			}
+
 			user_id
 		},
-		| _ => {
-			debug!("/login json_body: {:?}", &body.json_body);
-			return Err!(Request(Unknown(
-				debug_warn!(?body.login_info, "Invalid or unsupported login type")
-			)));
+		| _ => match jwt_login_token(body.json_body.as_ref()) {
+			| Some(token) if services.jwt.enabled() => {
+				debug!("Got JWT login type");
+				services.jwt.find_from_token(token).await?
+			},
+			| _ => {
+				debug!("/login json_body: {:?}", &body.json_body);
+				return Err!(Request(Unknown(
+					debug_warn!(?body.login_info, "Invalid or unsupported login type")
+				)));
+			},
 		},
 	};
 
+	if services.users.is_pending_approval(&user_id).await {
+		return Err(users::awaiting_approval_error());
+	}
+
+	if services.users.is_locked(&user_id).await {
+		return Err!(Request(UserLocked("This account has been locked.")));
+	}
+
+	if services.users.is_expired(&user_id).await {
+		return Err!(Request(UserLocked("This account has expired and needs to be renewed.")));
+	}
+
 	// Generate new device id if the user didn't specify one
 	let device_id = body
 		.device_id
@@ -253,6 +338,24 @@ pub(crate) async fn login_route(
 	})
 }
 
+/// The token of a JWT login, which ruma doesn't know about.
+fn jwt_login_token(json_body: Option<&CanonicalJsonValue>) -> Option<&str> {
+	let Some(CanonicalJsonValue::Object(body)) = json_body else {
+		return None;
+	};
+
+	match body.get("type") {
+		| Some(CanonicalJsonValue::String(login_type))
+			if login_type == jwt::LOGIN_TYPE || login_type == jwt::LOGIN_TYPE_STABLE => {},
+		| _ => return None,
+	}
+
+	match body.get("token") {
+		| Some(CanonicalJsonValue::String(token)) => Some(token),
+		| _ => None,
+	}
+}
+
 /// # `POST /_matrix/client/v1/login/get_token`
 ///
 /// Allows a logged-in user to get a short-lived token which can be used
@@ -269,6 +372,13 @@ pub(crate) async fn login_token_route(
 		return Err!(Request(Forbidden("Login via an existing session is not enabled")));
 	}
 
+	// the existing session can't complete password UIA; the provider issues logins
+	if services.oidc.enabled() {
+		return Err!(Request(Forbidden(
+			"Login via an existing session is delegated to the OpenID Connect provider"
+		)));
+	}
+
 	let sender_user = body.sender_user();
 	let sender_device = body.sender_device();
 
@@ -0,0 +1,97 @@
+use axum::{
+	extract::{RawQuery, State},
+	response::{Html, IntoResponse, Redirect},
+};
+use conduwuit::{
+	Err, Result, debug_warn, err, info,
+	utils::{self, html::Escape},
+};
+use ruma::api::client::session::{sso_login, sso_login_with_provider};
+use serde::Deserialize;
+
+use super::TOKEN_LENGTH;
+use crate::Ruma;
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+	state: String,
+	code: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user to the first configured SSO provider to log in.
+pub(crate) async fn sso_login_route(
+	State(services): State<crate::State>,
+	body: Ruma<sso_login::v3::Request>,
+) -> Result<sso_login::v3::Response> {
+	let location = services.sso.start_login(None, &body.redirect_url).await?;
+
+	Ok(sso_login::v3::Response::new(location.into()))
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Redirects the user to the SSO provider `idpId` to log in.
+pub(crate) async fn sso_login_with_provider_route(
+	State(services): State<crate::State>,
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	let location = services
+		.sso
+		.start_login(Some(&body.idp_id), &body.redirect_url)
+		.await?;
+
+	Ok(sso_login_with_provider::v3::Response::new(location.into()))
+}
+
+/// # `GET /_conduwuit/sso/callback`
+///
+/// Target SSO providers redirect users back to once they logged in. Redirects
+/// them to the client with a login token for `m.login.token`, asking them to
+/// confirm first unless the client is in `sso_redirect_allowlist`.
+pub(crate) async fn sso_callback_route(
+	State(services): State<crate::State>,
+	RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+	let query: CallbackQuery = serde_html_form::from_str(query.as_deref().unwrap_or(""))
+		.map_err(|e| err!(Request(InvalidParam("Invalid query: {e}"))))?;
+
+	let Some(code) = query.code else {
+		let error = query.error.as_deref().unwrap_or("unknown error");
+		let description = query.error_description.as_deref().unwrap_or_default();
+		debug_warn!(%error, %description, "SSO provider returned an error");
+		return Err!(Request(Forbidden("Login at the SSO provider failed: {error}")));
+	};
+
+	let (user_id, mut redirect_url) = services.sso.finish_login(&query.state, &code).await?;
+
+	let login_token = utils::random_string(TOKEN_LENGTH);
+	services.users.create_login_token(&user_id, &login_token);
+
+	redirect_url
+		.query_pairs_mut()
+		.append_pair("loginToken", &login_token);
+
+	info!("{user_id} logged in through SSO");
+
+	if services.sso.redirect_allowed(&redirect_url) {
+		return Ok(Redirect::to(redirect_url.as_str()).into_response());
+	}
+
+	let site = redirect_url.host_str().unwrap_or(redirect_url.scheme());
+
+	Ok(Html(format!(
+		"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Continue to \
+		 {site}?</title></head><body><p>You are logged in as {user_id}. Continue to \
+		 <strong>{site}</strong>, which will be able to access your account?</p><p><a \
+		 href=\"{url}\">Continue to {site}</a></p><p>If you did not start this login or do not \
+		 recognise this site, close this page.</p></body></html>\n",
+		site = Escape(site),
+		user_id = Escape(user_id.as_str()),
+		url = Escape(redirect_url.as_str()),
+	))
+	.into_response())
+}
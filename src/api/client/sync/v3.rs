@@ -17,7 +17,7 @@
 		self, BoolExt, IterStream, ReadyExt, TryFutureExtExt,
 		future::OptionStream,
 		math::ruma_from_u64,
-		stream::{BroadbandExt, Tools, TryExpect, WidebandExt},
+		stream::{BroadbandExt, Tools, TryExpect, TryIgnore, WidebandExt},
 	},
 	warn,
 };
@@ -42,13 +42,13 @@
 			v3::{
 				Ephemeral, Filter, GlobalAccountData, InviteState, InvitedRoom, JoinedRoom,
 				KnockState, KnockedRoom, LeftRoom, Presence, RoomAccountData, RoomSummary, Rooms,
-				State as RoomState, Timeline, ToDevice,
+				State as RoomState, StateEvents, Timeline, ToDevice,
 			},
 		},
 		uiaa::UiaaResponse,
 	},
 	events::{
-		AnyRawAccountDataEvent, AnySyncEphemeralRoomEvent, StateEventType,
+		AnyRawAccountDataEvent, AnySyncEphemeralRoomEvent, AnySyncStateEvent, StateEventType,
 		TimelineEventType::*,
 		presence::{PresenceEvent, PresenceEventContent},
 		room::member::{MembershipState, RoomMemberEventContent},
@@ -101,6 +101,11 @@ struct StateChanges {
 /// - EDUs that are active now (read receipts, typing updates, presence)
 /// - TODO: Allow multiple sync streams to support Pantalaimon
 ///
+/// If `use_state_after` is set (MSC4222), the state of joined and left rooms
+/// is returned in `state_after` instead of `state`. It then contains the
+/// changes up to the end of the timeline, including state events which are
+/// also part of the timeline.
+///
 /// For invited rooms:
 /// - If the user was invited after `since`: A subset of the state of the room
 ///   at the point of the invite
@@ -169,6 +174,7 @@ pub(crate) async fn build_sync_events(
 		.unwrap_or(0);
 
 	let full_state = body.body.full_state;
+	let use_state_after = body.body.use_state_after;
 	let filter = match body.body.filter.as_ref() {
 		| None => FilterDefinition::default(),
 		| Some(Filter::FilterDefinition(filter)) => filter.clone(),
@@ -193,6 +199,7 @@ pub(crate) async fn build_sync_events(
 				since,
 				next_batch,
 				full_state,
+				use_state_after,
 				&filter,
 			)
 			.map_ok(move |(joined_room, dlu, jeu)| (room_id, joined_room, dlu, jeu))
@@ -224,6 +231,7 @@ pub(crate) async fn build_sync_events(
 				sender_user,
 				next_batch,
 				full_state,
+				use_state_after,
 				filter.room.include_leave,
 				&filter,
 			)
@@ -389,6 +397,12 @@ async fn process_presence_updates(
 				.state_cache
 				.user_sees_user(syncing_user, user_id)
 		})
+		.filter(|(user_id, ..)| {
+			services
+				.users
+				.user_is_ignored(user_id, syncing_user)
+				.map(|ignored| !ignored)
+		})
 		.filter_map(|(user_id, _, presence_bytes)| {
 			services
 				.presence
@@ -418,6 +432,7 @@ async fn handle_left_room(
 	sender_user: &UserId,
 	next_batch: u64,
 	full_state: bool,
+	use_state_after: bool,
 	include_leave: bool,
 	filter: &FilterDefinition,
 ) -> Result<Option<LeftRoom>> {
@@ -468,9 +483,7 @@ async fn handle_left_room(
 				prev_batch: Some(next_batch.to_string()),
 				events: Vec::new(),
 			},
-			state: RoomState {
-				events: vec![event.into_sync_state_event()],
-			},
+			state: room_state(vec![event.into_sync_state_event()], use_state_after),
 		}));
 	}
 
@@ -566,7 +579,7 @@ async fn handle_left_room(
 			prev_batch: Some(next_batch.to_string()),
 			events: Vec::new(), // and so we dont need to set this to empty vec
 		},
-		state: RoomState { events: left_state_events },
+		state: room_state(left_state_events, use_state_after),
 	}))
 }
 
@@ -587,6 +600,7 @@ async fn load_joined_room(
 	since: u64,
 	next_batch: u64,
 	full_state: bool,
+	use_state_after: bool,
 	filter: &FilterDefinition,
 ) -> Result<(JoinedRoom, HashSet<OwnedUserId>, HashSet<OwnedUserId>)> {
 	let sincecount = PduCount::Normal(since);
@@ -728,12 +742,16 @@ async fn load_joined_room(
 				.is_some_and(is_equal_to!(sender_user.as_str()))
 	};
 
+	// With state_after the membership has to stay in the state as the timeline
+	// does not affect it.
 	let joined_sender_member: Option<_> = (joined_since_last_sync && timeline_pdus.is_empty())
 		.then(|| {
-			state_events
-				.iter()
-				.position(is_sender_membership)
-				.map(|pos| state_events.swap_remove(pos))
+			let pos = state_events.iter().position(is_sender_membership)?;
+			if use_state_after {
+				state_events.get(pos).cloned()
+			} else {
+				Some(state_events.swap_remove(pos))
+			}
 		})
 		.flatten();
 
@@ -871,12 +889,13 @@ async fn load_joined_room(
 			prev_batch: prev_batch.as_ref().map(ToString::to_string),
 			events: room_events,
 		},
-		state: RoomState {
-			events: state_events
+		state: room_state(
+			state_events
 				.into_iter()
 				.map(PduEvent::into_sync_state_event)
 				.collect(),
-		},
+			use_state_after,
+		),
 		ephemeral: Ephemeral { events: edus },
 		unread_thread_notifications: BTreeMap::new(),
 	};
@@ -884,6 +903,17 @@ async fn load_joined_room(
 	Ok((joined_room, device_list_updates, left_encrypted_users))
 }
 
+/// Returns the state of a room as `state_after` if the client asked for it
+/// (MSC4222), or as `state` otherwise.
+fn room_state(events: Vec<Raw<AnySyncStateEvent>>, use_state_after: bool) -> RoomState {
+	let events = StateEvents { events };
+	if use_state_after {
+		RoomState::After(events)
+	} else {
+		RoomState::Before(events)
+	}
+}
+
 #[tracing::instrument(
 	name = "state",
 	level = "trace",
@@ -1186,8 +1216,8 @@ async fn calculate_heroes(
 	services
 		.rooms
 		.timeline
-		.all_pdus(sender_user, room_id)
-		.ready_filter(|(_, pdu)| pdu.kind == RoomMember)
+		.pdus_filtered(Some(sender_user), room_id, None, |pdu| pdu.is_kind(&RoomMember))
+		.ignore_err()
 		.fold_default(|heroes: Vec<_>, (_, pdu)| {
 			fold_hero(heroes, services, room_id, sender_user, pdu)
 		})
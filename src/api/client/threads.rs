@@ -9,6 +9,8 @@
 use crate::Ruma;
 
 /// # `GET /_matrix/client/r0/rooms/{roomId}/threads`
+///
+/// Lists the threads of a room, most recently active first.
 pub(crate) async fn get_threads_route(
 	State(services): State<crate::State>,
 	ref body: Ruma<get_threads::v1::Request>,
@@ -99,6 +99,11 @@ pub(crate) async fn send_event_to_device_route(
 						.await;
 				},
 			}
+
+			services
+				.sending
+				.flush_appservices_for_user(target_user_id)
+				.await?;
 		}
 	}
 
@@ -20,6 +20,7 @@
 /// Note: Unstable features are used while developing new features. Clients
 /// should avoid using unstable features in their stable releases
 pub(crate) async fn get_supported_versions_route(
+	State(services): State<crate::State>,
 	_body: Ruma<get_supported_versions::Request>,
 ) -> Result<get_supported_versions::Response> {
 	let resp = get_supported_versions::Response {
@@ -54,6 +55,14 @@ pub(crate) async fn get_supported_versions_route(
 			("uk.tcpip.msc4133".to_owned(), true), /* Extending User Profile API with Key:Value Pairs (https://github.com/matrix-org/matrix-spec-proposals/pull/4133) */
 			("us.cloke.msc4175".to_owned(), true), /* Profile field for user time zone (https://github.com/matrix-org/matrix-spec-proposals/pull/4175) */
 			("org.matrix.simplified_msc3575".to_owned(), true), /* Simplified Sliding sync (https://github.com/matrix-org/matrix-spec-proposals/pull/4186) */
+			(
+				"org.matrix.msc3882".to_owned(),
+				services.server.config.login_via_existing_session && !services.oidc.enabled(),
+			), /* login via existing session (https://github.com/matrix-org/matrix-spec-proposals/pull/3882) */
+			("org.matrix.msc4108".to_owned(), services.server.config.rendezvous_enable), /* QR code login (https://github.com/matrix-org/matrix-spec-proposals/pull/4108) */
+			("org.matrix.msc4140".to_owned(), services.delayed_events.enabled()), /* delayed events (https://github.com/matrix-org/matrix-spec-proposals/pull/4140) */
+			("fi.mau.msc2815".to_owned(), services.rooms.unredacted.enabled()), /* moderator access to redacted content (https://github.com/matrix-org/matrix-spec-proposals/pull/2815) */
+			("org.matrix.msc4155".to_owned(), true), /* invite filtering (https://github.com/matrix-org/matrix-spec-proposals/pull/4155) */
 		]),
 	};
 
@@ -21,6 +21,7 @@
 ///
 /// - Hides any local users that aren't in any public rooms (i.e. those that
 ///   have the join rule set to public) and don't share a room with the sender
+/// - Hides accounts awaiting approval by an admin (MSC3866)
 pub(crate) async fn search_users_route(
 	State(services): State<crate::State>,
 	body: Ruma<search_users::v3::Request>,
@@ -56,6 +57,10 @@ pub(crate) async fn search_users_route(
 				return None;
 			}
 
+			if services.users.is_pending_approval(&user_id).await {
+				return None;
+			}
+
 			let user_in_public_room = services
 				.rooms
 				.state_cache
@@ -2,7 +2,9 @@
 use conduwuit::{Error, Result};
 use ruma::api::client::{
 	discovery::{
-		discover_homeserver::{self, HomeserverInfo, SlidingSyncProxyInfo},
+		discover_homeserver::{
+			self, AuthenticationServerInfo, HomeserverInfo, SlidingSyncProxyInfo,
+		},
 		discover_support::{self, Contact},
 	},
 	error::ErrorKind,
@@ -13,6 +15,9 @@
 /// # `GET /.well-known/matrix/client`
 ///
 /// Returns the .well-known URL if it is configured, otherwise returns 404.
+///
+/// - Advertises the OpenID Connect issuer if authentication is delegated
+///   (MSC2965)
 pub(crate) async fn well_known_client(
 	State(services): State<crate::State>,
 	_body: Ruma<discover_homeserver::Request>,
@@ -22,11 +27,22 @@ pub(crate) async fn well_known_client(
 		| None => return Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
 	};
 
+	let oidc = &services.server.config.oidc;
+	let authentication =
+		oidc.issuer
+			.as_ref()
+			.filter(|_| oidc.enable)
+			.map(|issuer| AuthenticationServerInfo {
+				issuer: issuer.to_string(),
+				account: oidc.account.as_ref().map(ToString::to_string),
+			});
+
 	Ok(discover_homeserver::Response {
 		homeserver: HomeserverInfo { base_url: client_url.clone() },
 		identity_server: None,
 		sliding_sync_proxy: Some(SlidingSyncProxyInfo { url: client_url }),
 		tile_server: None,
+		authentication,
 	})
 }
 
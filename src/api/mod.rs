@@ -6,9 +6,10 @@
 pub mod server;
 
 extern crate conduwuit_core as conduwuit;
+extern crate conduwuit_database as database;
 extern crate conduwuit_service as service;
 
-pub(crate) use self::router::{Ruma, RumaResponse, State};
+pub(crate) use self::router::{JsonStream, Ruma, RumaResponse, State};
 
 conduwuit::mod_ctor! {}
 conduwuit::mod_dtor! {}
@@ -1,9 +1,11 @@
 mod args;
 mod auth;
 mod handler;
+mod ratelimit;
 mod request;
 mod response;
 pub mod state;
+mod stream;
 
 use std::str::FromStr;
 
@@ -16,7 +18,9 @@
 use http::{Uri, uri};
 
 use self::handler::RouterExt;
-pub(super) use self::{args::Args as Ruma, response::RumaResponse, state::State};
+pub(super) use self::{
+	args::Args as Ruma, response::RumaResponse, state::State, stream::JsonStream,
+};
 use crate::{client, server};
 
 pub fn build(router: Router<State>, server: &Server) -> Router<State> {
@@ -35,6 +39,8 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
+		.ruma_route(&client::sso_login_route)
+		.ruma_route(&client::sso_login_with_provider_route)
 		.ruma_route(&client::whoami_route)
 		.ruma_route(&client::logout_route)
 		.ruma_route(&client::logout_all_route)
@@ -44,6 +50,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::request_3pid_management_token_via_email_route)
 		.ruma_route(&client::request_3pid_management_token_via_msisdn_route)
 		.ruma_route(&client::check_registration_token_validity)
+		.ruma_route(&client::request_password_change_token_via_email_route)
 		.ruma_route(&client::get_capabilities_route)
 		.ruma_route(&client::get_pushrules_all_route)
 		.ruma_route(&client::get_pushrules_global_route)
@@ -94,6 +101,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::redact_event_route)
 		.ruma_route(&client::report_event_route)
 		.ruma_route(&client::report_room_route)
+		.ruma_route(&client::report_user_route)
 		.ruma_route(&client::create_alias_route)
 		.ruma_route(&client::delete_alias_route)
 		.ruma_route(&client::get_alias_route)
@@ -113,13 +121,15 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_public_rooms_route)
 		.ruma_route(&client::get_public_rooms_filtered_route)
 		.ruma_route(&client::search_users_route)
-		.ruma_route(&client::get_member_events_route)
+		.ruma_stream_route(&client::get_member_events_route)
 		.ruma_route(&client::get_protocols_route)
 		.route("/_matrix/client/unstable/thirdparty/protocols",
 			get(client::get_protocols_route_unstable))
 		.ruma_route(&client::send_message_event_route)
 		.ruma_route(&client::send_state_event_for_key_route)
-		.ruma_route(&client::get_state_events_route)
+		.ruma_route(&client::update_delayed_event_route)
+		.ruma_route(&client::get_delayed_events_route)
+		.ruma_stream_route(&client::get_state_events_route)
 		.ruma_route(&client::get_state_events_for_key_route)
 		// Ruma doesn't have support for multiple paths for a single endpoint yet, and these routes
 		// share one Ruma request / response type pair with {get,send}_state_event_for_key_route
@@ -154,8 +164,16 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::send_event_to_device_route)
 		.ruma_route(&client::create_content_route)
 		.ruma_route(&client::get_content_thumbnail_route)
-		.ruma_route(&client::get_content_route)
-		.ruma_route(&client::get_content_as_filename_route)
+		// These can respond with a redirect to object storage, which the Ruma response
+		// types can't express
+		.route(
+			"/_matrix/client/v1/media/download/:server_name/:media_id",
+			get(client::get_content_route),
+		)
+		.route(
+			"/_matrix/client/v1/media/download/:server_name/:media_id/:filename",
+			get(client::get_content_as_filename_route),
+		)
 		.ruma_route(&client::get_media_preview_route)
 		.ruma_route(&client::get_media_config_route)
 		.ruma_route(&client::get_devices_route)
@@ -185,7 +203,25 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		)
 		.ruma_route(&client::well_known_support)
 		.ruma_route(&client::well_known_client)
+		.route("/_matrix/client/unstable/org.matrix.msc2965/auth_issuer",
+			get(client::auth_issuer_route))
+		.route("/_matrix/client/unstable/org.matrix.msc2965/auth_metadata",
+			get(client::auth_metadata_route))
+		.route("/_matrix/client/v1/auth_metadata", get(client::auth_metadata_route))
+		.route("/_matrix/client/unstable/org.matrix.msc4108/rendezvous",
+			post(client::create_rendezvous_route))
+		.route("/_matrix/client/unstable/org.matrix.msc4108/rendezvous/:id",
+			get(client::get_rendezvous_route)
+				.put(client::update_rendezvous_route)
+				.delete(client::delete_rendezvous_route))
 		.route("/_conduwuit/server_version", get(client::conduwuit_server_version))
+		.route("/_conduwuit/health/live", get(client::health_live_route))
+		.route("/_conduwuit/health/ready", get(client::health_ready_route))
+		.route("/_conduwuit/email/validate", get(client::validate_email_route))
+		.route("/_conduwuit/account/renew", get(client::renew_account_route))
+		.ruma_route(&client::send_renewal_email_route)
+		.route("/_conduwuit/sso/callback", get(client::sso_callback_route))
+		.ruma_route(&client::send_server_notice_route)
 		.ruma_route(&client::room_initial_sync_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
 
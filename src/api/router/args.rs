@@ -2,6 +2,7 @@
 
 use async_trait::async_trait;
 use axum::{body::Body, extract::FromRequest};
+use axum_client_ip::InsecureClientIp;
 use bytes::{BufMut, Bytes, BytesMut};
 use conduwuit::{Error, Result, debug, debug_warn, err, trace, utils::string::EMPTY};
 use ruma::{
@@ -9,8 +10,9 @@
 	OwnedUserId, ServerName, UserId, api::IncomingRequest,
 };
 use service::Services;
+use tracing::Span;
 
-use super::{auth, auth::Auth, request, request::Request};
+use super::{auth, auth::Auth, ratelimit, request, request::Request};
 use crate::{State, service::appservice::RegistrationInfo};
 
 /// Extractor for Ruma request structs
@@ -109,6 +111,24 @@ async fn from_request(
 			json_body = Some(CanonicalJsonValue::Object(CanonicalJsonObject::new()));
 		}
 		let auth = auth::auth(services, &mut request, json_body.as_ref(), &T::METADATA).await?;
+		if let (Some(span), Some(user)) =
+			(request.parts.extensions.get::<Span>(), auth.sender_user.as_deref())
+		{
+			span.record("user", user.as_str());
+		}
+
+		ratelimit::check(services, &request, json_body.as_ref(), &T::METADATA, &auth)?;
+		if let (Some(user_id), Some(device_id)) = (&auth.sender_user, &auth.sender_device) {
+			let ip = InsecureClientIp::from(&request.parts.headers, &request.parts.extensions)
+				.ok()
+				.map(|InsecureClientIp(ip)| ip);
+
+			services
+				.users
+				.update_device_last_seen(user_id, device_id, ip)
+				.await;
+		}
+
 		Ok(Self {
 			body: make_body::<T>(services, &mut request, json_body.as_mut(), &auth)?,
 			origin: auth.origin,
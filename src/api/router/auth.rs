@@ -5,16 +5,39 @@
 	typed_header::TypedHeaderRejectionReason,
 };
 use conduwuit::{Err, Error, Result, debug_error, err, warn};
+use http::StatusCode;
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 	api::{
 		AuthScheme, IncomingRequest, Metadata,
 		client::{
-			directory::get_public_rooms,
+			account::whoami,
+			alias::get_alias,
+			context::get_context,
+			directory::{get_public_rooms, get_public_rooms_filtered},
+			discovery::get_capabilities,
 			error::ErrorKind,
+			filter::{create_filter, get_filter},
+			keys::{claim_keys, get_key_changes, get_keys, upload_keys},
+			knock::knock_room,
+			membership::{
+				ban_user, get_member_events, invite_user, join_room_by_id,
+				join_room_by_id_or_alias, joined_members, kick_user, leave_room, unban_user,
+			},
+			message::{get_message_events, send_message_event},
 			profile::{
 				get_avatar_url, get_display_name, get_profile, get_profile_key, get_timezone_key,
+				set_avatar_url, set_display_name, set_profile_key,
 			},
+			read_marker::set_read_marker,
+			receipt::create_receipt,
+			redact::redact_event,
+			room::{create_room, get_room_event, initial_sync, upgrade_room},
+			session::{logout, logout_all},
+			state::{get_state_events, get_state_events_for_key, send_state_event},
+			sync::sync_events,
+			to_device::send_event_to_device,
+			typing::create_typing_event,
 			voip::get_turn_server_info,
 		},
 		federation::{authentication::XMatrix, openid::get_openid_userinfo},
@@ -26,7 +49,7 @@
 };
 
 use super::request::Request;
-use crate::service::appservice::RegistrationInfo;
+use crate::{client::send_renewal_email, service::appservice::RegistrationInfo};
 
 enum Token {
 	Appservice(Box<RegistrationInfo>),
@@ -59,6 +82,16 @@ pub(super) async fn auth(
 			| Some(reg_info) => Token::Appservice(Box::new(reg_info)),
 			| _ => match services.users.find_from_token(token).await {
 				| Ok((user_id, device_id)) => Token::User((user_id, device_id)),
+				// tokens issued by this server, e.g. to appservice logins and devices,
+				// stay valid when the OpenID Connect provider issues the others
+				| _ if services.oidc.enabled() =>
+					match services.oidc.find_from_token(token).await {
+						| Ok((user_id, device_id)) => Token::User((user_id, device_id)),
+						// when the provider can't be asked, the token is not known to be
+						// invalid; clients must not log out over it
+						| Err(e) if e.status_code().is_server_error() => return Err(e),
+						| Err(_) => Token::Invalid,
+					},
 				| _ => Token::Invalid,
 			},
 		}
@@ -112,6 +145,68 @@ pub(super) async fn auth(
 		}
 	}
 
+	// Locked users keep their sessions but may only log out (MSC3939). Expired
+	// accounts are soft-locked the same way until renewed, but may still ask
+	// for a new renewal link.
+	if let Token::User((user_id, _)) = &token {
+		if matches!(
+			metadata.authentication,
+			AuthScheme::AccessToken | AuthScheme::AccessTokenOptional
+		) && !matches!(
+			metadata,
+			&logout::v3::Request::METADATA | &logout_all::v3::Request::METADATA
+		) {
+			let locked = if services.users.is_locked(user_id).await {
+				Some("This account has been locked.")
+			} else if metadata != &send_renewal_email::Request::METADATA
+				&& services.users.is_expired(user_id).await
+			{
+				Some("This account has expired and needs to be renewed.")
+			} else {
+				None
+			};
+
+			if let Some(message) = locked {
+				return Err(Error::BadRequest(ErrorKind::UserLocked, message));
+			}
+		}
+	}
+
+	// Guests may only use the subset of the client API permitted to them, and
+	// only while guest access is enabled
+	if let Token::User((user_id, _)) = &token {
+		if matches!(
+			metadata.authentication,
+			AuthScheme::AccessToken | AuthScheme::AccessTokenOptional
+		) && services.users.is_guest(user_id).await
+			&& (!services.server.config.allow_guest_registration || !guest_can_access(metadata))
+		{
+			return Err(Error::BadRequest(
+				ErrorKind::GuestAccessForbidden,
+				"Guest access is not allowed for this endpoint.",
+			));
+		}
+	}
+
+	// In maintenance mode, only admins may send events to rooms. Federation,
+	// appservices and everything else, such as keys and to-device messages,
+	// keep working so clients are not broken.
+	if let Token::User((user_id, _)) = &token {
+		if creates_events(metadata) {
+			if let Some(message) = services.globals.maintenance() {
+				if !services.users.is_admin(user_id).await {
+					return Err(Error::Request(
+						ErrorKind::ResourceLimitExceeded {
+							admin_contact: admin_contact(services),
+						},
+						message.into(),
+						StatusCode::FORBIDDEN,
+					));
+				}
+			}
+		}
+	}
+
 	match (metadata.authentication, token) {
 		| (AuthScheme::AccessToken, Token::Appservice(info)) =>
 			Ok(auth_appservice(services, request, info).await?),
@@ -195,6 +290,67 @@ pub(super) async fn auth(
 	}
 }
 
+fn guest_can_access(metadata: &Metadata) -> bool {
+	matches!(
+		metadata,
+		&sync_events::v3::Request::METADATA
+			| &get_state_events::v3::Request::METADATA
+			| &get_state_events_for_key::v3::Request::METADATA
+			| &get_context::v3::Request::METADATA
+			| &get_room_event::v3::Request::METADATA
+			| &get_message_events::v3::Request::METADATA
+			| &get_member_events::v3::Request::METADATA
+			| &joined_members::v3::Request::METADATA
+			| &initial_sync::v3::Request::METADATA
+			| &set_display_name::v3::Request::METADATA
+			| &get_turn_server_info::v3::Request::METADATA
+			| &join_room_by_id::v3::Request::METADATA
+			| &join_room_by_id_or_alias::v3::Request::METADATA
+			| &leave_room::v3::Request::METADATA
+			| &send_message_event::v3::Request::METADATA
+			| &send_event_to_device::v3::Request::METADATA
+			| &get_public_rooms_filtered::v3::Request::METADATA
+			| &get_alias::v3::Request::METADATA
+			| &upload_keys::v3::Request::METADATA
+			| &get_keys::v3::Request::METADATA
+			| &claim_keys::v3::Request::METADATA
+			| &get_key_changes::v3::Request::METADATA
+			| &whoami::v3::Request::METADATA
+			| &logout::v3::Request::METADATA
+			| &logout_all::v3::Request::METADATA
+			| &create_filter::v3::Request::METADATA
+			| &get_filter::v3::Request::METADATA
+			| &create_receipt::v3::Request::METADATA
+			| &set_read_marker::v3::Request::METADATA
+			| &create_typing_event::v3::Request::METADATA
+			| &get_capabilities::v3::Request::METADATA
+	)
+}
+
+/// Client endpoints creating events in rooms, which are refused in
+/// maintenance mode.
+fn creates_events(metadata: &Metadata) -> bool {
+	matches!(
+		metadata,
+		&send_message_event::v3::Request::METADATA
+			| &send_state_event::v3::Request::METADATA
+			| &redact_event::v3::Request::METADATA
+			| &create_room::v3::Request::METADATA
+			| &upgrade_room::v3::Request::METADATA
+			| &join_room_by_id::v3::Request::METADATA
+			| &join_room_by_id_or_alias::v3::Request::METADATA
+			| &knock_room::v3::Request::METADATA
+			| &leave_room::v3::Request::METADATA
+			| &invite_user::v3::Request::METADATA
+			| &kick_user::v3::Request::METADATA
+			| &ban_user::v3::Request::METADATA
+			| &unban_user::v3::Request::METADATA
+			| &set_display_name::v3::Request::METADATA
+			| &set_avatar_url::v3::Request::METADATA
+			| &set_profile_key::unstable::Request::METADATA
+	)
+}
+
 async fn auth_appservice(
 	services: &Services,
 	request: &Request,
@@ -220,10 +376,32 @@ async fn auth_appservice(
 		return Err!(Request(Exclusive("User is not in namespace.")));
 	}
 
+	// Device masquerading (MSC3202)
+	let sender_device = match request.query.device_id.as_deref() {
+		| None => None,
+		| Some(_) if !info.extensions.msc3202 =>
+			return Err!(Request(Forbidden(
+				"Appservice is not allowed to masquerade as a device."
+			))),
+		| Some(device_id) => {
+			let device_id: OwnedDeviceId = device_id.into();
+			if services
+				.users
+				.get_device_metadata(&user_id, &device_id)
+				.await
+				.is_err()
+			{
+				return Err!(Request(Forbidden("Device does not exist for the user.")));
+			}
+
+			Some(device_id)
+		},
+	};
+
 	Ok(Auth {
 		origin: None,
 		sender_user: Some(user_id),
-		sender_device: None,
+		sender_device,
 		appservice_info: Some(*info),
 	})
 }
@@ -346,3 +524,18 @@ async fn parse_x_matrix(request: &mut Request) -> Result<XMatrix> {
 
 	Ok(x_matrix)
 }
+
+/// Where users can reach the admins: the support page or email advertised by
+/// the well-known support file, or the admin room.
+fn admin_contact(services: &Services) -> String {
+	let well_known = &services.server.config.well_known;
+	if let Some(page) = &well_known.support_page {
+		return page.to_string();
+	}
+
+	if let Some(email) = &well_known.support_email {
+		return format!("mailto:{email}");
+	}
+
+	format!("https://matrix.to/#/{}", services.globals.admin_alias)
+}
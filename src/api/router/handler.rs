@@ -16,10 +16,21 @@ pub(in super::super) trait RumaHandler<T> {
 	fn add_routes(&'static self, router: Router<State>) -> Router<State>;
 }
 
+/// Handler of a Ruma request responding with something other than the Ruma
+/// response, e.g. a [`JsonStream`](super::JsonStream) of the same body.
+pub(in super::super) trait RumaStreamHandler<T> {
+	fn add_route(&'static self, router: Router<State>, path: &str) -> Router<State>;
+	fn add_routes(&'static self, router: Router<State>) -> Router<State>;
+}
+
 pub(in super::super) trait RouterExt {
 	fn ruma_route<H, T>(self, handler: &'static H) -> Self
 	where
 		H: RumaHandler<T>;
+
+	fn ruma_stream_route<H, T>(self, handler: &'static H) -> Self
+	where
+		H: RumaStreamHandler<T>;
 }
 
 impl RouterExt for Router<State> {
@@ -29,6 +40,13 @@ fn ruma_route<H, T>(self, handler: &'static H) -> Self
 	{
 		handler.add_routes(self)
 	}
+
+	fn ruma_stream_route<H, T>(self, handler: &'static H) -> Self
+	where
+		H: RumaStreamHandler<T>,
+	{
+		handler.add_routes(self)
+	}
 }
 
 macro_rules! ruma_handler {
@@ -64,6 +82,35 @@ fn add_route(&'static self, router: Router<State>, path: &str) -> Router<State>
 ruma_handler!(T1, T2, T3);
 ruma_handler!(T1, T2, T3, T4);
 
+macro_rules! ruma_stream_handler {
+	( $($tx:ident),* $(,)? ) => {
+		#[allow(non_snake_case)]
+		impl<Err, Req, Res, Fut, Fun, $($tx,)*> RumaStreamHandler<($($tx,)* Ruma<Req>,)> for Fun
+		where
+			Fun: Fn($($tx,)* Ruma<Req>,) -> Fut + Send + Sync + 'static,
+			Fut: Future<Output = Result<Res, Err>> + Send,
+			Req: IncomingRequest + Send + Sync + 'static,
+			Res: IntoResponse + Send,
+			Err: IntoResponse + Send,
+			$( $tx: FromRequestParts<State> + Send + Sync + 'static, )*
+		{
+			fn add_routes(&'static self, router: Router<State>) -> Router<State> {
+				Req::METADATA
+					.history
+					.all_paths()
+					.fold(router, |router, path| self.add_route(router, path))
+			}
+
+			fn add_route(&'static self, router: Router<State>, path: &str) -> Router<State> {
+				let action = |$($tx,)* req| self($($tx,)* req);
+				let method = method_to_filter(&Req::METADATA.method);
+				router.route(path, on(method, action))
+			}
+		}
+	}
+}
+ruma_stream_handler!(T1);
+
 const fn method_to_filter(method: &Method) -> MethodFilter {
 	match *method {
 		| Method::DELETE => MethodFilter::DELETE,
@@ -0,0 +1,115 @@
+use axum_client_ip::{SecureClientIp, SecureClientIpSource};
+use conduwuit::Result;
+use ruma::{
+	CanonicalJsonValue, OwnedUserId, ServerName, UserId,
+	api::{
+		AuthScheme, IncomingRequest, Metadata,
+		client::{
+			account::register,
+			authenticated_media,
+			knock::knock_room,
+			media,
+			membership::{invite_user, join_room_by_id, join_room_by_id_or_alias},
+			message::send_message_event,
+			redact::redact_event,
+			session::login,
+			state::send_state_event,
+		},
+	},
+};
+use service::{Services, ratelimit::Class};
+
+use super::{auth::Auth, request::Request};
+
+/// Count the request against the rate limits of its endpoint class.
+pub(super) fn check(
+	services: &Services,
+	request: &Request,
+	json_body: Option<&CanonicalJsonValue>,
+	metadata: &Metadata,
+	auth: &Auth,
+) -> Result {
+	let Some(class) = class(metadata) else {
+		return Ok(());
+	};
+
+	if auth
+		.appservice_info
+		.as_ref()
+		.is_some_and(|info| !info.is_rate_limited())
+	{
+		return Ok(());
+	}
+
+	let login_user = (class == Class::Login)
+		.then(|| login_user(json_body, services.globals.server_name()))
+		.flatten();
+
+	let ip = request
+		.parts
+		.extensions
+		.get::<SecureClientIpSource>()
+		.and_then(|source| {
+			SecureClientIp::from(source, &request.parts.headers, &request.parts.extensions).ok()
+		})
+		.map(|SecureClientIp(ip)| ip);
+
+	// login attempts for a user are counted per address, so guessing the
+	// password from elsewhere doesn't lock the user out
+	let login_subject = login_user
+		.as_deref()
+		.zip(ip)
+		.map(|(user_id, ip)| format!("{user_id} {ip}"));
+
+	let user = match (&auth.sender_user, &auth.origin) {
+		| (Some(user_id), _) => Some(user_id.as_str()),
+		| (None, Some(origin)) => Some(origin.as_str()),
+		| (None, None) => login_subject.as_deref(),
+	};
+
+	services.ratelimit.check(class, user, ip)
+}
+
+/// The user a login attempt is made for, as given by the client.
+fn login_user(
+	json_body: Option<&CanonicalJsonValue>,
+	server_name: &ServerName,
+) -> Option<OwnedUserId> {
+	let body = json_body?.as_object()?;
+	let user = body
+		.get("identifier")
+		.and_then(CanonicalJsonValue::as_object)
+		.and_then(|identifier| identifier.get("user"))
+		.or_else(|| body.get("user"))
+		.and_then(CanonicalJsonValue::as_str)?;
+
+	UserId::parse_with_server_name(user.to_lowercase(), server_name).ok()
+}
+
+fn class(metadata: &Metadata) -> Option<Class> {
+	if metadata.authentication == AuthScheme::ServerSignatures {
+		return Some(Class::Federation);
+	}
+
+	let class = match metadata {
+		| &login::v3::Request::METADATA => Class::Login,
+		| &register::v3::Request::METADATA => Class::Registration,
+		| &send_message_event::v3::Request::METADATA
+		| &send_state_event::v3::Request::METADATA
+		| &redact_event::v3::Request::METADATA => Class::Message,
+		| &join_room_by_id::v3::Request::METADATA
+		| &join_room_by_id_or_alias::v3::Request::METADATA
+		| &knock_room::v3::Request::METADATA => Class::Join,
+		| &invite_user::v3::Request::METADATA => Class::Invite,
+		| &media::create_content::v3::Request::METADATA
+		| &media::get_content::v3::Request::METADATA
+		| &media::get_content_as_filename::v3::Request::METADATA
+		| &media::get_content_thumbnail::v3::Request::METADATA
+		| &authenticated_media::get_content::v1::Request::METADATA
+		| &authenticated_media::get_content_as_filename::v1::Request::METADATA
+		| &authenticated_media::get_content_thumbnail::v1::Request::METADATA => Class::Media,
+		| _ => return None,
+	};
+
+	Some(class)
+}
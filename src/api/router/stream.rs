@@ -0,0 +1,94 @@
+use std::mem;
+
+use axum::{
+	body::Body,
+	response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use conduwuit::debug_warn;
+use conduwuit_service::Services;
+use futures::{SinkExt, StreamExt, channel::mpsc, stream::BoxStream};
+use http::header::{CONTENT_TYPE, HeaderValue};
+use serde::Serialize;
+
+use super::State;
+
+/// JSON response serialized item by item while it is sent, so the memory used
+/// by a request does not grow with the size of the collection it returns.
+pub(crate) struct JsonStream(Body);
+
+/// Bytes serialized before they are handed to the connection
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Chunks buffered ahead of a slow client
+const CHUNKS_BUFFERED: usize = 4;
+
+impl JsonStream {
+	/// A JSON array of the items.
+	pub(crate) fn array<F, T>(services: State, items: F) -> Self
+	where
+		F: for<'a> FnOnce(&'a Services) -> BoxStream<'a, T> + Send + 'static,
+		T: Serialize + Send + 'static,
+	{
+		Self::spawn(services, "[", "]", items)
+	}
+
+	/// A JSON object holding the items as an array in its only field.
+	pub(crate) fn object<F, T>(services: State, field: &'static str, items: F) -> Self
+	where
+		F: for<'a> FnOnce(&'a Services) -> BoxStream<'a, T> + Send + 'static,
+		T: Serialize + Send + 'static,
+	{
+		let prefix = format!("{{\"{field}\":[");
+		Self::spawn(services, prefix, "]}", items)
+	}
+
+	fn spawn<F, T, P>(services: State, prefix: P, suffix: &'static str, items: F) -> Self
+	where
+		F: for<'a> FnOnce(&'a Services) -> BoxStream<'a, T> + Send + 'static,
+		T: Serialize + Send + 'static,
+		P: Into<Vec<u8>>,
+	{
+		let (mut sender, receiver) = mpsc::channel::<serde_json::Result<Bytes>>(CHUNKS_BUFFERED);
+		let mut buf: Vec<u8> = prefix.into();
+		services.server.spawn("api:stream", async move {
+			let mut items = items(&*services);
+			let mut first = true;
+			while let Some(item) = items.next().await {
+				if !first {
+					buf.push(b',');
+				}
+
+				first = false;
+				if let Err(e) = serde_json::to_writer(&mut buf, &item) {
+					debug_warn!("Failed to serialize streamed item: {e}");
+					sender.send(Err(e)).await.ok();
+					return;
+				}
+
+				if buf.len() >= CHUNK_SIZE
+					&& sender.send(Ok(mem::take(&mut buf).into())).await.is_err()
+				{
+					// The client went away
+					return;
+				}
+			}
+
+			buf.extend_from_slice(suffix.as_bytes());
+			sender.send(Ok(buf.into())).await.ok();
+		});
+
+		Self(Body::from_stream(receiver))
+	}
+}
+
+impl IntoResponse for JsonStream {
+	fn into_response(self) -> Response {
+		let mut response = self.0.into_response();
+		response
+			.headers_mut()
+			.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+		response
+	}
+}
@@ -1,15 +1,16 @@
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
-use base64::{Engine as _, engine::general_purpose};
 use conduwuit::{
-	Err, Error, PduEvent, Result, err, pdu::gen_event_id, utils, utils::hash::sha256, warn,
+	Err, Error, PduEvent, Result, config::StrangerInvitePolicy, err, pdu::gen_event_id, utils,
+	warn,
 };
 use ruma::{
-	CanonicalJsonValue, OwnedUserId, UserId,
+	CanonicalJsonValue, MilliSecondsSinceUnixEpoch, OwnedUserId, UserId,
 	api::{client::error::ErrorKind, federation::membership::create_invite},
 	events::room::member::{MembershipState, RoomMemberEventContent},
 	serde::JsonObject,
 };
+use service::{spam_checker::Check, strangers::HeldInvite, users::InvitePermission};
 
 use crate::Ruma;
 
@@ -106,6 +107,40 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(Forbidden("This server does not allow room invites.")));
 	}
 
+	// ignored invites are accepted but never reach the user
+	if services
+		.users
+		.invite_permission(sender, &invited_user)
+		.await == InvitePermission::Block
+	{
+		return Err!(Request(Forbidden("{invited_user} does not accept invites from {sender}.")));
+	}
+
+	services
+		.spam_checker
+		.check_allowed(&Check::Invite {
+			room_id: &body.room_id,
+			inviter: sender,
+			invitee: &invited_user,
+		})
+		.await?;
+
+	let server_in_room = services
+		.rooms
+		.state_cache
+		.server_in_room(services.globals.server_name(), &body.room_id)
+		.await;
+
+	let stranger_policy = services.config.stranger_invite_policy;
+	let from_stranger = stranger_policy != StrangerInvitePolicy::Allow
+		&& !services.users.is_admin(&invited_user).await
+		&& services.strangers.is_stranger(body.origin(), sender).await;
+
+	// invites into rooms we are in reach the user through /send and can't be held
+	if from_stranger && (stranger_policy == StrangerInvitePolicy::Reject || server_in_room) {
+		return Err!(Request(Forbidden("This server does not accept invites from strangers.")));
+	}
+
 	let mut invite_state = body.invite_room_state.clone();
 
 	let mut event: JsonObject = serde_json::from_str(body.event.get())
@@ -121,13 +156,23 @@ pub(crate) async fn create_invite_route(
 	// If we are active in the room, the remote server will notify us about the
 	// join/invite through /send. If we are not in the room, we need to manually
 	// record the invited state for client /sync through update_membership(), and
-	// send the invite PDU to the relevant appservices.
-	if !services
-		.rooms
-		.state_cache
-		.server_in_room(services.globals.server_name(), &body.room_id)
-		.await
-	{
+	// send the invite PDU to the relevant appservices. Held invites from strangers
+	// are recorded once an admin releases them.
+	if from_stranger {
+		services
+			.strangers
+			.hold(&HeldInvite {
+				room_id: body.room_id.clone(),
+				sender: sender.to_owned(),
+				recipient: invited_user.clone(),
+				invite_state,
+				event_id: pdu.event_id.clone(),
+				event: pdu.to_room_event(),
+				via: body.via.clone(),
+				received_ts: MilliSecondsSinceUnixEpoch::now(),
+			})
+			.await?;
+	} else if !server_in_room {
 		services
 			.rooms
 			.state_cache
@@ -142,24 +187,10 @@ pub(crate) async fn create_invite_route(
 			)
 			.await?;
 
-		for appservice in services.appservice.read().await.values() {
-			if appservice.is_user_match(&invited_user) {
-				services
-					.sending
-					.send_appservice_request(
-						appservice.registration.clone(),
-						ruma::api::appservice::event::push_events::v1::Request {
-							events: vec![pdu.to_room_event()],
-							txn_id: general_purpose::URL_SAFE_NO_PAD
-								.encode(sha256::hash(pdu.event_id.as_bytes()))
-								.into(),
-							ephemeral: Vec::new(),
-							to_device: Vec::new(),
-						},
-					)
-					.await?;
-			}
-		}
+		services
+			.sending
+			.send_invite_appservices(&invited_user, &pdu.event_id, &pdu.to_room_event())
+			.await?;
 	}
 
 	Ok(create_invite::v2::Response {
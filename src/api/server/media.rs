@@ -14,6 +14,9 @@
 /// # `GET /_matrix/federation/v1/media/download/{mediaId}`
 ///
 /// Load media from our server.
+///
+/// - Responds with the location in object storage if `media_redirect` is
+///   enabled for our media
 #[tracing::instrument(
 	name = "media_get",
 	level = "debug",
@@ -30,6 +33,13 @@ pub(crate) async fn get_content_route(
 		media_id: &body.media_id,
 	};
 
+	if let Some(location) = services.media.get_redirect(&mxc, None).await? {
+		return Ok(get_content::v1::Response {
+			content: FileOrLocation::Location(location),
+			metadata: ContentMetadata::new(),
+		});
+	}
+
 	let Some(FileMeta {
 		content,
 		content_type,
@@ -67,7 +77,7 @@ pub(crate) async fn get_content_thumbnail_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<get_content_thumbnail::v1::Request>,
 ) -> Result<get_content_thumbnail::v1::Response> {
-	let dim = Dim::from_ruma(body.width, body.height, body.method.clone())?;
+	let dim = Dim::from_ruma(body.width, body.height, body.method.clone(), body.animated)?;
 	let mxc = Mxc {
 		server_name: services.globals.server_name(),
 		media_id: &body.media_id,
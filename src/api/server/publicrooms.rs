@@ -35,6 +35,7 @@ pub(crate) async fn get_public_rooms_filtered_route(
 		body.since.as_deref(),
 		&body.filter,
 		&body.room_network,
+		false,
 	)
 	.await
 	.map_err(|_| {
@@ -72,6 +73,7 @@ pub(crate) async fn get_public_rooms_route(
 		body.since.as_deref(),
 		&Filter::default(),
 		&body.room_network,
+		false,
 	)
 	.await
 	.map_err(|_| {
@@ -6,6 +6,7 @@
 	Err, Error, Result, debug,
 	debug::INFO_SPAN_LEVEL,
 	debug_warn, err, error,
+	http::StatusCode,
 	result::LogErr,
 	trace,
 	utils::{
@@ -16,6 +17,7 @@
 };
 use conduwuit_service::{
 	Services,
+	rooms::event_handler::RoomQueueGuard,
 	sending::{EDU_LIMIT, PDU_LIMIT},
 };
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
@@ -78,6 +80,20 @@ pub(crate) async fn send_transaction_message_route(
 		)));
 	}
 
+	if let Ok(pdus) = services
+		.transaction_ids
+		.existing_server_txnid(body.origin(), &body.transaction_id)
+		.await
+	{
+		debug!(
+			id = ?body.transaction_id,
+			origin =?body.origin(),
+			"Responding to retried txn with cached results",
+		);
+
+		return Ok(send_transaction_message::v1::Response { pdus });
+	}
+
 	let txn_start_time = Instant::now();
 	trace!(
 		pdus = body.pdus.len(),
@@ -122,12 +138,37 @@ pub(crate) async fn send_transaction_message_route(
 		}
 	}
 
-	Ok(send_transaction_message::v1::Response {
-		pdus: results
-			.into_iter()
-			.map(|(e, r)| (e, r.map_err(error::sanitized_message)))
-			.collect(),
-	})
+	// a retry must be processed again when a PDU only failed for now, e.g.
+	// because its prev events could not be fetched
+	let resolved = results.values().all(is_resolved);
+	let pdus = results
+		.into_iter()
+		.map(|(e, r)| (e, r.map_err(error::sanitized_message)))
+		.collect();
+
+	if resolved {
+		services
+			.transaction_ids
+			.add_server_txnid(body.origin(), &body.transaction_id, &pdus)
+			.log_err()
+			.ok();
+	}
+
+	Ok(send_transaction_message::v1::Response { pdus })
+}
+
+/// Whether the PDU was handled or rejected for good, rather than failing for
+/// reasons that might not hold when the transaction is retried.
+fn is_resolved(result: &Result) -> bool {
+	let Err(e) = result else {
+		return true;
+	};
+
+	let status = e.status_code();
+	!matches!(e, Error::Federation(..))
+		&& status.is_client_error()
+		&& status != StatusCode::NOT_FOUND
+		&& status != StatusCode::TOO_MANY_REQUESTS
 }
 
 async fn handle(
@@ -149,12 +190,30 @@ async fn handle(
 		})
 		.await;
 
+	// a saturated room rejects the whole transaction with M_LIMIT_EXCEEDED
+	// before anything is handled, so the origin retries it later rather than
+	// dropping the PDUs of that room
+	let pdus = pdus
+		.into_iter()
+		.map(|(room_id, pdus): (OwnedRoomId, Vec<_>)| {
+			let queued = services
+				.rooms
+				.event_handler
+				.room_queue_reserve(&room_id, pdus.len())?;
+
+			Ok((room_id, pdus, queued))
+		})
+		.collect::<Result<Vec<_>>>()
+		.inspect_err(
+			|e| debug_warn!(%origin, "Rejecting txn with PDUs of saturated room: {e}"),
+		)?;
+
 	// we can evaluate rooms concurrently
 	let results: ResolvedMap = pdus
 		.into_iter()
 		.try_stream()
-		.broad_and_then(|(room_id, pdus): (_, Vec<_>)| {
-			handle_room(services, client, origin, started, room_id, pdus.into_iter())
+		.broad_and_then(|(room_id, pdus, queued)| {
+			handle_room(services, client, origin, started, room_id, pdus, queued)
 				.map_ok(Vec::into_iter)
 				.map_ok(IterStream::try_stream)
 		})
@@ -177,7 +236,8 @@ async fn handle_room(
 	origin: &ServerName,
 	txn_start_time: Instant,
 	room_id: OwnedRoomId,
-	pdus: impl Iterator<Item = Pdu> + Send,
+	pdus: Vec<Pdu>,
+	_queued: RoomQueueGuard,
 ) -> Result<Vec<(OwnedEventId, Result)>> {
 	let _room_lock = services
 		.rooms
@@ -563,6 +623,13 @@ async fn handle_edu_direct_to_device_event(
 				.await;
 		},
 	}
+
+	services
+		.sending
+		.flush_appservices_for_user(target_user_id)
+		.await
+		.log_err()
+		.ok();
 }
 
 async fn handle_edu_signing_key_update(
@@ -10,3 +10,11 @@ pub fn memory_stats(_opts: &str) -> Option<String> { None }
 /// Always returns None
 #[must_use]
 pub fn memory_usage() -> Option<String> { None }
+
+/// Always returns None
+#[must_use]
+pub fn resident() -> Option<usize> { None }
+
+/// Always returns None
+#[must_use]
+pub fn arena_stats() -> Option<String> { None }
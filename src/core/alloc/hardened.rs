@@ -9,6 +9,12 @@ pub fn trim<I: Into<Option<usize>>>(_: I) -> crate::Result { Ok(()) }
 //TODO: get usage
 pub fn memory_usage() -> Option<String> { None }
 
+#[must_use]
+pub fn resident() -> Option<usize> { None }
+
+#[must_use]
+pub fn arena_stats() -> Option<String> { None }
+
 #[must_use]
 pub fn memory_stats(_opts: &str) -> Option<String> {
 	Some("Extended statistics are not available from hardened_malloc.".to_owned())
@@ -87,6 +87,93 @@ pub fn memory_usage() -> Option<String> {
 #[cfg(not(feature = "jemalloc_stats"))]
 pub fn memory_usage() -> Option<String> { None }
 
+/// Bytes in physically resident pages mapped by the allocator.
+#[must_use]
+#[cfg(feature = "jemalloc_stats")]
+pub fn resident() -> Option<usize> {
+	// Acquire the epoch; ensure latest stats are pulled in
+	acq_epoch().ok()?;
+
+	mallctl::stats::resident::read().ok()
+}
+
+#[must_use]
+#[cfg(not(feature = "jemalloc_stats"))]
+pub fn resident() -> Option<usize> { None }
+
+/// Pages held by each arena, then the use of the slabs of each small size
+/// class merged across arenas. Regions of a slab left unused are
+/// fragmentation which cannot be returned to the system until the slab is
+/// empty.
+#[must_use]
+#[cfg(feature = "jemalloc_stats")]
+pub fn arena_stats() -> Option<String> {
+	use std::fmt::Write;
+
+	use crate::utils::bytes::pretty;
+
+	const ALL: usize = 4096;
+
+	let page: usize = get(&mallctl!("arenas.page")).ok()?;
+	let pages = |arena: usize, key: Key| {
+		get_indexed::<usize>(key, &[(2, arena)])
+			.map(|pages| pretty(pages.saturating_mul(page)))
+			.unwrap_or_default()
+	};
+
+	let mut out = String::new();
+	writeln!(out, "| arena | active | dirty | muzzy |").ok()?;
+	writeln!(out, "| ----- | ------ | ----- | ----- |").ok()?;
+	for arena in 0..arenas().ok()? {
+		let initialized = get_indexed::<u8>(mallctl!("arena.0.initialized"), &[(1, arena)]);
+		if !initialized.is_ok_and(is_nonzero!()) {
+			continue;
+		}
+
+		let active = pages(arena, mallctl!("stats.arenas.0.pactive"));
+		let dirty = pages(arena, mallctl!("stats.arenas.0.pdirty"));
+		let muzzy = pages(arena, mallctl!("stats.arenas.0.pmuzzy"));
+		writeln!(out, "| {arena} | {active} | {dirty} | {muzzy} |").ok()?;
+	}
+
+	let active = pages(ALL, mallctl!("stats.arenas.0.pactive"));
+	let dirty = pages(ALL, mallctl!("stats.arenas.0.pdirty"));
+	let muzzy = pages(ALL, mallctl!("stats.arenas.0.pmuzzy"));
+	writeln!(out, "| all | {active} | {dirty} | {muzzy} |\n").ok()?;
+
+	writeln!(out, "| size class | regions | slabs | unused | utilization |").ok()?;
+	writeln!(out, "| ---------- | ------- | ----- | ------ | ----------- |").ok()?;
+	let bins: u32 = get(&mallctl!("arenas.nbins")).ok()?;
+	for bin in 0..math::try_into::<usize, _>(bins).ok()? {
+		let size: usize = get_indexed(mallctl!("arenas.bin.0.size"), &[(2, bin)]).ok()?;
+		let nregs: u32 = get_indexed(mallctl!("arenas.bin.0.nregs"), &[(2, bin)]).ok()?;
+		let slab_key = &[(2, ALL), (4, bin)];
+		let regions: usize =
+			get_indexed(mallctl!("stats.arenas.0.bins.0.curregs"), slab_key).ok()?;
+		let slabs: usize =
+			get_indexed(mallctl!("stats.arenas.0.bins.0.curslabs"), slab_key).ok()?;
+		if slabs == 0 {
+			continue;
+		}
+
+		let capacity = slabs.saturating_mul(math::try_into(nregs).ok()?);
+		let unused = pretty(capacity.saturating_sub(regions).saturating_mul(size));
+		let utilization = regions
+			.saturating_mul(100)
+			.checked_div(capacity)
+			.unwrap_or_default();
+
+		let size = pretty(size);
+		writeln!(out, "| {size} | {regions} | {slabs} | {unused} | {utilization}% |").ok()?;
+	}
+
+	Some(out)
+}
+
+#[must_use]
+#[cfg(not(feature = "jemalloc_stats"))]
+pub fn arena_stats() -> Option<String> { None }
+
 pub fn memory_stats(opts: &str) -> Option<String> {
 	const MAX_LENGTH: usize = 1_048_576;
 
@@ -322,6 +409,20 @@ fn get_by_arena<T>(id: Option<usize>, mut key: Key) -> Result<T>
 	get(&key)
 }
 
+/// Read a key naming arenas or bins by index, with the segments of the key
+/// at the given positions replaced by the indexes.
+#[cfg(feature = "jemalloc_stats")]
+fn get_indexed<T>(mut key: Key, indexes: &[(usize, usize)]) -> Result<T>
+where
+	T: Copy + Debug,
+{
+	for &(seg, index) in indexes {
+		key[seg] = index;
+	}
+
+	get(&key)
+}
+
 fn notify(key: &Key) -> Result { xchg(key, ()) }
 
 fn set<T>(key: &Key, val: T) -> Result<T>
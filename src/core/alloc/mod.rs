@@ -4,7 +4,7 @@
 #[cfg(all(not(target_env = "msvc"), feature = "jemalloc"))]
 pub mod je;
 #[cfg(all(not(target_env = "msvc"), feature = "jemalloc"))]
-pub use je::{memory_stats, memory_usage, trim};
+pub use je::{arena_stats, memory_stats, memory_usage, resident, trim};
 
 #[cfg(all(not(target_env = "msvc"), feature = "hardened_malloc", not(feature = "jemalloc")))]
 pub mod hardened;
@@ -13,7 +13,7 @@
 	feature = "hardened_malloc",
 	not(feature = "jemalloc")
 ))]
-pub use hardened::{memory_stats, memory_usage, trim};
+pub use hardened::{arena_stats, memory_stats, memory_usage, resident, trim};
 
 #[cfg(any(
 	target_env = "msvc",
@@ -24,4 +24,4 @@
 	target_env = "msvc",
 	all(not(feature = "hardened_malloc"), not(feature = "jemalloc"))
 ))]
-pub use default::{memory_stats, memory_usage, trim};
+pub use default::{arena_stats, memory_stats, memory_usage, resident, trim};
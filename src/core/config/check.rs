@@ -1,9 +1,9 @@
-use std::env::consts::OS;
+use std::{collections::HashSet, env::consts::OS};
 
 use either::Either;
 use figment::Figment;
 
-use super::DEPRECATED_KEYS;
+use super::{DEPRECATED_KEYS, MediaStorageBackend};
 use crate::{Config, Err, Result, Server, debug, debug_info, debug_warn, error, warn};
 
 /// Performs check() with additional checks specific to reloading old config
@@ -49,6 +49,23 @@ pub fn check(config: &Config) -> Result {
 		);
 	}
 
+	if config.acme.enable && (config.tls.certs.is_some() || config.tls.key.is_some()) {
+		return Err!(Config(
+			"acme.enable",
+			"ACME obtains the TLS certificate itself; unset tls.certs and tls.key to use it."
+		));
+	}
+
+	if config
+		.maintenance_quiet_hours
+		.is_some_and(|hours| hours.iter().any(|&hour| hour > 23))
+	{
+		return Err!(Config(
+			"maintenance_quiet_hours",
+			"Quiet hours must be hours of the day from 0 to 23."
+		));
+	}
+
 	if cfg!(not(unix)) && config.unix_socket_path.is_some() {
 		return Err!(Config(
 			"unix_socket_path",
@@ -57,6 +74,30 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	for listener in &config.listeners {
+		if listener.roles.is_empty() {
+			return Err!(Config("listeners", "Every listener needs at least one role."));
+		}
+
+		if listener.port.is_some() == listener.unix_socket_path.is_some() {
+			return Err!(Config(
+				"listeners",
+				"Every listener needs either a port or a unix_socket_path, but not both."
+			));
+		}
+
+		if cfg!(not(unix)) && listener.unix_socket_path.is_some() {
+			return Err!(Config(
+				"listeners",
+				"UNIX socket support is only available on *nix platforms."
+			));
+		}
+	}
+
+	if !config.listeners.is_empty() && config.unix_socket_path.is_some() {
+		warn!("unix_socket_path is ignored because listeners are configured.");
+	}
+
 	if config.unix_socket_path.is_none() && config.get_bind_hosts().is_empty() {
 		return Err!(Config("address", "No TCP addresses were specified to listen on"));
 	}
@@ -106,6 +147,18 @@ pub fn check(config: &Config) -> Result {
 		});
 	}
 
+	if config
+		.otlp_sample_targets
+		.values()
+		.chain([&config.otlp_sample_ratio])
+		.any(|ratio| !(0.0..=1.0).contains(ratio))
+	{
+		return Err!(Config(
+			"otlp_sample_ratio",
+			"OTLP sample ratios must be between 0.0 and 1.0."
+		));
+	}
+
 	// rocksdb does not allow max_log_files to be 0
 	if config.rocksdb_max_log_files == 0 {
 		return Err!(Config(
@@ -170,6 +223,177 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if config.oidc.enable && (config.oidc.issuer.is_none() || config.oidc.client_id.is_none()) {
+		return Err!(Config(
+			"oidc",
+			"Delegating authentication to an OpenID Connect provider requires both `issuer` and \
+			 `client_id` to be set."
+		));
+	}
+
+	if config.jwt.enable && config.jwt.secret.is_none() && config.jwt.jwks_url.is_none() {
+		return Err!(Config(
+			"jwt",
+			"JWT login requires either `secret` or `jwks_url` to be set."
+		));
+	}
+
+	if config.ldap.enable && (config.ldap.uri.is_none() || config.ldap.base_dn.is_none()) {
+		return Err!(Config(
+			"ldap",
+			"LDAP authentication requires both `uri` and `base_dn` to be set."
+		));
+	}
+
+	if config
+		.password_policy
+		.minimum_zxcvbn_score
+		.is_some_and(|score| score > 4)
+	{
+		return Err!(Config(
+			"password_policy.minimum_zxcvbn_score",
+			"The zxcvbn score ranges from 0 to 4."
+		));
+	}
+
+	let rate_limit = &config.rate_limit;
+	let rate_limit_buckets = [
+		&rate_limit.login,
+		&rate_limit.registration,
+		&rate_limit.message,
+		&rate_limit.join,
+		&rate_limit.invite,
+		&rate_limit.media,
+		&rate_limit.federation,
+	]
+	.into_iter()
+	.flat_map(|class| [&class.per_user, &class.per_ip])
+	.flatten();
+
+	for bucket in rate_limit_buckets {
+		if !bucket.per_second.is_normal() || bucket.per_second < 0.0 || bucket.burst_count == 0 {
+			return Err!(Config(
+				"rate_limit",
+				"Rate limits require a positive `per_second` and a non-zero `burst_count`."
+			));
+		}
+	}
+
+	if config.join_limit.enable && config.join_limit.window == 0 {
+		return Err!(Config("join_limit.window", "The join limit window must be non-zero."));
+	}
+
+	if config.login_via_existing_session && config.login_token_ttl == 0 {
+		return Err!(Config(
+			"login_token_ttl",
+			"Login via an existing session requires a non-zero login token lifetime."
+		));
+	}
+
+	if !config.sso_providers.is_empty() && config.login_token_ttl == 0 {
+		return Err!(Config(
+			"login_token_ttl",
+			"Login through SSO providers requires a non-zero login token lifetime."
+		));
+	}
+
+	let mut sso_provider_ids = HashSet::new();
+	if let Some(provider) = config
+		.sso_providers
+		.iter()
+		.find(|provider| !sso_provider_ids.insert(&provider.id))
+	{
+		return Err!(Config("sso_providers", "Duplicate SSO provider ID {:?}.", provider.id));
+	}
+
+	if config.smtp.allow_password_reset
+		&& (config.smtp.connection_uri.is_none() || config.smtp.sender.is_none())
+	{
+		return Err!(Config(
+			"smtp",
+			"Password reset via email requires both `connection_uri` and `sender` to be set."
+		));
+	}
+
+	if config.media_redirect.enable && config.media_redirect.endpoint.is_none() {
+		return Err!(Config(
+			"media_redirect",
+			"Redirecting media downloads requires `endpoint` to be set."
+		));
+	}
+
+	if config.media_redirect.access_key_id.is_some()
+		!= config.media_redirect.secret_access_key.is_some()
+	{
+		return Err!(Config(
+			"media_redirect",
+			"Presigning redirect URLs requires both `access_key_id` and `secret_access_key`."
+		));
+	}
+
+	if config.media_scanner.clamd_socket.is_some() && config.media_scanner.clamd_address.is_some()
+	{
+		return Err!(Config(
+			"media_scanner",
+			"Only one of `clamd_socket` and `clamd_address` can be set."
+		));
+	}
+
+	if config.media_storage.backend == MediaStorageBackend::S3
+		&& (config.media_storage.endpoint.is_none() || config.media_storage.bucket.is_none())
+	{
+		return Err!(Config(
+			"media_storage",
+			"Storing media in S3 requires both `endpoint` and `bucket` to be set."
+		));
+	}
+
+	if config.media_storage.access_key_id.is_some()
+		!= config.media_storage.secret_access_key.is_some()
+	{
+		return Err!(Config(
+			"media_storage",
+			"Signing requests requires both `access_key_id` and `secret_access_key`."
+		));
+	}
+
+	if config.media_storage.part_size < 5 * 1024 * 1024 {
+		return Err!(Config(
+			"media_storage.part_size",
+			"Parts of multipart uploads must be at least 5 MiB."
+		));
+	}
+
+	if let Some(size) = config.media_thumbnail_pregenerate.iter().find(|size| {
+		!size.split_once('x').is_some_and(|(width, height)| {
+			matches!((width.parse::<u32>(), height.parse::<u32>()), (Ok(1..=800), Ok(1..=600)))
+		})
+	}) {
+		return Err!(Config(
+			"media_thumbnail_pregenerate",
+			"Invalid thumbnail size {size:?}; expected WIDTHxHEIGHT of at most 800x600."
+		));
+	}
+
+	if let Some((id, _)) = config
+		.appservice_transaction_concurrency
+		.iter()
+		.find(|&(_, &concurrency)| concurrency == 0)
+	{
+		return Err!(Config(
+			"appservice_transaction_concurrency",
+			"The transaction concurrency of appservice {id:?} must be non-zero."
+		));
+	}
+
+	if config.rendezvous_enable && !config.oidc.enable {
+		warn!(
+			"QR code login via rendezvous sessions is enabled, but authentication is not \
+			 delegated to an OpenID Connect provider. Clients will not be able to complete the \
+			 login."
+		);
+	}
+
 	// check if user specified valid IP CIDR ranges on startup
 	for cidr in &config.ip_range_denylist {
 		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
@@ -184,6 +408,7 @@ pub fn check(config: &Config) -> Result {
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_token
 	{
 		return Err!(Config(
 			"registration_token",
@@ -201,6 +426,7 @@ pub fn check(config: &Config) -> Result {
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_token
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -0,0 +1,71 @@
+//! Config files included by other config files
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::{Err, Result, err, toml};
+
+/// Limit on the depth of includes, which stops include cycles.
+const DEPTH_MAX: usize = 8;
+
+/// The config files in the order they are merged: each file is followed by
+/// the files it includes, recursively.
+pub(super) fn expand<I>(paths: I) -> Result<Vec<PathBuf>>
+where
+	I: Iterator<Item = PathBuf>,
+{
+	let mut files = Vec::new();
+	for path in paths {
+		expand_file(path, 0, &mut files)?;
+	}
+
+	Ok(files)
+}
+
+fn expand_file(path: PathBuf, depth: usize, files: &mut Vec<PathBuf>) -> Result {
+	if depth > DEPTH_MAX {
+		return Err!("Config files include each other more than {DEPTH_MAX} deep at {path:?}.");
+	}
+
+	let patterns = includes(&path)?;
+	let dir = path.parent().unwrap_or_else(|| Path::new(".")).to_owned();
+	files.push(path);
+
+	for pattern in patterns {
+		let pattern = dir.join(&pattern);
+		let pattern = pattern.to_string_lossy();
+		let mut matches = glob::glob(&pattern)
+			.map_err(|e| err!("Invalid config include pattern {pattern:?}: {e}"))?
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| err!("Failed to read config include {pattern:?}: {e}"))?;
+
+		matches.sort();
+		for path in matches {
+			expand_file(path, depth.saturating_add(1), files)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// The `include` patterns of the `[global]` section of the file; none if the
+/// file does not exist, as missing config files are skipped.
+fn includes(path: &Path) -> Result<Vec<String>> {
+	let Ok(contents) = fs::read_to_string(path) else {
+		return Ok(Vec::new());
+	};
+
+	let table: toml::Table = toml::from_str(&contents)
+		.map_err(|e| err!("There was a problem with your configuration file {path:?}: {e}"))?;
+
+	let Some(include) = table.get("global").and_then(|global| global.get("include")) else {
+		return Ok(Vec::new());
+	};
+
+	include
+		.clone()
+		.try_into()
+		.map_err(|e| err!("Config option include in {path:?} must be a list of paths: {e}"))
+}
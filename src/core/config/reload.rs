@@ -0,0 +1,82 @@
+//! Which configuration changes take effect when the configuration is
+//! reloaded while the server is running.
+
+use super::Config;
+
+/// Signal sent after a new configuration is made active, for the parts of the
+/// server which hold state derived from it, such as the TLS certificates.
+pub const SIGNAL: &str = "config_reloaded";
+
+/// Keys whose new values take effect on reload. A change to any other key
+/// requires a restart to be sure it takes effect.
+pub const RELOADABLE: &[&str] = &[
+	// logging
+	"log",
+	// rate limits
+	"rate_limit",
+	"join_limit",
+	// forbidden lists
+	"forbidden_alias_names",
+	"forbidden_remote_room_directory_server_names",
+	"forbidden_remote_server_names",
+	"forbidden_usernames",
+	"forbidden_usernames_enforcement",
+	// url previews
+	"url_preview_cache_max_entries",
+	"url_preview_cache_ttl",
+	"url_preview_check_root_domain",
+	"url_preview_domain_contains_allowlist",
+	"url_preview_domain_explicit_allowlist",
+	"url_preview_domain_explicit_denylist",
+	"url_preview_max_spider_size",
+	"url_preview_oembed",
+	"url_preview_url_contains_allowlist",
+	// media limits
+	"max_request_size",
+	"prevent_media_downloads_from",
+	// tls certificates
+	"tls.certs",
+	"tls.key",
+];
+
+/// Keys changed by a reload.
+#[derive(Debug, Default)]
+pub struct Changes {
+	/// Changes which took effect
+	pub applied: Vec<String>,
+
+	/// Changes which take effect after a restart
+	pub restart: Vec<String>,
+}
+
+impl Changes {
+	#[must_use]
+	pub fn new(old: &Config, new: &Config) -> Self {
+		let mut changes = Self::default();
+		for key in new.changed_fields(old) {
+			let keys: Vec<String> = match key {
+				| "catchall" => continue,
+				| "tls" => new
+					.tls
+					.changed_fields(&old.tls)
+					.into_iter()
+					.map(|field| format!("tls.{field}"))
+					.collect(),
+				| key => vec![key.to_owned()],
+			};
+
+			for key in keys {
+				if RELOADABLE.contains(&key.as_str()) {
+					changes.applied.push(key);
+				} else {
+					changes.restart.push(key);
+				}
+			}
+		}
+
+		changes
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.applied.is_empty() && self.restart.is_empty() }
+}
@@ -0,0 +1,169 @@
+//! Environment variables and secret files referenced by the configuration,
+//! so secrets need not be written into it.
+
+use std::{env, ffi::OsString, fs};
+
+use figment::{
+	Figment,
+	providers::Serialized,
+	value::{Dict, Value},
+};
+
+use crate::{Err, Result, err};
+
+/// Keys whose value can instead be read from the file named by `<key>_file`.
+/// `registration_token_file` and `turn_secret_file` are options of their own.
+pub const SECRET_FILE_KEYS: &[&str] = &[
+	"emergency_password",
+	"turn_password",
+	"jwt.secret",
+	"ldap.bind_password",
+	"media_redirect.secret_access_key",
+	"media_scanner.http_token",
+	"media_storage.secret_access_key",
+	"oidc.client_secret",
+	"smtp.connection_uri",
+	"spam_checker.webhook_token",
+];
+
+/// Replace `${VAR}` in string values with the value of the environment
+/// variable if it is set (`$${` for a literal `${`), then read the secrets
+/// given by `<key>_file`.
+pub(super) fn resolve(raw: &Figment) -> Result<Figment> {
+	let mut dict: Dict = raw
+		.extract()
+		.map_err(|e| err!("There was a problem with your configuration file: {e}"))?;
+
+	for (key, value) in &mut dict {
+		substitute(key, value)?;
+	}
+
+	read_secret_files(&mut dict)?;
+
+	Ok(Figment::from(Serialized::globals(dict)))
+}
+
+fn substitute(key: &str, value: &mut Value) -> Result {
+	match value {
+		| Value::String(_, string) if string.contains('$') => {
+			*string = interpolate(key, string)?;
+		},
+		| Value::Dict(_, dict) =>
+			for (name, value) in dict {
+				substitute(&format!("{key}.{name}"), value)?;
+			},
+		| Value::Array(_, values) =>
+			for value in values {
+				substitute(key, value)?;
+			},
+		| _ => (),
+	}
+
+	Ok(())
+}
+
+/// Only references to environment variables which are set are replaced;
+/// anything else, such as `${` in a regular expression, is kept as written so
+/// existing values keep loading.
+fn interpolate(key: &str, input: &str) -> Result<String> {
+	let mut output = String::with_capacity(input.len());
+	let mut rest = input;
+	while let Some((before, after)) = rest.split_once('$') {
+		output.push_str(before);
+		if let Some(after) = after.strip_prefix("${") {
+			output.push_str("${");
+			rest = after;
+		} else if let Some((value, after)) = after
+			.strip_prefix('{')
+			.and_then(|after| after.split_once('}'))
+			.and_then(|(name, after)| Some((variable(name)?, after)))
+		{
+			let value = value.into_string().map_err(|_| {
+				err!("Config option {key} refers to an environment variable which is not UTF-8.")
+			})?;
+
+			output.push_str(&value);
+			rest = after;
+		} else {
+			output.push('$');
+			rest = after;
+		}
+	}
+
+	output.push_str(rest);
+	Ok(output)
+}
+
+fn variable(name: &str) -> Option<OsString> {
+	let valid = name
+		.chars()
+		.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+	valid.then(|| env::var_os(name)).flatten()
+}
+
+fn read_secret_files(dict: &mut Dict) -> Result {
+	for key in SECRET_FILE_KEYS {
+		let (table, name) = match key.split_once('.') {
+			| Some((section, name)) => match dict.get_mut(section) {
+				| Some(Value::Dict(_, table)) => (table, name),
+				| _ => continue,
+			},
+			| None => (&mut *dict, *key),
+		};
+
+		let Some(path) = table.remove(&format!("{name}_file")) else {
+			continue;
+		};
+
+		let Some(path) = path.as_str().map(ToOwned::to_owned) else {
+			return Err!("Config option {key}_file must be a path.");
+		};
+
+		if table.contains_key(name) {
+			return Err!("Config options {key} and {key}_file are both set; set only one.");
+		}
+
+		let secret = fs::read_to_string(&path)
+			.map_err(|e| err!("Failed to read {key}_file {path:?}: {e}"))?;
+
+		let secret = secret.trim_end_matches(['\r', '\n']).to_owned();
+		table.insert(name.to_owned(), Value::from(secret));
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::interpolate;
+
+	#[test]
+	fn interpolate_plain() {
+		assert_eq!(interpolate("k", "^admin$").unwrap(), "^admin$");
+		assert_eq!(interpolate("k", "a$b$").unwrap(), "a$b$");
+	}
+
+	#[test]
+	fn interpolate_env() {
+		let path = std::env::var("PATH").unwrap();
+		assert_eq!(interpolate("k", "x${PATH}y").unwrap(), format!("x{path}y"));
+	}
+
+	#[test]
+	fn interpolate_escape() {
+		assert_eq!(interpolate("k", "$${PATH}").unwrap(), "${PATH}");
+	}
+
+	#[test]
+	fn interpolate_unknown() {
+		assert_eq!(
+			interpolate("k", "${CONDUWUIT_TEST_UNSET_VARIABLE}").unwrap(),
+			"${CONDUWUIT_TEST_UNSET_VARIABLE}"
+		);
+		assert_eq!(interpolate("k", "${PATH").unwrap(), "${PATH");
+		assert_eq!(interpolate("k", "^a${2,3}$").unwrap(), "^a${2,3}$");
+	}
+}
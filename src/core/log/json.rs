@@ -0,0 +1,128 @@
+//! One JSON object per line, for log collectors.
+
+use std::fmt::Write;
+
+use serde_json::{Map, Value};
+use tracing::{
+	Event, Subscriber,
+	field::{Field, Visit},
+};
+use tracing_subscriber::{
+	fmt::{
+		FmtContext, FormatEvent, FormattedFields,
+		format::{JsonFields, Writer},
+		time::{FormatTime, SystemTime},
+	},
+	registry::LookupSpan,
+};
+
+use crate::Result;
+
+/// Formats events as JSON objects holding the fields of the event, the
+/// spans it occurred in, and at the top level the request ID, user and room
+/// found in either; an inner span overrides an outer one.
+///
+/// Span fields are stored by [`JsonFields`], which the layer must use.
+#[derive(Default)]
+pub struct JsonFormat;
+
+/// Fields promoted to the top level of the object, by the names they are
+/// recorded under.
+const PROMOTED: &[(&str, &str)] = &[
+	("request_id", "request_id"),
+	("user", "user"),
+	("user_id", "user"),
+	("sender_user", "user"),
+	("room", "room"),
+	("room_id", "room"),
+];
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+	S: Subscriber + for<'a> LookupSpan<'a>,
+{
+	fn format_event(
+		&self,
+		ctx: &FmtContext<'_, S, JsonFields>,
+		mut writer: Writer<'_>,
+		event: &Event<'_>,
+	) -> Result<(), std::fmt::Error> {
+		let meta = event.metadata();
+		let mut timestamp = String::new();
+		SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+		let mut object = Map::new();
+		object.insert("timestamp".into(), timestamp.into());
+		object.insert("level".into(), meta.level().as_str().into());
+		object.insert("target".into(), meta.target().into());
+
+		let mut spans = Vec::new();
+		let mut current = None;
+		for span in ctx
+			.event_scope()
+			.into_iter()
+			.flat_map(|scope| scope.from_root())
+		{
+			let extensions = span.extensions();
+			let mut fields = extensions
+				.get::<FormattedFields<JsonFields>>()
+				.and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+				.unwrap_or_default();
+
+			promote(&mut object, &fields);
+			current = Some(span.name());
+			fields.insert("name".into(), span.name().into());
+			spans.push(Value::Object(fields));
+		}
+
+		let mut visitor = JsonVisitor(Map::new());
+		event.record(&mut visitor);
+		promote(&mut object, &visitor.0);
+
+		if let Some(current) = current {
+			object.insert("span".into(), current.into());
+		}
+
+		object.insert("fields".into(), visitor.0.into());
+		object.insert("spans".into(), spans.into());
+
+		let line = serde_json::to_string(&object).map_err(|_| std::fmt::Error)?;
+		writeln!(writer, "{line}")
+	}
+}
+
+fn promote(object: &mut Map<String, Value>, fields: &Map<String, Value>) {
+	for (name, promoted) in PROMOTED {
+		if let Some(value) = fields.get(*name) {
+			object.insert((*promoted).into(), value.clone());
+		}
+	}
+}
+
+struct JsonVisitor(Map<String, Value>);
+
+impl JsonVisitor {
+	fn insert<V: Into<Value>>(&mut self, field: &Field, value: V) {
+		if field.name().starts_with('_') {
+			return;
+		}
+
+		self.0.insert(field.name().into(), value.into());
+	}
+}
+
+impl Visit for JsonVisitor {
+	fn record_f64(&mut self, field: &Field, value: f64) { self.insert(field, value); }
+
+	fn record_i64(&mut self, field: &Field, value: i64) { self.insert(field, value); }
+
+	fn record_u64(&mut self, field: &Field, value: u64) { self.insert(field, value); }
+
+	fn record_bool(&mut self, field: &Field, value: bool) { self.insert(field, value); }
+
+	fn record_str(&mut self, field: &Field, value: &str) { self.insert(field, value); }
+
+	fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+		self.insert(field, format!("{value:?}"));
+	}
+}
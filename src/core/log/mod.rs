@@ -5,11 +5,14 @@
 pub mod console;
 pub mod fmt;
 pub mod fmt_span;
+pub mod json;
+pub mod propagate;
 mod reload;
 mod suppress;
 
 pub use capture::Capture;
 pub use console::{ConsoleFormat, ConsoleWriter, is_systemd_mode};
+pub use json::JsonFormat;
 pub use reload::{LogLevelReloadHandles, ReloadHandle};
 pub use suppress::Suppress;
 pub use tracing::Level;
@@ -0,0 +1,67 @@
+//! Propagation of the trace context in HTTP headers, e.g. W3C `traceparent`,
+//! so our spans join the trace of the caller and of the servers we call.
+//! These are no-ops unless built with `perf_measurements` and a propagator
+//! was installed by an exporter.
+
+use http::HeaderMap;
+use tracing::Span;
+
+/// Make the trace context given in the headers of a request the parent of
+/// the span handling it.
+#[cfg(feature = "perf_measurements")]
+pub fn extract(span: &Span, headers: &HeaderMap) {
+	use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+	let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+		propagator.extract(&Headers(headers))
+	});
+
+	span.set_parent(context);
+}
+
+/// Add the trace context of the span to the headers of an outgoing request.
+#[cfg(feature = "perf_measurements")]
+pub fn inject(span: &Span, headers: &mut HeaderMap) {
+	use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+	let context = span.context();
+	opentelemetry::global::get_text_map_propagator(|propagator| {
+		propagator.inject_context(&context, &mut HeadersMut(headers));
+	});
+}
+
+#[cfg(not(feature = "perf_measurements"))]
+#[inline]
+pub fn extract(_span: &Span, _headers: &HeaderMap) {}
+
+#[cfg(not(feature = "perf_measurements"))]
+#[inline]
+pub fn inject(_span: &Span, _headers: &mut HeaderMap) {}
+
+#[cfg(feature = "perf_measurements")]
+struct Headers<'a>(&'a HeaderMap);
+
+#[cfg(feature = "perf_measurements")]
+struct HeadersMut<'a>(&'a mut HeaderMap);
+
+#[cfg(feature = "perf_measurements")]
+impl opentelemetry::propagation::Extractor for Headers<'_> {
+	fn get(&self, key: &str) -> Option<&str> {
+		self.0.get(key).and_then(|value| value.to_str().ok())
+	}
+
+	fn keys(&self) -> Vec<&str> { self.0.keys().map(http::HeaderName::as_str).collect() }
+}
+
+#[cfg(feature = "perf_measurements")]
+impl opentelemetry::propagation::Injector for HeadersMut<'_> {
+	fn set(&mut self, key: &str, value: String) {
+		let Ok(name) = http::HeaderName::from_bytes(key.as_bytes()) else {
+			return;
+		};
+
+		if let Ok(value) = http::HeaderValue::from_str(&value) {
+			self.0.insert(name, value);
+		}
+	}
+}
@@ -1,3 +1,4 @@
+mod borrowed;
 mod builder;
 mod content;
 mod count;
@@ -23,7 +24,8 @@
 use serde_json::value::RawValue as RawJsonValue;
 
 pub use self::{
-	Count as PduCount, Id as PduId, Pdu as PduEvent, RawId as RawPduId,
+	Borrowed as BorrowedPdu, Count as PduCount, Id as PduId, Pdu as PduEvent, RawId as RawPduId,
+	borrowed::Borrowed,
 	builder::{Builder, Builder as PduBuilder},
 	count::Count,
 	event_id::*,
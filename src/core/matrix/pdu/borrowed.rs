@@ -0,0 +1,58 @@
+use std::borrow::Cow;
+
+use ruma::{UInt, events::TimelineEventType};
+use serde::Deserialize;
+use serde_json::value::RawValue as RawJsonValue;
+
+use crate::Result;
+
+/// Fields of a PDU borrowed from its JSON. Reading these is much cheaper than
+/// deserializing the whole PDU, so they decide which PDUs of a scan are worth
+/// deserializing at all. Strings are only copied when they contain escapes.
+#[derive(Debug, Deserialize)]
+pub struct Borrowed<'a> {
+	#[serde(borrow)]
+	pub event_id: Cow<'a, str>,
+
+	#[serde(borrow)]
+	pub room_id: Cow<'a, str>,
+
+	#[serde(borrow)]
+	pub sender: Cow<'a, str>,
+
+	#[serde(borrow, rename = "type")]
+	pub kind: Cow<'a, str>,
+
+	#[serde(borrow, default)]
+	pub state_key: Option<Cow<'a, str>>,
+
+	#[serde(borrow, default)]
+	pub redacts: Option<Cow<'a, str>>,
+
+	pub origin_server_ts: UInt,
+
+	#[serde(borrow)]
+	pub content: &'a RawJsonValue,
+}
+
+impl<'a> Borrowed<'a> {
+	#[inline]
+	pub fn from_slice(json: &'a [u8]) -> Result<Self> {
+		serde_json::from_slice(json).map_err(Into::into)
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn is_kind(&self, kind: &TimelineEventType) -> bool { self.kind == kind.to_cow_str() }
+
+	#[inline]
+	#[must_use]
+	pub fn is_state(&self) -> bool { self.state_key.is_some() }
+
+	/// Whether this is a state event of the type with the state key.
+	#[inline]
+	#[must_use]
+	pub fn is_state_of(&self, kind: &TimelineEventType, state_key: &str) -> bool {
+		self.is_kind(kind) && self.state_key.as_deref() == Some(state_key)
+	}
+}
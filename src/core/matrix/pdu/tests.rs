@@ -15,3 +15,31 @@ fn normal_parse() {
 
 	assert!(!backfilled, "backfilled variant");
 }
+
+#[test]
+fn borrowed_fields() {
+	use ruma::events::TimelineEventType;
+
+	use super::Borrowed;
+
+	let json = br#"{
+		"event_id": "$event:example.com",
+		"room_id": "!room:example.com",
+		"sender": "@alice:example.com",
+		"type": "m.room.member",
+		"state_key": "@alice:example.com",
+		"origin_server_ts": 1700000000000,
+		"content": {"membership": "join"},
+		"prev_events": [],
+		"auth_events": [],
+		"depth": 1,
+		"hashes": {"sha256": ""}
+	}"#;
+
+	let pdu = Borrowed::from_slice(json).expect("from_slice() failed");
+
+	assert_eq!(pdu.sender, "@alice:example.com");
+	assert!(pdu.is_state_of(&TimelineEventType::RoomMember, "@alice:example.com"));
+	assert!(!pdu.is_kind(&TimelineEventType::RoomMessage));
+	assert_eq!(pdu.content.get(), r#"{"membership": "join"}"#);
+}
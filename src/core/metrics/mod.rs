@@ -1,3 +1,5 @@
+pub mod requests;
+
 use std::sync::atomic::AtomicU32;
 
 use tokio::runtime;
@@ -5,6 +7,8 @@
 #[cfg(tokio_unstable)]
 use tokio_metrics::{RuntimeIntervals, RuntimeMonitor};
 
+pub use self::requests::Requests;
+
 pub struct Metrics {
 	_runtime: Option<runtime::Handle>,
 
@@ -22,6 +26,12 @@ pub struct Metrics {
 	pub requests_handle_active: AtomicU32,
 	pub requests_handle_finished: AtomicU32,
 	pub requests_panic: AtomicU32,
+
+	/// Listeners serving connections
+	pub listeners: AtomicU32,
+
+	/// Latency and errors by endpoint
+	pub requests: Requests,
 }
 
 impl Metrics {
@@ -49,6 +59,8 @@ pub fn new(runtime: Option<runtime::Handle>) -> Self {
 			requests_handle_active: AtomicU32::new(0),
 			requests_handle_finished: AtomicU32::new(0),
 			requests_panic: AtomicU32::new(0),
+			listeners: AtomicU32::new(0),
+			requests: Requests::default(),
 		}
 	}
 
@@ -0,0 +1,172 @@
+//! Latency and errors of the requests to each endpoint, kept per minute for
+//! the last hour.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::Mutex,
+	time::Duration,
+};
+
+use crate::utils::time::now_millis;
+
+/// Minutes of history kept for each endpoint
+pub const WINDOW: u64 = 60;
+
+/// Latencies are bucketed in microseconds, two buckets per power of two from
+/// 2^MIN_LOG to 2^MAX_LOG. Shorter and longer latencies count in the first
+/// and last buckets.
+const MIN_LOG: u32 = 6;
+const MAX_LOG: u32 = 26;
+const BUCKETS: usize = 40;
+
+#[derive(Default)]
+pub struct Requests {
+	endpoints: Mutex<HashMap<String, VecDeque<Minute>>>,
+}
+
+struct Minute {
+	minute: u64,
+	latency: [u32; BUCKETS],
+	client_errors: u32,
+	server_errors: u32,
+}
+
+/// Requests to an endpoint over the minutes asked for. Percentiles are the
+/// upper bound of the bucket they fall in.
+#[derive(Debug)]
+pub struct Stats {
+	pub endpoint: String,
+	pub requests: u64,
+	pub client_errors: u64,
+	pub server_errors: u64,
+	pub p50: Duration,
+	pub p95: Duration,
+	pub p99: Duration,
+}
+
+impl Requests {
+	/// Record a request to the endpoint answered with `status` after
+	/// `latency`.
+	pub fn record(&self, method: &str, path: &str, status: u16, latency: Duration) {
+		let endpoint = format!("{method} {path}");
+		let minute = now_millis() / 60_000;
+
+		let mut endpoints = self.endpoints.lock().expect("locked");
+		let history = endpoints.entry(endpoint).or_default();
+		while history
+			.front()
+			.is_some_and(|first| first.minute.saturating_add(WINDOW) <= minute)
+		{
+			history.pop_front();
+		}
+
+		if history.back().is_none_or(|last| last.minute != minute) {
+			history.push_back(Minute::new(minute));
+		}
+
+		let current = history.back_mut().expect("minute pushed above");
+		if let Some(count) = current.latency.get_mut(bucket(latency)) {
+			*count = count.saturating_add(1);
+		}
+
+		match status {
+			| 400..=499 => current.client_errors = current.client_errors.saturating_add(1),
+			| 500..=599 => current.server_errors = current.server_errors.saturating_add(1),
+			| _ => (),
+		}
+	}
+
+	/// Totals of each endpoint requested in the last `minutes`, the current
+	/// one included, busiest first.
+	#[must_use]
+	pub fn stats(&self, minutes: u64) -> Vec<Stats> {
+		let since = (now_millis() / 60_000).saturating_sub(minutes.min(WINDOW));
+		let endpoints = self.endpoints.lock().expect("locked");
+		let mut stats: Vec<_> = endpoints
+			.iter()
+			.filter_map(|(endpoint, history)| {
+				let mut latency = [0_u64; BUCKETS];
+				let (mut client_errors, mut server_errors) = (0_u64, 0_u64);
+				for minute in history.iter().filter(|minute| minute.minute > since) {
+					for (total, &count) in latency.iter_mut().zip(&minute.latency) {
+						*total = total.saturating_add(count.into());
+					}
+
+					client_errors = client_errors.saturating_add(minute.client_errors.into());
+					server_errors = server_errors.saturating_add(minute.server_errors.into());
+				}
+
+				let requests = latency.iter().fold(0_u64, |a, &b| a.saturating_add(b));
+				(requests > 0).then(|| Stats {
+					endpoint: endpoint.clone(),
+					requests,
+					client_errors,
+					server_errors,
+					p50: percentile(&latency, requests, 50),
+					p95: percentile(&latency, requests, 95),
+					p99: percentile(&latency, requests, 99),
+				})
+			})
+			.collect();
+
+		stats.sort_by(|a, b| b.requests.cmp(&a.requests));
+		stats
+	}
+}
+
+impl Minute {
+	fn new(minute: u64) -> Self {
+		Self {
+			minute,
+			latency: [0; BUCKETS],
+			client_errors: 0,
+			server_errors: 0,
+		}
+	}
+}
+
+fn percentile(latency: &[u64; BUCKETS], requests: u64, percent: u64) -> Duration {
+	let rank = requests.saturating_mul(percent).div_ceil(100);
+	let mut seen: u64 = 0;
+	let bucket = latency
+		.iter()
+		.position(|&count| {
+			seen = seen.saturating_add(count);
+			seen >= rank
+		})
+		.unwrap_or(BUCKETS - 1);
+
+	Duration::from_micros(upper_bound(bucket))
+}
+
+fn bucket(latency: Duration) -> usize {
+	let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+	let log = micros
+		.checked_ilog2()
+		.unwrap_or_default()
+		.clamp(MIN_LOG, MAX_LOG);
+
+	let base = 2_u64.saturating_pow(log);
+	let upper_half = micros >= base.saturating_add(base / 2);
+	let index = log
+		.saturating_sub(MIN_LOG)
+		.saturating_mul(2)
+		.saturating_add(upper_half.into());
+
+	usize::try_from(index)
+		.unwrap_or(usize::MAX)
+		.min(BUCKETS - 1)
+}
+
+fn upper_bound(bucket: usize) -> u64 {
+	let log = u32::try_from(bucket / 2)
+		.unwrap_or(MAX_LOG)
+		.saturating_add(MIN_LOG);
+
+	let base = 2_u64.saturating_pow(log);
+	if bucket % 2 == 0 {
+		base.saturating_add(base / 2)
+	} else {
+		base.saturating_mul(2)
+	}
+}
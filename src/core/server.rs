@@ -7,7 +7,11 @@
 };
 
 use ruma::OwnedServerName;
-use tokio::{runtime, sync::broadcast};
+use tokio::{
+	runtime,
+	sync::broadcast,
+	task::{AbortHandle, JoinHandle, JoinSet},
+};
 
 use crate::{Err, Result, config, config::Config, log::Log, metrics::Metrics};
 
@@ -125,6 +129,54 @@ pub fn runtime(&self) -> &runtime::Handle {
 			.expect("runtime handle available in Server")
 	}
 
+	/// Spawn a task on the runtime. The name identifies the task in
+	/// tokio-console when built with `tokio_unstable`.
+	#[cfg(tokio_unstable)]
+	pub fn spawn<F>(&self, name: &str, future: F) -> JoinHandle<F::Output>
+	where
+		F: Future + Send + 'static,
+		F::Output: Send + 'static,
+	{
+		tokio::task::Builder::new()
+			.name(name)
+			.spawn_on(future, self.runtime())
+			.expect("task spawned on runtime")
+	}
+
+	#[cfg(not(tokio_unstable))]
+	#[inline]
+	pub fn spawn<F>(&self, _name: &str, future: F) -> JoinHandle<F::Output>
+	where
+		F: Future + Send + 'static,
+		F::Output: Send + 'static,
+	{
+		self.runtime().spawn(future)
+	}
+
+	/// Spawn a task on the runtime into the set, named as with
+	/// [`Server::spawn`].
+	#[cfg(tokio_unstable)]
+	pub fn spawn_in<F>(&self, set: &mut JoinSet<F::Output>, name: &str, future: F) -> AbortHandle
+	where
+		F: Future + Send + 'static,
+		F::Output: Send + 'static,
+	{
+		set.build_task()
+			.name(name)
+			.spawn_on(future, self.runtime())
+			.expect("task spawned on runtime")
+	}
+
+	#[cfg(not(tokio_unstable))]
+	#[inline]
+	pub fn spawn_in<F>(&self, set: &mut JoinSet<F::Output>, _name: &str, future: F) -> AbortHandle
+	where
+		F: Future + Send + 'static,
+		F::Output: Send + 'static,
+	{
+		set.spawn_on(future, self.runtime())
+	}
+
 	#[inline]
 	pub fn check_running(&self) -> Result {
 		use std::{io, io::ErrorKind::Interrupted};
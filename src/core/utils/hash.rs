@@ -1,9 +1,19 @@
 mod argon;
 pub mod sha256;
 
-use crate::Result;
+use crate::{Result, err};
+
+/// Prefix of bcrypt hashes, as found in accounts imported from Synapse
+const BCRYPT_PREFIX: &str = "$2";
 
 pub fn verify_password(password: &str, password_hash: &str) -> Result {
+	if password_hash.starts_with(BCRYPT_PREFIX) {
+		return bcrypt::verify(password, password_hash)
+			.map_err(|e| err!("{e}"))?
+			.then_some(())
+			.ok_or_else(|| err!("password mismatch"));
+	}
+
 	argon::verify_password(password, password_hash)
 }
 
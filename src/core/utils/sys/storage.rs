@@ -128,6 +128,24 @@ pub fn dev_from_path(path: &Path) -> Result<(dev_t, dev_t)> {
 	Ok((major.try_into()?, minor.try_into()?))
 }
 
+/// Get the bytes available to unprivileged users and the total size of the
+/// filesystem on which Path is mounted.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion, clippy::unnecessary_fallible_conversions)]
+pub fn space_from_path(path: &Path) -> Result<(u64, u64)> {
+	let stat = nix::sys::statvfs::statvfs(path).map_err(std::io::Error::from)?;
+	let fragment: u64 = stat.fragment_size().try_into()?;
+	let available: u64 = stat.blocks_available().try_into()?;
+	let total: u64 = stat.blocks().try_into()?;
+
+	Ok((available.saturating_mul(fragment), total.saturating_mul(fragment)))
+}
+
+#[cfg(not(unix))]
+pub fn space_from_path(_path: &Path) -> Result<(u64, u64)> {
+	crate::Err!("Free space is not available on this platform.")
+}
+
 fn block_path((major, minor): (dev_t, dev_t)) -> PathBuf {
 	format!("/sys/dev/block/{major}:{minor}/").into()
 }
@@ -0,0 +1,137 @@
+//! Write batches spanning any number of columns.
+//!
+//! The writes gathered in a batch are applied atomically by a single write to
+//! the database. They are not visible to reads until the batch is written; a
+//! batch dropped without being written is discarded.
+
+use std::{convert::AsRef, fmt::Debug, sync::Arc, time::Instant};
+
+use rocksdb::WriteBatchWithTransaction;
+use serde::Serialize;
+
+use crate::{
+	Engine, Map,
+	keyval::{serialize_key, serialize_val},
+	map::write_options_default,
+	util::or_else,
+};
+
+#[derive(Default)]
+pub struct Batch {
+	batch: WriteBatchWithTransaction<false>,
+	db: Option<Arc<Engine>>,
+	inserted: Vec<(Arc<Map>, Vec<u8>)>,
+}
+
+impl Batch {
+	/// Insert Key/Value
+	///
+	/// - Key is serialized
+	/// - Val is serialized
+	pub fn put<K, V>(&mut self, map: &Arc<Map>, key: K, val: V)
+	where
+		K: Serialize + Debug,
+		V: Serialize,
+	{
+		let val = serialize_val(val).expect("failed to serialize insertion val");
+		self.put_raw(map, key, val);
+	}
+
+	/// Insert Key/Value
+	///
+	/// - Key is serialized
+	/// - Val is raw
+	pub fn put_raw<K, V>(&mut self, map: &Arc<Map>, key: K, val: V)
+	where
+		K: Serialize + Debug,
+		V: AsRef<[u8]>,
+	{
+		let key = serialize_key(key).expect("failed to serialize insertion key");
+		self.insert(map, &key, val);
+	}
+
+	/// Insert Key/Value
+	///
+	/// - Key is raw
+	/// - Val is serialized
+	pub fn raw_put<K, V>(&mut self, map: &Arc<Map>, key: K, val: V)
+	where
+		K: AsRef<[u8]>,
+		V: Serialize,
+	{
+		let val = serialize_val(val).expect("failed to serialize insertion val");
+		self.insert(map, &key, val);
+	}
+
+	/// Insert Key/Value
+	///
+	/// - Key is raw
+	/// - Val is raw
+	pub fn insert<K, V>(&mut self, map: &Arc<Map>, key: &K, val: V)
+	where
+		K: AsRef<[u8]> + ?Sized,
+		V: AsRef<[u8]>,
+	{
+		self.batch.put_cf(&map.cf(), key, val);
+		self.inserted.push((map.clone(), key.as_ref().to_vec()));
+		self.db.get_or_insert_with(|| map.db().clone());
+	}
+
+	/// Remove Key
+	///
+	/// - Key is serialized
+	pub fn del<K>(&mut self, map: &Arc<Map>, key: K)
+	where
+		K: Serialize + Debug,
+	{
+		let key = serialize_key(key).expect("failed to serialize deletion key");
+		self.remove(map, &key);
+	}
+
+	/// Remove Key
+	///
+	/// - Key is raw
+	pub fn remove<K>(&mut self, map: &Arc<Map>, key: &K)
+	where
+		K: AsRef<[u8]> + ?Sized,
+	{
+		self.batch.delete_cf(&map.cf(), key);
+		self.db.get_or_insert_with(|| map.db().clone());
+	}
+
+	/// Number of writes gathered.
+	#[inline]
+	#[must_use]
+	pub fn len(&self) -> usize { self.batch.len() }
+
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.batch.is_empty() }
+
+	/// Apply the gathered writes to the database, then wake the watchers of
+	/// the keys inserted.
+	#[tracing::instrument(skip_all, fields(len = self.len()), level = "trace")]
+	pub fn write(self) {
+		let Self { batch, db, inserted } = self;
+		let Some(db) = db else {
+			return;
+		};
+
+		let write_options = write_options_default(&db);
+		let started = Instant::now();
+		db.db
+			.write_opt(batch, &write_options)
+			.or_else(or_else)
+			.expect("database write batch error");
+
+		db.check_slow("batch", "write", started);
+
+		if !db.corked() {
+			db.flush().expect("database flush error");
+		}
+
+		for (map, key) in &inserted {
+			map.wake(key);
+		}
+	}
+}
@@ -1,4 +1,5 @@
 mod backup;
+mod cache;
 mod cf_opts;
 pub(crate) mod context;
 mod db_opts;
@@ -7,14 +8,17 @@
 mod logger;
 mod memory_usage;
 mod open;
-mod repair;
+pub(crate) mod repair;
+mod slow;
 
 use std::{
+	collections::BTreeMap,
 	ffi::CStr,
 	sync::{
-		Arc,
+		Arc, Mutex,
 		atomic::{AtomicU32, Ordering},
 	},
+	time::Duration,
 };
 
 use conduwuit::{Err, Result, debug, info, warn};
@@ -37,6 +41,8 @@ pub struct Engine {
 	pub(super) secondary: bool,
 	pub(crate) checksums: bool,
 	corks: AtomicU32,
+	slow_threshold: Option<Duration>,
+	slow: Mutex<BTreeMap<String, u64>>,
 }
 
 pub(crate) type Db = DBWithThreadMode<MultiThreaded>;
@@ -112,6 +118,18 @@ pub(crate) fn property(&self, cf: &impl AsColumnFamilyRef, name: &str) -> Result
 			.and_then(|val| val.map_or_else(|| Err!("Property {name:?} not found."), Ok))
 	}
 
+	/// Whether rocksdb is stopping or delaying writes until flushes and
+	/// compactions catch up.
+	pub fn is_write_stalled(&self) -> Result<bool> {
+		let stopped = result(self.db.property_int_value(c"rocksdb.is-write-stopped"))?;
+		let delayed = result(
+			self.db
+				.property_int_value(c"rocksdb.actual-delayed-write-rate"),
+		)?;
+
+		Ok(stopped.unwrap_or(0) > 0 || delayed.unwrap_or(0) > 0)
+	}
+
 	pub(crate) fn cf(&self, name: &str) -> Arc<BoundColumnFamily<'_>> {
 		self.db
 			.cf_handle(name)
@@ -1,34 +1,40 @@
-use std::fmt::Write;
+use std::{fmt::Write, path::Path, sync::Arc};
 
-use conduwuit::{Result, error, implement, info, utils::time::rfc2822_from_seconds, warn};
-use rocksdb::backup::{BackupEngine, BackupEngineOptions};
+use conduwuit::{Err, Result, err, error, implement, info, utils::time::rfc2822_from_seconds};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
 
 use super::Engine;
-use crate::{or_else, util::map_err};
+use crate::{Context, or_else, util::map_err};
 
+/// Create a backup. Backups share the table files they have in common, so
+/// only the files written since the previous backup are copied. An
+/// incremental backup does not flush the memtables first, copying the
+/// write-ahead log instead of writing new table files for the backup.
 #[implement(Engine)]
 #[tracing::instrument(skip(self))]
-pub fn backup(&self) -> Result {
+pub fn backup(&self, incremental: bool) -> Result {
 	let server = &self.ctx.server;
 	let config = &server.config;
-	let path = config.database_backup_path.as_ref();
-	if path.is_none() || path.is_some_and(|path| path.as_os_str().is_empty()) {
+	let Some(path) = backup_path(config.database_backup_path.as_deref()) else {
 		return Ok(());
-	}
+	};
 
-	let options =
-		BackupEngineOptions::new(path.expect("valid database backup path")).map_err(map_err)?;
-	let mut engine = BackupEngine::open(&options, &*self.ctx.env.lock()?).map_err(map_err)?;
+	let mut engine = self.backup_engine(path)?;
 	if config.database_backups_to_keep > 0 {
-		let flush = !self.is_read_only();
+		let flush = !incremental && !self.is_read_only();
 		engine
 			.create_new_backup_flush(&self.db, flush)
 			.map_err(map_err)?;
 
 		let engine_info = engine.get_backup_info();
 		let info = &engine_info.last().expect("backup engine info is not empty");
+		let kind = if engine_info.len() > 1 && incremental {
+			"incremental"
+		} else {
+			"full"
+		};
 		info!(
-			"Created database backup #{} using {} bytes in {} files",
+			"Created {kind} database backup #{} using {} bytes in {} files",
 			info.backup_id, info.size, info.num_files,
 		);
 	}
@@ -47,16 +53,14 @@ pub fn backup(&self) -> Result {
 pub fn backup_list(&self) -> Result<String> {
 	let server = &self.ctx.server;
 	let config = &server.config;
-	let path = config.database_backup_path.as_ref();
-	if path.is_none() || path.is_some_and(|path| path.as_os_str().is_empty()) {
+	let Some(path) = backup_path(config.database_backup_path.as_deref()) else {
 		return Ok("Configure database_backup_path to enable backups, or the path specified is \
 		           not valid"
 			.to_owned());
-	}
+	};
 
 	let mut res = String::new();
-	let options =
-		BackupEngineOptions::new(path.expect("valid database backup path")).or_else(or_else)?;
+	let options = BackupEngineOptions::new(path).or_else(or_else)?;
 	let engine = BackupEngine::open(&options, &*self.ctx.env.lock()?).or_else(or_else)?;
 	for info in engine.get_backup_info() {
 		writeln!(
@@ -71,3 +75,45 @@ pub fn backup_list(&self) -> Result<String> {
 
 	Ok(res)
 }
+
+/// Restore backup `backup_id` into the directory `to` after verifying its
+/// files.
+#[implement(Engine)]
+#[tracing::instrument(skip(ctx))]
+pub(crate) fn restore(ctx: &Arc<Context>, backup_id: u32, to: &Path) -> Result {
+	let config = &ctx.server.config;
+	let Some(path) = backup_path(config.database_backup_path.as_deref()) else {
+		return Err!("Configure database_backup_path to restore a backup.");
+	};
+
+	let options = BackupEngineOptions::new(path).map_err(map_err)?;
+	let mut engine = BackupEngine::open(&options, &*ctx.env.lock()?).map_err(map_err)?;
+	if !engine
+		.get_backup_info()
+		.iter()
+		.any(|info| info.backup_id == backup_id)
+	{
+		return Err!("Backup #{backup_id} not found in {path:?}.");
+	}
+
+	engine
+		.verify_backup(backup_id)
+		.map_err(|e| err!("Backup #{backup_id} failed verification: {e}"))?;
+
+	info!("Verified backup #{backup_id} in {path:?}; restoring...");
+	engine
+		.restore_from_backup(to, to, &RestoreOptions::default(), backup_id)
+		.map_err(map_err)?;
+
+	Ok(())
+}
+
+#[implement(Engine)]
+fn backup_engine(&self, path: &Path) -> Result<BackupEngine> {
+	let options = BackupEngineOptions::new(path).map_err(map_err)?;
+	BackupEngine::open(&options, &*self.ctx.env.lock()?).map_err(map_err)
+}
+
+fn backup_path(path: Option<&Path>) -> Option<&Path> {
+	path.filter(|path| !path.as_os_str().is_empty())
+}
@@ -0,0 +1,65 @@
+use conduwuit::{Err, Result, implement};
+
+use super::{Engine, descriptor::CacheDisp};
+use crate::maps::MAPS;
+
+/// Resize the block cache of a column to hold about `entries` of its expected
+/// entry size, which becomes its configured capacity. A cache shared with
+/// another column is resized for both. Returns the new capacity in bytes.
+#[implement(Engine)]
+pub fn set_cache_capacity(&self, name: &str, entries: usize) -> Result<usize> {
+	let Some(desc) = MAPS.iter().find(|desc| desc.name == name) else {
+		return Err!(Request(NotFound("No database column named {name:?}.")));
+	};
+
+	let ent_size = desc
+		.key_size_hint
+		.unwrap_or_default()
+		.saturating_add(desc.val_size_hint.unwrap_or_default());
+
+	if ent_size == 0 {
+		return Err!(Request(InvalidParam("Column {name:?} has no expected entry size.")));
+	}
+
+	let mut caches = self.ctx.col_cache.lock()?;
+	let cache_name = match desc.cache_disp {
+		| CacheDisp::SharedWith(other) if !caches.contains_key(name) => other,
+		| _ => name,
+	};
+
+	let Some(cache) = caches.get_mut(cache_name) else {
+		return Err!(Request(NotFound("Column {name:?} has no cache of its own.")));
+	};
+
+	let capacity = entries.saturating_mul(ent_size);
+	cache.set_capacity(capacity);
+	self.ctx
+		.col_cache_capacity
+		.lock()?
+		.insert(cache_name.to_owned(), capacity);
+
+	Ok(capacity)
+}
+
+/// Resize the row cache and every column cache to their configured capacity
+/// divided by `2^shrink`, evicting their least recently used entries when
+/// shrinking. A `shrink` of 0 restores the configured capacities.
+#[implement(Engine)]
+pub fn shrink_caches(&self, shrink: u32) -> Result {
+	let shrunk = |capacity: usize| capacity.checked_shr(shrink).unwrap_or(0);
+
+	self.ctx
+		.row_cache
+		.lock()?
+		.set_capacity(shrunk(self.ctx.row_cache_capacity));
+
+	let mut caches = self.ctx.col_cache.lock()?;
+	let capacities = self.ctx.col_cache_capacity.lock()?;
+	for (name, cache) in caches.iter_mut() {
+		if let Some(&capacity) = capacities.get(name) {
+			cache.set_capacity(shrunk(capacity));
+		}
+	}
+
+	Ok(())
+}
@@ -1,4 +1,9 @@
-use conduwuit::{Config, Result, err, utils::math::Expected};
+use conduwuit::{
+	Config, Result,
+	config::{RocksDbColumnOptions, RocksDbColumnPreset},
+	err,
+	utils::math::{Expected, usize_from_f64},
+};
 use rocksdb::{
 	BlockBasedIndexType, BlockBasedOptions, BlockBasedPinningTier, Cache,
 	DBCompressionType as CompressionType, DataBlockIndexType, FifoCompactOptions,
@@ -14,9 +19,12 @@
 /// db_options() as the argument to this function and use the return value in
 /// the arguments to open the specific column.
 pub(crate) fn cf_options(ctx: &Context, opts: Options, desc: &Descriptor) -> Result<Options> {
-	let cache = get_cache(ctx, desc);
 	let config = &ctx.server.config;
-	descriptor_cf_options(opts, *desc, config, cache.as_ref())
+	let mut desc = *desc;
+	set_column_options(&mut desc, config)?;
+
+	let cache = get_cache(ctx, &desc);
+	descriptor_cf_options(opts, desc, config, cache.as_ref())
 }
 
 fn descriptor_cf_options(
@@ -143,6 +151,68 @@ fn set_compression(desc: &mut Descriptor, config: &Config) {
 	}
 }
 
+/// Apply the column's options from the config, with those left unset taken
+/// from its preset.
+fn set_column_options(desc: &mut Descriptor, config: &Config) -> Result {
+	let Some(options) = config.rocksdb_column_options.get(desc.name) else {
+		return Ok(());
+	};
+
+	let preset = options.preset.map(preset_options).unwrap_or_default();
+
+	if let Some(share) = options.cache_share.or(preset.cache_share) {
+		let capacity = config.db_cache_capacity_mb * 1024.0 * 1024.0;
+		desc.cache_disp = CacheDisp::Unique;
+		desc.cache_size = usize_from_f64(capacity * share.clamp(0.0, 1.0))?;
+		desc.cache_share = Some(share);
+	}
+
+	if let Some(bits) = options.bloom_bits.or(preset.bloom_bits) {
+		desc.bloom_bits = (bits > 0.0).then_some(bits);
+	}
+
+	if let Some(size) = options.write_buffer_mb.or(preset.write_buffer_mb) {
+		desc.write_size = usize_from_f64(size * 1024.0 * 1024.0)?;
+	}
+
+	let levels = options
+		.compression_per_level
+		.as_ref()
+		.or(preset.compression_per_level.as_ref());
+
+	if let Some(levels) = levels.filter(|levels| !levels.is_empty()) {
+		for (i, shape) in desc.compression_shape.iter_mut().enumerate() {
+			let compressed = levels.get(i).or(levels.last()).copied();
+			*shape = compressed.unwrap_or_default().into();
+		}
+	}
+
+	Ok(())
+}
+
+fn preset_options(preset: RocksDbColumnPreset) -> RocksDbColumnOptions {
+	match preset {
+		| RocksDbColumnPreset::Minimal => RocksDbColumnOptions {
+			bloom_bits: Some(0.0),
+			write_buffer_mb: Some(1.0),
+			compression_per_level: Some(vec![true]),
+			..Default::default()
+		},
+		| RocksDbColumnPreset::Balanced => RocksDbColumnOptions {
+			bloom_bits: Some(10.0),
+			write_buffer_mb: Some(16.0),
+			..Default::default()
+		},
+		| RocksDbColumnPreset::Heavy => RocksDbColumnOptions {
+			cache_share: Some(0.25),
+			bloom_bits: Some(10.0),
+			write_buffer_mb: Some(128.0),
+			compression_per_level: Some(vec![false, false, true]),
+			..Default::default()
+		},
+	}
+}
+
 fn fifo_options(desc: &Descriptor) -> FifoCompactOptions {
 	let mut opts = FifoCompactOptions::default();
 	opts.set_max_table_files_size(desc.limit_size);
@@ -175,6 +245,10 @@ fn table_options(desc: &Descriptor, has_cache: bool) -> BlockBasedOptions {
 	opts.set_unpartitioned_pinning_tier(BlockBasedPinningTier::None);
 	opts.set_top_level_index_pinning_tier(BlockBasedPinningTier::None);
 
+	if let Some(bits) = desc.bloom_bits {
+		opts.set_bloom_filter(bits, false);
+	}
+
 	opts.set_partition_filters(true);
 	opts.set_use_delta_encoding(false);
 	opts.set_index_type(BlockBasedIndexType::TwoLevelIndexSearch);
@@ -217,7 +291,7 @@ fn get_cache(ctx: &Context, desc: &Descriptor) -> Option<Cache> {
 		.expected_add(desc.val_size_hint.unwrap_or_default());
 
 	let size = match cap {
-		| Some(cap) => cache_size(config, cap, ent_size),
+		| Some(cap) if desc.cache_share.is_none() => cache_size(config, cap, ent_size),
 		| _ => desc.cache_size,
 	};
 
@@ -238,12 +312,20 @@ fn get_cache(ctx: &Context, desc: &Descriptor) -> Option<Cache> {
 		| CacheDisp::Unique => {
 			let cache = Cache::new_lru_cache_opts(&cache_opts);
 			caches.insert(desc.name.into(), cache.clone());
+			ctx.col_cache_capacity
+				.lock()
+				.expect("locked")
+				.insert(desc.name.into(), size);
 			Some(cache)
 		},
 
 		| CacheDisp::SharedWith(other) if !caches.contains_key(other) => {
 			let cache = Cache::new_lru_cache_opts(&cache_opts);
 			caches.insert(desc.name.into(), cache.clone());
+			ctx.col_cache_capacity
+				.lock()
+				.expect("locked")
+				.insert(desc.name.into(), size);
 			Some(cache)
 		},
 
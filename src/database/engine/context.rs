@@ -16,6 +16,12 @@ pub(crate) struct Context {
 	pub(crate) pool: Arc<Pool>,
 	pub(crate) col_cache: Mutex<BTreeMap<String, Cache>>,
 	pub(crate) row_cache: Mutex<Cache>,
+
+	/// Configured capacities in bytes of the column caches, which may be
+	/// running shrunk under memory pressure.
+	pub(crate) col_cache_capacity: Mutex<BTreeMap<String, usize>>,
+	pub(crate) row_cache_capacity: usize,
+
 	pub(crate) env: Mutex<Env>,
 	pub(crate) server: Arc<Server>,
 }
@@ -41,6 +47,8 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 		col_cache_opts.set_capacity(col_cache_capacity_bytes);
 		let col_cache = Cache::new_lru_cache_opts(&col_cache_opts);
 		let col_cache: BTreeMap<_, _> = [("Shared".to_owned(), col_cache)].into();
+		let col_cache_capacity: BTreeMap<_, _> =
+			[("Shared".to_owned(), col_cache_capacity_bytes)].into();
 
 		let mut env = Env::new().or_else(or_else)?;
 
@@ -56,6 +64,8 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 			pool: Pool::new(server)?,
 			col_cache: col_cache.into(),
 			row_cache: row_cache.into(),
+			col_cache_capacity: col_cache_capacity.into(),
+			row_cache_capacity: row_cache_capacity_bytes,
 			env: env.into(),
 			server: server.clone(),
 		}))
@@ -39,6 +39,8 @@ pub(crate) struct Descriptor {
 	pub(crate) auto_readahead_thresh: u32,
 	pub(crate) auto_readahead_init: usize,
 	pub(crate) auto_readahead_max: usize,
+	pub(crate) bloom_bits: Option<f64>,
+	pub(crate) cache_share: Option<f64>,
 }
 
 /// Cache Disposition
@@ -81,6 +83,8 @@ pub(crate) enum CacheDisp {
 	auto_readahead_thresh: 0,
 	auto_readahead_init: 1024 * 16,
 	auto_readahead_max: 1024 * 1024 * 2,
+	bloom_bits: None,
+	cache_share: None,
 };
 
 /// Tombstone descriptor for columns which have been or will be deleted.
@@ -1,7 +1,8 @@
 use std::{
 	collections::BTreeSet,
 	path::Path,
-	sync::{Arc, atomic::AtomicU32},
+	sync::{Arc, Mutex, atomic::AtomicU32},
+	time::Duration,
 };
 
 use conduwuit::{Result, debug, implement, info, warn};
@@ -63,12 +64,15 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		secondary: config.rocksdb_secondary,
 		checksums: config.rocksdb_checksums,
 		corks: AtomicU32::new(0),
+		slow_threshold: (config.db_slow_query_ms > 0)
+			.then(|| Duration::from_millis(config.db_slow_query_ms)),
+		slow: Mutex::default(),
 	}))
 }
 
 #[implement(Engine)]
 #[tracing::instrument(name = "configure", skip_all)]
-fn configure_cfds(
+pub(super) fn configure_cfds(
 	ctx: &Arc<Context>,
 	db_opts: &Options,
 	desc: &[Descriptor],
@@ -101,6 +105,14 @@ fn configure_cfds(
 		debug!("Creating new column {name:?} not previously found in existing database.");
 	});
 
+	config
+		.rocksdb_column_options
+		.keys()
+		.filter(|&name| !desc.iter().any(|desc| desc.name == name))
+		.for_each(|name| {
+			warn!("Ignoring rocksdb_column_options for unknown column {name:?}.");
+		});
+
 	let missing_descriptors = missing.clone().map(|_| descriptor::DROPPED);
 
 	let cfopts: Vec<_> = desc
@@ -1,9 +1,40 @@
-use std::path::PathBuf;
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
-use conduwuit::{Err, Result, info, warn};
-use rocksdb::Options;
+use conduwuit::{Err, Result, debug, error, implement, info, warn};
+use rocksdb::{Options, ReadOptions};
 
-use super::Db;
+use super::{Db, Engine, db_opts::db_options, descriptor::Descriptor};
+use crate::{Context, or_else, util::map_err};
+
+/// Directory under the database path receiving quarantined table files.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Outcome of an offline repair.
+#[derive(Debug, Default)]
+pub struct RepairReport {
+	/// Whether the database could be opened and every failure to read it
+	/// attributed to a table file prior to the repair.
+	pub verified: bool,
+
+	/// Table files which failed verification and were moved aside.
+	pub quarantined: Vec<Quarantined>,
+}
+
+/// Table file which failed verification; the keys it held are lost.
+#[derive(Debug)]
+pub struct Quarantined {
+	pub column: String,
+	pub file: String,
+	pub level: i32,
+	pub entries: u64,
+	pub start_key: Option<Vec<u8>>,
+	pub end_key: Option<Vec<u8>>,
+	pub error: String,
+}
 
 pub(crate) fn repair(db_opts: &Options, path: &PathBuf) -> Result {
 	warn!("Starting database repair. This may take a long time...");
@@ -14,3 +45,137 @@ pub(crate) fn repair(db_opts: &Options, path: &PathBuf) -> Result {
 
 	Ok(())
 }
+
+/// Verify every table file of an unopened database, quarantine those which
+/// cannot be read, then have RocksDB rebuild the database from what remains.
+#[implement(Engine)]
+#[tracing::instrument(skip_all)]
+pub(crate) fn repair_offline(ctx: &Arc<Context>, desc: &[Descriptor]) -> Result<RepairReport> {
+	let config = &ctx.server.config;
+	let path = &config.database_path;
+
+	let db_opts = db_options(
+		config,
+		&ctx.env.lock().expect("environment locked"),
+		&ctx.row_cache.lock().expect("row cache locked"),
+	)?;
+
+	let mut report = RepairReport::default();
+	let cfds = Self::configure_cfds(ctx, &db_opts, desc)?;
+	match Db::open_cf_descriptors_read_only(&db_opts, path, cfds, false) {
+		| Ok(db) => {
+			(report.verified, report.quarantined) = verify(&db)?;
+		},
+		| Err(e) => {
+			error!("Database cannot be opened to verify its tables prior to repair: {e}");
+		},
+	}
+
+	quarantine(path, &report.quarantined)?;
+	repair(&db_opts, path)?;
+
+	Ok(report)
+}
+
+/// Read back the key range of every live table file with checksums verified.
+/// Reading a range also reads the other files overlapping it, so a failure is
+/// attributed to the file RocksDB names in its error rather than the file
+/// whose range was read; failures naming no live file leave the tables
+/// unverified and nothing is quarantined for them.
+fn verify(db: &Db) -> Result<(bool, Vec<Quarantined>)> {
+	let files = db.live_files().map_err(map_err)?;
+	info!(files = files.len(), "Verifying database tables...");
+
+	let mut verified = true;
+	let mut failed: Vec<Quarantined> = Vec::new();
+	for file in &files {
+		let Some(cf) = db.cf_handle(&file.column_family_name) else {
+			continue;
+		};
+
+		let mut opts = ReadOptions::default();
+		opts.set_verify_checksums(true);
+		opts.fill_cache(false);
+
+		let mut it = db.raw_iterator_cf_opt(&cf, opts);
+		match file.start_key.as_deref() {
+			| Some(start_key) => it.seek(start_key),
+			| None => it.seek_to_first(),
+		}
+
+		while it.valid() {
+			let past_end = file
+				.end_key
+				.as_deref()
+				.zip(it.key())
+				.is_some_and(|(end_key, key)| key > end_key);
+
+			if past_end {
+				break;
+			}
+
+			it.next();
+		}
+
+		let Err(e) = it.status() else {
+			debug!(column = %file.column_family_name, file = %file.name, "Verified");
+			continue;
+		};
+
+		let error = e.to_string();
+		let Some(culprit) = files.iter().find(|live| names_file(&error, &live.name)) else {
+			error!(
+				column = %file.column_family_name,
+				file = %file.name,
+				"Unreadable key range not attributable to a table file: {error}"
+			);
+			verified = false;
+			continue;
+		};
+
+		let name = culprit.name.trim_start_matches('/');
+		if failed.iter().any(|quarantined| quarantined.file == name) {
+			continue;
+		}
+
+		warn!(column = %culprit.column_family_name, file = %name, "Unreadable: {error}");
+		failed.push(Quarantined {
+			column: culprit.column_family_name.clone(),
+			file: name.to_owned(),
+			level: culprit.level,
+			entries: culprit.num_entries,
+			start_key: culprit.start_key.clone(),
+			end_key: culprit.end_key.clone(),
+			error,
+		});
+	}
+
+	Ok((verified, failed))
+}
+
+/// Whether a RocksDB error message names the table file `name`, which is
+/// given relative to the database directory, e.g. "/000123.sst".
+fn names_file(error: &str, name: &str) -> bool {
+	let name = name.trim_start_matches('/');
+	error.match_indices(name).any(|(at, _)| {
+		let preceding = error[..at].chars().next_back();
+		!preceding.is_some_and(|c| c.is_ascii_alphanumeric())
+	})
+}
+
+/// Move the table files out of the database directory so the repair does not
+/// pick them up again.
+fn quarantine(path: &Path, files: &[Quarantined]) -> Result {
+	if files.is_empty() {
+		return Ok(());
+	}
+
+	let dir = path.join(QUARANTINE_DIR);
+	fs::create_dir_all(&dir)?;
+	for file in files {
+		warn!(column = %file.column, file = %file.file, "Quarantining table file");
+		fs::rename(path.join(&file.file), dir.join(&file.file))?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,52 @@
+//! Logging of database operations taking longer than `db_slow_query_ms`.
+
+use std::time::Instant;
+
+use conduwuit::{implement, warn};
+
+use super::Engine;
+
+/// Log and count the operation on the column if it took longer than the
+/// configured threshold. For reads offloaded to the pool `started` is when a
+/// worker dequeued the operation, excluding time spent waiting in the queue.
+/// The event is emitted in the span of the caller.
+#[implement(Engine)]
+pub(crate) fn check_slow(&self, column: &str, op: &'static str, started: Instant) {
+	let Some(threshold) = self.slow_threshold else {
+		return;
+	};
+
+	let elapsed = started.elapsed();
+	if elapsed < threshold {
+		return;
+	}
+
+	let mut slow = self.slow.lock().expect("locked");
+	let count = slow.entry(column.to_owned()).or_default();
+	*count = count.saturating_add(1);
+	drop(slow);
+
+	warn!(
+		%column,
+		%op,
+		?elapsed,
+		monotonic_counter.db_slow_operations = 1_u64,
+		"Slow database operation"
+	);
+}
+
+/// Number of slow operations on each column since startup, most first.
+#[implement(Engine)]
+#[must_use]
+pub fn slow_operations(&self) -> Vec<(String, u64)> {
+	let mut slow: Vec<_> = self
+		.slow
+		.lock()
+		.expect("locked")
+		.iter()
+		.map(|(column, &count)| (column.clone(), count))
+		.collect();
+
+	slow.sort_by(|a, b| b.1.cmp(&a.1));
+	slow
+}
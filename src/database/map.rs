@@ -19,6 +19,7 @@
 mod rev_stream;
 mod rev_stream_from;
 mod rev_stream_prefix;
+mod size;
 mod stream;
 mod stream_from;
 mod stream_prefix;
@@ -93,6 +94,9 @@ pub(crate) fn db(&self) -> &Arc<Engine> { &self.db }
 
 	#[inline]
 	pub(crate) fn cf(&self) -> impl AsColumnFamilyRef + '_ { &*self.cf }
+
+	#[inline]
+	pub(crate) fn wake(&self, key: &[u8]) { self.watchers.wake(key); }
 }
 
 impl Debug for Map {
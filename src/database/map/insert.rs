@@ -3,7 +3,7 @@
 //! Overloads are provided for the user to choose the most efficient
 //! serialization or bypass for pre=serialized (raw) inputs.
 
-use std::{convert::AsRef, fmt::Debug, io::Write};
+use std::{convert::AsRef, fmt::Debug, io::Write, time::Instant};
 
 use conduwuit::{arrayvec::ArrayVec, implement};
 use rocksdb::WriteBatchWithTransaction;
@@ -198,12 +198,15 @@ pub fn insert<K, V>(&self, key: &K, val: V)
 	V: AsRef<[u8]>,
 {
 	let write_options = &self.write_options;
+	let started = Instant::now();
 	self.db
 		.db
 		.put_cf_opt(&self.cf(), key, val, write_options)
 		.or_else(or_else)
 		.expect("database insert error");
 
+	self.db.check_slow(self.name, "insert", started);
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
@@ -225,12 +228,15 @@ pub fn insert_batch<'a, I, K, V>(&'a self, iter: I)
 	}
 
 	let write_options = &self.write_options;
+	let started = Instant::now();
 	self.db
 		.db
 		.write_opt(batch, write_options)
 		.or_else(or_else)
 		.expect("database insert batch error");
 
+	self.db.check_slow(self.name, "insert_batch", started);
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
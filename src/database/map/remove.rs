@@ -1,4 +1,4 @@
-use std::{convert::AsRef, fmt::Debug, io::Write};
+use std::{convert::AsRef, fmt::Debug, io::Write, time::Instant};
 
 use conduwuit::{arrayvec::ArrayVec, implement};
 use serde::Serialize;
@@ -43,12 +43,15 @@ pub fn remove<K>(&self, key: &K)
 	K: AsRef<[u8]> + ?Sized + Debug,
 {
 	let write_options = &self.write_options;
+	let started = Instant::now();
 	self.db
 		.db
 		.delete_cf_opt(&self.cf(), key, write_options)
 		.or_else(or_else)
 		.expect("database remove error");
 
+	self.db.check_slow(self.name, "remove", started);
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
@@ -0,0 +1,33 @@
+use std::fmt::Debug;
+
+use conduwuit::implement;
+use rocksdb::Range;
+
+/// Estimate the on-disk size of the entries in the map matching a prefix.
+/// Entries still in the write buffer are not accounted for.
+///
+/// - Prefix is raw
+#[implement(super::Map)]
+pub fn raw_approximate_size_prefix<P>(&self, prefix: &P) -> u64
+where
+	P: AsRef<[u8]> + ?Sized + Debug,
+{
+	let start = prefix.as_ref();
+	let mut end = start.to_vec();
+	while end.last() == Some(&u8::MAX) {
+		end.pop();
+	}
+
+	if let Some(last) = end.last_mut() {
+		*last = last.saturating_add(1);
+	} else {
+		end = vec![u8::MAX; start.len().saturating_add(1)];
+	}
+
+	self.db
+		.db
+		.get_approximate_sizes_cf(&self.cf(), &[Range::new(start, end.as_slice())])
+		.first()
+		.copied()
+		.unwrap_or(0)
+}
@@ -7,6 +7,7 @@
 conduwuit::mod_dtor! {}
 conduwuit::rustc_flags_capture! {}
 
+mod batch;
 #[cfg(test)]
 mod benches;
 mod cork;
@@ -25,13 +26,15 @@
 pub(crate) mod util;
 mod watchers;
 
-use std::{ops::Index, sync::Arc};
+use std::{ops::Index, path::Path, sync::Arc};
 
 use conduwuit::{Result, Server, err};
 
 pub use self::{
+	batch::Batch,
 	de::{Ignore, IgnoreAll},
 	deserialized::Deserialized,
+	engine::repair::{Quarantined, RepairReport},
 	handle::Handle,
 	keyval::{KeyVal, Slice, serialize_key, serialize_val},
 	map::{Get, Map, Qry, compact},
@@ -61,6 +64,20 @@ pub async fn open(server: &Arc<Server>) -> Result<Arc<Self>> {
 		}))
 	}
 
+	/// Repair an unopened database, quarantining table files which cannot be
+	/// read and reporting the key ranges lost with them.
+	pub fn repair(server: &Arc<Server>) -> Result<RepairReport> {
+		let ctx = Context::new(server)?;
+		Engine::repair_offline(&ctx, maps::MAPS)
+	}
+
+	/// Restore an online backup into the directory `to`, verifying the backup
+	/// first.
+	pub fn restore(server: &Arc<Server>, backup_id: u32, to: &Path) -> Result {
+		let ctx = Context::new(server)?;
+		Engine::restore(&ctx, backup_id, to)
+	}
+
 	#[inline]
 	pub fn get(&self, name: &str) -> Result<&Arc<Map>> {
 		self.maps
@@ -8,6 +8,7 @@
 	},
 	thread,
 	thread::JoinHandle,
+	time::Instant,
 };
 
 use async_channel::{QueueStrategy, Receiver, RecvError, Sender};
@@ -47,7 +48,7 @@ pub(crate) enum Cmd {
 pub(crate) struct Get {
 	pub(crate) map: Arc<Map>,
 	pub(crate) key: BatchQuery<'static>,
-	pub(crate) res: Option<ResultSender<BatchResult<'static>>>,
+	pub(crate) res: Option<ResultSender<Dequeued<BatchResult<'static>>>>,
 }
 
 /// Iterator-seek.
@@ -58,13 +59,17 @@ pub(crate) struct Seek {
 	pub(crate) state: stream::State<'static>,
 	pub(crate) dir: Direction,
 	pub(crate) key: Option<KeyBuf>,
-	pub(crate) res: Option<ResultSender<stream::State<'static>>>,
+	pub(crate) res: Option<ResultSender<Dequeued<stream::State<'static>>>>,
 }
 
 pub(crate) type BatchQuery<'a> = SmallVec<[KeyBuf; BATCH_INLINE]>;
 pub(crate) type BatchResult<'a> = SmallVec<[ResultHandle<'a>; BATCH_INLINE]>;
 pub(crate) type ResultHandle<'a> = Result<Handle<'a>>;
 
+/// Result sent back by a worker along with when the worker dequeued the
+/// command, so time spent waiting in the queue is not counted as slow.
+pub(crate) type Dequeued<T> = (Instant, T);
+
 const WORKER_LIMIT: (usize, usize) = (1, 1024);
 const QUEUE_LIMIT: (usize, usize) = (1, 4096);
 const BATCH_INLINE: usize = 1;
@@ -197,13 +202,16 @@ pub(crate) async fn execute_get(self: &Arc<Self>, mut cmd: Get) -> Result<BatchR
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let map = cmd.map.clone();
+	let op = if cmd.key.len() > 1 { "get_batch" } else { "get" };
 	let queue = self.select_queue();
-	self.execute(queue, Cmd::Get(cmd))
-		.and_then(move |()| {
-			recv.map_ok(into_recv_get)
-				.map_err(|e| err!(error!("recv failed {e:?}")))
-		})
-		.await
+	let (dequeued, result) = self
+		.execute(queue, Cmd::Get(cmd))
+		.and_then(move |()| recv.map_err(|e| err!(error!("recv failed {e:?}"))))
+		.await?;
+
+	map.db().check_slow(map.name(), op, dequeued);
+	Ok(into_recv_get(result))
 }
 
 #[implement(Pool)]
@@ -212,13 +220,15 @@ pub(crate) async fn execute_iter(self: &Arc<Self>, mut cmd: Seek) -> Result<stre
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let map = cmd.map.clone();
 	let queue = self.select_queue();
-	self.execute(queue, Cmd::Iter(cmd))
-		.and_then(|()| {
-			recv.map_ok(into_recv_seek)
-				.map_err(|e| err!(error!("recv failed {e:?}")))
-		})
-		.await
+	let (dequeued, result) = self
+		.execute(queue, Cmd::Iter(cmd))
+		.and_then(|()| recv.map_err(|e| err!(error!("recv failed {e:?}"))))
+		.await?;
+
+	map.db().check_slow(map.name(), "iter", dequeued);
+	Ok(into_recv_seek(result))
 }
 
 #[implement(Pool)]
@@ -332,10 +342,11 @@ fn worker_wait(self: &Arc<Self>, recv: &Receiver<Cmd>) -> Result<Cmd, RecvError>
 
 #[implement(Pool)]
 fn worker_handle(self: &Arc<Self>, cmd: Cmd) {
+	let dequeued = Instant::now();
 	match cmd {
-		| Cmd::Get(cmd) if cmd.key.len() == 1 => self.handle_get(cmd),
-		| Cmd::Get(cmd) => self.handle_batch(cmd),
-		| Cmd::Iter(cmd) => self.handle_iter(cmd),
+		| Cmd::Get(cmd) if cmd.key.len() == 1 => self.handle_get(cmd, dequeued),
+		| Cmd::Get(cmd) => self.handle_batch(cmd, dequeued),
+		| Cmd::Iter(cmd) => self.handle_iter(cmd, dequeued),
 	}
 }
 
@@ -346,7 +357,7 @@ fn worker_handle(self: &Arc<Self>, cmd: Cmd) {
 	skip_all,
 	fields(%cmd.map),
 )]
-fn handle_iter(&self, mut cmd: Seek) {
+fn handle_iter(&self, mut cmd: Seek, dequeued: Instant) {
 	let chan = cmd.res.take().expect("missing result channel");
 
 	if chan.is_canceled() {
@@ -360,7 +371,7 @@ fn handle_iter(&self, mut cmd: Seek) {
 		| Direction::Reverse => cmd.state.init_rev(from),
 	};
 
-	let chan_result = chan.send(into_send_seek(result));
+	let chan_result = chan.send((dequeued, into_send_seek(result)));
 
 	let _chan_sent = chan_result.is_ok();
 }
@@ -375,7 +386,7 @@ fn handle_iter(&self, mut cmd: Seek) {
 		keys = %cmd.key.len(),
 	),
 )]
-fn handle_batch(self: &Arc<Self>, mut cmd: Get) {
+fn handle_batch(self: &Arc<Self>, mut cmd: Get, dequeued: Instant) {
 	debug_assert!(cmd.key.len() > 1, "should have more than one key");
 	debug_assert!(!cmd.key.iter().any(SmallVec::is_empty), "querying for empty key");
 
@@ -389,7 +400,7 @@ fn handle_batch(self: &Arc<Self>, mut cmd: Get) {
 
 	let result: SmallVec<_> = cmd.map.get_batch_blocking(keys).collect();
 
-	let chan_result = chan.send(into_send_get(result));
+	let chan_result = chan.send((dequeued, into_send_get(result)));
 
 	let _chan_sent = chan_result.is_ok();
 }
@@ -401,7 +412,7 @@ fn handle_batch(self: &Arc<Self>, mut cmd: Get) {
 	skip_all,
 	fields(%cmd.map),
 )]
-fn handle_get(&self, mut cmd: Get) {
+fn handle_get(&self, mut cmd: Get, dequeued: Instant) {
 	debug_assert!(!cmd.key[0].is_empty(), "querying for empty key");
 
 	// Obtain the result channel.
@@ -419,7 +430,7 @@ fn handle_get(&self, mut cmd: Get) {
 	let result = cmd.map.get_blocking(&cmd.key[0]);
 
 	// Send the result back to the submitter.
-	let chan_result = chan.send(into_send_get([result].into()));
+	let chan_result = chan.send((dequeued, into_send_get([result].into())));
 
 	// If the future was dropped during the query this will fail acceptably.
 	let _chan_sent = chan_result.is_ok();
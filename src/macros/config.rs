@@ -78,13 +78,21 @@ fn generate_example(input: &ItemStruct, args: &[Meta], write: bool) -> Result<To
 	}
 
 	let mut summary: Vec<TokenStream2> = Vec::new();
+	let mut changed: Vec<TokenStream2> = Vec::new();
 	if let Fields::Named(FieldsNamed { named, .. }) = &input.fields {
 		for field in named {
 			let Some(ident) = &field.ident else {
 				continue;
 			};
 
-			if ignore.contains(ident.to_string().as_str()) {
+			let name = ident.to_string();
+			changed.push(quote! {
+				if format!("{:?}", self.#ident) != format!("{:?}", other.#ident) {
+					fields.push(#name);
+				}
+			});
+
+			if ignore.contains(name.as_str()) {
 				continue;
 			}
 
@@ -132,14 +140,13 @@ fn generate_example(input: &ItemStruct, args: &[Meta], write: bool) -> Result<To
 
 			if !display_directive("hidden") {
 				let value = if display_directive("sensitive") {
-					quote! { "***********" }
+					quote! { "***********".to_owned() }
 				} else {
-					quote! { format_args!("{:?}", self.#ident) }
+					quote! { format!("{:?}", self.#ident) }
 				};
 
-				let name = ident.to_string();
 				summary.push(quote! {
-					writeln!(out, "| {} | {} |", #name, #value)?;
+					values.push((#name, #value));
 				});
 			}
 		}
@@ -158,10 +165,32 @@ impl std::fmt::Display for #struct_name {
 			fn fmt(&self, out: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 				writeln!(out, "| name | value |")?;
 				writeln!(out, "| :--- | :---  |")?;
-				#( #summary )*
+				for (name, value) in self.values() {
+					writeln!(out, "| {name} | {value} |")?;
+				}
+
 				Ok(())
 			}
 		}
+
+		impl #struct_name {
+			/// Names and values of the displayed fields, with sensitive values
+			/// masked.
+			#[must_use]
+			pub fn values(&self) -> Vec<(&'static str, String)> {
+				let mut values = Vec::new();
+				#( #summary )*
+				values
+			}
+
+			/// Names of the fields whose values differ from those in `other`.
+			#[must_use]
+			pub fn changed_fields(&self, other: &Self) -> Vec<&'static str> {
+				let mut fields = Vec::new();
+				#( #changed )*
+				fields
+			}
+		}
 	};
 
 	Ok(display)
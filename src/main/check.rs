@@ -0,0 +1,388 @@
+//! Offline database consistency checks
+
+use std::{
+	collections::{BTreeSet, HashSet},
+	ffi::OsString,
+	sync::Arc,
+};
+
+use conduwuit_core::{
+	Result, info,
+	matrix::PduEvent,
+	ruma::{
+		OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomAliasId,
+		events::{
+			StateEventType,
+			room::member::{MembershipState, RoomMemberEventContent},
+		},
+	},
+	utils::{self, stream::TryIgnore},
+	warn,
+};
+use conduwuit_service::Services;
+use futures::StreamExt;
+use tokio::fs;
+
+use crate::server::Server;
+
+/// Inconsistencies found and fixed by a check
+#[derive(Default)]
+struct Report {
+	checked: usize,
+	found: usize,
+	fixed: usize,
+}
+
+/// Check the invariants between the tables of the configured database,
+/// fixing what can be fixed when `fix` is set.
+pub(crate) async fn run(server: &Arc<Server>, fix: bool) -> Result {
+	let services = conduwuit_router::start(&server.server).await?;
+	let result = check(&services, fix).await;
+	conduwuit_router::stop(services).await?;
+
+	result
+}
+
+async fn check(services: &Services, fix: bool) -> Result {
+	let rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.metadata
+		.iter_ids()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let reports = [
+		("timeline events", timeline(services, &rooms, fix).await),
+		("memberships", memberships(services, &rooms, fix).await?),
+		("aliases", aliases(services, &rooms, fix).await?),
+		("media", media(services, fix).await?),
+	];
+
+	let mut found: usize = 0;
+	for (name, report) in &reports {
+		info!(
+			check = name,
+			checked = report.checked,
+			found = report.found,
+			fixed = report.fixed
+		);
+		found = found.saturating_add(report.found);
+	}
+
+	if found > 0 && !fix {
+		warn!(found, "Inconsistencies found; run again with --fix to repair them.");
+	}
+
+	Ok(())
+}
+
+/// Every timeline event has a shorteventid and is found by its event ID.
+async fn timeline(services: &Services, rooms: &[OwnedRoomId], fix: bool) -> Report {
+	let mut report = Report::default();
+	for room_id in rooms {
+		let mut pdus = services
+			.rooms
+			.timeline
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.boxed();
+
+		while let Some((_, pdu)) = pdus.next().await {
+			report.checked = report.checked.saturating_add(1);
+			let event_id = &pdu.event_id;
+			if services.rooms.timeline.get_pdu_id(event_id).await.is_err() {
+				warn!(%room_id, %event_id, "Timeline event not found by its event ID");
+				report.found = report.found.saturating_add(1);
+			}
+
+			if services
+				.rooms
+				.short
+				.get_shorteventid(event_id)
+				.await
+				.is_err()
+			{
+				warn!(%room_id, %event_id, "Timeline event has no shorteventid");
+				report.found = report.found.saturating_add(1);
+				if fix {
+					services
+						.rooms
+						.short
+						.get_or_create_shorteventid(event_id)
+						.await;
+					report.fixed = report.fixed.saturating_add(1);
+				}
+			}
+		}
+	}
+
+	report
+}
+
+/// The joined and invited members in the state cache are those of the
+/// current room state.
+async fn memberships(services: &Services, rooms: &[OwnedRoomId], fix: bool) -> Result<Report> {
+	let mut report = Report::default();
+	for room_id in rooms {
+		let members: Vec<PduEvent> = services
+			.rooms
+			.state_accessor
+			.room_state_full(room_id)
+			.ignore_err()
+			.filter_map(|((kind, _), pdu)| async move {
+				(kind == StateEventType::RoomMember).then_some(pdu)
+			})
+			.collect()
+			.await;
+
+		let mut joined_in_state = HashSet::new();
+		let mut changed = false;
+		for pdu in members {
+			report.checked = report.checked.saturating_add(1);
+			let (Some(user_id), Ok(content)) = (
+				pdu.state_key
+					.as_deref()
+					.and_then(|key| OwnedUserId::parse(key).ok()),
+				pdu.get_content::<RoomMemberEventContent>(),
+			) else {
+				continue;
+			};
+
+			let state_cache = &services.rooms.state_cache;
+			let consistent = match content.membership {
+				| MembershipState::Join => state_cache.is_joined(&user_id, room_id).await,
+				| MembershipState::Invite => state_cache.is_invited(&user_id, room_id).await,
+				| _ =>
+					!state_cache.is_joined(&user_id, room_id).await
+						&& !state_cache.is_invited(&user_id, room_id).await,
+			};
+
+			if content.membership == MembershipState::Join {
+				joined_in_state.insert(user_id.clone());
+			}
+
+			if consistent {
+				continue;
+			}
+
+			let membership = &content.membership;
+			warn!(%room_id, %user_id, %membership, "State cache differs from the room state");
+			report.found = report.found.saturating_add(1);
+			if fix {
+				state_cache
+					.update_membership(room_id, &user_id, content, &pdu.sender, None, None, false)
+					.await?;
+
+				changed = true;
+				report.fixed = report.fixed.saturating_add(1);
+			}
+		}
+
+		// Members joined in the state cache without a join in the room state
+		let stale: Vec<OwnedUserId> = services
+			.rooms
+			.state_cache
+			.room_members(room_id)
+			.filter(|user_id| {
+				let stale = !joined_in_state.contains(*user_id);
+				async move { stale }
+			})
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for user_id in stale {
+			warn!(%room_id, %user_id, "Joined in the state cache but not in the room state");
+			report.found = report.found.saturating_add(1);
+			if fix {
+				let content = RoomMemberEventContent::new(MembershipState::Leave);
+				services
+					.rooms
+					.state_cache
+					.update_membership(room_id, &user_id, content, &user_id, None, None, false)
+					.await?;
+
+				changed = true;
+				report.fixed = report.fixed.saturating_add(1);
+			}
+		}
+
+		if changed {
+			services
+				.rooms
+				.state_cache
+				.update_joined_count(room_id)
+				.await;
+		}
+	}
+
+	Ok(report)
+}
+
+/// Local aliases point to known rooms and are listed for them, and the
+/// canonical alias of a room, if local, points to it.
+async fn aliases(services: &Services, rooms: &[OwnedRoomId], fix: bool) -> Result<Report> {
+	let mut report = Report::default();
+	let server_name = services.globals.server_name();
+	let aliases: Vec<(OwnedRoomId, String)> = services
+		.rooms
+		.alias
+		.all_local_aliases()
+		.map(|(room_id, alias)| (room_id.to_owned(), alias.to_owned()))
+		.collect()
+		.await;
+
+	for (room_id, alias) in aliases {
+		report.checked = report.checked.saturating_add(1);
+		let Ok(alias) = RoomAliasId::parse(format!("#{alias}:{server_name}")) else {
+			warn!(%room_id, %alias, "Invalid alias");
+			report.found = report.found.saturating_add(1);
+			continue;
+		};
+
+		if !services.rooms.metadata.exists(&room_id).await {
+			warn!(%room_id, %alias, "Alias points to an unknown room");
+			report.found = report.found.saturating_add(1);
+			if fix {
+				services
+					.rooms
+					.alias
+					.remove_alias(&alias, &services.globals.server_user)
+					.await?;
+
+				report.fixed = report.fixed.saturating_add(1);
+			}
+
+			continue;
+		}
+
+		let listed = services
+			.rooms
+			.alias
+			.local_aliases_for_room(&room_id)
+			.any(|listed| {
+				let listed = listed == &*alias;
+				async move { listed }
+			})
+			.await;
+
+		if !listed {
+			warn!(%room_id, %alias, "Alias is not listed for its room");
+			report.found = report.found.saturating_add(1);
+		}
+	}
+
+	for room_id in rooms {
+		let Ok(alias) = services
+			.rooms
+			.state_accessor
+			.get_canonical_alias(room_id)
+			.await
+		else {
+			continue;
+		};
+
+		if !services.globals.server_is_ours(alias.server_name()) {
+			continue;
+		}
+
+		report.checked = report.checked.saturating_add(1);
+		let resolved = services.rooms.alias.resolve_local_alias(&alias).await;
+		if resolved.as_ref().is_ok_and(|resolved| resolved == room_id) {
+			continue;
+		}
+
+		warn!(%room_id, %alias, ?resolved, "Canonical alias does not point to its room");
+		report.found = report.found.saturating_add(1);
+	}
+
+	Ok(report)
+}
+
+/// Every media record has its file in the media directory, and every file
+/// there has a record.
+async fn media(services: &Services, fix: bool) -> Result<Report> {
+	let mut report = Report::default();
+	let media = &services.media;
+
+	// Files may only be in the bucket media is redirected to
+	let redirected = services.server.config.media_redirect.enable;
+
+	let mut expected: BTreeSet<OsString> = BTreeSet::new();
+	let mut missing: BTreeSet<OwnedMxcUri> = BTreeSet::new();
+	for key in media.get_all_media_keys().await {
+		report.checked = report.checked.saturating_add(1);
+		let path = media.get_media_file(&key);
+		if let Some(name) = path.file_name() {
+			expected.insert(name.to_owned());
+		}
+
+		// Legacy base64 names linking to the file for media_compat_file_link
+		if let Some(name) = media.get_media_file_b64(&key).file_name() {
+			expected.insert(name.to_owned());
+		}
+
+		if redirected || fs::try_exists(&path).await.unwrap_or(true) {
+			continue;
+		}
+
+		let mxc = key
+			.split(|&b| b == 0xFF)
+			.next()
+			.and_then(|mxc| utils::str_from_bytes(mxc).ok())
+			.map(OwnedMxcUri::from);
+
+		warn!(?mxc, ?path, "Media file is missing");
+		report.found = report.found.saturating_add(1);
+		missing.extend(mxc);
+	}
+
+	if fix {
+		for mxc in &missing {
+			let Ok(parts) = mxc.parts() else {
+				continue;
+			};
+
+			media.delete(&parts).await?;
+			report.fixed = report.fixed.saturating_add(1);
+		}
+	}
+
+	let dir = media.get_media_dir();
+	if !fs::try_exists(&dir).await? {
+		return Ok(report);
+	}
+
+	let mut dir = fs::read_dir(dir).await?;
+	while let Some(entry) = dir.next_entry().await? {
+		if expected.contains(&entry.file_name()) {
+			continue;
+		}
+
+		// directories, such as that of quarantined uploads, hold no media files
+		if entry.file_type().await?.is_dir() {
+			continue;
+		}
+
+		let path = entry.path();
+		let links_to_expected = fs::read_link(&path).await.is_ok_and(|target| {
+			target
+				.file_name()
+				.is_some_and(|name| expected.contains(name))
+		});
+
+		if links_to_expected {
+			continue;
+		}
+
+		warn!(?path, "Media file has no record");
+		report.found = report.found.saturating_add(1);
+		if fix {
+			fs::remove_file(&path).await?;
+			report.fixed = report.fixed.saturating_add(1);
+		}
+	}
+
+	Ok(report)
+}
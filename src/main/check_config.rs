@@ -0,0 +1,53 @@
+//! Validate the configuration without starting the server
+
+use std::path::PathBuf;
+
+use conduwuit_core::{
+	Result,
+	config::{Config, Figment},
+	info,
+};
+
+use crate::clap::Args;
+
+/// Load and check the configuration as the server would, logging warnings
+/// for deprecated and unknown keys, then print every option's effective
+/// value and its source.
+pub(crate) fn run(args: &Args) -> Result {
+	let config_paths = args
+		.config
+		.as_deref()
+		.into_iter()
+		.flat_map(<[_]>::iter)
+		.map(PathBuf::as_path);
+
+	let raw = Config::load(config_paths).and_then(|raw| crate::clap::update(raw, args))?;
+	let config = Config::new(&raw)?;
+	let (_reload_handles, _flame_guard, _capture) = crate::logging::init(&config)?;
+	config.check()?;
+
+	for (key, value) in config.effective_values() {
+		println!("{key} = {value}  # {}", source(&raw, &key));
+	}
+
+	info!("Configuration is valid.");
+	Ok(())
+}
+
+/// Where the value of the key was set: a file, the environment or the
+/// command line; otherwise it is the default.
+fn source(raw: &Figment, key: &str) -> String {
+	let Some(metadata) = raw.find_metadata(key) else {
+		return "default".to_owned();
+	};
+
+	if let Some(path) = metadata.source.as_ref().and_then(|source| source.file_path()) {
+		return path.display().to_string();
+	}
+
+	if metadata.name.contains("environment") {
+		return "environment".to_owned();
+	}
+
+	"command line".to_owned()
+}
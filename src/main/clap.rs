@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, Subcommand};
 use conduwuit_core::{
 	Err, Result,
 	config::{Figment, FigmentValue},
@@ -19,6 +19,10 @@
 	version = conduwuit_core::version(),
 )]
 pub(crate) struct Args {
+	/// Run an offline command instead of the server.
+	#[command(subcommand)]
+	pub(crate) command: Option<Command>,
+
 	#[arg(short, long)]
 	/// Path to the config TOML file (optional)
 	pub(crate) config: Option<Vec<PathBuf>>,
@@ -27,6 +31,11 @@ pub(crate) struct Args {
 	#[arg(long, short('O'))]
 	pub(crate) option: Vec<String>,
 
+	/// Validate the configuration and print the effective value of every
+	/// option with where it was set, then exit; nonzero if it is invalid.
+	#[arg(long)]
+	pub(crate) check_config: bool,
+
 	/// Run in a stricter read-only --maintenance mode.
 	#[arg(long)]
 	pub(crate) read_only: bool,
@@ -123,6 +132,85 @@ pub(crate) struct Args {
 	pub(crate) gc_muzzy: Option<bool>,
 }
 
+/// Offline commands
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+	/// Database maintenance; the server must not be running.
+	#[command(subcommand)]
+	Db(DbCommand),
+
+	/// Import another homeserver; the server must not be running.
+	#[command(subcommand)]
+	Import(ImportCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum DbCommand {
+	/// Cross-check the tables of the configured database: timeline events
+	/// against their IDs, the membership cache against room state, aliases
+	/// against rooms and media records against files. Take a backup before
+	/// fixing.
+	Check {
+		/// Fix the inconsistencies found where possible, e.g. by updating the
+		/// membership cache from room state or removing media records whose
+		/// file is missing and files without a record.
+		#[arg(long)]
+		fix: bool,
+	},
+
+	/// Copy every column of the configured database into a new database,
+	/// verifying the copy afterwards.
+	Migrate {
+		/// Path of the new database; must not exist or be empty.
+		#[arg(long)]
+		to_path: PathBuf,
+	},
+
+	/// Verify every table of the configured database, quarantine those which
+	/// cannot be read and rebuild the database from the remainder, reporting
+	/// the key ranges which were lost. Take a backup first.
+	Repair,
+
+	/// Restore an online backup into a new database after verifying it, then
+	/// report the state of the restored database.
+	Restore {
+		/// Number of the backup, as listed by `!admin server list-backups`.
+		#[arg(long)]
+		backup: u32,
+
+		/// Path to restore into; must not exist or be empty. Defaults to
+		/// database_path.
+		#[arg(long)]
+		to: Option<PathBuf>,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum ImportCommand {
+	/// Import users, their profiles, account data and media from a Synapse
+	/// database dump written by `pg_dump` or SQLite's `.dump`, then rejoin
+	/// them to their rooms. server_name must be the one Synapse used.
+	Synapse {
+		/// Path of the dump.
+		#[arg(long)]
+		dump: PathBuf,
+
+		/// Synapse's media_store_path, to import media uploaded by local users.
+		#[arg(long)]
+		media_store: Option<PathBuf>,
+
+		/// Import access tokens so clients stay logged in.
+		#[arg(long)]
+		access_tokens: bool,
+
+		/// Do not rejoin rooms, only list them. Rejoining serves federation
+		/// until done, as remote servers must fetch the server's new signing
+		/// keys.
+		#[arg(long)]
+		skip_rooms: bool,
+	},
+}
+
 /// Parse commandline arguments into structured data
 #[must_use]
 pub(super) fn parse() -> Args { Args::parse() }
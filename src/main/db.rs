@@ -0,0 +1,152 @@
+//! Offline database maintenance commands
+
+use std::{path::Path, sync::Arc};
+
+use conduwuit_core::{Err, Result, config::Config, info, log::Log, warn};
+use conduwuit_database::{Database, Deserialized, RepairReport};
+use futures::StreamExt;
+
+use crate::{check, clap::DbCommand, server::Server};
+
+/// Number of records after which copy progress is logged
+const PROGRESS_INTERVAL: usize = 100_000;
+
+pub(crate) async fn run(server: &Arc<Server>, command: &DbCommand) -> Result {
+	match command {
+		| DbCommand::Check { fix } => check::run(server, *fix).await,
+		| DbCommand::Migrate { to_path } => migrate(server, to_path).await,
+		| DbCommand::Repair => repair(server),
+		| DbCommand::Restore { backup, to } => restore(server, *backup, to.as_deref()).await,
+	}
+}
+
+/// Copy every column of the configured database into a new database at
+/// `to_path`, then compare both record by record.
+async fn migrate(server: &Arc<Server>, to_path: &Path) -> Result {
+	if to_path.exists() && to_path.read_dir()?.next().is_some() {
+		return Err!("Refusing to migrate into {to_path:?} as it is not empty.");
+	}
+
+	info!(?to_path, "Migrating database");
+	let source = Database::open(&server.server).await?;
+	let target = Database::open(&target_server(server, to_path)).await?;
+
+	let mut total: usize = 0;
+	for (name, map) in source.iter() {
+		let target_map = target.get(name)?;
+		let mut copied: usize = 0;
+		let cork = target.cork_and_sync();
+		let mut records = map.raw_stream();
+		while let Some(record) = records.next().await {
+			let (key, val) = record?;
+			target_map.insert(key, val);
+
+			copied = copied.saturating_add(1);
+			if copied % PROGRESS_INTERVAL == 0 {
+				info!(%name, ?copied, "Copying...");
+			}
+		}
+
+		drop(cork);
+		let mismatched = map
+			.raw_stream()
+			.zip(target_map.raw_stream())
+			.filter(|(source, target)| {
+				let equal = matches!((source, target), (Ok(s), Ok(t)) if s == t);
+				futures::future::ready(!equal)
+			})
+			.count()
+			.await;
+
+		let target_count = target_map.raw_keys().count().await;
+		if mismatched > 0 || target_count != copied {
+			return Err!(
+				"Verifying {name} failed: {mismatched} mismatched records, {target_count} of \
+				 {copied} records copied."
+			);
+		}
+
+		info!(%name, ?copied, "Copied and verified");
+		total = total.saturating_add(copied);
+	}
+
+	warn!(
+		?total,
+		?to_path,
+		"Migrated database; point database_path at the new database to use it."
+	);
+
+	Ok(())
+}
+
+/// Repair the configured database and report the key ranges lost.
+fn repair(server: &Arc<Server>) -> Result {
+	let RepairReport { verified, quarantined } = Database::repair(&server.server)?;
+	if !verified {
+		warn!("Tables could not be verified; the repair may have dropped unreported data.");
+	}
+
+	for lost in &quarantined {
+		warn!(
+			column = %lost.column,
+			file = %lost.file,
+			level = lost.level,
+			entries = lost.entries,
+			start = %display_key(lost.start_key.as_deref()),
+			end = %display_key(lost.end_key.as_deref()),
+			error = %lost.error,
+			"Lost key range",
+		);
+	}
+
+	info!(
+		quarantined = quarantined.len(),
+		"Repair complete; quarantined tables were moved to the quarantine directory of the \
+		 database.",
+	);
+
+	Ok(())
+}
+
+/// Restore a backup into `to`, or the configured database path, and report
+/// the state of the restored database.
+async fn restore(server: &Arc<Server>, backup: u32, to: Option<&Path>) -> Result {
+	let to = to.unwrap_or(&server.server.config.database_path);
+	if to.exists() && to.read_dir()?.next().is_some() {
+		return Err!("Refusing to restore into {to:?} as it is not empty; pass --to a new path.");
+	}
+
+	info!(?backup, ?to, "Restoring database backup");
+	Database::restore(&server.server, backup, to)?;
+
+	let restored = Database::open(&target_server(server, to)).await?;
+	let version: u64 = restored["global"]
+		.get(b"version")
+		.await
+		.deserialized()
+		.unwrap_or(0);
+
+	let users = restored["userid_password"].count().await;
+	let rooms = restored["roomid_shortroomid"].count().await;
+	info!(?version, ?users, ?rooms, "Restored database");
+	if to != server.server.config.database_path {
+		warn!(?to, "Point database_path at the restored database to use it.");
+	}
+
+	Ok(())
+}
+
+fn display_key(key: Option<&[u8]>) -> String {
+	key.map_or_else(|| "-".to_owned(), |key| key.escape_ascii().to_string())
+}
+
+/// Server state for the database at `path`, configured like the server's.
+fn target_server(server: &Arc<Server>, path: &Path) -> Arc<conduwuit_core::Server> {
+	let mut config = Config::clone(&server.server.config);
+	config.database_path = path.to_owned();
+
+	Arc::new(conduwuit_core::Server::new(config, server.server.runtime.clone(), Log {
+		reload: server.server.log.reload.clone(),
+		capture: server.server.log.capture.clone(),
+	}))
+}
@@ -0,0 +1,433 @@
+//! Reader for plain SQL dumps as written by `pg_dump` and SQLite's `.dump`
+
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufRead, BufReader},
+	mem::take,
+	path::Path,
+};
+
+use conduwuit_core::{Err, Result};
+
+/// Rows of the tables of interest in a dump.
+#[derive(Default)]
+pub(super) struct Dump {
+	tables: BTreeMap<String, Table>,
+}
+
+#[derive(Default)]
+struct Table {
+	columns: Vec<String>,
+	rows: Vec<Vec<Option<String>>>,
+}
+
+/// Row of a table, addressed by column name.
+pub(super) struct Row<'a> {
+	columns: &'a [String],
+	values: &'a [Option<String>],
+}
+
+impl Dump {
+	/// Read the rows of the `wanted` tables. PostgreSQL dumps are expected in
+	/// the default `COPY` format or with `--inserts`; SQLite dumps carry the
+	/// column names in their `CREATE TABLE` statements.
+	pub(super) fn read(path: &Path, wanted: &[&str]) -> Result<Self> {
+		Self::parse(BufReader::new(File::open(path)?), wanted)
+	}
+
+	pub(super) fn parse(reader: impl BufRead, wanted: &[&str]) -> Result<Self> {
+		let mut dump = Self::default();
+		let mut copying: Option<String> = None;
+		let mut statement = String::new();
+		for line in reader.lines() {
+			let line = line?;
+			if copying.is_some() {
+				if line == "\\." {
+					copying = None;
+				} else if let Some(table) =
+					copying.as_ref().and_then(|name| dump.tables.get_mut(name))
+				{
+					table.rows.push(line.split('\t').map(copy_value).collect());
+				}
+
+				continue;
+			}
+
+			if statement.is_empty() && (line.starts_with("--") || line.trim().is_empty()) {
+				continue;
+			}
+
+			statement.push_str(&line);
+			statement.push('\n');
+			if !is_complete(&statement) {
+				continue;
+			}
+
+			let statement = take(&mut statement);
+			let statement = statement.trim();
+			if let Some((name, columns)) = parse_copy(statement) {
+				if wanted.contains(&name.as_str()) {
+					dump.tables.entry(name.clone()).or_default().columns = columns;
+				}
+
+				copying = Some(name);
+			} else if let Some((name, columns)) = parse_create(statement) {
+				if wanted.contains(&name.as_str()) {
+					dump.tables.entry(name).or_default().columns = columns;
+				}
+			} else if let Some((name, columns, values)) = parse_insert(statement) {
+				if !wanted.contains(&name.as_str()) {
+					continue;
+				}
+
+				let Some(rows) = (Values { rest: values }).tuples() else {
+					return Err!("Malformed INSERT statement for table {name}");
+				};
+
+				let table = dump.tables.entry(name).or_default();
+				if let Some(columns) = columns {
+					table.columns = columns;
+				}
+
+				table.rows.extend(rows);
+			}
+		}
+
+		if copying.is_some() {
+			return Err!("Dump ended within the data of a COPY statement");
+		}
+
+		Ok(dump)
+	}
+
+	pub(super) fn rows(&self, table: &str) -> impl Iterator<Item = Row<'_>> + '_ {
+		self.tables.get(table).into_iter().flat_map(|table| {
+			table
+				.rows
+				.iter()
+				.map(|values| Row { columns: &table.columns, values })
+		})
+	}
+}
+
+impl<'a> Row<'a> {
+	/// Value of a column; None if NULL or if the table has no such column.
+	pub(super) fn get(&self, column: &str) -> Option<&'a str> {
+		self.columns
+			.iter()
+			.position(|name| name == column)
+			.and_then(|i| self.values.get(i))
+			.and_then(Option::as_deref)
+	}
+
+	/// Boolean column as written by either database.
+	pub(super) fn flag(&self, column: &str) -> bool {
+		matches!(self.get(column), Some("t" | "true" | "1"))
+	}
+}
+
+/// Whether the statement is terminated outside of any string literal or
+/// dollar-quoted function body.
+fn is_complete(statement: &str) -> bool {
+	statement.trim_end().ends_with(';')
+		&& statement.matches('\'').count() % 2 == 0
+		&& statement.matches("$$").count() % 2 == 0
+}
+
+/// `COPY public.users (name, password_hash) FROM stdin;`
+fn parse_copy(statement: &str) -> Option<(String, Vec<String>)> {
+	let (name, columns) = statement
+		.strip_prefix("COPY ")?
+		.strip_suffix(" FROM stdin;")?
+		.split_once(" (")?;
+
+	let columns = columns.strip_suffix(')')?.split(',').map(identifier);
+
+	Some((table_name(name), columns.collect()))
+}
+
+/// `CREATE TABLE [IF NOT EXISTS] users (name TEXT, ..., UNIQUE(name));`
+fn parse_create(statement: &str) -> Option<(String, Vec<String>)> {
+	let rest = strip_prefix_ignore_case(statement, "CREATE TABLE ")?;
+	let rest = strip_prefix_ignore_case(rest, "IF NOT EXISTS ").unwrap_or(rest);
+	let (name, body) = rest.split_once('(')?;
+	let (body, _) = body.rsplit_once(')')?;
+
+	let columns = split_top_level(body)
+		.into_iter()
+		.filter_map(|definition| definition.split_whitespace().next())
+		.filter(|first| {
+			!["CONSTRAINT", "PRIMARY", "UNIQUE", "FOREIGN", "CHECK", "EXCLUDE"]
+				.iter()
+				.any(|keyword| first.eq_ignore_ascii_case(keyword))
+		})
+		.map(identifier);
+
+	Some((table_name(name), columns.collect()))
+}
+
+/// `INSERT INTO users [(name, ...)] VALUES (...), (...);`
+fn parse_insert(statement: &str) -> Option<(String, Option<Vec<String>>, &str)> {
+	let rest = strip_prefix_ignore_case(statement, "INSERT INTO ")?;
+	let (head, values) = rest.split_once("VALUES")?;
+	let (name, columns) = match head.split_once('(') {
+		| Some((name, columns)) => {
+			let (columns, _) = columns.rsplit_once(')')?;
+			(name, Some(columns.split(',').map(identifier).collect()))
+		},
+		| None => (head, None),
+	};
+
+	Some((table_name(name), columns, values))
+}
+
+/// Field of a `COPY` row in PostgreSQL's text format.
+fn copy_value(field: &str) -> Option<String> {
+	if field == "\\N" {
+		return None;
+	}
+
+	let mut value = String::with_capacity(field.len());
+	let mut chars = field.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			value.push(c);
+			continue;
+		}
+
+		match chars.next() {
+			| Some('b') => value.push('\u{8}'),
+			| Some('f') => value.push('\u{c}'),
+			| Some('n') => value.push('\n'),
+			| Some('r') => value.push('\r'),
+			| Some('t') => value.push('\t'),
+			| Some('v') => value.push('\u{b}'),
+			| Some(c) => value.push(c),
+			| None => value.push('\\'),
+		}
+	}
+
+	Some(value)
+}
+
+/// Split on commas outside of parentheses and string literals.
+fn split_top_level(body: &str) -> Vec<&str> {
+	let mut parts = Vec::new();
+	let (mut depth, mut quoted, mut start) = (0_usize, false, 0_usize);
+	for (i, c) in body.char_indices() {
+		match c {
+			| '\'' => quoted = !quoted,
+			| '(' if !quoted => depth = depth.saturating_add(1),
+			| ')' if !quoted => depth = depth.saturating_sub(1),
+			| ',' if !quoted && depth == 0 => {
+				let (part, _) = body.split_at(i);
+				let (_, part) = part.split_at(start);
+				parts.push(part);
+				start = i.saturating_add(1);
+			},
+			| _ => {},
+		}
+	}
+
+	let (_, last) = body.split_at(start);
+	parts.push(last);
+	parts
+}
+
+fn table_name(name: &str) -> String {
+	let name = name.trim();
+	identifier(name.rsplit_once('.').map_or(name, |(_, name)| name))
+}
+
+fn identifier(name: &str) -> String { name.trim().trim_matches(['"', '`']).to_owned() }
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+	let (head, rest) = s.split_at_checked(prefix.len())?;
+	head.eq_ignore_ascii_case(prefix).then_some(rest)
+}
+
+/// Parser for the value tuples of an `INSERT` statement.
+struct Values<'a> {
+	rest: &'a str,
+}
+
+impl Values<'_> {
+	fn tuples(&mut self) -> Option<Vec<Vec<Option<String>>>> {
+		let mut rows = Vec::new();
+		while self.eat('(') {
+			rows.push(self.list()?);
+			if !self.eat(',') {
+				break;
+			}
+		}
+
+		Some(rows)
+	}
+
+	/// Comma-separated values up to and including the closing parenthesis.
+	fn list(&mut self) -> Option<Vec<Option<String>>> {
+		let mut values = Vec::new();
+		loop {
+			values.push(self.value()?);
+			if self.eat(')') {
+				return Some(values);
+			}
+
+			if !self.eat(',') {
+				return None;
+			}
+		}
+	}
+
+	fn value(&mut self) -> Option<Option<String>> {
+		self.rest = self.rest.trim_start();
+		if self.rest.starts_with('\'') {
+			return self.string().map(Some);
+		}
+
+		let end = self
+			.rest
+			.find(|c: char| matches!(c, ',' | '(' | ')') || c.is_whitespace())
+			.unwrap_or(self.rest.len());
+
+		let (token, rest) = self.rest.split_at(end);
+		self.rest = rest;
+
+		// SQLite writes strings containing line breaks as
+		// replace('...','\n',char(10)).
+		if self.eat('(') {
+			let args = self.list()?;
+			return match token.to_ascii_lowercase().as_str() {
+				| "replace" => match args.as_slice() {
+					| [Some(s), Some(from), Some(to)] => Some(Some(s.replace(from, to))),
+					| _ => None,
+				},
+				| "char" => args
+					.iter()
+					.map(|code| code.as_deref()?.parse().ok().and_then(char::from_u32))
+					.collect::<Option<String>>()
+					.map(Some),
+				| _ => None,
+			};
+		}
+
+		if token.eq_ignore_ascii_case("NULL") {
+			return Some(None);
+		}
+
+		Some(Some(token.to_owned()))
+	}
+
+	/// String literal with quotes doubled inside.
+	fn string(&mut self) -> Option<String> {
+		let mut value = String::new();
+		let mut rest = self.rest.strip_prefix('\'')?;
+		loop {
+			let (part, after) = rest.split_once('\'')?;
+			value.push_str(part);
+			match after.strip_prefix('\'') {
+				| Some(after) => {
+					value.push('\'');
+					rest = after;
+				},
+				| None => {
+					self.rest = after;
+					return Some(value);
+				},
+			}
+		}
+	}
+
+	fn eat(&mut self, c: char) -> bool {
+		self.rest = self.rest.trim_start();
+		match self.rest.strip_prefix(c) {
+			| Some(rest) => {
+				self.rest = rest;
+				true
+			},
+			| None => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Dump;
+
+	const TABLES: &[&str] = &["users", "profiles"];
+
+	#[test]
+	fn postgres_copy() {
+		let dump = Dump::parse(
+			"-- PostgreSQL database dump\n\nCOPY public.users (name, password_hash, admin) FROM \
+			 stdin;\n@alice:example.com\t$2b$12$hash\tt\n@bob:example.com\t\\N\tf\n\\.\n\nCOPY \
+			 public.events (event_id) FROM stdin;\n$event\n\\.\n"
+				.as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let users: Vec<_> = dump.rows("users").collect();
+		assert_eq!(users.len(), 2);
+		assert_eq!(users[0].get("name"), Some("@alice:example.com"));
+		assert_eq!(users[0].get("password_hash"), Some("$2b$12$hash"));
+		assert!(users[0].flag("admin"));
+		assert_eq!(users[1].get("password_hash"), None);
+		assert!(!users[1].flag("admin"));
+		assert_eq!(dump.rows("events").count(), 0);
+	}
+
+	#[test]
+	fn postgres_copy_escapes() {
+		let dump = Dump::parse(
+			"COPY users (name) FROM stdin;\na\\tb\\\\c\\nd\n\\.\n".as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let name = dump.rows("users").next().and_then(|row| row.get("name"));
+		assert_eq!(name, Some("a\tb\\c\nd"));
+	}
+
+	#[test]
+	fn postgres_unterminated_copy() {
+		assert!(
+			Dump::parse("COPY users (name) FROM stdin;\nalice\n".as_bytes(), TABLES).is_err()
+		);
+	}
+
+	#[test]
+	fn postgres_inserts() {
+		let dump = Dump::parse(
+			"INSERT INTO public.profiles (user_id, displayname) VALUES ('alice', 'Alice''s'), \
+			 ('bob', NULL);\n"
+				.as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let profiles: Vec<_> = dump.rows("profiles").collect();
+		assert_eq!(profiles.len(), 2);
+		assert_eq!(profiles[0].get("displayname"), Some("Alice's"));
+		assert_eq!(profiles[1].get("user_id"), Some("bob"));
+		assert_eq!(profiles[1].get("displayname"), None);
+	}
+
+	#[test]
+	fn sqlite_dump() {
+		let dump = Dump::parse(
+			"CREATE TABLE IF NOT EXISTS \"profiles\"(\n\tuser_id TEXT NOT NULL,\n\tdisplayname \
+			 TEXT,\n\tUNIQUE(user_id)\n);\nINSERT INTO profiles \
+			 VALUES('alice',replace('Line\\none','\\n',char(10)));\n"
+				.as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let profile = dump.rows("profiles").next().expect("row");
+		assert_eq!(profile.get("user_id"), Some("alice"));
+		assert_eq!(profile.get("displayname"), Some("Line\none"));
+		assert_eq!(profile.get("UNIQUE"), None);
+	}
+}
@@ -0,0 +1,127 @@
+//! Import of other homeservers
+
+mod dump;
+mod synapse;
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use conduwuit_api::client::join_room_by_id_helper;
+use conduwuit_core::{Result, debug_warn, info, ruma::OwnedRoomId, warn};
+use conduwuit_service::Services;
+
+use self::{dump::Dump, synapse::Rejoin};
+use crate::{clap::ImportCommand, server::Server};
+
+const REJOIN_REASON: &str = "Migrated from Synapse";
+
+pub(crate) async fn run(server: &Arc<Server>, command: &ImportCommand) -> Result {
+	match command {
+		| ImportCommand::Synapse {
+			dump,
+			media_store,
+			access_tokens,
+			skip_rooms,
+		} => synapse(server, dump, media_store.as_deref(), *access_tokens, *skip_rooms).await,
+	}
+}
+
+async fn synapse(
+	server: &Arc<Server>,
+	dump: &Path,
+	media_store: Option<&Path>,
+	access_tokens: bool,
+	skip_rooms: bool,
+) -> Result {
+	info!(?dump, "Reading Synapse database dump...");
+	let dump = Dump::read(dump, synapse::TABLES)?;
+
+	let services = conduwuit_router::start(&server.server).await?;
+	let result = import_synapse(&services, &dump, media_store, access_tokens, skip_rooms).await;
+	conduwuit_router::stop(services).await?;
+
+	result
+}
+
+async fn import_synapse(
+	services: &Arc<Services>,
+	dump: &Dump,
+	media_store: Option<&Path>,
+	access_tokens: bool,
+	skip_rooms: bool,
+) -> Result {
+	let mut report = synapse::Report::default();
+	let users = synapse::users(services, dump, &mut report).await?;
+	if access_tokens {
+		synapse::devices(services, dump, &users, &mut report).await?;
+	}
+
+	synapse::account_data(services, dump, &users, &mut report).await?;
+	match media_store {
+		| Some(media_store) => synapse::media(services, dump, media_store, &mut report).await?,
+		| None => warn!("No --media-store given; media uploaded to Synapse is not imported."),
+	}
+
+	info!(
+		users = report.users,
+		deactivated = report.deactivated,
+		skipped = report.skipped,
+		devices = report.devices,
+		account_data = report.account_data,
+		media = report.media,
+		media_missing = report.media_missing,
+		"Imported accounts from Synapse",
+	);
+
+	let rooms = synapse::rejoins(services, dump, &users).await;
+	if skip_rooms {
+		for (room_id, rejoin) in &rooms {
+			info!(%room_id, users = ?rejoin.users, servers = ?rejoin.servers, "Room not rejoined");
+		}
+
+		return Ok(());
+	}
+
+	rejoin(services, &rooms).await
+}
+
+/// Rejoin the local users to their rooms through the remote servers in them.
+/// The server listens meanwhile, as the remote servers fetch its new signing
+/// keys to accept the joins.
+async fn rejoin(services: &Arc<Services>, rooms: &BTreeMap<OwnedRoomId, Rejoin>) -> Result {
+	let server = &services.server;
+	let listener = server.runtime().spawn(conduwuit_router::run(services));
+
+	let (mut joined, mut failed, mut lost) = (0_usize, 0_usize, 0_usize);
+	for (room_id, rejoin) in rooms {
+		if rejoin.servers.is_empty() {
+			warn!(%room_id, "No other server is in this room; it cannot be recovered.");
+			lost = lost.saturating_add(1);
+			continue;
+		}
+
+		for user_id in &rejoin.users {
+			match join_room_by_id_helper(
+				services,
+				user_id,
+				room_id,
+				Some(REJOIN_REASON.to_owned()),
+				&rejoin.servers,
+				None,
+				&None,
+			)
+			.await
+			{
+				| Ok(_) => joined = joined.saturating_add(1),
+				| Err(e) => {
+					debug_warn!(%user_id, %room_id, "Failed to rejoin: {e}");
+					failed = failed.saturating_add(1);
+				},
+			}
+		}
+	}
+
+	info!(rooms = rooms.len(), joined, failed, lost, "Rejoined rooms");
+
+	server.shutdown()?;
+	listener.await?
+}
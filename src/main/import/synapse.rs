@@ -0,0 +1,470 @@
+//! Import of the accounts of a Synapse homeserver from a dump of its database
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	path::{Path, PathBuf},
+};
+
+use conduwuit_core::{
+	Err, Result, debug_warn, info,
+	ruma::{Mxc, OwnedDeviceId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, UserId},
+	utils::{self, content_disposition::make_content_disposition},
+	warn,
+};
+use conduwuit_service::Services;
+use serde_json::{Value as JsonValue, json};
+
+use super::dump::Dump;
+
+/// Tables read from the dump
+pub(super) const TABLES: &[&str] = &[
+	"access_tokens",
+	"account_data",
+	"current_state_events",
+	"devices",
+	"local_current_membership",
+	"local_media_repository",
+	"profiles",
+	"room_account_data",
+	"room_memberships",
+	"users",
+];
+
+/// Length of the random password given to accounts without a password hash,
+/// which logged in through SSO.
+const PASSWORD_LENGTH: usize = 32;
+
+#[derive(Default)]
+pub(super) struct Report {
+	pub(super) users: usize,
+	pub(super) deactivated: usize,
+	pub(super) skipped: usize,
+	pub(super) devices: usize,
+	pub(super) account_data: usize,
+	pub(super) media: usize,
+	pub(super) media_missing: usize,
+}
+
+/// Room to rejoin, as its history can only be recovered from the other
+/// servers in it.
+#[derive(Default)]
+pub(super) struct Rejoin {
+	/// Local users who were joined
+	pub(super) users: Vec<OwnedUserId>,
+
+	/// Remote servers with joined members
+	pub(super) servers: Vec<OwnedServerName>,
+}
+
+/// Import users with their password hashes and profiles, granting admin
+/// rights where Synapse did. Returns the users imported.
+pub(super) async fn users(
+	services: &Services,
+	dump: &Dump,
+	report: &mut Report,
+) -> Result<BTreeSet<OwnedUserId>> {
+	let server_name = services.globals.server_name();
+	let mut users = BTreeSet::new();
+	let mut admins = Vec::new();
+	for row in dump.rows("users") {
+		let Some(Ok(user_id)) = row.get("name").map(UserId::parse) else {
+			warn!(name = ?row.get("name"), "Skipping user with an invalid ID");
+			report.skipped = report.skipped.saturating_add(1);
+			continue;
+		};
+
+		if user_id.server_name() != server_name {
+			return Err!(
+				"{user_id} is not a user of {server_name}; server_name must be that of the \
+				 Synapse server."
+			);
+		}
+
+		if row.flag("is_guest")
+			|| row.get("appservice_id").is_some()
+			|| services.users.exists(&user_id).await
+		{
+			debug_warn!(%user_id, "Skipping guest, appservice or existing user");
+			report.skipped = report.skipped.saturating_add(1);
+			continue;
+		}
+
+		match row.get("password_hash").filter(|hash| !hash.is_empty()) {
+			| Some(hash) => {
+				services.users.create(&user_id, None)?;
+				services.users.set_password_hash(&user_id, hash);
+			},
+			| None => {
+				let password = utils::random_string(PASSWORD_LENGTH);
+				services.users.create(&user_id, Some(&password))?;
+			},
+		}
+
+		if row.flag("deactivated") {
+			services.users.deactivate_account(&user_id).await?;
+			report.deactivated = report.deactivated.saturating_add(1);
+		} else if row.flag("admin") {
+			admins.push(user_id.clone());
+		}
+
+		report.users = report.users.saturating_add(1);
+		users.insert(user_id);
+	}
+
+	for row in dump.rows("profiles") {
+		// Older schemas only have the localpart in user_id
+		let user_id = match row.get("full_user_id") {
+			| Some(user_id) => UserId::parse(user_id),
+			| None =>
+				UserId::parse_with_server_name(row.get("user_id").unwrap_or(""), server_name),
+		};
+
+		let Some(user_id) = user_id.ok().filter(|user_id| users.contains(user_id)) else {
+			continue;
+		};
+
+		services
+			.users
+			.set_displayname(&user_id, row.get("displayname").map(ToOwned::to_owned));
+
+		services
+			.users
+			.set_avatar_url(&user_id, row.get("avatar_url").map(Into::into));
+	}
+
+	for user_id in &admins {
+		if let Err(e) = services.admin.make_user_admin(user_id).await {
+			warn!(%user_id, "Failed to grant admin rights: {e}");
+		}
+	}
+
+	Ok(users)
+}
+
+/// Import the latest access token of every device so clients stay logged in.
+pub(super) async fn devices(
+	services: &Services,
+	dump: &Dump,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	let names: BTreeMap<_, _> = dump
+		.rows("devices")
+		.filter(|row| !row.flag("hidden"))
+		.filter_map(|row| Some(((row.get("user_id")?, row.get("device_id")?), row)))
+		.map(|(key, row)| (key, row.get("display_name")))
+		.collect();
+
+	let now = utils::millis_since_unix_epoch();
+	let mut tokens: BTreeMap<(&str, &str), (u64, &str)> = BTreeMap::new();
+	for row in dump.rows("access_tokens") {
+		let expired = row
+			.get("valid_until_ms")
+			.and_then(|ms| ms.parse::<u64>().ok())
+			.is_some_and(|ms| ms <= now);
+
+		if expired || row.get("puppets_user_id").is_some() {
+			continue;
+		}
+
+		let (Some(user), Some(device), Some(token)) =
+			(row.get("user_id"), row.get("device_id"), row.get("token"))
+		else {
+			continue;
+		};
+
+		let id = row.get("id").and_then(|id| id.parse().ok()).unwrap_or(0);
+		tokens
+			.entry((user, device))
+			.and_modify(|latest| {
+				if id > latest.0 {
+					*latest = (id, token);
+				}
+			})
+			.or_insert((id, token));
+	}
+
+	for ((user, device), (_, token)) in tokens {
+		let Some(user_id) = UserId::parse(user)
+			.ok()
+			.filter(|user_id| users.contains(user_id))
+		else {
+			continue;
+		};
+
+		if services
+			.users
+			.is_deactivated(&user_id)
+			.await
+			.unwrap_or(true)
+		{
+			continue;
+		}
+
+		let device_id: OwnedDeviceId = device.into();
+		let display_name = names
+			.get(&(user, device))
+			.copied()
+			.flatten()
+			.map(ToOwned::to_owned);
+
+		services
+			.users
+			.create_device(&user_id, &device_id, token, display_name, None)
+			.await?;
+
+		report.devices = report.devices.saturating_add(1);
+	}
+
+	Ok(())
+}
+
+/// Import global and per-room account data.
+pub(super) async fn account_data(
+	services: &Services,
+	dump: &Dump,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	for row in dump
+		.rows("account_data")
+		.chain(dump.rows("room_account_data"))
+	{
+		let (Some(user), Some(kind), Some(content)) =
+			(row.get("user_id"), row.get("account_data_type"), row.get("content"))
+		else {
+			continue;
+		};
+
+		let Some(user_id) = UserId::parse(user)
+			.ok()
+			.filter(|user_id| users.contains(user_id))
+		else {
+			continue;
+		};
+
+		let room_id = match row.get("room_id").map(RoomId::parse) {
+			| Some(Ok(room_id)) => Some(room_id),
+			| Some(Err(_)) => continue,
+			| None => None,
+		};
+
+		let Ok(content) = serde_json::from_str::<JsonValue>(content) else {
+			warn!(%user_id, ?room_id, kind, "Skipping account data with invalid content");
+			continue;
+		};
+
+		let data = json!({ "type": kind, "content": content });
+		services
+			.account_data
+			.update(room_id.as_deref(), &user_id, kind.into(), &data)
+			.await?;
+
+		report.account_data = report.account_data.saturating_add(1);
+	}
+
+	Ok(())
+}
+
+/// Import the media uploaded by local users from Synapse's media store, under
+/// the same media IDs so existing references stay valid.
+pub(super) async fn media(
+	services: &Services,
+	dump: &Dump,
+	media_store: &Path,
+	report: &mut Report,
+) -> Result {
+	let server_name = services.globals.server_name();
+	for row in dump.rows("local_media_repository") {
+		if row.get("url_cache").is_some() || row.get("quarantined_by").is_some() {
+			continue;
+		}
+
+		let Some(media_id) = row.get("media_id") else {
+			continue;
+		};
+
+		let Some(path) = local_content_path(media_store, media_id) else {
+			continue;
+		};
+
+		let file = match tokio::fs::read(&path).await {
+			| Ok(file) => file,
+			| Err(e) => {
+				warn!(?path, "Skipping unreadable media file: {e}");
+				report.media_missing = report.media_missing.saturating_add(1);
+				continue;
+			},
+		};
+
+		let user_id = row.get("user_id").and_then(|user| UserId::parse(user).ok());
+		let content_type = row.get("media_type");
+		let content_disposition =
+			make_content_disposition(None, content_type, row.get("upload_name"));
+
+		services
+			.media
+			.create(
+				&Mxc { server_name, media_id },
+				user_id.as_deref(),
+				Some(&content_disposition),
+				content_type,
+				&file,
+			)
+			.await?;
+
+		report.media = report.media.saturating_add(1);
+		if report.media % 1000 == 0 {
+			info!(media = report.media, "Importing media...");
+		}
+	}
+
+	Ok(())
+}
+
+/// Rooms the active imported users were joined to, with the remote servers
+/// to rejoin them through.
+pub(super) async fn rejoins(
+	services: &Services,
+	dump: &Dump,
+	users: &BTreeSet<OwnedUserId>,
+) -> BTreeMap<OwnedRoomId, Rejoin> {
+	let server_name = services.globals.server_name();
+	let mut rooms: BTreeMap<OwnedRoomId, Rejoin> = BTreeMap::new();
+	for row in dump
+		.rows("local_current_membership")
+		.filter(|row| row.get("membership") == Some("join"))
+	{
+		let (Some(Ok(room_id)), Some(Ok(user_id))) =
+			(row.get("room_id").map(RoomId::parse), row.get("user_id").map(UserId::parse))
+		else {
+			continue;
+		};
+
+		if users.contains(&user_id)
+			&& !services
+				.users
+				.is_deactivated(&user_id)
+				.await
+				.unwrap_or(true)
+		{
+			rooms.entry(room_id).or_default().users.push(user_id);
+		}
+	}
+
+	for (room_id, user_id) in joined_members(dump) {
+		let Some(rejoin) = rooms.get_mut(&room_id) else {
+			continue;
+		};
+
+		let server = user_id.server_name();
+		if server != server_name && !rejoin.servers.iter().any(|known| known == server) {
+			rejoin.servers.push(server.to_owned());
+		}
+	}
+
+	rooms
+}
+
+/// Members joined to each room according to its current state. Older schemas
+/// lack the membership column of `current_state_events`, which is then looked
+/// up in `room_memberships` by event ID.
+fn joined_members(dump: &Dump) -> impl Iterator<Item = (OwnedRoomId, OwnedUserId)> + '_ {
+	let memberships: BTreeMap<&str, &str> = dump
+		.rows("room_memberships")
+		.filter_map(|row| Some((row.get("event_id")?, row.get("membership")?)))
+		.collect();
+
+	dump.rows("current_state_events")
+		.filter(|row| row.get("type") == Some("m.room.member"))
+		.filter(move |row| {
+			let membership = row.get("membership").or_else(|| {
+				row.get("event_id")
+					.and_then(|event_id| memberships.get(event_id).copied())
+			});
+
+			membership == Some("join")
+		})
+		.filter_map(|row| {
+			let room_id = RoomId::parse(row.get("room_id")?).ok()?;
+			let user_id = UserId::parse(row.get("state_key")?).ok()?;
+
+			Some((room_id, user_id))
+		})
+}
+
+/// Synapse stores the media with ID `abcdefgh` as `local_content/ab/cd/efgh`.
+fn local_content_path(media_store: &Path, media_id: &str) -> Option<PathBuf> {
+	if !media_id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+		return None;
+	}
+
+	let (first, rest) = media_id.split_at_checked(2)?;
+	let (second, rest) = rest.split_at_checked(2)?;
+
+	Some(
+		media_store
+			.join("local_content")
+			.join(first)
+			.join(second)
+			.join(rest),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use super::{Dump, TABLES, joined_members, local_content_path};
+
+	#[test]
+	fn joined_members_current_schema() {
+		let dump = Dump::parse(
+			"COPY current_state_events (event_id, room_id, type, state_key, membership) FROM \
+			 stdin;\n$1\t!room:a.org\tm.room.member\t@alice:a.org\tjoin\n$2\t!room:a.org\tm.\
+			 room.member\t@bob:b.org\tleave\n$3\t!room:a.org\tm.room.member\t@carol:c.org\tban\\
+			 n$4\t!room:a.org\tm.room.name\t\t\\N\n\\.\n"
+				.as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let joined: Vec<_> = joined_members(&dump)
+			.map(|(_, user_id)| user_id.to_string())
+			.collect();
+
+		assert_eq!(joined, ["@alice:a.org"]);
+	}
+
+	#[test]
+	fn joined_members_older_schema() {
+		let dump = Dump::parse(
+			"COPY current_state_events (event_id, room_id, type, state_key) FROM \
+			 stdin;\n$1\t!room:a.org\tm.room.member\t@alice:a.org\n$2\t!room:a.org\tm.room.\
+			 member\t@bob:b.org\n$3\t!room:a.org\tm.room.member\t@carol:c.org\n\\.\nCOPY \
+			 room_memberships (event_id, user_id, membership) FROM \
+			 stdin;\n$1\t@alice:a.org\tjoin\n$2\t@bob:b.org\tinvite\n\\.\n"
+				.as_bytes(),
+			TABLES,
+		)
+		.expect("parsed");
+
+		let joined: Vec<_> = joined_members(&dump)
+			.map(|(_, user_id)| user_id.to_string())
+			.collect();
+
+		assert_eq!(joined, ["@alice:a.org"]);
+	}
+
+	#[test]
+	fn media_store_path() {
+		let store = Path::new("/media_store");
+		assert_eq!(
+			local_content_path(store, "abcdefgh"),
+			Some(store.join("local_content/ab/cd/efgh"))
+		);
+
+		assert_eq!(local_content_path(store, "abc"), None);
+		assert_eq!(local_content_path(store, "ab/../..x"), None);
+	}
+}
@@ -2,12 +2,17 @@
 
 use conduwuit_core::{
 	Result,
-	config::Config,
+	config::{Config, LogFormat},
 	debug_warn, err,
-	log::{ConsoleFormat, ConsoleWriter, LogLevelReloadHandles, capture, fmt_span},
+	log::{ConsoleFormat, ConsoleWriter, JsonFormat, LogLevelReloadHandles, capture, fmt_span},
 	result::UnwrapOrErr,
 };
-use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, reload};
+use tracing_subscriber::{
+	EnvFilter, Layer, Registry,
+	fmt::{self, format::JsonFields},
+	layer::SubscriberExt,
+	reload,
+};
 
 #[cfg(feature = "perf_measurements")]
 pub(crate) type TracingFlameGuard =
@@ -26,11 +31,21 @@ pub(crate) fn init(
 		.with_regex(config.log_filter_regex)
 		.parse(&config.log)
 		.map_err(|e| err!(Config("log", "{e}.")))?;
-	let console_layer = fmt::Layer::new()
-		.with_span_events(console_span_events)
-		.event_format(ConsoleFormat::new(config))
-		.fmt_fields(ConsoleFormat::new(config))
-		.with_writer(ConsoleWriter::new(config));
+	let console_layer: Box<dyn Layer<Registry> + Send + Sync> = match config.log_format {
+		| LogFormat::Console => Box::new(
+			fmt::Layer::new()
+				.with_span_events(console_span_events)
+				.event_format(ConsoleFormat::new(config))
+				.fmt_fields(ConsoleFormat::new(config))
+				.with_writer(ConsoleWriter::new(config)),
+		),
+		| LogFormat::Json => Box::new(
+			fmt::Layer::new()
+				.event_format(JsonFormat)
+				.fmt_fields(JsonFields::new())
+				.with_writer(ConsoleWriter::new(config)),
+		),
+	};
 
 	let (console_reload_filter, console_reload_handle) =
 		reload::Layer::new(console_filter.clone());
@@ -87,7 +102,37 @@ pub(crate) fn init(
 			Some(telemetry.with_filter(jaeger_reload_filter))
 		});
 
-		let subscriber = subscriber.with(flame_layer).with(jaeger_layer);
+		let otlp_filter = EnvFilter::try_new(&config.otlp_filter)
+			.map_err(|e| err!(Config("otlp_filter", "{e}.")))?;
+		let otlp_layer = config
+			.otlp_endpoint
+			.as_deref()
+			.map(|endpoint| -> Result<_> {
+				let tracer = crate::otlp::tracer(config, endpoint)?;
+				let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+				let (otlp_reload_filter, otlp_reload_handle) =
+					reload::Layer::new(otlp_filter.clone());
+				reload_handles.add("otlp", Box::new(otlp_reload_handle));
+				Ok(telemetry.with_filter(otlp_reload_filter))
+			})
+			.transpose()?;
+
+		let metrics_layer = config
+			.otlp_endpoint
+			.as_deref()
+			.filter(|_| config.otlp_metrics_interval > 0)
+			.map(|endpoint| -> Result<_> {
+				let provider = crate::otlp::meter_provider(config, endpoint)?;
+				let metrics = tracing_opentelemetry::MetricsLayer::new(provider);
+				Ok(metrics.with_filter(otlp_filter.clone()))
+			})
+			.transpose()?;
+
+		let subscriber = subscriber
+			.with(flame_layer)
+			.with(jaeger_layer)
+			.with(otlp_layer)
+			.with(metrics_layer);
 		(subscriber, flame_guard)
 	};
 
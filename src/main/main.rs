@@ -1,8 +1,14 @@
 #![type_length_limit = "49152"] //TODO: reduce me
 
+mod check;
+mod check_config;
 pub(crate) mod clap;
+mod db;
+mod import;
 mod logging;
 mod mods;
+#[cfg(feature = "perf_measurements")]
+mod otlp;
 mod restart;
 mod runtime;
 mod sentry;
@@ -19,8 +25,25 @@
 fn main() -> Result {
 	let args = clap::parse();
 	let runtime = runtime::new(&args)?;
+	if args.check_config {
+		let _runtime_guard = runtime.enter();
+		return check_config::run(&args);
+	}
+
 	let server = Server::new(&args, Some(runtime.handle()))?;
 
+	if let Some(command) = &args.command {
+		let result = runtime.block_on(async {
+			match command {
+				| clap::Command::Db(command) => db::run(&server, command).await,
+				| clap::Command::Import(command) => import::run(&server, command).await,
+			}
+		});
+
+		runtime::shutdown(&server, runtime);
+		return result;
+	}
+
 	runtime.spawn(signal::signal(server.clone()));
 	runtime.block_on(async_main(&server))?;
 	runtime::shutdown(&server, runtime);
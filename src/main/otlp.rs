@@ -0,0 +1,136 @@
+//! Export of traces and metrics to an OpenTelemetry collector over OTLP
+
+use std::time::Duration;
+
+use conduwuit_core::{
+	Result,
+	config::{Config, OtlpProtocol},
+	err,
+};
+use opentelemetry::{
+	Context, KeyValue,
+	trace::{Link, SamplingResult, SpanKind, TraceId},
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+	Resource,
+	metrics::MeterProvider,
+	propagation::TraceContextPropagator,
+	runtime,
+	trace::{Sampler, ShouldSample, Tracer},
+};
+
+/// Span attribute holding the module the span was recorded in, which is its
+/// target unless one was given explicitly.
+const TARGET: &str = "code.namespace";
+
+/// Samples each trace at the ratio configured for the target of the span
+/// starting it.
+#[derive(Clone, Debug)]
+struct TargetSampler {
+	/// Longest prefix first
+	targets: Vec<(String, Sampler)>,
+	default: Sampler,
+}
+
+/// Traces are joined across servers and proxies by the W3C `traceparent`
+/// header, which takes the place of the jaeger propagator if both are enabled.
+pub(crate) fn tracer(config: &Config, endpoint: &str) -> Result<Tracer> {
+	opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+	let sampler = Sampler::ParentBased(Box::new(TargetSampler::new(config)));
+	let trace_config = opentelemetry_sdk::trace::config()
+		.with_sampler(sampler)
+		.with_resource(resource());
+
+	let pipeline = opentelemetry_otlp::new_pipeline()
+		.tracing()
+		.with_trace_config(trace_config);
+
+	let pipeline = match config.otlp_protocol {
+		| OtlpProtocol::Grpc => pipeline.with_exporter(
+			opentelemetry_otlp::new_exporter()
+				.tonic()
+				.with_endpoint(endpoint),
+		),
+		| OtlpProtocol::Http => pipeline.with_exporter(
+			opentelemetry_otlp::new_exporter()
+				.http()
+				.with_endpoint(endpoint),
+		),
+	};
+
+	pipeline
+		.install_batch(runtime::Tokio)
+		.map_err(|e| err!(Config("otlp_endpoint", "{e}.")))
+}
+
+pub(crate) fn meter_provider(config: &Config, endpoint: &str) -> Result<MeterProvider> {
+	let pipeline = opentelemetry_otlp::new_pipeline()
+		.metrics(runtime::Tokio)
+		.with_period(Duration::from_secs(config.otlp_metrics_interval))
+		.with_resource(resource());
+
+	let pipeline = match config.otlp_protocol {
+		| OtlpProtocol::Grpc => pipeline.with_exporter(
+			opentelemetry_otlp::new_exporter()
+				.tonic()
+				.with_endpoint(endpoint),
+		),
+		| OtlpProtocol::Http => pipeline.with_exporter(
+			opentelemetry_otlp::new_exporter()
+				.http()
+				.with_endpoint(endpoint),
+		),
+	};
+
+	pipeline
+		.build()
+		.map_err(|e| err!(Config("otlp_endpoint", "{e}.")))
+}
+
+fn resource() -> Resource { Resource::new([KeyValue::new("service.name", "conduwuit")]) }
+
+impl TargetSampler {
+	fn new(config: &Config) -> Self {
+		let mut targets: Vec<_> = config
+			.otlp_sample_targets
+			.iter()
+			.map(|(target, &ratio)| (target.clone(), Sampler::TraceIdRatioBased(ratio)))
+			.collect();
+
+		targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+
+		Self {
+			targets,
+			default: Sampler::TraceIdRatioBased(config.otlp_sample_ratio),
+		}
+	}
+}
+
+impl ShouldSample for TargetSampler {
+	fn should_sample(
+		&self,
+		parent_context: Option<&Context>,
+		trace_id: TraceId,
+		name: &str,
+		span_kind: &SpanKind,
+		attributes: &[KeyValue],
+		links: &[Link],
+	) -> SamplingResult {
+		let target = attributes
+			.iter()
+			.find(|attribute| attribute.key.as_str() == TARGET)
+			.map(|attribute| attribute.value.as_str());
+
+		let sampler = target
+			.and_then(|target| {
+				self.targets
+					.iter()
+					.find(|(prefix, _)| target.starts_with(prefix.as_str()))
+			})
+			.map_or(&self.default, |(_, sampler)| sampler);
+
+		sampler.should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+	}
+}
@@ -14,6 +14,7 @@ pub(super) async fn signal(server: Arc<Server>) {
 	const CONSOLE: bool = cfg!(feature = "console");
 	const RELOADING: bool = cfg!(all(conduwuit_mods, feature = "conduwuit_mods", not(CONSOLE)));
 
+	let mut hup = unix::signal(SignalKind::hangup()).expect("SIGHUP handler");
 	let mut quit = unix::signal(SignalKind::quit()).expect("SIGQUIT handler");
 	let mut term = unix::signal(SignalKind::terminate()).expect("SIGTERM handler");
 	let mut usr1 = unix::signal(SignalKind::user_defined1()).expect("SIGUSR1 handler");
@@ -23,6 +24,7 @@ pub(super) async fn signal(server: Arc<Server>) {
 		let sig: &'static str;
 		tokio::select! {
 			_ = signal::ctrl_c() => { sig = "SIGINT"; },
+			_ = hup.recv() => { sig = "SIGHUP"; },
 			_ = quit.recv() => { sig = "SIGQUIT"; },
 			_ = term.recv() => { sig = "SIGTERM"; },
 			_ = usr1.recv() => { sig = "SIGUSR1"; },
@@ -5,14 +5,14 @@
 	extract::{DefaultBodyLimit, MatchedPath},
 };
 use axum_client_ip::SecureClientIpSource;
-use conduwuit::{Result, Server, debug, error};
+use conduwuit::{Result, Server, debug, err, error, log::propagate};
 use conduwuit_api::router::state::Guard;
 use conduwuit_service::Services;
 use http::{
 	HeaderValue, Method, StatusCode,
 	header::{self, HeaderName},
 };
-use tower::ServiceBuilder;
+use tower::{ServiceBuilder, util::MapRequestLayer};
 use tower_http::{
 	catch_panic::CatchPanicLayer,
 	cors::{self, CorsLayer},
@@ -37,6 +37,15 @@
 
 pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 	let server = &services.server;
+	let client_ip_source: SecureClientIpSource =
+		server.config.client_ip_source.parse().map_err(|_| {
+			err!(Config(
+				"client_ip_source",
+				"Unknown client IP source {:?}.",
+				server.config.client_ip_source
+			))
+		})?;
+
 	let layers = ServiceBuilder::new();
 
 	#[cfg(feature = "sentry_telemetry")]
@@ -50,8 +59,10 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 	let layers = layers.layer(compression_layer(server));
 
 	let services_ = services.clone();
+	let server_ = server.clone();
 	let layers = layers
 		.layer(SetSensitiveHeadersLayer::new([header::AUTHORIZATION]))
+		.layer(MapRequestLayer::new(request::with_request_id::<axum::body::Body>))
 		.layer(
 			TraceLayer::new_for_http()
 				.make_span_with(tracing_span::<_>)
@@ -59,8 +70,9 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 				.on_request(DefaultOnRequest::new().level(Level::TRACE))
 				.on_response(DefaultOnResponse::new().level(Level::DEBUG)),
 		)
+		.layer(MapRequestLayer::new(request::with_span::<axum::body::Body>))
 		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::handle))
-		.layer(SecureClientIpSource::ConnectInfo.into_extension())
+		.layer(client_ip_source.into_extension())
 		.layer(ResponseBodyTimeoutLayer::new(Duration::from_secs(
 			server.config.client_response_timeout,
 		)))
@@ -93,7 +105,8 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 			HeaderValue::from_str(&CONDUWUIT_CSP.join(";"))?,
 		))
 		.layer(cors_layer(server))
-		.layer(body_limit_layer(server))
+		.layer(DefaultBodyLimit::disable())
+		.layer(MapRequestLayer::new(move |req| request::with_body_limit(&server_, req)))
 		.layer(CatchPanicLayer::custom(move |panic| catch_panic(panic, services_.clone())));
 
 	let (router, guard) = router::build(services);
@@ -149,25 +162,29 @@ fn cors_layer(_server: &Server) -> CorsLayer {
 		Method::OPTIONS,
 	];
 
-	let headers: [HeaderName; 5] = [
+	let headers: [HeaderName; 7] = [
 		header::ORIGIN,
 		HeaderName::from_lowercase(b"x-requested-with").unwrap(),
 		header::CONTENT_TYPE,
 		header::ACCEPT,
 		header::AUTHORIZATION,
+		header::IF_MATCH,
+		header::IF_NONE_MATCH,
 	];
 
+	// entity tags of rendezvous sessions (MSC4108), and the ID to quote when
+	// reporting a failed request
+	let expose_headers: [HeaderName; 2] =
+		[header::ETAG, HeaderName::from_static(request::X_REQUEST_ID)];
+
 	CorsLayer::new()
 		.allow_origin(cors::Any)
 		.allow_methods(METHODS)
 		.allow_headers(headers)
+		.expose_headers(expose_headers)
 		.max_age(Duration::from_secs(86400))
 }
 
-fn body_limit_layer(server: &Server) -> DefaultBodyLimit {
-	DefaultBodyLimit::max(server.config.max_request_size)
-}
-
 #[tracing::instrument(name = "panic", level = "error", skip_all)]
 #[allow(clippy::needless_pass_by_value)]
 fn catch_panic(
@@ -208,13 +225,24 @@ fn tracing_span<T>(request: &http::Request<T>) -> tracing::Span {
 		.get::<MatchedPath>()
 		.map_or_else(|| request_path_str(request), truncated_matched_path);
 
-	tracing::span! {
+	let request_id = request
+		.headers()
+		.get(request::X_REQUEST_ID)
+		.and_then(|id| id.to_str().ok())
+		.unwrap_or_default();
+
+	let span = tracing::span! {
 		parent: None,
 		debug::INFO_SPAN_LEVEL,
 		"router",
 		method = %request.method(),
 		%path,
-	}
+		%request_id,
+		user = tracing::field::Empty,
+	};
+
+	propagate::extract(&span, request.headers());
+	span
 }
 
 fn request_path_str<T>(request: &http::Request<T>) -> &str {
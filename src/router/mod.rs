@@ -2,6 +2,7 @@
 
 mod layers;
 mod request;
+mod roles;
 mod router;
 mod run;
 mod serve;
@@ -1,20 +1,49 @@
 use std::{
 	fmt::Debug,
 	sync::{Arc, atomic::Ordering},
-	time::Duration,
+	time::{Duration, Instant},
 };
 
 use axum::{
-	extract::State,
+	body::{Body, HttpBody},
+	extract::{MatchedPath, State},
 	response::{IntoResponse, Response},
 };
-use conduwuit::{Result, debug, debug_error, debug_warn, err, error, trace};
+use conduwuit::{Result, Server, debug, debug_error, debug_warn, err, error, trace, utils};
 use conduwuit_service::Services;
 use futures::FutureExt;
-use http::{Method, StatusCode, Uri};
+use http::{HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use http_body_util::Limited;
+use serde_json::Value;
 use tokio::time::sleep;
 use tracing::Span;
 
+/// Header of the ID of a request, which is logged with everything done for
+/// it and returned in the response.
+pub(crate) const X_REQUEST_ID: &str = "x-request-id";
+const REQUEST_ID_MAX: usize = 64;
+const REQUEST_ID_LEN: usize = 16;
+
+/// Error bodies larger than this are passed on without the request ID.
+const ERROR_BODY_MAX: u64 = 65_536;
+
+/// Limit the size of the request body to `max_request_size` as configured when
+/// the request arrives, so a reloaded limit applies to the next request.
+pub(crate) fn with_body_limit(
+	server: &Server,
+	req: http::Request<axum::body::Body>,
+) -> http::Request<axum::body::Body> {
+	let limit = server.config.max_request_size;
+	req.map(|body| Body::new(Limited::new(body, limit)))
+}
+
+/// Make the span of the request available to its handler, which records on
+/// it what only the handler learns, such as the authenticated user.
+pub(crate) fn with_span<B>(mut req: http::Request<B>) -> http::Request<B> {
+	req.extensions_mut().insert(Span::current());
+	req
+}
+
 #[tracing::instrument(name = "request", level = "debug", skip_all)]
 pub(crate) async fn handle(
 	State(services): State<Arc<Services>>,
@@ -31,11 +60,18 @@ pub(crate) async fn handle(
 		return Err(StatusCode::SERVICE_UNAVAILABLE);
 	}
 
+	let started = Instant::now();
+	let endpoint = req
+		.extensions()
+		.get::<MatchedPath>()
+		.map(|path| path.as_str().to_owned());
+
 	let uri = req.uri().clone();
 	let method = req.method().clone();
+	let request_id = req.headers().get(X_REQUEST_ID).cloned();
 	let services_ = services.clone();
 	let parent = Span::current();
-	let task = services.server.runtime().spawn(async move {
+	let task = services.server.spawn("router:request", async move {
 		tokio::select! {
 			response = execute(&services_, req, next, &parent) => response,
 			response = services_.server.until_shutdown()
@@ -49,9 +85,99 @@ pub(crate) async fn handle(
 		}
 	});
 
-	task.await
+	let result = task
+		.await
 		.map_err(unhandled)
-		.and_then(move |result| handle_result(&method, &uri, result))
+		.and_then(|result| handle_result(&method, &uri, result));
+
+	if let Some(endpoint) = endpoint {
+		let status = result
+			.as_ref()
+			.map_or_else(|status| *status, Response::status);
+
+		services.server.metrics.requests.record(
+			method.as_str(),
+			&endpoint,
+			status.as_u16(),
+			started.elapsed(),
+		);
+	}
+
+	let Some(request_id) = request_id else {
+		return result;
+	};
+
+	let response = result.unwrap_or_else(IntoResponse::into_response);
+	Ok(set_request_id(response, request_id).await)
+}
+
+/// Give the request an ID unless a valid one was given by a reverse proxy,
+/// which is kept to correlate their logs with ours.
+pub(crate) fn with_request_id<B>(mut req: http::Request<B>) -> http::Request<B> {
+	let valid = req
+		.headers()
+		.get(X_REQUEST_ID)
+		.is_some_and(|id| is_valid_request_id(id.as_bytes()));
+
+	if !valid {
+		let request_id = utils::rand::string(REQUEST_ID_LEN);
+		let request_id = HeaderValue::from_str(&request_id).expect("alphanumeric request ID");
+		req.headers_mut()
+			.insert(HeaderName::from_static(X_REQUEST_ID), request_id);
+	}
+
+	req
+}
+
+/// A request ID given by the client is only kept if it is short and made of
+/// characters safe to log and to echo back, such as a UUID.
+fn is_valid_request_id(id: &[u8]) -> bool {
+	!id.is_empty()
+		&& id.len() <= REQUEST_ID_MAX
+		&& id
+			.iter()
+			.all(|&c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.'))
+}
+
+/// Return the request ID in a header of the response and, for a JSON error
+/// response, in the `request_id` field of its body, for the user to quote
+/// when reporting the error.
+async fn set_request_id(response: Response, request_id: HeaderValue) -> Response {
+	let is_json_error = (response.status().is_client_error()
+		|| response.status().is_server_error())
+		&& response
+			.headers()
+			.get(header::CONTENT_TYPE)
+			.is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"))
+		&& HttpBody::size_hint(response.body())
+			.upper()
+			.is_some_and(|len| len <= ERROR_BODY_MAX);
+
+	let mut response = if is_json_error {
+		let (mut parts, body) = response.into_parts();
+		let body = axum::body::to_bytes(body, usize::MAX)
+			.await
+			.unwrap_or_default();
+
+		let body = match serde_json::from_slice::<serde_json::Map<String, Value>>(&body) {
+			| Ok(mut object) => {
+				let id = request_id.to_str().unwrap_or_default();
+				object.insert("request_id".to_owned(), id.into());
+				serde_json::to_vec(&object).map_or(body, Into::into)
+			},
+			| Err(_) => body,
+		};
+
+		parts.headers.remove(header::CONTENT_LENGTH);
+		Response::from_parts(parts, Body::from(body))
+	} else {
+		response
+	};
+
+	response
+		.headers_mut()
+		.insert(HeaderName::from_static(X_REQUEST_ID), request_id);
+	response
 }
 
 #[tracing::instrument(
@@ -0,0 +1,46 @@
+//! Restriction of listeners to the APIs of their roles.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use axum::{
+	extract::{Request, State},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use conduwuit::config::ListenerRole::{self, Client, Federation, Metrics};
+
+use crate::router::not_found;
+
+/// Respond as if the route did not exist unless the request is for an API of
+/// one of the listener's roles.
+pub(crate) async fn restrict(
+	State(roles): State<Arc<BTreeSet<ListenerRole>>>,
+	req: Request,
+	next: Next,
+) -> Response {
+	if path_roles(req.uri().path())
+		.iter()
+		.any(|role| roles.contains(role))
+	{
+		return next.run(req).await;
+	}
+
+	not_found(req.uri().clone()).await.into_response()
+}
+
+/// Roles whose listeners serve the path. Media is served to both clients and
+/// servers, which fetch it from the legacy endpoints too.
+fn path_roles(path: &str) -> &'static [ListenerRole] {
+	if path.starts_with("/_matrix/federation/")
+		|| path.starts_with("/_matrix/key/")
+		|| path == "/.well-known/matrix/server"
+	{
+		&[Federation]
+	} else if path.starts_with("/_matrix/media/") {
+		&[Client, Federation]
+	} else if path.starts_with("/_conduwuit/health/") {
+		&[Metrics]
+	} else {
+		&[Client]
+	}
+}
@@ -18,7 +18,7 @@ pub(crate) fn build(services: &Arc<Services>) -> (Router, Guard) {
 	(router, guard)
 }
 
-async fn not_found(_uri: Uri) -> impl IntoResponse {
+pub(crate) async fn not_found(_uri: Uri) -> impl IntoResponse {
 	Error::Request(ErrorKind::Unrecognized, "Not Found".into(), StatusCode::NOT_FOUND)
 }
 
@@ -18,6 +18,9 @@
 
 use crate::serve;
 
+/// Time to send the responses of requests cut off by the drain timeout.
+const RESPONSE_GRACE: Duration = Duration::from_secs(2);
+
 /// Main loop base
 #[tracing::instrument(skip_all)]
 pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
@@ -30,14 +33,16 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 	// Setup shutdown/signal handling
 	let handle = ServerHandle::new();
 	let (tx, _) = broadcast::channel::<()>(1);
-	let sigs = server
-		.runtime()
-		.spawn(signal(server.clone(), tx.clone(), handle.clone()));
+	let sigs = server.spawn("router:signal", signal(server.clone(), tx.clone(), handle.clone()));
+	let mut listener = server
+		.spawn("router:serve", serve::serve(services.clone(), handle.clone(), tx.subscribe()));
+
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	let watchdog = server.spawn("router:watchdog", watchdog());
 
-	let mut listener =
-		server
-			.runtime()
-			.spawn(serve::serve(services.clone(), handle.clone(), tx.subscribe()));
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	sd_notify::notify(false, &[sd_notify::NotifyState::Ready])
+		.expect("failed to notify systemd of ready state");
 
 	// Focal point
 	debug!("Running");
@@ -50,6 +55,9 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 	sigs.abort();
 	_ = sigs.await;
 
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	watchdog.abort();
+
 	// Remove the admin room callback
 	admin::fini(&services.admin).await;
 
@@ -64,10 +72,6 @@ pub(crate) async fn start(server: Arc<Server>) -> Result<Arc<Services>> {
 
 	let services = Services::build(server).await?.start().await?;
 
-	#[cfg(all(feature = "systemd", target_os = "linux"))]
-	sd_notify::notify(true, &[sd_notify::NotifyState::Ready])
-		.expect("failed to notify systemd of ready state");
-
 	debug!("Started");
 	Ok(services)
 }
@@ -78,7 +82,7 @@ pub(crate) async fn stop(services: Arc<Services>) -> Result<()> {
 	debug!("Shutting down...");
 
 	#[cfg(all(feature = "systemd", target_os = "linux"))]
-	sd_notify::notify(true, &[sd_notify::NotifyState::Stopping])
+	sd_notify::notify(false, &[sd_notify::NotifyState::Stopping])
 		.expect("failed to notify systemd of stopping state");
 
 	// Wait for all completions before dropping or we'll lose them to the module
@@ -108,6 +112,26 @@ pub(crate) async fn stop(services: Arc<Services>) -> Result<()> {
 	Ok(())
 }
 
+/// Keep the systemd watchdog from restarting the server while the runtime is
+/// responsive, pinging it twice per `WatchdogSec`.
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+async fn watchdog() {
+	let mut usec = 0;
+	if !sd_notify::watchdog_enabled(false, &mut usec) {
+		return;
+	}
+
+	let period = Duration::from_micros(usec.saturating_div(2).max(1));
+	debug!(?period, "Pinging the systemd watchdog");
+	let mut interval = tokio::time::interval(period);
+	loop {
+		interval.tick().await;
+		if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+			debug_error!("Failed to ping the systemd watchdog: {e}");
+		}
+	}
+}
+
 #[tracing::instrument(skip_all)]
 async fn signal(server: Arc<Server>, tx: Sender<()>, handle: axum_server::Handle) {
 	server
@@ -124,13 +148,15 @@ async fn handle_shutdown(server: Arc<Server>, tx: Sender<()>, handle: axum_serve
 
 	let timeout = server.config.client_shutdown_timeout;
 	let timeout = Duration::from_secs(timeout);
-	debug!(
+	info!(
 		?timeout,
 		handle_active = ?server.metrics.requests_handle_active.load(Ordering::Relaxed),
-		"Notifying for graceful shutdown"
+		"Draining requests in progress"
 	);
 
-	handle.graceful_shutdown(Some(timeout));
+	// Requests still running at the timeout are answered with 503; leave time to
+	// send those answers before the connections are closed.
+	handle.graceful_shutdown(Some(timeout.saturating_add(RESPONSE_GRACE)));
 }
 
 async fn handle_services_poll(
@@ -0,0 +1,86 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::Router;
+use axum_server::Handle as ServerHandle;
+use conduwuit::{Result, Server};
+use futures::StreamExt;
+use rustls_acme::{AcmeConfig, axum::AxumAcceptor, caches::DirCache};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info};
+
+/// Start obtaining and renewing the certificate, returning the acceptor for
+/// the TCP listeners to serve TLS with it. The acceptor also answers the
+/// provider's "tls-alpn-01" challenges.
+pub(super) fn start(server: &Arc<Server>) -> Result<AxumAcceptor> {
+	let acme = &server.config.acme;
+	let domains: Vec<String> = std::iter::once(server.name.host().to_owned())
+		.chain(acme.alt_names.iter().cloned())
+		.collect();
+
+	let cache_dir = acme
+		.cache_dir
+		.clone()
+		.unwrap_or_else(|| server.config.database_path.join("acme"));
+
+	// see tls.rs; fails only when already installed.
+	_ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+	let mut state = AcmeConfig::new(&domains)
+		.contact(
+			acme.contact
+				.iter()
+				.map(|contact| format!("mailto:{contact}")),
+		)
+		.cache(DirCache::new(cache_dir.clone()))
+		.directory(&acme.directory)
+		.state();
+
+	let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+	info!(
+		?domains,
+		directory = %acme.directory,
+		cache = ?cache_dir,
+		"Obtaining TLS certificate with ACME",
+	);
+
+	let server_ = server.clone();
+	server.spawn("router:acme", async move {
+		loop {
+			tokio::select! {
+				() = server_.until_shutdown() => break,
+				event = state.next() => match event {
+					| Some(Ok(event)) => debug!("ACME: {event:?}"),
+					| Some(Err(e)) => error!("ACME: {e}"),
+					| None => break,
+				},
+			}
+		}
+	});
+
+	Ok(acceptor)
+}
+
+pub(super) async fn serve(
+	server: &Arc<Server>,
+	app: Router,
+	handle: ServerHandle,
+	addrs: Vec<SocketAddr>,
+	acceptor: AxumAcceptor,
+) -> Result {
+	let mut join_set = JoinSet::new();
+	let app = app.into_make_service_with_connect_info::<SocketAddr>();
+	for addr in &addrs {
+		let serve = axum_server::bind(*addr)
+			.acceptor(acceptor.clone())
+			.handle(handle.clone())
+			.serve(app.clone());
+
+		join_set.spawn_on(super::listener(server.clone(), serve), server.runtime());
+	}
+
+	info!("Listening on {addrs:?} with TLS certificate from ACME");
+	while join_set.join_next().await.is_some() {}
+
+	Ok(())
+}
@@ -1,16 +1,27 @@
+#[cfg(feature = "acme")]
+mod acme;
 mod plain;
+mod systemd;
 #[cfg(feature = "direct_tls")]
 mod tls;
 mod unix;
 
-use std::sync::Arc;
+use std::{
+	collections::BTreeSet,
+	sync::{Arc, atomic::Ordering},
+};
 
+use axum::Router;
 use axum_server::Handle as ServerHandle;
-use conduwuit::{Result, err};
+use conduwuit::{
+	Error, Result, Server,
+	config::{ListenerConfig, ListenerRole},
+	defer, err,
+};
 use conduwuit_service::Services;
-use tokio::sync::broadcast;
+use tokio::{sync::broadcast, task::JoinSet};
 
-use super::layers;
+use super::{layers, roles};
 
 /// Serve clients
 pub(super) async fn serve(
@@ -27,20 +38,133 @@ pub(super) async fn serve(
 			.map_err(|e| err!(error!("channel error: {e}")));
 	}
 
-	let addrs = config.get_bind_addrs();
 	let (app, _guard) = layers::build(&services)?;
-	if cfg!(unix) && config.unix_socket_path.is_some() {
-		unix::serve(server, app, shutdown).await
-	} else if config.tls.certs.is_some() {
+	let mut listeners = JoinSet::new();
+
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	for (listener, roles) in systemd::listeners(config)? {
+		let app = restrict(&app, &roles);
+		let (server_, handle, shutdown) =
+			(server.clone(), handle.clone(), shutdown.resubscribe());
+		let serve = async move {
+			match listener {
+				| systemd::Activated::Tcp(listener) =>
+					plain::serve_listener(&server_, app, handle, listener).await,
+				| systemd::Activated::Unix(listener) =>
+					unix::serve_listener(&server_, app, listener, shutdown).await,
+			}
+		};
+
+		server.spawn_in(&mut listeners, "router:listener", serve);
+	}
+
+	// Sockets passed by systemd are served in place of the configured listeners.
+	let listener_configs = if listeners.is_empty() {
+		config.get_listeners()
+	} else {
+		Vec::new()
+	};
+
+	#[cfg(feature = "acme")]
+	let acme = config
+		.acme
+		.enable
+		.then(|| acme::start(server))
+		.transpose()?;
+
+	#[cfg(not(feature = "acme"))]
+	if config.acme.enable {
+		return conduwuit::Err!(Config(
+			"acme.enable",
+			"conduwuit was not built with ACME support (\"acme\")"
+		));
+	}
+
+	for listener in listener_configs {
+		let app = restrict(&app, &listener.roles);
+
+		#[cfg(feature = "acme")]
+		if let (Some(acceptor), None) = (&acme, &listener.unix_socket_path) {
+			let (server_, handle, acceptor) = (server.clone(), handle.clone(), acceptor.clone());
+			let addrs = listener.get_bind_addrs();
+			let serve = async move { acme::serve(&server_, app, handle, addrs, acceptor).await };
+			server.spawn_in(&mut listeners, "router:listener", serve);
+			continue;
+		}
+
+		let serve =
+			serve_listener(server.clone(), app, handle.clone(), shutdown.resubscribe(), listener);
+
+		server.spawn_in(&mut listeners, "router:listener", serve);
+	}
+
+	// The first listener to fail fails the server; the others are aborted.
+	while let Some(served) = listeners.join_next().await {
+		served.map_err(Error::from).unwrap_or_else(Err)?;
+	}
+
+	debug_assert!(
+		server
+			.metrics
+			.requests_handle_active
+			.load(Ordering::Relaxed)
+			== 0,
+		"active request handles still pending"
+	);
+
+	Ok(())
+}
+
+async fn serve_listener(
+	server: Arc<Server>,
+	app: Router,
+	handle: ServerHandle,
+	shutdown: broadcast::Receiver<()>,
+	listener: ListenerConfig,
+) -> Result {
+	if let Some(path) = listener.unix_socket_path.as_deref() {
+		#[cfg(unix)]
+		return unix::serve(&server, app, path, shutdown).await;
+
+		#[cfg(not(unix))]
+		return conduwuit::Err!(Config(
+			"listeners",
+			"UNIX socket support is only available on *nix platforms ({path:?})."
+		));
+	}
+
+	let addrs = listener.get_bind_addrs();
+	if server.config.tls.certs.is_some() {
 		#[cfg(feature = "direct_tls")]
-		return tls::serve(server, app, handle, addrs).await;
+		return tls::serve(&server, app, handle, addrs).await;
 
 		#[cfg(not(feature = "direct_tls"))]
 		return conduwuit::Err!(Config(
 			"tls",
 			"conduwuit was not built with direct TLS support (\"direct_tls\")"
 		));
-	} else {
-		plain::serve(server, app, handle, addrs).await
 	}
+
+	plain::serve(&server, app, handle, addrs).await
+}
+
+/// Serve only the APIs of the roles, unless those are all of them.
+fn restrict(app: &Router, roles: &BTreeSet<ListenerRole>) -> Router {
+	if ListenerRole::ALL.iter().all(|role| roles.contains(role)) {
+		return app.clone();
+	}
+
+	let roles = Arc::new(roles.clone());
+	app.clone()
+		.layer(axum::middleware::from_fn_with_state(roles, roles::restrict))
+}
+
+/// Count the listener as serving connections while it runs.
+async fn listener<F: Future>(server: Arc<Server>, serve: F) -> F::Output {
+	server.metrics.listeners.fetch_add(1, Ordering::Relaxed);
+	defer! {{
+		server.metrics.listeners.fetch_sub(1, Ordering::Relaxed);
+	}};
+
+	serve.await
 }
@@ -17,8 +17,8 @@ pub(super) async fn serve(
 	let app = app.into_make_service_with_connect_info::<SocketAddr>();
 	let mut join_set = JoinSet::new();
 	for addr in &addrs {
-		join_set
-			.spawn_on(bind(*addr).handle(handle.clone()).serve(app.clone()), server.runtime());
+		let serve = bind(*addr).handle(handle.clone()).serve(app.clone());
+		join_set.spawn_on(super::listener(server.clone(), serve), server.runtime());
 	}
 
 	info!("Listening on {addrs:?}");
@@ -38,7 +38,24 @@ pub(super) async fn serve(
 		"Stopped listening on {addrs:?}",
 	);
 
-	debug_assert!(handle_active == 0, "active request handles still pending");
+	Ok(())
+}
+
+/// Serve on a socket which is already bound, such as one passed by systemd.
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+pub(super) async fn serve_listener(
+	server: &Arc<Server>,
+	app: Router,
+	handle: ServerHandle,
+	listener: std::net::TcpListener,
+) -> Result<()> {
+	let addr = listener.local_addr()?;
+	let app = app.into_make_service_with_connect_info::<SocketAddr>();
+	let serve = axum_server::from_tcp(listener).handle(handle).serve(app);
+
+	info!("Listening on {addr}");
+	super::listener(server.clone(), serve).await?;
+	debug_info!("Stopped listening on {addr}");
 
 	Ok(())
 }
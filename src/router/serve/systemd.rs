@@ -0,0 +1,103 @@
+#![cfg(all(feature = "systemd", target_os = "linux"))]
+
+use std::{
+	collections::BTreeSet,
+	net::TcpListener,
+	os::{
+		fd::{FromRawFd, OwnedFd},
+		unix::net::UnixListener,
+	},
+	sync::LazyLock,
+};
+
+use conduwuit::{
+	Err, Result,
+	config::{Config, ListenerRole},
+	debug_warn, info,
+};
+
+/// Socket passed by systemd socket activation.
+pub(super) enum Activated {
+	Tcp(TcpListener),
+	Unix(UnixListener),
+}
+
+/// Sockets passed by systemd, taken from the environment once for the life
+/// of the process. Each serve() is given duplicates, so the sockets stay
+/// open and keep accepting connections into their backlog across restarts
+/// of the router.
+static ACTIVATED: LazyLock<Vec<Activated>> = LazyLock::new(|| {
+	let fds = sd_notify::listen_fds()
+		.inspect_err(|e| debug_warn!("Failed to get sockets passed by systemd: {e}"))
+		.into_iter()
+		.flatten();
+
+	fds.map(|fd| {
+		// SAFETY: the descriptors passed by systemd are open, owned by this
+		// process and taken only once, here.
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+		let tcp = TcpListener::from(fd);
+		if tcp.local_addr().is_ok() {
+			Activated::Tcp(tcp)
+		} else {
+			Activated::Unix(UnixListener::from(OwnedFd::from(tcp)))
+		}
+	})
+	.collect()
+});
+
+/// Duplicates of the sockets passed by systemd, with the roles of the
+/// configured listener each matches by address or path, or all roles when
+/// none does.
+pub(super) fn listeners(config: &Config) -> Result<Vec<(Activated, BTreeSet<ListenerRole>)>> {
+	let configured = config.get_listeners();
+	let mut listeners = Vec::with_capacity(ACTIVATED.len());
+	for activated in ACTIVATED.iter() {
+		let (listener, roles) = match activated {
+			| Activated::Tcp(listener) => {
+				let addr = listener.local_addr()?;
+				let roles = configured
+					.iter()
+					.find(|listener| listener.get_bind_addrs().contains(&addr))
+					.map(|listener| listener.roles.clone());
+
+				info!("Using TCP socket {addr} passed by systemd");
+				(Activated::Tcp(listener.try_clone()?), roles)
+			},
+			| Activated::Unix(listener) => {
+				let addr = listener.local_addr()?;
+				let roles = configured
+					.iter()
+					.find(|listener| {
+						listener.unix_socket_path.is_some()
+							&& listener.unix_socket_path.as_deref() == addr.as_pathname()
+					})
+					.map(|listener| listener.roles.clone());
+
+				info!("Using UNIX socket {addr:?} passed by systemd");
+				(Activated::Unix(listener.try_clone()?), roles)
+			},
+		};
+
+		if matches!(listener, Activated::Tcp(_))
+			&& (config.tls.certs.is_some() || config.acme.enable)
+		{
+			return Err!(Config(
+				"tls",
+				"Direct TLS is not supported on sockets passed by systemd; use a reverse proxy."
+			));
+		}
+
+		set_nonblocking(&listener)?;
+		listeners.push((listener, roles.unwrap_or_else(|| ListenerRole::ALL.into())));
+	}
+
+	Ok(listeners)
+}
+
+fn set_nonblocking(listener: &Activated) -> std::io::Result<()> {
+	match listener {
+		| Activated::Tcp(listener) => listener.set_nonblocking(true),
+		| Activated::Unix(listener) => listener.set_nonblocking(true),
+	}
+}
@@ -6,9 +6,9 @@
 	ServerExt,
 	axum_server::{bind_rustls, tls_rustls::RustlsConfig},
 };
-use conduwuit::{Result, Server, err};
-use tokio::task::JoinSet;
-use tracing::{debug, info, warn};
+use conduwuit::{Result, Server, config::reload, err};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+use tracing::{debug, error, info, warn};
 
 pub(super) async fn serve(
 	server: &Arc<Server>,
@@ -26,10 +26,9 @@ pub(super) async fn serve(
 		.ok_or_else(|| err!(Config("tls.key", "Missing required value in tls config section")))?;
 
 	// we use ring for ruma and hashing state, but aws-lc-rs is the new default.
-	// without this, TLS mode will panic.
-	rustls::crypto::aws_lc_rs::default_provider()
-		.install_default()
-		.expect("failed to initialise aws-lc-rs rustls crypto provider");
+	// without this, TLS mode will panic. it fails only when already installed
+	// by another TLS listener.
+	_ = rustls::crypto::aws_lc_rs::default_provider().install_default();
 
 	debug!("Using direct TLS. Certificate path {certs} and certificate private key path {key}",);
 	info!(
@@ -42,22 +41,20 @@ pub(super) async fn serve(
 	let app = app.into_make_service_with_connect_info::<SocketAddr>();
 	if tls.dual_protocol {
 		for addr in &addrs {
-			join_set.spawn_on(
-				axum_server_dual_protocol::bind_dual_protocol(*addr, conf.clone())
-					.set_upgrade(false)
-					.handle(handle.clone())
-					.serve(app.clone()),
-				server.runtime(),
-			);
+			let serve = axum_server_dual_protocol::bind_dual_protocol(*addr, conf.clone())
+				.set_upgrade(false)
+				.handle(handle.clone())
+				.serve(app.clone());
+
+			join_set.spawn_on(super::listener(server.clone(), serve), server.runtime());
 		}
 	} else {
 		for addr in &addrs {
-			join_set.spawn_on(
-				bind_rustls(*addr, conf.clone())
-					.handle(handle.clone())
-					.serve(app.clone()),
-				server.runtime(),
-			);
+			let serve = bind_rustls(*addr, conf.clone())
+				.handle(handle.clone())
+				.serve(app.clone());
+
+			join_set.spawn_on(super::listener(server.clone(), serve), server.runtime());
 		}
 	}
 
@@ -70,7 +67,33 @@ pub(super) async fn serve(
 		info!("Listening on {addrs:?} with TLS certificate {certs}");
 	}
 
+	let reloader = server.spawn("router:tls_reload", reload_certs(server.clone(), conf));
 	while join_set.join_next().await.is_some() {}
+	reloader.abort();
 
 	Ok(())
 }
+
+/// Read the certificate files again whenever the configuration is reloaded,
+/// so a renewed certificate is served to new connections.
+async fn reload_certs(server: Arc<Server>, conf: RustlsConfig) {
+	let mut signals = server.signal.subscribe();
+	loop {
+		match signals.recv().await {
+			| Ok(reload::SIGNAL) => (),
+			| Ok(_) | Err(RecvError::Lagged(_)) => continue,
+			| Err(RecvError::Closed) => break,
+		}
+
+		let tls = &server.config.tls;
+		let (Some(certs), Some(key)) = (&tls.certs, &tls.key) else {
+			warn!("TLS certificate not reloaded: tls.certs or tls.key is no longer set");
+			continue;
+		};
+
+		match conf.reload_from_pem_file(certs, key).await {
+			| Ok(()) => info!("Reloaded TLS certificate {certs}"),
+			| Err(e) => error!("Failed to reload TLS certificate {certs}: {e}"),
+		}
+	}
+}
@@ -37,13 +37,42 @@
 pub(super) async fn serve(
 	server: &Arc<Server>,
 	app: Router,
-	mut shutdown: broadcast::Receiver<()>,
+	path: &Path,
+	shutdown: broadcast::Receiver<()>,
 ) -> Result<()> {
+	let listener = init(server, path).await?;
+	accept_loop(server, app, listener, shutdown).await;
+	remove_socket(path).await;
+
+	Ok(())
+}
+
+/// Serve on a socket which is already bound, such as one passed by systemd.
+/// The socket file belongs to whoever bound it and is left in place.
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+pub(super) async fn serve_listener(
+	server: &Arc<Server>,
+	app: Router,
+	listener: std::os::unix::net::UnixListener,
+	shutdown: broadcast::Receiver<()>,
+) -> Result<()> {
+	let listener = UnixListener::from_std(listener)?;
+	accept_loop(server, app, listener, shutdown).await;
+
+	Ok(())
+}
+
+async fn accept_loop(
+	server: &Arc<Server>,
+	app: Router,
+	listener: UnixListener,
+	mut shutdown: broadcast::Receiver<()>,
+) {
 	let mut tasks = JoinSet::<()>::new();
 	let executor = TokioExecutor::new();
 	let app = app.into_make_service_with_connect_info::<net::SocketAddr>();
 	let builder = server::conn::auto::Builder::new(executor);
-	let listener = init(server).await?;
+	server.metrics.listeners.fetch_add(1, Ordering::Relaxed);
 	while server.running() {
 		let app = app.clone();
 		let builder = builder.clone();
@@ -56,9 +85,9 @@ pub(super) async fn serve(
 		}
 	}
 
-	fini(server, listener, tasks).await;
+	server.metrics.listeners.fetch_sub(1, Ordering::Relaxed);
 
-	Ok(())
+	fini(server, listener, tasks).await;
 }
 
 #[tracing::instrument(
@@ -112,15 +141,10 @@ async fn accepted(
 	};
 }
 
-async fn init(server: &Arc<Server>) -> Result<UnixListener> {
+async fn init(server: &Arc<Server>, path: &Path) -> Result<UnixListener> {
 	use std::os::unix::fs::PermissionsExt;
 
 	let config = &server.config;
-	let path = config
-		.unix_socket_path
-		.as_ref()
-		.expect("failed to extract configured unix socket path");
-
 	if path.exists() {
 		warn!("Removing existing UNIX socket {:#?} (unclean shutdown?)...", path.display());
 		fs::remove_file(&path)
@@ -173,13 +197,11 @@ async fn fini(server: &Arc<Server>, listener: UnixListener, mut tasks: JoinSet<(
 
 	debug!("Shutting down...");
 	tasks.shutdown().await;
+}
 
-	if let Ok(local) = local {
-		if let Some(path) = local.as_pathname() {
-			debug!(?path, "Removing unix socket file.");
-			if let Err(e) = fs::remove_file(path).await {
-				warn!(?path, "Failed to remove UNIX socket file: {e}");
-			}
-		}
+async fn remove_socket(path: &Path) {
+	debug!(?path, "Removing unix socket file.");
+	if let Err(e) = fs::remove_file(path).await {
+		warn!(?path, "Failed to remove UNIX socket file: {e}");
 	}
 }
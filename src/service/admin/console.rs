@@ -53,7 +53,7 @@ pub async fn start(self: &Arc<Self>) {
 		let mut worker_join = self.worker_join.lock().expect("locked");
 		if worker_join.is_none() {
 			let self_ = Arc::clone(self);
-			_ = worker_join.insert(self.server.runtime().spawn(self_.worker()));
+			_ = worker_join.insert(self.server.spawn("admin:console", self_.worker()));
 		}
 	}
 
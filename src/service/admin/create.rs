@@ -1,8 +1,8 @@
 use std::collections::BTreeMap;
 
-use conduwuit::{Result, pdu::PduBuilder};
+use conduwuit::{Result, implement, pdu::PduBuilder};
 use ruma::{
-	RoomId, RoomVersionId,
+	OwnedRoomId, RoomAliasId, RoomId, RoomVersionId,
 	events::room::{
 		canonical_alias::RoomCanonicalAliasEventContent,
 		create::RoomCreateEventContent,
@@ -19,25 +19,76 @@
 
 use crate::Services;
 
+/// Room created and joined by the server user, such as the admin room.
+pub struct ServerRoom<'a> {
+	/// Name of the room.
+	pub name: String,
+
+	/// Alias set for the room and as its canonical alias.
+	pub alias: Option<&'a RoomAliasId>,
+
+	pub join_rule: JoinRule,
+
+	/// Whether users of other servers may join the room.
+	pub federate: bool,
+
+	/// Power levels of the room, which must give the server user power.
+	pub power_levels: RoomPowerLevelsEventContent,
+
+	/// Further state events, sent after the others.
+	pub state: Vec<PduBuilder>,
+}
+
 /// Create the admin room.
 ///
 /// Users in this room are considered admins by conduwuit, and the room can be
 /// used to issue admin commands by talking to the server user inside it.
 pub async fn create_admin_room(services: &Services) -> Result {
-	let room_id = RoomId::new(services.globals.server_name());
-	let room_version = &services.config.default_room_version;
+	// Create a user for the server
+	let server_user = services.globals.server_user.as_ref();
+	services.users.create(server_user, None)?;
+
+	let users = BTreeMap::from_iter([(server_user.into(), 69420.into())]);
+	let topic = format!(
+		"Manage {} | Run commands prefixed with `!admin` | Run `!admin -h` for help | \
+		 Documentation: https://conduwuit.puppyirl.gay/",
+		services.config.server_name
+	);
+
+	services
+		.admin
+		.create_room(ServerRoom {
+			name: format!("{} Admin Room", services.config.server_name),
+			alias: Some(services.globals.admin_alias.as_ref()),
+			join_rule: JoinRule::Invite,
+			federate: true,
+			power_levels: RoomPowerLevelsEventContent { users, ..Default::default() },
+			state: vec![
+				PduBuilder::state(String::new(), &RoomTopicEventContent { topic }),
+				// (ad-hoc) Disable room URL previews for everyone by default
+				PduBuilder::state(String::new(), &RoomPreviewUrlsEventContent { disabled: true }),
+			],
+		})
+		.await?;
 
-	let _short_id = services
-		.rooms
+	Ok(())
+}
+
+/// Create a room owned by the server user, with shared history and no guest
+/// access. The alias, if any, is set once all the events are sent.
+#[implement(super::Service)]
+pub async fn create_room(&self, room: ServerRoom<'_>) -> Result<OwnedRoomId> {
+	let room_id = RoomId::new(self.services.globals.server_name());
+	let room_version = &self.services.server.config.default_room_version;
+	let server_user = self.services.globals.server_user.as_ref();
+
+	let _short_id = self
+		.services
 		.short
 		.get_or_create_shortroomid(&room_id)
 		.await;
 
-	let state_lock = services.rooms.state.mutex.lock(&room_id).await;
-
-	// Create a user for the server
-	let server_user = services.globals.server_user.as_ref();
-	services.users.create(server_user, None)?;
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
 
 	let create_content = {
 		use RoomVersionId::*;
@@ -48,156 +99,49 @@ pub async fn create_admin_room(services: &Services) -> Result {
 		}
 	};
 
-	// 1. The room create event
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomCreateEventContent {
-				federate: true,
-				predecessor: None,
-				room_version: room_version.clone(),
-				..create_content
-			}),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 2. Make server user/bot join
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(
-				String::from(server_user),
-				&RoomMemberEventContent::new(MembershipState::Join),
-			),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 3. Power levels
-	let users = BTreeMap::from_iter([(server_user.into(), 69420.into())]);
-
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomPowerLevelsEventContent {
-				users,
-				..Default::default()
-			}),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 4.1 Join Rules
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(JoinRule::Invite)),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 4.2 History Visibility
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(
-				String::new(),
-				&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
-			),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 4.3 Guest Access
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(
-				String::new(),
-				&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
-			),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 5. Events implied by name and topic
-	let room_name = format!("{} Admin Room", services.config.server_name);
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomNameEventContent::new(room_name)),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomTopicEventContent {
-				topic: format!("Manage {} | Run commands prefixed with `!admin` | Run `!admin -h` for help | Documentation: https://conduwuit.puppyirl.gay/", services.config.server_name),
-			}),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	// 6. Room alias
-	let alias = &services.globals.admin_alias;
-
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomCanonicalAliasEventContent {
-				alias: Some(alias.clone()),
-				alt_aliases: Vec::new(),
-			}),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	services
-		.rooms
-		.alias
-		.set_alias(alias, &room_id, server_user)?;
-
-	// 7. (ad-hoc) Disable room URL previews for everyone by default
-	services
-		.rooms
-		.timeline
-		.build_and_append_pdu(
-			PduBuilder::state(String::new(), &RoomPreviewUrlsEventContent { disabled: true }),
-			server_user,
-			&room_id,
-			&state_lock,
-		)
-		.await?;
-
-	Ok(())
+	let events = [
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			federate: room.federate,
+			predecessor: None,
+			room_version: room_version.clone(),
+			..create_content
+		}),
+		PduBuilder::state(
+			String::from(server_user),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		PduBuilder::state(String::new(), &room.power_levels),
+		PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(room.join_rule)),
+		PduBuilder::state(
+			String::new(),
+			&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
+		),
+		PduBuilder::state(String::new(), &RoomNameEventContent::new(room.name)),
+	];
+
+	let alias = room.alias.map(|alias| {
+		PduBuilder::state(String::new(), &RoomCanonicalAliasEventContent {
+			alias: Some(alias.to_owned()),
+			alt_aliases: Vec::new(),
+		})
+	});
+
+	for event in events.into_iter().chain(alias).chain(room.state) {
+		self.services
+			.timeline
+			.build_and_append_pdu(event, server_user, &room_id, &state_lock)
+			.await?;
+	}
+
+	if let Some(alias) = room.alias {
+		self.services
+			.alias
+			.set_alias(alias, &room_id, server_user)?;
+	}
+
+	Ok(room_id)
 }
@@ -1,4 +1,6 @@
-use conduwuit::{Err, Result, debug, debug_info, error, implement, info};
+use conduwuit::{
+	Err, Result, config::ForbiddenUsernameAction, debug, debug_info, error, implement, info,
+};
 use ruma::events::room::message::RoomMessageEventContent;
 use tokio::time::{Duration, sleep};
 
@@ -37,6 +39,18 @@ pub(super) async fn startup_execute(&self) -> Result {
 	//TODO: remove this after run-states are broadcast
 	sleep(Duration::from_millis(500)).await;
 
+	// Users with forbidden names were only locked by the migrations; leaving
+	// their rooms needs the full deactivation of the admin command
+	let action = self.services.server.config.forbidden_usernames_enforcement;
+	if action == ForbiddenUsernameAction::Deactivate {
+		let command = "users enforce-forbidden-usernames".to_owned();
+		if let Err(e) = self.execute_command(0, command).await {
+			if !errors {
+				return Err(e);
+			}
+		}
+	}
+
 	for (i, command) in commands.iter().enumerate() {
 		if let Err(e) = self.execute_command(i, command.clone()).await {
 			if !errors {
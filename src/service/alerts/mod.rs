@@ -0,0 +1,261 @@
+use std::{
+	collections::HashMap,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Result, Server, debug, debug_warn,
+	utils::{bytes::pretty, sys::storage::space_from_path, time},
+};
+use database::Database;
+use tokio::{
+	fs,
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, admin, media, sending};
+
+/// Posts notices to the admin room when the server runs into trouble an
+/// admin should know about: low disk space, delayed federation, stalled
+/// database writes or an unwritable media directory. Other services post
+/// their own notices through [`Service::post`].
+pub struct Service {
+	interrupt: Notify,
+	last_posted: Mutex<HashMap<String, Instant>>,
+
+	/// Consecutive checks each condition has failed
+	failing: Mutex<HashMap<String, usize>>,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	db: Arc<Database>,
+	admin: Dep<admin::Service>,
+	media: Dep<media::Service>,
+	sending: Dep<sending::Service>,
+}
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Consecutive checks a condition must fail before a notice is posted for it,
+/// so that brief spikes do not page the admins.
+const DEBOUNCE_CHECKS: usize = 3;
+
+/// Servers listed in a federation backlog notice
+const BACKLOG_LIST_MAX: usize = 10;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			last_posted: Mutex::default(),
+			failing: Mutex::default(),
+			services: Services {
+				server: args.server.clone(),
+				db: args.db.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				media: args.depend::<media::Service>("media"),
+				sending: args.depend::<sending::Service>("sending"),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "alerts", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(CHECK_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.tick().await;
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.check().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Run every enabled check, posting a notice for each one failing for
+	/// DEBOUNCE_CHECKS checks in a row unless one was posted for it within the
+	/// cooldown.
+	async fn check(&self) {
+		let config = &self.services.server.config;
+		let mut alerts = Vec::new();
+
+		if config.admin_alert_disk_free_percent > 0 {
+			let database = config.database_path.clone();
+			let media = self.services.media.get_media_dir();
+			for path in [database, media] {
+				if let Some(alert) = self.check_disk(&path) {
+					alerts.push((format!("disk:{}", path.display()), alert));
+				}
+			}
+		}
+
+		if config.admin_alert_federation_backlog_secs > 0 {
+			if let Some(alert) = self.check_federation_backlog() {
+				alerts.push(("federation_backlog".to_owned(), alert));
+			}
+		}
+
+		if config.admin_alert_write_stall {
+			if let Some(alert) = self.check_write_stall() {
+				alerts.push(("write_stall".to_owned(), alert));
+			}
+		}
+
+		if config.admin_alert_media_unwritable {
+			if let Some(alert) = self.check_media_writable().await {
+				alerts.push(("media_unwritable".to_owned(), alert));
+			}
+		}
+
+		for (key, alert) in self.debounce(alerts) {
+			self.post(key, &alert).await;
+		}
+	}
+
+	/// Alerts whose condition failed DEBOUNCE_CHECKS checks in a row; the
+	/// count of conditions which passed this check is reset.
+	fn debounce(&self, alerts: Vec<(String, String)>) -> Vec<(String, String)> {
+		let mut failing = self.failing.lock().expect("locked");
+		failing.retain(|key, _| alerts.iter().any(|(failed, _)| failed == key));
+
+		alerts
+			.into_iter()
+			.filter(|(key, _)| {
+				let checks = failing.entry(key.clone()).or_default();
+				*checks = checks.saturating_add(1);
+				if *checks < DEBOUNCE_CHECKS {
+					debug!(%key, checks = *checks, "Alert condition failing");
+					return false;
+				}
+
+				true
+			})
+			.collect()
+	}
+
+	/// Post a notice to the admin room for the condition `key`, unless one was
+	/// posted for it within the cooldown.
+	pub async fn post(&self, key: String, alert: &str) {
+		if self.cooled_down(key) {
+			self.services.admin.send_text(alert).await;
+		}
+	}
+
+	fn cooled_down(&self, key: String) -> bool {
+		let cooldown = Duration::from_secs(self.services.server.config.admin_alert_cooldown);
+		let mut last_posted = self.last_posted.lock().expect("locked");
+		if last_posted
+			.get(&key)
+			.is_some_and(|posted| posted.elapsed() < cooldown)
+		{
+			debug!(%key, "Alert suppressed by cooldown");
+			return false;
+		}
+
+		last_posted.insert(key, Instant::now());
+		true
+	}
+
+	fn check_disk(&self, path: &Path) -> Option<String> {
+		let threshold = u64::from(self.services.server.config.admin_alert_disk_free_percent);
+		let (available, total) = space_from_path(path)
+			.inspect_err(|e| debug_warn!(?path, "Failed to get free disk space: {e}"))
+			.ok()?;
+
+		let percent = available
+			.saturating_mul(100)
+			.checked_div(total)
+			.unwrap_or(100);
+
+		(percent < threshold).then(|| {
+			let available = pretty(usize::try_from(available).unwrap_or(usize::MAX));
+			format!(
+				"Low disk space: {available} ({percent}%) free on the filesystem of `{}`.",
+				path.display()
+			)
+		})
+	}
+
+	fn check_federation_backlog(&self) -> Option<String> {
+		let limit = Duration::from_secs(
+			self.services
+				.server
+				.config
+				.admin_alert_federation_backlog_secs,
+		);
+
+		let delayed: Vec<_> = self
+			.services
+			.sending
+			.health()
+			.into_iter()
+			.filter(|(_, health)| health.consecutive_failures == 0)
+			.filter(|(_, health)| health.backlog_age() > limit)
+			.collect();
+
+		if delayed.is_empty() {
+			return None;
+		}
+
+		let servers = delayed
+			.iter()
+			.take(BACKLOG_LIST_MAX)
+			.map(|(server, health)| format!("{server} ({})", time::pretty(health.backlog_age())))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		Some(format!(
+			"Federation is delayed: events to {} reachable servers have waited longer than {}: \
+			 {servers}. See `!admin federation health`.",
+			delayed.len(),
+			time::pretty(limit),
+		))
+	}
+
+	fn check_write_stall(&self) -> Option<String> {
+		let stalled = self
+			.services
+			.db
+			.db
+			.is_write_stalled()
+			.inspect_err(|e| debug_warn!("Failed to query database write stall: {e}"))
+			.ok()?;
+
+		stalled.then(|| {
+			"Database writes are stalled while flushes and compactions catch up; the disk may be \
+			 too slow or too full."
+				.to_owned()
+		})
+	}
+
+	async fn check_media_writable(&self) -> Option<String> {
+		let dir = self.services.media.get_media_dir();
+		let probe = dir.join(".write_probe");
+		let result = async {
+			fs::write(&probe, b"").await?;
+			fs::remove_file(&probe).await
+		}
+		.await;
+
+		result
+			.err()
+			.map(|e| format!("The media directory `{}` is not writable: {e}", dir.display()))
+	}
+}
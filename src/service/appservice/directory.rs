@@ -0,0 +1,291 @@
+//! Appservice registration directory
+//!
+//! Registrations can be kept as YAML files in `appservice_registration_dir`
+//! instead of being registered through the admin room. Reloading the
+//! directory lints the registrations, compares them with the registered
+//! appservices and applies the difference. Appservices whose file was
+//! removed are unregistered; those registered through the admin room are
+//! left alone unless a file takes over their ID.
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	path::Path,
+};
+
+use conduwuit::{Err, Result, debug, implement, utils::stream::TryIgnore};
+use futures::StreamExt;
+use ruma::api::appservice::{Namespace, Registration};
+use tokio::fs;
+
+use super::RegistrationInfo;
+
+/// Outcome of reloading the registration directory, by appservice ID.
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+	pub added: Vec<String>,
+	pub changed: Vec<String>,
+	pub removed: Vec<String>,
+	pub unchanged: Vec<String>,
+
+	/// Files which were skipped, with the reason
+	pub errors: Vec<String>,
+
+	/// Problems of registrations which were applied nevertheless
+	pub warnings: Vec<String>,
+}
+
+/// Registration read from a file in the directory.
+struct File {
+	name: String,
+	body: String,
+	registration: Registration,
+}
+
+/// Characters with a meaning in regular expressions, ending the literal
+/// prefix of a namespace
+const REGEX_META: &[char] = &['\\', '.', '+', '*', '?', '(', ')', '|', '[', ']', '{', '}', '$'];
+
+/// Read the registrations in `appservice_registration_dir` and add, update
+/// or unregister appservices accordingly. With `dry_run` nothing is applied.
+#[implement(super::Service)]
+pub async fn reload_registrations(&self, dry_run: bool) -> Result<ReloadReport> {
+	let Some(dir) = &self.services.server.config.appservice_registration_dir else {
+		return Err!(Config(
+			"appservice_registration_dir",
+			"No appservice registration directory is configured."
+		));
+	};
+
+	let mut report = ReloadReport::default();
+	let (files, failed) = read_dir(dir, &mut report).await?;
+
+	let registered = self.read().await.clone();
+	self.lint(&files, &registered, &mut report);
+
+	let tracked: BTreeMap<String, String> = self
+		.db
+		.appserviceid_registrationfile
+		.stream()
+		.ignore_err()
+		.map(|(id, file): (&str, &str)| (id.to_owned(), file.to_owned()))
+		.collect()
+		.await;
+
+	for file in &files {
+		let id = &file.registration.id;
+		let unchanged = match self.db.id_appserviceregistrations.get(id).await {
+			| Ok(body) if *body == *file.body.as_bytes() => {
+				report.unchanged.push(id.clone());
+				true
+			},
+			| Ok(_) => {
+				report.changed.push(id.clone());
+				false
+			},
+			| Err(_) => {
+				report.added.push(id.clone());
+				false
+			},
+		};
+
+		if dry_run {
+			continue;
+		}
+
+		if !unchanged {
+			self.register_appservice(&file.registration, &file.body)
+				.await?;
+		}
+
+		self.db.appserviceid_registrationfile.insert(id, &file.name);
+	}
+
+	let ids: HashSet<_> = files.iter().map(|file| &file.registration.id).collect();
+	for (id, name) in tracked {
+		// keep appservices whose file is only broken for now
+		if ids.contains(&id) || failed.contains(&name) {
+			continue;
+		}
+
+		if !dry_run {
+			self.db.appserviceid_registrationfile.remove(&id);
+			if registered.contains_key(&id) {
+				self.unregister_appservice(&id).await?;
+			}
+		}
+
+		report.removed.push(id);
+	}
+
+	Ok(report)
+}
+
+/// Read the registration files of `dir`, reporting the ones which are
+/// invalid. Returns the registrations and the names of the invalid files.
+async fn read_dir(dir: &Path, report: &mut ReloadReport) -> Result<(Vec<File>, HashSet<String>)> {
+	let mut names = Vec::new();
+	let mut entries = fs::read_dir(dir).await?;
+	while let Some(entry) = entries.next_entry().await? {
+		let path = entry.path();
+		let is_yaml = path
+			.extension()
+			.is_some_and(|extension| extension == "yaml" || extension == "yml");
+
+		if is_yaml && entry.file_type().await?.is_file() {
+			names.push(entry.file_name().to_string_lossy().into_owned());
+		}
+	}
+
+	names.sort_unstable();
+
+	let mut files: Vec<File> = Vec::with_capacity(names.len());
+	let mut failed = HashSet::new();
+	for name in names {
+		let body = match fs::read_to_string(dir.join(&name)).await {
+			| Ok(body) => body,
+			| Err(e) => {
+				report.errors.push(format!("{name}: {e}"));
+				failed.insert(name);
+				continue;
+			},
+		};
+
+		let registration: Registration = match serde_yaml::from_str(&body) {
+			| Ok(registration) => registration,
+			| Err(e) => {
+				report
+					.errors
+					.push(format!("{name}: invalid registration: {e}"));
+				failed.insert(name);
+				continue;
+			},
+		};
+
+		if let Err(e) = RegistrationInfo::try_from(registration.clone()) {
+			report
+				.errors
+				.push(format!("{name}: invalid namespace regex: {e}"));
+			failed.insert(name);
+			continue;
+		}
+
+		if let Some(other) = files
+			.iter()
+			.find(|file| file.registration.id == registration.id)
+		{
+			report.errors.push(format!(
+				"{name}: ID {:?} is already used by {}",
+				registration.id, other.name
+			));
+			failed.insert(name);
+			continue;
+		}
+
+		debug!(%name, id = %registration.id, "Read appservice registration");
+		files.push(File { name, body, registration });
+	}
+
+	Ok((files, failed))
+}
+
+/// Warn of namespaces which are not anchored, and of exclusive namespaces
+/// which may overlap namespaces or the sender of another appservice.
+#[implement(super::Service)]
+fn lint(
+	&self,
+	files: &[File],
+	registered: &BTreeMap<String, RegistrationInfo>,
+	report: &mut ReloadReport,
+) {
+	let server_name = &self.services.server.config.server_name;
+	let others = registered
+		.values()
+		.map(|info| &info.registration)
+		.filter(|registration| {
+			!files
+				.iter()
+				.any(|file| file.registration.id == registration.id)
+		});
+
+	let registrations: Vec<&Registration> = files
+		.iter()
+		.map(|file| &file.registration)
+		.chain(others)
+		.collect();
+
+	for file in files {
+		let registration = &file.registration;
+		for (kind, namespace) in namespaces(registration) {
+			let regex = &namespace.regex;
+			if !regex.starts_with('^') || !regex.ends_with('$') {
+				report.warnings.push(format!(
+					"{}: {kind} namespace {regex:?} is not anchored with ^ and $, so it also \
+					 matches IDs merely containing a match",
+					file.name
+				));
+			}
+		}
+
+		for other in registrations
+			.iter()
+			.filter(|other| other.id != registration.id)
+		{
+			for (kind, namespace) in namespaces(registration).filter(|(_, ns)| ns.exclusive) {
+				let overlapping = namespaces(other)
+					.filter(|(other_kind, _)| other_kind == &kind)
+					.find(|(_, other_ns)| may_overlap(&namespace.regex, &other_ns.regex));
+
+				if let Some((_, other_ns)) = overlapping {
+					report.warnings.push(format!(
+						"{}: exclusive {kind} namespace {:?} may overlap {:?} of appservice {:?}",
+						file.name, namespace.regex, other_ns.regex, other.id
+					));
+				}
+
+				let sender = format!("@{}:{server_name}", other.sender_localpart);
+				if kind == "users" && regex_matches(&namespace.regex, &sender) {
+					report.warnings.push(format!(
+						"{}: exclusive users namespace {:?} matches the sender {sender} of \
+						 appservice {:?}",
+						file.name, namespace.regex, other.id
+					));
+				}
+			}
+		}
+	}
+}
+
+fn namespaces(registration: &Registration) -> impl Iterator<Item = (&'static str, &Namespace)> {
+	let namespaces = &registration.namespaces;
+	let users = namespaces.users.iter().map(|ns| ("users", ns));
+	let aliases = namespaces.aliases.iter().map(|ns| ("aliases", ns));
+	let rooms = namespaces.rooms.iter().map(|ns| ("rooms", ns));
+
+	users.chain(aliases).chain(rooms)
+}
+
+/// Whether two namespace regexes may match the same ID: they are the same,
+/// or both are anchored at the start and the literal prefix of one starts
+/// with that of the other.
+fn may_overlap(a: &str, b: &str) -> bool {
+	if a == b {
+		return true;
+	}
+
+	match (literal_prefix(a), literal_prefix(b)) {
+		| (Some(a), Some(b)) => a.starts_with(b) || b.starts_with(a),
+		| _ => false,
+	}
+}
+
+/// The part of an anchored regex before its first metacharacter, if any.
+fn literal_prefix(regex: &str) -> Option<&str> {
+	let regex = regex.strip_prefix('^')?;
+	let end = regex.find(REGEX_META).unwrap_or(regex.len());
+
+	regex.get(..end).filter(|prefix| !prefix.is_empty())
+}
+
+fn regex_matches(regex: &str, haystack: &str) -> bool {
+	regex::Regex::new(regex).is_ok_and(|regex| regex.is_match(haystack))
+}
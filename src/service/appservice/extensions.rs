@@ -0,0 +1,18 @@
+use serde::Deserialize;
+
+/// Unstable registration options which aren't part of ruma's `Registration`.
+/// These are read from the same registration YAML.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Extensions {
+	/// Receive device list changes and one-time key counts in transactions,
+	/// and act as a specific device of a user in the namespace (MSC3202).
+	#[serde(default, rename = "org.matrix.msc3202")]
+	pub msc3202: bool,
+}
+
+impl Extensions {
+	/// Parse the extensions from an appservice registration YAML. Unknown or
+	/// malformed options are treated as absent.
+	#[must_use]
+	pub fn from_yaml(yaml: &[u8]) -> Self { serde_yaml::from_slice(yaml).unwrap_or_default() }
+}
@@ -0,0 +1,155 @@
+use std::{
+	collections::BTreeMap,
+	time::{Duration, SystemTime},
+};
+
+use conduwuit::{Err, Result, debug, implement, warn};
+use ruma::{
+	OwnedTransactionId,
+	api::appservice::{Registration, ping::send_ping},
+};
+use tokio::time::Instant;
+
+/// Connectivity of an appservice as observed by the health monitor and by
+/// pings requested through the client API.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+	/// Time of the most recent ping
+	pub last_check: Option<SystemTime>,
+
+	/// Time of the most recent successful ping
+	pub last_success: Option<SystemTime>,
+
+	/// Round-trip time of the most recent successful ping
+	pub latency: Option<Duration>,
+
+	/// Error of the most recent ping, if it failed
+	pub error: Option<String>,
+
+	/// Number of consecutive failed pings
+	pub failures: u64,
+}
+
+pub(super) type HealthMap = std::sync::RwLock<BTreeMap<String, Health>>;
+
+impl Health {
+	#[inline]
+	#[must_use]
+	pub fn is_healthy(&self) -> bool { self.failures == 0 }
+}
+
+/// Ping an appservice and record the outcome in its health status. Returns
+/// the round-trip time.
+#[implement(super::Service)]
+pub async fn ping(
+	&self,
+	registration: &Registration,
+	transaction_id: Option<OwnedTransactionId>,
+) -> Result<Duration> {
+	if registration
+		.url
+		.as_ref()
+		.is_none_or(|url| url.is_empty() || url == "null")
+	{
+		return Err!(Request(UrlNotSet(
+			"Appservice does not have a URL set, there is nothing to ping."
+		)));
+	}
+
+	let timer = Instant::now();
+	let result = self
+		.services
+		.sending
+		.send_appservice_request(registration.clone(), send_ping::v1::Request { transaction_id })
+		.await
+		.map(|_| timer.elapsed());
+
+	self.record_ping(&registration.id, &result);
+
+	result
+}
+
+/// Current health status of every registered appservice that was pinged.
+#[implement(super::Service)]
+#[must_use]
+pub fn health(&self) -> BTreeMap<String, Health> {
+	self.health.read().expect("locked for reading").clone()
+}
+
+/// Ping all registered appservices which have a URL, reporting changes in
+/// their reachability to the admin room.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub(super) async fn check_health(&self) {
+	let registrations: Vec<_> = self
+		.read()
+		.await
+		.values()
+		.map(|info| info.registration.clone())
+		.filter(|registration| {
+			registration
+				.url
+				.as_ref()
+				.is_some_and(|url| !url.is_empty() && url != "null")
+		})
+		.collect();
+
+	for registration in registrations {
+		let id = &registration.id;
+		let was_healthy = self
+			.health
+			.read()
+			.expect("locked for reading")
+			.get(id)
+			.is_none_or(Health::is_healthy);
+
+		match self.ping(&registration, None).await {
+			| Ok(latency) => {
+				debug!(?id, ?latency, "Appservice is reachable");
+				if !was_healthy {
+					self.services
+						.admin
+						.send_text(&format!("Appservice `{id}` is reachable again."))
+						.await;
+				}
+			},
+			| Err(e) => {
+				warn!(?id, "Appservice is unreachable: {e}");
+				if was_healthy {
+					self.services
+						.admin
+						.send_text(&format!("Appservice `{id}` is unreachable: {e}"))
+						.await;
+				}
+			},
+		}
+	}
+
+	// forget appservices which have since been unregistered
+	let registered = self.iter_ids().await;
+	self.health
+		.write()
+		.expect("locked for writing")
+		.retain(|id, _| registered.contains(id));
+}
+
+#[implement(super::Service)]
+fn record_ping(&self, id: &str, result: &Result<Duration>) {
+	let now = SystemTime::now();
+	let mut health = self.health.write().expect("locked for writing");
+	let health = health.entry(id.to_owned()).or_default();
+
+	health.last_check = Some(now);
+	match result {
+		| Ok(latency) => {
+			health.last_success = Some(now);
+			health.latency = Some(*latency);
+			health.error = None;
+			health.failures = 0;
+		},
+		| Err(e) => {
+			health.error = Some(e.to_string());
+			health.failures = health.failures.saturating_add(1);
+		},
+	}
+}
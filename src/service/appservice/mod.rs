@@ -1,29 +1,58 @@
+mod directory;
+mod extensions;
+mod health;
 mod namespace_regex;
 mod registration_info;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
 use async_trait::async_trait;
-use conduwuit::{Result, err, utils::stream::TryIgnore};
+use conduwuit::{
+	Result, Server, err, error, info,
+	utils::{ReadyExt, stream::TryIgnore},
+	warn,
+};
 use database::Map;
 use futures::{Future, StreamExt, TryStreamExt};
-use ruma::{RoomAliasId, RoomId, UserId, api::appservice::Registration};
-use tokio::sync::RwLock;
+use ruma::{OwnedUserId, RoomAliasId, RoomId, UserId, api::appservice::Registration};
+use tokio::{
+	sync::{Notify, RwLock},
+	time::{Instant, MissedTickBehavior, interval_at},
+};
 
-pub use self::{namespace_regex::NamespaceRegex, registration_info::RegistrationInfo};
-use crate::{Dep, sending};
+use self::health::HealthMap;
+pub use self::{
+	directory::ReloadReport, extensions::Extensions, health::Health,
+	namespace_regex::NamespaceRegex, registration_info::RegistrationInfo,
+};
+use crate::{Dep, admin, sending, users};
 
 pub struct Service {
 	registration_info: RwLock<BTreeMap<String, RegistrationInfo>>,
+	local_users: Mutex<LocalUsersCache>,
+	health: HealthMap,
+	interrupt: Notify,
 	services: Services,
 	db: Data,
 }
 
 struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
 	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
 }
 
+/// Local users in the namespace of each appservice, along with the number of
+/// created users the list is current as of
+type LocalUsersCache = HashMap<String, (u64, Arc<Vec<OwnedUserId>>)>;
+
 struct Data {
+	appserviceid_registrationfile: Arc<Map>,
 	id_appserviceregistrations: Arc<Map>,
 }
 
@@ -32,10 +61,17 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			registration_info: RwLock::new(BTreeMap::new()),
+			local_users: Mutex::new(LocalUsersCache::new()),
+			health: HealthMap::default(),
+			interrupt: Notify::new(),
 			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
 				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
 			},
 			db: Data {
+				appserviceid_registrationfile: args.db["appserviceid_registrationfile"].clone(),
 				id_appserviceregistrations: args.db["id_appserviceregistrations"].clone(),
 			},
 		}))
@@ -43,19 +79,65 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		// Inserting registrations into cache
-		for appservice in self.iter_db_ids().await? {
-			self.registration_info.write().await.insert(
-				appservice.0,
-				appservice
-					.1
-					.try_into()
-					.expect("Should be validated on registration"),
-			);
+		for (id, registration) in self.iter_db_ids().await? {
+			let extensions = self.get_db_extensions(&id).await;
+			let info = RegistrationInfo::try_from(registration)
+				.expect("Should be validated on registration")
+				.with_extensions(extensions);
+
+			self.registration_info.write().await.insert(id, info);
+		}
+
+		if self
+			.services
+			.server
+			.config
+			.appservice_registration_dir
+			.is_some()
+		{
+			match self.reload_registrations(false).await {
+				| Ok(report) => {
+					for problem in report.errors.iter().chain(&report.warnings) {
+						warn!("Appservice registration directory: {problem}");
+					}
+
+					info!(
+						added = report.added.len(),
+						changed = report.changed.len(),
+						removed = report.removed.len(),
+						"Loaded appservice registration directory"
+					);
+				},
+				| Err(e) => error!("Failed to load appservice registration directory: {e}"),
+			}
+		}
+
+		let period = self.services.server.config.appservice_health_interval;
+		if period == 0 {
+			return Ok(());
+		}
+
+		// give appservices starting alongside the server a chance to come up
+		let period = Duration::from_secs(period);
+		let start = Instant::now()
+			.checked_add(period)
+			.unwrap_or_else(Instant::now);
+		let mut i = interval_at(start, period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.check_health().await;
 		}
 
 		Ok(())
 	}
 
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -67,10 +149,17 @@ pub async fn register_appservice(
 		appservice_config_body: &str,
 	) -> Result {
 		//TODO: Check for collisions between exclusive appservice namespaces
+		let extensions = Extensions::from_yaml(appservice_config_body.as_bytes());
+		let info = RegistrationInfo::try_from(registration.clone())?.with_extensions(extensions);
 		self.registration_info
 			.write()
 			.await
-			.insert(registration.id.clone(), registration.clone().try_into()?);
+			.insert(registration.id.clone(), info);
+
+		self.local_users
+			.lock()
+			.expect("locked")
+			.remove(&registration.id);
 
 		self.db
 			.id_appserviceregistrations
@@ -92,6 +181,11 @@ pub async fn unregister_appservice(&self, appservice_id: &str) -> Result<()> {
 			.remove(appservice_id)
 			.ok_or_else(|| err!("Appservice not found"))?;
 
+		self.local_users
+			.lock()
+			.expect("locked")
+			.remove(appservice_id);
+
 		// remove the appservice from the database
 		self.db.id_appserviceregistrations.del(appservice_id);
 
@@ -112,6 +206,43 @@ pub async fn get_registration(&self, id: &str) -> Option<Registration> {
 			.map(|info| info.registration)
 	}
 
+	/// Local users in the namespace of an appservice. The list is cached until
+	/// a user is created or the registration changes.
+	pub async fn local_users(&self, info: &RegistrationInfo) -> Arc<Vec<OwnedUserId>> {
+		let id = &info.registration.id;
+		let created = self.services.users.created_count();
+		if let Some((_, users)) = self
+			.local_users
+			.lock()
+			.expect("locked")
+			.get(id)
+			.filter(|(cached, _)| *cached == created)
+		{
+			return users.clone();
+		}
+
+		let users: Arc<Vec<OwnedUserId>> = Arc::new(
+			self.services
+				.users
+				.stream()
+				.ready_filter(|user_id| info.is_user_match(user_id))
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+		);
+
+		self.local_users
+			.lock()
+			.expect("locked")
+			.insert(id.clone(), (created, users.clone()));
+
+		users
+	}
+
+	pub async fn get_registration_info(&self, id: &str) -> Option<RegistrationInfo> {
+		self.registration_info.read().await.get(id).cloned()
+	}
+
 	pub async fn iter_ids(&self) -> Vec<String> {
 		self.registration_info
 			.read()
@@ -129,6 +260,14 @@ pub async fn find_from_token(&self, token: &str) -> Option<RegistrationInfo> {
 			.cloned()
 	}
 
+	/// Checks if a given user id matches any appservice regex, exclusive or not
+	pub async fn is_user_id(&self, user_id: &UserId) -> bool {
+		self.read()
+			.await
+			.values()
+			.any(|info| info.is_user_match(user_id))
+	}
+
 	/// Checks if a given user id matches any exclusive appservice regex
 	pub async fn is_exclusive_user_id(&self, user_id: &UserId) -> bool {
 		self.read()
@@ -175,6 +314,15 @@ pub async fn get_db_registration(&self, id: &str) -> Result<Registration> {
 			.map_err(|e| err!(Database("Invalid appservice {id:?} registration: {e:?}")))
 	}
 
+	async fn get_db_extensions(&self, id: &str) -> Extensions {
+		self.db
+			.id_appserviceregistrations
+			.get(id)
+			.await
+			.map(|ref bytes| Extensions::from_yaml(bytes))
+			.unwrap_or_default()
+	}
+
 	async fn iter_db_ids(&self) -> Result<Vec<(String, Registration)>> {
 		self.db
 			.id_appserviceregistrations
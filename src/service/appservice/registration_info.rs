@@ -1,7 +1,7 @@
 use conduwuit::Result;
 use ruma::{UserId, api::appservice::Registration};
 
-use super::NamespaceRegex;
+use super::{Extensions, NamespaceRegex};
 
 /// Appservice registration combined with its compiled regular expressions.
 #[derive(Clone, Debug)]
@@ -10,15 +10,25 @@ pub struct RegistrationInfo {
 	pub users: NamespaceRegex,
 	pub aliases: NamespaceRegex,
 	pub rooms: NamespaceRegex,
+	pub extensions: Extensions,
 }
 
 impl RegistrationInfo {
+	#[must_use]
+	pub fn with_extensions(self, extensions: Extensions) -> Self { Self { extensions, ..self } }
+
 	#[must_use]
 	pub fn is_user_match(&self, user_id: &UserId) -> bool {
 		self.users.is_match(user_id.as_str())
 			|| self.registration.sender_localpart == user_id.localpart()
 	}
 
+	/// Whether the appservice's requests count against rate limits, i.e. it
+	/// did not register with `rate_limited: false`.
+	#[inline]
+	#[must_use]
+	pub fn is_rate_limited(&self) -> bool { self.registration.rate_limited != Some(false) }
+
 	#[inline]
 	#[must_use]
 	pub fn is_exclusive_user_match(&self, user_id: &UserId) -> bool {
@@ -35,6 +45,7 @@ fn try_from(value: Registration) -> Result<Self, regex::Error> {
 			users: value.namespaces.users.clone().try_into()?,
 			aliases: value.namespaces.aliases.clone().try_into()?,
 			rooms: value.namespaces.rooms.clone().try_into()?,
+			extensions: Extensions::default(),
 			registration: value,
 		})
 	}
@@ -0,0 +1,120 @@
+//! Audit log of admin actions
+//!
+//! Every admin command executed and every API call in which a server admin
+//! used their privileges is appended to the `auditid_entry` column, so
+//! servers with several admins can account for who did what. Entries are
+//! never modified or removed by the server.
+
+use std::{fmt, sync::Arc};
+
+use conduwuit::{
+	Result, error, implement,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use database::{Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::{Dep, globals};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	auditid_entry: Arc<Map>,
+}
+
+/// Entry of the audit log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+	/// When the action was taken
+	pub ts: MilliSecondsSinceUnixEpoch,
+
+	/// The user who took the action; None for commands from the server
+	/// console or executed at startup
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user: Option<OwnedUserId>,
+
+	pub kind: Kind,
+
+	/// The command line with secrets redacted, or the API endpoint and what
+	/// the privileges were used for
+	pub action: String,
+
+	/// The error the action failed with, if it did
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+	/// Admin command
+	Command,
+
+	/// Client API call which used admin privileges
+	Api,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				auditid_entry: args.db["auditid_entry"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Append an entry to the audit log. A failure to do so is logged rather
+/// than failing the action being recorded.
+#[implement(Service)]
+pub fn record(&self, kind: Kind, user: Option<&UserId>, action: String, error: Option<String>) {
+	let entry = Entry {
+		ts: MilliSecondsSinceUnixEpoch::now(),
+		user: user.map(ToOwned::to_owned),
+		kind,
+		action,
+		error,
+	};
+
+	match self.services.globals.next_count() {
+		| Ok(id) => self.db.auditid_entry.put(id, Json(&entry)),
+		| Err(e) => error!(?entry, "Failed to record audit log entry: {e}"),
+	}
+}
+
+/// Stream the entries recorded at or after `since`, newest first.
+#[implement(Service)]
+pub fn entries_since(
+	&self,
+	since: MilliSecondsSinceUnixEpoch,
+) -> impl Stream<Item = Entry> + Send + '_ {
+	self.db
+		.auditid_entry
+		.rev_stream::<u64, Entry>()
+		.ignore_err()
+		.map(|(_, entry)| entry)
+		.ready_take_while(move |entry| entry.ts >= since)
+}
+
+impl fmt::Display for Kind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Command => write!(f, "command"),
+			| Self::Api => write!(f, "api"),
+		}
+	}
+}
@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug, debug_warn, error, implement, info,
+	matrix::pdu::PduBuilder,
+	utils::{self, MutexMap, ReadyExt, stream::TryIgnore},
+	warn,
+};
+use database::Map;
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomAliasId, RoomOrAliasId,
+	UserId,
+	events::room::{
+		join_rules::JoinRule,
+		member::{MembershipState, RoomMemberEventContent},
+		power_levels::RoomPowerLevelsEventContent,
+	},
+};
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{
+	Dep,
+	admin::{self, ServerRoom},
+	globals, rooms, users,
+};
+
+pub struct Service {
+	interrupt: Notify,
+	/// Serializes creating the room of each alias
+	create: MutexMap<OwnedRoomAliasId, ()>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+	alias: Dep<rooms::alias::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	userroomid_autojoin: Arc<Map>,
+}
+
+/// Interval at which rooms which could not be joined yet are retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Time after which a room which could not be joined is given up on
+const RETRY_EXPIRE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			create: MutexMap::new(),
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: Data {
+				userroomid_autojoin: args.db["userroomid_autojoin"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = sleep(RETRY_INTERVAL) => (),
+			}
+
+			self.retry_pending().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Join (or invite) a newly registered local user to the rooms in
+/// `auto_join_rooms`. Rooms which cannot be joined yet are retried later;
+/// failures are logged and never returned, so registration is not failed by
+/// them.
+#[implement(Service)]
+pub async fn join(&self, user_id: &UserId) {
+	for room in &self.services.server.config.auto_join_rooms {
+		match self.join_room(user_id, room).await {
+			| Ok(true) => info!("Automatically joined room {room} for user {user_id}"),
+			| Ok(false) => {
+				debug!("Room {room} cannot be joined yet, retrying later for user {user_id}");
+				self.db.userroomid_autojoin.put_aput::<8, _, _>(
+					(user_id, room.as_str()),
+					utils::millis_since_unix_epoch(),
+				);
+			},
+			| Err(e) =>
+				error!("Failed to automatically join room {room} for user {user_id}: {e}"),
+		}
+	}
+}
+
+/// Retry the rooms which could not be joined at registration.
+#[implement(Service)]
+async fn retry_pending(&self) {
+	let pending: BTreeMap<(OwnedUserId, OwnedRoomOrAliasId), u64> = self
+		.db
+		.userroomid_autojoin
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|((user_id, room), queued): ((&UserId, &str), u64)| {
+			let room = RoomOrAliasId::parse(room).ok()?;
+			Some(((user_id.to_owned(), room), queued))
+		})
+		.collect()
+		.await;
+
+	let expire = utils::millis_since_unix_epoch()
+		.saturating_sub(RETRY_EXPIRE.as_millis().try_into().unwrap_or(u64::MAX));
+
+	for ((user_id, room), queued) in pending {
+		match self.join_room(&user_id, &room).await {
+			| Ok(false) if queued >= expire => continue,
+			| Ok(false) => warn!(
+				"Giving up automatically joining room {room} for user {user_id}: the room could \
+				 not be joined for a week"
+			),
+			| Ok(true) => info!("Automatically joined room {room} for user {user_id}"),
+			| Err(e) =>
+				error!("Failed to automatically join room {room} for user {user_id}: {e}"),
+		}
+
+		self.db.userroomid_autojoin.del((&user_id, room.as_str()));
+	}
+}
+
+/// Join or invite the user to the room. Returns false when the room does not
+/// exist or the server is not in it yet, so the attempt should be retried.
+#[implement(Service)]
+async fn join_room(&self, user_id: &UserId, room: &RoomOrAliasId) -> Result<bool> {
+	let config = &self.services.server.config;
+	let room_id = match self.services.alias.resolve(room).await {
+		| Ok(room_id) => room_id,
+		| Err(e) => match <&RoomAliasId>::try_from(room) {
+			| Ok(alias)
+				if config.auto_join_rooms_create
+					&& self.services.globals.server_is_ours(alias.server_name()) =>
+				self.create_room(alias).await?,
+			| _ => {
+				debug_warn!("Failed to resolve {room} to automatically join: {e}");
+				return Ok(false);
+			},
+		},
+	};
+
+	if !self
+		.services
+		.state_cache
+		.server_in_room(self.services.globals.server_name(), &room_id)
+		.await
+	{
+		return Ok(false);
+	}
+
+	if self.services.state_cache.is_joined(user_id, &room_id).await
+		|| (config.auto_join_rooms_invite
+			&& self
+				.services
+				.state_cache
+				.is_invited(user_id, &room_id)
+				.await)
+	{
+		return Ok(true);
+	}
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+	if config.auto_join_rooms_invite {
+		let server_user = &self.services.globals.server_user;
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(
+					user_id.to_string(),
+					&RoomMemberEventContent::new(MembershipState::Invite),
+				),
+				server_user,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+	} else {
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
+					displayname: self.services.users.displayname(user_id).await.ok(),
+					avatar_url: self.services.users.avatar_url(user_id).await.ok(),
+					reason: Some("Automatically joining this room upon registration".to_owned()),
+					..RoomMemberEventContent::new(MembershipState::Join)
+				}),
+				user_id,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+	}
+
+	Ok(true)
+}
+
+/// Create a public room owned by the server user for a local alias in
+/// `auto_join_rooms` which does not exist yet. Registrations racing to create
+/// the same room wait for the first, then find the room it created.
+#[implement(Service)]
+async fn create_room(&self, alias: &RoomAliasId) -> Result<OwnedRoomId> {
+	if alias == self.services.globals.admin_alias {
+		return Err!("Refusing to create the admin room alias {alias}");
+	}
+
+	let _create = self.create.lock(alias).await;
+	if let Ok(room_id) = self.services.alias.resolve_local_alias(alias).await {
+		return Ok(room_id);
+	}
+
+	let server_user = &self.services.globals.server_user;
+	let users = BTreeMap::from_iter([(server_user.clone(), 100.into())]);
+	let room_id = self
+		.services
+		.admin
+		.create_room(ServerRoom {
+			name: alias.alias().to_owned(),
+			alias: Some(alias),
+			join_rule: JoinRule::Public,
+			federate: true,
+			power_levels: RoomPowerLevelsEventContent { users, ..Default::default() },
+			state: Vec::new(),
+		})
+		.await?;
+
+	info!("Created room {room_id} for {alias} to automatically join users to");
+
+	Ok(room_id)
+}
@@ -0,0 +1,210 @@
+use std::{
+	collections::BTreeMap,
+	sync::{
+		Arc,
+		atomic::{AtomicU32, Ordering},
+	},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug, implement, info, utils::bytes::pretty, warn};
+use database::Database;
+use tokio::{
+	sync::{Mutex, Notify},
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, rooms};
+
+/// Adjusts the capacities of the in-memory and database caches at runtime,
+/// shrinking them when the allocator holds more memory than configured and
+/// restoring them once it no longer does.
+pub struct Service {
+	/// Capacities of the in-memory caches set at startup or by `set_capacity`
+	configured: Mutex<BTreeMap<&'static str, usize>>,
+
+	/// Caches run at their configured capacity divided by `2^shrink`
+	shrink: AtomicU32,
+
+	interrupt: Notify,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	db: Arc<Database>,
+	auth_chain: Dep<rooms::auth_chain::Service>,
+	spaces: Dep<rooms::spaces::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
+}
+
+/// In-memory caches, by the name given to `set_capacity`
+const CACHES: [&str; 3] = ["auth_chain", "stateinfo", "spacehierarchy"];
+
+const PRESSURE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Caches are shrunk to no less than 1/2^MAX_SHRINK of their capacity.
+const MAX_SHRINK: u32 = 4;
+
+/// Share of cache_shrink_resident_mb in percent below which the caches grow
+/// back towards their capacity.
+const RESTORE_PERCENT: usize = 80;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			configured: Mutex::new(BTreeMap::new()),
+			shrink: AtomicU32::new(0),
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				db: args.db.clone(),
+				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "caches", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let limit_mb = self.services.server.config.cache_shrink_resident_mb;
+		if limit_mb == 0 {
+			return Ok(());
+		}
+
+		if conduwuit::alloc::resident().is_none() {
+			warn!("cache_shrink_resident_mb is set but allocator statistics are unavailable");
+			return Ok(());
+		}
+
+		let limit = usize::try_from(limit_mb.saturating_mul(1024 * 1024))?;
+		let restore = limit / 100 * RESTORE_PERCENT;
+		let mut i = interval(PRESSURE_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let Some(resident) = conduwuit::alloc::resident() else {
+				continue;
+			};
+
+			let shrink = self.shrink.load(Ordering::Relaxed);
+			if resident > limit && shrink < MAX_SHRINK {
+				let resident = pretty(resident);
+				warn!(%resident, "Memory above cache_shrink_resident_mb; shrinking caches");
+				self.resize(shrink.saturating_add(1)).await?;
+				conduwuit::alloc::trim(None)?;
+			} else if resident < restore && shrink > 0 {
+				let resident = pretty(resident);
+				info!(%resident, "Memory below cache_shrink_resident_mb; growing caches back");
+				self.resize(shrink.saturating_sub(1)).await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Set the capacity of a cache in entries. The name is one of `auth_chain`,
+/// `stateinfo` or `spacehierarchy`, or a database column with a cache, e.g.
+/// `pduid_pdu` for events. While the caches are shrunk under memory pressure
+/// the cache is shrunk likewise, growing to `entries` once the pressure
+/// clears.
+#[implement(Service)]
+pub async fn set_capacity(&self, name: &str, entries: usize) -> Result {
+	let shrink = self.shrink.load(Ordering::Relaxed);
+	if let Some(cache) = CACHES.into_iter().find(|&cache| cache == name) {
+		self.configured.lock().await.insert(cache, entries);
+		self.set_memory_capacity(cache, shrunk(entries, shrink))
+			.await;
+	} else {
+		let bytes = self.services.db.db.set_cache_capacity(name, entries)?;
+		self.services.db.db.shrink_caches(shrink)?;
+		debug!(%name, entries, bytes, "Resized database cache");
+	}
+
+	Ok(())
+}
+
+/// Resize every cache to its capacity divided by `2^shrink`.
+#[implement(Service)]
+async fn resize(&self, shrink: u32) -> Result {
+	self.shrink.store(shrink, Ordering::Relaxed);
+
+	let mut configured = self.configured.lock().await;
+	for cache in CACHES {
+		let capacity = match configured.get(cache) {
+			| Some(&capacity) => capacity,
+			| None => {
+				let capacity = self.memory_capacity(cache).await;
+				configured.insert(cache, capacity);
+				capacity
+			},
+		};
+
+		self.set_memory_capacity(cache, shrunk(capacity, shrink))
+			.await;
+	}
+
+	drop(configured);
+	debug!(shrink, "Resized caches");
+
+	self.services.db.db.shrink_caches(shrink)
+}
+
+#[implement(Service)]
+async fn memory_capacity(&self, cache: &str) -> usize {
+	match cache {
+		| "auth_chain" => self.services.auth_chain.get_cache_usage().1,
+		| "stateinfo" => self
+			.services
+			.state_compressor
+			.stateinfo_cache
+			.lock()
+			.expect("locked")
+			.capacity(),
+		| "spacehierarchy" => self
+			.services
+			.spaces
+			.roomid_spacehierarchy_cache
+			.lock()
+			.await
+			.capacity(),
+		| _ => 0,
+	}
+}
+
+#[implement(Service)]
+async fn set_memory_capacity(&self, cache: &str, capacity: usize) {
+	match cache {
+		| "auth_chain" => self.services.auth_chain.set_cache_capacity(capacity),
+		| "stateinfo" => self
+			.services
+			.state_compressor
+			.stateinfo_cache
+			.lock()
+			.expect("locked")
+			.set_capacity(capacity),
+		| "spacehierarchy" => self
+			.services
+			.spaces
+			.roomid_spacehierarchy_cache
+			.lock()
+			.await
+			.set_capacity(capacity),
+		| _ => (),
+	}
+}
+
+fn shrunk(capacity: usize, shrink: u32) -> usize { capacity.checked_shr(shrink).unwrap_or(0) }
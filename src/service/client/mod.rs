@@ -1,6 +1,6 @@
 use std::{sync::Arc, time::Duration};
 
-use conduwuit::{Config, Result, err, implement, trace};
+use conduwuit::{Config, Result, config::proxy::ProxyConfig, err, implement, trace};
 use either::Either;
 use ipaddress::IPAddress;
 use reqwest::redirect;
@@ -55,7 +55,10 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				.redirect(redirect::Policy::limited(3))
 				.build()?,
 
-			well_known: base(config)?
+			well_known: config
+				.federation_proxy_well_known
+				.then(|| federation_base(config))
+				.unwrap_or_else(|| base(config))?
 				.dns_resolver(resolver.resolver.clone())
 				.connect_timeout(Duration::from_secs(config.well_known_conn_timeout))
 				.read_timeout(Duration::from_secs(config.well_known_timeout))
@@ -64,7 +67,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				.redirect(redirect::Policy::limited(4))
 				.build()?,
 
-			federation: base(config)?
+			federation: federation_base(config)?
 				.dns_resolver(resolver.resolver.hooked.clone())
 				.read_timeout(Duration::from_secs(config.federation_timeout))
 				.pool_max_idle_per_host(config.federation_idle_per_host.into())
@@ -72,14 +75,14 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				.redirect(redirect::Policy::limited(3))
 				.build()?,
 
-			synapse: base(config)?
+			synapse: federation_base(config)?
 				.dns_resolver(resolver.resolver.hooked.clone())
 				.read_timeout(Duration::from_secs(305))
 				.pool_max_idle_per_host(0)
 				.redirect(redirect::Policy::limited(3))
 				.build()?,
 
-			sender: base(config)?
+			sender: federation_base(config)?
 				.dns_resolver(resolver.resolver.hooked.clone())
 				.read_timeout(Duration::from_secs(config.sender_timeout))
 				.timeout(Duration::from_secs(config.sender_timeout))
@@ -119,6 +122,19 @@ fn name(&self) -> &str { service::make_name(std::module_path!()) }
 }
 
 fn base(config: &Config) -> Result<reqwest::ClientBuilder> {
+	proxied(builder(config), &config.proxy)
+}
+
+fn federation_base(config: &Config) -> Result<reqwest::ClientBuilder> {
+	let proxy = match &config.federation_proxy {
+		| ProxyConfig::None => &config.proxy,
+		| proxy => proxy,
+	};
+
+	proxied(builder(config), proxy)
+}
+
+fn builder(config: &Config) -> reqwest::ClientBuilder {
 	let mut builder = reqwest::Client::builder()
 		.hickory_dns(true)
 		.connect_timeout(Duration::from_secs(config.request_conn_timeout))
@@ -173,7 +189,14 @@ fn base(config: &Config) -> Result<reqwest::ClientBuilder> {
 		builder = builder.no_zstd();
 	};
 
-	match config.proxy.to_proxy()? {
+	builder
+}
+
+fn proxied(
+	builder: reqwest::ClientBuilder,
+	proxy: &ProxyConfig,
+) -> Result<reqwest::ClientBuilder> {
+	match proxy.to_proxy()? {
 		| Some(proxy) => Ok(builder.proxy(proxy)),
 		| _ => Ok(builder),
 	}
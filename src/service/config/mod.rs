@@ -3,15 +3,17 @@
 use async_trait::async_trait;
 use conduwuit::{
 	Result, Server,
-	config::{Config, check},
-	error, implement,
+	config::{Config, check, reload, reload::Changes},
+	error, implement, info,
+	log::EnvFilter,
 };
 
 pub struct Service {
 	server: Arc<Server>,
 }
 
-const SIGNAL: &str = "SIGUSR1";
+/// Signals on which the configuration is reloaded
+const SIGNALS: &[&str] = &["SIGHUP", "SIGUSR1"];
 
 #[async_trait]
 impl crate::Service for Service {
@@ -21,7 +23,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 
 	async fn worker(self: Arc<Self>) -> Result {
 		while self.server.running() {
-			if self.server.signal.subscribe().recv().await == Ok(SIGNAL) {
+			let signal = self.server.signal.subscribe().recv().await;
+			if signal.is_ok_and(|signal| SIGNALS.contains(&signal)) {
 				if let Err(e) = self.handle_reload() {
 					error!("Failed to reload config: {e}");
 				}
@@ -44,22 +47,38 @@ fn deref(&self) -> &Self::Target { &self.server.config }
 #[implement(Service)]
 fn handle_reload(&self) -> Result {
 	if self.server.config.config_reload_signal {
-		#[cfg(all(feature = "systemd", target_os = "linux"))]
-		sd_notify::notify(true, &[sd_notify::NotifyState::Reloading])
-			.expect("failed to notify systemd of reloading state");
-
 		self.reload(iter::empty())?;
-
-		#[cfg(all(feature = "systemd", target_os = "linux"))]
-		sd_notify::notify(true, &[sd_notify::NotifyState::Ready])
-			.expect("failed to notify systemd of ready state");
 	}
 
 	Ok(())
 }
 
+/// Load the configuration again and make it active. Returns the keys which
+/// changed, split into those which took effect and those which require a
+/// restart.
+#[implement(Service)]
+pub fn reload<'a, I>(&self, paths: I) -> Result<Changes>
+where
+	I: Iterator<Item = &'a Path>,
+{
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	sd_notify::notify(false, &[
+		sd_notify::NotifyState::Reloading,
+		sd_notify::NotifyState::monotonic_usec_now().expect("failed to read monotonic clock"),
+	])
+	.expect("failed to notify systemd of reloading state");
+
+	let result = self.apply(paths);
+
+	#[cfg(all(feature = "systemd", target_os = "linux"))]
+	sd_notify::notify(false, &[sd_notify::NotifyState::Ready])
+		.expect("failed to notify systemd of ready state");
+
+	result
+}
+
 #[implement(Service)]
-pub fn reload<'a, I>(&self, paths: I) -> Result<Arc<Config>>
+fn apply<'a, I>(&self, paths: I) -> Result<Changes>
 where
 	I: Iterator<Item = &'a Path>,
 {
@@ -67,5 +86,21 @@ pub fn reload<'a, I>(&self, paths: I) -> Result<Arc<Config>>
 	let new = Config::load(paths).and_then(|raw| Config::new(&raw))?;
 
 	check::reload(&old, &new)?;
-	self.server.config.update(new)
+	let changes = Changes::new(&old, &new);
+	self.server.config.update(new)?;
+
+	if changes.applied.iter().any(|key| key == "log") {
+		let filter = EnvFilter::try_new(&self.server.config.log)?;
+		self.server.log.reload.reload(&filter, Some(&["console"]))?;
+	}
+
+	self.server.signal(reload::SIGNAL)?;
+
+	info!(
+		applied = ?changes.applied,
+		restart = ?changes.restart,
+		"Configuration reloaded"
+	);
+
+	Ok(changes)
 }
@@ -0,0 +1,476 @@
+use std::{
+	collections::BTreeSet,
+	fmt::Write,
+	mem,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Error, Result, Server, debug, debug_warn, err,
+	http::StatusCode,
+	implement,
+	matrix::pdu::PduBuilder,
+	utils::{self, MutexMap, ReadyExt, stream::TryIgnore},
+	warn,
+};
+use database::{Ignore, Interfix, Map};
+use futures::{Stream, StreamExt};
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId, api::client::error::ErrorKind,
+	events::TimelineEventType, serde::Raw,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{Dep, rooms, spam_checker, spam_checker::Check, users};
+
+pub struct Service {
+	/// Scheduled delayed events ordered by the time they are due to be sent,
+	/// in milliseconds since the unix epoch.
+	timers: Mutex<BTreeSet<(u64, String)>>,
+	/// Held while a delayed event is claimed to be sent, restarted or
+	/// cancelled, so only one of them takes effect.
+	mutex: MutexMap<String, ()>,
+	wake: Notify,
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	directory: Dep<rooms::directory::Service>,
+	spam_checker: Dep<spam_checker::Service>,
+	state: Dep<rooms::state::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	delayid_delayedevent: Arc<Map>,
+	userid_delayid: Arc<Map>,
+}
+
+/// Event scheduled to be sent after a delay (MSC4140).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DelayedEvent {
+	pub delay_id: String,
+	pub room_id: OwnedRoomId,
+	pub sender: OwnedUserId,
+
+	#[serde(rename = "type")]
+	pub event_type: TimelineEventType,
+
+	/// Set for state events
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub state_key: Option<String>,
+
+	pub content: Box<RawJsonValue>,
+
+	/// Delay in milliseconds after which the event is sent
+	pub delay: u64,
+
+	/// Time the delay was last (re)started at, in milliseconds since the unix
+	/// epoch
+	pub running_since: u64,
+}
+
+/// Action requested on a scheduled delayed event.
+#[derive(Clone, Copy, Debug)]
+pub enum UpdateAction {
+	/// Start the delay over
+	Restart,
+
+	/// Send the event now
+	Send,
+
+	/// Drop the event without sending it
+	Cancel,
+}
+
+/// Length of randomly generated delay IDs
+const DELAY_ID_LENGTH: usize = 24;
+
+/// Time the worker sleeps for when no delayed event is scheduled
+const IDLE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			timers: Mutex::new(BTreeSet::new()),
+			mutex: MutexMap::new(),
+			wake: Notify::new(),
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
+				spam_checker: args.depend::<spam_checker::Service>("spam_checker"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				delayid_delayedevent: args.db["delayid_delayedevent"].clone(),
+				userid_delayid: args.db["userid_delayid"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		self.delayed_events()
+			.ready_for_each(|event| {
+				self.timers
+					.lock()
+					.expect("locked")
+					.insert((event.send_at(), event.delay_id));
+			})
+			.await;
+
+		loop {
+			let next = self
+				.timers
+				.lock()
+				.expect("locked")
+				.first()
+				.map(|(send_at, _)| *send_at);
+
+			let timeout = next.map_or(IDLE_INTERVAL, |send_at| {
+				Duration::from_millis(send_at.saturating_sub(utils::millis_since_unix_epoch()))
+			});
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.wake.notified() => continue,
+				() = sleep(timeout) => (),
+			}
+
+			self.send_due().await;
+		}
+
+		Ok(())
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let timers = self.timers.lock().expect("locked").len();
+		writeln!(out, "delayed_event_timers: {timers}")?;
+
+		let mutex = self.mutex.len();
+		writeln!(out, "delayed_event_mutex: {mutex}")?;
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl DelayedEvent {
+	/// Time the event is due to be sent at, in milliseconds since the unix
+	/// epoch.
+	#[must_use]
+	pub fn send_at(&self) -> u64 { self.running_since.saturating_add(self.delay) }
+}
+
+/// Whether clients may schedule delayed events.
+#[implement(Service)]
+#[must_use]
+pub fn enabled(&self) -> bool { self.services.server.config.max_event_delay > 0 }
+
+/// Schedule an event of `sender` to be sent into `room_id` once `delay` has
+/// passed without the delay being restarted. Returns the delay ID the event
+/// can be updated with; scheduling a state event identical to one already
+/// scheduled returns the ID of that one.
+#[implement(Service)]
+pub async fn schedule(
+	&self,
+	sender: &UserId,
+	room_id: &RoomId,
+	event_type: TimelineEventType,
+	state_key: Option<&str>,
+	content: &RawJsonValue,
+	delay: Duration,
+) -> Result<String> {
+	let config = &self.services.server.config;
+	if !self.enabled() {
+		return Err!(Request(Unrecognized("Delayed events are not enabled on this server.")));
+	}
+
+	if delay > Duration::from_secs(config.max_event_delay) {
+		return Err!(Request(InvalidParam(
+			"The delay may be at most {} seconds.",
+			config.max_event_delay
+		)));
+	}
+
+	if let Some(state_key) = state_key {
+		let existing = self
+			.delayed_events_for(sender)
+			.ready_filter(|event| {
+				event.room_id == room_id
+					&& event.event_type == event_type
+					&& event.state_key.as_deref() == Some(state_key)
+					&& event.content.get() == content.get()
+					&& u128::from(event.delay) == delay.as_millis()
+			})
+			.boxed()
+			.next()
+			.await;
+
+		if let Some(event) = existing {
+			return Ok(event.delay_id);
+		}
+	}
+
+	let scheduled = self.delay_ids_for(sender).count().await;
+
+	if scheduled >= config.max_delayed_events_per_user {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many delayed events are scheduled; cancel some first.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let event = DelayedEvent {
+		delay_id: utils::random_string(DELAY_ID_LENGTH),
+		room_id: room_id.to_owned(),
+		sender: sender.to_owned(),
+		event_type,
+		state_key: state_key.map(ToOwned::to_owned),
+		content: content.to_owned(),
+		delay: delay.as_millis().try_into()?,
+		running_since: utils::millis_since_unix_epoch(),
+	};
+
+	self.check(&event).await?;
+
+	debug!(delay_id = %event.delay_id, %sender, %room_id, ?delay, "Scheduling delayed event");
+	self.put(&event)?;
+
+	Ok(event.delay_id)
+}
+
+/// Restart, send or cancel a delayed event of `sender`.
+#[implement(Service)]
+pub async fn update(&self, sender: &UserId, delay_id: &str, action: UpdateAction) -> Result {
+	let _lock = self.mutex.lock(delay_id).await;
+	let mut event = self
+		.get(delay_id)
+		.await
+		.ok()
+		.filter(|event| event.sender == sender)
+		.ok_or_else(|| err!(Request(NotFound("Delayed event {delay_id:?} not found."))))?;
+
+	self.unschedule(&event);
+	match action {
+		| UpdateAction::Restart => {
+			event.running_since = utils::millis_since_unix_epoch();
+			self.put(&event)?;
+		},
+		| UpdateAction::Send => self.send(&event).await?,
+		| UpdateAction::Cancel => {
+			debug!(%delay_id, %sender, "Cancelled delayed event");
+		},
+	}
+
+	Ok(())
+}
+
+/// All delayed events scheduled by `sender`.
+#[implement(Service)]
+pub fn delayed_events_for<'a>(
+	&'a self,
+	sender: &'a UserId,
+) -> impl Stream<Item = DelayedEvent> + Send + 'a {
+	self.delay_ids_for(sender)
+		.then(|delay_id| self.get(delay_id))
+		.ignore_err()
+}
+
+#[implement(Service)]
+fn delay_ids_for<'a>(&'a self, sender: &'a UserId) -> impl Stream<Item = &'a str> + Send + 'a {
+	let prefix = (sender, Interfix);
+	self.db
+		.userid_delayid
+		.keys_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, delay_id): (Ignore, &str)| delay_id)
+}
+
+#[implement(Service)]
+fn delayed_events(&self) -> impl Stream<Item = DelayedEvent> + Send + '_ {
+	self.db
+		.delayid_delayedevent
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(_, value)| serde_json::from_slice(value).ok())
+}
+
+#[implement(Service)]
+async fn get(&self, delay_id: &str) -> Result<DelayedEvent> {
+	self.db
+		.delayid_delayedevent
+		.get(delay_id)
+		.await
+		.and_then(|value| {
+			serde_json::from_slice(&value)
+				.map_err(|e| err!(Database("Invalid delayed event {delay_id:?}: {e}")))
+		})
+}
+
+/// Persist a delayed event and (re)arm its timer.
+#[implement(Service)]
+fn put(&self, event: &DelayedEvent) -> Result {
+	let value = serde_json::to_vec(event)?;
+	self.db.delayid_delayedevent.insert(&event.delay_id, value);
+	self.db
+		.userid_delayid
+		.put_raw((&event.sender, &event.delay_id), []);
+
+	self.timers
+		.lock()
+		.expect("locked")
+		.insert((event.send_at(), event.delay_id.clone()));
+
+	self.wake.notify_one();
+
+	Ok(())
+}
+
+/// Drop a delayed event and its timer.
+#[implement(Service)]
+fn unschedule(&self, event: &DelayedEvent) {
+	self.db.delayid_delayedevent.remove(&event.delay_id);
+	self.db.userid_delayid.del((&event.sender, &event.delay_id));
+
+	self.timers
+		.lock()
+		.expect("locked")
+		.remove(&(event.send_at(), event.delay_id.clone()));
+}
+
+/// Send all delayed events which are due.
+#[implement(Service)]
+async fn send_due(&self) {
+	let now = utils::millis_since_unix_epoch();
+	let due: Vec<_> = {
+		let mut timers = self.timers.lock().expect("locked");
+		let pending = timers.split_off(&(now.saturating_add(1), String::new()));
+		mem::replace(&mut *timers, pending)
+			.into_iter()
+			.map(|(_, delay_id)| delay_id)
+			.collect()
+	};
+
+	for delay_id in due {
+		let _lock = self.mutex.lock(delay_id.as_str()).await;
+		let Ok(event) = self.get(&delay_id).await else {
+			continue;
+		};
+
+		// restarted since the timer was armed
+		if event.send_at() > now {
+			continue;
+		}
+
+		self.unschedule(&event);
+		if let Err(e) = self.send(&event).await {
+			warn!(
+				%delay_id,
+				sender = %event.sender,
+				room_id = %event.room_id,
+				"Failed to send delayed event: {e}"
+			);
+		}
+	}
+}
+
+#[implement(Service)]
+async fn send(&self, event: &DelayedEvent) -> Result {
+	self.check(event).await?;
+
+	let state_lock = self.services.state.mutex.lock(&event.room_id).await;
+	let event_id = self
+		.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				event_type: event.event_type.clone(),
+				content: event.content.clone(),
+				state_key: event.state_key.as_deref().map(Into::into),
+				..Default::default()
+			},
+			&event.sender,
+			&event.room_id,
+			&state_lock,
+		)
+		.await
+		.inspect_err(
+			|e| debug_warn!(delay_id = %event.delay_id, "Delayed event rejected: {e}"),
+		)?;
+
+	debug!(delay_id = %event.delay_id, %event_id, "Sent delayed event");
+
+	Ok(())
+}
+
+/// Checks the event must pass on top of the auth rules, the same as events
+/// sent right away. Run when the event is scheduled and again when it is
+/// sent, as the room may have changed in between.
+#[implement(Service)]
+async fn check(&self, event: &DelayedEvent) -> Result {
+	match event.state_key.as_deref() {
+		| Some(state_key) => {
+			self.services
+				.state
+				.allowed_to_send_state_event(
+					&event.room_id,
+					&event.event_type.to_string().into(),
+					state_key,
+					&Raw::from_json(event.content.clone()),
+				)
+				.await?;
+		},
+		| None => {
+			if event.event_type == TimelineEventType::RoomEncrypted
+				&& !self.services.server.config.allow_encryption
+			{
+				return Err!(Request(Forbidden("Encryption has been disabled")));
+			}
+
+			if event.event_type != TimelineEventType::RoomMessage
+				&& self.services.users.is_guest(&event.sender).await
+			{
+				return Err!(Request(GuestAccessForbidden(
+					"Guests may only send m.room.message events"
+				)));
+			}
+
+			if event.event_type == TimelineEventType::CallInvite
+				&& self.services.directory.is_public_room(&event.room_id).await
+			{
+				return Err!(Request(Forbidden(
+					"Room call invites are not allowed in public rooms"
+				)));
+			}
+		},
+	}
+
+	self.services
+		.spam_checker
+		.check(&Check::Event {
+			event_id: None,
+			room_id: &event.room_id,
+			sender: &event.sender,
+			kind: &event.event_type,
+			state_key: event.state_key.as_deref(),
+			content: &event.content,
+		})
+		.await
+		.allowed()
+}
@@ -0,0 +1,369 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Error, Result, Server, debug, err, http::StatusCode, implement, utils};
+use database::{Deserialized, Map};
+use lettre::{
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+	message::{Mailbox, header::ContentType},
+};
+use ruma::{
+	ClientSecret, OwnedClientSecret, OwnedSessionId, OwnedUserId, SessionId, UInt, UserId,
+	api::client::error::ErrorKind,
+};
+use url::Url;
+
+pub struct Service {
+	transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+	sessions: RwLock<Sessions>,
+	last_sent: RwLock<LastSent>,
+	server: Arc<Server>,
+	db: Data,
+}
+
+struct Data {
+	email_userid: Arc<Map>,
+	userid_email: Arc<Map>,
+}
+
+type Sessions = HashMap<OwnedSessionId, Session>;
+type LastSent = HashMap<String, SystemTime>;
+
+/// Email validation session started by a password reset request.
+#[derive(Clone, Debug)]
+struct Session {
+	client_secret: OwnedClientSecret,
+	user_id: OwnedUserId,
+	token: String,
+	send_attempt: UInt,
+	next_link: Option<String>,
+	expires: SystemTime,
+	validated: bool,
+}
+
+/// Path of the endpoint the links sent by email point to
+pub const VALIDATE_PATH: &str = "/_conduwuit/email/validate";
+
+/// Maximum number of concurrent validation sessions
+const MAX_SESSIONS: usize = 1024;
+
+/// Length of the randomly generated session ID
+const SESSION_ID_LENGTH: usize = 32;
+
+/// Length of the randomly generated validation token
+const TOKEN_LENGTH: usize = 32;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let transport = args
+			.server
+			.config
+			.smtp
+			.connection_uri
+			.as_deref()
+			.map(AsyncSmtpTransport::<Tokio1Executor>::from_url)
+			.transpose()
+			.map_err(|e| err!(Config("smtp.connection_uri", "Invalid SMTP URL: {e}")))?
+			.map(|builder| builder.build());
+
+		Ok(Arc::new(Self {
+			transport,
+			sessions: RwLock::new(Sessions::new()),
+			last_sent: RwLock::new(LastSent::new()),
+			server: args.server.clone(),
+			db: Data {
+				email_userid: args.db["email_userid"].clone(),
+				userid_email: args.db["userid_email"].clone(),
+			},
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let sessions = self.sessions.read().expect("locked for reading").len();
+		let last_sent = self.last_sent.read().expect("locked for reading").len();
+		writeln!(out, "email_sessions: {sessions}")?;
+		writeln!(out, "email_last_sent: {last_sent}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.last_sent.write().expect("locked for writing").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether an SMTP server to send emails through is configured.
+#[implement(Service)]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.transport.is_some() }
+
+/// Whether users may reset their password through an email bound to their
+/// account.
+#[implement(Service)]
+#[must_use]
+pub fn password_reset_enabled(&self) -> bool {
+	self.transport.is_some() && self.server.config.smtp.allow_password_reset
+}
+
+/// Start a password reset by sending a validation link to `email`. Repeated
+/// requests with the same client secret and a send attempt that is not
+/// higher than the last one return the existing session without sending
+/// another email.
+#[implement(Service)]
+pub async fn request_password_reset(
+	&self,
+	email: &str,
+	client_secret: &ClientSecret,
+	send_attempt: UInt,
+	next_link: Option<String>,
+) -> Result<OwnedSessionId> {
+	if !self.password_reset_enabled() {
+		return Err!(Request(ThreepidDenied("Password reset via email is not enabled.")));
+	}
+
+	let email = normalize(email);
+	let user_id = self
+		.find_user(&email)
+		.await
+		.map_err(|_| err!(Request(ThreepidNotFound("Email is not bound to any account."))))?;
+
+	if next_link
+		.as_deref()
+		.map(Url::parse)
+		.is_some_and(|link| !link.is_ok_and(|link| matches!(link.scheme(), "http" | "https")))
+	{
+		return Err!(Request(InvalidParam("next_link must be an http or https URL.")));
+	}
+
+	let now = SystemTime::now();
+	let existing = self
+		.sessions
+		.read()
+		.expect("locked for reading")
+		.iter()
+		.find(|(_, session)| {
+			*session.client_secret == *client_secret
+				&& session.user_id == user_id
+				&& session.send_attempt >= send_attempt
+				&& session.expires > now
+		})
+		.map(|(sid, _)| sid.clone());
+
+	if let Some(sid) = existing {
+		return Ok(sid);
+	}
+
+	self.check_rate_limit(&email, now)?;
+
+	let config = &self.server.config.smtp;
+	let sid: OwnedSessionId = utils::random_string(SESSION_ID_LENGTH).try_into()?;
+	let session = Session {
+		client_secret: client_secret.to_owned(),
+		user_id,
+		token: utils::random_string(TOKEN_LENGTH),
+		send_attempt,
+		next_link,
+		expires: utils::time::timepoint_from_now(Duration::from_secs(config.token_lifetime))?,
+		validated: false,
+	};
+
+	let link = format!(
+		"{}{VALIDATE_PATH}?sid={sid}&client_secret={client_secret}&token={}",
+		self.base_url(),
+		session.token,
+	);
+
+	let body = format!(
+		"A password reset was requested for the Matrix account {}.\n\nTo confirm the reset, \
+		 open the following link within {}:\n\n{link}\n\nIf you did not request this, you can \
+		 safely ignore this email.\n",
+		session.user_id,
+		utils::time::pretty(Duration::from_secs(config.token_lifetime)),
+	);
+
+	{
+		let mut sessions = self.sessions.write().expect("locked for writing");
+		sessions.retain(|_, session| session.expires > now);
+		if sessions.len() >= MAX_SESSIONS {
+			return Err(limit_exceeded("Too many password reset requests; try again later."));
+		}
+
+		sessions.insert(sid.clone(), session);
+	}
+
+	self.send(&email, "Reset your password", body).await?;
+	debug!(%sid, "Sent password reset email");
+
+	Ok(sid)
+}
+
+/// Mark a session as validated after the user followed the link sent to
+/// them. Returns the URL the user asked to be sent to afterwards, if any.
+#[implement(Service)]
+pub fn validate(
+	&self,
+	sid: &SessionId,
+	client_secret: &ClientSecret,
+	token: &str,
+) -> Result<Option<String>> {
+	let now = SystemTime::now();
+	let mut sessions = self.sessions.write().expect("locked for writing");
+	let Some(session) = sessions
+		.get_mut(sid)
+		.filter(|session| *session.client_secret == *client_secret && session.expires > now)
+	else {
+		return Err!(Request(ThreepidAuthFailed("Unknown or expired session.")));
+	};
+
+	if session.token != token {
+		return Err!(Request(ThreepidAuthFailed("Invalid validation token.")));
+	}
+
+	session.validated = true;
+
+	Ok(session.next_link.clone())
+}
+
+/// Consume a validated session, returning the user whose password may be
+/// reset.
+#[implement(Service)]
+pub fn take_validated(
+	&self,
+	sid: &SessionId,
+	client_secret: &ClientSecret,
+) -> Result<OwnedUserId> {
+	let now = SystemTime::now();
+	let mut sessions = self.sessions.write().expect("locked for writing");
+	if !sessions.get(sid).is_some_and(|session| {
+		*session.client_secret == *client_secret && session.expires > now && session.validated
+	}) {
+		return Err!(Request(ThreepidAuthFailed("Email has not been validated.")));
+	}
+
+	let session = sessions.remove(sid).expect("session exists");
+
+	Ok(session.user_id)
+}
+
+/// Bind an email address to an account, or unbind it if None.
+#[implement(Service)]
+pub async fn set_email(&self, user_id: &UserId, email: Option<&str>) -> Result {
+	let email = email.map(normalize);
+	if let Some(email) = &email {
+		if email.parse::<lettre::Address>().is_err() {
+			return Err!(Request(InvalidParam("{email:?} is not a valid email address.")));
+		}
+
+		if self
+			.find_user(email)
+			.await
+			.is_ok_and(|owner| owner != user_id)
+		{
+			return Err!(Request(ThreepidInUse("Email is already bound to another account.")));
+		}
+	}
+
+	if let Ok(current) = self.email(user_id).await {
+		self.db.email_userid.remove(&current);
+		self.db.userid_email.remove(user_id);
+	}
+
+	if let Some(email) = &email {
+		self.db.email_userid.insert(email, user_id);
+		self.db.userid_email.insert(user_id, email);
+	}
+
+	Ok(())
+}
+
+/// Get the email address bound to an account.
+#[implement(Service)]
+pub async fn email(&self, user_id: &UserId) -> Result<String> {
+	self.db.userid_email.get(user_id).await.deserialized()
+}
+
+/// Find the account an email address is bound to.
+#[implement(Service)]
+pub async fn find_user(&self, email: &str) -> Result<OwnedUserId> {
+	self.db
+		.email_userid
+		.get(&normalize(email))
+		.await
+		.deserialized()
+}
+
+/// Send a plain text email through the configured SMTP server.
+#[implement(Service)]
+pub async fn send(&self, to: &str, subject: &str, body: String) -> Result {
+	let Some(transport) = &self.transport else {
+		return Err!(FeatureDisabled("email"));
+	};
+
+	let sender: Mailbox = self
+		.server
+		.config
+		.smtp
+		.sender
+		.as_deref()
+		.ok_or_else(|| err!(Config("smtp.sender", "No sender configured.")))?
+		.parse()
+		.map_err(|e| err!(Config("smtp.sender", "Invalid sender: {e}")))?;
+
+	let recipient: Mailbox = to
+		.parse()
+		.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))?;
+
+	let message = Message::builder()
+		.from(sender)
+		.to(recipient)
+		.subject(subject)
+		.header(ContentType::TEXT_PLAIN)
+		.body(body)
+		.map_err(|e| err!("Failed to build email: {e}"))?;
+
+	transport
+		.send(message)
+		.await
+		.map_err(|e| err!(BadServerResponse(error!("Failed to send email: {e}"))))?;
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn check_rate_limit(&self, email: &str, now: SystemTime) -> Result {
+	let interval = Duration::from_secs(self.server.config.smtp.request_interval);
+	let mut last_sent = self.last_sent.write().expect("locked for writing");
+	last_sent.retain(|_, sent| now.duration_since(*sent).is_ok_and(|age| age < interval));
+	if last_sent.contains_key(email) {
+		return Err(limit_exceeded("An email was sent to this address recently."));
+	}
+
+	last_sent.insert(email.to_owned(), now);
+
+	Ok(())
+}
+
+/// Base URL of the links sent by email.
+#[implement(Service)]
+pub fn base_url(&self) -> String {
+	self.server.config.well_known.client.as_ref().map_or_else(
+		|| format!("https://{}", self.server.config.server_name),
+		|url| url.as_str().trim_end_matches('/').to_owned(),
+	)
+}
+
+fn limit_exceeded(message: &'static str) -> Error {
+	Error::Request(
+		ErrorKind::LimitExceeded { retry_after: None },
+		message.into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	)
+}
+
+fn normalize(email: &str) -> String { email.trim().to_lowercase() }
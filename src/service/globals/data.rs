@@ -10,6 +10,7 @@ pub struct Data {
 }
 
 const COUNTER: &[u8] = b"c";
+const MAINTENANCE_MESSAGE: &[u8] = b"maintenance_message";
 
 impl Data {
 	pub(super) fn new(args: &crate::Args<'_>) -> Self {
@@ -73,8 +74,22 @@ pub fn bump_database_version(&self, new_version: u64) {
 		self.global.raw_put(b"version", new_version);
 	}
 
+	pub(super) fn maintenance_message(&self) -> Option<String> {
+		self.global
+			.get_blocking(MAINTENANCE_MESSAGE)
+			.deserialized()
+			.ok()
+	}
+
+	pub(super) fn set_maintenance_message(&self, message: Option<&str>) {
+		match message {
+			| Some(message) => self.global.insert(MAINTENANCE_MESSAGE, message),
+			| None => self.global.remove(MAINTENANCE_MESSAGE),
+		}
+	}
+
 	#[inline]
-	pub fn backup(&self) -> Result { self.db.db.backup() }
+	pub fn backup(&self, incremental: bool) -> Result { self.db.db.backup(incremental) }
 
 	#[inline]
 	pub fn backup_list(&self) -> Result<String> { self.db.db.backup_list() }
@@ -24,6 +24,10 @@ pub struct Service {
 	pub admin_alias: OwnedRoomAliasId,
 	pub turn_secret: String,
 	pub registration_token: Option<String>,
+
+	/// Message returned to users other than admins while the server is in
+	/// maintenance mode; persisted across restarts.
+	maintenance: RwLock<Option<String>>,
 }
 
 type RateLimitState = (Instant, u32); // Time if last failed try, number of failed tries
@@ -59,6 +63,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			},
 		);
 
+		let maintenance = RwLock::new(db.maintenance_message());
+
 		Ok(Arc::new(Self {
 			db,
 			server: args.server.clone(),
@@ -72,6 +78,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			.expect("@conduit:server_name is valid"),
 			turn_secret,
 			registration_token,
+			maintenance,
 		}))
 	}
 
@@ -111,6 +118,18 @@ pub fn current_count(&self) -> Result<u64> { Ok(self.db.current_count()) }
 	#[inline]
 	pub fn server_name(&self) -> &ServerName { self.server.name.as_ref() }
 
+	/// The maintenance message, while the server is in maintenance mode.
+	#[must_use]
+	pub fn maintenance(&self) -> Option<String> {
+		self.maintenance.read().expect("locked").clone()
+	}
+
+	/// Enter maintenance mode with the message, or leave it with None.
+	pub fn set_maintenance(&self, message: Option<String>) {
+		self.db.set_maintenance_message(message.as_deref());
+		*self.maintenance.write().expect("locked") = message;
+	}
+
 	pub fn allow_public_room_directory_over_federation(&self) -> bool {
 		self.server
 			.config
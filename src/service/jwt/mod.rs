@@ -0,0 +1,197 @@
+use std::{
+	str::FromStr,
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, Server, debug, debug_warn, err, implement};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use ruma::{OwnedUserId, UserId};
+use serde_json::{Map as JsonObject, Value as JsonValue};
+
+use crate::{Dep, client, globals, users};
+
+pub struct Service {
+	jwks: RwLock<Option<(JwkSet, Instant)>>,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+}
+
+/// Login type of JWT logins, as implemented by Synapse
+pub const LOGIN_TYPE: &str = "org.matrix.login.jwt";
+
+/// Login type of JWT logins in stable form
+pub const LOGIN_TYPE_STABLE: &str = "m.login.jwt";
+
+/// Duration for which the key set is cached
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Minimum duration between two fetches of the key set, which is refetched
+/// early when a token is signed with an unknown key
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			jwks: RwLock::new(None),
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	async fn clear_cache(&self) { self.jwks.write().expect("locked for writing").take(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether users may log in with a JSON Web Token.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn enabled(&self) -> bool { self.services.server.config.jwt.enable }
+
+/// Validate a JSON Web Token and find out which user it logs in. Users
+/// unknown to this server are registered if `register_user` is enabled.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn find_from_token(&self, token: &str) -> Result<OwnedUserId> {
+	let config = &self.services.server.config.jwt;
+	let algorithm = Algorithm::from_str(&config.algorithm)
+		.map_err(|e| err!(Config("jwt.algorithm", "Invalid JWT algorithm: {e}")))?;
+
+	let key = self.decoding_key(token, algorithm).await?;
+
+	let mut validation = Validation::new(algorithm);
+	if let Some(issuer) = &config.issuer {
+		validation.set_issuer(&[issuer]);
+	}
+
+	if config.audience.is_empty() {
+		validation.validate_aud = false;
+	} else {
+		validation.set_audience(&config.audience);
+	}
+
+	let claims = jsonwebtoken::decode::<JsonObject<String, JsonValue>>(token, &key, &validation)
+		.map_err(|e| err!(Request(Forbidden(debug_warn!("Invalid JSON Web Token: {e}")))))?
+		.claims;
+
+	let localpart = claims
+		.get(&config.localpart_claim)
+		.and_then(JsonValue::as_str)
+		.ok_or_else(|| {
+			err!(Request(Forbidden(
+				"JSON Web Token is missing the `{}` claim.",
+				config.localpart_claim
+			)))
+		})?;
+
+	let user_id = UserId::parse_with_server_name(localpart, self.services.globals.server_name())
+		.map_err(|e| err!(Request(InvalidUsername("Username is invalid: {e}"))))?;
+
+	if !self.services.globals.user_is_local(&user_id) {
+		return Err!(Request(Unknown("User ID does not belong to this homeserver")));
+	}
+
+	let users = &self.services.users;
+	if !users.exists(&user_id).await {
+		if !config.register_user {
+			return Err!(Request(Forbidden("User {user_id} does not exist.")));
+		}
+
+		users.provision(&user_id, None, "a JSON Web Token").await?;
+	} else if users.is_deactivated(&user_id).await.unwrap_or(false) {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	Ok(user_id)
+}
+
+/// The key the token is signed with: the configured secret for the HMAC
+/// algorithms, otherwise the key of the token's `kid` from the key set.
+#[implement(Service)]
+async fn decoding_key(&self, token: &str, algorithm: Algorithm) -> Result<DecodingKey> {
+	let config = &self.services.server.config.jwt;
+	if matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+		let secret = config
+			.secret
+			.as_deref()
+			.ok_or_else(|| err!(Config("jwt.secret", "JWT secret is not set.")))?;
+
+		return Ok(DecodingKey::from_secret(secret.as_bytes()));
+	}
+
+	let kid = jsonwebtoken::decode_header(token)
+		.map_err(|e| err!(Request(Forbidden("Invalid JSON Web Token header: {e}"))))?
+		.kid
+		.ok_or_else(|| err!(Request(Forbidden("JSON Web Token header is missing a `kid`."))))?;
+
+	let cached = self
+		.jwks
+		.read()
+		.expect("locked for reading")
+		.as_ref()
+		.filter(|(_, fetched)| fetched.elapsed() < JWKS_CACHE_TTL)
+		.map(|(jwks, fetched)| (jwks.find(&kid).cloned(), *fetched));
+
+	let jwk = match cached {
+		| Some((Some(jwk), _)) => jwk,
+		| Some((None, fetched)) if fetched.elapsed() < JWKS_REFRESH_INTERVAL =>
+			return Err!(Request(Forbidden("JSON Web Token is signed with an unknown key."))),
+		| _ => self
+			.fetch_jwks()
+			.await?
+			.find(&kid)
+			.cloned()
+			.ok_or_else(|| {
+				err!(Request(Forbidden("JSON Web Token is signed with an unknown key.")))
+			})?,
+	};
+
+	DecodingKey::from_jwk(&jwk)
+		.map_err(|e| err!(BadServerResponse("Invalid key {kid} in JSON Web Key Set: {e}")))
+}
+
+#[implement(Service)]
+async fn fetch_jwks(&self) -> Result<JwkSet> {
+	let url = self
+		.services
+		.server
+		.config
+		.jwt
+		.jwks_url
+		.as_ref()
+		.ok_or_else(|| err!(Config("jwt.jwks_url", "JSON Web Key Set URL is not set.")))?;
+
+	debug!(%url, "Fetching JSON Web Key Set");
+	let response = self.services.client.default.get(url.clone()).send().await?;
+
+	if !response.status().is_success() {
+		debug_warn!(status = ?response.status(), "Fetching JSON Web Key Set failed");
+		return Err!(BadServerResponse(
+			"Fetching JSON Web Key Set failed: {}",
+			response.status()
+		));
+	}
+
+	let jwks: JwkSet = serde_json::from_str(&response.text().await?)?;
+
+	self.jwks
+		.write()
+		.expect("locked for writing")
+		.replace((jwks.clone(), Instant::now()));
+
+	Ok(jwks)
+}
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+#[cfg(feature = "ldap")]
+use conduwuit::{Err, Error, debug, debug_warn, err, info, warn};
+use conduwuit::{Result, Server, implement};
+use database::Map;
+#[cfg(feature = "ldap")]
+use ldap3::{LdapConnAsync, LdapResult, Scope, SearchEntry, SearchResult, ldap_escape};
+use ruma::UserId;
+
+use crate::{Dep, admin, email, users};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+}
+
+#[cfg_attr(not(feature = "ldap"), allow(dead_code))]
+struct Data {
+	userid_ldapdn: Arc<Map>,
+}
+
+#[cfg_attr(not(feature = "ldap"), allow(dead_code))]
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	email: Dep<email::Service>,
+	users: Dep<users::Service>,
+}
+
+/// Entry of a user in the directory.
+#[cfg(feature = "ldap")]
+#[derive(Debug)]
+struct Entry {
+	dn: String,
+	displayname: Option<String>,
+	mail: Option<String>,
+	admin: bool,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				email: args.depend::<email::Service>("email"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				userid_ldapdn: args.db["userid_ldapdn"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether password logins are authenticated against an LDAP directory.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn enabled(&self) -> bool { self.services.server.config.ldap.enable }
+
+#[implement(Service)]
+#[cfg(not(feature = "ldap"))]
+pub async fn login(&self, _user_id: &UserId, _password: &str) -> Result<bool> {
+	conduwuit::Err!(FeatureDisabled("ldap"))
+}
+
+/// Authenticate a password login against the directory. Returns false if the
+/// user has no entry in the directory, in which case their local password
+/// applies. While the directory cannot be reached, only users who never logged
+/// in through it fall back to their local password. Users with an entry are
+/// registered on their first login, and granted admin privileges if their
+/// entry matches `admin_filter`.
+#[implement(Service)]
+#[cfg(feature = "ldap")]
+pub async fn login(&self, user_id: &UserId, password: &str) -> Result<bool> {
+	let entry = match self.authenticate(user_id.localpart(), password).await {
+		| Ok(Some(entry)) => entry,
+		| Ok(None) => return Ok(false),
+		| Err(Error::BadServerResponse(e)) => {
+			if self.db.userid_ldapdn.get(user_id).await.is_ok() {
+				return Err!(BadServerResponse(
+					"The LDAP directory cannot be reached, try again later."
+				));
+			}
+
+			warn!(%user_id, "{e}, falling back to the local password");
+			return Ok(false);
+		},
+		| Err(e) => return Err(e),
+	};
+
+	debug!(%user_id, ?entry, "Authenticated user against LDAP directory");
+	self.db.userid_ldapdn.insert(user_id, &entry.dn);
+
+	// accounts awaiting approval are set up all the same before the login fails
+	let users = &self.services.users;
+	let provisioned = if !users.exists(user_id).await {
+		debug!(%user_id, dn = %entry.dn, "Registering user from LDAP directory");
+		let provisioned = users
+			.provision(user_id, entry.displayname, "the LDAP directory")
+			.await;
+
+		if !users.exists(user_id).await {
+			return provisioned.map(|()| false);
+		}
+
+		if let Some(mail) = &entry.mail {
+			self.services
+				.email
+				.set_email(user_id, Some(mail))
+				.await
+				.inspect_err(|e| debug!(%user_id, "Failed to bind email from LDAP: {e}"))
+				.ok();
+		}
+
+		provisioned
+	} else if users.is_deactivated(user_id).await.unwrap_or(false) {
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	} else {
+		Ok(())
+	};
+
+	if entry.admin && !self.services.admin.user_is_admin(user_id).await {
+		info!(%user_id, "Granting admin privileges from LDAP directory");
+		self.services.admin.make_user_admin(user_id).await?;
+	}
+
+	provisioned?;
+
+	Ok(true)
+}
+
+/// Find the entry of a user in the directory and verify their password by
+/// binding as it. Returns None if the user has no entry, and errors with
+/// BadServerResponse if the directory cannot be reached.
+#[implement(Service)]
+#[cfg(feature = "ldap")]
+async fn authenticate(&self, localpart: &str, password: &str) -> Result<Option<Entry>> {
+	let config = &self.services.server.config.ldap;
+	let uri = config
+		.uri
+		.as_ref()
+		.ok_or_else(|| err!(Config("ldap.uri", "LDAP server URI is not set.")))?;
+
+	let base_dn = config
+		.base_dn
+		.as_deref()
+		.ok_or_else(|| err!(Config("ldap.base_dn", "LDAP base DN is not set.")))?;
+
+	let (conn, mut ldap) = match LdapConnAsync::new(uri.as_str()).await {
+		| Ok(conn) => conn,
+		| Err(e) => return Err!(BadServerResponse("Failed to connect to LDAP server: {e}")),
+	};
+
+	self.services.server.spawn("ldap:connection", async move {
+		if let Err(e) = conn.drive().await {
+			debug_warn!("LDAP connection failed: {e}");
+		}
+	});
+
+	if let Some(bind_dn) = &config.bind_dn {
+		if let Err(e) = ldap
+			.simple_bind(bind_dn, config.bind_password.as_deref().unwrap_or_default())
+			.await
+			.and_then(LdapResult::success)
+		{
+			return Err!(BadServerResponse("Failed to bind to LDAP server: {e}"));
+		}
+	}
+
+	let filter =
+		format!("(&{}({}={}))", config.filter, config.uid_attribute, ldap_escape(localpart));
+
+	let attributes = [&config.displayname_attribute, &config.mail_attribute];
+	let entries = match ldap
+		.search(base_dn, Scope::Subtree, &filter, attributes.to_vec())
+		.await
+		.and_then(SearchResult::success)
+	{
+		| Ok((entries, _)) => entries,
+		| Err(e) => return Err!(BadServerResponse("Failed to search LDAP directory: {e}")),
+	};
+
+	let mut entries = entries.into_iter().map(SearchEntry::construct);
+	let Some(mut entry) = entries.next() else {
+		return Ok(None);
+	};
+
+	if entries.next().is_some() {
+		return Err!(Request(Forbidden("Username matches multiple LDAP entries.")));
+	}
+
+	// binding with an empty password is an anonymous bind which always succeeds
+	if password.is_empty()
+		|| ldap
+			.simple_bind(&entry.dn, password)
+			.await
+			.and_then(LdapResult::success)
+			.is_err()
+	{
+		return Err!(Request(Forbidden("Wrong username or password.")));
+	}
+
+	let admin = match &config.admin_filter {
+		| Some(admin_filter) => ldap
+			.search(&entry.dn, Scope::Base, admin_filter, Vec::<&str>::new())
+			.await
+			.and_then(SearchResult::success)
+			.map_err(|e| err!(BadServerResponse("Failed to search LDAP directory: {e}")))
+			.map(|(entries, _)| !entries.is_empty())?,
+		| None => false,
+	};
+
+	ldap.unbind().await.ok();
+
+	let mut attribute = |name: &str| {
+		entry
+			.attrs
+			.remove(name)
+			.and_then(|values| values.into_iter().next())
+	};
+
+	Ok(Some(Entry {
+		displayname: attribute(&config.displayname_attribute),
+		mail: attribute(&config.mail_attribute),
+		dn: entry.dn,
+		admin,
+	}))
+}
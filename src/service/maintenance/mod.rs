@@ -0,0 +1,280 @@
+use std::{
+	mem,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU32, AtomicU64, Ordering},
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug, error, implement, info};
+use database::{Map, compact};
+use tokio::{
+	sync::{Notify, oneshot},
+	time::{MissedTickBehavior, interval},
+};
+
+/// Defers heavy database maintenance, such as manual compactions and purges
+/// of expired data, to quiet hours or periods of low load.
+pub struct Service {
+	queue: Mutex<Vec<Job>>,
+	next_id: AtomicU64,
+
+	/// Requests handled during the last check interval; u32::MAX until
+	/// measured.
+	load: AtomicU32,
+
+	wake: Notify,
+	interrupt: Notify,
+	server: Arc<Server>,
+}
+
+struct Job {
+	id: u64,
+	name: String,
+	queued_at: SystemTime,
+	task: Task,
+}
+
+enum Task {
+	/// Manual compaction of the columns
+	Compact(Vec<Arc<Map>>, compact::Options),
+
+	/// Task of another service waiting in `defer()` to be let through
+	Waiting(oneshot::Sender<()>),
+}
+
+/// Job waiting in the maintenance queue
+#[derive(Debug)]
+pub struct Queued {
+	pub id: u64,
+	pub name: String,
+	pub queued_at: SystemTime,
+}
+
+/// Interval in which the load is measured and the queue is checked. Load is
+/// measured in requests per interval, so this must stay one minute.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			queue: Mutex::new(Vec::new()),
+			next_id: AtomicU64::new(1),
+			load: AtomicU32::new(u32::MAX),
+			wake: Notify::new(),
+			interrupt: Notify::new(),
+			server: args.server.clone(),
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "maintenance", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut finished = self.requests_finished();
+		let mut i = interval(CHECK_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.tick().await;
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.wake.notified() => (),
+				_ = i.tick() => {
+					let current = self.requests_finished();
+					self.load.store(current.wrapping_sub(finished), Ordering::Relaxed);
+					finished = current;
+				},
+			}
+
+			self.run_due().await;
+		}
+
+		// Let deferred tasks of other services through for them to shut down
+		self.queue.lock().expect("locked").clear();
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Queue a manual compaction of the columns until the server is quiet.
+#[implement(Service)]
+pub fn compact(&self, maps: Vec<Arc<Map>>, options: compact::Options) -> u64 {
+	let names: Vec<_> = maps.iter().map(|map| map.name()).collect();
+	let name = format!("compact {}", names.join(", "));
+	let id = self.enqueue(name, Task::Compact(maps, options));
+	self.wake.notify_one();
+
+	id
+}
+
+/// Wait until the server is quiet before running the maintenance task
+/// `name`. Returns immediately when no quiet period is configured.
+#[implement(Service)]
+pub async fn defer(&self, name: &str) {
+	if !self.deferring() || !self.server.running() {
+		return;
+	}
+
+	let (sender, receiver) = oneshot::channel();
+	self.enqueue(name.to_owned(), Task::Waiting(sender));
+	self.wake.notify_one();
+
+	// Also let through when the queue is cleared on shutdown.
+	receiver.await.ok();
+}
+
+/// Jobs waiting for a quiet period, oldest first.
+#[implement(Service)]
+#[must_use]
+pub fn queued(&self) -> Vec<Queued> {
+	self.queue
+		.lock()
+		.expect("locked")
+		.iter()
+		.map(|job| Queued {
+			id: job.id,
+			name: job.name.clone(),
+			queued_at: job.queued_at,
+		})
+		.collect()
+}
+
+/// Run the queued job `id`, or all of them, now regardless of load. Returns
+/// the number of jobs run.
+#[implement(Service)]
+pub async fn run_now(&self, id: Option<u64>) -> usize {
+	let jobs = self.take(|job| id.is_none_or(|id| job.id == id));
+	let count = jobs.len();
+	for job in jobs {
+		self.execute(job).await;
+	}
+
+	count
+}
+
+/// Whether the server is quiet enough for heavy maintenance.
+#[implement(Service)]
+#[must_use]
+pub fn is_quiet(&self) -> bool {
+	let config = &self.server.config;
+	let in_hours = config.maintenance_quiet_hours.is_some_and(|[start, end]| {
+		let hour = current_hour();
+		if start <= end {
+			(start..end).contains(&hour)
+		} else {
+			hour >= start || hour < end
+		}
+	});
+
+	let threshold = config.maintenance_quiet_requests_per_minute;
+	let idle = threshold > 0 && self.load.load(Ordering::Relaxed) < threshold;
+
+	!self.deferring() || in_hours || idle
+}
+
+/// Requests handled during the last minute, if measured yet.
+#[implement(Service)]
+#[must_use]
+pub fn load(&self) -> Option<u32> {
+	Some(self.load.load(Ordering::Relaxed)).filter(|&load| load != u32::MAX)
+}
+
+#[implement(Service)]
+async fn run_due(&self) {
+	let quiet = self.is_quiet();
+	let max_deferral = Duration::from_secs(self.server.config.maintenance_max_deferral);
+	let jobs = self.take(|job| {
+		quiet
+			|| job
+				.queued_at
+				.elapsed()
+				.is_ok_and(|waited| waited >= max_deferral)
+	});
+
+	for job in jobs {
+		self.execute(job).await;
+	}
+}
+
+#[implement(Service)]
+async fn execute(&self, job: Job) {
+	let Job { id, name, queued_at, task } = job;
+	let waited = queued_at.elapsed().unwrap_or_default();
+	debug!(id, %name, ?waited, "Running deferred maintenance");
+	match task {
+		| Task::Waiting(sender) => {
+			sender.send(()).ok();
+		},
+		| Task::Compact(maps, options) => {
+			let result = self
+				.server
+				.runtime()
+				.spawn_blocking(move || {
+					maps.iter()
+						.try_for_each(|map| map.compact_blocking(options.clone()))
+				})
+				.await;
+
+			match result {
+				| Ok(Ok(())) => info!(id, %name, "Deferred compaction complete"),
+				| Ok(Err(e)) => error!(id, %name, "Deferred compaction failed: {e}"),
+				| Err(e) => error!(id, %name, "Deferred compaction failed: {e}"),
+			}
+		},
+	}
+}
+
+#[implement(Service)]
+fn enqueue(&self, name: String, task: Task) -> u64 {
+	let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+	self.queue.lock().expect("locked").push(Job {
+		id,
+		name,
+		queued_at: SystemTime::now(),
+		task,
+	});
+
+	id
+}
+
+#[implement(Service)]
+fn take<F>(&self, pred: F) -> Vec<Job>
+where
+	F: Fn(&Job) -> bool,
+{
+	let mut queue = self.queue.lock().expect("locked");
+	let (taken, waiting) = mem::take(&mut *queue).into_iter().partition(pred);
+	*queue = waiting;
+
+	taken
+}
+
+/// Whether maintenance is held back until a quiet period at all.
+#[implement(Service)]
+fn deferring(&self) -> bool {
+	let config = &self.server.config;
+	config.maintenance_quiet_hours.is_some() || config.maintenance_quiet_requests_per_minute > 0
+}
+
+#[implement(Service)]
+fn requests_finished(&self) -> u32 {
+	self.server
+		.metrics
+		.requests_handle_finished
+		.load(Ordering::Relaxed)
+}
+
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+fn current_hour() -> u8 {
+	let secs = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+
+	((secs / 3600) % 24) as u8
+}
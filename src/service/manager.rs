@@ -49,8 +49,7 @@ pub(super) async fn start(self: Arc<Self>) -> Result<()> {
 		let self_ = self.clone();
 		_ = self.manager.lock().await.insert(
 			self.server
-				.runtime()
-				.spawn(async move { self_.worker().await }),
+				.spawn("service:manager", async move { self_.worker().await }),
 		);
 
 		// we can't hold the lock during the iteration with start_worker so the values
@@ -162,7 +161,9 @@ async fn start_worker(
 		}
 
 		debug!("Service {:?} worker starting...", service.name());
-		workers.spawn_on(worker(service.clone()), self.server.runtime());
+		let name = format!("service:{}", service.name());
+		self.server
+			.spawn_in(workers, &name, worker(service.clone()));
 
 		Ok(())
 	}
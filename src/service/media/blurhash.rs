@@ -6,7 +6,7 @@
 
 #[implement(Service)]
 #[cfg(not(feature = "blurhashing"))]
-pub fn create_blurhash(
+pub async fn create_blurhash(
 	&self,
 	_file: &[u8],
 	_content_type: Option<&str>,
@@ -17,9 +17,12 @@ pub fn create_blurhash(
 	Ok(None)
 }
 
+/// Blurhashes an image on the blocking thread pool. At most
+/// `max_concurrent_jobs` images are blurhashed at once; further requests wait
+/// for a free slot.
 #[implement(Service)]
 #[cfg(feature = "blurhashing")]
-pub fn create_blurhash(
+pub async fn create_blurhash(
 	&self,
 	file: &[u8],
 	content_type: Option<&str>,
@@ -32,11 +35,39 @@ pub fn create_blurhash(
 		return Ok(None);
 	}
 
-	get_blurhash_from_request(file, content_type, file_name, config)
+	let _permit = self
+		.blurhash_permits
+		.acquire()
+		.await
+		.expect("blurhash semaphore is never closed");
+
+	let file = file.to_vec();
+	let content_type = content_type.map(ToOwned::to_owned);
+	let file_name = file_name.map(ToOwned::to_owned);
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || {
+			get_blurhash_from_request(
+				&file,
+				content_type.as_deref(),
+				file_name.as_deref(),
+				config,
+			)
+		})
+		.await?
 		.map_err(|e| conduwuit::err!(debug_error!("blurhashing error: {e}")))
 		.map(Some)
 }
 
+/// Whether a blurhash is generated for every uploaded image, whether or not
+/// the client asked for one.
+#[implement(Service)]
+#[must_use]
+pub fn blurhash_on_upload(&self) -> bool {
+	cfg!(feature = "blurhashing") && self.services.server.config.blurhashing.on_upload
+}
+
 /// Returns the blurhash or a blurhash error which implements Display.
 #[tracing::instrument(
 	name = "blurhash",
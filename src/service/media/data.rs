@@ -1,21 +1,47 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+	sync::{
+		Arc,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::Duration,
+};
 
 use conduwuit::{
-	Err, Result, debug, debug_info, err,
+	Err, Result,
+	config::RemoteMediaPolicy,
+	debug, debug_info, err,
 	utils::{ReadyExt, str_from_bytes, stream::TryIgnore, string_from_bytes},
 };
-use database::{Database, Interfix, Map};
-use futures::StreamExt;
-use ruma::{Mxc, OwnedMxcUri, UserId, http_headers::ContentDisposition};
+use database::{Database, Deserialized, Ignore, Interfix, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{
+	Mxc, OwnedMxcUri, OwnedServerName, RoomId, ServerName, UserId,
+	http_headers::ContentDisposition,
+};
 
-use super::{preview::UrlPreviewData, thumbnail::Dim};
+use super::{
+	preview::{UrlPreviewData, UrlPreviewPolicy},
+	thumbnail::Dim,
+};
 
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
+	mediaid_lastref: Arc<Map>,
 	mediaid_user: Arc<Map>,
+	mediaretention_policy: Arc<Map>,
+	roomid_mediaid: Arc<Map>,
+	servername_mediapolicy: Arc<Map>,
 	url_previews: Arc<Map>,
+	urlpreviewdomain_policy: Arc<Map>,
+
+	/// Number of cached URL previews, counted on first use and then kept up
+	/// to date as previews are added and removed
+	url_preview_count: AtomicUsize,
 }
 
+/// `url_preview_count` before the cached previews were counted
+const UNCOUNTED: usize = usize::MAX;
+
 #[derive(Debug)]
 pub(super) struct Metadata {
 	pub(super) content_disposition: Option<ContentDisposition>,
@@ -27,8 +53,14 @@ impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_lastref: db["mediaid_lastref"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
+			mediaretention_policy: db["mediaretention_policy"].clone(),
+			roomid_mediaid: db["roomid_mediaid"].clone(),
+			servername_mediapolicy: db["servername_mediapolicy"].clone(),
 			url_previews: db["url_previews"].clone(),
+			urlpreviewdomain_policy: db["urlpreviewdomain_policy"].clone(),
+			url_preview_count: AtomicUsize::new(UNCOUNTED),
 		}
 	}
 
@@ -40,8 +72,8 @@ pub(super) fn create_file_metadata(
 		content_disposition: Option<&ContentDisposition>,
 		content_type: Option<&str>,
 	) -> Result<Vec<u8>> {
-		let dim: &[u32] = &[dim.width, dim.height];
-		let key = (mxc, dim, content_disposition, content_type);
+		let dim = dim.key();
+		let key = (mxc, dim.as_slice(), content_disposition, content_type);
 		let key = database::serialize_key(key)?;
 		self.mediaid_file.insert(&key, []);
 		if let Some(user) = user {
@@ -77,6 +109,8 @@ pub(super) async fn delete_file_mxc(&self, mxc: &Mxc<'_>) {
 				self.mediaid_user.remove(key);
 			})
 			.await;
+
+		self.mediaid_lastref.remove(mxc.to_string().as_bytes());
 	}
 
 	/// Searches for all files with the given MXC
@@ -106,8 +140,8 @@ pub(super) async fn search_file_metadata(
 		mxc: &Mxc<'_>,
 		dim: &Dim,
 	) -> Result<Metadata> {
-		let dim: &[u32] = &[dim.width, dim.height];
-		let prefix = (mxc, dim, Interfix);
+		let dim = dim.key();
+		let prefix = (mxc, dim.as_slice(), Interfix);
 
 		let key = self
 			.mediaid_file
@@ -164,18 +198,23 @@ pub(crate) async fn get_all_media_keys(&self) -> Vec<Vec<u8>> {
 			.await
 	}
 
-	#[inline]
-	pub(super) fn remove_url_preview(&self, url: &str) -> Result<()> {
-		self.url_previews.remove(url.as_bytes());
+	pub(super) async fn remove_url_preview(&self, url: &str) -> Result<()> {
+		if self.url_previews.exists(url).await.is_ok() {
+			self.url_previews.remove(url.as_bytes());
+			self.adjust_url_preview_count(usize::checked_sub);
+		}
+
 		Ok(())
 	}
 
-	pub(super) fn set_url_preview(
+	pub(super) async fn set_url_preview(
 		&self,
 		url: &str,
 		data: &UrlPreviewData,
 		timestamp: Duration,
 	) -> Result<()> {
+		let replaced = self.url_previews.exists(url).await.is_ok();
+
 		let mut value = Vec::<u8>::new();
 		value.extend_from_slice(&timestamp.as_secs().to_be_bytes());
 		value.push(0xFF);
@@ -207,21 +246,25 @@ pub(super) fn set_url_preview(
 		value.extend_from_slice(&data.image_height.unwrap_or(0).to_be_bytes());
 
 		self.url_previews.insert(url.as_bytes(), &value);
+		if !replaced {
+			self.adjust_url_preview_count(usize::checked_add);
+		}
 
 		Ok(())
 	}
 
-	pub(super) async fn get_url_preview(&self, url: &str) -> Result<UrlPreviewData> {
+	/// Returns the cached preview of `url` along with the time it was cached
+	/// at as a duration since the unix epoch.
+	pub(super) async fn get_url_preview(&self, url: &str) -> Result<(Duration, UrlPreviewData)> {
 		let values = self.url_previews.get(url).await?;
 
-		let mut values = values.split(|&b| b == 0xFF);
+		// the timestamp is fixed-size and may itself contain the separator
+		let (timestamp, values) = values
+			.split_first_chunk::<8>()
+			.ok_or_else(|| err!(Database("Invalid URL preview for {url:?}")))?;
 
-		let _ts = values.next();
-		/* if we ever decide to use timestamp, this is here.
-		match values.next().map(|b| u64::from_be_bytes(b.try_into().expect("valid BE array"))) {
-			Some(0) => None,
-			x => x,
-		};*/
+		let timestamp = Duration::from_secs(u64::from_be_bytes(*timestamp));
+		let mut values = values.split(|&b| b == 0xFF).skip(1);
 
 		let title = match values
 			.next()
@@ -266,13 +309,160 @@ pub(super) async fn get_url_preview(&self, url: &str) -> Result<UrlPreviewData>
 			| x => x,
 		};
 
-		Ok(UrlPreviewData {
+		Ok((timestamp, UrlPreviewData {
 			title,
 			description,
 			image,
 			image_size,
 			image_width,
 			image_height,
-		})
+		}))
+	}
+
+	/// Returns the URLs of all cached previews along with the time they were
+	/// cached at.
+	pub(super) fn url_previews(&self) -> impl Stream<Item = (String, Duration)> + Send + '_ {
+		self.url_previews
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(url, value)| {
+				let url = string_from_bytes(url).ok()?;
+				let timestamp = value.first_chunk::<8>().copied().map(u64::from_be_bytes)?;
+
+				Some((url, Duration::from_secs(timestamp)))
+			})
+	}
+
+	pub(super) async fn url_preview_count(&self) -> usize {
+		match self.url_preview_count.load(Ordering::Relaxed) {
+			| UNCOUNTED => {
+				let count = self.url_previews.count().await;
+				self.url_preview_count.store(count, Ordering::Relaxed);
+				count
+			},
+			| count => count,
+		}
+	}
+
+	fn adjust_url_preview_count(&self, op: fn(usize, usize) -> Option<usize>) {
+		self.url_preview_count
+			.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+				(count != UNCOUNTED).then(|| op(count, 1)).flatten()
+			})
+			.ok();
+	}
+
+	pub(super) async fn get_url_preview_policy(&self, domain: &str) -> Result<UrlPreviewPolicy> {
+		self.urlpreviewdomain_policy
+			.get(domain)
+			.await
+			.deserialized()
+	}
+
+	pub(super) fn set_url_preview_policy(&self, domain: &str, policy: &UrlPreviewPolicy) {
+		self.urlpreviewdomain_policy.raw_put(domain, Json(policy));
+	}
+
+	pub(super) fn remove_url_preview_policy(&self, domain: &str) {
+		self.urlpreviewdomain_policy.remove(domain);
+	}
+
+	pub(super) fn url_preview_policies(
+		&self,
+	) -> impl Stream<Item = (String, UrlPreviewPolicy)> + Send + '_ {
+		self.urlpreviewdomain_policy
+			.stream()
+			.ignore_err()
+			.map(|(domain, policy): (&str, UrlPreviewPolicy)| (domain.to_owned(), policy))
+	}
+
+	/// Time (milliseconds since the epoch) `mxc` was last referenced.
+	pub(super) async fn get_last_reference(&self, mxc: &Mxc<'_>) -> Result<u64> {
+		self.mediaid_lastref
+			.get(mxc.to_string().as_bytes())
+			.await
+			.deserialized()
+	}
+
+	pub(super) fn set_last_reference(&self, mxc: &Mxc<'_>, ts: u64) {
+		self.mediaid_lastref.raw_put(mxc.to_string().as_bytes(), ts);
+	}
+
+	/// Remember when `mxc` was first sent to `room_id`.
+	pub(super) async fn add_room_reference(&self, room_id: &RoomId, mxc: &Mxc<'_>, ts: u64) {
+		let mxc = mxc.to_string();
+		let key = (room_id, mxc.as_str());
+		if self.roomid_mediaid.qry(&key).await.is_err() {
+			self.roomid_mediaid.put(key, ts);
+		}
+	}
+
+	pub(super) fn remove_room_reference(&self, room_id: &RoomId, mxc: &str) {
+		self.roomid_mediaid.del((room_id, mxc));
+	}
+
+	/// The media sent to `room_id`, with the time each was first sent there.
+	pub(super) fn room_references<'a>(
+		&'a self,
+		room_id: &'a RoomId,
+	) -> impl Stream<Item = (OwnedMxcUri, u64)> + Send + 'a {
+		let prefix = (room_id, Interfix);
+		self.roomid_mediaid
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.map(|((_, mxc), ts): ((Ignore, &str), u64)| (mxc.into(), ts))
+	}
+
+	pub(super) async fn get_retention_policy(&self, scope: &str) -> Result<u64> {
+		self.mediaretention_policy.get(scope).await.deserialized()
+	}
+
+	pub(super) fn set_retention_policy(&self, scope: &str, retention: u64) {
+		self.mediaretention_policy.raw_put(scope, retention);
+	}
+
+	pub(super) fn remove_retention_policy(&self, scope: &str) {
+		self.mediaretention_policy.remove(scope);
+	}
+
+	pub(super) fn retention_policies(&self) -> impl Stream<Item = (String, u64)> + Send + '_ {
+		self.mediaretention_policy
+			.stream()
+			.ignore_err()
+			.map(|(scope, retention): (&str, u64)| (scope.to_owned(), retention))
+	}
+
+	pub(super) async fn get_remote_media_policy(
+		&self,
+		server: &ServerName,
+	) -> Result<RemoteMediaPolicy> {
+		self.servername_mediapolicy
+			.get(server.as_str())
+			.await
+			.deserialized()
+	}
+
+	pub(super) fn set_remote_media_policy(
+		&self,
+		server: &ServerName,
+		policy: &RemoteMediaPolicy,
+	) {
+		self.servername_mediapolicy
+			.raw_put(server.as_str(), Json(policy));
+	}
+
+	pub(super) fn remove_remote_media_policy(&self, server: &ServerName) {
+		self.servername_mediapolicy.remove(server.as_str());
+	}
+
+	pub(super) fn remote_media_policies(
+		&self,
+	) -> impl Stream<Item = (OwnedServerName, RemoteMediaPolicy)> + Send + '_ {
+		self.servername_mediapolicy
+			.stream()
+			.ignore_err()
+			.ready_filter_map(|(server, policy): (&str, RemoteMediaPolicy)| {
+				Some((server.try_into().ok()?, policy))
+			})
 	}
 }
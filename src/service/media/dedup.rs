@@ -0,0 +1,129 @@
+//! Media content statistics and orphaned file cleanup
+//!
+//! Every media record (file or thumbnail) is stored as its own file, even
+//! when the same content was uploaded before. The report hashes the stored
+//! files to show how much of the storage identical content takes up. Files
+//! which no record refers to any more, e.g. left behind by a purge whose
+//! removal failed, can be deleted.
+
+use std::collections::{HashMap, HashSet};
+
+use conduwuit::{Result, debug_warn, implement, info, utils::hash::sha256};
+
+use super::media_file_name;
+
+/// Statistics of the content of stored media files.
+#[derive(Debug, Default)]
+pub struct DedupReport {
+	/// Media records, including thumbnails
+	pub records: usize,
+
+	/// Records whose file could not be read
+	pub missing: usize,
+
+	/// Distinct contents of the files
+	pub contents: usize,
+
+	/// Records whose content is also stored for another record
+	pub shared_records: usize,
+
+	/// Size of all files in bytes
+	pub total_size: u64,
+
+	/// Size in bytes taken up by the copies of content stored more than once
+	pub duplicate_size: u64,
+}
+
+/// Hash the content of every media file to find content stored more than
+/// once. Reads all stored media.
+#[implement(super::Service)]
+pub async fn dedup_report(&self) -> Result<DedupReport> {
+	let mut report = DedupReport::default();
+	let mut contents: HashMap<sha256::Digest, (usize, u64)> = HashMap::new();
+	for key in self.db.get_all_media_keys().await {
+		report.records = report.records.saturating_add(1);
+		let content = match self.read_media_file(&key).await {
+			| Ok(content) => content,
+			| Err(e) => {
+				debug_warn!(?key, "Failed to read media file: {e}");
+				report.missing = report.missing.saturating_add(1);
+				continue;
+			},
+		};
+
+		let size = u64::try_from(content.len())?;
+		let (count, _) = contents.entry(sha256::hash(&content)).or_insert((0, size));
+
+		*count = count.saturating_add(1);
+		report.total_size = report.total_size.saturating_add(size);
+	}
+
+	report.contents = contents.len();
+	for (count, size) in contents.into_values().filter(|&(count, _)| count > 1) {
+		let copies = u64::try_from(count.saturating_sub(1))?;
+		report.shared_records = report.shared_records.saturating_add(count);
+		report.duplicate_size = report
+			.duplicate_size
+			.saturating_add(size.saturating_mul(copies));
+	}
+
+	Ok(report)
+}
+
+/// Delete the files in the configured storage which no media record refers
+/// to. Returns the number of files deleted and their size in bytes, or only
+/// counts them with `dry_run`.
+#[implement(super::Service)]
+pub async fn delete_orphaned_files(&self, dry_run: bool) -> Result<(usize, u64)> {
+	// list the files first, so files of media uploaded meanwhile are referenced
+	let names = self.storage.list().await?;
+	let referenced: HashSet<_> = self
+		.db
+		.get_all_media_keys()
+		.await
+		.iter()
+		.map(|key| media_file_name(key))
+		.collect();
+
+	let mut deleted: usize = 0;
+	let mut size: u64 = 0;
+	for name in names {
+		if referenced.contains(&name) || !is_media_file_name(&name) {
+			continue;
+		}
+
+		let stat = match self.storage.stat(&name).await {
+			| Ok(stat) => stat,
+			| Err(e) => {
+				debug_warn!(%name, "Failed to stat orphaned media file: {e}");
+				continue;
+			},
+		};
+
+		if !dry_run {
+			if let Err(e) = self.storage.delete(&name).await {
+				debug_warn!(%name, "Failed to delete orphaned media file: {e}");
+				continue;
+			}
+		}
+
+		deleted = deleted.saturating_add(1);
+		size = size.saturating_add(stat.size);
+	}
+
+	if !dry_run {
+		info!("Deleted {deleted} orphaned media files of {size} bytes");
+	}
+
+	Ok((deleted, size))
+}
+
+/// Whether `name` could have been produced by [`media_file_name`], so files
+/// placed in the storage by others are left alone.
+fn is_media_file_name(name: &str) -> bool {
+	// unpadded base64 of a SHA-256 digest
+	name.len() == 43
+		&& name
+			.bytes()
+			.all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
@@ -0,0 +1,314 @@
+//! Stripping metadata such as EXIF (GPS coordinates, camera serial numbers)
+//! from uploaded JPEG, PNG and WebP images. The image data itself is copied
+//! as is; only the metadata segments or chunks are dropped. A non-default
+//! EXIF orientation is kept, so images are still displayed the right way up.
+
+use conduwuit::{Result, implement};
+use database::Deserialized;
+use ruma::UserId;
+use serde::Deserialize;
+
+use super::Service;
+
+/// Account data type users override `media_strip_metadata` with, e.g.
+/// `{"enabled": false}` to keep the metadata of their uploads.
+pub const STRIP_METADATA_CONFIG: &str = "im.conduwuit.strip_media_metadata";
+
+#[derive(Deserialize)]
+struct StripMetadataConfigEvent {
+	content: StripMetadataConfig,
+}
+
+#[derive(Deserialize)]
+struct StripMetadataConfig {
+	enabled: bool,
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADERS: [&[u8]; 2] =
+	[b"http://ns.adobe.com/xap/1.0/\0", b"http://ns.adobe.com/xmp/extension/\0"];
+
+/// EXIF tag of the orientation
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Whether metadata is stripped from the images `user` uploads: their
+/// override in account data, or `media_strip_metadata` if they have none.
+#[implement(Service)]
+pub async fn strip_metadata_enabled(&self, user: &UserId) -> bool {
+	self.services
+		.account_data
+		.get_raw(None, user, STRIP_METADATA_CONFIG)
+		.await
+		.deserialized::<StripMetadataConfigEvent>()
+		.map_or(self.services.server.config.media_strip_metadata, |event| event.content.enabled)
+}
+
+/// Strip metadata from an image on the blocking thread pool. Returns None if
+/// the file is not a JPEG, PNG or WebP image or has no metadata to strip.
+#[implement(Service)]
+pub async fn strip_metadata(&self, file: &[u8]) -> Result<Option<Vec<u8>>> {
+	let file = file.to_vec();
+	let stripped = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || strip(&file))
+		.await?;
+
+	Ok(stripped)
+}
+
+#[tracing::instrument(
+	name = "strip_metadata",
+	level = "debug",
+	skip_all,
+	fields(bytes = data.len()),
+)]
+pub(super) fn strip(data: &[u8]) -> Option<Vec<u8>> {
+	if data.starts_with(&[0xFF, 0xD8]) {
+		strip_jpeg(data)
+	} else if data.starts_with(PNG_SIGNATURE) {
+		strip_png(data)
+	} else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+		strip_webp(data)
+	} else {
+		None
+	}
+}
+
+/// Drop the EXIF, XMP, Photoshop (IPTC) and comment segments of a JPEG image.
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+	let (soi, mut rest) = data.split_first_chunk::<2>()?;
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(soi);
+
+	let mut stripped = false;
+	loop {
+		let (&[0xFF, marker], after) = rest.split_first_chunk::<2>()? else {
+			return None;
+		};
+
+		match marker {
+			// fill byte
+			| 0xFF => {
+				rest = rest.get(1..)?;
+				continue;
+			},
+			// start of scan or end of image: the rest is image data
+			| 0xDA | 0xD9 => {
+				out.extend_from_slice(rest);
+				break;
+			},
+			// markers without a segment
+			| 0x01 | 0xD0..=0xD7 => {
+				out.extend_from_slice(&[0xFF, marker]);
+				rest = after;
+				continue;
+			},
+			| _ => {},
+		}
+
+		let (len, _) = after.split_first_chunk::<2>()?;
+		let len = usize::from(u16::from_be_bytes(*len));
+		let segment = after.get(..len)?;
+		let payload = segment.get(2..)?;
+		rest = after.get(len..)?;
+
+		let metadata = match marker {
+			| 0xE1 if payload.starts_with(EXIF_HEADER) => {
+				let tiff = payload.get(EXIF_HEADER.len()..)?;
+				if let Some(orientation) = exif_orientation(tiff) {
+					let exif = [EXIF_HEADER, orientation_exif(orientation).as_slice()].concat();
+					let len = u16::try_from(exif.len().checked_add(2)?).ok()?;
+					out.extend_from_slice(&[0xFF, 0xE1]);
+					out.extend_from_slice(&len.to_be_bytes());
+					out.extend_from_slice(&exif);
+				}
+
+				true
+			},
+			| 0xE1 => XMP_HEADERS.iter().any(|header| payload.starts_with(header)),
+			| 0xED | 0xFE => true,
+			| _ => false,
+		};
+
+		if metadata {
+			stripped = true;
+		} else {
+			out.extend_from_slice(&[0xFF, marker]);
+			out.extend_from_slice(segment);
+		}
+	}
+
+	stripped.then_some(out)
+}
+
+/// Drop the EXIF, text and modification time chunks of a PNG image.
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+	let mut rest = data.strip_prefix(PNG_SIGNATURE)?;
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(PNG_SIGNATURE);
+
+	let mut stripped = false;
+	while !rest.is_empty() {
+		let (len, after) = rest.split_first_chunk::<4>()?;
+		let len = usize::try_from(u32::from_be_bytes(*len)).ok()?;
+		let (kind, after) = after.split_first_chunk::<4>()?;
+		let payload = after.get(..len)?;
+		let chunk = rest.get(..len.checked_add(12)?)?;
+		rest = rest.get(chunk.len()..)?;
+
+		match kind {
+			| b"eXIf" => {
+				if let Some(orientation) = exif_orientation(payload) {
+					let exif = orientation_exif(orientation);
+					let len = u32::try_from(exif.len()).ok()?;
+					let crc = crc32(kind.iter().chain(exif.iter()));
+					out.extend_from_slice(&len.to_be_bytes());
+					out.extend_from_slice(kind);
+					out.extend_from_slice(&exif);
+					out.extend_from_slice(&crc.to_be_bytes());
+				}
+
+				stripped = true;
+			},
+			| b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => stripped = true,
+			| _ => out.extend_from_slice(chunk),
+		}
+	}
+
+	stripped.then_some(out)
+}
+
+/// Drop the EXIF and XMP chunks of a WebP image, updating the flags of the
+/// extended header and the size of the RIFF container.
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+	let (header, mut rest) = data.split_first_chunk::<12>()?;
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(header);
+
+	let mut stripped = false;
+	let mut kept_exif = false;
+	let mut vp8x = None;
+	while !rest.is_empty() {
+		let (fourcc, after) = rest.split_first_chunk::<4>()?;
+		let (len, after) = after.split_first_chunk::<4>()?;
+		let len = usize::try_from(u32::from_le_bytes(*len)).ok()?;
+		let payload = after.get(..len)?;
+		let padded = len.checked_add(len & 1)?;
+		rest = after.get(padded..).unwrap_or_default();
+
+		match fourcc {
+			| b"EXIF" => {
+				let tiff = payload.strip_prefix(EXIF_HEADER).unwrap_or(payload);
+				if let Some(orientation) = exif_orientation(tiff) {
+					let exif = orientation_exif(orientation);
+					out.extend_from_slice(fourcc);
+					out.extend_from_slice(&u32::try_from(exif.len()).ok()?.to_le_bytes());
+					out.extend_from_slice(&exif);
+					kept_exif = true;
+				}
+
+				stripped = true;
+			},
+			| b"XMP " => stripped = true,
+			| _ => {
+				if fourcc == b"VP8X" {
+					vp8x = Some(out.len().checked_add(8)?);
+				}
+
+				out.extend_from_slice(fourcc);
+				out.extend_from_slice(&u32::try_from(len).ok()?.to_le_bytes());
+				out.extend_from_slice(payload);
+				if padded != len {
+					out.push(0);
+				}
+			},
+		}
+	}
+
+	if !stripped {
+		return None;
+	}
+
+	if let Some(flags) = vp8x.and_then(|at| out.get_mut(at)) {
+		// XMP and EXIF flags
+		*flags &= if kept_exif { !0x04 } else { !0x0C };
+	}
+
+	let riff_len = u32::try_from(out.len().checked_sub(8)?).ok()?;
+	out.get_mut(4..8)?.copy_from_slice(&riff_len.to_le_bytes());
+
+	Some(out)
+}
+
+/// The orientation in EXIF (TIFF) data, unless it is the default one.
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+	let big_endian = match tiff.get(..2)? {
+		| b"MM" => true,
+		| b"II" => false,
+		| _ => return None,
+	};
+
+	let read_u16 = |at: usize| -> Option<u16> {
+		let bytes = tiff.get(at..)?.first_chunk::<2>()?;
+		Some(if big_endian {
+			u16::from_be_bytes(*bytes)
+		} else {
+			u16::from_le_bytes(*bytes)
+		})
+	};
+
+	let read_u32 = |at: usize| -> Option<u32> {
+		let bytes = tiff.get(at..)?.first_chunk::<4>()?;
+		Some(if big_endian {
+			u32::from_be_bytes(*bytes)
+		} else {
+			u32::from_le_bytes(*bytes)
+		})
+	};
+
+	let ifd = usize::try_from(read_u32(4)?).ok()?;
+	let entries = read_u16(ifd)?;
+	(0..usize::from(entries))
+		.filter_map(|i| i.checked_mul(12)?.checked_add(ifd)?.checked_add(2))
+		.find(|&entry| read_u16(entry) == Some(ORIENTATION_TAG))
+		.and_then(|entry| read_u16(entry.checked_add(8)?))
+		.filter(|&orientation| orientation != 1)
+}
+
+/// EXIF (TIFF) data holding nothing but the orientation.
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+	let mut tiff = Vec::with_capacity(26);
+	// big endian header with the first IFD right after it
+	tiff.extend_from_slice(b"MM\0\x2A\0\0\0\x08");
+	// one entry: the orientation as a SHORT
+	tiff.extend_from_slice(&1_u16.to_be_bytes());
+	tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+	tiff.extend_from_slice(&3_u16.to_be_bytes());
+	tiff.extend_from_slice(&1_u32.to_be_bytes());
+	tiff.extend_from_slice(&orientation.to_be_bytes());
+	tiff.extend_from_slice(&[0, 0]);
+	// no further IFD
+	tiff.extend_from_slice(&0_u32.to_be_bytes());
+	tiff
+}
+
+/// CRC-32 of PNG chunks
+fn crc32<'a, I>(bytes: I) -> u32
+where
+	I: Iterator<Item = &'a u8>,
+{
+	let crc = bytes.fold(!0_u32, |crc, &byte| {
+		(0..8).fold(crc ^ u32::from(byte), |crc, _| {
+			if crc & 1 == 1 {
+				crc.wrapping_shr(1) ^ 0xEDB8_8320
+			} else {
+				crc.wrapping_shr(1)
+			}
+		})
+	});
+
+	!crc
+}
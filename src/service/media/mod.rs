@@ -1,28 +1,57 @@
 pub mod blurhash;
 mod data;
+mod dedup;
+mod metadata;
 pub(super) mod migrations;
+#[cfg(feature = "url_preview")]
+mod oembed;
 mod preview;
+mod redirect;
 mod remote;
+mod remote_policy;
+mod retention;
+mod sigv4;
+mod storage;
 mod tests;
 mod thumbnail;
-use std::{path::PathBuf, sync::Arc, time::SystemTime};
+use std::{
+	collections::HashSet,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
 use base64::{Engine as _, engine::general_purpose};
 use conduwuit::{
-	Err, Result, Server, debug, debug_error, debug_info, debug_warn, err, error, trace,
+	Err, Result, Server,
+	config::MediaStorageBackend,
+	debug, debug_error, debug_info, debug_warn, err, error, info, trace,
 	utils::{self, MutexMap},
 	warn,
 };
+use futures::{TryStreamExt, future};
 use ruma::{Mxc, OwnedMxcUri, UserId, http_headers::ContentDisposition};
+#[cfg(feature = "blurhashing")]
+use tokio::sync::Semaphore;
 use tokio::{
 	fs,
-	io::{AsyncReadExt, AsyncWriteExt, BufReader},
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
 };
 
-use self::data::{Data, Metadata};
-pub use self::thumbnail::Dim;
-use crate::{Dep, client, globals, sending};
+use self::{
+	data::{Data, Metadata},
+	storage::{Filesystem, S3, Stat, Storage},
+};
+pub use self::{
+	dedup::DedupReport,
+	metadata::STRIP_METADATA_CONFIG,
+	preview::UrlPreviewPolicy,
+	retention::{RetentionCandidate, RetentionScope},
+	thumbnail::Dim,
+};
+use crate::{Dep, account_data, admin, client, globals, maintenance, sending, users};
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -33,15 +62,29 @@ pub struct FileMeta {
 
 pub struct Service {
 	url_preview_mutex: MutexMap<String, ()>,
+	#[cfg(feature = "blurhashing")]
+	blurhash_permits: Semaphore,
 	pub(super) db: Data,
 	services: Services,
+	interrupt: Notify,
+	storage: Box<dyn Storage>,
+	/// The media directory, from which media not yet moved to object storage
+	/// is still served
+	local: Filesystem,
+	/// Media reported to fall under retention policies, deleted on the next
+	/// run if they still apply to it
+	retention_reported: Mutex<HashSet<OwnedMxcUri>>,
 }
 
 struct Services {
 	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	admin: Dep<admin::Service>,
 	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
+	maintenance: Dep<maintenance::Service>,
 	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
 }
 
 /// generated MXC ID (`media-id`) length
@@ -53,27 +96,81 @@ struct Services {
 /// Default cross-origin resource policy.
 pub const CORP_CROSS_ORIGIN: &str = "cross-origin";
 
+/// Interval of evaluating media retention and remote media policies
+const RETENTION_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let media_dir = config.database_path.join("media");
+		let storage: Box<dyn Storage> = match config.media_storage.backend {
+			| MediaStorageBackend::Filesystem => Box::new(Filesystem::new(media_dir.clone())),
+			| MediaStorageBackend::S3 => {
+				let client = args.require::<client::Service>("client");
+				Box::new(S3::new(client.default.clone(), &config.media_storage)?)
+			},
+		};
+
 		Ok(Arc::new(Self {
 			url_preview_mutex: MutexMap::new(),
+			#[cfg(feature = "blurhashing")]
+			blurhash_permits: Semaphore::new(
+				args.server.config.blurhashing.max_concurrent_jobs.max(1),
+			),
 			db: Data::new(args.db),
 			services: Services {
 				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				admin: args.depend::<admin::Service>("admin"),
 				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
 				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
 			},
+			interrupt: Notify::new(),
+			storage,
+			local: Filesystem::new(media_dir),
+			retention_reported: Mutex::default(),
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		self.create_media_dir().await?;
 
+		let mut i = interval(RETENTION_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.services.maintenance.defer("media retention") => (),
+			}
+
+			match self.enforce_retention().await {
+				| Ok(0) => {},
+				| Ok(deleted) => info!("Deleted {deleted} media files under retention policies"),
+				| Err(e) => warn!("Failed to enforce media retention policies: {e}"),
+			}
+
+			match self.enforce_remote_media_policies().await {
+				| Ok(0) => {},
+				| Ok(deleted) =>
+					info!("Deleted {deleted} cached media files under remote media policies"),
+				| Err(e) => warn!("Failed to enforce remote media policies: {e}"),
+			}
+		}
+
 		Ok(())
 	}
 
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -97,8 +194,8 @@ pub async fn create(
 		)?;
 
 		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
+		self.write_media_file(&key, file).await?;
+		self.reference_media(mxc).await;
 
 		Ok(())
 	}
@@ -157,15 +254,17 @@ pub async fn delete_from_user(&self, user: &UserId) -> Result<usize> {
 		Ok(deletion_count)
 	}
 
+	/// Gets the MXC URIs of all media uploaded by the specified user
+	pub async fn get_user_mxcs(&self, user: &UserId) -> Vec<OwnedMxcUri> {
+		self.db.get_all_user_mxcs(user).await
+	}
+
 	/// Downloads a file.
 	pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
 		match self.db.search_file_metadata(mxc, &Dim::default()).await {
 			| Ok(Metadata { content_disposition, content_type, key }) => {
-				let mut content = Vec::with_capacity(8192);
-				let path = self.get_media_file(&key);
-				BufReader::new(fs::File::open(path).await?)
-					.read_to_end(&mut content)
-					.await?;
+				let content = self.read_media_file(&key).await?;
+				self.reference_media(mxc).await;
 
 				Ok(Some(FileMeta {
 					content: Some(content),
@@ -177,6 +276,11 @@ pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
 		}
 	}
 
+	/// Gets the keys of all media in our database, including thumbnails. The
+	/// file of each is stored as `media_file_name()` of its key.
+	#[inline]
+	pub async fn get_all_media_keys(&self) -> Vec<Vec<u8>> { self.db.get_all_media_keys().await }
+
 	/// Gets all the MXC URIs in our media database
 	pub async fn get_all_mxcs(&self) -> Result<Vec<OwnedMxcUri>> {
 		let all_keys = self.db.get_all_media_keys().await;
@@ -263,29 +367,13 @@ pub async fn delete_all_remote_media_at_after_time(
 				continue;
 			}
 
-			let path = self.get_media_file(&key);
-
-			let file_metadata = match fs::metadata(path.clone()).await {
-				| Ok(file_metadata) => file_metadata,
-				| Err(e) => {
-					error!(
-						"Failed to obtain file metadata for MXC {mxc} at file path \
-						 \"{path:?}\", skipping: {e}"
-					);
-					continue;
-				},
-			};
-
-			trace!(%mxc, ?path, "File metadata: {file_metadata:?}");
-
-			let file_created_at = match file_metadata.created() {
-				| Ok(value) => value,
-				| Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
-					debug!("btime is unsupported, using mtime instead");
-					file_metadata.modified()?
+			let file_created_at = match self.stat_media_file(&key).await {
+				| Ok(stat) => {
+					trace!(%mxc, "File metadata: {stat:?}");
+					stat.modified
 				},
-				| Err(err) => {
-					error!("Could not delete MXC {mxc} at path {path:?}: {err:?}. Skipping...");
+				| Err(e) => {
+					error!("Failed to obtain file metadata for MXC {mxc}, skipping: {e}");
 					continue;
 				},
 			};
@@ -342,12 +430,54 @@ pub async fn create_media_dir(&self) -> Result<()> {
 		Ok(fs::create_dir_all(dir).await?)
 	}
 
+	async fn write_media_file(&self, key: &[u8], content: &[u8]) -> Result<()> {
+		let name = media_file_name(key);
+		debug!(?key, %name, "Creating media file");
+		self.storage.put(&name, content).await?;
+
+		if self.is_local() && self.services.server.config.media_compat_file_link {
+			let path = self.get_media_file(key);
+			let legacy = self.get_media_file_b64(key);
+			if let Err(e) = fs::symlink(&path, &legacy).await {
+				debug_error!(
+					key = ?encode_key(key), ?path, ?legacy,
+					"Failed to create legacy media symlink: {e}"
+				);
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn read_media_file(&self, key: &[u8]) -> Result<Vec<u8>> {
+		let name = media_file_name(key);
+		let content = match self.storage.get(&name).await {
+			| Err(e) if e.is_not_found() && !self.is_local() => self.local.get(&name).await?,
+			| result => result?,
+		};
+
+		content
+			.try_fold(Vec::new(), |mut content, chunk| {
+				content.extend_from_slice(&chunk);
+				future::ok(content)
+			})
+			.await
+	}
+
+	async fn stat_media_file(&self, key: &[u8]) -> Result<Stat> {
+		let name = media_file_name(key);
+		match self.storage.stat(&name).await {
+			| Err(e) if e.is_not_found() && !self.is_local() => self.local.stat(&name).await,
+			| result => result,
+		}
+	}
+
 	async fn remove_media_file(&self, key: &[u8]) -> Result<()> {
-		let path = self.get_media_file(key);
+		let name = media_file_name(key);
 		let legacy = self.get_media_file_b64(key);
-		debug!(?key, ?path, ?legacy, "Removing media file");
+		debug!(?key, %name, ?legacy, "Removing media file");
 
-		let file_rm = fs::remove_file(&path);
+		let file_rm = self.storage.delete(&name);
 		let legacy_rm = fs::remove_file(&legacy);
 		let (file_rm, legacy_rm) = tokio::join!(file_rm, legacy_rm);
 		if let Err(e) = legacy_rm {
@@ -356,25 +486,58 @@ async fn remove_media_file(&self, key: &[u8]) -> Result<()> {
 			}
 		}
 
-		Ok(file_rm?)
+		if !self.is_local() {
+			// the file may not have been moved to object storage yet
+			match self.local.delete(&name).await {
+				| Err(e) if e.is_not_found() => {},
+				| Err(e) => debug_error!(?key, "Failed to remove local media file: {e}"),
+				| Ok(()) => return Ok(()),
+			}
+		}
+
+		file_rm
 	}
 
-	async fn create_media_file(&self, key: &[u8]) -> Result<fs::File> {
-		let path = self.get_media_file(key);
-		debug!(?key, ?path, "Creating media file");
+	/// Move the files in the media directory to object storage. Returns the
+	/// number of files moved.
+	pub async fn migrate_to_s3(&self) -> Result<usize> {
+		if self.is_local() {
+			return Err!(
+				"Media is stored on the filesystem; set `media_storage.backend` to \"s3\" and \
+				 restart first."
+			);
+		}
 
-		let file = fs::File::create(&path).await?;
-		if self.services.server.config.media_compat_file_link {
-			let legacy = self.get_media_file_b64(key);
-			if let Err(e) = fs::symlink(&path, &legacy).await {
-				debug_error!(
-					key = ?encode_key(key), ?path, ?legacy,
-					"Failed to create legacy media symlink: {e}"
-				);
+		let mut moved: usize = 0;
+		for key in self.db.get_all_media_keys().await {
+			let name = media_file_name(&key);
+			let path = self.local.path(&name);
+			if !fs::try_exists(&path).await? {
+				continue;
+			}
+
+			self.storage.put_file(&name, &path).await?;
+			self.local.delete(&name).await?;
+			if let Err(e) = fs::remove_file(self.get_media_file_b64(&key)).await {
+				if self.services.server.config.media_compat_file_link {
+					debug_error!(?key, "Failed to remove legacy media symlink: {e}");
+				}
+			}
+
+			moved = moved.saturating_add(1);
+			if moved % 1000 == 0 {
+				info!("Moved {moved} media files to object storage");
 			}
 		}
 
-		Ok(file)
+		Ok(moved)
+	}
+
+	/// Whether media files are stored in the media directory.
+	#[inline]
+	#[must_use]
+	pub fn is_local(&self) -> bool {
+		self.services.server.config.media_storage.backend == MediaStorageBackend::Filesystem
 	}
 
 	#[inline]
@@ -399,12 +562,7 @@ pub fn get_media_file(&self, key: &[u8]) -> PathBuf { self.get_media_file_sha256
 	#[must_use]
 	pub fn get_media_file_sha256(&self, key: &[u8]) -> PathBuf {
 		let mut r = self.get_media_dir();
-		// Using the hash of the base64 key as the filename
-		// This is to prevent the total length of the path from exceeding the maximum
-		// length in most filesystems
-		let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
-		let encoded = encode_key(&digest);
-		r.push(encoded);
+		r.push(media_file_name(key));
 		r
 	}
 
@@ -431,3 +589,14 @@ pub fn get_media_dir(&self) -> PathBuf {
 #[inline]
 #[must_use]
 pub fn encode_key(key: &[u8]) -> String { general_purpose::URL_SAFE_NO_PAD.encode(key) }
+
+/// Name media files are stored as, in the media directory and in object
+/// storage.
+#[must_use]
+pub fn media_file_name(key: &[u8]) -> String {
+	// Using the hash of the base64 key as the filename
+	// This is to prevent the total length of the path from exceeding the maximum
+	// length in most filesystems
+	let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
+	encode_key(&digest)
+}
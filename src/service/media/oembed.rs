@@ -0,0 +1,228 @@
+//! oEmbed support for URL previews
+//!
+//! Sites which render their content with JavaScript (e.g. YouTube, Twitter)
+//! expose little through OpenGraph, but describe their content through an
+//! oEmbed endpoint. Endpoints are taken from a bundled list of well-known
+//! providers or discovered through `<link>` elements in the page.
+
+use std::sync::LazyLock;
+
+use conduwuit::{Err, Result, debug, err, implement, utils::string::EMPTY};
+use ipaddress::IPAddress;
+use itertools::Itertools;
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use url::Url;
+
+use super::{Service, preview::UrlPreviewData};
+
+/// oEmbed providers known to support the JSON format, as pairs of endpoint
+/// and URL schemes. Schemes use `*` as a wildcard like in the oEmbed provider
+/// registry.
+const PROVIDERS: &[(&str, &[&str])] = &[
+	("https://www.youtube.com/oembed", &[
+		"https://*.youtube.com/watch*",
+		"https://*.youtube.com/v/*",
+		"https://*.youtube.com/shorts/*",
+		"https://*.youtube.com/live/*",
+		"https://youtube.com/watch*",
+		"https://youtube.com/shorts/*",
+		"https://youtu.be/*",
+	]),
+	("https://publish.twitter.com/oembed", &[
+		"https://twitter.com/*/status/*",
+		"https://*.twitter.com/*/status/*",
+		"https://x.com/*/status/*",
+	]),
+	("https://vimeo.com/api/oembed.json", &[
+		"https://vimeo.com/*",
+		"https://player.vimeo.com/video/*",
+	]),
+	("https://soundcloud.com/oembed", &["https://soundcloud.com/*"]),
+	("https://open.spotify.com/oembed", &["https://open.spotify.com/*"]),
+	("https://www.flickr.com/services/oembed/", &[
+		"https://*.flickr.com/photos/*",
+		"https://flic.kr/p/*",
+	]),
+	("https://www.reddit.com/oembed", &[
+		"https://reddit.com/r/*/comments/*",
+		"https://www.reddit.com/r/*/comments/*",
+	]),
+];
+
+static PROVIDER_SCHEMES: LazyLock<Vec<(&str, RegexSet)>> = LazyLock::new(|| {
+	PROVIDERS
+		.iter()
+		.map(|&(endpoint, schemes)| {
+			let schemes = schemes
+				.iter()
+				.map(|scheme| format!("^{}$", scheme.split('*').map(regex::escape).join(".*")));
+
+			let schemes = RegexSet::new(schemes).expect("valid oEmbed provider schemes");
+
+			(endpoint, schemes)
+		})
+		.collect()
+});
+
+static LINK_ELEMENT: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(r"(?is)<link\s[^>]*>").expect("valid regex"));
+
+static LINK_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+	Regex::new(r#"(?is)([a-z]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("valid regex")
+});
+
+/// Subset of an oEmbed response used for previews.
+#[derive(Debug, Default, Deserialize)]
+struct OEmbed {
+	#[serde(rename = "type")]
+	kind: String,
+	title: Option<String>,
+	author_name: Option<String>,
+	provider_name: Option<String>,
+	url: Option<String>,
+	thumbnail_url: Option<String>,
+}
+
+/// Returns the oEmbed endpoint of a bundled provider for `url`, if any.
+#[must_use]
+pub(super) fn provider_endpoint(url: &Url) -> Option<Url> {
+	PROVIDER_SCHEMES
+		.iter()
+		.find(|(_, schemes)| schemes.is_match(url.as_str()))
+		.and_then(|(endpoint, _)| Url::parse(endpoint).ok())
+}
+
+/// Returns the JSON oEmbed endpoint advertised by a page through a
+/// `<link rel="alternate" type="application/json+oembed">` element, if any.
+#[must_use]
+pub(super) fn discover_endpoint(page: &Url, html: &str) -> Option<Url> {
+	LINK_ELEMENT
+		.find_iter(html)
+		.map(|element| {
+			LINK_ATTRIBUTE
+				.captures_iter(element.as_str())
+				.filter_map(|attr| {
+					let name = attr.get(1)?.as_str().to_lowercase();
+					let value = attr.get(2).or_else(|| attr.get(3))?.as_str();
+
+					Some((name, value))
+				})
+				.collect::<Vec<_>>()
+		})
+		.find(|attrs| {
+			attrs.iter().any(|(name, value)| {
+				name == "type" && value.eq_ignore_ascii_case("application/json+oembed")
+			})
+		})
+		.and_then(|attrs| {
+			attrs
+				.into_iter()
+				.find(|(name, _)| name == "href")
+				.map(|(_, href)| href.replace("&amp;", "&"))
+		})
+		.and_then(|href| page.join(&href).ok())
+}
+
+/// Build a preview of `url` through the oEmbed endpoint advertised by its
+/// HTML, if any.
+#[implement(Service)]
+pub(super) async fn discovered_oembed_preview(
+	&self,
+	url: &str,
+	html: &str,
+	images: bool,
+) -> Option<UrlPreviewData> {
+	let url = Url::parse(url).ok()?;
+	let endpoint = discover_endpoint(&url, html)?;
+
+	self.oembed_preview(&url, endpoint, true, images)
+		.await
+		.inspect_err(|e| debug!(?url, "Failed to use discovered oEmbed endpoint: {e}"))
+		.ok()
+}
+
+/// Build a preview of `url` through the oEmbed endpoint `endpoint`. When the
+/// endpoint was discovered, `endpoint` is the full URL advertised by the page;
+/// otherwise the URL of the content is added to the query. Thumbnails are only
+/// downloaded if `images` is set.
+#[implement(Service)]
+pub(super) async fn oembed_preview(
+	&self,
+	url: &Url,
+	mut endpoint: Url,
+	discovered: bool,
+	images: bool,
+) -> Result<UrlPreviewData> {
+	if !discovered {
+		endpoint
+			.query_pairs_mut()
+			.append_pair("url", url.as_str())
+			.append_pair("format", "json");
+	}
+
+	if !matches!(endpoint.scheme(), "http" | "https") {
+		return Err!(Request(Forbidden("oEmbed endpoint is not an HTTP(S) URL")));
+	}
+
+	if discovered && !self.url_preview_allowed(&endpoint) {
+		return Err!(Request(Forbidden("oEmbed endpoint is not allowed")));
+	}
+
+	if let Ok(ip) = IPAddress::parse(endpoint.host_str().unwrap_or(EMPTY)) {
+		if !self.services.client.valid_cidr_range(&ip) {
+			return Err!(Request(Forbidden("Requesting from this address is forbidden")));
+		}
+	}
+
+	let client = &self.services.client.url_preview;
+	let mut response = client.get(endpoint.as_str()).send().await?;
+
+	if let Some(remote_addr) = response.remote_addr() {
+		if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
+			if !self.services.client.valid_cidr_range(&ip) {
+				return Err!(Request(Forbidden("Requesting from this address is forbidden")));
+			}
+		}
+	}
+
+	let status = response.status();
+	if !status.is_success() {
+		return Err!(Request(Unknown("oEmbed endpoint responded with {status}")));
+	}
+
+	let max_size = self.services.globals.url_preview_max_spider_size();
+	let mut bytes: Vec<u8> = Vec::new();
+	while let Some(chunk) = response.chunk().await? {
+		bytes.extend_from_slice(&chunk);
+		if bytes.len() > max_size {
+			return Err!(Request(TooLarge(
+				"oEmbed response exceeds url_preview_max_spider_size"
+			)));
+		}
+	}
+
+	let oembed: OEmbed = serde_json::from_slice(&bytes)
+		.map_err(|e| err!(Request(Unknown("Failed to parse oEmbed response: {e}"))))?;
+
+	debug!(?url, ?oembed, "oEmbed response");
+
+	// photos are the content itself; every other type may only have a thumbnail
+	let image = match oembed.kind.as_str() {
+		| "photo" => oembed.url.as_ref().or(oembed.thumbnail_url.as_ref()),
+		| _ => oembed.thumbnail_url.as_ref(),
+	};
+
+	let mut data = match image.filter(|_| images) {
+		| Some(image) => self.download_image(image).await.unwrap_or_default(),
+		| None => UrlPreviewData::default(),
+	};
+
+	data.title = oembed.title;
+	data.description = match (oembed.author_name, oembed.provider_name) {
+		| (Some(author), Some(provider)) => Some(format!("{author} on {provider}")),
+		| (author, provider) => author.or(provider),
+	};
+
+	Ok(data)
+}
@@ -5,15 +5,18 @@
 //! of dependencies and nulls out results through the existing interface when
 //! not featured.
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use conduwuit::{Err, Result, debug, err};
+use conduwuit::{Err, Result, debug, err, utils::stream::ReadyExt};
 use conduwuit_core::implement;
+use futures::{Stream, StreamExt};
 use ipaddress::IPAddress;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use super::Service;
+#[cfg(feature = "url_preview")]
+use super::oembed;
 
 #[derive(Serialize, Default)]
 pub struct UrlPreviewData {
@@ -31,10 +34,27 @@ pub struct UrlPreviewData {
 	pub image_height: Option<u32>,
 }
 
+/// Rules for previewing URLs on a domain and its subdomains, managed at
+/// runtime through the admin room.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UrlPreviewPolicy {
+	/// Never preview URLs on the domain.
+	#[serde(default)]
+	pub deny: bool,
+
+	/// Don't download images for previews of URLs on the domain.
+	#[serde(default)]
+	pub no_images: bool,
+
+	/// Seconds to cache previews for instead of `url_preview_cache_ttl`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ttl: Option<u64>,
+}
+
 #[implement(Service)]
 pub async fn remove_url_preview(&self, url: &str) -> Result<()> {
 	// TODO: also remove the downloaded image
-	self.db.remove_url_preview(url)
+	self.db.remove_url_preview(url).await
 }
 
 #[implement(Service)]
@@ -42,32 +62,184 @@ pub async fn set_url_preview(&self, url: &str, data: &UrlPreviewData) -> Result<
 	let now = SystemTime::now()
 		.duration_since(SystemTime::UNIX_EPOCH)
 		.expect("valid system time");
-	self.db.set_url_preview(url, data, now)
+	self.db.set_url_preview(url, data, now).await?;
+	self.evict_url_previews().await
 }
 
 #[implement(Service)]
 pub async fn get_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
-	if let Ok(preview) = self.db.get_url_preview(url.as_str()).await {
+	let policy = self
+		.url_preview_policy(url.host_str().unwrap_or_default())
+		.await
+		.unwrap_or_default();
+
+	if policy.deny {
+		return Err!(Request(Forbidden("URL previews are disabled for this domain")));
+	}
+
+	if let Some(preview) = self.cached_url_preview(url, &policy).await {
 		return Ok(preview);
 	}
 
 	// ensure that only one request is made per URL
 	let _request_lock = self.url_preview_mutex.lock(url.as_str()).await;
 
-	match self.db.get_url_preview(url.as_str()).await {
-		| Ok(preview) => Ok(preview),
-		| Err(_) => self.request_url_preview(url).await,
+	match self.cached_url_preview(url, &policy).await {
+		| Some(preview) => Ok(preview),
+		| None => self.request_url_preview(url, &policy).await,
+	}
+}
+
+/// Returns the cached preview of `url` unless it has expired.
+#[implement(Service)]
+async fn cached_url_preview(
+	&self,
+	url: &Url,
+	policy: &UrlPreviewPolicy,
+) -> Option<UrlPreviewData> {
+	let (cached_at, preview) = self.db.get_url_preview(url.as_str()).await.ok()?;
+
+	let ttl = policy
+		.ttl
+		.unwrap_or(self.services.server.config.url_preview_cache_ttl);
+
+	let age = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.expect("valid system time")
+		.saturating_sub(cached_at);
+
+	(ttl == 0 || age < Duration::from_secs(ttl)).then_some(preview)
+}
+
+/// Remove the oldest cached previews once there are more than
+/// `url_preview_cache_max_entries`, leaving some room to not have to do this
+/// again on the next insertion.
+#[implement(Service)]
+async fn evict_url_previews(&self) -> Result<()> {
+	let max_entries = self.services.server.config.url_preview_cache_max_entries;
+	if max_entries == 0 {
+		return Ok(());
+	}
+
+	let count = self.db.url_preview_count().await;
+	if count <= max_entries {
+		return Ok(());
+	}
+
+	let mut previews: Vec<_> = self.db.url_previews().collect().await;
+	previews.sort_unstable_by_key(|(_, cached_at)| *cached_at);
+
+	let excess = count.saturating_sub(max_entries.saturating_sub(max_entries / 10));
+	debug!(count, excess, "Evicting URL previews exceeding url_preview_cache_max_entries");
+
+	for (url, _) in previews.into_iter().take(excess) {
+		self.remove_url_preview(&url).await?;
 	}
+
+	Ok(())
 }
 
+/// Remove cached previews of URLs on `domain` and its subdomains, or all
+/// cached previews if None. Returns the number of removed previews.
 #[implement(Service)]
-async fn request_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
+pub async fn clear_url_previews(&self, domain: Option<&str>) -> Result<usize> {
+	let urls: Vec<_> = self
+		.db
+		.url_previews()
+		.ready_filter(|(url, _)| {
+			domain.is_none_or(|domain| {
+				Url::parse(url)
+					.ok()
+					.and_then(|url| url.host_str().map(|host| is_within(host, domain)))
+					.unwrap_or(false)
+			})
+		})
+		.map(|(url, _)| url)
+		.collect()
+		.await;
+
+	for url in &urls {
+		self.remove_url_preview(url).await?;
+	}
+
+	Ok(urls.len())
+}
+
+/// Returns the policy for previewing URLs on `host`. Policies set on a domain
+/// apply to its subdomains unless they have a policy of their own.
+#[implement(Service)]
+pub async fn url_preview_policy(&self, host: &str) -> Option<UrlPreviewPolicy> {
+	let host = host.to_lowercase();
+	let mut domain = Some(host.as_str());
+	while let Some(current) = domain {
+		if let Ok(policy) = self.db.get_url_preview_policy(current).await {
+			return Some(policy);
+		}
+
+		domain = current.split_once('.').map(|(_, parent)| parent);
+	}
+
+	None
+}
+
+#[implement(Service)]
+pub fn set_url_preview_policy(&self, domain: &str, policy: &UrlPreviewPolicy) {
+	self.db
+		.set_url_preview_policy(&domain.to_lowercase(), policy);
+}
+
+#[implement(Service)]
+pub async fn remove_url_preview_policy(&self, domain: &str) -> Result<()> {
+	let domain = domain.to_lowercase();
+	if self.db.get_url_preview_policy(&domain).await.is_err() {
+		return Err!(Request(NotFound("No URL preview policy for {domain:?}.")));
+	}
+
+	self.db.remove_url_preview_policy(&domain);
+
+	Ok(())
+}
+
+#[implement(Service)]
+pub fn url_preview_policies(&self) -> impl Stream<Item = (String, UrlPreviewPolicy)> + Send + '_ {
+	self.db.url_preview_policies()
+}
+
+fn is_within(host: &str, domain: &str) -> bool {
+	host.eq_ignore_ascii_case(domain)
+		|| host
+			.to_lowercase()
+			.ends_with(&format!(".{}", domain.to_lowercase()))
+}
+
+#[implement(Service)]
+async fn request_url_preview(
+	&self,
+	url: &Url,
+	policy: &UrlPreviewPolicy,
+) -> Result<UrlPreviewData> {
 	if let Ok(ip) = IPAddress::parse(url.host_str().expect("URL previously validated")) {
 		if !self.services.client.valid_cidr_range(&ip) {
 			return Err!(Request(Forbidden("Requesting from this address is forbidden")));
 		}
 	}
 
+	#[cfg(feature = "url_preview")]
+	if self.services.server.config.url_preview_oembed {
+		if let Some(endpoint) = oembed::provider_endpoint(url) {
+			match self
+				.oembed_preview(url, endpoint, false, !policy.no_images)
+				.await
+			{
+				| Ok(data) => {
+					self.set_url_preview(url.as_str(), &data).await?;
+					return Ok(data);
+				},
+				| Err(e) => debug!(?url, "oEmbed provider failed, falling back to scraping: {e}"),
+			}
+		}
+	}
+
 	let client = &self.services.client.url_preview;
 	let response = client.head(url.as_str()).send().await?;
 
@@ -92,8 +264,11 @@ async fn request_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
 		.map_err(|e| err!(Request(Unknown("Unknown or invalid Content-Type header: {e}"))))?;
 
 	let data = match content_type {
-		| html if html.starts_with("text/html") => self.download_html(url.as_str()).await?,
-		| img if img.starts_with("image/") => self.download_image(url.as_str()).await?,
+		| html if html.starts_with("text/html") =>
+			self.download_html(url.as_str(), !policy.no_images).await?,
+		| img if img.starts_with("image/") && !policy.no_images =>
+			self.download_image(url.as_str()).await?,
+		| img if img.starts_with("image/") => UrlPreviewData::default(),
 		| _ => return Err!(Request(Unknown("Unsupported Content-Type"))),
 	};
 
@@ -144,7 +319,7 @@ pub async fn download_image(&self, _url: &str) -> Result<UrlPreviewData> {
 
 #[cfg(feature = "url_preview")]
 #[implement(Service)]
-async fn download_html(&self, url: &str) -> Result<UrlPreviewData> {
+async fn download_html(&self, url: &str, images: bool) -> Result<UrlPreviewData> {
 	use webpage::HTML;
 
 	let client = &self.services.client.url_preview;
@@ -169,23 +344,36 @@ async fn download_html(&self, url: &str) -> Result<UrlPreviewData> {
 		return Err!(Request(Unknown("Failed to parse HTML")));
 	};
 
+	let mut oembed = if self.services.server.config.url_preview_oembed {
+		self.discovered_oembed_preview(url, &body, images).await
+	} else {
+		None
+	}
+	.unwrap_or_default();
+
+	let (title, description) = (oembed.title.take(), oembed.description.take());
 	let mut data = match html.opengraph.images.first() {
-		| None => UrlPreviewData::default(),
-		| Some(obj) => self.download_image(&obj.url).await?,
+		| Some(obj) if images && oembed.image.is_none() => self.download_image(&obj.url).await?,
+		| _ => oembed,
 	};
 
 	let props = html.opengraph.properties;
 
-	/* use OpenGraph title/description, but fall back to HTML if not available */
-	data.title = props.get("title").cloned().or(html.title);
-	data.description = props.get("description").cloned().or(html.description);
+	/* prefer the oEmbed title and OpenGraph description, but fall back to HTML
+	 * and then each other if not available */
+	data.title = title.or_else(|| props.get("title").cloned()).or(html.title);
+	data.description = props
+		.get("description")
+		.cloned()
+		.or(html.description)
+		.or(description);
 
 	Ok(data)
 }
 
 #[cfg(not(feature = "url_preview"))]
 #[implement(Service)]
-async fn download_html(&self, _url: &str) -> Result<UrlPreviewData> {
+async fn download_html(&self, _url: &str, _images: bool) -> Result<UrlPreviewData> {
 	Err!(FeatureDisabled("url_preview"))
 }
 
@@ -0,0 +1,120 @@
+use std::time::SystemTime;
+
+use conduwuit::{
+	Err, Result, err, implement,
+	utils::{content_disposition::make_content_disposition, time},
+};
+use ruma::{Mxc, ServerName};
+
+use super::{
+	Dim,
+	data::Metadata,
+	media_file_name,
+	sigv4::{UNSIGNED_PAYLOAD, canonical_query, canonical_request, scope, signature, uri_encode},
+};
+
+/// Whether downloads of media from `origin` are redirected to object storage.
+#[implement(super::Service)]
+#[must_use]
+pub fn redirect_enabled_for(&self, origin: &ServerName) -> bool {
+	let config = &self.services.server.config.media_redirect;
+	config.enable
+		&& config.endpoint.is_some()
+		&& (config.origins.is_empty() || config.origins.is_match(origin.as_str()))
+}
+
+/// Returns the URL a download of `mxc` can be redirected to, or None if the
+/// media has to be served directly because redirects are disabled for its
+/// origin, it is not cached locally, or it is too small to be worth it.
+#[implement(super::Service)]
+pub async fn get_redirect(
+	&self,
+	mxc: &Mxc<'_>,
+	filename: Option<&str>,
+) -> Result<Option<String>> {
+	if !self.redirect_enabled_for(mxc.server_name) {
+		return Ok(None);
+	}
+
+	let Ok(Metadata { content_disposition, content_type, key }) =
+		self.db.search_file_metadata(mxc, &Dim::default()).await
+	else {
+		return Ok(None);
+	};
+
+	let Ok(file) = self.stat_media_file(&key).await else {
+		return Ok(None);
+	};
+
+	let config = &self.services.server.config.media_redirect;
+	if file.size < config.min_size {
+		return Ok(None);
+	}
+
+	let object = &media_file_name(&key);
+
+	let content_disposition =
+		make_content_disposition(content_disposition.as_ref(), content_type.as_deref(), filename);
+
+	let mut query = vec![("response-content-disposition", content_disposition.to_string())];
+	if let Some(content_type) = content_type {
+		query.push(("response-content-type", content_type));
+	}
+
+	let url = self.object_url(object, query)?;
+	self.reference_media(mxc).await;
+
+	Ok(Some(url))
+}
+
+/// Build the URL of `object` in the configured bucket, presigned with AWS
+/// Signature Version 4 if credentials are configured.
+#[implement(super::Service)]
+fn object_url(&self, object: &str, mut query: Vec<(&str, String)>) -> Result<String> {
+	let config = &self.services.server.config.media_redirect;
+	let endpoint = config
+		.endpoint
+		.as_ref()
+		.ok_or_else(|| err!(Config("media_redirect.endpoint", "No endpoint configured.")))?;
+
+	let host = match (endpoint.host_str(), endpoint.port()) {
+		| (Some(host), Some(port)) => format!("{host}:{port}"),
+		| (Some(host), None) => host.to_owned(),
+		| (None, _) => return Err!(Config("media_redirect.endpoint", "Endpoint has no host.")),
+	};
+
+	let path = format!("{}/{}", endpoint.path().trim_end_matches('/'), uri_encode(object));
+
+	if let (Some(access_key_id), Some(secret_access_key)) =
+		(&config.access_key_id, &config.secret_access_key)
+	{
+		let now = SystemTime::now();
+		let amz_date = time::format(now, "%Y%m%dT%H%M%SZ");
+		let scope = scope(&time::format(now, "%Y%m%d"), &config.region);
+
+		query.extend([
+			("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_owned()),
+			("X-Amz-Credential", format!("{access_key_id}/{scope}")),
+			("X-Amz-Date", amz_date.clone()),
+			("X-Amz-Expires", config.presign_ttl.to_string()),
+			("X-Amz-SignedHeaders", "host".to_owned()),
+		]);
+
+		let canonical_query = canonical_query(&mut query);
+		let headers = [("host".to_owned(), host.clone())];
+		let canonical_request =
+			canonical_request("GET", &path, &canonical_query, &headers, UNSIGNED_PAYLOAD);
+
+		let signature =
+			signature(secret_access_key, &amz_date, &config.region, &canonical_request)?;
+
+		return Ok(format!(
+			"{}://{host}{path}?{canonical_query}&X-Amz-Signature={signature}",
+			endpoint.scheme()
+		));
+	}
+
+	let canonical_query = canonical_query(&mut query);
+
+	Ok(format!("{}://{host}{path}?{canonical_query}", endpoint.scheme()))
+}
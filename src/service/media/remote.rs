@@ -83,7 +83,7 @@ async fn fetch_thumbnail_authenticated(
 		method: dim.method.clone().into(),
 		width: dim.width.into(),
 		height: dim.height.into(),
-		animated: true.into(),
+		animated: dim.animated.into(),
 		timeout_ms,
 	};
 
@@ -134,7 +134,7 @@ async fn fetch_thumbnail_unauthenticated(
 	let request = Request {
 		allow_remote: true,
 		allow_redirect: true,
-		animated: true.into(),
+		animated: dim.animated.into(),
 		method: dim.method.clone().into(),
 		width: dim.width.into(),
 		height: dim.height.into(),
@@ -194,16 +194,22 @@ async fn handle_thumbnail_file(
 		None,
 	);
 
-	self.upload_thumbnail(
-		mxc,
-		user,
-		Some(&content_disposition),
-		content.content_type.as_deref(),
-		dim,
-		&content.file,
-	)
-	.await
-	.map(|()| FileMeta {
+	if self
+		.caches_remote_media(mxc.server_name, content.file.len())
+		.await
+	{
+		self.upload_thumbnail(
+			mxc,
+			user,
+			Some(&content_disposition),
+			content.content_type.as_deref(),
+			dim,
+			&content.file,
+		)
+		.await?;
+	}
+
+	Ok(FileMeta {
 		content: Some(content.file),
 		content_type: content.content_type.map(Into::into),
 		content_disposition: Some(content_disposition),
@@ -223,15 +229,21 @@ async fn handle_content_file(
 		None,
 	);
 
-	self.create(
-		mxc,
-		user,
-		Some(&content_disposition),
-		content.content_type.as_deref(),
-		&content.file,
-	)
-	.await
-	.map(|()| FileMeta {
+	if self
+		.caches_remote_media(mxc.server_name, content.file.len())
+		.await
+	{
+		self.create(
+			mxc,
+			user,
+			Some(&content_disposition),
+			content.content_type.as_deref(),
+			&content.file,
+		)
+		.await?;
+	}
+
+	Ok(FileMeta {
 		content: Some(content.file),
 		content_type: content.content_type.map(Into::into),
 		content_disposition: Some(content_disposition),
@@ -372,9 +384,21 @@ pub async fn fetch_remote_thumbnail_legacy(
 		})
 		.await?;
 
-	let dim = Dim::from_ruma(body.width, body.height, body.method.clone())?;
-	self.upload_thumbnail(&mxc, None, None, reponse.content_type.as_deref(), &dim, &reponse.file)
+	let dim = Dim::from_ruma(body.width, body.height, body.method.clone(), body.animated)?;
+	if self
+		.caches_remote_media(mxc.server_name, reponse.file.len())
+		.await
+	{
+		self.upload_thumbnail(
+			&mxc,
+			None,
+			None,
+			reponse.content_type.as_deref(),
+			&dim,
+			&reponse.file,
+		)
 		.await?;
+	}
 
 	Ok(reponse)
 }
@@ -407,14 +431,19 @@ pub async fn fetch_remote_content_legacy(
 		None,
 	);
 
-	self.create(
-		mxc,
-		None,
-		Some(&content_disposition),
-		response.content_type.as_deref(),
-		&response.file,
-	)
-	.await?;
+	if self
+		.caches_remote_media(mxc.server_name, response.file.len())
+		.await
+	{
+		self.create(
+			mxc,
+			None,
+			Some(&content_disposition),
+			response.content_type.as_deref(),
+			&response.file,
+		)
+		.await?;
+	}
 
 	Ok(response)
 }
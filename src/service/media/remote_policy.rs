@@ -0,0 +1,149 @@
+//! Remote media caching policies
+//!
+//! Media fetched from remote servers is cached like local media unless a
+//! policy for its origin server says otherwise: it may not be cached at all,
+//! only up to a size, or only for some time. Policies come from the config
+//! and from the admin room, the latter taking precedence.
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	time::{Duration, SystemTime},
+};
+
+use conduwuit::{Err, Result, config::RemoteMediaPolicy, implement, warn};
+use futures::StreamExt;
+use ruma::{Mxc, OwnedServerName, ServerName};
+
+/// The caching policy for media from `server`.
+#[implement(super::Service)]
+pub async fn remote_media_policy(&self, server: &ServerName) -> Option<RemoteMediaPolicy> {
+	match self.db.get_remote_media_policy(server).await {
+		| Ok(policy) => Some(policy),
+		| Err(_) => self
+			.services
+			.server
+			.config
+			.remote_media_policies
+			.get(server)
+			.cloned(),
+	}
+}
+
+/// Whether media of `size` bytes from `server` may be cached.
+#[implement(super::Service)]
+pub(super) async fn caches_remote_media(&self, server: &ServerName, size: usize) -> bool {
+	self.remote_media_policy(server)
+		.await
+		.is_none_or(|policy| !policy.no_cache && policy.max_size.is_none_or(|max| size <= max))
+}
+
+/// Set the caching policy for media from `server`, replacing any existing
+/// policy set in the admin room or the config.
+#[implement(super::Service)]
+pub fn set_remote_media_policy(&self, server: &ServerName, policy: &RemoteMediaPolicy) {
+	self.db.set_remote_media_policy(server, policy);
+}
+
+#[implement(super::Service)]
+pub async fn remove_remote_media_policy(&self, server: &ServerName) -> Result {
+	if self.db.get_remote_media_policy(server).await.is_err() {
+		if self
+			.services
+			.server
+			.config
+			.remote_media_policies
+			.contains_key(server)
+		{
+			return Err!(Request(Forbidden(
+				"The remote media policy for {server} is set in the config."
+			)));
+		}
+
+		return Err!(Request(NotFound("No remote media policy for {server}.")));
+	}
+
+	self.db.remove_remote_media_policy(server);
+
+	Ok(())
+}
+
+/// The policies of all servers, from the config and the admin room.
+#[implement(super::Service)]
+pub async fn remote_media_policies(&self) -> BTreeMap<OwnedServerName, RemoteMediaPolicy> {
+	let mut policies = self.services.server.config.remote_media_policies.clone();
+	let set: Vec<_> = self.db.remote_media_policies().collect().await;
+	policies.extend(set);
+
+	policies
+}
+
+/// Delete cached media which its server's policy no longer allows to be
+/// cached: all media of servers not to cache media from, and media cached
+/// longer than the TTL of its server. Returns the number of deleted files.
+#[implement(super::Service)]
+pub async fn enforce_remote_media_policies(&self) -> Result<usize> {
+	let policies = self.remote_media_policies().await;
+	if policies
+		.values()
+		.all(|policy| !policy.no_cache && policy.ttl.is_none())
+	{
+		return Ok(0);
+	}
+
+	let now = SystemTime::now();
+	// thumbnails are listed along with their media
+	let mxcs: HashSet<_> = self.get_all_mxcs().await?.into_iter().collect();
+
+	let mut deleted: usize = 0;
+	for owned in mxcs {
+		let Ok(mxc) = <Mxc<'_>>::try_from(owned.as_str()) else {
+			continue;
+		};
+
+		let Some(policy) = policies.get(mxc.server_name) else {
+			continue;
+		};
+
+		if self.services.globals.server_is_ours(mxc.server_name) {
+			continue;
+		}
+
+		let expired = match policy.ttl {
+			| _ if policy.no_cache => true,
+			| Some(ttl) => match now.checked_sub(Duration::from_secs(ttl)) {
+				| Some(before) => self.cached_before(&mxc, before).await,
+				| None => false,
+			},
+			| None => false,
+		};
+
+		if !expired {
+			continue;
+		}
+
+		match self.delete(&mxc).await {
+			| Ok(()) => deleted = deleted.saturating_add(1),
+			| Err(e) => warn!(%owned, "Failed to delete media under remote media policy: {e}"),
+		}
+	}
+
+	Ok(deleted)
+}
+
+/// Whether any file of `mxc` was cached before `before`.
+#[implement(super::Service)]
+async fn cached_before(&self, mxc: &Mxc<'_>, before: SystemTime) -> bool {
+	let Ok(keys) = self.db.search_mxc_metadata_prefix(mxc).await else {
+		return false;
+	};
+
+	for key in keys {
+		if let Ok(stat) = self.stat_media_file(&key).await {
+			if stat.modified < before {
+				return true;
+			}
+		}
+	}
+
+	false
+}
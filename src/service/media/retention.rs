@@ -0,0 +1,393 @@
+//! Media retention
+//!
+//! Local media is deleted once a retention policy applies to it: media not
+//! referenced for some time, media sent to a room and media uploaded by a
+//! user. Media is referenced by being uploaded, sent in an event, set as an
+//! avatar or downloaded; the profile avatars of local users are always
+//! referenced. The worker evaluates the policies periodically and reports
+//! what they apply to to the admin room, deleting it on its next run.
+
+use std::{
+	collections::HashSet,
+	fmt,
+	str::FromStr,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use conduwuit::{
+	Err, Error, PduEvent, Result, debug_warn, implement, info,
+	utils::{millis_since_unix_epoch, time},
+	warn,
+};
+use futures::{FutureExt, StreamExt};
+use ruma::{Mxc, OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId};
+use serde::Deserialize;
+
+use super::{Dim, RETENTION_INTERVAL};
+
+/// What a media retention policy applies to.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RetentionScope {
+	/// Local media, once it was not referenced for the retention
+	Unreferenced,
+
+	/// Media sent to the room, once the retention passed since it was first
+	/// sent there
+	Room(OwnedRoomId),
+
+	/// Media uploaded by the user, once the retention passed since its upload
+	User(OwnedUserId),
+}
+
+/// Media a retention policy applies to.
+#[derive(Debug)]
+pub struct RetentionCandidate {
+	pub mxc: OwnedMxcUri,
+	pub scope: RetentionScope,
+}
+
+/// Media of events: the file and thumbnail, either of which may be
+/// encrypted, or the avatar of a room or member.
+#[derive(Deserialize)]
+struct MediaContent {
+	url: Option<OwnedMxcUri>,
+	file: Option<EncryptedFile>,
+	info: Option<MediaInfo>,
+	avatar_url: Option<OwnedMxcUri>,
+}
+
+#[derive(Deserialize)]
+struct MediaInfo {
+	thumbnail_url: Option<OwnedMxcUri>,
+	thumbnail_file: Option<EncryptedFile>,
+}
+
+#[derive(Deserialize)]
+struct EncryptedFile {
+	url: OwnedMxcUri,
+}
+
+/// Downloads only update the time media was last referenced once this long
+/// (milliseconds) passed, instead of on every download.
+const REFERENCE_RESOLUTION: u64 = 60 * 60 * 1000;
+
+impl fmt::Display for RetentionScope {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Unreferenced => f.write_str("unreferenced"),
+			| Self::Room(room_id) => write!(f, "{room_id}"),
+			| Self::User(user_id) => write!(f, "{user_id}"),
+		}
+	}
+}
+
+impl FromStr for RetentionScope {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			| "unreferenced" => Ok(Self::Unreferenced),
+			| room_id if room_id.starts_with('!') => Ok(Self::Room(room_id.try_into()?)),
+			| user_id if user_id.starts_with('@') => Ok(Self::User(user_id.try_into()?)),
+			| _ => Err!(
+				"Unknown media retention scope {s:?}; expected \"unreferenced\", a room ID or a \
+				 user ID."
+			),
+		}
+	}
+}
+
+/// Set the retention of media in `scope`, replacing any existing policy.
+#[implement(super::Service)]
+pub fn set_retention_policy(&self, scope: &RetentionScope, retention: Duration) {
+	self.db
+		.set_retention_policy(&scope.to_string(), retention.as_secs());
+}
+
+#[implement(super::Service)]
+pub async fn remove_retention_policy(&self, scope: &RetentionScope) -> Result {
+	let scope = scope.to_string();
+	if self.db.get_retention_policy(&scope).await.is_err() {
+		return Err!(Request(NotFound("No media retention policy for {scope}.")));
+	}
+
+	self.db.remove_retention_policy(&scope);
+
+	Ok(())
+}
+
+#[implement(super::Service)]
+pub async fn retention_policies(&self) -> Vec<(RetentionScope, Duration)> {
+	self.db
+		.retention_policies()
+		.filter_map(|(scope, retention)| async move {
+			match scope.parse() {
+				| Ok(scope) => Some((scope, Duration::from_secs(retention))),
+				| Err(e) => {
+					debug_warn!("Ignoring media retention policy for {scope:?}: {e}");
+					None
+				},
+			}
+		})
+		.collect()
+		.await
+}
+
+/// Mark the local media an event refers to as referenced, and as sent to the
+/// event's room.
+#[implement(super::Service)]
+pub async fn reference_event_media(&self, pdu: &PduEvent) {
+	let Ok(content) = pdu.get_content::<MediaContent>() else {
+		return;
+	};
+
+	let info = content.info.into_iter().flat_map(|info| {
+		info.thumbnail_url
+			.into_iter()
+			.chain(info.thumbnail_file.map(|file| file.url))
+	});
+
+	let mxcs = content
+		.url
+		.into_iter()
+		.chain(content.file.map(|file| file.url))
+		.chain(info)
+		.chain(content.avatar_url);
+
+	let now = millis_since_unix_epoch();
+	for mxc in mxcs {
+		let Ok(mxc) = <Mxc<'_>>::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if !self.services.globals.server_is_ours(mxc.server_name) {
+			continue;
+		}
+
+		self.db.set_last_reference(&mxc, now);
+		self.db.add_room_reference(&pdu.room_id, &mxc, now).await;
+	}
+}
+
+/// Mark local media as referenced by a download or upload.
+#[implement(super::Service)]
+pub(super) async fn reference_media(&self, mxc: &Mxc<'_>) {
+	if !self.services.globals.server_is_ours(mxc.server_name) {
+		return;
+	}
+
+	let now = millis_since_unix_epoch();
+	let last = self.db.get_last_reference(mxc).await.unwrap_or_default();
+	if now.saturating_sub(last) >= REFERENCE_RESOLUTION {
+		self.db.set_last_reference(mxc, now);
+	}
+}
+
+/// The media retention policies apply to, without deleting it.
+#[implement(super::Service)]
+pub async fn retention_candidates(&self) -> Result<Vec<RetentionCandidate>> {
+	let now = millis_since_unix_epoch();
+	let mut seen = HashSet::new();
+	let mut candidates = Vec::new();
+	for (scope, retention) in self.retention_policies().await {
+		let retention = u64::try_from(retention.as_millis())?;
+		let before = now.saturating_sub(retention);
+		let mxcs = match &scope {
+			| RetentionScope::Unreferenced => self.unreferenced_media(before).await?,
+			| RetentionScope::Room(room_id) => self.room_media(room_id, before).await,
+			| RetentionScope::User(user_id) => {
+				let mut mxcs = self.db.get_all_user_mxcs(user_id).await;
+				mxcs.retain(|mxc| !seen.contains(mxc));
+				self.media_uploaded_before(mxcs, before).await
+			},
+		};
+
+		for mxc in mxcs {
+			if seen.insert(mxc.clone()) {
+				candidates.push(RetentionCandidate { mxc, scope: scope.clone() });
+			}
+		}
+	}
+
+	Ok(candidates)
+}
+
+/// Local media last referenced before `before`. Media referenced before
+/// references were tracked, and profile avatars of local users, count as
+/// referenced now.
+#[implement(super::Service)]
+async fn unreferenced_media(&self, before: u64) -> Result<Vec<OwnedMxcUri>> {
+	let now = millis_since_unix_epoch();
+	// thumbnails are listed along with their media
+	let mxcs: HashSet<_> = self.get_all_mxcs().await?.into_iter().collect();
+	let avatars: HashSet<_> = self
+		.services
+		.users
+		.list_local_users()
+		.filter_map(|user_id| self.services.users.avatar_url(user_id).map(Result::ok))
+		.collect()
+		.await;
+
+	let mut unreferenced = Vec::new();
+	for owned in mxcs {
+		let Ok(mxc) = <Mxc<'_>>::try_from(owned.as_str()) else {
+			continue;
+		};
+
+		if !self.services.globals.server_is_ours(mxc.server_name) {
+			continue;
+		}
+
+		if avatars.contains(&owned) {
+			self.db.set_last_reference(&mxc, now);
+			continue;
+		}
+
+		match self.db.get_last_reference(&mxc).await {
+			| Ok(last) if last < before => unreferenced.push(owned),
+			| Ok(_) => {},
+			| Err(_) => self.db.set_last_reference(&mxc, now),
+		}
+	}
+
+	Ok(unreferenced)
+}
+
+/// Media first sent to `room_id` before `before`.
+#[implement(super::Service)]
+async fn room_media(&self, room_id: &RoomId, before: u64) -> Vec<OwnedMxcUri> {
+	let references: Vec<_> = self.db.room_references(room_id).collect().await;
+
+	let mut mxcs = Vec::new();
+	for (mxc, ts) in references {
+		if ts >= before {
+			continue;
+		}
+
+		let exists = match <Mxc<'_>>::try_from(mxc.as_str()) {
+			| Ok(parsed) => self.db.search_mxc_metadata_prefix(&parsed).await.is_ok(),
+			| Err(_) => false,
+		};
+
+		if exists {
+			mxcs.push(mxc);
+		} else {
+			// the media was deleted since
+			self.db.remove_room_reference(room_id, mxc.as_str());
+		}
+	}
+
+	mxcs
+}
+
+/// Media of `mxcs` whose file was created before `before`.
+#[implement(super::Service)]
+async fn media_uploaded_before(&self, mxcs: Vec<OwnedMxcUri>, before: u64) -> Vec<OwnedMxcUri> {
+	let mut uploaded = Vec::new();
+	for owned in mxcs {
+		let Ok(mxc) = <Mxc<'_>>::try_from(owned.as_str()) else {
+			continue;
+		};
+
+		let Ok(metadata) = self.db.search_file_metadata(&mxc, &Dim::default()).await else {
+			continue;
+		};
+
+		let Ok(stat) = self.stat_media_file(&metadata.key).await else {
+			continue;
+		};
+
+		let created = stat
+			.modified
+			.duration_since(UNIX_EPOCH)
+			.map(|created| created.as_millis());
+
+		if created.is_ok_and(|created| created < u128::from(before)) {
+			uploaded.push(owned);
+		}
+	}
+
+	uploaded
+}
+
+/// Delete the media retention policies applied to on the previous run as
+/// well, and report the media they newly apply to to the admin room, to be
+/// deleted on the next run unless the policies change by then. Returns the
+/// number of deleted files.
+#[implement(super::Service)]
+pub async fn enforce_retention(&self) -> Result<usize> {
+	let candidates = self.retention_candidates().await?;
+	let (due, new): (Vec<_>, Vec<_>) = {
+		let reported = self.retention_reported.lock().expect("locked");
+		candidates
+			.into_iter()
+			.partition(|candidate| reported.contains(&candidate.mxc))
+	};
+
+	if !new.is_empty() {
+		let report = format!(
+			"{} media files fall under media retention policies and will be deleted in \
+			 {}:\n{}\n\nList them with `!admin media retention preview`, or delete them now \
+			 with `!admin media retention run`.",
+			new.len(),
+			time::pretty(RETENTION_INTERVAL),
+			retention_summary(&new),
+		);
+
+		info!("{report}");
+		self.services.admin.send_text(&report).await;
+	}
+
+	*self.retention_reported.lock().expect("locked") =
+		new.into_iter().map(|candidate| candidate.mxc).collect();
+
+	Ok(self.delete_retention_candidates(&due).await)
+}
+
+/// Delete the media retention policies apply to right away, without reporting
+/// it first. Returns the number of deleted files.
+#[implement(super::Service)]
+pub async fn enforce_retention_now(&self) -> Result<usize> {
+	let candidates = self.retention_candidates().await?;
+	self.retention_reported.lock().expect("locked").clear();
+
+	Ok(self.delete_retention_candidates(&candidates).await)
+}
+
+#[implement(super::Service)]
+async fn delete_retention_candidates(&self, candidates: &[RetentionCandidate]) -> usize {
+	let mut deleted: usize = 0;
+	for candidate in candidates {
+		let Ok(mxc) = <Mxc<'_>>::try_from(candidate.mxc.as_str()) else {
+			continue;
+		};
+
+		match self.delete(&mxc).await {
+			| Ok(()) => deleted = deleted.saturating_add(1),
+			| Err(e) =>
+				warn!(%candidate.mxc, "Failed to delete media under retention policy: {e}"),
+		}
+	}
+
+	deleted
+}
+
+/// Number of candidates per policy, one per line.
+fn retention_summary(candidates: &[RetentionCandidate]) -> String {
+	let mut counts: Vec<(&RetentionScope, usize)> = Vec::new();
+	for candidate in candidates {
+		match counts
+			.iter_mut()
+			.find(|(scope, _)| *scope == &candidate.scope)
+		{
+			| Some((_, count)) => *count = count.saturating_add(1),
+			| None => counts.push((&candidate.scope, 1)),
+		}
+	}
+
+	counts
+		.iter()
+		.map(|(scope, count)| format!("- {scope}: {count}"))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use conduwuit::{Error, Result, debug};
+use futures::{StreamExt, stream};
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use super::{ByteStream, Stat, Storage};
+
+/// Size of the chunks media files are read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Backend storing media files in a local directory.
+pub(in super::super) struct Filesystem {
+	dir: PathBuf,
+}
+
+impl Filesystem {
+	pub(in super::super) fn new(dir: PathBuf) -> Self { Self { dir } }
+
+	pub(in super::super) fn path(&self, name: &str) -> PathBuf { self.dir.join(name) }
+}
+
+#[async_trait]
+impl Storage for Filesystem {
+	async fn put(&self, name: &str, content: &[u8]) -> Result {
+		let path = self.path(name);
+		debug!(?path, "Creating media file");
+
+		let mut file = fs::File::create(&path).await?;
+		file.write_all(content).await?;
+
+		Ok(())
+	}
+
+	async fn put_file(&self, name: &str, path: &Path) -> Result {
+		fs::copy(path, self.path(name)).await?;
+
+		Ok(())
+	}
+
+	async fn get(&self, name: &str) -> Result<ByteStream> {
+		let file = fs::File::open(self.path(name)).await?;
+		let chunks = stream::try_unfold(file, |mut file| async move {
+			let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+			if file.read_buf(&mut chunk).await? == 0 {
+				return Ok::<_, Error>(None);
+			}
+
+			Ok(Some((chunk.freeze(), file)))
+		});
+
+		Ok(chunks.boxed())
+	}
+
+	async fn delete(&self, name: &str) -> Result {
+		let path = self.path(name);
+		debug!(?path, "Removing media file");
+
+		Ok(fs::remove_file(&path).await?)
+	}
+
+	async fn list(&self) -> Result<Vec<String>> {
+		let mut names = Vec::new();
+		let mut dir = fs::read_dir(&self.dir).await?;
+		while let Some(entry) = dir.next_entry().await? {
+			// legacy symlinks for Conduit compatibility are not media files
+			if !entry.file_type().await?.is_file() {
+				continue;
+			}
+
+			if let Ok(name) = entry.file_name().into_string() {
+				names.push(name);
+			}
+		}
+
+		Ok(names)
+	}
+
+	async fn stat(&self, name: &str) -> Result<Stat> {
+		let metadata = fs::metadata(self.path(name)).await?;
+		let modified = match metadata.created() {
+			| Ok(created) => created,
+			| Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+				debug!("btime is unsupported, using mtime instead");
+				metadata.modified()?
+			},
+			| Err(e) => return Err(e.into()),
+		};
+
+		Ok(Stat { size: metadata.len(), modified })
+	}
+}
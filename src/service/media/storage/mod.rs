@@ -0,0 +1,46 @@
+//! Media file storage backends
+//!
+//! Media files are stored under a name derived from their key, either in
+//! the media directory under the database path or in an S3-compatible
+//! object storage bucket.
+
+mod fs;
+mod s3;
+
+use std::{path::Path, time::SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use conduwuit::Result;
+use futures::stream::BoxStream;
+
+pub(super) use self::{fs::Filesystem, s3::S3};
+
+/// Content of a stored media file, read as it is consumed.
+pub(super) type ByteStream = BoxStream<'static, Result<Bytes>>;
+
+/// Size and modification time of a stored media file.
+#[derive(Debug)]
+pub(super) struct Stat {
+	pub(super) size: u64,
+	pub(super) modified: SystemTime,
+}
+
+#[async_trait]
+pub(super) trait Storage: Send + Sync {
+	/// Store `content` as `name`, replacing any file of that name.
+	async fn put(&self, name: &str, content: &[u8]) -> Result;
+
+	/// Store the local file at `path` as `name`, without reading the whole
+	/// file into memory at once.
+	async fn put_file(&self, name: &str, path: &Path) -> Result;
+
+	async fn get(&self, name: &str) -> Result<ByteStream>;
+
+	async fn delete(&self, name: &str) -> Result;
+
+	/// Names of all stored files.
+	async fn list(&self) -> Result<Vec<String>>;
+
+	async fn stat(&self, name: &str) -> Result<Stat>;
+}
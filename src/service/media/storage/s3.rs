@@ -0,0 +1,480 @@
+use std::{
+	fmt::Write,
+	path::Path,
+	time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, config::MediaStorageConfig, debug, err, utils::time, warn};
+use futures::{StreamExt, TryStreamExt};
+use reqwest::{
+	Body, Method, Response, StatusCode,
+	header::{AUTHORIZATION, CONTENT_LENGTH, ETAG, LAST_MODIFIED},
+};
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncReadExt};
+
+use super::{ByteStream, Stat, Storage};
+use crate::media::sigv4::{
+	UNSIGNED_PAYLOAD, canonical_query, canonical_request, hex, scope, signature, signed_headers,
+	uri_encode,
+};
+
+/// Backend storing media files as objects in an S3-compatible bucket,
+/// addressed in path style.
+pub(in super::super) struct S3 {
+	client: reqwest::Client,
+	scheme: String,
+	host: String,
+	/// Path of the bucket, including the path of the endpoint
+	bucket_path: String,
+	prefix: String,
+	region: String,
+	credentials: Option<(String, String)>,
+	sse: Option<String>,
+	sse_kms_key_id: Option<String>,
+	multipart_threshold: usize,
+	part_size: usize,
+	timeout: Duration,
+}
+
+/// Body of a request.
+enum Payload {
+	/// Body held in memory, whose hash is signed with the request.
+	Bytes(Vec<u8>),
+
+	/// Body streamed as it is sent, which is left unsigned.
+	Stream(Body),
+}
+
+/// Multipart upload in progress.
+struct Multipart<'a> {
+	s3: &'a S3,
+	name: &'a str,
+	upload_id: String,
+	etags: Vec<String>,
+}
+
+impl S3 {
+	pub(in super::super) fn new(
+		client: reqwest::Client,
+		config: &MediaStorageConfig,
+	) -> Result<Self> {
+		let endpoint = config
+			.endpoint
+			.as_ref()
+			.ok_or_else(|| err!(Config("media_storage.endpoint", "No endpoint configured.")))?;
+
+		let bucket = config
+			.bucket
+			.as_ref()
+			.ok_or_else(|| err!(Config("media_storage.bucket", "No bucket configured.")))?;
+
+		let host = match (endpoint.host_str(), endpoint.port()) {
+			| (Some(host), Some(port)) => format!("{host}:{port}"),
+			| (Some(host), None) => host.to_owned(),
+			| (None, _) =>
+				return Err!(Config("media_storage.endpoint", "Endpoint has no host.")),
+		};
+
+		let credentials = config
+			.access_key_id
+			.clone()
+			.zip(config.secret_access_key.clone());
+
+		Ok(Self {
+			client,
+			scheme: endpoint.scheme().to_owned(),
+			host,
+			bucket_path: format!(
+				"{}/{}",
+				endpoint.path().trim_end_matches('/'),
+				uri_encode(bucket)
+			),
+			prefix: config.prefix.clone(),
+			region: config.region.clone(),
+			credentials,
+			sse: config.sse.clone(),
+			sse_kms_key_id: config.sse_kms_key_id.clone(),
+			multipart_threshold: config.multipart_threshold,
+			part_size: config.part_size,
+			timeout: Duration::from_secs(config.timeout),
+		})
+	}
+
+	/// Headers requesting server-side encryption of new objects.
+	fn sse_headers(&self) -> Vec<(&'static str, String)> {
+		let mut headers = Vec::new();
+		if let Some(sse) = &self.sse {
+			headers.push(("x-amz-server-side-encryption", sse.clone()));
+		}
+
+		if let Some(key_id) = &self.sse_kms_key_id {
+			headers.push(("x-amz-server-side-encryption-aws-kms-key-id", key_id.clone()));
+		}
+
+		headers
+	}
+
+	/// Send a request for the object `name`.
+	async fn request(
+		&self,
+		method: Method,
+		name: &str,
+		query: Vec<(&str, String)>,
+		headers: Vec<(&str, String)>,
+		body: impl Into<Payload> + Send,
+	) -> Result<Response> {
+		let key = format!("{}{name}", self.prefix);
+		let path = format!(
+			"{}/{}",
+			self.bucket_path,
+			key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+		);
+
+		self.send(method, name, &path, query, headers, body).await
+	}
+
+	/// Send a request for `path`, signed with AWS Signature Version 4 if
+	/// credentials are configured. `name` is what the request is for in
+	/// errors.
+	async fn send(
+		&self,
+		method: Method,
+		name: &str,
+		path: &str,
+		mut query: Vec<(&str, String)>,
+		headers: Vec<(&str, String)>,
+		body: impl Into<Payload> + Send,
+	) -> Result<Response> {
+		let canonical_query = canonical_query(&mut query);
+		let (payload_hash, body) = match body.into() {
+			| Payload::Bytes(body) => (hex(&Sha256::digest(&body)), Body::from(body)),
+			| Payload::Stream(body) => (UNSIGNED_PAYLOAD.to_owned(), body),
+		};
+
+		let now = SystemTime::now();
+		let amz_date = time::format(now, "%Y%m%dT%H%M%SZ");
+
+		let mut headers: Vec<(String, String)> = headers
+			.into_iter()
+			.map(|(header, value)| (header.to_lowercase(), value.trim().to_owned()))
+			.chain([
+				("host".to_owned(), self.host.clone()),
+				("x-amz-content-sha256".to_owned(), payload_hash.clone()),
+				("x-amz-date".to_owned(), amz_date.clone()),
+			])
+			.collect();
+
+		headers.sort_unstable();
+
+		let url = if canonical_query.is_empty() {
+			format!("{}://{}{path}", self.scheme, self.host)
+		} else {
+			format!("{}://{}{path}?{canonical_query}", self.scheme, self.host)
+		};
+
+		let mut request = self
+			.client
+			.request(method.clone(), url)
+			.timeout(self.timeout)
+			.body(body);
+
+		for (header, value) in headers.iter().filter(|(header, _)| header != "host") {
+			request = request.header(header, value);
+		}
+
+		if let Some((access_key_id, secret_access_key)) = &self.credentials {
+			let scope = scope(&time::format(now, "%Y%m%d"), &self.region);
+			let signed_headers = signed_headers(&headers);
+			let canonical_request = canonical_request(
+				method.as_str(),
+				path,
+				&canonical_query,
+				&headers,
+				&payload_hash,
+			);
+
+			let signature =
+				signature(secret_access_key, &amz_date, &self.region, &canonical_request)?;
+
+			request = request.header(
+				AUTHORIZATION,
+				format!(
+					"AWS4-HMAC-SHA256 Credential={access_key_id}/{scope}, \
+					 SignedHeaders={signed_headers}, Signature={signature}"
+				),
+			);
+		}
+
+		let response = request.send().await?;
+		let status = response.status();
+		if status == StatusCode::NOT_FOUND {
+			return Err!(Request(NotFound("Media file {name} not found in object storage.")));
+		}
+
+		if !status.is_success() {
+			let body = response.text().await.unwrap_or_default();
+			return Err!("Object storage responded to {method} of {name} with {status}: {body}");
+		}
+
+		Ok(response)
+	}
+
+	async fn create_multipart<'a>(&'a self, name: &'a str) -> Result<Multipart<'a>> {
+		let response = self
+			.request(
+				Method::POST,
+				name,
+				vec![("uploads", String::new())],
+				self.sse_headers(),
+				Vec::new(),
+			)
+			.await?;
+
+		let body = response.text().await?;
+		let upload_id = xml_element(&body, "UploadId")
+			.ok_or_else(|| err!("Object storage did not return the ID of the multipart upload."))?
+			.to_owned();
+
+		debug!(%name, %upload_id, "Started multipart upload");
+
+		Ok(Multipart {
+			s3: self,
+			name,
+			upload_id,
+			etags: Vec::new(),
+		})
+	}
+}
+
+impl Multipart<'_> {
+	async fn upload_part(&mut self, part: Vec<u8>) -> Result {
+		let number = self.etags.len().saturating_add(1);
+		let response = self
+			.s3
+			.request(
+				Method::PUT,
+				self.name,
+				vec![("partNumber", number.to_string()), ("uploadId", self.upload_id.clone())],
+				Vec::new(),
+				part,
+			)
+			.await?;
+
+		let etag = response
+			.headers()
+			.get(ETAG)
+			.and_then(|etag| etag.to_str().ok())
+			.ok_or_else(|| err!("Object storage did not return the ETag of part {number}."))?;
+
+		self.etags.push(etag.to_owned());
+
+		Ok(())
+	}
+
+	async fn complete(self) -> Result {
+		let parts = self
+			.etags
+			.iter()
+			.enumerate()
+			.fold(String::new(), |mut out, (i, etag)| {
+				let number = i.saturating_add(1);
+				write!(out, "<Part><PartNumber>{number}</PartNumber><ETag>{etag}</ETag></Part>")
+					.expect("writing to string");
+				out
+			});
+
+		let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+		let response = self
+			.s3
+			.request(
+				Method::POST,
+				self.name,
+				vec![("uploadId", self.upload_id.clone())],
+				Vec::new(),
+				body.into_bytes(),
+			)
+			.await?;
+
+		// errors can be reported in the body of a successful response
+		let body = response.text().await?;
+		if let Some(error) = xml_element(&body, "Error") {
+			return Err!("Object storage failed to complete the multipart upload: {error}");
+		}
+
+		Ok(())
+	}
+
+	async fn abort(self) {
+		if let Err(e) = self
+			.s3
+			.request(
+				Method::DELETE,
+				self.name,
+				vec![("uploadId", self.upload_id.clone())],
+				Vec::new(),
+				Vec::new(),
+			)
+			.await
+		{
+			warn!(name = %self.name, "Failed to abort multipart upload: {e}");
+		}
+	}
+}
+
+#[async_trait]
+impl Storage for S3 {
+	async fn put(&self, name: &str, content: &[u8]) -> Result {
+		if content.len() <= self.multipart_threshold {
+			self.request(Method::PUT, name, Vec::new(), self.sse_headers(), content.to_vec())
+				.await?;
+
+			return Ok(());
+		}
+
+		let mut upload = self.create_multipart(name).await?;
+		for part in content.chunks(self.part_size) {
+			if let Err(e) = upload.upload_part(part.to_vec()).await {
+				upload.abort().await;
+				return Err(e);
+			}
+		}
+
+		upload.complete().await
+	}
+
+	async fn put_file(&self, name: &str, path: &Path) -> Result {
+		let mut file = fs::File::open(path).await?;
+		let len = file.metadata().await?.len();
+		if len <= u64::try_from(self.multipart_threshold)? {
+			let mut headers = self.sse_headers();
+			headers.push(("content-length", len.to_string()));
+			self.request(Method::PUT, name, Vec::new(), headers, Payload::Stream(file.into()))
+				.await?;
+
+			return Ok(());
+		}
+
+		let mut upload = self.create_multipart(name).await?;
+		loop {
+			let mut part = Vec::with_capacity(self.part_size);
+			let read = (&mut file)
+				.take(u64::try_from(self.part_size)?)
+				.read_to_end(&mut part)
+				.await;
+
+			match read {
+				| Ok(0) => break,
+				| Ok(_) =>
+					if let Err(e) = upload.upload_part(part).await {
+						upload.abort().await;
+						return Err(e);
+					},
+				| Err(e) => {
+					upload.abort().await;
+					return Err(e.into());
+				},
+			}
+		}
+
+		upload.complete().await
+	}
+
+	async fn get(&self, name: &str) -> Result<ByteStream> {
+		let response = self
+			.request(Method::GET, name, Vec::new(), Vec::new(), Vec::new())
+			.await?;
+
+		Ok(response.bytes_stream().map_err(Into::into).boxed())
+	}
+
+	async fn delete(&self, name: &str) -> Result {
+		self.request(Method::DELETE, name, Vec::new(), Vec::new(), Vec::new())
+			.await?;
+
+		Ok(())
+	}
+
+	async fn list(&self) -> Result<Vec<String>> {
+		let path = format!("{}/", self.bucket_path);
+		let mut names = Vec::new();
+		let mut token = None;
+		loop {
+			let mut query = vec![("list-type", "2".to_owned()), ("prefix", self.prefix.clone())];
+			if let Some(token) = token.take() {
+				query.push(("continuation-token", token));
+			}
+
+			let response = self
+				.send(Method::GET, "the bucket listing", &path, query, Vec::new(), Vec::new())
+				.await?;
+
+			let body = response.text().await?;
+			names.extend(
+				xml_elements(&body, "Key")
+					.into_iter()
+					.filter_map(|key| key.strip_prefix(&self.prefix))
+					.map(ToOwned::to_owned),
+			);
+
+			match xml_element(&body, "NextContinuationToken") {
+				| Some(next) if xml_element(&body, "IsTruncated") == Some("true") =>
+					token = Some(next.to_owned()),
+				| _ => break,
+			}
+		}
+
+		Ok(names)
+	}
+
+	async fn stat(&self, name: &str) -> Result<Stat> {
+		let response = self
+			.request(Method::HEAD, name, Vec::new(), Vec::new(), Vec::new())
+			.await?;
+
+		let headers = response.headers();
+		let size = headers
+			.get(CONTENT_LENGTH)
+			.and_then(|len| len.to_str().ok()?.parse().ok())
+			.ok_or_else(|| err!("Object storage did not return the size of {name}."))?;
+
+		let modified = headers
+			.get(LAST_MODIFIED)
+			.and_then(|modified| modified.to_str().ok())
+			.and_then(|modified| chrono::DateTime::parse_from_rfc2822(modified).ok())
+			.map(SystemTime::from)
+			.ok_or_else(|| {
+				err!("Object storage did not return the modification time of {name}.")
+			})?;
+
+		Ok(Stat { size, modified })
+	}
+}
+
+impl From<Vec<u8>> for Payload {
+	fn from(body: Vec<u8>) -> Self { Self::Bytes(body) }
+}
+
+/// The text of the first element `name` of an XML document.
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+	xml_split(xml, name).map(|(text, _)| text)
+}
+
+/// The text of all elements `name` of an XML document.
+fn xml_elements<'a>(mut xml: &'a str, name: &str) -> Vec<&'a str> {
+	let mut elements = Vec::new();
+	while let Some((text, rest)) = xml_split(xml, name) {
+		elements.push(text);
+		xml = rest;
+	}
+
+	elements
+}
+
+/// The text of the first element `name` and the document after it.
+fn xml_split<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+	let (_, rest) = xml.split_once(&format!("<{name}>"))?;
+
+	rest.split_once(&format!("</{name}>"))
+}
@@ -5,25 +5,39 @@
 //! inclusion of dependencies and nulls out results using the existing interface
 //! when not featured.
 
-use std::{cmp, num::Saturating as Sat};
+use std::{cmp, collections::HashSet, num::Saturating as Sat, str::FromStr};
 
-use conduwuit::{Result, checked, err, implement};
+use conduwuit::{Err, Error, Result, checked, debug_warn, err, implement, info};
 use ruma::{Mxc, UInt, UserId, http_headers::ContentDisposition, media::Method};
-use tokio::{
-	fs,
-	io::{AsyncReadExt, AsyncWriteExt},
-};
 
 use super::{FileMeta, data::Metadata};
 
 /// Dimension specification for a thumbnail.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Dim {
 	pub width: u32,
 	pub height: u32,
 	pub method: Method,
+
+	/// Whether an animated thumbnail was requested, stored apart from the
+	/// still thumbnail of the same size
+	pub animated: bool,
 }
 
+/// Speed of GIF encoding for animated thumbnails, from 1 (slowest, best
+/// quality) to 30
+#[cfg(feature = "media_thumbnail")]
+const GIF_ENCODER_SPEED: i32 = 10;
+
+/// Largest width and height of animated images animated thumbnails are
+/// generated for, as each of their frames is decoded at its full size
+#[cfg(feature = "media_thumbnail")]
+const ANIMATED_MAX_DIMENSION: u32 = 4096;
+
+/// Most memory the decoder of an animated image may allocate at once
+#[cfg(feature = "media_thumbnail")]
+const ANIMATED_MAX_ALLOC: u64 = 128 * 1024 * 1024;
+
 impl super::Service {
 	/// Uploads or replaces a file thumbnail.
 	#[allow(clippy::too_many_arguments)]
@@ -41,10 +55,7 @@ pub async fn upload_thumbnail(
 				.create_file_metadata(mxc, user, dim, content_disposition, content_type)?;
 
 		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
-
-		Ok(())
+		self.write_media_file(&key, file).await
 	}
 
 	/// Downloads a file's thumbnail.
@@ -65,13 +76,19 @@ pub async fn get_thumbnail(&self, mxc: &Mxc<'_>, dim: &Dim) -> Result<Option<Fil
 		// 0, 0 because that's the original file
 		let dim = dim.normalized();
 
-		match self.db.search_file_metadata(mxc, &dim).await {
+		let thumbnail = match self.db.search_file_metadata(mxc, &dim).await {
 			| Ok(metadata) => self.get_thumbnail_saved(metadata).await,
 			| _ => match self.db.search_file_metadata(mxc, &Dim::default()).await {
 				| Ok(metadata) => self.get_thumbnail_generate(mxc, &dim, metadata).await,
 				| _ => Ok(None),
 			},
+		};
+
+		if matches!(thumbnail, Ok(Some(_))) {
+			self.reference_media(mxc).await;
 		}
+
+		thumbnail
 	}
 }
 
@@ -79,12 +96,7 @@ pub async fn get_thumbnail(&self, mxc: &Mxc<'_>, dim: &Dim) -> Result<Option<Fil
 #[implement(super::Service)]
 #[tracing::instrument(name = "saved", level = "debug", skip(self, data))]
 async fn get_thumbnail_saved(&self, data: Metadata) -> Result<Option<FileMeta>> {
-	let mut content = Vec::new();
-	let path = self.get_media_file(&data.key);
-	fs::File::open(path)
-		.await?
-		.read_to_end(&mut content)
-		.await?;
+	let content = self.read_media_file(&data.key).await?;
 
 	Ok(Some(into_filemeta(data, content)))
 }
@@ -99,12 +111,18 @@ async fn get_thumbnail_generate(
 	dim: &Dim,
 	data: Metadata,
 ) -> Result<Option<FileMeta>> {
-	let mut content = Vec::new();
-	let path = self.get_media_file(&data.key);
-	fs::File::open(path)
-		.await?
-		.read_to_end(&mut content)
-		.await?;
+	let content = self.read_media_file(&data.key).await?;
+
+	if dim.animated {
+		if let Some(thumbnail_bytes) = self.animated_thumbnail(&content, dim).await? {
+			let data = Metadata {
+				content_type: Some("image/gif".to_owned()),
+				..data
+			};
+
+			return self.save_thumbnail(mxc, dim, data, thumbnail_bytes).await;
+		}
+	}
 
 	let Ok(image) = image::load_from_memory(&content) else {
 		// Couldn't parse file to generate thumbnail, send original
@@ -115,14 +133,21 @@ async fn get_thumbnail_generate(
 		return Ok(Some(into_filemeta(data, content)));
 	}
 
-	let mut thumbnail_bytes = Vec::new();
-	let thumbnail = thumbnail_generate(&image, dim)?;
-	let mut cursor = std::io::Cursor::new(&mut thumbnail_bytes);
-	thumbnail
-		.write_to(&mut cursor, image::ImageFormat::Png)
-		.map_err(|error| err!(error!(?error, "Error writing PNG thumbnail.")))?;
+	let thumbnail_bytes = thumbnail_png(&image, dim)?;
+
+	self.save_thumbnail(mxc, dim, data, thumbnail_bytes).await
+}
 
-	// Save thumbnail in database so we don't have to generate it again next time
+/// Save thumbnail in database so we don't have to generate it again next time
+#[cfg(feature = "media_thumbnail")]
+#[implement(super::Service)]
+async fn save_thumbnail(
+	&self,
+	mxc: &Mxc<'_>,
+	dim: &Dim,
+	data: Metadata,
+	thumbnail_bytes: Vec<u8>,
+) -> Result<Option<FileMeta>> {
 	let thumbnail_key = self.db.create_file_metadata(
 		mxc,
 		None,
@@ -131,12 +156,35 @@ async fn get_thumbnail_generate(
 		data.content_type.as_deref(),
 	)?;
 
-	let mut f = self.create_media_file(&thumbnail_key).await?;
-	f.write_all(&thumbnail_bytes).await?;
+	self.write_media_file(&thumbnail_key, &thumbnail_bytes)
+		.await?;
 
 	Ok(Some(into_filemeta(data, thumbnail_bytes)))
 }
 
+/// Generate an animated GIF thumbnail on the blocking thread pool. Returns
+/// None if the image is not animated, exceeds the limits for animated
+/// thumbnails or is not larger than the thumbnail, leaving it to the still
+/// thumbnail.
+#[cfg(feature = "media_thumbnail")]
+#[implement(super::Service)]
+#[tracing::instrument(name = "animated", level = "debug", skip(self, content))]
+async fn animated_thumbnail(&self, content: &[u8], dim: &Dim) -> Result<Option<Vec<u8>>> {
+	let config = &self.services.server.config;
+	let max_frames = config.media_thumbnail_animated_max_frames;
+	if max_frames == 0 || content.len() > config.media_thumbnail_animated_max_size {
+		return Ok(None);
+	}
+
+	let content = content.to_vec();
+	let dim = dim.clone();
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || animated_thumbnail_generate(&content, &dim, max_frames))
+		.await?
+}
+
 #[cfg(not(feature = "media_thumbnail"))]
 #[implement(super::Service)]
 #[tracing::instrument(name = "fallback", level = "debug", skip_all)]
@@ -149,6 +197,248 @@ async fn get_thumbnail_generate(
 	self.get_thumbnail_saved(data).await
 }
 
+/// Generate the thumbnails sized by `media_thumbnail_pregenerate` which do
+/// not exist yet for a file, on the blocking thread pool. Returns the number
+/// of thumbnails generated.
+#[cfg(feature = "media_thumbnail")]
+#[implement(super::Service)]
+#[tracing::instrument(name = "pregenerate", level = "debug", skip(self))]
+pub async fn pregenerate_thumbnails(&self, mxc: &Mxc<'_>) -> Result<usize> {
+	let mut dims = Vec::new();
+	for dim in self.pregenerate_dims() {
+		if self.db.search_file_metadata(mxc, &dim).await.is_err() {
+			dims.push(dim);
+		}
+	}
+
+	if dims.is_empty() {
+		return Ok(0);
+	}
+
+	let data = self.db.search_file_metadata(mxc, &Dim::default()).await?;
+
+	let content = self.read_media_file(&data.key).await?;
+	let thumbnails = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || thumbnails_generate(&content, dims))
+		.await??;
+
+	for (dim, thumbnail_bytes) in &thumbnails {
+		let thumbnail_key = self.db.create_file_metadata(
+			mxc,
+			None,
+			dim,
+			data.content_disposition.as_ref(),
+			data.content_type.as_deref(),
+		)?;
+
+		self.write_media_file(&thumbnail_key, thumbnail_bytes)
+			.await?;
+	}
+
+	Ok(thumbnails.len())
+}
+
+#[cfg(not(feature = "media_thumbnail"))]
+#[implement(super::Service)]
+pub async fn pregenerate_thumbnails(&self, _mxc: &Mxc<'_>) -> Result<usize> { Ok(0) }
+
+/// Generate the missing thumbnails sized by `media_thumbnail_pregenerate` for
+/// all local media. Returns the number of thumbnails generated.
+#[implement(super::Service)]
+pub async fn pregenerate_all_thumbnails(&self) -> Result<usize> {
+	if self.pregenerate_dims().is_empty() {
+		return Err!("No thumbnail sizes are configured in `media_thumbnail_pregenerate`.");
+	}
+
+	// thumbnails are listed along with their media
+	let mxcs: HashSet<_> = self.get_all_mxcs().await?.into_iter().collect();
+
+	let mut generated: usize = 0;
+	for owned in mxcs {
+		let Ok(mxc) = <Mxc<'_>>::try_from(owned.as_str()) else {
+			continue;
+		};
+
+		if !self.services.globals.server_is_ours(mxc.server_name) {
+			continue;
+		}
+
+		match self.pregenerate_thumbnails(&mxc).await {
+			| Ok(count) => generated = generated.saturating_add(count),
+			| Err(e) => debug_warn!(%owned, "Failed to generate thumbnails: {e}"),
+		}
+	}
+
+	info!("Generated {generated} thumbnails of local media");
+
+	Ok(generated)
+}
+
+/// The normalized sizes of `media_thumbnail_pregenerate`, without duplicates.
+#[implement(super::Service)]
+fn pregenerate_dims(&self) -> Vec<Dim> {
+	let mut dims: Vec<Dim> = Vec::new();
+	for size in &self.services.server.config.media_thumbnail_pregenerate {
+		let Ok(dim) = size.parse::<Dim>() else {
+			continue;
+		};
+
+		// too large to be thumbnailed
+		let dim = dim.normalized();
+		if dim.width == 0 {
+			continue;
+		}
+
+		if !dims
+			.iter()
+			.any(|seen| (seen.width, seen.height) == (dim.width, dim.height))
+		{
+			dims.push(dim);
+		}
+	}
+
+	dims
+}
+
+/// Decode an image once to generate its thumbnails of `dims`. Sizes larger
+/// than the image are skipped, as are files which are not images.
+#[cfg(feature = "media_thumbnail")]
+fn thumbnails_generate(content: &[u8], dims: Vec<Dim>) -> Result<Vec<(Dim, Vec<u8>)>> {
+	let Ok(image) = image::load_from_memory(content) else {
+		return Ok(Vec::new());
+	};
+
+	dims.into_iter()
+		.filter(|dim| dim.width <= image.width() && dim.height <= image.height())
+		.map(|dim| thumbnail_png(&image, &dim).map(|bytes| (dim, bytes)))
+		.collect()
+}
+
+/// Resize the frames of an animated image one at a time, so only a single
+/// frame of the full size is held in memory.
+#[cfg(feature = "media_thumbnail")]
+fn animated_thumbnail_generate(
+	content: &[u8],
+	dim: &Dim,
+	max_frames: usize,
+) -> Result<Option<Vec<u8>>> {
+	use image::{
+		DynamicImage, Frame,
+		codecs::gif::{GifEncoder, Repeat},
+	};
+
+	let Some(frames) = animation_frames(content) else {
+		return Ok(None);
+	};
+
+	let mut thumbnail_frames = Vec::new();
+	for frame in frames {
+		if thumbnail_frames.len() >= max_frames {
+			return Ok(None);
+		}
+
+		let Ok(frame) = frame else {
+			return Ok(None);
+		};
+
+		let delay = frame.delay();
+		let image = DynamicImage::ImageRgba8(frame.into_buffer());
+		if thumbnail_frames.is_empty()
+			&& (dim.width > image.width() || dim.height > image.height())
+		{
+			return Ok(None);
+		}
+
+		let thumbnail = thumbnail_generate(&image, dim)?.into_rgba8();
+		thumbnail_frames.push(Frame::from_parts(thumbnail, 0, 0, delay));
+	}
+
+	if thumbnail_frames.len() < 2 {
+		return Ok(None);
+	}
+
+	let mut thumbnail_bytes = Vec::new();
+	let mut encoder = GifEncoder::new_with_speed(&mut thumbnail_bytes, GIF_ENCODER_SPEED);
+	encoder
+		.set_repeat(Repeat::Infinite)
+		.and_then(|()| encoder.encode_frames(thumbnail_frames))
+		.map_err(|error| err!(error!(?error, "Error writing GIF thumbnail.")))?;
+
+	drop(encoder);
+
+	Ok(Some(thumbnail_bytes))
+}
+
+/// The decoder of the frames of a GIF, WebP or PNG image, unless it is a
+/// still WebP or PNG image, another format or exceeds the limits of animated
+/// images.
+#[cfg(feature = "media_thumbnail")]
+fn animation_frames(content: &[u8]) -> Option<image::Frames<'_>> {
+	use std::io::Cursor;
+
+	use image::{
+		AnimationDecoder, ImageFormat,
+		codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+	};
+
+	let cursor = Cursor::new(content);
+	let frames = match image::guess_format(content).ok()? {
+		| ImageFormat::Gif => limit_decoder(GifDecoder::new(cursor).ok()?)?.into_frames(),
+		| ImageFormat::WebP => {
+			let decoder = limit_decoder(WebPDecoder::new(cursor).ok()?)?;
+			if !decoder.has_animation() {
+				return None;
+			}
+
+			decoder.into_frames()
+		},
+		| ImageFormat::Png => {
+			let decoder = limit_decoder(PngDecoder::new(cursor).ok()?)?;
+			if !decoder.is_apng().ok()? {
+				return None;
+			}
+
+			decoder.apng().ok()?.into_frames()
+		},
+		| _ => return None,
+	};
+
+	Some(frames)
+}
+
+/// Limit the dimensions of the image and the allocations of its decoder, or
+/// None if the image is too large.
+#[cfg(feature = "media_thumbnail")]
+fn limit_decoder<D: image::ImageDecoder>(mut decoder: D) -> Option<D> {
+	let (width, height) = decoder.dimensions();
+	if width > ANIMATED_MAX_DIMENSION || height > ANIMATED_MAX_DIMENSION {
+		return None;
+	}
+
+	let mut limits = image::Limits::default();
+	limits.max_image_width = Some(ANIMATED_MAX_DIMENSION);
+	limits.max_image_height = Some(ANIMATED_MAX_DIMENSION);
+	limits.max_alloc = Some(ANIMATED_MAX_ALLOC);
+	decoder.set_limits(limits).ok()?;
+
+	Some(decoder)
+}
+
+#[cfg(feature = "media_thumbnail")]
+fn thumbnail_png(image: &image::DynamicImage, dim: &Dim) -> Result<Vec<u8>> {
+	let mut thumbnail_bytes = Vec::new();
+	let thumbnail = thumbnail_generate(image, dim)?;
+	let mut cursor = std::io::Cursor::new(&mut thumbnail_bytes);
+	thumbnail
+		.write_to(&mut cursor, image::ImageFormat::Png)
+		.map_err(|error| err!(error!(?error, "Error writing PNG thumbnail.")))?;
+
+	Ok(thumbnail_bytes)
+}
+
 #[cfg(feature = "media_thumbnail")]
 fn thumbnail_generate(
 	image: &image::DynamicImage,
@@ -179,8 +469,14 @@ fn into_filemeta(data: Metadata, content: Vec<u8>) -> FileMeta {
 }
 
 impl Dim {
-	/// Instantiate a Dim from Ruma integers with optional method.
-	pub fn from_ruma(width: UInt, height: UInt, method: Option<Method>) -> Result<Self> {
+	/// Instantiate a Dim from Ruma integers with optional method and
+	/// preference for an animated thumbnail.
+	pub fn from_ruma(
+		width: UInt,
+		height: UInt,
+		method: Option<Method>,
+		animated: Option<bool>,
+	) -> Result<Self> {
 		let width = width
 			.try_into()
 			.map_err(|e| err!(Request(InvalidParam("Width is invalid: {e:?}"))))?;
@@ -188,7 +484,10 @@ pub fn from_ruma(width: UInt, height: UInt, method: Option<Method>) -> Result<Se
 			.try_into()
 			.map_err(|e| err!(Request(InvalidParam("Height is invalid: {e:?}"))))?;
 
-		Ok(Self::new(width, height, method))
+		Ok(Self {
+			animated: animated.unwrap_or(false),
+			..Self::new(width, height, method)
+		})
 	}
 
 	/// Instantiate a Dim with optional method
@@ -199,6 +498,7 @@ pub fn new(width: u32, height: u32, method: Option<Method>) -> Self {
 			width,
 			height,
 			method: method.unwrap_or(Method::Scale),
+			animated: false,
 		}
 	}
 
@@ -229,6 +529,7 @@ pub fn scaled(&self, image: &Self) -> Result<Self> {
 			width: x,
 			height: y,
 			method: Method::Scale,
+			animated: self.animated,
 		})
 	}
 
@@ -237,13 +538,25 @@ pub fn scaled(&self, image: &Self) -> Result<Self> {
 	/// Ignores the input Method.
 	#[must_use]
 	pub fn normalized(&self) -> Self {
-		match (self.width, self.height) {
+		let normalized = match (self.width, self.height) {
 			| (0..=32, 0..=32) => Self::new(32, 32, Some(Method::Crop)),
 			| (0..=96, 0..=96) => Self::new(96, 96, Some(Method::Crop)),
 			| (0..=320, 0..=240) => Self::new(320, 240, Some(Method::Scale)),
 			| (0..=640, 0..=480) => Self::new(640, 480, Some(Method::Scale)),
 			| (0..=800, 0..=600) => Self::new(800, 600, Some(Method::Scale)),
-			| _ => Self::default(),
+			| _ => return Self::default(),
+		};
+
+		Self { animated: self.animated, ..normalized }
+	}
+
+	/// Dimensions in the database key of the thumbnail. Animated thumbnails
+	/// are keyed apart from still ones of the same size.
+	pub(super) fn key(&self) -> Vec<u32> {
+		if self.animated {
+			vec![self.width, self.height, 1]
+		} else {
+			vec![self.width, self.height]
 		}
 	}
 
@@ -253,6 +566,19 @@ pub fn normalized(&self) -> Self {
 	pub fn crop(&self) -> bool { self.method == Method::Crop }
 }
 
+impl FromStr for Dim {
+	type Err = Error;
+
+	/// Parse a size like `320x240`.
+	fn from_str(s: &str) -> Result<Self> {
+		let Some((width, height)) = s.split_once('x') else {
+			return Err!("Thumbnail size {s:?} is not WIDTHxHEIGHT.");
+		};
+
+		Ok(Self::new(width.parse()?, height.parse()?, None))
+	}
+}
+
 impl Default for Dim {
 	#[inline]
 	fn default() -> Self {
@@ -260,6 +586,7 @@ fn default() -> Self {
 			width: 0,
 			height: 0,
 			method: Method::Scale,
+			animated: false,
 		}
 	}
 }
@@ -0,0 +1,95 @@
+use std::{path::PathBuf, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, err};
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	net::{TcpStream, UnixStream},
+	time::timeout,
+};
+
+use super::{Backend, ScanResult};
+
+/// Backend streaming content to a ClamAV daemon with the `INSTREAM` command.
+pub struct Clamd {
+	socket: Socket,
+	timeout: Duration,
+}
+
+enum Socket {
+	Unix(PathBuf),
+	Tcp(String),
+}
+
+/// Size of the chunks content is streamed to clamd in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+impl Clamd {
+	#[must_use]
+	pub fn unix(path: PathBuf, timeout: Duration) -> Self {
+		Self { socket: Socket::Unix(path), timeout }
+	}
+
+	#[must_use]
+	pub fn tcp(address: String, timeout: Duration) -> Self {
+		Self { socket: Socket::Tcp(address), timeout }
+	}
+
+	async fn scan_inner(&self, content: &[u8]) -> Result<String> {
+		match &self.socket {
+			| Socket::Unix(path) => instream(UnixStream::connect(path).await?, content).await,
+			| Socket::Tcp(address) =>
+				instream(TcpStream::connect(address.as_str()).await?, content).await,
+		}
+	}
+}
+
+#[async_trait]
+impl Backend for Clamd {
+	async fn scan(&self, content: &[u8]) -> Result<ScanResult> {
+		let reply = timeout(self.timeout, self.scan_inner(content))
+			.await
+			.map_err(|_| err!("Timed out scanning with clamd"))??;
+
+		parse_reply(&reply)
+	}
+}
+
+async fn instream<S>(mut stream: S, content: &[u8]) -> Result<String>
+where
+	S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+	stream.write_all(b"zINSTREAM\0").await?;
+	for chunk in content.chunks(CHUNK_SIZE) {
+		let len = u32::try_from(chunk.len())?;
+		stream.write_all(&len.to_be_bytes()).await?;
+		stream.write_all(chunk).await?;
+	}
+
+	stream.write_all(&0_u32.to_be_bytes()).await?;
+	stream.flush().await?;
+
+	let mut reply = Vec::new();
+	stream.read_to_end(&mut reply).await?;
+
+	Ok(String::from_utf8_lossy(&reply)
+		.trim_end_matches(['\0', '\n'])
+		.to_owned())
+}
+
+/// Parse a reply like `stream: OK` or `stream: Eicar-Signature FOUND`.
+fn parse_reply(reply: &str) -> Result<ScanResult> {
+	let reply = reply.strip_prefix("stream: ").unwrap_or(reply);
+	if reply == "OK" {
+		return Ok(ScanResult { clean: true, info: None });
+	}
+
+	if let Some(signature) = reply.strip_suffix(" FOUND") {
+		return Ok(ScanResult {
+			clean: false,
+			info: Some(signature.to_owned()),
+		});
+	}
+
+	Err!("Unexpected reply from clamd: {reply}")
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result};
+use reqwest::header::CONTENT_TYPE;
+use url::Url;
+
+use super::{Backend, ScanResult};
+
+/// Backend POSTing content to an external HTTP scanner, which responds with
+/// a JSON object whose `clean` is whether the content is safe, with an
+/// optional `info` describing what was found.
+pub struct Http {
+	client: reqwest::Client,
+	url: Url,
+	token: Option<String>,
+	timeout: Duration,
+}
+
+impl Http {
+	#[must_use]
+	pub fn new(
+		client: reqwest::Client,
+		url: Url,
+		token: Option<String>,
+		timeout: Duration,
+	) -> Self {
+		Self { client, url, token, timeout }
+	}
+}
+
+#[async_trait]
+impl Backend for Http {
+	async fn scan(&self, content: &[u8]) -> Result<ScanResult> {
+		let mut request = self
+			.client
+			.post(self.url.clone())
+			.timeout(self.timeout)
+			.header(CONTENT_TYPE, "application/octet-stream")
+			.body(content.to_vec());
+
+		if let Some(token) = &self.token {
+			request = request.bearer_auth(token);
+		}
+
+		let response = request.send().await?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err!("Media scanner responded with {status}");
+		}
+
+		let body = response.bytes().await?;
+
+		Ok(serde_json::from_slice(&body)?)
+	}
+}
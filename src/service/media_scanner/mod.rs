@@ -0,0 +1,283 @@
+//! Media scanning
+//!
+//! Uploaded media is passed to the configured scanner backends before it is
+//! stored. Infected uploads are refused, and with the quarantine action a
+//! copy is kept for admins to review. Scan results are cached by the SHA-256
+//! hash of the content, so the same file is not scanned twice.
+
+mod clamd;
+mod http;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server,
+	config::MediaScanAction,
+	debug, debug_info,
+	utils::{ReadyExt, hash::sha256, stream::TryIgnore},
+	warn,
+};
+use database::{Deserialized, Json, Map};
+use futures::StreamExt;
+use ruma::{MilliSecondsSinceUnixEpoch, UInt, UserId};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Notify, time::sleep};
+
+pub use self::{clamd::Clamd, http::Http};
+use crate::{Dep, client, media, media::encode_key};
+
+pub struct Service {
+	backends: Vec<Arc<dyn Backend>>,
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	media: Dep<media::Service>,
+}
+
+struct Data {
+	sha256_mediascan: Arc<Map>,
+}
+
+/// Media scanner backend.
+#[async_trait]
+pub trait Backend: Send + Sync {
+	/// Scan `content` for malware.
+	async fn scan(&self, content: &[u8]) -> Result<ScanResult>;
+}
+
+/// Result of scanning media.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScanResult {
+	/// Whether no malware was found
+	pub clean: bool,
+
+	/// What was found, if anything
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub info: Option<String>,
+}
+
+/// Scan result remembered for the hash of the content.
+#[derive(Debug, Deserialize, Serialize)]
+struct Cached {
+	result: ScanResult,
+	scanned_ts: MilliSecondsSinceUnixEpoch,
+}
+
+/// Interval at which expired scan results are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.media_scanner;
+		let timeout = Duration::from_secs(config.timeout);
+		let mut backends: Vec<Arc<dyn Backend>> = Vec::new();
+		if let Some(path) = &config.clamd_socket {
+			backends.push(Arc::new(Clamd::unix(path.clone(), timeout)));
+		} else if let Some(address) = &config.clamd_address {
+			backends.push(Arc::new(Clamd::tcp(address.clone(), timeout)));
+		}
+
+		if let Some(url) = &config.http_url {
+			let client = args.require::<client::Service>("client");
+			backends.push(Arc::new(Http::new(
+				client.default.clone(),
+				url.clone(),
+				config.http_token.clone(),
+				timeout,
+			)));
+		}
+
+		Ok(Arc::new(Self {
+			backends,
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				media: args.depend::<media::Service>("media"),
+			},
+			db: Data {
+				sha256_mediascan: args.db["sha256_mediascan"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			self.prune().await;
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = sleep(PRUNE_INTERVAL) => (),
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Whether any scanner is configured.
+	#[inline]
+	#[must_use]
+	pub fn is_enabled(&self) -> bool { !self.backends.is_empty() }
+
+	/// Scan media uploaded by `user`, returning an M_FORBIDDEN error if it is
+	/// infected, or if scanning fails and `media_scanner.fail_open` is
+	/// disabled.
+	pub async fn check_upload(&self, user: &UserId, content: &[u8]) -> Result {
+		if !self.is_enabled() {
+			return Ok(());
+		}
+
+		let config = &self.services.server.config.media_scanner;
+		let digest = sha256::hash(content);
+		let result = match self.cached(&digest).await {
+			| Some(result) => result,
+			| None => match self.scan(content).await {
+				| Ok(result) => {
+					self.cache(&digest, &result);
+					result
+				},
+				| Err(e) => {
+					warn!(%user, "Failed to scan uploaded media: {e}");
+					if config.fail_open {
+						return Ok(());
+					}
+
+					return Err!(Request(Forbidden(
+						"The upload could not be scanned for malware; try again later."
+					)));
+				},
+			},
+		};
+
+		if result.clean {
+			return Ok(());
+		}
+
+		let info = result.info.as_deref().unwrap_or("malware");
+		warn!(%user, "Refusing uploaded media as the scanner found {info}");
+
+		if config.action == MediaScanAction::Quarantine {
+			match self.quarantine(&digest, content).await {
+				| Ok(path) => warn!(%user, ?path, "Quarantined uploaded media"),
+				| Err(e) => warn!(%user, "Failed to quarantine uploaded media: {e}"),
+			}
+		}
+
+		Err!(Request(Forbidden("The upload was found to contain malware.")))
+	}
+
+	/// Pass `content` to every backend; the first result which is not clean
+	/// is final.
+	async fn scan(&self, content: &[u8]) -> Result<ScanResult> {
+		let mut result = ScanResult { clean: true, info: None };
+		for backend in &self.backends {
+			result = backend.scan(content).await?;
+			if !result.clean {
+				break;
+			}
+		}
+
+		debug!(?result, "Scanned media");
+
+		Ok(result)
+	}
+
+	async fn cached(&self, digest: &sha256::Digest) -> Option<ScanResult> {
+		let ttl = self.services.server.config.media_scanner.cache_ttl;
+		if ttl == 0 {
+			return None;
+		}
+
+		let cached: Cached = self
+			.db
+			.sha256_mediascan
+			.get(digest)
+			.await
+			.deserialized()
+			.ok()?;
+
+		(!Self::is_expired(&cached, ttl)).then_some(cached.result)
+	}
+
+	fn is_expired(cached: &Cached, ttl: u64) -> bool {
+		let age = MilliSecondsSinceUnixEpoch::now()
+			.get()
+			.saturating_sub(cached.scanned_ts.get());
+
+		age >= UInt::new_saturating(ttl.saturating_mul(1000))
+	}
+
+	/// Remove the scan results older than `media_scanner.cache_ttl`, or all of
+	/// them when caching is disabled.
+	async fn prune(&self) {
+		let ttl = self.services.server.config.media_scanner.cache_ttl;
+		let expired: Vec<Vec<u8>> = self
+			.db
+			.sha256_mediascan
+			.stream::<&[u8], Cached>()
+			.ignore_err()
+			.ready_filter(|(_, cached)| ttl == 0 || Self::is_expired(cached, ttl))
+			.map(|(digest, _)| digest.to_vec())
+			.collect()
+			.await;
+
+		if expired.is_empty() {
+			return;
+		}
+
+		for digest in &expired {
+			self.db.sha256_mediascan.remove(digest);
+		}
+
+		debug_info!(count = expired.len(), "Removed expired media scan results");
+	}
+
+	fn cache(&self, digest: &sha256::Digest, result: &ScanResult) {
+		if self.services.server.config.media_scanner.cache_ttl == 0 {
+			return;
+		}
+
+		let cached = Cached {
+			result: result.clone(),
+			scanned_ts: MilliSecondsSinceUnixEpoch::now(),
+		};
+
+		self.db.sha256_mediascan.raw_put(digest, Json(cached));
+	}
+
+	/// Keep a copy of infected content in the quarantine directory, named
+	/// after its hash.
+	async fn quarantine(&self, digest: &sha256::Digest, content: &[u8]) -> Result<PathBuf> {
+		let dir = self.quarantine_dir();
+		fs::create_dir_all(&dir).await?;
+
+		let path = dir.join(encode_key(digest));
+		fs::write(&path, content).await?;
+
+		Ok(path)
+	}
+
+	/// Directory receiving quarantined uploads: `media_scanner.quarantine_path`
+	/// or the `quarantine` directory in the media directory.
+	#[must_use]
+	pub fn quarantine_dir(&self) -> PathBuf {
+		self.services
+			.server
+			.config
+			.media_scanner
+			.quarantine_path
+			.clone()
+			.unwrap_or_else(|| self.services.media.get_media_dir().join("quarantine"))
+	}
+}
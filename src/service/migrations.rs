@@ -62,6 +62,8 @@ async fn fresh(services: &Services) -> Result<()> {
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", []);
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
+	db["global"].insert(b"index_thread_activity", []);
+	db["global"].insert(b"mark_devices_seen", []);
 
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room(services).boxed().await?;
@@ -95,7 +97,7 @@ async fn migrate(services: &Services) -> Result<()> {
 
 	if db["global"].get(b"feat_sha256_media").await.is_not_found() {
 		media::migrations::migrate_sha256_media(services).await?;
-	} else if config.media_startup_check {
+	} else if config.media_startup_check && services.media.is_local() {
 		media::migrations::checkup_sha256_media(services).await?;
 	}
 
@@ -133,6 +135,25 @@ async fn migrate(services: &Services) -> Result<()> {
 		fix_readreceiptid_readreceipt_duplicates(services).await?;
 	}
 
+	if db["global"]
+		.get(b"index_thread_activity")
+		.await
+		.is_not_found()
+	{
+		services.rooms.threads.index_existing_threads().await?;
+		db["global"].insert(b"index_thread_activity", []);
+	}
+
+	if db["global"].get(b"mark_devices_seen").await.is_not_found() {
+		services.users.mark_all_devices_seen().await;
+		db["global"].insert(b"mark_devices_seen", []);
+		info!("Migration: Marked all devices as seen for stale device tracking");
+	}
+
+	if db["global"].get(b"mark_legacy_guests").await.is_not_found() {
+		mark_legacy_guests(services).await?;
+	}
+
 	if services.globals.db.database_version().await < 17 {
 		services.globals.db.bump_database_version(17);
 		info!("Migration: Bumped database version to 17");
@@ -147,29 +168,10 @@ async fn migrate(services: &Services) -> Result<()> {
 		DATABASE_VERSION,
 	);
 
-	{
-		let patterns = services.globals.forbidden_usernames();
-		if !patterns.is_empty() {
-			services
-				.users
-				.stream()
-				.filter(|user_id| services.users.is_active_local(user_id))
-				.ready_for_each(|user_id| {
-					let matches = patterns.matches(user_id.localpart());
-					if matches.matched_any() {
-						warn!(
-							"User {} matches the following forbidden username patterns: {}",
-							user_id.to_string(),
-							matches
-								.into_iter()
-								.map(|x| &patterns.patterns()[x])
-								.join(", ")
-						);
-					}
-				})
-				.await;
-		}
-	}
+	services
+		.users
+		.enforce_forbidden_usernames(services.server.config.forbidden_usernames_enforcement)
+		.await;
 
 	{
 		let patterns = services.globals.forbidden_alias_names();
@@ -557,3 +559,46 @@ async fn fix_readreceiptid_readreceipt_duplicates(services: &Services) -> Result
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
 	db.db.sort()
 }
+
+/// Guest accounts were previously only recognisable by having no password.
+/// Mark those which still have devices as guests so the guest restrictions
+/// apply to them; accounts without a password but with devices are otherwise
+/// only created for appservices and OpenID Connect, which are skipped.
+async fn mark_legacy_guests(services: &Services) -> Result<()> {
+	let db = &services.db;
+
+	if services.oidc.enabled() {
+		warn!(
+			"Skipping migration of guest accounts as users of the OpenID Connect provider \
+			 cannot be told apart from them."
+		);
+	} else {
+		let passwordless: Vec<OwnedUserId> = db["userid_password"]
+			.stream()
+			.ignore_err()
+			.ready_filter_map(|(user_id, password): (&UserId, &[u8])| {
+				password.is_empty().then(|| user_id.to_owned())
+			})
+			.collect()
+			.await;
+
+		let mut marked: usize = 0;
+		for user_id in &passwordless {
+			if user_id == &services.globals.server_user
+				|| !services.globals.user_is_local(user_id)
+				|| services.appservice.is_user_id(user_id).await
+				|| services.users.all_device_ids(user_id).count().await == 0
+			{
+				continue;
+			}
+
+			services.users.mark_as_guest(user_id);
+			marked = marked.saturating_add(1);
+		}
+
+		info!(?marked, "Migration: Marked existing guest accounts as guests");
+	}
+
+	db["global"].insert(b"mark_legacy_guests", []);
+	db.db.sort()
+}
@@ -8,20 +8,42 @@
 
 pub mod account_data;
 pub mod admin;
+pub mod alerts;
 pub mod appservice;
+pub mod audit;
+pub mod auto_join;
+pub mod caches;
 pub mod client;
 pub mod config;
+pub mod delayed_events;
+pub mod email;
 pub mod emergency;
 pub mod federation;
 pub mod globals;
+pub mod jwt;
 pub mod key_backups;
+pub mod ldap;
+pub mod maintenance;
 pub mod media;
+pub mod media_scanner;
+pub mod oidc;
+pub mod policy_lists;
 pub mod presence;
+pub mod pruning;
 pub mod pusher;
+pub mod ratelimit;
+pub mod registration_limits;
+pub mod registration_tokens;
+pub mod rendezvous;
+pub mod reports;
 pub mod resolver;
 pub mod rooms;
 pub mod sending;
 pub mod server_keys;
+pub mod server_notices;
+pub mod spam_checker;
+pub mod strangers;
+pub mod sso;
 pub mod sync;
 pub mod transaction_ids;
 pub mod uiaa;
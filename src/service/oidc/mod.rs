@@ -0,0 +1,289 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Error, Result, Server, debug, debug_warn, err, implement, info, utils};
+use ruma::{DeviceId, OwnedDeviceId, OwnedUserId, UserId, api::client::error::ErrorKind};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use url::Url;
+
+use crate::{Dep, client, globals, users};
+
+pub struct Service {
+	cache: RwLock<TokenCache>,
+	metadata: RwLock<Option<(JsonValue, Instant)>>,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+}
+
+type TokenCache = HashMap<String, (OwnedUserId, OwnedDeviceId, Instant)>;
+
+/// RFC 7662 token introspection response; only the fields we use.
+#[derive(Debug, Deserialize)]
+struct Introspection {
+	active: bool,
+	#[serde(default)]
+	scope: Option<String>,
+	#[serde(default)]
+	username: Option<String>,
+	#[serde(default)]
+	exp: Option<u64>,
+}
+
+/// Scope granting access to the client-server API (MSC2967)
+const SCOPE_API: &str = "urn:matrix:org.matrix.msc2967.client:api:*";
+
+/// Scope prefix binding the token to a device (MSC2967)
+const SCOPE_DEVICE: &str = "urn:matrix:org.matrix.msc2967.client:device:";
+
+/// Length of the unusable access token set on provisioned devices
+const DEVICE_TOKEN_LENGTH: usize = 32;
+
+/// Duration for which the provider's metadata is cached
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			cache: RwLock::new(TokenCache::new()),
+			metadata: RwLock::new(None),
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let cache = self.cache.read().expect("locked for reading").len();
+		writeln!(out, "oidc_token_cache: {cache}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) {
+		self.cache.write().expect("locked for writing").clear();
+		self.metadata.write().expect("locked for writing").take();
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether authentication is delegated to an OpenID Connect provider.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn enabled(&self) -> bool { self.services.server.config.oidc.enable }
+
+/// The OAuth 2.0 authorization server metadata (RFC 8414) of the OpenID
+/// Connect provider, served to clients for discovery (MSC2965).
+#[implement(Service)]
+pub async fn auth_metadata(&self) -> Result<JsonValue> {
+	if let Some((metadata, fetched)) = self
+		.metadata
+		.read()
+		.expect("locked for reading")
+		.as_ref()
+		.filter(|(_, fetched)| fetched.elapsed() < METADATA_CACHE_TTL)
+	{
+		debug!(?fetched, "Using cached OpenID Connect provider metadata");
+		return Ok(metadata.clone());
+	}
+
+	let issuer = self
+		.services
+		.server
+		.config
+		.oidc
+		.issuer
+		.as_ref()
+		.ok_or_else(|| err!(Config("oidc.issuer", "OpenID Connect issuer is not set.")))?;
+
+	let endpoint = issuer
+		.join(".well-known/openid-configuration")
+		.map_err(|e| err!(Config("oidc.issuer", "Invalid OpenID Connect issuer URL: {e}")))?;
+
+	let response = self.services.client.default.get(endpoint).send().await?;
+
+	if !response.status().is_success() {
+		debug_warn!(status = ?response.status(), "Fetching provider metadata failed");
+		return Err!(BadServerResponse(
+			"Fetching OpenID Connect provider metadata failed: {}",
+			response.status()
+		));
+	}
+
+	let metadata: JsonValue = serde_json::from_str(&response.text().await?)?;
+
+	// RFC 8414 section 3.3
+	let same_issuer = metadata
+		.get("issuer")
+		.and_then(JsonValue::as_str)
+		.and_then(|advertised| Url::parse(advertised).ok())
+		.is_some_and(|advertised| advertised == *issuer);
+
+	if !same_issuer {
+		return Err!(BadServerResponse(
+			"OpenID Connect provider metadata is for a different issuer."
+		));
+	}
+
+	self.metadata
+		.write()
+		.expect("locked for writing")
+		.replace((metadata.clone(), Instant::now()));
+
+	Ok(metadata)
+}
+
+/// Find out which user and device an access token issued by the OpenID
+/// Connect provider belongs to. Users and devices unknown to this server are
+/// provisioned on first use. Errors with a server error when the provider
+/// could not be asked, rather than treating the token as invalid.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn find_from_token(&self, token: &str) -> Result<(OwnedUserId, OwnedDeviceId)> {
+	if let Some((user_id, device_id, expires)) = self
+		.cache
+		.read()
+		.expect("locked for reading")
+		.get(token)
+		.cloned()
+	{
+		if expires > Instant::now() {
+			return Ok((user_id, device_id));
+		}
+	}
+
+	let introspection = self.introspect(token).await?;
+	if !introspection.active {
+		return Err(Error::BadRequest(
+			ErrorKind::UnknownToken { soft_logout: false },
+			"Token is not active.",
+		));
+	}
+
+	let scopes = introspection.scope.as_deref().unwrap_or_default();
+	if !scopes.split(' ').any(|scope| scope == SCOPE_API) {
+		return Err!(Request(Forbidden("Token does not grant access to the client API.")));
+	}
+
+	let device_id: OwnedDeviceId = scopes
+		.split(' ')
+		.find_map(|scope| scope.strip_prefix(SCOPE_DEVICE))
+		.ok_or_else(|| err!(Request(Forbidden("Token is not bound to a device."))))?
+		.into();
+
+	let localpart = introspection
+		.username
+		.as_deref()
+		.ok_or_else(|| err!(Request(Forbidden("Token introspection is missing a username."))))?;
+
+	let user_id = UserId::parse_with_server_name(localpart, self.services.globals.server_name())
+		.map_err(|e| err!(Request(InvalidUsername("Username is invalid: {e}"))))?;
+
+	self.provision(&user_id, &device_id).await?;
+
+	let ttl = Duration::from_secs(self.services.server.config.oidc.introspection_cache_ttl);
+	let expires = introspection
+		.exp
+		.map(|exp| {
+			exp.saturating_mul(1000)
+				.saturating_sub(utils::millis_since_unix_epoch())
+		})
+		.map(Duration::from_millis)
+		.map_or(ttl, |remaining| remaining.min(ttl));
+
+	let expires = Instant::now()
+		.checked_add(expires)
+		.unwrap_or_else(Instant::now);
+
+	let mut cache = self.cache.write().expect("locked for writing");
+	let now = Instant::now();
+	cache.retain(|_, (.., expires)| *expires > now);
+	cache.insert(token.to_owned(), (user_id.clone(), device_id.clone(), expires));
+
+	Ok((user_id, device_id))
+}
+
+#[implement(Service)]
+async fn introspect(&self, token: &str) -> Result<Introspection> {
+	let config = &self.services.server.config.oidc;
+	let endpoint = self.introspection_endpoint()?;
+	let client_id = config
+		.client_id
+		.as_deref()
+		.ok_or_else(|| err!(Config("oidc.client_id", "OpenID Connect client_id is not set.")))?;
+
+	let response = self
+		.services
+		.client
+		.default
+		.post(endpoint)
+		.basic_auth(client_id, config.client_secret.as_deref())
+		.form(&[("token", token)])
+		.send()
+		.await
+		.map_err(|e| err!(BadServerResponse("Token introspection request failed: {e}")))?;
+
+	if !response.status().is_success() {
+		debug_warn!(status = ?response.status(), "Token introspection failed");
+		return Err!(BadServerResponse("Token introspection failed: {}", response.status()));
+	}
+
+	let response = response.text().await?;
+
+	Ok(serde_json::from_str(&response)?)
+}
+
+#[implement(Service)]
+fn introspection_endpoint(&self) -> Result<Url> {
+	let config = &self.services.server.config.oidc;
+	if let Some(endpoint) = &config.introspection_endpoint {
+		return Ok(endpoint.clone());
+	}
+
+	config
+		.issuer
+		.as_ref()
+		.ok_or_else(|| err!(Config("oidc.issuer", "OpenID Connect issuer is not set.")))?
+		.join("oauth2/introspect")
+		.map_err(|e| err!(Config("oidc.issuer", "Invalid OpenID Connect issuer URL: {e}")))
+}
+
+/// Create the local user and device for a subject of the OpenID Connect
+/// provider, if they don't exist yet.
+#[implement(Service)]
+async fn provision(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
+	let users = &self.services.users;
+	if !users.exists(user_id).await {
+		users.check_forbidden_username(user_id)?;
+		info!(%user_id, "Provisioning user delegated by OpenID Connect provider");
+		users.create(user_id, None)?;
+		users.set_displayname(user_id, Some(user_id.localpart().to_owned()));
+	}
+
+	if users.get_device_metadata(user_id, device_id).await.is_err() {
+		debug!(%user_id, %device_id, "Provisioning device delegated by OpenID Connect provider");
+		let token = utils::random_string(DEVICE_TOKEN_LENGTH);
+		users
+			.create_device(user_id, device_id, &token, None, None)
+			.await?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,696 @@
+//! Policy list subscriptions
+//!
+//! Policy lists (ban lists) are rooms holding `m.policy.rule.user`,
+//! `m.policy.rule.server` and `m.policy.rule.room` state events. Rules with
+//! the `m.ban` recommendation from subscribed lists are periodically matched
+//! against the rooms on this server; the resulting actions are either applied
+//! immediately or queued for review by an admin.
+
+#[cfg(test)]
+mod tests;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug, implement, info,
+	matrix::{PduEvent, pdu::PduBuilder},
+	utils::{ReadyExt, stream::TryIgnore},
+	warn,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use itertools::Itertools;
+use regex::Regex;
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId,
+	events::{
+		StateEventType, TimelineEventType,
+		policy::rule::{PolicyRuleEventContent, Recommendation},
+		room::{
+			member::{MembershipState, RoomMemberEventContent},
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+			server_acl::RoomServerAclEventContent,
+		},
+	},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, admin, globals, rooms, users::glob_regex};
+
+pub struct Service {
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	alias: Dep<rooms::alias::Service>,
+	directory: Dep<rooms::directory::Service>,
+	globals: Dep<globals::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	policylistroomids: Arc<Map>,
+	policyactionid_action: Arc<Map>,
+	policyaction_dismissed: Arc<Map>,
+}
+
+/// Action taken in response to a policy rule.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+	/// Ban a user from a room
+	BanUser {
+		room_id: OwnedRoomId,
+		user_id: OwnedUserId,
+	},
+
+	/// Add a server glob to the deny list of a room's server ACL
+	DenyServer {
+		room_id: OwnedRoomId,
+		server: String,
+	},
+
+	/// Ban a room on this server and disable federation with it
+	BanRoom {
+		room_id: OwnedRoomId,
+	},
+}
+
+/// Action found by matching a policy rule, awaiting review by an admin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingAction {
+	/// The action to take
+	pub action: Action,
+
+	/// The policy list the matching rule is from
+	pub policy_list: OwnedRoomId,
+
+	/// The entity of the matching rule
+	pub entity: String,
+
+	/// The reason given by the matching rule
+	pub reason: String,
+
+	/// When the action was found
+	pub detected_ts: MilliSecondsSinceUnixEpoch,
+}
+
+/// Rule with the `m.ban` recommendation from a policy list.
+struct Rule {
+	kind: RuleKind,
+	entity: String,
+	pattern: Regex,
+	reason: String,
+	policy_list: OwnedRoomId,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum RuleKind {
+	User,
+	Server,
+	Room,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
+				globals: args.depend::<globals::Service>("globals"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: Data {
+				policylistroomids: args.db["policylistroomids"].clone(),
+				policyactionid_action: args.db["policyactionid_action"].clone(),
+				policyaction_dismissed: args.db["policyaction_dismissed"].clone(),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "policy_lists", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let period = Duration::from_secs(self.services.server.config.policy_list_interval);
+		if period.is_zero() {
+			debug!("Disabling periodic policy list checks");
+			return Ok(());
+		}
+
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.reset_after(period);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			if let Err(e) = self.check().await {
+				warn!(%e, "Failed to check policy lists");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Subscribe to a policy list. The server must be joined to the room.
+#[implement(Service)]
+pub async fn subscribe(&self, room_id: &RoomId) -> Result {
+	if !self
+		.services
+		.state_cache
+		.server_in_room(self.services.globals.server_name(), room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("This server is not joined to {room_id}.")));
+	}
+
+	self.db.policylistroomids.insert(room_id, []);
+
+	Ok(())
+}
+
+/// Unsubscribe from a policy list. Lists from the config can't be
+/// unsubscribed from.
+#[implement(Service)]
+pub async fn unsubscribe(&self, room_id: &RoomId) -> Result {
+	if self.db.policylistroomids.get(room_id).await.is_err() {
+		return Err!(Request(NotFound("Not subscribed to {room_id} through an admin command.")));
+	}
+
+	self.db.policylistroomids.remove(room_id);
+
+	Ok(())
+}
+
+/// Returns the policy lists subscribed to in the config and through admin
+/// commands.
+#[implement(Service)]
+pub async fn subscriptions(&self) -> Vec<OwnedRoomId> {
+	let mut room_ids = Vec::new();
+	for room in &self.services.server.config.policy_lists {
+		match self.services.alias.resolve(room).await {
+			| Ok(room_id) => room_ids.push(room_id),
+			| Err(e) => warn!(%room, "Failed to resolve policy list: {e}"),
+		}
+	}
+
+	room_ids.extend(
+		self.db
+			.policylistroomids
+			.keys()
+			.ignore_err()
+			.map(|room_id: &RoomId| room_id.to_owned())
+			.collect::<Vec<_>>()
+			.await,
+	);
+
+	room_ids.into_iter().unique().collect()
+}
+
+/// Match the rules of all subscribed policy lists against the rooms on this
+/// server. Actions are applied immediately if `policy_list_auto_apply` is
+/// enabled, and queued for review otherwise. Actions which are already
+/// pending or were dismissed are skipped. Returns the number of new actions.
+#[implement(Service)]
+pub async fn check(&self) -> Result<usize> {
+	let policy_lists = self.subscriptions().await;
+	let rules = self.rules(&policy_lists).await;
+	if rules.is_empty() {
+		return Ok(0);
+	}
+
+	let admin_room = self.services.admin.get_admin_room().await.ok();
+	let is_excluded = |room_id: &RoomId| {
+		policy_lists
+			.iter()
+			.any(|policy_list| policy_list == room_id)
+			|| admin_room.as_deref() == Some(room_id)
+	};
+
+	let mut found = Vec::new();
+	for room_id in self.rooms_to_ban(&rules).await {
+		if let Some(rule) = room_rule(&rules, &room_id).filter(|_| !is_excluded(&room_id)) {
+			found.push((Action::BanRoom { room_id }, rule));
+		}
+	}
+
+	// users and servers can only be acted upon in rooms the server user is in
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(&self.services.globals.server_user)
+		.ready_filter(|room_id| !is_excluded(room_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for room_id in &rooms {
+		self.room_actions(room_id, &rules, &mut found).await;
+	}
+
+	let pending: Vec<Action> = self
+		.pending_actions()
+		.map(|(_, pending)| pending.action)
+		.collect()
+		.await;
+
+	let auto_apply = self.services.server.config.policy_list_auto_apply;
+	let mut count: usize = 0;
+	for (action, rule) in found {
+		if pending.contains(&action) || self.is_dismissed(&action).await {
+			continue;
+		}
+
+		let pending = PendingAction {
+			action,
+			policy_list: rule.policy_list.clone(),
+			entity: rule.entity.clone(),
+			reason: rule.reason.clone(),
+			detected_ts: MilliSecondsSinceUnixEpoch::now(),
+		};
+
+		if auto_apply {
+			if let Err(e) = self.apply(&pending).await {
+				warn!(action = ?pending.action, "Failed to apply policy list action: {e}");
+				continue;
+			}
+
+			info!(
+				action = ?pending.action, entity = %pending.entity,
+				"Applied policy list action"
+			);
+		} else {
+			let id = self.services.globals.next_count()?;
+			self.db.policyactionid_action.put(id, Json(&pending));
+		}
+
+		count = count.saturating_add(1);
+	}
+
+	if count > 0 {
+		let notice = if auto_apply {
+			format!("Applied {count} actions from policy lists.")
+		} else {
+			format!(
+				"{count} new actions from policy lists are pending review. Use `!admin \
+				 policy-lists pending` to list them."
+			)
+		};
+
+		self.services.admin.send_text(&notice).await;
+	}
+
+	Ok(count)
+}
+
+/// Get a pending action by its ID.
+#[implement(Service)]
+pub async fn get_pending_action(&self, id: u64) -> Result<PendingAction> {
+	self.db.policyactionid_action.qry(&id).await.deserialized()
+}
+
+/// Stream the actions pending review with their IDs, oldest first.
+#[implement(Service)]
+pub fn pending_actions(&self) -> impl Stream<Item = (u64, PendingAction)> + Send + '_ {
+	self.db
+		.policyactionid_action
+		.stream::<u64, PendingAction>()
+		.ignore_err()
+}
+
+/// Apply a pending action and remove it from the queue.
+#[implement(Service)]
+pub async fn apply_pending_action(&self, id: u64) -> Result<PendingAction> {
+	let pending = self.get_pending_action(id).await?;
+	self.apply(&pending).await?;
+	self.db.policyactionid_action.del(id);
+
+	Ok(pending)
+}
+
+/// Remove a pending action from the queue without applying it. The action is
+/// not queued again by later checks.
+#[implement(Service)]
+pub async fn dismiss_pending_action(&self, id: u64) -> Result<PendingAction> {
+	let pending = self.get_pending_action(id).await?;
+	self.db
+		.policyaction_dismissed
+		.insert(&serde_json::to_vec(&pending.action)?, []);
+
+	self.db.policyactionid_action.del(id);
+
+	Ok(pending)
+}
+
+#[implement(Service)]
+async fn is_dismissed(&self, action: &Action) -> bool {
+	let Ok(key) = serde_json::to_vec(action) else {
+		return false;
+	};
+
+	self.db.policyaction_dismissed.get(&key).await.is_ok()
+}
+
+/// Collect the rules with the `m.ban` recommendation from the current state
+/// of the policy lists.
+#[implement(Service)]
+async fn rules(&self, policy_lists: &[OwnedRoomId]) -> Vec<Rule> {
+	let mut rules = Vec::new();
+	for policy_list in policy_lists {
+		rules.extend(
+			self.services
+				.state_accessor
+				.room_state_full_pdus(policy_list)
+				.ignore_err()
+				.ready_filter_map(|pdu| Rule::from_pdu(policy_list, &pdu))
+				.collect::<Vec<_>>()
+				.await,
+		);
+	}
+
+	rules
+}
+
+/// Find the rooms known to this server which are not banned yet and match a
+/// room rule. Rules without wildcards are looked up directly, so all rooms
+/// are only scanned if a room rule contains a wildcard.
+#[implement(Service)]
+async fn rooms_to_ban(&self, rules: &[Rule]) -> Vec<OwnedRoomId> {
+	let room_rules = || rules.iter().filter(|rule| rule.kind == RuleKind::Room);
+
+	let candidates: Vec<OwnedRoomId> = if room_rules().any(Rule::has_wildcards) {
+		self.services
+			.metadata
+			.iter_ids()
+			.ready_filter(|room_id| room_rule(rules, room_id).is_some())
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
+	} else {
+		room_rules()
+			.filter_map(|rule| RoomId::parse(&rule.entity).ok())
+			.unique()
+			.collect()
+	};
+
+	let mut room_ids = Vec::new();
+	for room_id in candidates {
+		if self.services.metadata.exists(&room_id).await
+			&& !self.services.metadata.is_banned(&room_id).await
+		{
+			room_ids.push(room_id);
+		}
+	}
+
+	room_ids
+}
+
+/// Find the actions against users and servers in `room_id`, which the server
+/// user is joined to. Nothing is done unless it has the power to do so.
+#[implement(Service)]
+async fn room_actions<'a>(
+	&self,
+	room_id: &RoomId,
+	rules: &'a [Rule],
+	found: &mut Vec<(Action, &'a Rule)>,
+) {
+	let server_user = &self.services.globals.server_user;
+
+	let Ok(power_levels) = self
+		.services
+		.state_accessor
+		.room_state_get_content::<RoomPowerLevelsEventContent>(
+			room_id,
+			&StateEventType::RoomPowerLevels,
+			"",
+		)
+		.await
+		.map(RoomPowerLevels::from)
+	else {
+		return;
+	};
+
+	if power_levels.user_can_ban(server_user) {
+		let own_level = power_levels.for_user(server_user);
+		let members: Vec<OwnedUserId> = self
+			.services
+			.state_cache
+			.room_members(room_id)
+			.chain(self.services.state_cache.room_members_invited(room_id))
+			.ready_filter(|user_id| power_levels.for_user(user_id) < own_level)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for user_id in members {
+			let Some(rule) = rules.iter().find(|rule| {
+				rule.kind == RuleKind::User && rule.pattern.is_match(user_id.as_str())
+			}) else {
+				continue;
+			};
+
+			if self.services.globals.user_is_local(&user_id)
+				&& self.services.admin.user_is_admin(&user_id).await
+			{
+				warn!(
+					%user_id, %room_id, entity = %rule.entity,
+					"Policy list rule matches a server admin, ignoring"
+				);
+				continue;
+			}
+
+			found.push((Action::BanUser { room_id: room_id.to_owned(), user_id }, rule));
+		}
+	}
+
+	if power_levels.user_can_send_state(server_user, StateEventType::RoomServerAcl) {
+		let acl = self
+			.services
+			.state_accessor
+			.room_state_get_content::<RoomServerAclEventContent>(
+				room_id,
+				&StateEventType::RoomServerAcl,
+				"",
+			)
+			.await
+			.ok();
+
+		let servers: Vec<OwnedServerName> = self
+			.services
+			.state_cache
+			.room_servers(room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let our_server = self.services.globals.server_name();
+		for rule in rules.iter().filter(|rule| rule.kind == RuleKind::Server) {
+			if rule.pattern.is_match(our_server.as_str())
+				|| acl
+					.as_ref()
+					.is_some_and(|acl| acl.deny.contains(&rule.entity))
+				|| !servers
+					.iter()
+					.any(|server| rule.pattern.is_match(server.as_str()))
+			{
+				continue;
+			}
+
+			found.push((
+				Action::DenyServer {
+					room_id: room_id.to_owned(),
+					server: rule.entity.clone(),
+				},
+				rule,
+			));
+		}
+	}
+}
+
+/// Apply an action as the server user.
+#[implement(Service)]
+async fn apply(&self, pending: &PendingAction) -> Result {
+	let server_user = &self.services.globals.server_user;
+	let reason = format!("{} (policy list {})", pending.reason, pending.policy_list);
+
+	match &pending.action {
+		| Action::BanUser { room_id, user_id } => {
+			let state_lock = self.services.state.mutex.lock(room_id).await;
+			let current_member_content = self
+				.services
+				.state_accessor
+				.get_member(room_id, user_id)
+				.await
+				.unwrap_or_else(|_| RoomMemberEventContent::new(MembershipState::Ban));
+
+			self.services
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
+						membership: MembershipState::Ban,
+						reason: Some(reason),
+						displayname: None,
+						avatar_url: None,
+						is_direct: None,
+						join_authorized_via_users_server: None,
+						third_party_invite: None,
+						..current_member_content
+					}),
+					server_user,
+					room_id,
+					&state_lock,
+				)
+				.await?;
+		},
+		| Action::DenyServer { room_id, server } => {
+			let state_lock = self.services.state.mutex.lock(room_id).await;
+			let mut acl = self
+				.services
+				.state_accessor
+				.room_state_get_content::<RoomServerAclEventContent>(
+					room_id,
+					&StateEventType::RoomServerAcl,
+					"",
+				)
+				.await
+				.unwrap_or_else(|_| {
+					RoomServerAclEventContent::new(true, vec!["*".to_owned()], Vec::new())
+				});
+
+			if acl.deny.contains(server) {
+				return Ok(());
+			}
+
+			acl.deny.push(server.clone());
+			self.services
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(String::new(), &acl),
+					server_user,
+					room_id,
+					&state_lock,
+				)
+				.await?;
+		},
+		| Action::BanRoom { room_id } => {
+			self.services.metadata.ban_room(room_id, true);
+			self.evict_local_users(room_id).await;
+			self.services
+				.alias
+				.local_aliases_for_room(room_id)
+				.map(ToOwned::to_owned)
+				.for_each(|alias| async move {
+					self.services
+						.alias
+						.remove_alias(&alias, server_user)
+						.await
+						.ok();
+				})
+				.await;
+
+			self.services.directory.set_not_public(room_id);
+			self.services.metadata.disable_room(room_id, true);
+		},
+	}
+
+	Ok(())
+}
+
+/// Make the local users leave a room which was banned, like the `ban-room`
+/// admin command does. The room is rejected locally without federating.
+#[implement(Service)]
+async fn evict_local_users(&self, room_id: &RoomId) {
+	let users: Vec<OwnedUserId> = self
+		.services
+		.state_cache
+		.room_members(room_id)
+		.chain(self.services.state_cache.room_members_invited(room_id))
+		.ready_filter(|user_id| self.services.globals.user_is_local(user_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for user_id in &users {
+		debug!(%user_id, %room_id, "Making user leave room banned by a policy list");
+		if let Err(e) = self
+			.services
+			.state_cache
+			.update_membership(
+				room_id,
+				user_id,
+				RoomMemberEventContent::new(MembershipState::Leave),
+				user_id,
+				None,
+				None,
+				true,
+			)
+			.await
+		{
+			warn!(%user_id, %room_id, "Failed to make user leave banned room: {e}");
+		}
+
+		self.services.state_cache.forget(room_id, user_id);
+	}
+}
+
+impl Rule {
+	fn from_pdu(policy_list: &RoomId, pdu: &PduEvent) -> Option<Self> {
+		let kind = match pdu.kind {
+			| TimelineEventType::PolicyRuleUser => RuleKind::User,
+			| TimelineEventType::PolicyRuleServer => RuleKind::Server,
+			| TimelineEventType::PolicyRuleRoom => RuleKind::Room,
+			| _ => return None,
+		};
+
+		// rules are removed by redacting them or replacing them with empty content
+		let content: PolicyRuleEventContent = pdu.get_content().ok()?;
+		if content.recommendation != Recommendation::Ban {
+			return None;
+		}
+
+		Some(Self {
+			kind,
+			pattern: glob_regex(&content.entity)?,
+			entity: content.entity,
+			reason: content.reason,
+			policy_list: policy_list.to_owned(),
+		})
+	}
+
+	fn has_wildcards(&self) -> bool { self.entity.contains(['*', '?']) }
+}
+
+/// Find the room rule matching `room_id`.
+fn room_rule<'a>(rules: &'a [Rule], room_id: &RoomId) -> Option<&'a Rule> {
+	rules
+		.iter()
+		.find(|rule| rule.kind == RuleKind::Room && rule.pattern.is_match(room_id.as_str()))
+}
@@ -0,0 +1,36 @@
+use ruma::{owned_room_id, room_id};
+
+use super::{Rule, RuleKind, room_rule};
+use crate::users::glob_regex;
+
+fn rule(kind: RuleKind, entity: &str) -> Rule {
+	Rule {
+		kind,
+		entity: entity.to_owned(),
+		pattern: glob_regex(entity).expect("valid entity"),
+		reason: "spam".to_owned(),
+		policy_list: owned_room_id!("!policies:example.com"),
+	}
+}
+
+#[test]
+fn wildcard_detection() {
+	assert!(rule(RuleKind::Room, "!*:evil.org").has_wildcards());
+	assert!(rule(RuleKind::Room, "!room?:evil.org").has_wildcards());
+	assert!(!rule(RuleKind::Room, "!room:evil.org").has_wildcards());
+}
+
+#[test]
+fn room_rule_only_matches_room_rules() {
+	let rules = [
+		rule(RuleKind::Server, "evil.org"),
+		rule(RuleKind::User, "!room:evil.org"),
+		rule(RuleKind::Room, "!*:evil.org"),
+	];
+
+	let matched = room_rule(&rules, room_id!("!room:evil.org")).unwrap();
+	assert!(matched.kind == RuleKind::Room);
+	assert_eq!(matched.entity, "!*:evil.org");
+
+	assert!(room_rule(&rules, room_id!("!room:example.com")).is_none());
+}
@@ -5,8 +5,8 @@
 	utils::{ReadyExt, stream::TryIgnore},
 };
 use database::{Deserialized, Json, Map};
-use futures::Stream;
-use ruma::{UInt, UserId, events::presence::PresenceEvent, presence::PresenceState};
+use futures::{Stream, StreamExt};
+use ruma::{OwnedUserId, UInt, UserId, events::presence::PresenceEvent, presence::PresenceState};
 
 use super::Presence;
 use crate::{Dep, globals, users};
@@ -144,6 +144,44 @@ pub(super) async fn remove_presence(&self, user_id: &UserId) {
 		self.userid_presenceid.remove(user_id);
 	}
 
+	pub(super) async fn prune(&self, before: u64) -> usize {
+		let records: Vec<(u64, OwnedUserId, u64)> = self
+			.presenceid_presence
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(key, presence)| {
+				let (count, user_id) = presenceid_parse(key).ok()?;
+				let presence = Presence::from_json_bytes(presence).ok()?;
+				Some((count, user_id.to_owned(), presence.last_active_ts()))
+			})
+			.collect()
+			.await;
+
+		let mut pruned: usize = 0;
+		for (count, user_id, last_active_ts) in records {
+			let current = self
+				.userid_presenceid
+				.get(&*user_id)
+				.await
+				.deserialized::<u64>();
+
+			if current.is_ok_and(|current| current == count) {
+				if last_active_ts >= before {
+					continue;
+				}
+
+				self.userid_presenceid.remove(&*user_id);
+			}
+
+			self.presenceid_presence
+				.remove(&presenceid_key(count, &user_id));
+
+			pruned = pruned.saturating_add(1);
+		}
+
+		pruned
+	}
+
 	#[inline]
 	pub(super) fn presence_since(
 		&self,
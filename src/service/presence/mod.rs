@@ -167,6 +167,11 @@ pub async fn set_presence(
 		Ok(())
 	}
 
+	/// Removes the presence of users last active before the timestamp
+	/// `before`, and records replaced by a newer one. Returns the number of
+	/// records removed.
+	pub async fn prune(&self, before: u64) -> usize { self.db.prune(before).await }
+
 	/// Removes the presence record for the given user from the database.
 	///
 	/// TODO: Why is this not used?
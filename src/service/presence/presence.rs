@@ -39,6 +39,9 @@ pub(super) fn from_json_bytes(bytes: &[u8]) -> Result<Self> {
 			.map_err(|_| Error::bad_database("Invalid presence data in database"))
 	}
 
+	#[inline]
+	pub(super) fn last_active_ts(&self) -> u64 { self.last_active_ts }
+
 	/// Creates a PresenceEvent from available data.
 	pub(super) async fn to_presence_event(
 		&self,
@@ -0,0 +1,259 @@
+use std::{
+	collections::BTreeMap,
+	fmt,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug, implement, utils::millis_since_unix_epoch, warn};
+use database::Map;
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, globals, maintenance, presence, rooms, uiaa, users};
+
+/// Removes ephemeral data past its retention: presence, typing notifications,
+/// superseded read receipts, UIAA sessions and device list change markers.
+pub struct Service {
+	stats: Mutex<BTreeMap<Table, Stats>>,
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	maintenance: Dep<maintenance::Service>,
+	presence: Dep<presence::Service>,
+	read_receipt: Dep<rooms::read_receipt::Service>,
+	typing: Dep<rooms::typing::Service>,
+	uiaa: Dep<uiaa::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	global: Arc<Map>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Table {
+	Presence,
+	Typing,
+	ReadReceipts,
+	UiaaSessions,
+	DeviceListChanges,
+}
+
+/// Pruning of a table since startup
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+	/// Entries removed in total
+	pub pruned: usize,
+
+	/// Entries removed by the last run
+	pub last_pruned: usize,
+
+	pub last_run: Option<SystemTime>,
+	pub last_duration: Duration,
+}
+
+/// Key in `global` of the counts reached at past runs, which date the
+/// device list change markers keyed by count.
+const CHECKPOINTS: &[u8] = b"prune_count_checkpoints";
+const CHECKPOINT_LEN: usize = 2 * size_of::<u64>();
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			stats: Mutex::new(BTreeMap::new()),
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
+				presence: args.depend::<presence::Service>("presence"),
+				read_receipt: args.depend::<rooms::read_receipt::Service>("rooms::read_receipt"),
+				typing: args.depend::<rooms::typing::Service>("rooms::typing"),
+				uiaa: args.depend::<uiaa::Service>("uiaa"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data { global: args.db["global"].clone() },
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "pruning", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(PRUNE_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.services.maintenance.defer("prune ephemeral data") => (),
+			}
+
+			self.prune().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Table {
+	pub const ALL: [Self; 5] = [
+		Self::Presence,
+		Self::Typing,
+		Self::ReadReceipts,
+		Self::UiaaSessions,
+		Self::DeviceListChanges,
+	];
+}
+
+impl fmt::Display for Table {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::Presence => "presence",
+			| Self::Typing => "typing",
+			| Self::ReadReceipts => "read receipts",
+			| Self::UiaaSessions => "uiaa sessions",
+			| Self::DeviceListChanges => "device list changes",
+		})
+	}
+}
+
+/// Prune every table with pruning enabled.
+#[implement(Service)]
+pub async fn prune(&self) {
+	self.checkpoint();
+	for table in Table::ALL {
+		let Some(retention) = self.retention(table) else {
+			continue;
+		};
+
+		let started = Instant::now();
+		let before = millis_since_unix_epoch().saturating_sub(retention.saturating_mul(1000));
+		let result = match table {
+			| Table::Presence => Ok(self.services.presence.prune(before).await),
+			| Table::Typing => self.services.typing.prune(before).await,
+			| Table::ReadReceipts => Ok(self.services.read_receipt.prune_superseded().await),
+			| Table::UiaaSessions => Ok(self.services.uiaa.prune(before).await),
+			| Table::DeviceListChanges => Ok(self.prune_keys_changed(before).await),
+		};
+
+		let pruned = match result {
+			| Ok(pruned) => pruned,
+			| Err(e) => {
+				warn!(%table, "Failed to prune: {e}");
+				continue;
+			},
+		};
+
+		let duration = started.elapsed();
+		debug!(%table, pruned, ?duration, "Pruned ephemeral data");
+
+		let mut stats = self.stats.lock().expect("locked");
+		let stats = stats.entry(table).or_default();
+		stats.pruned = stats.pruned.saturating_add(pruned);
+		stats.last_pruned = pruned;
+		stats.last_run = Some(SystemTime::now());
+		stats.last_duration = duration;
+	}
+}
+
+/// Retention of the table in seconds, if pruning it is enabled. Superseded
+/// read receipts are pruned right away.
+#[implement(Service)]
+#[must_use]
+pub fn retention(&self, table: Table) -> Option<u64> {
+	let config = &self.services.server.config;
+	let retention = match table {
+		| Table::Presence => config.presence_retention,
+		| Table::Typing => config.typing_retention,
+		| Table::ReadReceipts => return config.prune_superseded_receipts.then_some(0),
+		| Table::UiaaSessions => config.uiaa_session_retention,
+		| Table::DeviceListChanges => config.device_list_change_retention,
+	};
+
+	Some(retention).filter(|&retention| retention > 0)
+}
+
+#[implement(Service)]
+#[must_use]
+pub fn stats(&self) -> BTreeMap<Table, Stats> { self.stats.lock().expect("locked").clone() }
+
+/// Device list change markers are keyed by count, so they are pruned up to
+/// the count reached at the latest run before the timestamp `before`.
+#[implement(Service)]
+async fn prune_keys_changed(&self, before: u64) -> usize {
+	let count = self
+		.checkpoints()
+		.into_iter()
+		.rev()
+		.find(|&(timestamp, _)| timestamp <= before);
+
+	match count {
+		| Some((_, count)) => self.services.users.prune_keys_changed(count).await,
+		| None => 0,
+	}
+}
+
+/// Record the current count, dropping records no longer needed to date the
+/// device list change markers.
+#[implement(Service)]
+fn checkpoint(&self) {
+	let Ok(count) = self.services.globals.current_count() else {
+		return;
+	};
+
+	let now = millis_since_unix_epoch();
+	let retention = self.services.server.config.device_list_change_retention;
+	let before = now.saturating_sub(retention.saturating_mul(1000));
+
+	let mut checkpoints = self.checkpoints();
+	let older = checkpoints.partition_point(|&(timestamp, _)| timestamp <= before);
+	checkpoints.drain(..older.saturating_sub(1));
+	checkpoints.push((now, count));
+
+	let value: Vec<u8> = checkpoints
+		.iter()
+		.flat_map(|(timestamp, count)| {
+			timestamp
+				.to_be_bytes()
+				.into_iter()
+				.chain(count.to_be_bytes())
+		})
+		.collect();
+
+	self.db.global.insert(CHECKPOINTS, value);
+}
+
+/// Timestamps and the counts reached at them, oldest first.
+#[implement(Service)]
+fn checkpoints(&self) -> Vec<(u64, u64)> {
+	let Ok(value) = self.db.global.get_blocking(CHECKPOINTS) else {
+		return Vec::new();
+	};
+
+	value
+		.chunks_exact(CHECKPOINT_LEN)
+		.filter_map(|checkpoint| {
+			let (timestamp, count) = checkpoint.split_first_chunk::<8>()?;
+			Some((u64::from_be_bytes(*timestamp), u64::from_be_bytes(count.try_into().ok()?)))
+		})
+		.collect()
+}
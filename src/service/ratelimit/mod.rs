@@ -0,0 +1,351 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt,
+	fmt::Write,
+	net::IpAddr,
+	str::FromStr,
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Error, Result, Server,
+	config::{JoinLimits, RateLimitBucket, RateLimitClass, RateLimitConfig},
+	debug,
+	http::StatusCode,
+	implement,
+};
+use ruma::{
+	OwnedUserId, UserId,
+	api::client::error::{ErrorKind, RetryAfter},
+};
+
+pub struct Service {
+	state: RwLock<State>,
+	server: Arc<Server>,
+}
+
+#[derive(Default)]
+struct State {
+	/// Theoretical arrival time of the next request of each bucket; a bucket
+	/// is full again once it has passed.
+	buckets: HashMap<(Class, Dimension, String), Instant>,
+
+	/// Times of the joins of each user within the join limit window, oldest
+	/// first, counted separately for local and remote joins.
+	joins: HashMap<(OwnedUserId, JoinKind), VecDeque<Instant>>,
+	last_pruned: Option<Instant>,
+}
+
+/// Whether a join is of a room the server is already in or over federation.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum JoinKind {
+	Local,
+	Remote,
+}
+
+/// Endpoint classes which are rate limited separately.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Class {
+	Login,
+	Registration,
+	Message,
+	Join,
+	Invite,
+	Media,
+	Federation,
+}
+
+/// What requests of a bucket are counted by.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Dimension {
+	/// The user, or the origin server for federation requests.
+	User,
+	Ip,
+}
+
+/// Snapshot of a bucket which is not full.
+#[derive(Debug)]
+pub struct BucketInfo {
+	pub class: Class,
+	pub dimension: Dimension,
+	pub subject: String,
+
+	/// Duration until the bucket is full again.
+	pub refills_in: Duration,
+}
+
+/// Interval in which buckets which are full again are dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			state: RwLock::new(State::default()),
+			server: args.server.clone(),
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let (buckets, joins) = {
+			let state = self.state.read().expect("locked for reading");
+			(state.buckets.len(), state.joins.len())
+		};
+
+		writeln!(out, "ratelimit_buckets: {buckets}")?;
+		writeln!(out, "ratelimit_joins: {joins}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.reset(None, None); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Count a request of `class` against the buckets of the user (or origin
+/// server) and client IP address making it. Fails with `M_LIMIT_EXCEEDED` if
+/// any of the buckets is exhausted, in which case none of them are charged.
+#[implement(Service)]
+pub fn check(&self, class: Class, user: Option<&str>, ip: Option<IpAddr>) -> Result {
+	let config = &self.server.config.rate_limit;
+	if !config.enable {
+		return Ok(());
+	}
+
+	let limits = class.limits(config);
+	let ip = ip.map(|ip| ip.to_string());
+	let candidates = [
+		(Dimension::User, limits.per_user, user),
+		(Dimension::Ip, limits.per_ip, ip.as_deref()),
+	];
+
+	let now = Instant::now();
+	let mut state = self.state.write().expect("locked for writing");
+	state.prune(now, self.join_window());
+
+	let mut charged = Vec::with_capacity(candidates.len());
+	for (dimension, bucket, subject) in candidates {
+		let (Some(bucket), Some(subject)) = (bucket, subject) else {
+			continue;
+		};
+
+		let key = (class, dimension, subject.to_owned());
+		let arrival = state.buckets.get(&key).map_or(now, |&tat| tat.max(now));
+		let wait = arrival.saturating_duration_since(now);
+		let interval = interval(&bucket);
+		let tolerance = interval.saturating_mul(bucket.burst_count.saturating_sub(1));
+
+		if wait > tolerance {
+			let retry_after = wait.saturating_sub(tolerance);
+			debug!(?class, ?dimension, %subject, ?retry_after, "Rate limit exceeded");
+			return Err(limit_exceeded(retry_after));
+		}
+
+		let next = arrival.checked_add(interval).unwrap_or(arrival);
+		charged.push((key, next));
+	}
+
+	state.buckets.extend(charged);
+
+	Ok(())
+}
+
+/// Count a join of `user_id` against the join limit of its kind. Fails with
+/// `M_LIMIT_EXCEEDED` once the user has joined as many rooms of that kind as
+/// allowed within the window, in which case the join is not counted.
+#[implement(Service)]
+pub fn check_join(&self, user_id: &UserId, kind: JoinKind) -> Result {
+	let config = &self.server.config.join_limit;
+	if !config.enable {
+		return Ok(());
+	}
+
+	let limits = config
+		.overrides
+		.get(user_id)
+		.copied()
+		.unwrap_or(JoinLimits {
+			local: config.local,
+			remote: config.remote,
+		});
+
+	let limit = match kind {
+		| JoinKind::Local => limits.local,
+		| JoinKind::Remote => limits.remote,
+	};
+
+	let now = Instant::now();
+	let window = self.join_window();
+	let mut state = self.state.write().expect("locked for writing");
+	state.prune(now, window);
+
+	let joins = state.joins.entry((user_id.to_owned(), kind)).or_default();
+
+	while joins
+		.front()
+		.is_some_and(|&joined| now.saturating_duration_since(joined) >= window)
+	{
+		joins.pop_front();
+	}
+
+	if joins.len() >= usize::try_from(limit).unwrap_or(usize::MAX) {
+		let retry_after = joins.front().map_or(window, |&oldest| {
+			window.saturating_sub(now.saturating_duration_since(oldest))
+		});
+
+		debug!(%user_id, ?kind, ?retry_after, "Join limit exceeded");
+		return Err(limit_exceeded(retry_after));
+	}
+
+	joins.push_back(now);
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn join_window(&self) -> Duration { Duration::from_secs(self.server.config.join_limit.window) }
+
+/// Buckets which are not full, optionally only those of `class`.
+#[implement(Service)]
+pub fn buckets(&self, class: Option<Class>) -> Vec<BucketInfo> {
+	let now = Instant::now();
+	let state = self.state.read().expect("locked for reading");
+	let mut buckets: Vec<_> = state
+		.buckets
+		.iter()
+		.filter(|((bucket_class, ..), _)| class.is_none_or(|class| class == *bucket_class))
+		.filter(|(_, tat)| **tat > now)
+		.map(|((class, dimension, subject), tat)| BucketInfo {
+			class: *class,
+			dimension: *dimension,
+			subject: subject.clone(),
+			refills_in: tat.saturating_duration_since(now),
+		})
+		.collect();
+
+	buckets.sort_by(|a, b| b.refills_in.cmp(&a.refills_in));
+	buckets
+}
+
+/// Reset buckets, optionally only those of `class` and of `subject` (a user
+/// ID, server name or IP address). Login buckets of a user from an address
+/// match either. The join limit counters of users are reset along with the
+/// buckets of the join class. Returns the number of buckets reset.
+#[implement(Service)]
+pub fn reset(&self, class: Option<Class>, subject: Option<&str>) -> usize {
+	let mut state = self.state.write().expect("locked for writing");
+	let before = state.buckets.len();
+	state
+		.buckets
+		.retain(|(bucket_class, _, bucket_subject), _| {
+			class.is_some_and(|class| class != *bucket_class)
+				|| subject
+					.is_some_and(|subject| !bucket_subject.split(' ').any(|part| part == subject))
+		});
+
+	if class.is_none_or(|class| class == Class::Join) {
+		state
+			.joins
+			.retain(|(user_id, _), _| subject.is_some_and(|subject| subject != user_id.as_str()));
+	}
+
+	before.saturating_sub(state.buckets.len())
+}
+
+impl State {
+	fn prune(&mut self, now: Instant, join_window: Duration) {
+		if self
+			.last_pruned
+			.is_some_and(|last| now.saturating_duration_since(last) < PRUNE_INTERVAL)
+		{
+			return;
+		}
+
+		self.buckets.retain(|_, tat| *tat > now);
+		self.joins.retain(|_, joins| {
+			joins
+				.back()
+				.is_some_and(|&joined| now.saturating_duration_since(joined) < join_window)
+		});
+		self.last_pruned = Some(now);
+	}
+}
+
+impl Class {
+	pub const ALL: [Self; 7] = [
+		Self::Login,
+		Self::Registration,
+		Self::Message,
+		Self::Join,
+		Self::Invite,
+		Self::Media,
+		Self::Federation,
+	];
+
+	#[must_use]
+	pub fn as_str(self) -> &'static str {
+		match self {
+			| Self::Login => "login",
+			| Self::Registration => "registration",
+			| Self::Message => "message",
+			| Self::Join => "join",
+			| Self::Invite => "invite",
+			| Self::Media => "media",
+			| Self::Federation => "federation",
+		}
+	}
+
+	fn limits(self, config: &RateLimitConfig) -> &RateLimitClass {
+		match self {
+			| Self::Login => &config.login,
+			| Self::Registration => &config.registration,
+			| Self::Message => &config.message,
+			| Self::Join => &config.join,
+			| Self::Invite => &config.invite,
+			| Self::Media => &config.media,
+			| Self::Federation => &config.federation,
+		}
+	}
+}
+
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+impl FromStr for Class {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match Self::ALL.into_iter().find(|class| class.as_str() == s) {
+			| Some(class) => Ok(class),
+			| None => Err!("Unknown rate limit class {s:?}"),
+		}
+	}
+}
+
+impl fmt::Display for Dimension {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::User => "user",
+			| Self::Ip => "ip",
+		})
+	}
+}
+
+/// Interval in which a single request of a bucket is refilled.
+fn interval(bucket: &RateLimitBucket) -> Duration {
+	Duration::try_from_secs_f64(bucket.per_second.recip()).unwrap_or(Duration::MAX)
+}
+
+fn limit_exceeded(retry_after: Duration) -> Error {
+	Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		"Too many requests; try again later.".into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	)
+}
@@ -0,0 +1,177 @@
+use std::{
+	collections::VecDeque,
+	net::{IpAddr, Ipv6Addr},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	Err, Error, Result, Server, err, http::StatusCode, implement, info, utils::time::pretty,
+};
+use ipaddress::IPAddress;
+use ruma::api::client::error::{ErrorKind, RetryAfter};
+
+use crate::{Dep, alerts};
+
+/// Restricts where registrations may come from and how many are allowed per
+/// day, overall and per IP address. IPv6 addresses are counted per /64, as
+/// hosts are usually given a whole /64 to pick addresses from.
+pub struct Service {
+	/// Time and client IP address of the registrations within the last day,
+	/// oldest first
+	recent: Mutex<VecDeque<(Instant, IpAddr)>>,
+	allowlist: Vec<IPAddress>,
+	denylist: Vec<IPAddress>,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	alerts: Dep<alerts::Service>,
+}
+
+/// Window the registration limits count registrations in
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		Ok(Arc::new(Self {
+			recent: Mutex::new(VecDeque::new()),
+			allowlist: config
+				.registration_ip_allowlist
+				.iter()
+				.map(IPAddress::parse)
+				.collect::<Result<_, String>>()
+				.map_err(|e| err!(Config("registration_ip_allowlist", e)))?,
+			denylist: config
+				.registration_ip_denylist
+				.iter()
+				.map(IPAddress::parse)
+				.collect::<Result<_, String>>()
+				.map_err(|e| err!(Config("registration_ip_denylist", e)))?,
+			services: Services {
+				server: args.server.clone(),
+				alerts: args.depend::<alerts::Service>("alerts"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether a registration from `ip` is allowed now. Fails with `M_FORBIDDEN`
+/// for addresses which are not allowed to register and `M_LIMIT_EXCEEDED`
+/// once a daily limit is reached.
+#[implement(Service)]
+pub async fn check(&self, ip: IpAddr) -> Result {
+	let ip = ip.to_canonical();
+	if !self.ip_allowed(ip) {
+		info!(%ip, "Rejecting registration from an address not allowed to register");
+		return Err!(Request(Forbidden("Registration is not allowed from your network.")));
+	}
+
+	let config = &self.services.server.config;
+	let counted = counted_address(ip);
+	let now = Instant::now();
+	let (ip_wait, total_wait) = {
+		let mut recent = self.recent.lock().expect("locked");
+		prune(&mut recent, now);
+
+		let from_ip: Vec<Instant> = recent
+			.iter()
+			.filter(|(_, recent_ip)| *recent_ip == counted)
+			.map(|(registered, _)| *registered)
+			.collect();
+
+		let wait = |registered: Instant| DAY.saturating_sub(now.duration_since(registered));
+		let ip_wait = over_limit(from_ip.len(), config.registration_ip_daily_limit)
+			.then(|| from_ip.first().copied().map(wait))
+			.flatten();
+
+		let total_wait = over_limit(recent.len(), config.registration_daily_limit)
+			.then(|| recent.front().map(|(registered, _)| wait(*registered)))
+			.flatten();
+
+		(ip_wait, total_wait)
+	};
+
+	if let Some(retry_after) = ip_wait {
+		info!(%ip, "Rejecting registration as the daily limit per IP address is reached");
+		return Err(limit_exceeded(retry_after));
+	}
+
+	if let Some(retry_after) = total_wait {
+		info!(%ip, "Rejecting registration as the daily limit of new accounts is reached");
+		self.services
+			.alerts
+			.post(
+				"registration_daily_limit".to_owned(),
+				&format!(
+					"The daily limit of {} new accounts (`registration_daily_limit`) was \
+					 reached; registration is refused for the next {}.",
+					config.registration_daily_limit,
+					pretty(retry_after),
+				),
+			)
+			.await;
+
+		return Err(limit_exceeded(retry_after));
+	}
+
+	Ok(())
+}
+
+/// Count a completed registration from `ip` against the daily limits.
+#[implement(Service)]
+pub fn record(&self, ip: IpAddr) {
+	let ip = counted_address(ip.to_canonical());
+	let now = Instant::now();
+	let mut recent = self.recent.lock().expect("locked");
+	prune(&mut recent, now);
+	recent.push_back((now, ip));
+}
+
+#[implement(Service)]
+fn ip_allowed(&self, ip: IpAddr) -> bool {
+	let Ok(ip) = IPAddress::parse(ip.to_string()) else {
+		return false;
+	};
+
+	let allowed =
+		self.allowlist.is_empty() || self.allowlist.iter().any(|range| range.includes(&ip));
+	let denied = self.denylist.iter().any(|range| range.includes(&ip));
+
+	allowed && !denied
+}
+
+/// The address registrations from `ip` count against: its /64 for IPv6.
+fn counted_address(ip: IpAddr) -> IpAddr {
+	match ip {
+		| IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+		| ip => ip,
+	}
+}
+
+fn prune(recent: &mut VecDeque<(Instant, IpAddr)>, now: Instant) {
+	while recent
+		.front()
+		.is_some_and(|(registered, _)| now.duration_since(*registered) >= DAY)
+	{
+		recent.pop_front();
+	}
+}
+
+fn over_limit(count: usize, limit: u32) -> bool {
+	limit > 0 && count >= usize::try_from(limit).unwrap_or(usize::MAX)
+}
+
+fn limit_exceeded(retry_after: Duration) -> Error {
+	Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		"Too many registrations; try again later.".into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	)
+}
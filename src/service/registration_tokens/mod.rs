@@ -0,0 +1,211 @@
+use std::{sync::Arc, time::Duration};
+
+use conduwuit::{
+	Err, Result, Server, checked, err, implement,
+	utils::{self, stream::TryIgnore},
+};
+use database::Map;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{Dep, globals};
+
+pub struct Service {
+	consume: Mutex<()>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	registrationtoken_info: Arc<Map>,
+}
+
+/// Registration token issued by an admin (MSC3231).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenInfo {
+	/// Number of registrations the token may be used for; unlimited if None
+	pub uses_allowed: Option<u64>,
+
+	/// Number of registrations completed with the token
+	pub completed: u64,
+
+	/// Time after which the token is no longer valid, in milliseconds since
+	/// the unix epoch; never expires if None
+	pub expiry_time: Option<u64>,
+}
+
+/// Length of randomly generated tokens
+pub const TOKEN_LENGTH: usize = 16;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			consume: Mutex::new(()),
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				registrationtoken_info: args.db["registrationtoken_info"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl TokenInfo {
+	/// Whether the token can still be used to register.
+	#[must_use]
+	pub fn is_valid(&self) -> bool {
+		let now = utils::millis_since_unix_epoch();
+		self.expiry_time.is_none_or(|expiry| expiry > now)
+			&& self
+				.uses_allowed
+				.is_none_or(|allowed| self.completed < allowed)
+	}
+}
+
+/// Whether registering requires a registration token.
+#[implement(Service)]
+#[must_use]
+pub fn tokens_required(&self) -> bool {
+	self.services.globals.registration_token.is_some()
+		|| self
+			.services
+			.server
+			.config
+			.registration_token_file
+			.is_some()
+		|| self.services.server.config.registration_requires_token
+}
+
+/// Issue a new registration token. A random token is generated if none is
+/// given.
+#[implement(Service)]
+pub async fn issue_token(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expires_in: Option<Duration>,
+) -> Result<(String, TokenInfo)> {
+	let token = token.unwrap_or_else(|| utils::random_string(TOKEN_LENGTH));
+	if token.is_empty() || token.len() > 64 || !token.chars().all(is_token_char) {
+		return Err!(Request(InvalidParam(
+			"Registration tokens may only contain up to 64 characters of [A-Za-z0-9._~-]."
+		)));
+	}
+
+	if self.get_token(&token).await.is_ok() {
+		return Err!(Request(InvalidParam("Registration token {token:?} already exists.")));
+	}
+
+	let expiry_time = expires_in
+		.map(|expires_in| {
+			let expires_in: u64 = expires_in.as_millis().try_into()?;
+			checked!(utils::millis_since_unix_epoch() + expires_in)
+		})
+		.transpose()?;
+
+	let info = TokenInfo { uses_allowed, completed: 0, expiry_time };
+	self.put_token(&token, &info)?;
+
+	Ok((token, info))
+}
+
+/// Delete a registration token.
+#[implement(Service)]
+pub async fn revoke_token(&self, token: &str) -> Result {
+	self.get_token(token).await?;
+	self.db.registrationtoken_info.remove(token);
+
+	Ok(())
+}
+
+/// Expire a registration token immediately while keeping its record.
+#[implement(Service)]
+pub async fn expire_token(&self, token: &str) -> Result<TokenInfo> {
+	let mut info = self.get_token(token).await?;
+	info.expiry_time = Some(utils::millis_since_unix_epoch());
+	self.put_token(token, &info)?;
+
+	Ok(info)
+}
+
+/// Check an issued registration token without using it up.
+#[implement(Service)]
+pub async fn is_token_valid(&self, token: &str) -> bool {
+	self.get_token(token)
+		.await
+		.is_ok_and(|info| info.is_valid())
+}
+
+/// Count a registration against an issued token. Errors if the token does
+/// not exist or can no longer be used.
+#[implement(Service)]
+pub async fn use_token(&self, token: &str) -> Result<TokenInfo> {
+	let _lock = self.consume.lock().await;
+	let mut info = self.get_token(token).await?;
+	if !info.is_valid() {
+		return Err!(Request(Forbidden("Registration token is expired or used up.")));
+	}
+
+	info.completed = info.completed.saturating_add(1);
+	self.put_token(token, &info)?;
+
+	Ok(info)
+}
+
+/// Give back a use of an issued token, for a registration which failed after
+/// using it.
+#[implement(Service)]
+pub async fn release_token(&self, token: &str) -> Result<TokenInfo> {
+	let _lock = self.consume.lock().await;
+	let mut info = self.get_token(token).await?;
+	info.completed = info.completed.saturating_sub(1);
+	self.put_token(token, &info)?;
+
+	Ok(info)
+}
+
+#[implement(Service)]
+pub async fn get_token(&self, token: &str) -> Result<TokenInfo> {
+	self.db
+		.registrationtoken_info
+		.get(token)
+		.await
+		.and_then(|value| {
+			serde_json::from_slice(&value)
+				.map_err(|e| err!(Database("Invalid registration token {token:?}: {e}")))
+		})
+}
+
+/// All issued registration tokens.
+#[implement(Service)]
+pub fn tokens(&self) -> impl Stream<Item = (String, TokenInfo)> + Send + '_ {
+	self.db
+		.registrationtoken_info
+		.raw_stream()
+		.ignore_err()
+		.filter_map(|(token, value)| async move {
+			let token = String::from_utf8(token.to_vec()).ok()?;
+			let info = serde_json::from_slice(value).ok()?;
+			Some((token, info))
+		})
+}
+
+#[implement(Service)]
+fn put_token(&self, token: &str, info: &TokenInfo) -> Result {
+	let value = serde_json::to_vec(info)?;
+	self.db.registrationtoken_info.insert(token, value);
+
+	Ok(())
+}
+
+fn is_token_char(c: char) -> bool { c.is_ascii_alphanumeric() || "._~-".contains(c) }
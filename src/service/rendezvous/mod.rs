@@ -0,0 +1,184 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	net::IpAddr,
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use conduwuit::{Err, Error, Result, Server, debug, http::StatusCode, implement, utils};
+use ruma::api::client::error::ErrorKind;
+
+pub struct Service {
+	sessions: RwLock<Sessions>,
+	server: Arc<Server>,
+}
+
+type Sessions = HashMap<String, (IpAddr, Session)>;
+
+/// Ephemeral payload exchanged between two devices during QR code login
+/// (MSC4108).
+#[derive(Clone, Debug)]
+pub struct Session {
+	pub content: Bytes,
+	pub content_type: String,
+	pub etag: String,
+	pub last_modified: SystemTime,
+	pub expires: SystemTime,
+}
+
+/// Maximum size of a rendezvous payload in bytes
+pub const MAX_CONTENT_LENGTH: usize = 4096;
+
+/// Maximum number of concurrent rendezvous sessions
+const MAX_SESSIONS: usize = 1024;
+
+/// Maximum number of concurrent rendezvous sessions created from a single IP
+/// address
+const MAX_SESSIONS_PER_IP: usize = 8;
+
+/// Length of the randomly generated session ID
+const SESSION_ID_LENGTH: usize = 32;
+
+/// Length of the randomly generated entity tag
+const ETAG_LENGTH: usize = 16;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			sessions: RwLock::new(Sessions::new()),
+			server: args.server.clone(),
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let sessions = self.sessions.read().expect("locked for reading").len();
+		writeln!(out, "rendezvous_sessions: {sessions}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.sessions.write().expect("locked for writing").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Create a new rendezvous session holding `content` for a client at `ip`.
+/// Returns the session ID along with the stored session.
+#[implement(Service)]
+pub fn create(
+	&self,
+	ip: IpAddr,
+	content_type: String,
+	content: Bytes,
+) -> Result<(String, Session)> {
+	check_content_length(&content)?;
+
+	let ip = ip.to_canonical();
+	let mut sessions = self.sessions.write().expect("locked for writing");
+	let now = SystemTime::now();
+	sessions.retain(|_, (_, session)| session.expires > now);
+	let from_ip = sessions
+		.values()
+		.filter(|(creator, _)| *creator == ip)
+		.count();
+
+	if sessions.len() >= MAX_SESSIONS || from_ip >= MAX_SESSIONS_PER_IP {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many rendezvous sessions; try again later.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let id = utils::random_string(SESSION_ID_LENGTH);
+	let session = self.session(content_type, content, now);
+	sessions.insert(id.clone(), (ip, session.clone()));
+	debug!(%id, "Created rendezvous session");
+
+	Ok((id, session))
+}
+
+/// Get the current state of an unexpired rendezvous session.
+#[implement(Service)]
+pub fn get(&self, id: &str) -> Result<Session> {
+	let now = SystemTime::now();
+	self.sessions
+		.read()
+		.expect("locked for reading")
+		.get(id)
+		.map(|(_, session)| session)
+		.filter(|session| session.expires > now)
+		.cloned()
+		.map_or_else(|| Err!(Request(NotFound("Rendezvous session not found."))), Ok)
+}
+
+/// Replace the content of a rendezvous session. The write only succeeds when
+/// `if_match` is the entity tag of the current content, so concurrent writers
+/// can't silently overwrite one another.
+#[implement(Service)]
+pub fn update(
+	&self,
+	id: &str,
+	if_match: &str,
+	content_type: String,
+	content: Bytes,
+) -> Result<Session> {
+	check_content_length(&content)?;
+
+	let mut sessions = self.sessions.write().expect("locked for writing");
+	let now = SystemTime::now();
+	let Some((_, current)) = sessions
+		.get_mut(id)
+		.filter(|(_, session)| session.expires > now)
+	else {
+		return Err!(Request(NotFound("Rendezvous session not found.")));
+	};
+
+	if current.etag != if_match {
+		return Err(Error::Request(
+			ErrorKind::Unknown,
+			"Rendezvous session was modified concurrently.".into(),
+			StatusCode::PRECONDITION_FAILED,
+		));
+	}
+
+	*current = self.session(content_type, content, now);
+
+	Ok(current.clone())
+}
+
+/// Delete a rendezvous session.
+#[implement(Service)]
+pub fn delete(&self, id: &str) -> Result {
+	self.sessions
+		.write()
+		.expect("locked for writing")
+		.remove(id)
+		.map(|_| debug!(%id, "Deleted rendezvous session"))
+		.map_or_else(|| Err!(Request(NotFound("Rendezvous session not found."))), Ok)
+}
+
+#[implement(Service)]
+fn session(&self, content_type: String, content: Bytes, now: SystemTime) -> Session {
+	let ttl = Duration::from_secs(self.server.config.rendezvous_ttl);
+
+	Session {
+		content,
+		content_type,
+		etag: utils::random_string(ETAG_LENGTH),
+		last_modified: now,
+		expires: now.checked_add(ttl).unwrap_or(now),
+	}
+}
+
+fn check_content_length(content: &Bytes) -> Result {
+	if content.len() > MAX_CONTENT_LENGTH {
+		return Err!(Request(TooLarge("Rendezvous payload is too large.")));
+	}
+
+	Ok(())
+}
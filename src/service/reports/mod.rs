@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use conduwuit::{
+	Err, Result, implement,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{Int, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId};
+use serde::{Deserialize, Serialize};
+
+use crate::{Dep, globals};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	reportid_report: Arc<Map>,
+}
+
+/// Report of an event or a user submitted by a local user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	/// The user who submitted the report
+	pub reporter: OwnedUserId,
+
+	/// The room the reported event was sent in; None for user reports
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub room_id: Option<OwnedRoomId>,
+
+	/// The reported event; None for user reports
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub event_id: Option<OwnedEventId>,
+
+	/// The reported user, or the sender of the reported event. Event reports
+	/// stored before user reports were added name it `event_sender`.
+	#[serde(alias = "event_sender")]
+	pub reported_user: OwnedUserId,
+
+	/// The reason given by the reporter
+	pub reason: Option<String>,
+
+	/// The score given by the reporter, from -100 (most offensive) to 0
+	pub score: Option<Int>,
+
+	/// When the report was received
+	pub received_ts: MilliSecondsSinceUnixEpoch,
+
+	/// When the report was resolved by an admin, if it was
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub resolved: Option<Resolution>,
+}
+
+/// Resolution of a report by an admin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Resolution {
+	/// When the report was resolved
+	pub resolved_ts: MilliSecondsSinceUnixEpoch,
+
+	/// Note left by the admin resolving the report
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub note: Option<String>,
+}
+
+/// Which reports to list and in what order.
+#[derive(Clone, Copy, Debug)]
+pub enum Direction {
+	/// Reports received before the report with this ID, newest first
+	Before(u64),
+
+	/// Reports received after the report with this ID, oldest first
+	After(u64),
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				reportid_report: args.db["reportid_report"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Store a new report. Returns its ID.
+#[implement(Service)]
+pub fn add_report(&self, report: &Report) -> Result<u64> {
+	let id = self.services.globals.next_count()?;
+	self.db.reportid_report.put(id, Json(report));
+
+	Ok(id)
+}
+
+/// Get a report by its ID.
+#[implement(Service)]
+pub async fn get_report(&self, id: u64) -> Result<Report> {
+	self.db.reportid_report.qry(&id).await.deserialized()
+}
+
+/// Mark a report as resolved.
+#[implement(Service)]
+pub async fn resolve_report(&self, id: u64, note: Option<String>) -> Result<Report> {
+	let mut report = self.get_report(id).await?;
+	if report.resolved.is_some() {
+		return Err!(Request(InvalidParam("Report {id} has already been resolved.")));
+	}
+
+	report.resolved = Some(Resolution {
+		resolved_ts: MilliSecondsSinceUnixEpoch::now(),
+		note,
+	});
+
+	self.db.reportid_report.put(id, Json(&report));
+
+	Ok(report)
+}
+
+/// Stream reports with their IDs, optionally only those about events in
+/// `room_id`. Without a direction, the newest reports are streamed first.
+#[implement(Service)]
+pub fn reports<'a>(
+	&'a self,
+	room_id: Option<&'a RoomId>,
+	direction: Option<Direction>,
+) -> impl Stream<Item = (u64, Report)> + Send + 'a {
+	type KeyVal = (u64, Report);
+
+	let reports = match direction {
+		| None => self.db.reportid_report.rev_stream::<u64, Report>().boxed(),
+		| Some(Direction::Before(id)) => self
+			.db
+			.reportid_report
+			.rev_stream_from::<u64, Report, _>(&id.saturating_sub(1))
+			.boxed(),
+		| Some(Direction::After(id)) => self
+			.db
+			.reportid_report
+			.stream_from::<u64, Report, _>(&id.saturating_add(1))
+			.boxed(),
+	};
+
+	reports
+		.ignore_err()
+		.ready_filter(move |(_, report): &KeyVal| {
+			room_id.is_none_or(|room_id| report.room_id.as_deref() == Some(room_id))
+		})
+}
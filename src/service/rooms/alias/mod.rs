@@ -75,6 +75,10 @@ pub fn set_alias(
 			return Err!(Request(Forbidden("Only the server user can set this alias")));
 		}
 
+		if self.is_forbidden(alias) && user_id != self.services.globals.server_user {
+			return Err!(Request(Forbidden("Room alias is forbidden.")));
+		}
+
 		// Comes first as we don't want a stuck alias
 		self.db
 			.alias_userid
@@ -98,21 +102,22 @@ pub async fn remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) -> Resul
 			return Err!(Request(Forbidden("User is not permitted to remove this alias.")));
 		}
 
-		let alias = alias.alias();
-		let Ok(room_id) = self.db.alias_roomid.get(&alias).await else {
+		let Ok(room_id) = self.db.alias_roomid.get(alias.alias()).await else {
 			return Err!(Request(NotFound("Alias does not exist or is invalid.")));
 		};
 
+		// Only drop this alias from the aliases of the room, not the others
 		let prefix = (&room_id, Interfix);
 		self.db
 			.aliasid_alias
-			.keys_prefix_raw(&prefix)
+			.stream_prefix_raw(&prefix)
 			.ignore_err()
-			.ready_for_each(|key| self.db.aliasid_alias.remove(key))
+			.ready_filter(|(_, val)| *val == alias.as_bytes())
+			.ready_for_each(|(key, _)| self.db.aliasid_alias.remove(key))
 			.await;
 
-		self.db.alias_roomid.remove(alias.as_bytes());
-		self.db.alias_userid.remove(alias.as_bytes());
+		self.db.alias_roomid.remove(alias.alias().as_bytes());
+		self.db.alias_userid.remove(alias.alias().as_bytes());
 
 		Ok(())
 	}
@@ -196,6 +201,39 @@ pub fn all_local_aliases<'a>(&'a self) -> impl Stream<Item = (&RoomId, &str)> +
 			.map(|(alias_localpart, room_id): (&str, &RoomId)| (room_id, alias_localpart))
 	}
 
+	/// Whether the localpart of the alias matches `forbidden_alias_names`.
+	#[must_use]
+	pub fn is_forbidden(&self, alias: &RoomAliasId) -> bool {
+		self.services
+			.globals
+			.forbidden_alias_names()
+			.is_match(alias.alias())
+	}
+
+	/// Whether a local alias or the canonical alias of the room matches
+	/// `forbidden_alias_names`.
+	pub async fn room_has_forbidden_alias(&self, room_id: &RoomId) -> bool {
+		if self.services.globals.forbidden_alias_names().is_empty() {
+			return false;
+		}
+
+		self.local_aliases_for_room(room_id)
+			.ready_any(|alias| self.is_forbidden(alias))
+			.await || self
+			.services
+			.state_accessor
+			.get_canonical_alias(room_id)
+			.await
+			.is_ok_and(|alias| self.is_forbidden(&alias))
+	}
+
+	/// Local aliases whose localpart matches `forbidden_alias_names`.
+	pub fn forbidden_local_aliases(&self) -> impl Stream<Item = (&RoomId, &str)> + Send + '_ {
+		let patterns = self.services.globals.forbidden_alias_names();
+		self.all_local_aliases()
+			.ready_filter(move |(_, localpart)| patterns.is_match(localpart))
+	}
+
 	async fn user_can_remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) -> Result<bool> {
 		let room_id = self
 			.resolve_local_alias(alias)
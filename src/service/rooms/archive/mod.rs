@@ -0,0 +1,368 @@
+use std::{
+	collections::BTreeSet,
+	io::ErrorKind,
+	mem,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug, err, implement, info,
+	utils::{ReadyExt, millis_since_unix_epoch, stream::TryIgnore},
+	warn,
+};
+use database::{Database, Map};
+use futures::StreamExt;
+use lru_cache::LruCache;
+use ruma::{RoomId, UInt};
+use serde::{Deserialize, de::IgnoredAny};
+use tokio::{
+	fs,
+	io::AsyncWriteExt,
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, globals, maintenance, rooms};
+
+/// Moves old events of selected rooms out of the database into compressed
+/// segment files, leaving a stub in their place in `pduid_pdu` by which they
+/// are read back.
+pub struct Service {
+	segments: Mutex<LruCache<u64, Arc<Segment>>>,
+	interrupt: Notify,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	maintenance: Dep<maintenance::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+}
+
+struct Data {
+	pduid_pdu: Arc<Map>,
+	db: Arc<Database>,
+}
+
+/// Decompressed segment file; the JSON of each of its PDUs
+type Segment = Vec<Box<[u8]>>;
+
+/// Fields of a PDU deciding whether it is archived
+#[derive(Deserialize)]
+struct Candidate {
+	origin_server_ts: UInt,
+	state_key: Option<IgnoredAny>,
+}
+
+/// First byte of a stub, which cannot start the JSON of a PDU. It is followed
+/// by the segment id and the index of the PDU in it.
+const STUB_MARKER: u8 = 0x00;
+const STUB_LEN: usize = 1 + size_of::<u64>() + size_of::<u32>();
+
+/// Maximum number of PDUs per segment file
+const SEGMENT_PDUS: usize = 4096;
+
+/// Number of decompressed segments kept in memory
+const SEGMENT_CACHE: usize = 16;
+
+const COMPRESSION_LEVEL: i32 = 19;
+
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			segments: Mutex::new(LruCache::new(SEGMENT_CACHE)),
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+			},
+			db: Data {
+				pduid_pdu: args.db["pduid_pdu"].clone(),
+				db: args.db.clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(ARCHIVE_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			if !self.enabled() {
+				continue;
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.services.maintenance.defer("archive old events") => (),
+			}
+
+			for room_id in &self.services.server.config.event_archive_rooms {
+				match self.archive_room(room_id).await {
+					| Ok(0) => (),
+					| Ok(archived) => info!(%room_id, archived, "Archived old events"),
+					| Err(e) => warn!(%room_id, "Failed to archive old events: {e}"),
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether the value of a PDU in `pduid_pdu` is a stub of an archived PDU.
+#[inline]
+#[must_use]
+pub fn is_archived(value: &[u8]) -> bool { value.first() == Some(&STUB_MARKER) }
+
+#[implement(Service)]
+#[must_use]
+pub fn enabled(&self) -> bool {
+	let config = &self.services.server.config;
+	config.event_archive_after > 0 && !config.event_archive_rooms.is_empty()
+}
+
+/// Read the JSON of an archived PDU back from its segment.
+#[implement(Service)]
+pub async fn rehydrate(&self, stub: &[u8]) -> Result<Vec<u8>> {
+	let (segment_id, index) = parse_stub(stub)?;
+	let segment = self.segment(segment_id).await?;
+
+	usize::try_from(index)
+		.ok()
+		.and_then(|index| segment.get(index))
+		.map(|pdu| pdu.to_vec())
+		.ok_or_else(|| err!(Database("PDU {index} missing from archive segment {segment_id}")))
+}
+
+/// Archive the events of the room older than event_archive_after. Returns
+/// the number of events archived.
+#[implement(Service)]
+pub async fn archive_room(&self, room_id: &RoomId) -> Result<usize> {
+	let after = self.services.server.config.event_archive_after;
+	let cutoff = millis_since_unix_epoch().saturating_sub(after.saturating_mul(1000));
+	let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+
+	let mut archived: usize = 0;
+	let mut batch = Vec::with_capacity(SEGMENT_PDUS);
+	let mut pdus = self
+		.db
+		.pduid_pdu
+		.raw_stream_prefix(&shortroomid.to_be_bytes())
+		.ignore_err()
+		.boxed();
+
+	while let Some((pdu_id, pdu)) = pdus.next().await {
+		if is_archived(pdu) {
+			continue;
+		}
+
+		let Ok(candidate) = serde_json::from_slice::<Candidate>(pdu) else {
+			continue;
+		};
+
+		if candidate.state_key.is_some() || u64::from(candidate.origin_server_ts) >= cutoff {
+			continue;
+		}
+
+		batch.push((pdu_id.to_vec(), pdu.to_vec()));
+		if batch.len() >= SEGMENT_PDUS {
+			let batch = mem::take(&mut batch);
+			archived = archived.saturating_add(self.write_segment(room_id, batch).await?);
+		}
+	}
+
+	if !batch.is_empty() {
+		archived = archived.saturating_add(self.write_segment(room_id, batch).await?);
+	}
+
+	Ok(archived)
+}
+
+/// Write the PDUs into a new segment file, then replace them with stubs
+/// unless they changed meanwhile, e.g. by a redaction. The stubs are written
+/// holding the room's state lock, under which events are appended and
+/// redacted.
+#[implement(Service)]
+async fn write_segment(&self, room_id: &RoomId, batch: Vec<(Vec<u8>, Vec<u8>)>) -> Result<usize> {
+	let segment_id = self.services.globals.next_count()?;
+	let mut raw = Vec::new();
+	for (_, pdu) in &batch {
+		raw.extend_from_slice(&u32::try_from(pdu.len())?.to_be_bytes());
+		raw.extend_from_slice(pdu);
+	}
+
+	let compressed = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || zstd::encode_all(raw.as_slice(), COMPRESSION_LEVEL))
+		.await??;
+
+	let path = self.segment_path(segment_id);
+	fs::create_dir_all(self.archive_dir()).await?;
+	let mut file = fs::File::create(&path).await?;
+	file.write_all(&compressed).await?;
+	file.sync_all().await?;
+	debug!(?path, pdus = batch.len(), bytes = compressed.len(), "Wrote archive segment");
+
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	let cork = self.db.db.cork_and_flush();
+	let mut archived: usize = 0;
+	for (index, (pdu_id, pdu)) in batch.iter().enumerate() {
+		let unchanged = self
+			.db
+			.pduid_pdu
+			.get_blocking(pdu_id)
+			.is_ok_and(|current| *current == **pdu);
+
+		if unchanged {
+			let stub = make_stub(segment_id, u32::try_from(index)?);
+			self.db.pduid_pdu.insert(pdu_id, stub);
+			archived = archived.saturating_add(1);
+		}
+	}
+
+	drop(cork);
+	drop(state_lock);
+	if archived == 0 {
+		fs::remove_file(&path).await?;
+	}
+
+	Ok(archived)
+}
+
+/// Delete the segment files holding archived events of the room, along with
+/// the stubs pointing into them. Called when the room's events are purged, as
+/// the segments would otherwise outlive them. Returns the number of segment
+/// files deleted.
+#[implement(Service)]
+pub async fn purge_room(&self, room_id: &RoomId) -> Result<usize> {
+	let Ok(shortroomid) = self.services.short.get_shortroomid(room_id).await else {
+		return Ok(0);
+	};
+
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	let mut segment_ids = BTreeSet::new();
+	let mut stubs = self
+		.db
+		.pduid_pdu
+		.raw_stream_prefix(&shortroomid.to_be_bytes())
+		.ignore_err()
+		.ready_filter(|(_, pdu)| is_archived(pdu))
+		.boxed();
+
+	while let Some((pdu_id, stub)) = stubs.next().await {
+		let (segment_id, _) = parse_stub(stub)?;
+		segment_ids.insert(segment_id);
+		self.db.pduid_pdu.remove(pdu_id);
+	}
+
+	drop(stubs);
+	drop(state_lock);
+	let mut deleted: usize = 0;
+	for segment_id in segment_ids {
+		self.segments.lock().expect("locked").remove(&segment_id);
+		match fs::remove_file(self.segment_path(segment_id)).await {
+			| Ok(()) => deleted = deleted.saturating_add(1),
+			| Err(e) if e.kind() == ErrorKind::NotFound => (),
+			| Err(e) => return Err(e.into()),
+		}
+	}
+
+	debug!(%room_id, deleted, "Deleted archive segments");
+
+	Ok(deleted)
+}
+
+#[implement(Service)]
+async fn segment(&self, segment_id: u64) -> Result<Arc<Segment>> {
+	if let Some(segment) = self.segments.lock().expect("locked").get_mut(&segment_id) {
+		return Ok(segment.clone());
+	}
+
+	let compressed = fs::read(self.segment_path(segment_id)).await?;
+	let raw = zstd::decode_all(compressed.as_slice())?;
+
+	let mut segment = Segment::new();
+	let mut rest = raw.as_slice();
+	while !rest.is_empty() {
+		let Some((len, tail)) = rest.split_first_chunk::<4>() else {
+			return Err!(Database("Truncated archive segment {segment_id}"));
+		};
+
+		let len = usize::try_from(u32::from_be_bytes(*len))?;
+		if tail.len() < len {
+			return Err!(Database("Truncated archive segment {segment_id}"));
+		}
+
+		let (pdu, tail) = tail.split_at(len);
+		segment.push(pdu.into());
+		rest = tail;
+	}
+
+	let segment = Arc::new(segment);
+	self.segments
+		.lock()
+		.expect("locked")
+		.insert(segment_id, segment.clone());
+
+	Ok(segment)
+}
+
+#[implement(Service)]
+fn segment_path(&self, segment_id: u64) -> PathBuf {
+	self.archive_dir().join(format!("{segment_id}.zst"))
+}
+
+#[implement(Service)]
+fn archive_dir(&self) -> PathBuf {
+	let config = &self.services.server.config;
+	config
+		.event_archive_path
+		.clone()
+		.unwrap_or_else(|| config.database_path.join("archive"))
+}
+
+fn make_stub(segment_id: u64, index: u32) -> Vec<u8> {
+	let mut stub = Vec::with_capacity(STUB_LEN);
+	stub.push(STUB_MARKER);
+	stub.extend_from_slice(&segment_id.to_be_bytes());
+	stub.extend_from_slice(&index.to_be_bytes());
+
+	stub
+}
+
+fn parse_stub(stub: &[u8]) -> Result<(u64, u32)> {
+	let parsed = stub
+		.split_first()
+		.filter(|&(&marker, _)| marker == STUB_MARKER)
+		.and_then(|(_, rest)| rest.split_first_chunk::<8>())
+		.and_then(|(segment_id, rest)| Some((segment_id, rest.first_chunk::<4>()?)));
+
+	let Some((segment_id, index)) = parsed else {
+		return Err!(Database("Invalid archived PDU stub"));
+	};
+
+	Ok((u64::from_be_bytes(*segment_id), u32::from_be_bytes(*index)))
+}
@@ -255,3 +255,12 @@ pub fn get_cache_usage(&self) -> (usize, usize) {
 
 #[implement(Service)]
 pub fn clear_cache(&self) { self.db.auth_chain_cache.lock().expect("locked").clear(); }
+
+#[implement(Service)]
+pub fn set_cache_capacity(&self, capacity: usize) {
+	self.db
+		.auth_chain_cache
+		.lock()
+		.expect("locked")
+		.set_capacity(capacity);
+}
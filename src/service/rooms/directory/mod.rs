@@ -1,27 +1,78 @@
-use std::sync::Arc;
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
-use conduwuit::{Result, implement, utils::stream::TryIgnore};
+use async_trait::async_trait;
+use conduwuit::{
+	Result, Server, debug, implement,
+	utils::{math::usize_from_f64, stream::TryIgnore},
+};
 use database::Map;
 use futures::Stream;
-use ruma::{RoomId, api::client::room::Visibility};
+use lru_cache::LruCache;
+use ruma::{
+	OwnedServerName, RoomId, ServerName, UInt,
+	api::{client::room::Visibility, federation::directory::get_public_rooms_filtered},
+	directory::{Filter, RoomNetwork},
+};
+
+use crate::{Dep, sending};
 
 pub struct Service {
+	/// Directories of remote servers recently fetched over federation.
+	remote_cache: Mutex<RemoteCache>,
+	services: Services,
 	db: Data,
 }
 
+struct Services {
+	server: Arc<Server>,
+	sending: Dep<sending::Service>,
+}
+
 struct Data {
 	publicroomids: Arc<Map>,
 }
 
+/// Page of a remote server's public room directory.
+pub type RemoteDirectory = get_public_rooms_filtered::v1::Response;
+
+/// Server, limit, since token and serialized filter of a remote directory
+/// request.
+type RemoteKey = (OwnedServerName, Option<UInt>, Option<String>, String);
+
+type RemoteCache = LruCache<RemoteKey, (Instant, Arc<RemoteDirectory>)>;
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let cache_size = f64::from(config.public_room_directory_cache_capacity);
+		let cache_size = cache_size * config.cache_capacity_modifier;
 		Ok(Arc::new(Self {
+			remote_cache: Mutex::new(LruCache::new(usize_from_f64(cache_size)?)),
+			services: Services {
+				server: args.server.clone(),
+				sending: args.depend::<sending::Service>("sending"),
+			},
 			db: Data {
 				publicroomids: args.db["publicroomids"].clone(),
 			},
 		}))
 	}
 
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let remote_cache = self.remote_cache.lock().expect("locked").len();
+
+		writeln!(out, "remote_directory_cache: {remote_cache}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.remote_cache.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -49,3 +100,57 @@ pub async fn visibility(&self, room_id: &RoomId) -> Visibility {
 		Visibility::Private
 	}
 }
+
+/// Fetch a page of the public room directory of a remote server, answering
+/// from the cache if the same page was fetched within
+/// `public_room_directory_cache_ttl`.
+#[implement(Service)]
+pub async fn remote_public_rooms(
+	&self,
+	server: &ServerName,
+	limit: Option<UInt>,
+	since: Option<&str>,
+	filter: &Filter,
+) -> Result<Arc<RemoteDirectory>> {
+	let ttl = Duration::from_secs(self.services.server.config.public_room_directory_cache_ttl);
+	let key: RemoteKey = (
+		server.to_owned(),
+		limit,
+		since.map(ToOwned::to_owned),
+		serde_json::to_string(filter)?,
+	);
+
+	{
+		let mut cache = self.remote_cache.lock().expect("locked");
+		if let Some((fetched, directory)) = cache.get_mut(&key) {
+			if fetched.elapsed() < ttl {
+				return Ok(directory.clone());
+			}
+
+			cache.remove(&key);
+		}
+	}
+
+	let directory: Arc<_> = self
+		.services
+		.sending
+		.send_federation_request(server, get_public_rooms_filtered::v1::Request {
+			limit,
+			since: since.map(ToOwned::to_owned),
+			filter: Filter {
+				generic_search_term: filter.generic_search_term.clone(),
+				room_types: filter.room_types.clone(),
+			},
+			room_network: RoomNetwork::Matrix,
+		})
+		.await?
+		.into();
+
+	if !ttl.is_zero() {
+		let mut cache = self.remote_cache.lock().expect("locked");
+		cache.insert(key, (Instant::now(), directory.clone()));
+		debug!(%server, cached = cache.len(), "Cached remote public room directory");
+	}
+
+	Ok(directory)
+}
@@ -7,6 +7,7 @@
 mod handle_prev_pdu;
 mod parse_incoming_pdu;
 mod resolve_state;
+mod room_queue;
 mod state_at_incoming;
 mod upgrade_outlier_pdu;
 
@@ -24,11 +25,14 @@
 	events::room::create::RoomCreateEventContent,
 };
 
-use crate::{Dep, globals, rooms, sending, server_keys};
+pub use self::room_queue::RoomQueueGuard;
+use self::room_queue::RoomQueueMap;
+use crate::{Dep, globals, rooms, sending, server_keys, spam_checker};
 
 pub struct Service {
 	pub mutex_federation: RoomMutexMap,
 	pub federation_handletime: StdRwLock<HandleTimeMap>,
+	room_queue: RoomQueueMap,
 	services: Services,
 }
 
@@ -45,6 +49,7 @@ struct Services {
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_compressor: Dep<rooms::state_compressor::Service>,
 	timeline: Dep<rooms::timeline::Service>,
+	spam_checker: Dep<spam_checker::Service>,
 	server: Arc<Server>,
 }
 
@@ -57,6 +62,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			mutex_federation: RoomMutexMap::new(),
 			federation_handletime: HandleTimeMap::new().into(),
+			room_queue: RoomQueueMap::default(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
@@ -72,6 +78,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				state_compressor: args
 					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				spam_checker: args.depend::<spam_checker::Service>("spam_checker"),
 				server: args.server.clone(),
 			},
 		}))
@@ -88,6 +95,9 @@ async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
 			.len();
 		writeln!(out, "federation_handletime: {federation_handletime}")?;
 
+		let room_queue = self.room_queue.lock().expect("locked").len();
+		writeln!(out, "room_queue: {room_queue}")?;
+
 		Ok(())
 	}
 
@@ -0,0 +1,90 @@
+use std::{
+	collections::{HashMap, hash_map::Entry},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use conduwuit::{Error, Result, debug_warn, http::StatusCode, implement};
+use ruma::{
+	OwnedRoomId, RoomId,
+	api::client::error::{ErrorKind, RetryAfter},
+};
+
+pub(super) type RoomQueueMap = Arc<Mutex<HashMap<OwnedRoomId, usize>>>;
+
+/// Reservation of capacity in a room's inbound federation queue. The capacity
+/// is released when the guard is dropped.
+#[must_use]
+pub struct RoomQueueGuard {
+	map: RoomQueueMap,
+	room_id: OwnedRoomId,
+	count: usize,
+}
+
+/// Reserve capacity for `count` incoming PDUs in the room's queue. Errors with
+/// M_LIMIT_EXCEEDED when the room already has too many PDUs queued, which
+/// rejects the whole transaction so the origin retries it later; a room
+/// with an empty queue always accepts the reservation so oversized
+/// transactions can make progress.
+#[implement(super::Service)]
+pub fn room_queue_reserve(&self, room_id: &RoomId, count: usize) -> Result<RoomQueueGuard> {
+	let config = &self.services.server.config;
+	let limit = config.federation_room_queue_size;
+
+	let mut map = self.room_queue.lock().expect("locked");
+	let queued = map.entry(room_id.to_owned()).or_default();
+	if limit > 0 && *queued > 0 && queued.saturating_add(count) > limit {
+		debug_warn!(%room_id, queued, count, limit, "Inbound room queue is full");
+		return Err(self.room_queue_full());
+	}
+
+	*queued = queued.saturating_add(count);
+
+	Ok(RoomQueueGuard {
+		map: self.room_queue.clone(),
+		room_id: room_id.to_owned(),
+		count,
+	})
+}
+
+/// The error for PDUs rejected because their room's queue is full.
+#[implement(super::Service)]
+#[must_use]
+pub fn room_queue_full(&self) -> Error {
+	let retry_after =
+		Duration::from_millis(self.services.server.config.federation_room_queue_retry_ms);
+
+	Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		"Too many events are queued for this room; try again later.".into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	)
+}
+
+/// Number of PDUs currently queued or in-flight for the room.
+#[implement(super::Service)]
+#[must_use]
+pub fn room_queue_len(&self, room_id: &RoomId) -> usize {
+	self.room_queue
+		.lock()
+		.expect("locked")
+		.get(room_id)
+		.copied()
+		.unwrap_or(0)
+}
+
+impl Drop for RoomQueueGuard {
+	fn drop(&mut self) {
+		let mut map = self.map.lock().expect("locked");
+		if let Entry::Occupied(mut entry) = map.entry(self.room_id.clone()) {
+			let queued = entry.get().saturating_sub(self.count);
+			if queued == 0 {
+				entry.remove();
+			} else {
+				*entry.get_mut() = queued;
+			}
+		}
+	}
+}
@@ -11,9 +11,12 @@
 use ruma::{CanonicalJsonValue, RoomId, ServerName, events::StateEventType};
 
 use super::{get_room_version_id, to_room_version};
-use crate::rooms::{
-	state_compressor::{CompressedState, HashSetCompressStateEvent},
-	timeline::RawPduId,
+use crate::{
+	rooms::{
+		state_compressor::{CompressedState, HashSetCompressStateEvent},
+		timeline::RawPduId,
+	},
+	spam_checker::{Check, Verdict},
 };
 
 #[implement(super::Service)]
@@ -133,6 +136,15 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 				.await?,
 	};
 
+	// Non-state events the spam checker does not allow are soft failed as well
+	let soft_fail = soft_fail
+		|| (incoming_pdu.state_key.is_none()
+			&& self
+				.services
+				.spam_checker
+				.check(&Check::from(&incoming_pdu))
+				.await != Verdict::Allow);
+
 	// 13. Use state resolution to find new room state
 
 	// We start looking at current room state now, so lets lock the room
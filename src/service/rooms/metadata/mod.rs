@@ -15,6 +15,7 @@ pub struct Service {
 struct Data {
 	disabledroomids: Arc<Map>,
 	bannedroomids: Arc<Map>,
+	quarantinedroomids: Arc<Map>,
 	roomid_shortroomid: Arc<Map>,
 	pduid_pdu: Arc<Map>,
 }
@@ -29,6 +30,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			db: Data {
 				disabledroomids: args.db["disabledroomids"].clone(),
 				bannedroomids: args.db["bannedroomids"].clone(),
+				quarantinedroomids: args.db["quarantinedroomids"].clone(),
 				roomid_shortroomid: args.db["roomid_shortroomid"].clone(),
 				pduid_pdu: args.db["pduid_pdu"].clone(),
 			},
@@ -87,6 +89,23 @@ pub fn list_banned_rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
 	self.db.bannedroomids.keys().ignore_err()
 }
 
+/// Quarantine a room: local users can no longer join or send events to it,
+/// while events from other servers are still received.
+#[implement(Service)]
+#[inline]
+pub fn quarantine_room(&self, room_id: &RoomId, quarantined: bool) {
+	if quarantined {
+		self.db.quarantinedroomids.insert(room_id, []);
+	} else {
+		self.db.quarantinedroomids.remove(room_id);
+	}
+}
+
+#[implement(Service)]
+pub fn list_quarantined_rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
+	self.db.quarantinedroomids.keys().ignore_err()
+}
+
 #[implement(Service)]
 #[inline]
 pub async fn is_disabled(&self, room_id: &RoomId) -> bool {
@@ -98,3 +117,9 @@ pub async fn is_disabled(&self, room_id: &RoomId) -> bool {
 pub async fn is_banned(&self, room_id: &RoomId) -> bool {
 	self.db.bannedroomids.get(room_id).await.is_ok()
 }
+
+#[implement(Service)]
+#[inline]
+pub async fn is_quarantined(&self, room_id: &RoomId) -> bool {
+	self.db.quarantinedroomids.get(room_id).await.is_ok()
+}
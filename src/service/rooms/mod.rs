@@ -1,4 +1,5 @@
 pub mod alias;
+pub mod archive;
 pub mod auth_chain;
 pub mod directory;
 pub mod event_handler;
@@ -17,12 +18,14 @@
 pub mod threads;
 pub mod timeline;
 pub mod typing;
+pub mod unredacted;
 pub mod user;
 
 use std::sync::Arc;
 
 pub struct Service {
 	pub alias: Arc<alias::Service>,
+	pub archive: Arc<archive::Service>,
 	pub auth_chain: Arc<auth_chain::Service>,
 	pub directory: Arc<directory::Service>,
 	pub event_handler: Arc<event_handler::Service>,
@@ -41,5 +44,6 @@ pub struct Service {
 	pub threads: Arc<threads::Service>,
 	pub timeline: Arc<timeline::Service>,
 	pub typing: Arc<typing::Service>,
+	pub unredacted: Arc<unredacted::Service>,
 	pub user: Arc<user::Service>,
 }
@@ -1,4 +1,4 @@
-use std::{mem::size_of, sync::Arc};
+use std::sync::Arc;
 
 use conduwuit::{
 	PduCount, PduEvent,
@@ -10,7 +10,7 @@
 		u64_from_u8,
 	},
 };
-use database::Map;
+use database::{Batch, Map};
 use futures::{Stream, StreamExt};
 use ruma::{EventId, RoomId, UserId, api::Direction};
 
@@ -48,11 +48,9 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 		}
 	}
 
-	pub(super) fn add_relation(&self, from: u64, to: u64) {
-		const BUFSIZE: usize = size_of::<u64>() * 2;
-
+	pub(super) fn add_relation(&self, batch: &mut Batch, from: u64, to: u64) {
 		let key: &[u64] = &[to, from];
-		self.tofrom_relation.aput_raw::<BUFSIZE, _, _>(key, []);
+		batch.put_raw(&self.tofrom_relation, key, []);
 	}
 
 	pub(super) fn get_relations<'a>(
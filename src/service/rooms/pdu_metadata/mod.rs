@@ -2,6 +2,7 @@
 use std::sync::Arc;
 
 use conduwuit::{PduCount, Result};
+use database::Batch;
 use futures::{StreamExt, future::try_join};
 use ruma::{EventId, RoomId, UserId, api::Direction};
 
@@ -34,9 +35,9 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 
 impl Service {
 	#[tracing::instrument(skip(self, from, to), level = "debug")]
-	pub fn add_relation(&self, from: PduCount, to: PduCount) {
+	pub fn add_relation(&self, batch: &mut Batch, from: PduCount, to: PduCount) {
 		match (from, to) {
-			| (PduCount::Normal(f), PduCount::Normal(t)) => self.db.add_relation(f, t),
+			| (PduCount::Normal(f), PduCount::Normal(t)) => self.db.add_relation(batch, f, t),
 			| _ => {
 				// TODO: Relations with backfilled pdus
 			},
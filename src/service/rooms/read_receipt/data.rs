@@ -1,13 +1,13 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use conduwuit::{
 	Result,
 	utils::{ReadyExt, stream::TryIgnore},
 };
-use database::{Deserialized, Json, Map};
-use futures::{Stream, StreamExt};
+use database::{Batch, Deserialized, Json, Map};
+use futures::{Stream, StreamExt, pin_mut};
 use ruma::{
-	CanonicalJsonObject, RoomId, UserId,
+	CanonicalJsonObject, OwnedRoomId, OwnedUserId, RoomId, UserId,
 	events::{AnySyncEphemeralRoomEvent, receipt::ReceiptEvent},
 	serde::Raw,
 };
@@ -86,12 +86,48 @@ pub(super) fn readreceipts_since<'a>(
 			.ignore_err()
 	}
 
-	pub(super) fn private_read_set(&self, room_id: &RoomId, user_id: &UserId, pdu_count: u64) {
+	pub(super) async fn prune_superseded(&self) -> usize {
+		type Key<'a> = (&'a RoomId, u64, &'a UserId);
+
+		let receipts = self
+			.readreceiptid_readreceipt
+			.rev_keys::<Key<'_>>()
+			.ignore_err();
+
+		pin_mut!(receipts);
+		let mut room: Option<OwnedRoomId> = None;
+		let mut users: HashSet<OwnedUserId> = HashSet::new();
+		let mut pruned: usize = 0;
+		while let Some((room_id, count, user_id)) = receipts.next().await {
+			if room.as_deref() != Some(room_id) {
+				room = Some(room_id.to_owned());
+				users.clear();
+			}
+
+			// Newest first, so any receipt of a user seen before is superseded
+			if !users.insert(user_id.to_owned()) {
+				self.readreceiptid_readreceipt
+					.del((room_id, count, user_id));
+
+				pruned = pruned.saturating_add(1);
+			}
+		}
+
+		pruned
+	}
+
+	pub(super) fn private_read_set(
+		&self,
+		batch: &mut Batch,
+		room_id: &RoomId,
+		user_id: &UserId,
+		pdu_count: u64,
+	) {
 		let key = (room_id, user_id);
 		let next_count = self.services.globals.next_count().unwrap();
 
-		self.roomuserid_privateread.put(key, pdu_count);
-		self.roomuserid_lastprivatereadupdate.put(key, next_count);
+		batch.put(&self.roomuserid_privateread, key, pdu_count);
+		batch.put(&self.roomuserid_lastprivatereadupdate, key, next_count);
 	}
 
 	pub(super) async fn private_read_get_count(
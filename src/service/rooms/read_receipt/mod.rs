@@ -7,6 +7,7 @@
 	matrix::pdu::{PduCount, PduId, RawPduId},
 	warn,
 };
+use database::Batch;
 use futures::{Stream, TryFutureExt, try_join};
 use ruma::{
 	OwnedEventId, OwnedUserId, RoomId, UserId,
@@ -117,8 +118,14 @@ pub fn readreceipts_since<'a>(
 	/// Sets a private read marker at PDU `count`.
 	#[inline]
 	#[tracing::instrument(skip(self), level = "debug")]
-	pub fn private_read_set(&self, room_id: &RoomId, user_id: &UserId, count: u64) {
-		self.db.private_read_set(room_id, user_id, count);
+	pub fn private_read_set(
+		&self,
+		batch: &mut Batch,
+		room_id: &RoomId,
+		user_id: &UserId,
+		count: u64,
+	) {
+		self.db.private_read_set(batch, room_id, user_id, count);
 	}
 
 	/// Returns the private read marker PDU count.
@@ -137,6 +144,11 @@ pub async fn private_read_get_count(
 	pub async fn last_privateread_update(&self, user_id: &UserId, room_id: &RoomId) -> u64 {
 		self.db.last_privateread_update(user_id, room_id).await
 	}
+
+	/// Removes read receipts superseded by a newer receipt of the same user
+	/// in the same room. Returns the number removed.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn prune_superseded(&self) -> usize { self.db.prune_superseded().await }
 }
 
 #[must_use]
@@ -9,7 +9,7 @@
 		stream::{TryIgnore, WidebandExt},
 	},
 };
-use database::{Map, keyval::Val};
+use database::{Batch, Map, keyval::Val};
 use futures::{Stream, StreamExt};
 use ruma::{RoomId, UserId, api::client::search::search_events::v3::Criteria};
 
@@ -68,20 +68,20 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 #[implement(Service)]
-pub fn index_pdu(&self, shortroomid: ShortRoomId, pdu_id: &RawPduId, message_body: &str) {
-	let batch = tokenize(message_body)
-		.map(|word| {
-			let mut key = shortroomid.to_be_bytes().to_vec();
-			key.extend_from_slice(word.as_bytes());
-			key.push(0xFF);
-			key.extend_from_slice(pdu_id.as_ref()); // TODO: currently we save the room id a second time here
-			key
-		})
-		.collect::<Vec<_>>();
-
-	self.db
-		.tokenids
-		.insert_batch(batch.iter().map(|k| (k.as_slice(), &[])));
+pub fn index_pdu(
+	&self,
+	batch: &mut Batch,
+	shortroomid: ShortRoomId,
+	pdu_id: &RawPduId,
+	message_body: &str,
+) {
+	for word in tokenize(message_body) {
+		let mut key = shortroomid.to_be_bytes().to_vec();
+		key.extend_from_slice(word.as_bytes());
+		key.push(0xFF);
+		key.extend_from_slice(pdu_id.as_ref()); // TODO: currently we save the room id a second time here
+		batch.insert(&self.db.tokenids, &key, []);
+	}
 }
 
 #[implement(Service)]
@@ -0,0 +1,237 @@
+use conduwuit::{Err, Result, err, implement};
+use ruma::{
+	RoomId, UserId,
+	events::{
+		AnyStateEventContent, StateEventType,
+		room::{
+			canonical_alias::RoomCanonicalAliasEventContent,
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			server_acl::RoomServerAclEventContent,
+		},
+	},
+	serde::Raw,
+};
+
+/// Checks a state event sent by a local client must pass on top of the auth
+/// rules, refusing changes which would break the room or expose the admin
+/// room.
+#[implement(super::Service)]
+pub async fn allowed_to_send_state_event(
+	&self,
+	room_id: &RoomId,
+	event_type: &StateEventType,
+	state_key: &str,
+	json: &Raw<AnyStateEventContent>,
+) -> Result {
+	match event_type {
+		| StateEventType::RoomCreate => {
+			return Err!(Request(BadJson(debug_warn!(
+				?room_id,
+				"You cannot update m.room.create after a room has been created."
+			))));
+		},
+		| StateEventType::RoomServerAcl => {
+			// prevents common ACL paw-guns as ACL management is difficult and prone to
+			// irreversible mistakes
+			match json.deserialize_as::<RoomServerAclEventContent>() {
+				| Ok(acl_content) => {
+					if acl_content.allow_is_empty() {
+						return Err!(Request(BadJson(debug_warn!(
+							?room_id,
+							"Sending an ACL event with an empty allow key will permanently \
+							 brick the room for non-conduwuit's as this equates to no servers \
+							 being allowed to participate in this room."
+						))));
+					}
+
+					if acl_content.deny_contains("*") && acl_content.allow_contains("*") {
+						return Err!(Request(BadJson(debug_warn!(
+							?room_id,
+							"Sending an ACL event with a deny and allow key value of \"*\" will \
+							 permanently brick the room for non-conduwuit's as this equates to \
+							 no servers being allowed to participate in this room."
+						))));
+					}
+
+					if acl_content.deny_contains("*")
+						&& !acl_content.is_allowed(self.services.globals.server_name())
+						&& !acl_content
+							.allow_contains(self.services.globals.server_name().as_str())
+					{
+						return Err!(Request(BadJson(debug_warn!(
+							?room_id,
+							"Sending an ACL event with a deny key value of \"*\" and without \
+							 your own server name in the allow key will result in you being \
+							 unable to participate in this room."
+						))));
+					}
+
+					if !acl_content.allow_contains("*")
+						&& !acl_content.is_allowed(self.services.globals.server_name())
+						&& !acl_content
+							.allow_contains(self.services.globals.server_name().as_str())
+					{
+						return Err!(Request(BadJson(debug_warn!(
+							?room_id,
+							"Sending an ACL event for an allow key without \"*\" and without \
+							 your own server name in the allow key will result in you being \
+							 unable to participate in this room."
+						))));
+					}
+				},
+				| Err(e) => {
+					return Err!(Request(BadJson(debug_warn!(
+						"Room server ACL event is invalid: {e}"
+					))));
+				},
+			}
+		},
+		| StateEventType::RoomEncryption =>
+		// Forbid m.room.encryption if encryption is disabled
+			if !self.services.server.config.allow_encryption {
+				return Err!(Request(Forbidden("Encryption is disabled on this homeserver.")));
+			},
+		| StateEventType::RoomJoinRules => {
+			// admin room is a sensitive room, it should not ever be made public
+			if let Ok(admin_room_id) = self.services.admin.get_admin_room().await {
+				if admin_room_id == room_id {
+					match json.deserialize_as::<RoomJoinRulesEventContent>() {
+						| Ok(join_rule) =>
+							if join_rule.join_rule == JoinRule::Public {
+								return Err!(Request(Forbidden(
+									"Admin room is a sensitive room, it cannot be made public"
+								)));
+							},
+						| Err(e) => {
+							return Err!(Request(BadJson(debug_warn!(
+								"Room join rules event is invalid: {e}"
+							))));
+						},
+					}
+				}
+			}
+		},
+		| StateEventType::RoomHistoryVisibility => {
+			// admin room is a sensitive room, it should not ever be made world readable
+			if let Ok(admin_room_id) = self.services.admin.get_admin_room().await {
+				match json.deserialize_as::<RoomHistoryVisibilityEventContent>() {
+					| Ok(visibility_content) => {
+						if admin_room_id == room_id
+							&& visibility_content.history_visibility
+								== HistoryVisibility::WorldReadable
+						{
+							return Err!(Request(Forbidden(
+								"Admin room is a sensitive room, it cannot be made world \
+								 readable (public room history)."
+							)));
+						}
+					},
+					| Err(e) => {
+						return Err!(Request(BadJson(debug_warn!(
+							"Room history visibility event is invalid: {e}"
+						))));
+					},
+				}
+			}
+		},
+		| StateEventType::RoomCanonicalAlias => {
+			match json.deserialize_as::<RoomCanonicalAliasEventContent>() {
+				| Ok(canonical_alias_content) => {
+					let mut aliases = canonical_alias_content.alt_aliases.clone();
+
+					if let Some(alias) = canonical_alias_content.alias {
+						aliases.push(alias);
+					}
+
+					for alias in aliases {
+						if self.services.globals.server_is_ours(alias.server_name())
+							&& self.services.alias.is_forbidden(&alias)
+						{
+							return Err!(Request(Forbidden("Room alias {alias} is forbidden.")));
+						}
+
+						let (alias_room_id, _servers) = self
+							.services
+							.alias
+							.resolve_alias(&alias, None)
+							.await
+							.map_err(|e| {
+								err!(Request(Unknown("Failed resolving alias \"{alias}\": {e}")))
+							})?;
+
+						if alias_room_id != room_id {
+							return Err!(Request(BadAlias(
+								"Room alias {alias} does not belong to room {room_id}"
+							)));
+						}
+					}
+				},
+				| Err(e) => {
+					return Err!(Request(InvalidParam(debug_warn!(
+						"Room canonical alias event is invalid: {e}"
+					))));
+				},
+			}
+		},
+		| StateEventType::RoomMember => match json.deserialize_as::<RoomMemberEventContent>() {
+			| Ok(membership_content) => {
+				let Ok(state_key) = UserId::parse(state_key) else {
+					return Err!(Request(BadJson(
+						"Membership event has invalid or non-existent state key"
+					)));
+				};
+
+				if let Some(authorising_user) =
+					membership_content.join_authorized_via_users_server
+				{
+					if membership_content.membership != MembershipState::Join {
+						return Err!(Request(BadJson(
+							"join_authorised_via_users_server is only for member joins"
+						)));
+					}
+
+					if self
+						.services
+						.state_cache
+						.is_joined(state_key, room_id)
+						.await
+					{
+						return Err!(Request(InvalidParam(
+							"{state_key} is already joined, an authorising user is not required."
+						)));
+					}
+
+					if !self.services.globals.user_is_local(&authorising_user) {
+						return Err!(Request(InvalidParam(
+							"Authorising user {authorising_user} does not belong to this \
+							 homeserver"
+						)));
+					}
+
+					if !self
+						.services
+						.state_cache
+						.is_joined(&authorising_user, room_id)
+						.await
+					{
+						return Err!(Request(InvalidParam(
+							"Authorising user {authorising_user} is not in the room, they \
+							 cannot authorise the join."
+						)));
+					}
+				}
+			},
+			| Err(e) => {
+				return Err!(Request(BadJson(
+					"Membership content must have a valid JSON body with at least a valid \
+					 membership state: {e}"
+				)));
+			},
+		},
+		| _ => (),
+	}
+
+	Ok(())
+}
@@ -1,8 +1,10 @@
+mod allowed;
+
 use std::{collections::HashMap, fmt::Write, iter::once, sync::Arc};
 
 use async_trait::async_trait;
 use conduwuit::{
-	PduEvent, Result, err,
+	PduEvent, Result, Server, err,
 	result::FlatOk,
 	state_res::{self, StateMap},
 	utils::{
@@ -25,7 +27,7 @@
 };
 
 use crate::{
-	Dep, globals, rooms,
+	Dep, admin, globals, rooms,
 	rooms::{
 		short::{ShortEventId, ShortStateHash},
 		state_compressor::{CompressedState, parse_compressed_state_event},
@@ -39,6 +41,9 @@ pub struct Service {
 }
 
 struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	alias: Dep<rooms::alias::Service>,
 	globals: Dep<globals::Service>,
 	short: Dep<rooms::short::Service>,
 	spaces: Dep<rooms::spaces::Service>,
@@ -63,6 +68,9 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			mutex: RoomMutexMap::new(),
 			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
 				globals: args.depend::<globals::Service>("globals"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
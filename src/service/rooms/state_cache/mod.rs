@@ -4,7 +4,7 @@
 };
 
 use conduwuit::{
-	Result, is_not_empty,
+	Result, err, is_not_empty,
 	result::LogErr,
 	utils::{ReadyExt, StreamTools, stream::TryIgnore},
 	warn,
@@ -13,7 +13,7 @@
 use futures::{Stream, StreamExt, future::join5, pin_mut, stream::iter};
 use itertools::Itertools;
 use ruma::{
-	OwnedRoomId, OwnedServerName, RoomId, ServerName, UserId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 	events::{
 		AnyStrippedStateEvent, AnySyncStateEvent, GlobalAccountDataEventType,
 		RoomAccountDataEventType, StateEventType,
@@ -28,7 +28,10 @@
 	serde::Raw,
 };
 
-use crate::{Dep, account_data, appservice::RegistrationInfo, config, globals, rooms, users};
+use crate::{
+	Dep, account_data, appservice::RegistrationInfo, config, globals, rooms, users,
+	users::InvitePermission,
+};
 
 pub struct Service {
 	appservice_in_room_cache: AppServiceInRoomCache,
@@ -266,6 +269,15 @@ pub async fn update_membership(
 					return Ok(());
 				}
 
+				// Invites not permitted by the receiver's invite permissions (MSC4155)
+				// never reach them
+				if self.services.globals.user_is_local(user_id)
+					&& self.services.users.invite_permission(sender, user_id).await
+						!= InvitePermission::Allow
+				{
+					return Ok(());
+				}
+
 				self.mark_as_invited(user_id, room_id, last_state, invite_via)
 					.await;
 			},
@@ -694,6 +706,24 @@ pub async fn invite_state(
 			})
 	}
 
+	/// Returns the user who invited `user_id` to `room_id`, according to the
+	/// stripped state of the invite.
+	#[tracing::instrument(skip(self), level = "trace")]
+	pub async fn invite_sender(&self, user_id: &UserId, room_id: &RoomId) -> Result<OwnedUserId> {
+		self.invite_state(user_id, room_id)
+			.await?
+			.iter()
+			.filter(|event| {
+				event.get_field::<StateEventType>("type").ok().flatten()
+					== Some(StateEventType::RoomMember)
+			})
+			.filter(|event| {
+				event.get_field::<&str>("state_key").ok().flatten() == Some(user_id.as_str())
+			})
+			.find_map(|event| event.get_field::<OwnedUserId>("sender").ok().flatten())
+			.ok_or_else(|| err!(Database("No membership event in the invite state")))
+	}
+
 	#[tracing::instrument(skip(self), level = "trace")]
 	pub async fn knock_state(
 		&self,
@@ -10,7 +10,7 @@
 	Result,
 	arrayvec::ArrayVec,
 	at, checked, err, expected, utils,
-	utils::{bytes, math::usize_from_f64, stream::IterStream},
+	utils::{bytes, hash::sha256::Digest, math::usize_from_f64, stream::IterStream},
 };
 use database::Map;
 use futures::{Stream, StreamExt};
@@ -59,6 +59,19 @@ pub struct HashSetCompressStateEvent {
 	pub removed: Arc<CompressedState>,
 }
 
+/// Totals of rebuilding the diff chains of states with `recompress()`, carried
+/// across calls to deduplicate identical states of different rooms.
+#[derive(Default)]
+pub struct Recompressed {
+	pub states: usize,
+	pub deduplicated: usize,
+	pub bytes_before: usize,
+	pub bytes_after: usize,
+
+	/// Hash of each full state rebuilt so far
+	seen: HashMap<Digest, ShortStateHash>,
+}
+
 type StateInfoLruCache = LruCache<ShortStateHash, ShortStateInfoVec>;
 type ShortStateInfoVec = Vec<ShortStateInfo>;
 type ParentStatesVec = Vec<ShortStateInfo>;
@@ -430,6 +443,84 @@ pub async fn save_state(
 		})
 	}
 
+	/// Rebuilds the diff chains of the states of a room, oldest first, as if
+	/// each was saved after the one before it. A state identical to one
+	/// already rebuilt, possibly in another room, is stored as an empty diff to
+	/// it instead. The full states are unchanged.
+	#[tracing::instrument(skip(self, shortstatehashes, totals), level = "debug")]
+	pub async fn recompress(
+		&self,
+		shortstatehashes: &BTreeSet<ShortStateHash>,
+		totals: &mut Recompressed,
+	) -> Result {
+		let mut previous: Option<ShortStateHash> = None;
+		for &shortstatehash in shortstatehashes {
+			let stack = self.load_shortstatehash_info(shortstatehash).await?;
+			let full_state = stack.last().expect("at least one layer").full_state.clone();
+
+			let bytes_before = self.statediff_len(shortstatehash).await?;
+			let state_hash = utils::calculate_hash(full_state.iter().map(|bytes| &bytes[..]));
+			if let Some(&canonical) = totals
+				.seen
+				.get(&state_hash)
+				.filter(|&&canonical| canonical != shortstatehash)
+			{
+				self.save_statediff(shortstatehash, &StateDiff {
+					parent: Some(canonical),
+					added: Arc::default(),
+					removed: Arc::default(),
+				});
+
+				totals.deduplicated = totals.deduplicated.saturating_add(1);
+			} else {
+				let parent_states = match previous {
+					| Some(previous) => self.load_shortstatehash_info(previous).await?,
+					| None => ShortStateInfoVec::new(),
+				};
+
+				let (statediffnew, statediffremoved) = match parent_states.last() {
+					| Some(parent) => (
+						full_state.difference(&parent.full_state).copied().collect(),
+						parent.full_state.difference(&full_state).copied().collect(),
+					),
+					| None => ((*full_state).clone(), CompressedState::new()),
+				};
+
+				self.save_state_from_diff(
+					shortstatehash,
+					Arc::new(statediffnew),
+					Arc::new(statediffremoved),
+					2,
+					parent_states,
+				)?;
+
+				totals.seen.insert(state_hash, shortstatehash);
+			}
+
+			// Later states of the room are layered on the rebuilt chain.
+			self.stateinfo_cache.lock()?.remove(&shortstatehash);
+
+			let bytes_after = self.statediff_len(shortstatehash).await?;
+			totals.states = totals.states.saturating_add(1);
+			totals.bytes_before = totals.bytes_before.saturating_add(bytes_before);
+			totals.bytes_after = totals.bytes_after.saturating_add(bytes_after);
+			previous = Some(shortstatehash);
+		}
+
+		// Cached layers of other states may still follow the old chains.
+		self.stateinfo_cache.lock()?.clear();
+
+		Ok(())
+	}
+
+	async fn statediff_len(&self, shortstatehash: ShortStateHash) -> Result<usize> {
+		self.db
+			.shortstatehash_statediff
+			.get(&shortstatehash.to_be_bytes())
+			.await
+			.map(|value| value.len())
+	}
+
 	#[tracing::instrument(skip(self), level = "debug", name = "get")]
 	async fn get_statediff(&self, shortstatehash: ShortStateHash) -> Result<StateDiff> {
 		const BUFSIZE: usize = size_of::<ShortStateHash>();
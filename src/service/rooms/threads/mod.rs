@@ -1,7 +1,7 @@
 use std::{collections::BTreeMap, sync::Arc};
 
 use conduwuit::{
-	Result, err,
+	Result, err, info,
 	matrix::pdu::{PduCount, PduEvent, PduId, RawPduId},
 	utils::{
 		ReadyExt,
@@ -11,10 +11,14 @@
 use conduwuit_database::{Deserialized, Map};
 use futures::{Stream, StreamExt};
 use ruma::{
-	CanonicalJsonValue, EventId, OwnedUserId, RoomId, UserId,
-	api::client::threads::get_threads::v1::IncludeThreads, events::relation::BundledThread, uint,
+	CanonicalJsonValue, EventId, OwnedUserId, RoomId, UInt, UserId,
+	api::client::threads::get_threads::v1::IncludeThreads, events::relation::BundledThread,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{
+	json,
+	value::{RawValue as RawJsonValue, Value as JsonValue, to_raw_value},
 };
-use serde_json::json;
 
 use crate::{Dep, rooms, rooms::short::ShortRoomId};
 
@@ -30,6 +34,19 @@ struct Services {
 
 pub(super) struct Data {
 	threadid_userids: Arc<Map>,
+	threadid_summary: Arc<Map>,
+	threadactivity_threadid: Arc<Map>,
+}
+
+/// Aggregation of a thread, maintained as replies are persisted.
+#[derive(Default, Deserialize, Serialize)]
+struct ThreadSummary {
+	/// Number of replies in the thread
+	count: u64,
+
+	/// Count of the latest reply, under which the thread is listed in the
+	/// activity index
+	latest: Option<u64>,
 }
 
 impl crate::Service for Service {
@@ -37,6 +54,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
 				threadid_userids: args.db["threadid_userids"].clone(),
+				threadid_summary: args.db["threadid_summary"].clone(),
+				threadactivity_threadid: args.db["threadactivity_threadid"].clone(),
 			},
 			services: Services {
 				short: args.depend::<rooms::short::Service>("rooms::short"),
@@ -49,7 +68,15 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 impl Service {
-	pub async fn add_to_thread(&self, root_event_id: &EventId, pdu: &PduEvent) -> Result<()> {
+	/// Record a reply to a thread: update the summary bundled with the thread
+	/// root, its participants and its position in the activity index of the
+	/// room.
+	pub async fn add_to_thread(
+		&self,
+		root_event_id: &EventId,
+		pdu: &PduEvent,
+		pdu_id: &RawPduId,
+	) -> Result<()> {
 		let root_id = self
 			.services
 			.timeline
@@ -73,46 +100,30 @@ pub async fn add_to_thread(&self, root_event_id: &EventId, pdu: &PduEvent) -> Re
 			.await
 			.map_err(|e| err!(Request(InvalidParam("Thread root pdu not found: {e:?}"))))?;
 
+		let mut summary = self.get_summary(&root_id).await.unwrap_or_default();
+		summary.count = summary.count.saturating_add(1);
+		self.set_latest(&root_id, &mut summary, pdu_id);
+		self.put_summary(&root_id, &summary)?;
+
 		if let CanonicalJsonValue::Object(unsigned) = root_pdu_json
 			.entry("unsigned".to_owned())
 			.or_insert_with(|| CanonicalJsonValue::Object(BTreeMap::default()))
 		{
-			if let Some(mut relations) = unsigned
-				.get("m.relations")
-				.and_then(|r| r.as_object())
-				.and_then(|r| r.get("m.thread"))
-				.and_then(|relations| {
-					serde_json::from_value::<BundledThread>(relations.clone().into()).ok()
-				}) {
-				// Thread already existed
-				relations.count = relations.count.saturating_add(uint!(1));
-				relations.latest_event = pdu.to_message_like_event();
-
-				let content = serde_json::to_value(relations).expect("to_value always works");
-
-				unsigned.insert(
-					"m.relations".to_owned(),
-					json!({ "m.thread": content })
-						.try_into()
-						.expect("thread is valid json"),
-				);
-			} else {
-				// New thread
-				let relations = BundledThread {
-					latest_event: pdu.to_message_like_event(),
-					count: uint!(1),
-					current_user_participated: true,
-				};
-
-				let content = serde_json::to_value(relations).expect("to_value always works");
-
-				unsigned.insert(
-					"m.relations".to_owned(),
-					json!({ "m.thread": content })
-						.try_into()
-						.expect("thread is valid json"),
-				);
-			}
+			// Participation is set for the requesting user when the thread is listed
+			let relations = BundledThread {
+				latest_event: pdu.to_message_like_event(),
+				count: UInt::new_saturating(summary.count),
+				current_user_participated: true,
+			};
+
+			let content = serde_json::to_value(relations).expect("to_value always works");
+
+			unsigned.insert(
+				"m.relations".to_owned(),
+				json!({ "m.thread": content })
+					.try_into()
+					.expect("thread is valid json"),
+			);
 
 			self.services
 				.timeline
@@ -129,17 +140,23 @@ pub async fn add_to_thread(&self, root_event_id: &EventId, pdu: &PduEvent) -> Re
 				users.push(root_pdu.sender);
 			},
 		}
-		users.push(pdu.sender.clone());
+
+		if !users.contains(&pdu.sender) {
+			users.push(pdu.sender.clone());
+		}
 
 		self.update_participants(&root_id, &users)
 	}
 
+	/// Threads of a room ordered by their latest reply, most recently active
+	/// first, starting before the reply with count `shorteventid`. The count
+	/// of each thread's latest reply serves as pagination token.
 	pub async fn threads_until<'a>(
 		&'a self,
 		user_id: &'a UserId,
 		room_id: &'a RoomId,
 		shorteventid: PduCount,
-		_inc: &'a IncludeThreads,
+		include: &'a IncludeThreads,
 	) -> Result<impl Stream<Item = (PduCount, PduEvent)> + Send + 'a> {
 		let shortroomid: ShortRoomId = self.services.short.get_shortroomid(room_id).await?;
 
@@ -151,25 +168,143 @@ pub async fn threads_until<'a>(
 
 		let stream = self
 			.db
-			.threadid_userids
-			.rev_raw_keys_from(&current)
+			.threadactivity_threadid
+			.rev_raw_stream_from(&current)
 			.ignore_err()
-			.map(RawPduId::from)
-			.ready_take_while(move |pdu_id| pdu_id.shortroomid() == shortroomid.to_be_bytes())
-			.wide_filter_map(move |pdu_id| async move {
-				let mut pdu = self.services.timeline.get_pdu_from_id(&pdu_id).await.ok()?;
-				let pdu_id: PduId = pdu_id.into();
+			.map(|(latest, root_id)| (RawPduId::from(latest), RawPduId::from(root_id)))
+			.ready_take_while(move |(latest, _)| {
+				latest.shortroomid() == shortroomid.to_be_bytes()
+			})
+			.wide_filter_map(move |(latest, root_id)| async move {
+				let participated = self.participated(&root_id, user_id).await;
+				if matches!(include, IncludeThreads::Participated) && !participated {
+					return None;
+				}
+
+				let mut pdu = self
+					.services
+					.timeline
+					.get_pdu_from_id(&root_id)
+					.await
+					.ok()?;
 
 				if pdu.sender != user_id {
 					pdu.remove_transaction_id().ok();
 				}
 
-				Some((pdu_id.shorteventid, pdu))
+				set_participated(&mut pdu, participated).ok();
+
+				Some((latest.pdu_count(), pdu))
 			});
 
 		Ok(stream)
 	}
 
+	/// Build the summaries and the activity index of threads which predate
+	/// them from the summaries bundled with their roots.
+	pub async fn index_existing_threads(&self) -> Result {
+		let root_ids: Vec<RawPduId> = self
+			.db
+			.threadid_userids
+			.raw_keys()
+			.ignore_err()
+			.map(RawPduId::from)
+			.collect()
+			.await;
+
+		let mut indexed: usize = 0;
+		for root_id in &root_ids {
+			if self.get_summary(root_id).await.is_ok() {
+				continue;
+			}
+
+			let Ok(root_pdu) = self.services.timeline.get_pdu_from_id(root_id).await else {
+				continue;
+			};
+
+			let bundled: Option<BundledThread> = root_pdu
+				.get_unsigned_property::<JsonValue>("m.relations")
+				.ok()
+				.and_then(|relations| relations.get("m.thread").cloned())
+				.and_then(|thread| serde_json::from_value(thread).ok());
+
+			let latest_id = match &bundled {
+				| Some(bundled) => {
+					let latest_event_id = bundled
+						.latest_event
+						.get_field::<String>("event_id")
+						.ok()
+						.flatten()
+						.and_then(|event_id| EventId::parse(event_id).ok());
+
+					match latest_event_id {
+						| Some(event_id) =>
+							self.services.timeline.get_pdu_id(&event_id).await.ok(),
+						| None => None,
+					}
+				},
+				| None => None,
+			};
+
+			let mut summary = ThreadSummary {
+				count: bundled.map_or(0, |bundled| bundled.count.into()),
+				latest: None,
+			};
+
+			self.set_latest(root_id, &mut summary, &latest_id.unwrap_or(*root_id));
+			self.put_summary(root_id, &summary)?;
+			indexed = indexed.saturating_add(1);
+		}
+
+		info!(?indexed, "Indexed the activity of existing threads.");
+
+		Ok(())
+	}
+
+	/// Move the thread to the position of its latest reply in the activity
+	/// index.
+	fn set_latest(&self, root_id: &RawPduId, summary: &mut ThreadSummary, latest: &RawPduId) {
+		let root: PduId = (*root_id).into();
+		if let Some(previous) = summary.latest {
+			let previous: RawPduId = PduId {
+				shortroomid: root.shortroomid,
+				shorteventid: PduCount::Normal(previous),
+			}
+			.into();
+
+			self.db.threadactivity_threadid.remove(&previous);
+		}
+
+		let latest_id: PduId = (*latest).into();
+		summary.latest = Some(latest_id.shorteventid.into_unsigned());
+		self.db.threadactivity_threadid.insert(latest, root_id);
+	}
+
+	async fn get_summary(&self, root_id: &RawPduId) -> Result<ThreadSummary> {
+		self.db
+			.threadid_summary
+			.get(root_id)
+			.await
+			.and_then(|value| {
+				serde_json::from_slice(&value)
+					.map_err(|e| err!(Database("Invalid thread summary: {e}")))
+			})
+	}
+
+	fn put_summary(&self, root_id: &RawPduId, summary: &ThreadSummary) -> Result {
+		self.db
+			.threadid_summary
+			.insert(root_id, serde_json::to_vec(summary)?);
+
+		Ok(())
+	}
+
+	async fn participated(&self, root_id: &RawPduId, user_id: &UserId) -> bool {
+		self.get_participants(root_id)
+			.await
+			.is_ok_and(|users| users.iter().any(|user| user == user_id))
+	}
+
 	pub(super) fn update_participants(
 		&self,
 		root_id: &RawPduId,
@@ -190,3 +325,26 @@ pub(super) async fn get_participants(&self, root_id: &RawPduId) -> Result<Vec<Ow
 		self.db.threadid_userids.get(root_id).await.deserialized()
 	}
 }
+
+/// Set whether the requesting user participated in the thread in the summary
+/// bundled with its root.
+fn set_participated(pdu: &mut PduEvent, participated: bool) -> Result {
+	use serde_json::Map;
+
+	let Some(unsigned) = pdu.unsigned.as_deref().map(RawJsonValue::get) else {
+		return Ok(());
+	};
+
+	let mut unsigned: Map<String, JsonValue> = serde_json::from_str(unsigned)?;
+	if let Some(thread) = unsigned
+		.get_mut("m.relations")
+		.and_then(|relations| relations.get_mut("m.thread"))
+		.and_then(JsonValue::as_object_mut)
+	{
+		thread.insert("current_user_participated".to_owned(), participated.into());
+	}
+
+	pdu.unsigned = Some(to_raw_value(&unsigned)?);
+
+	Ok(())
+}
@@ -2,13 +2,19 @@
 
 use conduwuit::{
 	Err, PduCount, PduEvent, Result, at, err,
+	matrix::pdu::BorrowedPdu,
 	result::{LogErr, NotFound},
 	utils,
 	utils::stream::TryReadyExt,
 };
-use database::{Database, Deserialized, Json, KeyVal, Map};
-use futures::{FutureExt, Stream, TryFutureExt, TryStreamExt, future::select_ok, pin_mut};
+use database::{Batch, Database, Deserialized, Json, KeyVal, Map};
+use futures::{
+	FutureExt, Stream, TryFutureExt, TryStreamExt,
+	future::{Either, select_ok},
+	pin_mut,
+};
 use ruma::{CanonicalJsonObject, EventId, OwnedUserId, RoomId, UserId, api::Direction};
+use serde::Deserialize;
 
 use super::{PduId, RawPduId};
 use crate::{Dep, rooms, rooms::short::ShortRoomId};
@@ -24,6 +30,7 @@ pub(super) struct Data {
 }
 
 struct Services {
+	archive: Dep<rooms::archive::Service>,
 	short: Dep<rooms::short::Service>,
 }
 
@@ -40,6 +47,7 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 			userroomid_notificationcount: db["userroomid_notificationcount"].clone(),
 			db: args.db.clone(),
 			services: Services {
+				archive: args.depend::<rooms::archive::Service>("rooms::archive"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 			},
 		}
@@ -106,7 +114,7 @@ pub(super) async fn get_non_outlier_pdu_json(
 	) -> Result<CanonicalJsonObject> {
 		let pduid = self.get_pdu_id(event_id).await?;
 
-		self.pduid_pdu.get(&pduid).await.deserialized()
+		self.get_pdu_value(&pduid).await
 	}
 
 	/// Returns the pdu's id.
@@ -122,7 +130,7 @@ pub(super) async fn get_pdu_id(&self, event_id: &EventId) -> Result<RawPduId> {
 	pub(super) async fn get_non_outlier_pdu(&self, event_id: &EventId) -> Result<PduEvent> {
 		let pduid = self.get_pdu_id(event_id).await?;
 
-		self.pduid_pdu.get(&pduid).await.deserialized()
+		self.get_pdu_value(&pduid).await
 	}
 
 	/// Like get_non_outlier_pdu(), but without the expense of fetching and
@@ -166,7 +174,7 @@ pub(super) async fn pdu_exists(&self, event_id: &EventId) -> Result {
 	///
 	/// This does __NOT__ check the outliers `Tree`.
 	pub(super) async fn get_pdu_from_id(&self, pdu_id: &RawPduId) -> Result<PduEvent> {
-		self.pduid_pdu.get(pdu_id).await.deserialized()
+		self.get_pdu_value(pdu_id).await
 	}
 
 	/// Returns the pdu as a `BTreeMap<String, CanonicalJsonValue>`.
@@ -174,11 +182,27 @@ pub(super) async fn get_pdu_json_from_id(
 		&self,
 		pdu_id: &RawPduId,
 	) -> Result<CanonicalJsonObject> {
-		self.pduid_pdu.get(pdu_id).await.deserialized()
+		self.get_pdu_value(pdu_id).await
+	}
+
+	/// Returns the pdu from `pduid_pdu`, reading it from the archive if it was
+	/// archived.
+	async fn get_pdu_value<T>(&self, pdu_id: &RawPduId) -> Result<T>
+	where
+		T: for<'de> Deserialize<'de>,
+	{
+		let handle = self.pduid_pdu.get(pdu_id).await?;
+		if !rooms::archive::is_archived(&handle) {
+			return (&handle).deserialized();
+		}
+
+		let json = self.services.archive.rehydrate(&handle).await?;
+		serde_json::from_slice(&json).map_err(Into::into)
 	}
 
 	pub(super) async fn append_pdu(
 		&self,
+		batch: &mut Batch,
 		pdu_id: &RawPduId,
 		pdu: &PduEvent,
 		json: &CanonicalJsonObject,
@@ -186,20 +210,21 @@ pub(super) async fn append_pdu(
 	) {
 		debug_assert!(matches!(count, PduCount::Normal(_)), "PduCount not Normal");
 
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(pdu.event_id.as_bytes(), pdu_id);
-		self.eventid_outlierpdu.remove(pdu.event_id.as_bytes());
+		batch.raw_put(&self.pduid_pdu, pdu_id, Json(json));
+		batch.insert(&self.eventid_pduid, pdu.event_id.as_bytes(), pdu_id);
+		batch.remove(&self.eventid_outlierpdu, pdu.event_id.as_bytes());
 	}
 
 	pub(super) fn prepend_backfill_pdu(
 		&self,
+		batch: &mut Batch,
 		pdu_id: &RawPduId,
 		event_id: &EventId,
 		json: &CanonicalJsonObject,
 	) {
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(event_id, pdu_id);
-		self.eventid_outlierpdu.remove(event_id);
+		batch.raw_put(&self.pduid_pdu, pdu_id, Json(json));
+		batch.insert(&self.eventid_pduid, event_id, pdu_id);
+		batch.remove(&self.eventid_outlierpdu, event_id);
 	}
 
 	/// Removes a pdu and creates a new one with the same id.
@@ -234,6 +259,7 @@ pub(super) fn pdus_rev<'a>(
 					.rev_raw_stream_from(&current)
 					.ready_try_take_while(move |(key, _)| Ok(key.starts_with(&prefix)))
 					.ready_and_then(move |item| Self::each_pdu(item, user_id))
+					.and_then(move |item| self.rehydrate_pdu(item, user_id))
 			})
 			.try_flatten_stream()
 	}
@@ -251,13 +277,101 @@ pub(super) fn pdus<'a>(
 					.raw_stream_from(&current)
 					.ready_try_take_while(move |(key, _)| Ok(key.starts_with(&prefix)))
 					.ready_and_then(move |item| Self::each_pdu(item, user_id))
+					.and_then(move |item| self.rehydrate_pdu(item, user_id))
 			})
 			.try_flatten_stream()
 	}
 
-	fn each_pdu((pdu_id, pdu): KeyVal<'_>, user_id: Option<&UserId>) -> Result<PdusIterItem> {
+	/// Forward iteration over the PDUs accepted by `filter`, which is given
+	/// the fields borrowed from each PDU's JSON; rejected PDUs are never fully
+	/// deserialized.
+	pub(super) fn pdus_filtered<'a, F>(
+		&'a self,
+		user_id: Option<&'a UserId>,
+		room_id: &'a RoomId,
+		from: PduCount,
+		filter: F,
+	) -> impl Stream<Item = Result<PdusIterItem>> + Send + 'a
+	where
+		F: Fn(&BorrowedPdu<'_>) -> bool + Clone + Send + Sync + 'a,
+	{
+		self.count_to_id(room_id, from, Direction::Forward)
+			.map_ok(move |current| {
+				let prefix = current.shortroomid();
+				let archived_filter = filter.clone();
+				self.pduid_pdu
+					.raw_stream_from(&current)
+					.ready_try_take_while(move |(key, _)| Ok(key.starts_with(&prefix)))
+					.ready_try_filter_map(move |(pdu_id, pdu)| {
+						let pdu_id: RawPduId = pdu_id.into();
+						if rooms::archive::is_archived(pdu) {
+							return Ok(Some(Either::Right((pdu_id, pdu.to_vec()))));
+						}
+
+						if !filter(&BorrowedPdu::from_slice(pdu)?) {
+							return Ok(None);
+						}
+
+						Self::parse_pdu(pdu_id, pdu, user_id).map(|item| Some(Either::Left(item)))
+					})
+					.try_filter_map(move |item| {
+						self.rehydrate_pdu_filtered(item, user_id, archived_filter.clone())
+					})
+			})
+			.try_flatten_stream()
+	}
+
+	/// Parses an item of `pduid_pdu`, or passes it on to be read from the
+	/// archive if it was archived.
+	fn each_pdu(
+		(pdu_id, pdu): KeyVal<'_>,
+		user_id: Option<&UserId>,
+	) -> Result<Either<PdusIterItem, (RawPduId, Vec<u8>)>> {
 		let pdu_id: RawPduId = pdu_id.into();
+		if rooms::archive::is_archived(pdu) {
+			return Ok(Either::Right((pdu_id, pdu.to_vec())));
+		}
+
+		Self::parse_pdu(pdu_id, pdu, user_id).map(Either::Left)
+	}
+
+	async fn rehydrate_pdu(
+		&self,
+		item: Either<PdusIterItem, (RawPduId, Vec<u8>)>,
+		user_id: Option<&UserId>,
+	) -> Result<PdusIterItem> {
+		match item {
+			| Either::Left(item) => Ok(item),
+			| Either::Right((pdu_id, stub)) => {
+				let json = self.services.archive.rehydrate(&stub).await?;
+				Self::parse_pdu(pdu_id, &json, user_id)
+			},
+		}
+	}
+
+	async fn rehydrate_pdu_filtered<F>(
+		&self,
+		item: Either<PdusIterItem, (RawPduId, Vec<u8>)>,
+		user_id: Option<&UserId>,
+		filter: F,
+	) -> Result<Option<PdusIterItem>>
+	where
+		F: Fn(&BorrowedPdu<'_>) -> bool,
+	{
+		match item {
+			| Either::Left(item) => Ok(Some(item)),
+			| Either::Right((pdu_id, stub)) => {
+				let json = self.services.archive.rehydrate(&stub).await?;
+				if !filter(&BorrowedPdu::from_slice(&json)?) {
+					return Ok(None);
+				}
+
+				Self::parse_pdu(pdu_id, &json, user_id).map(Some)
+			},
+		}
+	}
 
+	fn parse_pdu(pdu_id: RawPduId, pdu: &[u8], user_id: Option<&UserId>) -> Result<PdusIterItem> {
 		let mut pdu = serde_json::from_slice::<PduEvent>(pdu)?;
 
 		if Some(pdu.sender.borrow()) != user_id {
@@ -271,24 +385,23 @@ fn each_pdu((pdu_id, pdu): KeyVal<'_>, user_id: Option<&UserId>) -> Result<PdusI
 
 	pub(super) fn increment_notification_counts(
 		&self,
+		batch: &mut Batch,
 		room_id: &RoomId,
 		notifies: Vec<OwnedUserId>,
 		highlights: Vec<OwnedUserId>,
 	) {
-		let _cork = self.db.cork();
-
 		for user in notifies {
 			let mut userroom_id = user.as_bytes().to_vec();
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
-			increment(&self.userroomid_notificationcount, &userroom_id);
+			increment(batch, &self.userroomid_notificationcount, &userroom_id);
 		}
 
 		for user in highlights {
 			let mut userroom_id = user.as_bytes().to_vec();
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
-			increment(&self.userroomid_highlightcount, &userroom_id);
+			increment(batch, &self.userroomid_highlightcount, &userroom_id);
 		}
 	}
 
@@ -316,8 +429,8 @@ async fn count_to_id(
 }
 
 //TODO: this is an ABA
-fn increment(db: &Arc<Map>, key: &[u8]) {
+fn increment(batch: &mut Batch, db: &Arc<Map>, key: &[u8]) {
 	let old = db.get_blocking(key);
 	let new = utils::increment(old.ok().as_deref());
-	db.insert(key, new);
+	batch.insert(db, key, new);
 }
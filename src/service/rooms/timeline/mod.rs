@@ -15,7 +15,7 @@
 	Err, Error, Result, Server, at, debug, debug_warn, err, error, implement, info,
 	matrix::{
 		Event,
-		pdu::{EventHash, PduBuilder, PduCount, PduEvent, gen_event_id},
+		pdu::{BorrowedPdu, EventHash, PduBuilder, PduCount, PduEvent, gen_event_id},
 		state_res::{self, RoomVersion},
 	},
 	utils::{
@@ -23,6 +23,7 @@
 	},
 	validated, warn,
 };
+use database::Batch;
 use futures::{
 	Future, FutureExt, Stream, StreamExt, TryStreamExt, future, future::ready, pin_mut,
 };
@@ -53,7 +54,7 @@
 use crate::{
 	Dep, account_data, admin, appservice,
 	appservice::NamespaceRegex,
-	globals, pusher, rooms,
+	globals, media, pusher, rooms,
 	rooms::{short::ShortRoomId, state_compressor::CompressedState},
 	sending, server_keys, users,
 };
@@ -93,6 +94,8 @@ struct Services {
 	admin: Dep<admin::Service>,
 	alias: Dep<rooms::alias::Service>,
 	globals: Dep<globals::Service>,
+	media: Dep<media::Service>,
+	metadata: Dep<rooms::metadata::Service>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
@@ -108,6 +111,7 @@ struct Services {
 	search: Dep<rooms::search::Service>,
 	spaces: Dep<rooms::spaces::Service>,
 	event_handler: Dep<rooms::event_handler::Service>,
+	unredacted: Dep<rooms::unredacted::Service>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
@@ -124,6 +128,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				admin: args.depend::<admin::Service>("admin"),
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
 				globals: args.depend::<globals::Service>("globals"),
+				media: args.depend::<media::Service>("media"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
@@ -141,6 +147,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
 				event_handler: args
 					.depend::<rooms::event_handler::Service>("rooms::event_handler"),
+				unredacted: args.depend::<rooms::unredacted::Service>("rooms::unredacted"),
 			},
 			db: Data::new(&args),
 			mutex_insert: RoomMutexMap::new(),
@@ -347,26 +354,6 @@ pub async fn append_pdu<'a, Leafs>(
 			.set_forward_extremities(&pdu.room_id, leafs, state_lock)
 			.await;
 
-		let insert_lock = self.mutex_insert.lock(&pdu.room_id).await;
-
-		let count1 = self.services.globals.next_count().unwrap();
-		// Mark as read first so the sending client doesn't get a notification even if
-		// appending fails
-		self.services
-			.read_receipt
-			.private_read_set(&pdu.room_id, &pdu.sender, count1);
-		self.services
-			.user
-			.reset_notification_counts(&pdu.sender, &pdu.room_id);
-
-		let count2 = PduCount::Normal(self.services.globals.next_count().unwrap());
-		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count2 }.into();
-
-		// Insert pdu
-		self.db.append_pdu(&pdu_id, pdu, &pdu_json, count2).await;
-
-		drop(insert_lock);
-
 		// See if the event matches any known pushers via power level
 		let power_levels: RoomPowerLevelsEventContent = self
 			.services
@@ -390,6 +377,7 @@ pub async fn append_pdu<'a, Leafs>(
 
 		let mut notifies = Vec::with_capacity(push_target.len().saturating_add(1));
 		let mut highlights = Vec::with_capacity(push_target.len().saturating_add(1));
+		let mut push_keys = Vec::new();
 
 		if pdu.kind == TimelineEventType::RoomMember {
 			if let Some(state_key) = &pdu.state_key {
@@ -446,17 +434,86 @@ pub async fn append_pdu<'a, Leafs>(
 			self.services
 				.pusher
 				.get_pushkeys(user)
-				.ready_for_each(|push_key| {
-					self.services
-						.sending
-						.send_pdu_push(&pdu_id, user, push_key.to_owned())
-						.expect("TODO: replace with future");
-				})
+				.ready_for_each(|push_key| push_keys.push((user, push_key.to_owned())))
 				.await;
 		}
 
+		let body = match pdu.kind {
+			| TimelineEventType::RoomMessage => pdu
+				.get_content::<ExtractBody>()
+				.ok()
+				.and_then(|content| content.body),
+			| _ => None,
+		};
+
+		let mut related = Vec::new();
+		if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
+			if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
+				related.push(related_pducount);
+			}
+		}
+
+		if let Ok(content) = pdu.get_content::<ExtractRelatesTo>() {
+			// We need to do it again here, because replies don't have
+			// event_id as a top level field
+			if let Relation::Reply { in_reply_to } = content.relates_to {
+				if let Ok(related_pducount) = self.get_pdu_count(&in_reply_to.event_id).await {
+					related.push(related_pducount);
+				}
+			}
+		}
+
+		// Everything written for the event itself goes into one batch, which is
+		// written before the insert lock is released so the event and its
+		// relations, search tokens and notification counts appear together.
+		let insert_lock = self.mutex_insert.lock(&pdu.room_id).await;
+		let mut batch = Batch::default();
+
+		let count1 = self.services.globals.next_count().unwrap();
+		// Mark as read first so the sending client doesn't get a notification even if
+		// appending fails
+		self.services.read_receipt.private_read_set(
+			&mut batch,
+			&pdu.room_id,
+			&pdu.sender,
+			count1,
+		);
+		self.services
+			.user
+			.reset_notification_counts(&mut batch, &pdu.sender, &pdu.room_id);
+
+		let count2 = PduCount::Normal(self.services.globals.next_count().unwrap());
+		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count2 }.into();
+
+		// Insert pdu
 		self.db
-			.increment_notification_counts(&pdu.room_id, notifies, highlights);
+			.append_pdu(&mut batch, &pdu_id, pdu, &pdu_json, count2)
+			.await;
+
+		self.db
+			.increment_notification_counts(&mut batch, &pdu.room_id, notifies, highlights);
+
+		if let Some(body) = &body {
+			self.services
+				.search
+				.index_pdu(&mut batch, shortroomid, &pdu_id, body);
+		}
+
+		for related_pducount in related {
+			self.services
+				.pdu_metadata
+				.add_relation(&mut batch, count2, related_pducount);
+		}
+
+		batch.write();
+		drop(insert_lock);
+
+		for (user, push_key) in push_keys {
+			self.services
+				.sending
+				.send_pdu_push(&pdu_id, user, push_key)
+				.expect("TODO: replace with future");
+		}
 
 		match pdu.kind {
 			| TimelineEventType::RoomRedaction => {
@@ -530,48 +587,35 @@ pub async fn append_pdu<'a, Leafs>(
 						.await?;
 				}
 			},
-			| TimelineEventType::RoomMessage => {
-				let content: ExtractBody = pdu.get_content()?;
-				if let Some(body) = content.body {
-					self.services.search.index_pdu(shortroomid, &pdu_id, &body);
-
+			| TimelineEventType::RoomMessage =>
+				if let Some(body) = body {
 					if self.services.admin.is_admin_command(pdu, &body).await {
-						self.services
-							.admin
-							.command(body, Some((*pdu.event_id).into()))?;
+						self.services.admin.command(
+							body,
+							Some((*pdu.event_id).into()),
+							pdu.sender.clone(),
+						)?;
 					}
-				}
-			},
+				},
 			| _ => {},
 		}
 
-		if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
-			if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
-				self.services
-					.pdu_metadata
-					.add_relation(count2, related_pducount);
-			}
+		if matches!(
+			pdu.kind,
+			TimelineEventType::RoomMessage
+				| TimelineEventType::Sticker
+				| TimelineEventType::RoomAvatar
+				| TimelineEventType::RoomMember
+		) {
+			self.services.media.reference_event_media(pdu).await;
 		}
 
 		if let Ok(content) = pdu.get_content::<ExtractRelatesTo>() {
-			match content.relates_to {
-				| Relation::Reply { in_reply_to } => {
-					// We need to do it again here, because replies don't have
-					// event_id as a top level field
-					if let Ok(related_pducount) = self.get_pdu_count(&in_reply_to.event_id).await
-					{
-						self.services
-							.pdu_metadata
-							.add_relation(count2, related_pducount);
-					}
-				},
-				| Relation::Thread(thread) => {
-					self.services
-						.threads
-						.add_to_thread(&thread.event_id, pdu)
-						.await?;
-				},
-				| _ => {}, // TODO: Aggregate other types
+			if let Relation::Thread(thread) = content.relates_to {
+				self.services
+					.threads
+					.add_to_thread(&thread.event_id, pdu, &pdu_id)
+					.await?;
 			}
 		}
 
@@ -838,6 +882,10 @@ pub async fn build_and_append_pdu(
 			self.check_pdu_for_admin_room(&pdu, sender).boxed().await?;
 		}
 
+		if self.services.metadata.is_quarantined(&pdu.room_id).await {
+			self.check_pdu_for_quarantined_room(&pdu, sender).await?;
+		}
+
 		// If redaction event is not authorized, do not append it to the timeline
 		if pdu.kind == TimelineEventType::RoomRedaction {
 			use RoomVersionId::*;
@@ -1026,6 +1074,24 @@ pub fn pdus<'a>(
 			.pdus(user_id, room_id, from.unwrap_or_else(PduCount::min))
 	}
 
+	/// Forward iteration starting at from over the PDUs accepted by the
+	/// filter, which only reads the fields it is given from each PDU's JSON.
+	/// Prefer this to filtering `pdus()` when most PDUs are rejected.
+	#[tracing::instrument(skip(self, filter), level = "debug")]
+	pub fn pdus_filtered<'a, F>(
+		&'a self,
+		user_id: Option<&'a UserId>,
+		room_id: &'a RoomId,
+		from: Option<PduCount>,
+		filter: F,
+	) -> impl Stream<Item = Result<PdusIterItem>> + Send + 'a
+	where
+		F: Fn(&BorrowedPdu<'_>) -> bool + Clone + Send + Sync + 'a,
+	{
+		self.db
+			.pdus_filtered(user_id, room_id, from.unwrap_or_else(PduCount::min), filter)
+	}
+
 	/// Replace a PDU with the redacted form.
 	#[tracing::instrument(name = "redact", level = "debug", skip(self))]
 	pub async fn redact_pdu(
@@ -1052,6 +1118,11 @@ pub async fn redact_pdu(
 			}
 		}
 
+		if self.services.unredacted.enabled() && !pdu.is_redacted() {
+			let original = self.get_pdu_json_from_id(&pdu_id).await?;
+			self.services.unredacted.retain(event_id, original)?;
+		}
+
 		let room_version_id = self.services.state.get_room_version(&pdu.room_id).await?;
 
 		pdu.redact(&room_version_id, reason)?;
@@ -1216,16 +1287,21 @@ pub async fn backfill_pdu(&self, origin: &ServerName, pdu: Box<RawJsonValue>) ->
 		.into();
 
 		// Insert pdu
-		self.db.prepend_backfill_pdu(&pdu_id, &event_id, &value);
-
-		drop(insert_lock);
+		let mut batch = Batch::default();
+		self.db
+			.prepend_backfill_pdu(&mut batch, &pdu_id, &event_id, &value);
 
 		if pdu.kind == TimelineEventType::RoomMessage {
 			let content: ExtractBody = pdu.get_content()?;
 			if let Some(body) = content.body {
-				self.services.search.index_pdu(shortroomid, &pdu_id, &body);
+				self.services
+					.search
+					.index_pdu(&mut batch, shortroomid, &pdu_id, &body);
 			}
 		}
+
+		batch.write();
+		drop(insert_lock);
 		drop(mutex_lock);
 
 		debug!("Prepended backfill pdu");
@@ -1233,6 +1309,35 @@ pub async fn backfill_pdu(&self, origin: &ServerName, pdu: Box<RawJsonValue>) ->
 	}
 }
 
+/// Local users may only leave quarantined rooms, while admins may still
+/// redact events in them to clean them up.
+#[implement(Service)]
+async fn check_pdu_for_quarantined_room(&self, pdu: &PduEvent, sender: &UserId) -> Result<()> {
+	if sender == self.services.globals.server_user {
+		return Ok(());
+	}
+
+	if pdu.kind == TimelineEventType::RoomRedaction && self.services.users.is_admin(sender).await
+	{
+		return Ok(());
+	}
+
+	let leaving = pdu.kind == TimelineEventType::RoomMember
+		&& pdu.state_key.as_deref() == Some(sender.as_str())
+		&& pdu
+			.get_content::<RoomMemberEventContent>()
+			.is_ok_and(|content| content.membership == MembershipState::Leave);
+
+	if !leaving {
+		return Err!(Request(Forbidden(
+			"This room is quarantined by the server administrators; you cannot join or send \
+			 events to it."
+		)));
+	}
+
+	Ok(())
+}
+
 #[implement(Service)]
 #[tracing::instrument(skip_all, level = "debug")]
 async fn check_pdu_for_admin_room(&self, pdu: &PduEvent, sender: &UserId) -> Result<()> {
@@ -167,6 +167,33 @@ async fn typings_maintain(&self, room_id: &RoomId) -> Result<()> {
 		Ok(())
 	}
 
+	/// Clears typing notifications whose timeout passed before the timestamp
+	/// `before`, and rooms nobody is typing in anymore. Returns the number of
+	/// notifications cleared.
+	pub async fn prune(&self, before: u64) -> Result<usize> {
+		let expired: Vec<(OwnedRoomId, usize)> = self
+			.typing
+			.read()
+			.await
+			.iter()
+			.map(|(room_id, room)| {
+				let expired = room.values().filter(|&&timeout| timeout < before).count();
+				(room_id.clone(), expired)
+			})
+			.filter(|&(_, expired)| expired > 0)
+			.collect();
+
+		let mut pruned: usize = 0;
+		for (room_id, expired) in expired {
+			self.typings_maintain(&room_id).await?;
+			pruned = pruned.saturating_add(expired);
+		}
+
+		self.typing.write().await.retain(|_, room| !room.is_empty());
+
+		Ok(pruned)
+	}
+
 	/// Returns the count of the last typing update in this room.
 	pub async fn last_typing_update(&self, room_id: &RoomId) -> Result<u64> {
 		self.typings_maintain(room_id).await?;
@@ -0,0 +1,179 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug, err, implement,
+	utils::{self, stream::TryIgnore},
+	warn,
+};
+use database::Map;
+use futures::StreamExt;
+use ruma::{CanonicalJsonObject, EventId};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, maintenance};
+
+/// Retains the original content of redacted events for a while, so room
+/// moderators can still review it (MSC2815).
+pub struct Service {
+	interrupt: Notify,
+	server: Arc<Server>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	maintenance: Dep<maintenance::Service>,
+}
+
+struct Data {
+	eventid_unredactedpdu: Arc<Map>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct UnredactedPdu {
+	/// Time the event was redacted at, in milliseconds since the unix epoch
+	redacted_at: u64,
+	pdu: CanonicalJsonObject,
+}
+
+/// Maximum interval in which expired originals are scrubbed
+const SCRUB_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			server: args.server.clone(),
+			services: Services {
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
+			},
+			db: Data {
+				eventid_unredactedpdu: args.db["eventid_unredactedpdu"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let retention = self.retention().unwrap_or(SCRUB_INTERVAL);
+		let mut i = interval(retention.min(SCRUB_INTERVAL));
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.services.maintenance.defer("scrub redacted content") => (),
+			}
+
+			self.scrub().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether the original content of redacted events is retained.
+#[implement(Service)]
+#[must_use]
+pub fn enabled(&self) -> bool { self.retention().is_some() }
+
+#[implement(Service)]
+fn retention(&self) -> Option<Duration> {
+	let retention = self.server.config.redacted_content_retention;
+	(retention > 0).then(|| Duration::from_secs(retention))
+}
+
+/// Keep the original of an event which is being redacted.
+#[implement(Service)]
+pub fn retain(&self, event_id: &EventId, pdu: CanonicalJsonObject) -> Result {
+	if !self.enabled() {
+		return Ok(());
+	}
+
+	let value = UnredactedPdu {
+		redacted_at: utils::millis_since_unix_epoch(),
+		pdu,
+	};
+
+	self.db
+		.eventid_unredactedpdu
+		.insert(event_id, serde_json::to_vec(&value)?);
+
+	Ok(())
+}
+
+/// The original of a redacted event, unless it was scrubbed already.
+#[implement(Service)]
+pub async fn get(&self, event_id: &EventId) -> Result<CanonicalJsonObject> {
+	let value: UnredactedPdu =
+		self.db
+			.eventid_unredactedpdu
+			.get(event_id)
+			.await
+			.and_then(|value| {
+				serde_json::from_slice(&value)
+					.map_err(|e| err!(Database("Invalid unredacted PDU {event_id}: {e}")))
+			})?;
+
+	if self.is_expired(&value) {
+		self.db.eventid_unredactedpdu.remove(event_id);
+		return Err!(Request(NotFound("Event {event_id} has no retained original.")));
+	}
+
+	Ok(value.pdu)
+}
+
+#[implement(Service)]
+fn is_expired(&self, value: &UnredactedPdu) -> bool {
+	let Some(retention) = self.retention() else {
+		return true;
+	};
+
+	let age = utils::millis_since_unix_epoch().saturating_sub(value.redacted_at);
+
+	u128::from(age) >= retention.as_millis()
+}
+
+/// Delete the originals which are past the retention period.
+#[implement(Service)]
+async fn scrub(&self) {
+	let expired: Vec<_> = self
+		.db
+		.eventid_unredactedpdu
+		.raw_stream()
+		.ignore_err()
+		.filter_map(|(key, value)| {
+			let expired = serde_json::from_slice::<UnredactedPdu>(value)
+				.map_or(true, |value| self.is_expired(&value));
+
+			futures::future::ready(expired.then(|| key.to_vec()))
+		})
+		.collect()
+		.await;
+
+	if expired.is_empty() {
+		return;
+	}
+
+	debug!(count = expired.len(), "Scrubbing originals of redacted events");
+	for key in &expired {
+		self.db.eventid_unredactedpdu.remove(key);
+	}
+
+	if !self.enabled() {
+		warn!("Deleted the retained originals of redacted events as retention is disabled.");
+	}
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use conduwuit::{Result, implement};
-use database::{Database, Deserialized, Map};
+use database::{Batch, Database, Deserialized, Map};
 use ruma::{RoomId, UserId};
 
 use crate::{Dep, globals, rooms, rooms::short::ShortStateHash};
@@ -46,16 +46,14 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 #[implement(Service)]
-pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
+pub fn reset_notification_counts(&self, batch: &mut Batch, user_id: &UserId, room_id: &RoomId) {
 	let userroom_id = (user_id, room_id);
-	self.db.userroomid_highlightcount.put(userroom_id, 0_u64);
-	self.db.userroomid_notificationcount.put(userroom_id, 0_u64);
+	batch.put(&self.db.userroomid_highlightcount, userroom_id, 0_u64);
+	batch.put(&self.db.userroomid_notificationcount, userroom_id, 0_u64);
 
 	let roomuser_id = (room_id, user_id);
 	let count = self.services.globals.next_count().unwrap();
-	self.db
-		.roomuserid_lastnotificationread
-		.put(roomuser_id, count);
+	batch.put(&self.db.roomuserid_lastnotificationread, roomuser_id, count);
 }
 
 #[implement(Service)]
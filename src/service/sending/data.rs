@@ -20,6 +20,8 @@ pub struct Data {
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
 	servername_educount: Arc<Map>,
+	appserviceid_keychangecount: Arc<Map>,
+	appserviceid_pendingupto: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
 }
@@ -35,6 +37,8 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
 			servername_educount: db["servername_educount"].clone(),
+			appserviceid_keychangecount: db["appserviceid_keychangecount"].clone(),
+			appserviceid_pendingupto: db["appserviceid_pendingupto"].clone(),
 			db: args.db.clone(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
@@ -169,6 +173,18 @@ pub fn queued_requests(
 			})
 	}
 
+	/// IDs of the appservices with queued requests, in order and possibly
+	/// repeated.
+	pub(super) fn queued_appservices(&self) -> impl Stream<Item = String> + Send + '_ {
+		self.servernameevent_data
+			.raw_keys_prefix(b"+")
+			.ignore_err()
+			.ready_filter_map(|key| {
+				let id = key.get(1..)?.split(|&b| b == 0xFF).next()?;
+				utils::string_from_bytes(id).ok()
+			})
+	}
+
 	pub(super) fn set_latest_educount(&self, server_name: &ServerName, last_count: u64) {
 		self.servername_educount.raw_put(server_name, last_count);
 	}
@@ -180,6 +196,37 @@ pub async fn get_latest_educount(&self, server_name: &ServerName) -> u64 {
 			.deserialized()
 			.unwrap_or(0)
 	}
+
+	pub(super) fn set_latest_keychangecount(&self, appservice_id: &str, last_count: u64) {
+		self.appserviceid_keychangecount
+			.raw_put(appservice_id, last_count);
+	}
+
+	pub async fn get_latest_keychangecount(&self, appservice_id: &str) -> u64 {
+		self.appserviceid_keychangecount
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.unwrap_or(0)
+	}
+
+	/// The count the device data of the appservice's pending transaction was
+	/// selected up to, so a retry of the transaction carries the same data.
+	pub(super) async fn get_pending_upto(&self, appservice_id: &str) -> Option<u64> {
+		self.appserviceid_pendingupto
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.ok()
+	}
+
+	pub(super) fn set_pending_upto(&self, appservice_id: &str, upto: u64) {
+		self.appserviceid_pendingupto.raw_put(appservice_id, upto);
+	}
+
+	pub(super) fn remove_pending_upto(&self, appservice_id: &str) {
+		self.appserviceid_pendingupto.remove(appservice_id);
+	}
 }
 
 fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, SendingEvent)> {
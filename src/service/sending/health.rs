@@ -0,0 +1,166 @@
+//! Delivery health of each federation destination since startup.
+
+use std::{
+	cmp::Reverse,
+	sync::atomic::Ordering,
+	time::{Duration, Instant, SystemTime},
+};
+
+use conduwuit::{Error, implement, info};
+use ruma::{OwnedServerName, ServerName};
+
+use super::{Destination, Service};
+
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+	/// Transactions delivered
+	pub sent: u64,
+
+	/// Transactions which failed
+	pub failed: u64,
+
+	/// Failures since the last delivery
+	pub consecutive_failures: u64,
+
+	/// Moving average of the time taken by a transaction
+	pub latency: Duration,
+
+	pub last_error: Option<String>,
+	pub last_sent: Option<SystemTime>,
+
+	/// Since when requests have been waiting to be delivered without any
+	/// transaction succeeding, as seen by the sender; None when nothing is
+	/// waiting.
+	pub backlog_since: Option<Instant>,
+}
+
+impl Health {
+	/// Percentage of the transactions which failed
+	#[must_use]
+	pub fn failure_rate(&self) -> u64 {
+		self.failed
+			.saturating_mul(100)
+			.checked_div(self.sent.saturating_add(self.failed))
+			.unwrap_or_default()
+	}
+
+	/// Time requests have been waiting without any delivery succeeding
+	#[must_use]
+	pub fn backlog_age(&self) -> Duration {
+		self.backlog_since
+			.as_ref()
+			.map(Instant::elapsed)
+			.unwrap_or_default()
+	}
+}
+
+/// Health of each destination sent to since startup, worst first: by
+/// failures since the last delivery, then failure rate, backlog age and
+/// latency.
+#[implement(Service)]
+#[must_use]
+pub fn health(&self) -> Vec<(OwnedServerName, Health)> {
+	let mut health: Vec<_> = self
+		.health
+		.lock()
+		.expect("locked")
+		.iter()
+		.map(|(server, health)| (server.clone(), health.clone()))
+		.collect();
+
+	health.sort_by_key(|(_, health)| {
+		Reverse((
+			health.consecutive_failures,
+			health.failure_rate(),
+			health.backlog_age(),
+			health.latency,
+		))
+	});
+
+	health
+}
+
+/// Number of sender workers running, and of those started by the service;
+/// fewer running means deliveries to some destinations have stopped.
+#[implement(Service)]
+#[must_use]
+pub fn senders(&self) -> (usize, usize) {
+	(self.senders_running.load(Ordering::Relaxed), self.channels.len())
+}
+
+/// Record the outcome of a transaction sent to a destination.
+#[implement(Service)]
+pub(super) fn record_transaction(
+	&self,
+	server: &ServerName,
+	elapsed: Duration,
+	result: Result<(), &Error>,
+) {
+	let mut health = self.health.lock().expect("locked");
+	let health = health.entry(server.to_owned()).or_default();
+	health.latency = if health.sent == 0 && health.failed == 0 {
+		elapsed
+	} else {
+		health
+			.latency
+			.saturating_mul(7)
+			.saturating_add(elapsed)
+			.checked_div(8)
+			.unwrap_or(elapsed)
+	};
+
+	match result {
+		| Ok(()) => {
+			health.sent = health.sent.saturating_add(1);
+			health.consecutive_failures = 0;
+			health.last_sent = Some(SystemTime::now());
+
+			// The backlog's age measures how long deliveries have been stalled
+			if health.backlog_since.is_some() {
+				health.backlog_since = Some(Instant::now());
+			}
+		},
+		| Err(e) => {
+			health.failed = health.failed.saturating_add(1);
+			health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+			health.last_error = Some(e.to_string());
+		},
+	}
+
+	let latency_ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+	info!(
+		destination = %server,
+		histogram.federation_send_latency_ms = latency_ms,
+		monotonic_counter.federation_send_failures = u64::from(result.is_err()),
+		"Federation transaction completed"
+	);
+}
+
+/// Note requests are waiting to be delivered to the destination, unless
+/// older ones already are.
+#[implement(Service)]
+pub(super) fn backlog_queued(&self, dest: &Destination) {
+	let Destination::Federation(server) = dest else {
+		return;
+	};
+
+	self.health
+		.lock()
+		.expect("locked")
+		.entry(server.clone())
+		.or_default()
+		.backlog_since
+		.get_or_insert_with(Instant::now);
+}
+
+/// Note nothing is left to deliver to the destination.
+#[implement(Service)]
+pub(super) fn backlog_drained(&self, dest: &Destination) {
+	let Destination::Federation(server) = dest else {
+		return;
+	};
+
+	if let Some(health) = self.health.lock().expect("locked").get_mut(server) {
+		health.backlog_since = None;
+	}
+}
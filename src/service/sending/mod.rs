@@ -1,32 +1,45 @@
 mod appservice;
 mod data;
 mod dest;
+mod health;
 mod sender;
 
 use std::{
+	collections::{BTreeMap, HashMap},
 	fmt::Debug,
 	hash::{DefaultHasher, Hash, Hasher},
 	iter::once,
-	sync::Arc,
+	sync::{Arc, Mutex, atomic::AtomicUsize},
 };
 
 use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
 use conduwuit::{
 	Result, Server, debug, debug_warn, err, error,
 	smallvec::SmallVec,
-	utils::{ReadyExt, TryReadyExt, available_parallelism, math::usize_from_u64_truncated},
+	utils::{
+		ReadyExt, TryReadyExt, available_parallelism, hash::sha256,
+		math::usize_from_u64_truncated,
+	},
 	warn,
 };
 use futures::{FutureExt, Stream, StreamExt};
 use ruma::{
-	RoomId, ServerName, UserId,
-	api::{OutgoingRequest, appservice::Registration},
+	EventId, OwnedServerName, RoomId, ServerName, UserId,
+	api::{
+		OutgoingRequest,
+		appservice::{Registration, event::push_events},
+		client::sync::sync_events::DeviceLists,
+	},
+	events::AnyTimelineEvent,
+	serde::Raw,
 };
 use tokio::{task, task::JoinSet};
 
 use self::data::Data;
 pub use self::{
 	dest::Destination,
+	health::Health,
 	sender::{EDU_LIMIT, PDU_LIMIT},
 };
 use crate::{
@@ -39,6 +52,8 @@ pub struct Service {
 	server: Arc<Server>,
 	services: Services,
 	channels: Vec<(loole::Sender<Msg>, loole::Receiver<Msg>)>,
+	health: Mutex<HashMap<OwnedServerName, Health>>,
+	senders_running: AtomicUsize,
 }
 
 struct Services {
@@ -101,6 +116,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				federation: args.depend::<federation::Service>("federation"),
 			},
 			channels: (0..num_senders).map(|_| loole::unbounded()).collect(),
+			health: Mutex::default(),
+			senders_running: AtomicUsize::new(0),
 		}))
 	}
 
@@ -118,8 +135,8 @@ async fn worker(self: Arc<Self>) -> Result {
 						worker.boxed()
 					};
 
-					let runtime = self.server.runtime();
-					let _abort = joinset.spawn_on(worker, runtime);
+					let name = format!("sending:sender:{id}");
+					let _abort = self.server.spawn_in(&mut joinset, &name, worker);
 					joinset
 				});
 
@@ -289,6 +306,35 @@ pub async fn flush_servers<'a, S>(&self, servers: S) -> Result<()>
 			.await
 	}
 
+	/// Wake the appservice which receives to-device messages for the user
+	/// (MSC2409), so pending messages are delivered without waiting for the
+	/// next event. Only an appservice exclusively owning the user receives
+	/// them, as they are removed once delivered.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn flush_appservices_for_user(&self, user_id: &UserId) -> Result {
+		let appservices: Vec<_> = self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.filter(|info| {
+				info.registration.receive_ephemeral && info.is_exclusive_user_match(user_id)
+			})
+			.map(|info| info.registration.id.clone())
+			.collect();
+
+		for id in appservices {
+			self.dispatch(Msg {
+				dest: Destination::Appservice(id),
+				event: SendingEvent::Flush,
+				queue_id: Vec::<u8>::new(),
+			})?;
+		}
+
+		Ok(())
+	}
+
 	/// Sends a request to a federation server
 	#[inline]
 	pub async fn send_federation_request<T>(
@@ -334,6 +380,38 @@ pub async fn send_appservice_request<T>(
 		appservice::send_request(client, registration, request).await
 	}
 
+	/// Push an invite into a room this server is not in to the appservices
+	/// interested in the invited user, as it does not reach them through the
+	/// timeline.
+	pub async fn send_invite_appservices(
+		&self,
+		recipient: &UserId,
+		event_id: &EventId,
+		event: &Raw<AnyTimelineEvent>,
+	) -> Result {
+		for appservice in self.services.appservice.read().await.values() {
+			if appservice.is_user_match(recipient) {
+				self.send_appservice_request(
+					appservice.registration.clone(),
+					push_events::v1::Request {
+						events: vec![event.clone()],
+						txn_id: general_purpose::URL_SAFE_NO_PAD
+							.encode(sha256::hash(event_id.as_bytes()))
+							.into(),
+						ephemeral: Vec::new(),
+						to_device: Vec::new(),
+						device_lists: DeviceLists::new(),
+						device_one_time_keys_count: BTreeMap::new(),
+						device_unused_fallback_key_types: BTreeMap::new(),
+					},
+				)
+				.await?;
+			}
+		}
+
+		Ok(())
+	}
+
 	/// Clean up queued sending event data
 	///
 	/// Used after we remove an appservice registration or a user deletes a push
@@ -360,6 +438,7 @@ pub async fn cleanup_events(
 				self.db
 					.delete_all_requests_for(&Destination::Appservice(appservice_id.to_owned()))
 					.await;
+				self.db.remove_pending_upto(appservice_id);
 
 				Ok(())
 			},
@@ -1,5 +1,6 @@
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
+	cmp::Reverse,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	fmt::Debug,
 	sync::{
 		Arc,
@@ -10,11 +11,12 @@
 
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
 use conduwuit::{
-	Error, Result, debug, err, error,
+	Error, Result, at, debug, defer, err, error,
 	result::LogErr,
 	trace,
 	utils::{
-		ReadyExt, calculate_hash, continue_exponential_backoff_secs,
+		ReadyExt, calculate_hash, continue_exponential_backoff,
+		continue_exponential_backoff_secs,
 		future::TryExtExt,
 		stream::{BroadbandExt, IterStream, WidebandExt},
 	},
@@ -22,15 +24,16 @@
 };
 use futures::{
 	FutureExt, StreamExt,
-	future::{BoxFuture, OptionFuture},
+	future::{BoxFuture, OptionFuture, join_all},
 	join, pin_mut,
 	stream::FuturesUnordered,
 };
 use ruma::{
-	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName, OwnedUserId,
-	RoomId, RoomVersionId, ServerName, UInt,
+	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OwnedDeviceId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UInt, UserId,
 	api::{
 		appservice::event::push_events::v1::EphemeralData,
+		client::sync::sync_events::DeviceLists,
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
@@ -41,18 +44,20 @@
 	},
 	device_id,
 	events::{
-		AnySyncEphemeralRoomEvent, GlobalAccountDataEventType, push_rules::PushRulesEvent,
-		receipt::ReceiptType,
+		AnySyncEphemeralRoomEvent, AnyToDeviceEvent, GlobalAccountDataEventType,
+		push_rules::PushRulesEvent, receipt::ReceiptType,
 	},
 	push,
-	serde::Raw,
+	serde::{JsonObject, Raw},
 	uint,
 };
 use serde_json::value::{RawValue as RawJsonValue, to_raw_value};
+use tokio::time::{MissedTickBehavior, interval};
 
 use super::{
 	Destination, EduBuf, EduVec, Msg, SendingEvent, Service, appservice, data::QueueItem,
 };
+use crate::appservice::RegistrationInfo;
 
 #[derive(Debug)]
 enum TransactionStatus {
@@ -66,11 +71,15 @@ enum TransactionStatus {
 type SendingFuture<'a> = BoxFuture<'a, SendingResult>;
 type SendingFutures<'a> = FuturesUnordered<SendingFuture<'a>>;
 type CurTransactionStatus = HashMap<Destination, TransactionStatus>;
+type OneTimeKeyCounts =
+	BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, BTreeMap<OneTimeKeyAlgorithm, UInt>>>;
 
 const SELECT_PRESENCE_LIMIT: usize = 256;
 const SELECT_RECEIPT_LIMIT: usize = 256;
 const SELECT_EDU_LIMIT: usize = EDU_LIMIT - 2;
 const DEQUEUE_LIMIT: usize = 48;
+const APPSERVICE_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+const APPSERVICE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 pub const PDU_LIMIT: usize = 50;
 pub const EDU_LIMIT: usize = 100;
@@ -78,6 +87,11 @@ enum TransactionStatus {
 impl Service {
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub(super) async fn sender(self: Arc<Self>, id: usize) -> Result {
+		self.senders_running.fetch_add(1, Ordering::Relaxed);
+		defer! {{
+			self.senders_running.fetch_sub(1, Ordering::Relaxed);
+		}};
+
 		let mut statuses: CurTransactionStatus = CurTransactionStatus::new();
 		let mut futures: SendingFutures<'_> = FuturesUnordered::new();
 
@@ -115,11 +129,15 @@ async fn work_loop<'a>(
 			.map(|(_, receiver)| receiver.clone())
 			.expect("Missing channel for sender worker");
 
+		let mut retry = interval(APPSERVICE_RETRY_INTERVAL);
+		retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
 		while !receiver.is_closed() {
 			tokio::select! {
 				Some(response) = futures.next() => {
 					self.handle_response(response, futures, statuses).await;
 				},
+				_ = retry.tick() => self.retry_appservices(futures, statuses).await,
 				request = receiver.recv_async() => match request {
 					Ok(request) => self.handle_request(request, futures, statuses).await,
 					Err(_) => return,
@@ -165,13 +183,29 @@ async fn handle_response_ok<'a>(
 		let _cork = self.db.db.cork();
 		self.db.delete_all_active_requests_for(dest).await;
 
+		// A successful retry means the destination recovered from an outage; send
+		// the latest events of each room before trickling out the backlog.
+		// Appservices cannot fetch skipped history, so they get the backlog in
+		// order.
+		let recovered = matches!(statuses.get(dest), Some(TransactionStatus::Retrying(_)))
+			&& matches!(dest, Destination::Federation(_));
+		let catchup = if recovered && self.server.config.sender_catchup {
+			statuses.insert(dest.clone(), TransactionStatus::Running);
+			self.select_catchup(dest).await
+		} else {
+			Vec::new()
+		};
+
 		// Find events that have been added since starting the last request
-		let new_events = self
-			.db
-			.queued_requests(dest)
-			.take(DEQUEUE_LIMIT)
-			.collect::<Vec<_>>()
-			.await;
+		let new_events = if catchup.is_empty() {
+			self.db
+				.queued_requests(dest)
+				.take(self.dequeue_limit(dest))
+				.collect::<Vec<_>>()
+				.await
+		} else {
+			catchup
+		};
 
 		// Insert any pdus we found
 		if !new_events.is_empty() {
@@ -181,9 +215,115 @@ async fn handle_response_ok<'a>(
 			futures.push(self.send_events(dest.clone(), new_events_vec));
 		} else {
 			statuses.remove(dest);
+			self.backlog_drained(dest);
+		}
+	}
+
+	/// Number of queued events to send at once; appservices with concurrent
+	/// transactions take a batch for each transaction.
+	fn dequeue_limit(&self, dest: &Destination) -> usize {
+		match dest {
+			| Destination::Appservice(id) =>
+				DEQUEUE_LIMIT.saturating_mul(self.appservice_concurrency(id)),
+			| _ => DEQUEUE_LIMIT,
 		}
 	}
 
+	fn appservice_concurrency(&self, id: &str) -> usize {
+		self.server
+			.config
+			.appservice_transaction_concurrency
+			.get(id)
+			.copied()
+			.unwrap_or(1)
+			.max(1)
+	}
+
+	/// Retry the transactions of appservices whose backoff has expired. Unlike
+	/// remote servers, appservices are retried without waiting for new events
+	/// to them, so the backlog of a bridge which was down is delivered as soon
+	/// as it is back.
+	#[allow(clippy::needless_pass_by_ref_mut)]
+	async fn retry_appservices<'a>(
+		&'a self,
+		futures: &mut SendingFutures<'a>,
+		statuses: &mut CurTransactionStatus,
+	) {
+		let due: Vec<_> = statuses
+			.iter()
+			.filter(|(dest, status)| match status {
+				| TransactionStatus::Failed(tries, time) =>
+					matches!(dest, Destination::Appservice(_))
+						&& !self.backing_off(dest, *tries, time.elapsed()),
+				| _ => false,
+			})
+			.map(|(dest, _)| dest.clone())
+			.collect();
+
+		for dest in due {
+			if let Ok(Some(mut events)) = self.select_events(&dest, Vec::new(), statuses).await {
+				// the failed transaction only flushed; send an empty one to pick up the
+				// queue once it succeeds
+				if events.is_empty() {
+					events.push(SendingEvent::Flush);
+				}
+
+				futures.push(self.send_events(dest, events));
+			}
+		}
+	}
+
+	/// Whether a destination which failed `tries` times, the last time
+	/// `elapsed` ago, is not to be retried yet.
+	fn backing_off(&self, dest: &Destination, tries: u32, elapsed: Duration) -> bool {
+		let config = &self.server.config;
+		match dest {
+			| Destination::Appservice(_) => {
+				let max = Duration::from_secs(config.appservice_retry_backoff_limit);
+				continue_exponential_backoff(APPSERVICE_RETRY_BACKOFF, max, elapsed, tries)
+			},
+			| _ => continue_exponential_backoff_secs(
+				config.sender_timeout,
+				config.sender_retry_backoff_limit,
+				elapsed,
+				tries,
+			),
+		}
+	}
+
+	/// Select the latest queued PDU of each room for a destination which has
+	/// recovered from an outage, favouring the most recently active rooms. The
+	/// rest of the backlog remains queued for subsequent transactions.
+	#[tracing::instrument(name = "catchup", level = "debug", skip(self))]
+	async fn select_catchup(&self, dest: &Destination) -> Vec<QueueItem> {
+		let mut queued: usize = 0;
+		let mut latest = BTreeMap::new();
+		self.db
+			.queued_requests(dest)
+			.ready_for_each(|item| {
+				queued = queued.saturating_add(1);
+				if let (_, SendingEvent::Pdu(pdu_id)) = &item {
+					latest.insert(pdu_id.shortroomid(), item);
+				}
+			})
+			.await;
+
+		// only worthwhile when there is a backlog to skip over
+		if queued <= DEQUEUE_LIMIT {
+			return Vec::new();
+		}
+
+		let mut events: Vec<QueueItem> = latest.into_values().collect();
+		events.sort_unstable_by_key(|(_, event)| match event {
+			| SendingEvent::Pdu(pdu_id) => Reverse(pdu_id.shorteventid()),
+			| _ => Reverse([0; 8]),
+		});
+
+		events.truncate(PDU_LIMIT);
+		debug!(rooms = events.len(), "Sending catch-up transaction");
+		events
+	}
+
 	#[allow(clippy::needless_pass_by_ref_mut)]
 	#[tracing::instrument(name = "request", level = "debug", skip_all)]
 	async fn handle_request<'a>(
@@ -192,12 +332,14 @@ async fn handle_request<'a>(
 		futures: &mut SendingFutures<'a>,
 		statuses: &mut CurTransactionStatus,
 	) {
+		self.backlog_queued(&msg.dest);
 		let iv = vec![(msg.queue_id, msg.event)];
 		if let Ok(Some(events)) = self.select_events(&msg.dest, iv, statuses).await {
 			if !events.is_empty() {
 				futures.push(self.send_events(msg.dest, events));
 			} else {
 				statuses.remove(&msg.dest);
+				self.backlog_drained(&msg.dest);
 			}
 		}
 	}
@@ -221,7 +363,14 @@ async fn finish_responses<'a>(&'a self, futures: &mut SendingFutures<'a>) {
 		loop {
 			trace!("Waiting for {} requests to complete...", futures.len());
 			select! {
-				() = sleep_until(deadline) => return,
+				() = sleep_until(deadline) => {
+					warn!(
+						"{} federation transactions not delivered before shutdown; they will be \
+						 sent again at startup.",
+						futures.len()
+					);
+					return;
+				},
 				response = futures.next() => match response {
 					Some(Ok(dest)) => self.db.delete_all_active_requests_for(&dest).await,
 					Some(_) => continue,
@@ -254,8 +403,11 @@ async fn startup_netburst<'a>(
 				continue;
 			}
 
+			// appservice events are never dropped, as bridges cannot fetch them
+			let appservice = matches!(dest, Destination::Appservice(_));
 			let entry = txns.entry(dest.clone()).or_default();
-			if self.server.config.startup_netburst_keep >= 0 && entry.len() >= keep {
+			if self.server.config.startup_netburst_keep >= 0 && entry.len() >= keep && !appservice
+			{
 				warn!("Dropping unsent event {dest:?} {:?}", String::from_utf8_lossy(&key));
 				self.db.delete_active_request(&key);
 			} else {
@@ -264,11 +416,39 @@ async fn startup_netburst<'a>(
 		}
 
 		for (dest, events) in txns {
-			if self.server.config.startup_netburst && !events.is_empty() {
+			let appservice = matches!(dest, Destination::Appservice(_));
+			if (self.server.config.startup_netburst || appservice) && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
+				self.backlog_queued(&dest);
 				futures.push(self.send_events(dest.clone(), events));
 			}
 		}
+
+		// Appservices with events queued before the restart are woken now rather
+		// than on their next event.
+		let queued: BTreeSet<String> = self.db.queued_appservices().collect().await;
+		for appservice in queued {
+			let dest = Destination::Appservice(appservice);
+			if self.shard_id(&dest) != id || statuses.contains_key(&dest) {
+				continue;
+			}
+
+			let events: Vec<_> = self
+				.db
+				.queued_requests(&dest)
+				.take(self.dequeue_limit(&dest))
+				.collect()
+				.await;
+
+			if events.is_empty() {
+				continue;
+			}
+
+			self.db.mark_as_active(events.iter());
+			statuses.insert(dest.clone(), TransactionStatus::Running);
+			self.backlog_queued(&dest);
+			futures.push(self.send_events(dest, events.into_iter().map(at!(1)).collect()));
+		}
 	}
 
 	#[tracing::instrument(
@@ -340,11 +520,7 @@ fn select_events_current(
 			.and_modify(|e| match e {
 				TransactionStatus::Failed(tries, time) => {
 					// Fail if a request has failed recently (exponential backoff)
-					let min = self.server.config.sender_timeout;
-					let max = self.server.config.sender_retry_backoff_limit;
-					if continue_exponential_backoff_secs(min, max, time.elapsed(), *tries)
-						&& !matches!(dest, Destination::Appservice(_))
-					{
+					if self.backing_off(dest, *tries, time.elapsed()) {
 						allow = false;
 					} else {
 						retry = true;
@@ -674,13 +850,64 @@ async fn send_events_dest_appservice(
 		id: String,
 		events: Vec<SendingEvent>,
 	) -> SendingResult {
-		let Some(appservice) = self.services.appservice.get_registration(&id).await else {
+		let Some(info) = self.services.appservice.get_registration_info(&id).await else {
 			return Err((
 				Destination::Appservice(id.clone()),
 				err!(Database(warn!(?id, "Missing appservice registration"))),
 			));
 		};
 
+		// the batch is split across the concurrent transactions by room; only the
+		// first carries the to-device messages and device list changes, and is
+		// sent even without events when it has to
+		let device_data = info.extensions.msc3202 || info.registration.receive_ephemeral;
+		let chunks = appservice_chunks(events, self.appservice_concurrency(&id));
+		let (appservice_id, info) = (id.as_str(), &info);
+		let transactions = chunks
+			.iter()
+			.enumerate()
+			.filter(|(i, events)| !events.is_empty() || (*i == 0 && device_data))
+			.map(|(i, events)| async move {
+				let result = self
+					.send_appservice_transaction(appservice_id, info, events, i == 0)
+					.await;
+
+				(events, result)
+			});
+
+		let mut sent = Vec::new();
+		let mut failed = None;
+		for (events, result) in join_all(transactions).await {
+			match result {
+				| Ok(()) => sent.extend(events),
+				| Err(e) => failed = Some(e),
+			}
+		}
+
+		let dest = Destination::Appservice(id);
+		let Some(e) = failed else {
+			return Ok(dest);
+		};
+
+		// only the events of the failed transactions are retried, which are
+		// split the same way again
+		self.db
+			.active_requests_for(&dest)
+			.ready_filter(|(_, event)| sent.contains(&event))
+			.ready_for_each(|(key, _)| self.db.delete_active_request(&key))
+			.await;
+
+		Err((dest, e))
+	}
+
+	async fn send_appservice_transaction(
+		&self,
+		id: &str,
+		info: &RegistrationInfo,
+		events: &[SendingEvent],
+		device_data: bool,
+	) -> Result {
+		let appservice = &info.registration;
 		let mut pdu_jsons = Vec::with_capacity(
 			events
 				.iter()
@@ -693,7 +920,7 @@ async fn send_events_dest_appservice(
 				.filter(|event| matches!(event, SendingEvent::Edu(_)))
 				.count(),
 		);
-		for event in &events {
+		for event in events {
 			match event {
 				| SendingEvent::Pdu(pdu_id) => {
 					if let Ok(pdu) = self.services.timeline.get_pdu_from_id(pdu_id).await {
@@ -710,32 +937,210 @@ async fn send_events_dest_appservice(
 			}
 		}
 
-		let txn_hash = calculate_hash(events.iter().filter_map(|e| match e {
-			| SendingEvent::Edu(b) => Some(&**b),
-			| SendingEvent::Pdu(b) => Some(b.as_ref()),
-			| SendingEvent::Flush => None,
-		}));
+		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
+		// transaction");
+		let e2ee = device_data && info.extensions.msc3202;
+		let to_device = device_data && appservice.receive_ephemeral;
+
+		// device data is selected up to a count kept until the transaction
+		// succeeds, so a retry carries the same data under the same ID
+		let upto = if e2ee || to_device {
+			match self.db.get_pending_upto(id).await {
+				| Some(upto) => upto,
+				| None => {
+					let upto = self.services.globals.current_count()?;
+					self.db.set_pending_upto(id, upto);
+					upto
+				},
+			}
+		} else {
+			0
+		};
+
+		let upto_bytes = upto.to_be_bytes();
+		let txn_hash = calculate_hash(
+			events
+				.iter()
+				.filter_map(|e| match e {
+					| SendingEvent::Edu(b) => Some(&**b),
+					| SendingEvent::Pdu(b) => Some(b.as_ref()),
+					| SendingEvent::Flush => None,
+				})
+				.chain((e2ee || to_device).then_some(upto_bytes.as_slice())),
+		);
 
 		let txn_id = &*URL_SAFE_NO_PAD.encode(txn_hash);
+		let users = if e2ee || to_device {
+			self.services.appservice.local_users(info).await
+		} else {
+			Arc::default()
+		};
+
+		let (device_lists, device_one_time_keys_count) = if e2ee {
+			self.select_appservice_e2ee(id, &users, upto).await
+		} else {
+			Default::default()
+		};
+
+		let (to_device_events, to_device_devices) = if to_device {
+			self.select_appservice_to_device(info, &users, upto).await
+		} else {
+			Default::default()
+		};
 
-		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
-		// transaction");
 		let client = &self.services.client.appservice;
-		match appservice::send_request(
+		appservice::send_request(
 			client,
-			appservice,
+			appservice.clone(),
 			ruma::api::appservice::event::push_events::v1::Request {
 				events: pdu_jsons,
 				txn_id: txn_id.into(),
 				ephemeral: edu_jsons,
-				to_device: Vec::new(), // TODO
+				to_device: to_device_events,
+				device_lists,
+				device_one_time_keys_count,
+				// fallback keys are not stored by this server
+				device_unused_fallback_key_types: BTreeMap::new(),
 			},
 		)
-		.await
-		{
-			| Ok(_) => Ok(Destination::Appservice(id)),
-			| Err(e) => Err((Destination::Appservice(id), e)),
+		.await?;
+
+		if e2ee {
+			self.db.set_latest_keychangecount(id, upto);
+		}
+
+		// the appservice acknowledged the to-device messages
+		for (user_id, device_id) in &to_device_devices {
+			self.services
+				.users
+				.remove_to_device_events(user_id, device_id, upto)
+				.await;
+		}
+
+		if e2ee || to_device {
+			self.db.remove_pending_upto(id);
+		}
+
+		Ok(())
+	}
+
+	/// Collect the device list changes and one-time key counts relevant to an
+	/// appservice's users since its last successful transaction (MSC3202).
+	#[tracing::instrument(name = "e2ee", level = "trace", skip(self, users))]
+	async fn select_appservice_e2ee(
+		&self,
+		id: &str,
+		users: &[OwnedUserId],
+		upto: u64,
+	) -> (DeviceLists, OneTimeKeyCounts) {
+		let since = self.db.get_latest_keychangecount(id).await;
+		let mut changed = BTreeSet::<OwnedUserId>::new();
+		let mut rooms = BTreeSet::<OwnedRoomId>::new();
+		let mut counts = OneTimeKeyCounts::new();
+		for user_id in users {
+			self.services
+				.users
+				.keys_changed(user_id, since, Some(upto))
+				.ready_for_each(|user_id| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+
+			self.services
+				.state_cache
+				.rooms_joined(user_id)
+				.ready_for_each(|room_id| {
+					rooms.insert(room_id.to_owned());
+				})
+				.await;
+
+			if self.services.users.last_one_time_keys_update(user_id).await <= since {
+				continue;
+			}
+
+			for device_id in self.user_device_ids(user_id).await {
+				let count = self
+					.services
+					.users
+					.count_one_time_keys(user_id, &device_id)
+					.await;
+
+				counts
+					.entry(user_id.clone())
+					.or_default()
+					.insert(device_id, count);
+			}
+		}
+
+		// rooms shared by several of the users are only queried once
+		for room_id in &rooms {
+			self.services
+				.users
+				.room_keys_changed(room_id, since, Some(upto))
+				.ready_for_each(|(user_id, _)| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+		}
+
+		let device_lists = DeviceLists {
+			changed: changed.into_iter().collect(),
+			left: Vec::new(),
+		};
+
+		(device_lists, counts)
+	}
+
+	/// Collect the pending to-device messages for the users an appservice
+	/// exclusively owns (MSC2409). Returns the messages along with the devices
+	/// they were pending for, so they can be removed once the appservice
+	/// acknowledged the transaction. Users only in a non-exclusive namespace
+	/// receive their messages on their own devices.
+	#[tracing::instrument(name = "to_device", level = "trace", skip(self, info, users))]
+	async fn select_appservice_to_device(
+		&self,
+		info: &RegistrationInfo,
+		users: &[OwnedUserId],
+		upto: u64,
+	) -> (Vec<Raw<AnyToDeviceEvent>>, Vec<(OwnedUserId, OwnedDeviceId)>) {
+		let mut events = Vec::new();
+		let mut devices = Vec::new();
+		let users = users
+			.iter()
+			.filter(|user_id| info.is_exclusive_user_match(user_id));
+
+		for user_id in users {
+			for device_id in self.user_device_ids(user_id).await {
+				let pending: Vec<_> = self
+					.services
+					.users
+					.get_to_device_events(user_id, &device_id, None, Some(upto))
+					.ready_filter_map(|event| {
+						let mut event: JsonObject = event.deserialize_as().ok()?;
+						event.insert("to_user_id".into(), user_id.as_str().into());
+						event.insert("to_device_id".into(), device_id.as_str().into());
+						to_raw_value(&event).ok().map(Raw::from_json)
+					})
+					.collect()
+					.await;
+
+				if !pending.is_empty() {
+					events.extend(pending);
+					devices.push((user_id.clone(), device_id));
+				}
+			}
 		}
+
+		(events, devices)
+	}
+
+	async fn user_device_ids(&self, user_id: &UserId) -> Vec<OwnedDeviceId> {
+		self.services
+			.users
+			.all_device_ids(user_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
 	}
 
 	#[tracing::instrument(
@@ -860,12 +1265,15 @@ async fn send_events_dest_federation(
 			edus,
 		};
 
+		let started = Instant::now();
 		let result = self
 			.services
 			.federation
 			.execute_on(&self.services.client.sender, &server, request)
 			.await;
 
+		self.record_transaction(&server, started.elapsed(), result.as_ref().map(|_| ()));
+
 		for (event_id, result) in result.iter().flat_map(|resp| resp.pdus.iter()) {
 			if let Err(e) = result {
 				warn!(
@@ -919,3 +1327,24 @@ pub async fn convert_to_outgoing_federation_event(
 		to_raw_value(&pdu_json).expect("CanonicalJson is valid serde_json::Value")
 	}
 }
+
+/// Split the events of a batch for an appservice across `concurrency`
+/// transactions. The events of a room always go to the same transaction, in
+/// order; ephemeral events go to the first.
+fn appservice_chunks(events: Vec<SendingEvent>, concurrency: usize) -> Vec<Vec<SendingEvent>> {
+	let concurrency = concurrency.max(1);
+	let mut chunks = vec![Vec::new(); concurrency];
+	for event in events {
+		let chunk = match &event {
+			| SendingEvent::Pdu(pdu_id) => u64::from_be_bytes(pdu_id.shortroomid())
+				.checked_rem(u64::try_from(concurrency).unwrap_or(1))
+				.and_then(|chunk| usize::try_from(chunk).ok())
+				.unwrap_or(0),
+			| SendingEvent::Edu(_) | SendingEvent::Flush => 0,
+		};
+
+		chunks[chunk].push(event);
+	}
+
+	chunks
+}
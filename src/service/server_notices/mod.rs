@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduwuit::{Err, Result, Server, debug, implement, info, matrix::pdu::PduBuilder};
+use database::{Deserialized, Map};
+use ruma::{
+	OwnedEventId, OwnedRoomId, UserId,
+	events::{
+		RoomAccountDataEventType,
+		room::{
+			join_rules::JoinRule,
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::{TagEvent, TagEventContent, TagInfo},
+	},
+	int,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+	Dep, account_data,
+	admin::{self, ServerRoom},
+	globals, rooms, users,
+};
+
+pub struct Service {
+	/// Serializes finding or creating the notices room of a user
+	create: Mutex<()>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	userid_servernoticeroomid: Arc<Map>,
+}
+
+/// Tag clients show server notices rooms under
+const SERVER_NOTICE_TAG: &str = "m.server_notice";
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			create: Mutex::new(()),
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: Data {
+				userid_servernoticeroomid: args.db["userid_servernoticeroomid"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Send a server notice to a local user in their server notices room, which
+/// is created and the user invited to it as needed. Returns the ID of the
+/// notice event.
+#[implement(Service)]
+pub async fn send(
+	&self,
+	user_id: &UserId,
+	content: &RoomMessageEventContent,
+) -> Result<OwnedEventId> {
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Server notices can only be sent to local users.")));
+	}
+
+	if !self.services.users.is_active(user_id).await {
+		return Err!(Request(NotFound("User {user_id} does not exist or is deactivated.")));
+	}
+
+	let room_id = self.room(user_id).await?;
+	let server_user = &self.services.globals.server_user;
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	if !self.services.state_cache.is_joined(user_id, &room_id).await
+		&& !self
+			.services
+			.state_cache
+			.is_invited(user_id, &room_id)
+			.await
+	{
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(
+					user_id.to_string(),
+					&RoomMemberEventContent::new(MembershipState::Invite),
+				),
+				server_user,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+	}
+
+	let event_id = self
+		.services
+		.timeline
+		.build_and_append_pdu(PduBuilder::timeline(content), server_user, &room_id, &state_lock)
+		.await?;
+
+	debug!(%user_id, %room_id, %event_id, "Sent server notice");
+
+	Ok(event_id)
+}
+
+/// The server notices room of the user, creating it when the user has none
+/// or the server user is no longer in it.
+#[implement(Service)]
+pub async fn room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	let _create = self.create.lock().await;
+	let existing: Result<OwnedRoomId> = self
+		.db
+		.userid_servernoticeroomid
+		.get(user_id)
+		.await
+		.deserialized();
+
+	if let Ok(room_id) = existing {
+		if self
+			.services
+			.state_cache
+			.is_joined(&self.services.globals.server_user, &room_id)
+			.await
+		{
+			return Ok(room_id);
+		}
+	}
+
+	let room_id = self.create_room(user_id).await?;
+	self.db.userid_servernoticeroomid.insert(user_id, &room_id);
+
+	info!("Created server notices room {room_id} for {user_id}");
+
+	Ok(room_id)
+}
+
+#[implement(Service)]
+async fn create_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	let server_user = &self.services.globals.server_user;
+
+	// The user may read and leave the room but not send anything into it
+	let users = BTreeMap::from_iter([(server_user.clone(), int!(100))]);
+	let room_id = self
+		.services
+		.admin
+		.create_room(ServerRoom {
+			name: self.services.server.config.server_notices_room_name.clone(),
+			alias: None,
+			join_rule: JoinRule::Invite,
+			federate: false,
+			power_levels: RoomPowerLevelsEventContent {
+				users,
+				users_default: int!(-10),
+				..Default::default()
+			},
+			state: Vec::new(),
+		})
+		.await?;
+
+	let mut tags = TagEvent {
+		content: TagEventContent { tags: BTreeMap::new() },
+	};
+
+	tags.content
+		.tags
+		.insert(SERVER_NOTICE_TAG.into(), TagInfo::new());
+
+	self.services
+		.account_data
+		.update(
+			Some(&room_id),
+			user_id,
+			RoomAccountDataEventType::Tag,
+			&serde_json::to_value(tags).expect("to json value always works"),
+		)
+		.await?;
+
+	Ok(room_id)
+}
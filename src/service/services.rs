@@ -4,36 +4,63 @@
 	sync::{Arc, RwLock},
 };
 
-use conduwuit::{Result, Server, debug, debug_info, info, trace, utils::stream::IterStream};
+use conduwuit::{
+	Result, Server, debug, debug_info, error, info, trace, utils::stream::IterStream,
+};
 use database::Database;
 use futures::{Stream, StreamExt, TryStreamExt};
 use tokio::sync::Mutex;
 
 use crate::{
-	account_data, admin, appservice, client, config, emergency, federation, globals, key_backups,
+	account_data, admin, alerts, appservice, audit, auto_join, caches, client, config,
+	delayed_events, email, emergency, federation, globals, jwt, key_backups, ldap, maintenance,
 	manager::Manager,
-	media, presence, pusher, resolver, rooms, sending, server_keys, service,
+	media, media_scanner, oidc, policy_lists, presence, pruning, pusher, ratelimit,
+	registration_limits, registration_tokens, rendezvous, reports, resolver, rooms, sending,
+	server_keys, server_notices, service,
 	service::{Args, Map, Service},
-	sync, transaction_ids, uiaa, updates, users,
+	spam_checker, sso, strangers, sync, transaction_ids, uiaa, updates, users,
 };
 
 pub struct Services {
 	pub account_data: Arc<account_data::Service>,
 	pub admin: Arc<admin::Service>,
+	pub alerts: Arc<alerts::Service>,
 	pub appservice: Arc<appservice::Service>,
+	pub audit: Arc<audit::Service>,
+	pub auto_join: Arc<auto_join::Service>,
+	pub caches: Arc<caches::Service>,
 	pub config: Arc<config::Service>,
 	pub client: Arc<client::Service>,
+	pub delayed_events: Arc<delayed_events::Service>,
+	pub email: Arc<email::Service>,
 	pub emergency: Arc<emergency::Service>,
 	pub globals: Arc<globals::Service>,
+	pub jwt: Arc<jwt::Service>,
 	pub key_backups: Arc<key_backups::Service>,
+	pub ldap: Arc<ldap::Service>,
+	pub maintenance: Arc<maintenance::Service>,
 	pub media: Arc<media::Service>,
+	pub media_scanner: Arc<media_scanner::Service>,
+	pub oidc: Arc<oidc::Service>,
+	pub policy_lists: Arc<policy_lists::Service>,
 	pub presence: Arc<presence::Service>,
+	pub pruning: Arc<pruning::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub ratelimit: Arc<ratelimit::Service>,
+	pub registration_limits: Arc<registration_limits::Service>,
+	pub registration_tokens: Arc<registration_tokens::Service>,
+	pub rendezvous: Arc<rendezvous::Service>,
+	pub reports: Arc<reports::Service>,
 	pub resolver: Arc<resolver::Service>,
 	pub rooms: rooms::Service,
 	pub federation: Arc<federation::Service>,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub server_notices: Arc<server_notices::Service>,
+	pub spam_checker: Arc<spam_checker::Service>,
+	pub sso: Arc<sso::Service>,
+	pub strangers: Arc<strangers::Service>,
 	pub sync: Arc<sync::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
@@ -66,18 +93,37 @@ macro_rules! build {
 		Ok(Arc::new(Self {
 			account_data: build!(account_data::Service),
 			admin: build!(admin::Service),
+			alerts: build!(alerts::Service),
 			appservice: build!(appservice::Service),
+			audit: build!(audit::Service),
+			auto_join: build!(auto_join::Service),
+			caches: build!(caches::Service),
 			resolver: build!(resolver::Service),
 			client: build!(client::Service),
 			config: build!(config::Service),
+			delayed_events: build!(delayed_events::Service),
+			email: build!(email::Service),
 			emergency: build!(emergency::Service),
 			globals: build!(globals::Service),
+			jwt: build!(jwt::Service),
 			key_backups: build!(key_backups::Service),
+			ldap: build!(ldap::Service),
+			maintenance: build!(maintenance::Service),
 			media: build!(media::Service),
+			media_scanner: build!(media_scanner::Service),
+			oidc: build!(oidc::Service),
+			policy_lists: build!(policy_lists::Service),
 			presence: build!(presence::Service),
+			pruning: build!(pruning::Service),
 			pusher: build!(pusher::Service),
+			ratelimit: build!(ratelimit::Service),
+			registration_limits: build!(registration_limits::Service),
+			registration_tokens: build!(registration_tokens::Service),
+			rendezvous: build!(rendezvous::Service),
+			reports: build!(reports::Service),
 			rooms: rooms::Service {
 				alias: build!(rooms::alias::Service),
+				archive: build!(rooms::archive::Service),
 				auth_chain: build!(rooms::auth_chain::Service),
 				directory: build!(rooms::directory::Service),
 				event_handler: build!(rooms::event_handler::Service),
@@ -96,11 +142,16 @@ macro_rules! build {
 				threads: build!(rooms::threads::Service),
 				timeline: build!(rooms::timeline::Service),
 				typing: build!(rooms::typing::Service),
+				unredacted: build!(rooms::unredacted::Service),
 				user: build!(rooms::user::Service),
 			},
 			federation: build!(federation::Service),
 			sending: build!(sending::Service),
 			server_keys: build!(server_keys::Service),
+			server_notices: build!(server_notices::Service),
+			spam_checker: build!(spam_checker::Service),
+			sso: build!(sso::Service),
+			strangers: build!(strangers::Service),
 			sync: build!(sync::Service),
 			transaction_ids: build!(transaction_ids::Service),
 			uiaa: build!(uiaa::Service),
@@ -159,6 +210,15 @@ pub async fn stop(&self) {
 
 		self.admin.set_services(None);
 
+		// Persist the memtables and WAL so the next startup has nothing to
+		// recover.
+		if !self.db.is_read_only() {
+			info!("Flushing database...");
+			if let Err(e) = self.db.db.sort().and_then(|()| self.db.db.sync()) {
+				error!("Failed to flush database at shutdown: {e}");
+			}
+		}
+
 		debug_info!("Services shutdown complete.");
 	}
 
@@ -0,0 +1,228 @@
+//! Spam checking
+//!
+//! Events, invites, room creations and registrations are passed to the
+//! registered spam checker backends before they are accepted. Backends are
+//! consulted in the order they were registered and the first verdict other
+//! than [`Verdict::Allow`] is final. A webhook backend POSTing checks to an
+//! external HTTP service is built in and registered from the config.
+
+mod webhook;
+
+use std::{
+	fmt,
+	sync::{Arc, RwLock},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, PduEvent, Result, debug_info, warn};
+use ruma::{EventId, RoomId, UserId, events::TimelineEventType};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+
+pub use self::webhook::Webhook;
+use crate::client;
+
+pub struct Service {
+	backends: RwLock<Vec<Arc<dyn Backend>>>,
+	fail_open: bool,
+}
+
+/// Spam checker backend.
+#[async_trait]
+pub trait Backend: Send + Sync {
+	/// Decide whether the action described by `check` is allowed.
+	async fn check(&self, check: &Check<'_>) -> Result<Verdict>;
+}
+
+/// Action passed to the spam checker. Its debug representation leaves out the
+/// content of events, so checks can be logged without private messages.
+#[derive(Serialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum Check<'a> {
+	/// An event sent by a local user, or received over federation
+	Event {
+		/// The ID of the event; None for events of local users which were not
+		/// created yet
+		#[serde(skip_serializing_if = "Option::is_none")]
+		event_id: Option<&'a EventId>,
+		room_id: &'a RoomId,
+		sender: &'a UserId,
+		#[serde(rename = "type")]
+		kind: &'a TimelineEventType,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		state_key: Option<&'a str>,
+		content: &'a RawJsonValue,
+	},
+
+	/// An invite received by a local user
+	Invite {
+		room_id: &'a RoomId,
+		inviter: &'a UserId,
+		invitee: &'a UserId,
+	},
+
+	/// A room being created by a local user
+	RoomCreation {
+		user_id: &'a UserId,
+	},
+
+	/// An account being registered
+	Registration {
+		user_id: &'a UserId,
+		guest: bool,
+	},
+}
+
+impl fmt::Debug for Check<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Event {
+				event_id,
+				room_id,
+				sender,
+				kind,
+				state_key,
+				..
+			} => f
+				.debug_struct("Event")
+				.field("event_id", event_id)
+				.field("room_id", room_id)
+				.field("sender", sender)
+				.field("kind", kind)
+				.field("state_key", state_key)
+				.finish_non_exhaustive(),
+			| Self::Invite { room_id, inviter, invitee } => f
+				.debug_struct("Invite")
+				.field("room_id", room_id)
+				.field("inviter", inviter)
+				.field("invitee", invitee)
+				.finish(),
+			| Self::RoomCreation { user_id } => f
+				.debug_struct("RoomCreation")
+				.field("user_id", user_id)
+				.finish(),
+			| Self::Registration { user_id, guest } => f
+				.debug_struct("Registration")
+				.field("user_id", user_id)
+				.field("guest", guest)
+				.finish(),
+		}
+	}
+}
+
+/// Verdict of a spam checker backend.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Verdict {
+	/// The action is allowed
+	#[default]
+	Allow,
+
+	/// The action is rejected with an error
+	Deny {
+		#[serde(default)]
+		reason: Option<String>,
+	},
+
+	/// Events are accepted but not shown to other users; other actions are
+	/// rejected like with [`Verdict::Deny`]
+	SoftFail,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.spam_checker;
+		let mut backends: Vec<Arc<dyn Backend>> = Vec::new();
+		if let Some(url) = &config.webhook_url {
+			let client = args.require::<client::Service>("client");
+			backends.push(Arc::new(Webhook::new(
+				client.default.clone(),
+				url.clone(),
+				config.webhook_token.clone(),
+				Duration::from_secs(config.webhook_timeout),
+			)));
+		}
+
+		Ok(Arc::new(Self {
+			backends: RwLock::new(backends),
+			fail_open: config.fail_open,
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Register an additional backend, consulted after the existing ones.
+	pub fn register(&self, backend: Arc<dyn Backend>) {
+		self.backends
+			.write()
+			.expect("locked for writing")
+			.push(backend);
+	}
+
+	/// Whether any backend is registered.
+	#[must_use]
+	pub fn is_enabled(&self) -> bool {
+		!self.backends.read().expect("locked for reading").is_empty()
+	}
+
+	/// Pass `check` to the registered backends. Backends failing to respond
+	/// allow the action if `spam_checker.fail_open` is enabled, and deny it
+	/// otherwise.
+	pub async fn check(&self, check: &Check<'_>) -> Verdict {
+		let backends = self.backends.read().expect("locked for reading").clone();
+		for backend in backends {
+			let verdict = match backend.check(check).await {
+				| Ok(verdict) => verdict,
+				| Err(e) => {
+					warn!(?check, "Spam checker failed: {e}");
+					if self.fail_open {
+						continue;
+					}
+
+					Verdict::Deny { reason: None }
+				},
+			};
+
+			if verdict != Verdict::Allow {
+				debug_info!(?check, ?verdict, "Spam checker did not allow action");
+				return verdict;
+			}
+		}
+
+		Verdict::Allow
+	}
+
+	/// Pass `check` to the registered backends, returning an M_FORBIDDEN error
+	/// unless the action is allowed.
+	pub async fn check_allowed(&self, check: &Check<'_>) -> Result {
+		self.check(check).await.allowed()
+	}
+}
+
+impl Verdict {
+	/// Returns an M_FORBIDDEN error unless the action is allowed.
+	pub fn allowed(self) -> Result {
+		match self {
+			| Self::Allow => Ok(()),
+			| Self::Deny { reason: Some(reason) } => Err!(Request(Forbidden("{reason}"))),
+			| Self::Deny { reason: None } | Self::SoftFail =>
+				Err!(Request(Forbidden("This action was rejected by the spam checker."))),
+		}
+	}
+}
+
+impl<'a> From<&'a PduEvent> for Check<'a> {
+	fn from(pdu: &'a PduEvent) -> Self {
+		Self::Event {
+			event_id: Some(&pdu.event_id),
+			room_id: &pdu.room_id,
+			sender: &pdu.sender,
+			kind: &pdu.kind,
+			state_key: pdu.state_key.as_deref(),
+			content: &pdu.content,
+		}
+	}
+}
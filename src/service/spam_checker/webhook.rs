@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result};
+use reqwest::header::CONTENT_TYPE;
+use url::Url;
+
+use super::{Backend, Check, Verdict};
+
+/// Backend POSTing checks as JSON to an external HTTP service, which responds
+/// with a JSON object whose `action` is `allow`, `deny` (with an optional
+/// `reason`) or `soft_fail`.
+pub struct Webhook {
+	client: reqwest::Client,
+	url: Url,
+	token: Option<String>,
+	timeout: Duration,
+}
+
+impl Webhook {
+	#[must_use]
+	pub fn new(
+		client: reqwest::Client,
+		url: Url,
+		token: Option<String>,
+		timeout: Duration,
+	) -> Self {
+		Self { client, url, token, timeout }
+	}
+}
+
+#[async_trait]
+impl Backend for Webhook {
+	async fn check(&self, check: &Check<'_>) -> Result<Verdict> {
+		let mut request = self
+			.client
+			.post(self.url.clone())
+			.timeout(self.timeout)
+			.header(CONTENT_TYPE, "application/json")
+			.body(serde_json::to_vec(check)?);
+
+		if let Some(token) = &self.token {
+			request = request.bearer_auth(token);
+		}
+
+		let response = request.send().await?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err!("Spam checker webhook responded with {status}");
+		}
+
+		let body = response.bytes().await?;
+
+		Ok(serde_json::from_slice(&body)?)
+	}
+}
@@ -0,0 +1,436 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Error, Result, Server, config::SsoProvider, debug, debug_warn, err, http::StatusCode,
+	implement, utils,
+};
+use database::{Deserialized, Map};
+use ruma::{OwnedUserId, UserId, api::client::error::ErrorKind};
+use serde::Deserialize;
+use serde_json::{Map as JsonObject, Value as JsonValue};
+use url::Url;
+
+use crate::{Dep, client, globals, users};
+
+pub struct Service {
+	sessions: RwLock<Sessions>,
+	metadata: RwLock<HashMap<String, (Metadata, Instant)>>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	ssoprovidersubject_userid: Arc<Map>,
+}
+
+/// Login started by redirecting the user to a provider, keyed by the `state`
+/// sent along.
+#[derive(Debug)]
+struct Session {
+	provider: String,
+	redirect_url: Url,
+	expires: Instant,
+}
+
+type Sessions = HashMap<String, Session>;
+
+/// Endpoints of a provider; only the fields we use of its OpenID Connect
+/// metadata.
+#[derive(Clone, Debug, Deserialize)]
+struct Metadata {
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	userinfo_endpoint: Url,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	access_token: String,
+}
+
+/// Path of the endpoint providers redirect users back to
+pub const CALLBACK_PATH: &str = "/_conduwuit/sso/callback";
+
+/// Duration users have to complete the login at the provider
+const SESSION_LIFETIME: Duration = Duration::from_secs(600);
+
+/// Maximum number of concurrent logins
+const MAX_SESSIONS: usize = 1024;
+
+/// Length of the randomly generated `state` of logins
+const STATE_LENGTH: usize = 32;
+
+/// Duration for which the metadata of providers is cached
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			sessions: RwLock::new(Sessions::new()),
+			metadata: RwLock::new(HashMap::new()),
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				ssoprovidersubject_userid: args.db["ssoprovidersubject_userid"].clone(),
+			},
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let sessions = self.sessions.read().expect("locked for reading").len();
+		writeln!(out, "sso_sessions: {sessions}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.metadata.write().expect("locked for writing").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// The configured upstream providers.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn providers(&self) -> &[SsoProvider] { &self.services.server.config.sso_providers }
+
+/// Whether users may log in through an upstream provider.
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn enabled(&self) -> bool { !self.providers().is_empty() }
+
+/// Start a login through the provider `provider_id`, or the first provider if
+/// None. Returns the URL of the provider to redirect the user to; once they
+/// return, they are redirected to `redirect_url` with a login token.
+#[implement(Service)]
+pub async fn start_login(&self, provider_id: Option<&str>, redirect_url: &str) -> Result<Url> {
+	let provider = match provider_id {
+		| Some(provider_id) => self.provider(provider_id)?,
+		| None => self
+			.providers()
+			.first()
+			.ok_or_else(|| err!(Request(NotFound("No SSO providers are configured."))))?,
+	};
+
+	let redirect_url = Url::parse(redirect_url)
+		.map_err(|e| err!(Request(InvalidParam("Invalid redirect URL: {e}"))))?;
+
+	if matches!(redirect_url.scheme(), "javascript" | "data" | "vbscript") {
+		return Err!(Request(InvalidParam("Invalid redirect URL scheme.")));
+	}
+
+	let metadata = self.metadata(provider).await?;
+	let state = utils::random_string(STATE_LENGTH);
+	let session = Session {
+		provider: provider.id.clone(),
+		redirect_url,
+		expires: Instant::now()
+			.checked_add(SESSION_LIFETIME)
+			.unwrap_or_else(Instant::now),
+	};
+
+	{
+		let mut sessions = self.sessions.write().expect("locked for writing");
+		let now = Instant::now();
+		sessions.retain(|_, session| session.expires > now);
+		if sessions.len() >= MAX_SESSIONS {
+			return Err(Error::Request(
+				ErrorKind::LimitExceeded { retry_after: None },
+				"Too many SSO logins in progress.".into(),
+				StatusCode::TOO_MANY_REQUESTS,
+			));
+		}
+
+		sessions.insert(state.clone(), session);
+	}
+
+	let mut url = metadata.authorization_endpoint;
+	url.query_pairs_mut()
+		.append_pair("response_type", "code")
+		.append_pair("client_id", &provider.client_id)
+		.append_pair("redirect_uri", &self.callback_url())
+		.append_pair("scope", &provider.scopes.join(" "))
+		.append_pair("state", &state);
+
+	Ok(url)
+}
+
+/// Whether users are sent back to `redirect_url` without confirming first;
+/// see `sso_redirect_allowlist`.
+#[implement(Service)]
+#[must_use]
+pub fn redirect_allowed(&self, redirect_url: &Url) -> bool {
+	self.services
+		.server
+		.config
+		.sso_redirect_allowlist
+		.iter()
+		.any(|prefix| redirect_url.as_str().starts_with(prefix.as_str()))
+}
+
+/// Complete a login the user returned from the provider with. Returns the
+/// local user they are logged in as, registering them on their first login,
+/// and the URL to redirect them to.
+#[implement(Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn finish_login(&self, state: &str, code: &str) -> Result<(OwnedUserId, Url)> {
+	let session = self
+		.sessions
+		.write()
+		.expect("locked for writing")
+		.remove(state)
+		.filter(|session| session.expires > Instant::now())
+		.ok_or_else(|| err!(Request(Forbidden("Unknown or expired SSO login."))))?;
+
+	let provider = self.provider(&session.provider)?;
+	let metadata = self.metadata(provider).await?;
+
+	let response = self
+		.services
+		.client
+		.default
+		.post(metadata.token_endpoint)
+		.basic_auth(&provider.client_id, provider.client_secret.as_deref())
+		.header("Accept", "application/json")
+		.form(&[
+			("grant_type", "authorization_code"),
+			("client_id", &provider.client_id),
+			("code", code),
+			("redirect_uri", &self.callback_url()),
+		])
+		.send()
+		.await?;
+
+	if !response.status().is_success() {
+		let status = response.status();
+		debug_warn!(provider = %provider.id, ?status, "Token exchange failed");
+		return Err!(BadServerResponse("Token exchange failed: {status}"));
+	}
+
+	let token: TokenResponse = serde_json::from_str(&response.text().await?)?;
+
+	let response = self
+		.services
+		.client
+		.default
+		.get(metadata.userinfo_endpoint)
+		.bearer_auth(&token.access_token)
+		.header("Accept", "application/json")
+		.send()
+		.await?;
+
+	if !response.status().is_success() {
+		let status = response.status();
+		debug_warn!(provider = %provider.id, ?status, "Fetching userinfo failed");
+		return Err!(BadServerResponse("Fetching userinfo failed: {status}"));
+	}
+
+	let claims: JsonObject<String, JsonValue> = serde_json::from_str(&response.text().await?)?;
+	debug!(provider = %provider.id, ?claims, "Userinfo");
+
+	let user_id = self.user_for(provider, &claims).await?;
+
+	Ok((user_id, session.redirect_url))
+}
+
+/// The local user of the subject in `claims`, registering them if the
+/// subject is new.
+#[implement(Service)]
+async fn user_for(
+	&self,
+	provider: &SsoProvider,
+	claims: &JsonObject<String, JsonValue>,
+) -> Result<OwnedUserId> {
+	let subject = claim(claims, &provider.subject_claim).ok_or_else(|| {
+		err!(Request(Forbidden(
+			"Provider did not return the `{}` claim.",
+			provider.subject_claim
+		)))
+	})?;
+
+	let key = (provider.id.as_str(), subject.as_str());
+	if let Ok(user_id) = self
+		.db
+		.ssoprovidersubject_userid
+		.qry(&key)
+		.await
+		.deserialized::<OwnedUserId>()
+	{
+		if self
+			.services
+			.users
+			.is_deactivated(&user_id)
+			.await
+			.unwrap_or(false)
+		{
+			return Err!(Request(UserDeactivated("The user has been deactivated")));
+		}
+
+		return Ok(user_id);
+	}
+
+	let localpart = claim(claims, &provider.localpart_claim)
+		.as_deref()
+		.map(localpart_from)
+		.filter(|localpart| !localpart.is_empty())
+		.ok_or_else(|| {
+			err!(Request(Forbidden(
+				"Provider did not return a usable `{}` claim.",
+				provider.localpart_claim
+			)))
+		})?;
+
+	let user_id = UserId::parse_with_server_name(localpart, self.services.globals.server_name())
+		.map_err(|e| err!(Request(InvalidUsername("Username is invalid: {e}"))))?;
+
+	let users = &self.services.users;
+	if users.exists(&user_id).await {
+		return Err!(Request(UserInUse(
+			"The username {user_id} is already taken by an account not linked to this provider."
+		)));
+	}
+
+	debug!(%user_id, provider = %provider.id, %subject, "Registering user from SSO provider");
+	let source = format!("SSO provider {}", provider.id);
+	let displayname = claim(claims, &provider.displayname_claim);
+	let provisioned = users.provision(&user_id, displayname, &source).await;
+
+	// accounts awaiting approval are linked all the same before the login fails
+	if users.exists(&user_id).await {
+		self.db.ssoprovidersubject_userid.put(key, &user_id);
+	}
+
+	provisioned.map(|()| user_id)
+}
+
+#[implement(Service)]
+fn provider(&self, provider_id: &str) -> Result<&SsoProvider> {
+	self.providers()
+		.iter()
+		.find(|provider| provider.id == provider_id)
+		.ok_or_else(|| err!(Request(NotFound("Unknown SSO provider {provider_id:?}."))))
+}
+
+/// Endpoints of the provider, discovered from the issuer's OpenID Connect
+/// metadata unless all of them are configured.
+#[implement(Service)]
+async fn metadata(&self, provider: &SsoProvider) -> Result<Metadata> {
+	if let (Some(authorization_endpoint), Some(token_endpoint), Some(userinfo_endpoint)) = (
+		&provider.authorization_endpoint,
+		&provider.token_endpoint,
+		&provider.userinfo_endpoint,
+	) {
+		return Ok(Metadata {
+			authorization_endpoint: authorization_endpoint.clone(),
+			token_endpoint: token_endpoint.clone(),
+			userinfo_endpoint: userinfo_endpoint.clone(),
+		});
+	}
+
+	let cached = self
+		.metadata
+		.read()
+		.expect("locked for reading")
+		.get(&provider.id)
+		.filter(|(_, fetched)| fetched.elapsed() < METADATA_CACHE_TTL)
+		.map(|(metadata, _)| metadata.clone());
+
+	let discovered = match cached {
+		| Some(metadata) => metadata,
+		| None => {
+			let endpoint = provider
+				.issuer
+				.join(".well-known/openid-configuration")
+				.map_err(|e| err!(Config("sso_providers", "Invalid issuer URL: {e}")))?;
+
+			let response = self.services.client.default.get(endpoint).send().await?;
+			if !response.status().is_success() {
+				return Err!(BadServerResponse(
+					"Fetching metadata of SSO provider {} failed: {}",
+					provider.id,
+					response.status()
+				));
+			}
+
+			let metadata: Metadata = serde_json::from_str(&response.text().await?)?;
+			self.metadata
+				.write()
+				.expect("locked for writing")
+				.insert(provider.id.clone(), (metadata.clone(), Instant::now()));
+
+			metadata
+		},
+	};
+
+	Ok(Metadata {
+		authorization_endpoint: provider
+			.authorization_endpoint
+			.clone()
+			.unwrap_or(discovered.authorization_endpoint),
+		token_endpoint: provider
+			.token_endpoint
+			.clone()
+			.unwrap_or(discovered.token_endpoint),
+		userinfo_endpoint: provider
+			.userinfo_endpoint
+			.clone()
+			.unwrap_or(discovered.userinfo_endpoint),
+	})
+}
+
+#[implement(Service)]
+fn callback_url(&self) -> String {
+	let base_url = self
+		.services
+		.server
+		.config
+		.well_known
+		.client
+		.as_ref()
+		.map_or_else(
+			|| format!("https://{}", self.services.server.config.server_name),
+			|url| url.as_str().trim_end_matches('/').to_owned(),
+		);
+
+	format!("{base_url}{CALLBACK_PATH}")
+}
+
+/// A string or numeric claim as a string.
+fn claim(claims: &JsonObject<String, JsonValue>, name: &str) -> Option<String> {
+	match claims.get(name)? {
+		| JsonValue::String(value) => Some(value.clone()),
+		| JsonValue::Number(value) => Some(value.to_string()),
+		| _ => None,
+	}
+}
+
+/// Lowercase `value` and replace characters not allowed in localparts.
+fn localpart_from(value: &str) -> String {
+	value
+		.to_lowercase()
+		.chars()
+		.map(|c| match c {
+			| 'a'..='z' | '0'..='9' | '.' | '_' | '=' | '-' | '/' => c,
+			| _ => '_',
+		})
+		.collect()
+}
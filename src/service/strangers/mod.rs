@@ -0,0 +1,274 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug_warn, implement, info,
+	utils::{ReadyExt, millis_since_unix_epoch, stream::TryIgnore},
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId,
+	ServerName, UserId,
+	events::{
+		AnyStrippedStateEvent, AnyTimelineEvent,
+		room::member::{MembershipState, RoomMemberEventContent},
+	},
+	serde::Raw,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{Dep, admin, globals, rooms, sending, users::glob_matches};
+
+/// Invites from strangers: servers which share no room with this server, or
+/// users matching `stranger_invite_patterns`. Depending on
+/// `stranger_invite_policy` they are rejected or held back from the invited
+/// user until an admin releases them.
+pub struct Service {
+	interrupt: Notify,
+	/// Invites held since the admins were last told, by origin
+	unannounced: Mutex<BTreeMap<OwnedServerName, usize>>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+}
+
+struct Data {
+	heldinviteid_invite: Arc<Map>,
+}
+
+/// Invite received over federation which is held back from its recipient.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HeldInvite {
+	pub room_id: OwnedRoomId,
+	pub sender: OwnedUserId,
+	pub recipient: OwnedUserId,
+
+	/// Stripped state of the room sent along with the invite
+	pub invite_state: Vec<Raw<AnyStrippedStateEvent>>,
+
+	/// The invite event, pushed to appservices on release
+	pub event_id: OwnedEventId,
+	pub event: Raw<AnyTimelineEvent>,
+
+	/// Servers to join the room through, if the inviting server sent them
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub via: Option<Vec<OwnedServerName>>,
+
+	/// When the invite was received
+	pub received_ts: MilliSecondsSinceUnixEpoch,
+}
+
+/// Interval at which the admins are told about newly held invites, and old
+/// ones are expired
+const NOTICE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Time after which a held invite nobody released is dropped
+const HELD_EXPIRE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Number of invites held from one server at most; further invites from it
+/// are rejected until some are released, discarded or expire
+const HELD_PER_ORIGIN: usize = 50;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			unannounced: Mutex::default(),
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+			},
+			db: Data {
+				heldinviteid_invite: args.db["heldinviteid_invite"].clone(),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = sleep(NOTICE_INTERVAL) => (),
+			}
+
+			self.announce().await;
+			self.expire().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether an invite sent by `sender` through `origin` is from a stranger.
+#[implement(Service)]
+pub async fn is_stranger(&self, origin: &ServerName, sender: &UserId) -> bool {
+	let patterns = &self.services.server.config.stranger_invite_patterns;
+	if patterns
+		.iter()
+		.any(|pattern| glob_matches(pattern, sender.as_str()))
+	{
+		return true;
+	}
+
+	!self
+		.services
+		.state_cache
+		.server_rooms(origin)
+		.any(|room_id| {
+			self.services
+				.state_cache
+				.server_in_room(self.services.globals.server_name(), room_id)
+		})
+		.await
+}
+
+/// Hold back an invite from its recipient until an admin releases it. The
+/// admins are told about held invites together every few minutes. Fails when
+/// too many invites from the sender's server are held already.
+#[implement(Service)]
+pub async fn hold(&self, invite: &HeldInvite) -> Result<u64> {
+	let origin = invite.sender.server_name();
+	let held = self
+		.held()
+		.ready_filter(|(_, held)| held.sender.server_name() == origin)
+		.count()
+		.await;
+
+	if held >= HELD_PER_ORIGIN {
+		return Err!(Request(Forbidden(
+			"Too many invites from {origin} are awaiting review by the admins."
+		)));
+	}
+
+	let id = self.services.globals.next_count()?;
+	self.db.heldinviteid_invite.put(id, Json(invite));
+
+	info!(
+		%id,
+		room_id = %invite.room_id,
+		sender = %invite.sender,
+		recipient = %invite.recipient,
+		"Holding invite from a stranger for review"
+	);
+
+	let mut unannounced = self.unannounced.lock().expect("locked");
+	let count = unannounced.entry(origin.to_owned()).or_default();
+	*count = count.saturating_add(1);
+
+	Ok(id)
+}
+
+/// Tell the admins how many invites were held since they were last told.
+#[implement(Service)]
+async fn announce(&self) {
+	let unannounced = std::mem::take(&mut *self.unannounced.lock().expect("locked"));
+	if unannounced.is_empty() {
+		return;
+	}
+
+	let total: usize = unannounced.values().sum();
+	let mut text = format!("Held {total} new invites from strangers:\n");
+	for (origin, count) in &unannounced {
+		writeln!(text, "- {count} from {origin}").expect("write to string");
+	}
+
+	text.push_str(
+		"\nList them with `!admin federation held-invites list`, then release or discard them \
+		 with `!admin federation held-invites release <id>` or `discard <id>`.",
+	);
+
+	self.services.admin.send_text(&text).await;
+}
+
+/// Drop the held invites nobody released within `HELD_EXPIRE`.
+#[implement(Service)]
+async fn expire(&self) {
+	let expire = millis_since_unix_epoch()
+		.saturating_sub(HELD_EXPIRE.as_millis().try_into().unwrap_or(u64::MAX));
+
+	let expired: Vec<u64> = self
+		.held()
+		.ready_filter(|(_, invite)| u64::from(invite.received_ts.get()) < expire)
+		.map(|(id, _)| id)
+		.collect()
+		.await;
+
+	for id in expired {
+		debug_warn!(%id, "Dropping held invite nobody released");
+		self.db.heldinviteid_invite.del(id);
+	}
+}
+
+/// Invites which are held back, oldest first.
+#[implement(Service)]
+pub fn held(&self) -> impl Stream<Item = (u64, HeldInvite)> + Send + '_ {
+	self.db
+		.heldinviteid_invite
+		.stream::<u64, HeldInvite>()
+		.ignore_err()
+}
+
+/// Get a held invite by its ID.
+#[implement(Service)]
+pub async fn get_held(&self, id: u64) -> Result<HeldInvite> {
+	self.db.heldinviteid_invite.qry(&id).await.deserialized()
+}
+
+/// Deliver a held invite to its recipient and the appservices interested in
+/// them.
+#[implement(Service)]
+pub async fn release(&self, id: u64) -> Result<HeldInvite> {
+	let invite = self.get_held(id).await?;
+	self.services
+		.state_cache
+		.update_membership(
+			&invite.room_id,
+			&invite.recipient,
+			RoomMemberEventContent::new(MembershipState::Invite),
+			&invite.sender,
+			Some(invite.invite_state.clone()),
+			invite.via.clone(),
+			true,
+		)
+		.await?;
+
+	self.db.heldinviteid_invite.del(id);
+
+	self.services
+		.sending
+		.send_invite_appservices(&invite.recipient, &invite.event_id, &invite.event)
+		.await?;
+
+	Ok(invite)
+}
+
+/// Drop a held invite without delivering it.
+#[implement(Service)]
+pub async fn discard(&self, id: u64) -> Result<HeldInvite> {
+	let invite = self.get_held(id).await?;
+	self.db.heldinviteid_invite.del(id);
+
+	Ok(invite)
+}
@@ -1,26 +1,85 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
-use conduwuit::{Result, implement};
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Result, Server, debug, err, implement,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+	},
+};
 use database::{Handle, Map};
-use ruma::{DeviceId, TransactionId, UserId};
+use futures::StreamExt;
+use ruma::{DeviceId, OwnedEventId, ServerName, TransactionId, UserId};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, maintenance};
 
 pub struct Service {
+	interrupt: Notify,
+	server: Arc<Server>,
+	services: Services,
 	db: Data,
 }
 
+struct Services {
+	maintenance: Dep<maintenance::Service>,
+}
+
 struct Data {
 	userdevicetxnid_response: Arc<Map>,
+	servertxnid_response: Arc<Map>,
 }
 
+/// Results of an inbound federation transaction, keyed by event ID.
+pub type ServerTxnResults = BTreeMap<OwnedEventId, Result<(), String>>;
+
+const EXPIRES_AT_LEN: usize = size_of::<u64>();
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 15);
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			server: args.server.clone(),
+			services: Services {
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
+			},
 			db: Data {
 				userdevicetxnid_response: args.db["userdevicetxnid_response"].clone(),
+				servertxnid_response: args.db["servertxnid_response"].clone(),
 			},
 		}))
 	}
 
+	#[tracing::instrument(skip_all, name = "transaction_ids", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(PRUNE_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.services.maintenance.defer("prune federation transactions") => (),
+			}
+
+			let pruned = self.prune_server_txnids().await;
+			debug!(pruned, "Pruned expired federation transaction results");
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -52,3 +111,82 @@ pub async fn existing_txnid(
 	let key = (user_id, device_id, txn_id);
 	self.db.userdevicetxnid_response.qry(&key).await
 }
+
+/// Persist the results of a processed inbound federation transaction so a
+/// retry of the same transaction receives an identical response.
+#[implement(Service)]
+pub fn add_server_txnid(
+	&self,
+	origin: &ServerName,
+	txn_id: &TransactionId,
+	results: &ServerTxnResults,
+) -> Result {
+	use std::num::Saturating as Sat;
+
+	let ttl = self.server.config.federation_txn_cache_ttl;
+	if ttl == 0 {
+		return Ok(());
+	}
+
+	let expires_at = Sat(utils::millis_since_unix_epoch()) + Sat(ttl) * Sat(1000);
+	let mut value = expires_at.0.to_be_bytes().to_vec();
+	serde_json::to_writer(&mut value, results)?;
+
+	let key = (origin, txn_id);
+	self.db.servertxnid_response.put_raw(key, value);
+
+	Ok(())
+}
+
+/// Find the cached results of a previously processed inbound federation
+/// transaction. If there's no unexpired entry, this is a new transaction.
+#[implement(Service)]
+pub async fn existing_server_txnid(
+	&self,
+	origin: &ServerName,
+	txn_id: &TransactionId,
+) -> Result<ServerTxnResults> {
+	let key = (origin, txn_id);
+	let value = self.db.servertxnid_response.qry(&key).await?;
+	let (expires_at, results) = split_expires_at(&value)?;
+	if expires_at < utils::millis_since_unix_epoch() {
+		self.db.servertxnid_response.del(key);
+		return Err!(Request(NotFound("Transaction results expired")));
+	}
+
+	serde_json::from_slice(results)
+		.map_err(|e| err!(Database("Invalid cached transaction results: {e}")))
+}
+
+/// Remove all expired inbound federation transaction results. Returns the
+/// number of entries removed.
+#[implement(Service)]
+pub async fn prune_server_txnids(&self) -> usize {
+	let now = utils::millis_since_unix_epoch();
+	self.db
+		.servertxnid_response
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, value)| {
+			split_expires_at(value)
+				.ok()
+				.filter(|(expires_at, _)| *expires_at < now)
+				.map(|_| key.to_vec())
+		})
+		.collect::<Vec<_>>()
+		.await
+		.into_iter()
+		.inspect(|key| self.db.servertxnid_response.remove(key))
+		.count()
+}
+
+fn split_expires_at(value: &[u8]) -> Result<(u64, &[u8])> {
+	if value.len() < EXPIRES_AT_LEN {
+		return Err!(Database("Cached transaction results are truncated"));
+	}
+
+	let (expires_at, results) = value.split_at(EXPIRES_AT_LEN);
+	let expires_at = u64::from_be_bytes(expires_at.try_into()?);
+
+	Ok((expires_at, results))
+}
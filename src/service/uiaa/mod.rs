@@ -1,13 +1,14 @@
 use std::{
-	collections::{BTreeMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashSet},
 	sync::{Arc, RwLock},
 };
 
 use conduwuit::{
 	Err, Error, Result, err, error, implement, utils,
-	utils::{hash, string::EMPTY},
+	utils::{hash, stream::TryIgnore, string::EMPTY},
 };
 use database::{Deserialized, Json, Map};
+use futures::StreamExt;
 use ruma::{
 	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 	api::client::{
@@ -16,18 +17,21 @@
 	},
 };
 
-use crate::{Dep, config, globals, users};
+use crate::{Dep, config, globals, ldap, registration_tokens, users};
 
 pub struct Service {
 	userdevicesessionid_uiaarequest: RwLock<RequestMap>,
+	userdevicesessionid_uiaastarted: RwLock<StartedMap>,
 	db: Data,
 	services: Services,
 }
 
 struct Services {
 	globals: Dep<globals::Service>,
+	ldap: Dep<ldap::Service>,
 	users: Dep<users::Service>,
 	config: Dep<config::Service>,
+	registration_tokens: Dep<registration_tokens::Service>,
 }
 
 struct Data {
@@ -36,6 +40,7 @@ struct Data {
 
 type RequestMap = BTreeMap<RequestKey, CanonicalJsonValue>;
 type RequestKey = (OwnedUserId, OwnedDeviceId, String);
+type StartedMap = BTreeMap<RequestKey, u64>;
 
 pub const SESSION_ID_LENGTH: usize = 32;
 
@@ -43,13 +48,17 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			userdevicesessionid_uiaarequest: RwLock::new(RequestMap::new()),
+			userdevicesessionid_uiaastarted: RwLock::new(StartedMap::new()),
 			db: Data {
 				userdevicesessionid_uiaainfo: args.db["userdevicesessionid_uiaainfo"].clone(),
 			},
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
+				ldap: args.depend::<ldap::Service>("ldap"),
 				users: args.depend::<users::Service>("users"),
 				config: args.depend::<config::Service>("config"),
+				registration_tokens: args
+					.depend::<registration_tokens::Service>("registration_tokens"),
 			},
 		}))
 	}
@@ -162,24 +171,47 @@ pub async fn try_auth(
 			}
 			let user_id = user_id_from_username;
 
-			// Check if password is correct
-			if let Ok(hash) = self.services.users.password_hash(&user_id).await {
-				let hash_matches = hash::verify_password(password, &hash).is_ok();
-				if !hash_matches {
-					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-						kind: ErrorKind::forbidden(),
-						message: "Invalid username or password.".to_owned(),
-					});
-					return Ok((false, uiaainfo));
-				}
+			// Check if password is correct; accounts in the LDAP directory
+			// authenticate against it
+			let ldap_login = if self.services.ldap.enabled() {
+				Some(self.services.ldap.login(&user_id, password).await)
+			} else {
+				None
+			};
+
+			let password_matches = match ldap_login {
+				| Some(Ok(true)) => true,
+				| Some(Err(_)) => false,
+				| Some(Ok(false)) | None => self
+					.services
+					.users
+					.password_hash(&user_id)
+					.await
+					.map_or(true, |hash| hash::verify_password(password, &hash).is_ok()),
+			};
+
+			if !password_matches {
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: ErrorKind::forbidden(),
+					message: "Invalid username or password.".to_owned(),
+				});
+				return Ok((false, uiaainfo));
 			}
 
 			// Password was correct! Let's add it to `completed`
 			uiaainfo.completed.push(AuthType::Password);
 		},
 		| AuthData::RegistrationToken(t) => {
+			// issued tokens are only used up right before the account is created
+			let token = t.token.trim();
 			let tokens = self.read_tokens().await?;
-			if tokens.contains(t.token.trim()) {
+			if tokens.contains(token)
+				|| self
+					.services
+					.registration_tokens
+					.is_token_valid(token)
+					.await
+			{
 				uiaainfo.completed.push(AuthType::RegistrationToken);
 			} else {
 				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
@@ -273,16 +305,80 @@ fn update_uiaa_session(
 	uiaainfo: Option<&UiaaInfo>,
 ) {
 	let key = (user_id, device_id, session);
+	let started_key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
+	let mut started = self
+		.userdevicesessionid_uiaastarted
+		.write()
+		.expect("locked for writing");
 
 	if let Some(uiaainfo) = uiaainfo {
+		started
+			.entry(started_key)
+			.or_insert_with(utils::millis_since_unix_epoch);
+
 		self.db
 			.userdevicesessionid_uiaainfo
 			.put(key, Json(uiaainfo));
 	} else {
+		started.remove(&started_key);
 		self.db.userdevicesessionid_uiaainfo.del(key);
 	}
 }
 
+/// Removes sessions started before the timestamp `before`. Sessions without a
+/// known start, such as those from before a restart, count as started when
+/// first seen here. Returns the number of sessions removed.
+#[implement(Service)]
+pub async fn prune(&self, before: u64) -> usize {
+	type Key<'a> = (&'a UserId, &'a DeviceId, &'a str);
+
+	let sessions: BTreeSet<RequestKey> = self
+		.db
+		.userdevicesessionid_uiaainfo
+		.keys::<Key<'_>>()
+		.ignore_err()
+		.map(|(user_id, device_id, session)| {
+			(user_id.to_owned(), device_id.to_owned(), session.to_owned())
+		})
+		.collect()
+		.await;
+
+	let now = utils::millis_since_unix_epoch();
+	let expired: Vec<RequestKey> = {
+		let mut started = self
+			.userdevicesessionid_uiaastarted
+			.write()
+			.expect("locked for writing");
+
+		started.retain(|key, _| sessions.contains(key));
+		let expired: Vec<_> = sessions
+			.into_iter()
+			.filter(|key| *started.entry(key.clone()).or_insert(now) < before)
+			.collect();
+
+		for key in &expired {
+			started.remove(key);
+		}
+
+		expired
+	};
+
+	let mut requests = self
+		.userdevicesessionid_uiaarequest
+		.write()
+		.expect("locked for writing");
+
+	for key in &expired {
+		requests.remove(key);
+		let (user_id, device_id, session) = key;
+		self.db
+			.userdevicesessionid_uiaainfo
+			.del((user_id, device_id, session));
+	}
+
+	expired.len()
+}
+
 #[implement(Service)]
 async fn get_uiaa_session(
 	&self,
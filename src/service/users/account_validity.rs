@@ -0,0 +1,315 @@
+//! Account validity
+//!
+//! With `account_validity.period_days` set, local accounts expire after that
+//! many days unless renewed, either through a link emailed to the user
+//! shortly before expiry or by an admin. Expired accounts are locked out of
+//! the client API until renewed, except for requesting a new renewal link;
+//! admins and users of appservices never expire.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use conduwuit::{
+	Err, Error, Result, debug,
+	http::StatusCode,
+	implement,
+	utils::{self, ReadyExt, millis_since_unix_epoch, stream::TryIgnore},
+	warn,
+};
+use database::{Deserialized, Json};
+use futures::StreamExt;
+use ruma::{
+	OwnedUserId, UserId,
+	api::client::error::{ErrorKind, RetryAfter},
+};
+use serde::{Deserialize, Serialize};
+
+/// Path of the link renewing an account
+pub const RENEW_PATH: &str = "/_conduwuit/account/renew";
+
+pub(super) const RENEWAL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Length of the randomly generated renewal token
+const RENEWAL_TOKEN_LENGTH: usize = 32;
+
+/// How long a renewal link can be used after it was sent
+const RENEWAL_TOKEN_LIFETIME_MS: u64 = 7 * DAY_MS;
+
+/// Minimum time between renewal links requested by the user
+const RENEWAL_RESEND_INTERVAL_MS: u64 = 10 * 60 * 1000;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AccountValidity {
+	/// Milliseconds since the unix epoch the account expires at
+	expires_at: u64,
+
+	/// Token of the renewal link emailed to the user, if any
+	renewal_token: Option<String>,
+
+	/// Milliseconds since the unix epoch the renewal link was sent at
+	#[serde(default)]
+	renewal_sent_at: u64,
+}
+
+impl AccountValidity {
+	fn new(expires_at: u64) -> Self {
+		Self {
+			expires_at,
+			renewal_token: None,
+			renewal_sent_at: 0,
+		}
+	}
+
+	/// Whether the renewal link sent to the user can still be used at `now`.
+	fn renewal_token_valid(&self, now: u64) -> bool {
+		let expires_at = self
+			.renewal_sent_at
+			.saturating_add(RENEWAL_TOKEN_LIFETIME_MS);
+
+		self.renewal_token.is_some() && now < expires_at
+	}
+}
+
+#[implement(super::Service)]
+#[must_use]
+pub fn account_validity_enabled(&self) -> bool {
+	self.services.server.config.account_validity.period_days > 0
+}
+
+/// Whether the account has expired. Accounts without an expiry yet, such as
+/// those created before account validity was enabled, are given a full
+/// period from now. Users in the namespace of an appservice never expire,
+/// as the appservice is not able to renew them.
+#[implement(super::Service)]
+pub async fn is_expired(&self, user_id: &UserId) -> bool {
+	if !self.account_validity_enabled() || !self.services.globals.user_is_local(user_id) {
+		return false;
+	}
+
+	if self.services.appservice.is_user_id(user_id).await {
+		return false;
+	}
+
+	let Ok(validity) = self.account_validity(user_id).await else {
+		self.start_account_validity(user_id);
+		return false;
+	};
+
+	validity.expires_at <= millis_since_unix_epoch() && !self.is_admin(user_id).await
+}
+
+/// Give a new account a full period from now.
+#[implement(super::Service)]
+pub(super) fn start_account_validity(&self, user_id: &UserId) {
+	let period = self.services.server.config.account_validity.period_days;
+	self.set_account_validity(user_id, &AccountValidity::new(expiry_from_now(period)));
+}
+
+/// Renew the account for `days`, or `account_validity.period_days` by
+/// default. Returns when the account expires next.
+#[implement(super::Service)]
+pub async fn renew_account(&self, user_id: &UserId, days: Option<u64>) -> Result<SystemTime> {
+	if !self.account_validity_enabled() {
+		return Err!(Config("account_validity.period_days", "Account expiry is not enabled."));
+	}
+
+	if let Ok(validity) = self.account_validity(user_id).await {
+		if let Some(token) = &validity.renewal_token {
+			self.db.renewaltoken_userid.remove(token);
+		}
+	}
+
+	let days = days.unwrap_or(self.services.server.config.account_validity.period_days);
+	let expires_at = expiry_from_now(days);
+	self.set_account_validity(user_id, &AccountValidity::new(expires_at));
+	debug!(%user_id, %expires_at, "Renewed account");
+
+	Ok(UNIX_EPOCH
+		.checked_add(Duration::from_millis(expires_at))
+		.unwrap_or(UNIX_EPOCH))
+}
+
+/// Renew the account the renewal link with `token` was sent for. Each token
+/// renews the account once, and only within its lifetime.
+#[implement(super::Service)]
+pub async fn renew_account_by_token(&self, token: &str) -> Result<(OwnedUserId, SystemTime)> {
+	let Ok(user_id) = self
+		.db
+		.renewaltoken_userid
+		.get(token)
+		.await
+		.deserialized::<OwnedUserId>()
+	else {
+		return Err!(Request(Forbidden("Invalid or already used renewal token.")));
+	};
+
+	let valid = self.account_validity(&user_id).await.is_ok_and(|validity| {
+		validity.renewal_token.as_deref() == Some(token)
+			&& validity.renewal_token_valid(millis_since_unix_epoch())
+	});
+
+	if !valid {
+		return Err!(Request(Forbidden(
+			"This renewal link has expired, request a new one from your client."
+		)));
+	}
+
+	let expires = self.renew_account(&user_id, None).await?;
+
+	Ok((user_id, expires))
+}
+
+/// Email a renewal link to the users whose account expires within
+/// `account_validity.renew_before_days` and who were not sent one yet, or
+/// whose link expired before their account did. Returns the number of emails
+/// sent.
+#[implement(super::Service)]
+pub async fn send_renewal_emails(&self) -> Result<usize> {
+	if !self.account_validity_enabled() || !self.services.email.is_enabled() {
+		return Ok(0);
+	}
+
+	let config = &self.services.server.config.account_validity;
+	let now = millis_since_unix_epoch();
+	let before = expiry_from_now(config.renew_before_days);
+	let expiring: Vec<(OwnedUserId, AccountValidity)> = self
+		.db
+		.userid_accountvalidity
+		.stream()
+		.ignore_err()
+		.ready_filter(|(_, validity): &(&UserId, AccountValidity)| {
+			validity.expires_at <= before
+				&& (validity.renewal_token.is_none()
+					|| (!validity.renewal_token_valid(now) && validity.expires_at > now))
+		})
+		.map(|(user_id, validity)| (user_id.to_owned(), validity))
+		.collect()
+		.await;
+
+	let mut sent: usize = 0;
+	for (user_id, validity) in expiring {
+		if self.services.appservice.is_user_id(&user_id).await {
+			continue;
+		}
+
+		let Ok(email) = self.services.email.email(&user_id).await else {
+			continue;
+		};
+
+		if let Err(e) = self.send_renewal_link(&user_id, &email, validity).await {
+			warn!(%user_id, "Failed to send account renewal email: {e}");
+			continue;
+		}
+
+		sent = sent.saturating_add(1);
+	}
+
+	Ok(sent)
+}
+
+/// Email a new renewal link to the user on their request, replacing the one
+/// sent before. Expired users may request one as well.
+#[implement(super::Service)]
+pub async fn resend_renewal_email(&self, user_id: &UserId) -> Result {
+	if !self.account_validity_enabled() {
+		return Err!(Config("account_validity.period_days", "Account expiry is not enabled."));
+	}
+
+	if !self.services.email.is_enabled() {
+		return Err!(Request(Forbidden("Sending emails is not enabled on this server.")));
+	}
+
+	let Ok(email) = self.services.email.email(user_id).await else {
+		return Err!(Request(Forbidden(
+			"No email address is bound to this account, ask an admin to renew it."
+		)));
+	};
+
+	let period = self.services.server.config.account_validity.period_days;
+	let validity = self
+		.account_validity(user_id)
+		.await
+		.unwrap_or_else(|_| AccountValidity::new(expiry_from_now(period)));
+
+	let next = validity
+		.renewal_sent_at
+		.saturating_add(RENEWAL_RESEND_INTERVAL_MS);
+	let wait = next.saturating_sub(millis_since_unix_epoch());
+	if validity.renewal_token.is_some() && wait > 0 {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded {
+				retry_after: Some(RetryAfter::Delay(Duration::from_millis(wait))),
+			},
+			"A renewal link was sent recently; try again later.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	self.send_renewal_link(user_id, &email, validity).await
+}
+
+/// Email a renewal link with a new token to the user, invalidating the one
+/// sent before.
+#[implement(super::Service)]
+async fn send_renewal_link(
+	&self,
+	user_id: &UserId,
+	email: &str,
+	validity: AccountValidity,
+) -> Result {
+	let token = utils::random_string(RENEWAL_TOKEN_LENGTH);
+	let link = format!("{}{RENEW_PATH}?token={token}", self.services.email.base_url());
+	let expires = UNIX_EPOCH
+		.checked_add(Duration::from_millis(validity.expires_at))
+		.unwrap_or(UNIX_EPOCH);
+
+	let body = format!(
+		"Your Matrix account {user_id} expires on {}.\n\nTo keep using it, open the following \
+		 link within {} days:\n\n{link}\n",
+		utils::time::format(expires, "%Y-%m-%d %H:%M UTC"),
+		RENEWAL_TOKEN_LIFETIME_MS / DAY_MS,
+	);
+
+	self.services
+		.email
+		.send(email, "Renew your account", body)
+		.await?;
+
+	if let Some(old_token) = &validity.renewal_token {
+		self.db.renewaltoken_userid.remove(old_token);
+	}
+
+	self.db
+		.renewaltoken_userid
+		.insert(&token, user_id.as_bytes());
+	self.set_account_validity(user_id, &AccountValidity {
+		renewal_token: Some(token),
+		renewal_sent_at: millis_since_unix_epoch(),
+		..validity
+	});
+
+	debug!(%user_id, "Sent account renewal email");
+
+	Ok(())
+}
+
+#[implement(super::Service)]
+async fn account_validity(&self, user_id: &UserId) -> Result<AccountValidity> {
+	self.db
+		.userid_accountvalidity
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+#[implement(super::Service)]
+fn set_account_validity(&self, user_id: &UserId, validity: &AccountValidity) {
+	self.db
+		.userid_accountvalidity
+		.raw_put(user_id, Json(validity));
+}
+
+fn expiry_from_now(days: u64) -> u64 {
+	millis_since_unix_epoch().saturating_add(days.saturating_mul(DAY_MS))
+}
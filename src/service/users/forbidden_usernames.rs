@@ -0,0 +1,111 @@
+use conduwuit::{Err, Result, config::ForbiddenUsernameAction, implement, utils::ReadyExt, warn};
+use futures::StreamExt;
+use ruma::{OwnedUserId, UserId};
+
+/// Local user whose name matches `forbidden_usernames`.
+#[derive(Debug)]
+pub struct ForbiddenUser {
+	pub user_id: OwnedUserId,
+
+	/// The patterns the localpart matches
+	pub patterns: Vec<String>,
+
+	/// Admins are reported but never acted on
+	pub is_admin: bool,
+}
+
+/// The patterns of `forbidden_usernames` the localpart of `user_id` matches.
+#[implement(super::Service)]
+#[must_use]
+pub fn forbidden_patterns(&self, user_id: &UserId) -> Vec<String> {
+	let patterns = self.services.globals.forbidden_usernames();
+	patterns
+		.matches(user_id.localpart())
+		.into_iter()
+		.map(|i| patterns.patterns()[i].clone())
+		.collect()
+}
+
+/// Refuse to create `user_id` when its name is forbidden and
+/// `forbidden_usernames_enforcement` is enabled, for accounts created other
+/// than by registration, which always refuses forbidden names.
+#[implement(super::Service)]
+pub fn check_forbidden_username(&self, user_id: &UserId) -> Result {
+	let action = self.services.server.config.forbidden_usernames_enforcement;
+	if action != ForbiddenUsernameAction::Warn && !self.forbidden_patterns(user_id).is_empty() {
+		return Err!(Request(Forbidden("Username is forbidden")));
+	}
+
+	Ok(())
+}
+
+/// Active local users whose names match `forbidden_usernames`.
+#[implement(super::Service)]
+pub async fn forbidden_users(&self) -> Vec<ForbiddenUser> {
+	if self.services.globals.forbidden_usernames().is_empty() {
+		return Vec::new();
+	}
+
+	let matching: Vec<(OwnedUserId, Vec<String>)> = self
+		.stream()
+		.filter(|user_id| self.is_active_local(user_id))
+		.ready_filter(|user_id| *user_id != self.services.globals.server_user.as_ref())
+		.ready_filter_map(|user_id| {
+			let patterns = self.forbidden_patterns(user_id);
+			(!patterns.is_empty()).then(|| (user_id.to_owned(), patterns))
+		})
+		.collect()
+		.await;
+
+	let mut users = Vec::with_capacity(matching.len());
+	for (user_id, patterns) in matching {
+		let is_admin = self.is_admin(&user_id).await;
+		users.push(ForbiddenUser { user_id, patterns, is_admin });
+	}
+
+	users
+}
+
+/// Apply `action` to the active local users whose names match
+/// `forbidden_usernames`, other than admins. Returns the matching users.
+///
+/// Users to deactivate are only locked here: deactivating them fully, which
+/// makes them leave their rooms, is left to the `enforce-forbidden-usernames`
+/// admin command, which is also run at startup.
+#[implement(super::Service)]
+pub async fn enforce_forbidden_usernames(
+	&self,
+	action: ForbiddenUsernameAction,
+) -> Vec<ForbiddenUser> {
+	let users = self.forbidden_users().await;
+	for user in &users {
+		let patterns = user.patterns.join(", ");
+		match action {
+			| _ if user.is_admin => warn!(
+				"Admin {} matches the following forbidden username patterns: {patterns}",
+				user.user_id
+			),
+			| ForbiddenUsernameAction::Warn => warn!(
+				"User {} matches the following forbidden username patterns: {patterns}",
+				user.user_id
+			),
+			| ForbiddenUsernameAction::Lock => {
+				warn!(
+					"Locking {} matching forbidden username patterns: {patterns}",
+					user.user_id
+				);
+				self.set_locked(&user.user_id, true);
+			},
+			| ForbiddenUsernameAction::Deactivate => {
+				warn!(
+					"Locking {} matching forbidden username patterns until deactivated: \
+					 {patterns}",
+					user.user_id
+				);
+				self.set_locked(&user.user_id, true);
+			},
+		}
+	}
+
+	users
+}
@@ -0,0 +1,117 @@
+use conduwuit::{config::InvitePolicy, implement};
+use database::Deserialized;
+use itertools::Itertools;
+use regex::Regex;
+use ruma::UserId;
+use serde::{Deserialize, Serialize};
+
+/// Account data type users configure their invite permissions with (MSC4155).
+pub const INVITE_PERMISSION_CONFIG: &str = "org.matrix.msc4155.invite_permission_config";
+
+/// Invite permissions of a user (MSC4155). Entries are globs of user IDs or
+/// server names. User entries take precedence over server entries; within
+/// each, allowed entries take precedence over ignored ones, which take
+/// precedence over blocked ones. For example, `"blocked_servers": ["*"]`
+/// with some `allowed_users` only permits invites from those users.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InvitePermissionConfig {
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub allowed_users: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub ignored_users: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub blocked_users: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub allowed_servers: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub ignored_servers: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub blocked_servers: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct InvitePermissionConfigEvent {
+	content: InvitePermissionConfig,
+}
+
+/// Whether an invite reaches its recipient.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InvitePermission {
+	/// The invite is delivered
+	Allow,
+
+	/// The invite is silently dropped
+	Ignore,
+
+	/// The invite is rejected with an error
+	Block,
+}
+
+/// Decide whether an invite from `sender` reaches the local user `recipient`,
+/// based on the recipient's invite permission config, or the server's
+/// `default_invite_permission` if they have none.
+#[implement(super::Service)]
+pub async fn invite_permission(&self, sender: &UserId, recipient: &UserId) -> InvitePermission {
+	// the server user invites admins to the admin room
+	if sender == self.services.globals.server_user {
+		return InvitePermission::Allow;
+	}
+
+	if let Ok(event) = self
+		.services
+		.account_data
+		.get_raw(None, recipient, INVITE_PERMISSION_CONFIG)
+		.await
+		.deserialized::<InvitePermissionConfigEvent>()
+	{
+		return event.content.permission(sender);
+	}
+
+	match self.services.server.config.default_invite_permission {
+		| InvitePolicy::Allow => InvitePermission::Allow,
+		| InvitePolicy::BlockRemote if self.services.globals.user_is_local(sender) =>
+			InvitePermission::Allow,
+		| InvitePolicy::BlockRemote | InvitePolicy::Block => InvitePermission::Block,
+	}
+}
+
+impl InvitePermissionConfig {
+	#[must_use]
+	pub fn permission(&self, sender: &UserId) -> InvitePermission {
+		let user = sender.as_str();
+		let server = sender.server_name().as_str();
+
+		[
+			(&self.allowed_users, user, InvitePermission::Allow),
+			(&self.ignored_users, user, InvitePermission::Ignore),
+			(&self.blocked_users, user, InvitePermission::Block),
+			(&self.allowed_servers, server, InvitePermission::Allow),
+			(&self.ignored_servers, server, InvitePermission::Ignore),
+			(&self.blocked_servers, server, InvitePermission::Block),
+		]
+		.into_iter()
+		.find(|(globs, value, _)| globs.iter().any(|glob| glob_matches(glob, value)))
+		.map_or(InvitePermission::Allow, |(.., permission)| permission)
+	}
+}
+
+/// Whether `value` matches `glob`, in which `*` matches any number of
+/// characters and `?` any single character.
+pub fn glob_matches(glob: &str, value: &str) -> bool {
+	glob_regex(glob).is_some_and(|regex| regex.is_match(value))
+}
+
+/// Compile `glob` for matching many values; see [`glob_matches`].
+pub(crate) fn glob_regex(glob: &str) -> Option<Regex> {
+	let pattern = glob
+		.split('*')
+		.map(|part| part.split('?').map(regex::escape).join("."))
+		.join(".*");
+
+	Regex::new(&format!("^{pattern}$")).ok()
+}
@@ -1,8 +1,29 @@
-use std::{collections::BTreeMap, mem, sync::Arc};
+mod account_validity;
+mod forbidden_usernames;
+mod invite_permission;
+mod password_policy;
+mod provision;
+mod stale_devices;
+#[cfg(test)]
+mod tests;
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	mem,
+	net::IpAddr,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
+};
 
+use async_trait::async_trait;
 use conduwuit::{
-	Err, Error, Result, Server, at, debug_warn, err, trace,
+	Err, Error, Result, Server, at, debug_warn, err,
+	http::StatusCode,
+	info, trace,
 	utils::{self, ReadyExt, stream::TryIgnore, string::Unquoted},
+	warn,
 };
 use database::{Deserialized, Ignore, Interfix, Json, Map};
 use futures::{Stream, StreamExt, TryFutureExt};
@@ -17,19 +38,45 @@
 	serde::Raw,
 };
 use serde_json::json;
+use tokio::{
+	sync::Notify,
+	time::{Instant, MissedTickBehavior, interval, interval_at},
+};
 
-use crate::{Dep, account_data, admin, globals, rooms};
+pub(crate) use self::invite_permission::glob_regex;
+use self::stale_devices::LastSeen;
+pub use self::{
+	account_validity::RENEW_PATH,
+	forbidden_usernames::ForbiddenUser,
+	invite_permission::{
+		INVITE_PERMISSION_CONFIG, InvitePermission, InvitePermissionConfig, glob_matches,
+	},
+	password_policy::PASSWORD_POLICY_CAPABILITY,
+};
+use crate::{
+	Dep, account_data, admin, appservice, auto_join, email, globals, maintenance, rooms,
+};
 
 pub struct Service {
+	password_denylist: HashSet<String>,
+
+	/// Number of users created since startup, so lists of users can be
+	/// cached until it changes
+	created: AtomicU64,
 	services: Services,
 	db: Data,
+	interrupt: Notify,
 }
 
 struct Services {
 	server: Arc<Server>,
 	account_data: Dep<account_data::Service>,
 	admin: Dep<admin::Service>,
+	appservice: Dep<appservice::Service>,
+	auto_join: Dep<auto_join::Service>,
+	email: Dep<email::Service>,
 	globals: Dep<globals::Service>,
+	maintenance: Dep<maintenance::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 }
@@ -41,30 +88,47 @@ struct Data {
 	openidtoken_expiresatuserid: Arc<Map>,
 	logintoken_expiresatuserid: Arc<Map>,
 	todeviceid_events: Arc<Map>,
+	renewaltoken_userid: Arc<Map>,
 	token_userdeviceid: Arc<Map>,
+	userdeviceid_createdts: Arc<Map>,
+	userdeviceid_lastseen: Arc<Map>,
 	userdeviceid_metadata: Arc<Map>,
 	userdeviceid_token: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
+	userid_accountvalidity: Arc<Map>,
 	userid_avatarurl: Arc<Map>,
 	userid_blurhash: Arc<Map>,
 	userid_devicelistversion: Arc<Map>,
 	userid_displayname: Arc<Map>,
 	userid_lastonetimekeyupdate: Arc<Map>,
+	userid_guest: Arc<Map>,
+	userid_locked: Arc<Map>,
 	userid_masterkeyid: Arc<Map>,
 	userid_password: Arc<Map>,
+	userid_pendingapproval: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
 }
 
+/// Minimum time between writes of a device's last seen time
+const LAST_SEEN_RESOLUTION_MS: u64 = 5 * 60 * 1000;
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			password_denylist: password_policy::denylist(&args.server.config.password_policy)?,
+			created: AtomicU64::new(0),
 			services: Services {
 				server: args.server.clone(),
 				account_data: args.depend::<account_data::Service>("account_data"),
 				admin: args.depend::<admin::Service>("admin"),
+				appservice: args.depend::<appservice::Service>("appservice"),
+				auto_join: args.depend::<auto_join::Service>("auto_join"),
+				email: args.depend::<email::Service>("email"),
 				globals: args.depend::<globals::Service>("globals"),
+				maintenance: args.depend::<maintenance::Service>("maintenance"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
@@ -76,24 +140,77 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				openidtoken_expiresatuserid: args.db["openidtoken_expiresatuserid"].clone(),
 				logintoken_expiresatuserid: args.db["logintoken_expiresatuserid"].clone(),
 				todeviceid_events: args.db["todeviceid_events"].clone(),
+				renewaltoken_userid: args.db["renewaltoken_userid"].clone(),
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
+				userdeviceid_createdts: args.db["userdeviceid_createdts"].clone(),
+				userdeviceid_lastseen: args.db["userdeviceid_lastseen"].clone(),
 				userdeviceid_metadata: args.db["userdeviceid_metadata"].clone(),
 				userdeviceid_token: args.db["userdeviceid_token"].clone(),
 				userfilterid_filter: args.db["userfilterid_filter"].clone(),
+				userid_accountvalidity: args.db["userid_accountvalidity"].clone(),
 				userid_avatarurl: args.db["userid_avatarurl"].clone(),
 				userid_blurhash: args.db["userid_blurhash"].clone(),
 				userid_devicelistversion: args.db["userid_devicelistversion"].clone(),
 				userid_displayname: args.db["userid_displayname"].clone(),
 				userid_lastonetimekeyupdate: args.db["userid_lastonetimekeyupdate"].clone(),
+				userid_guest: args.db["userid_guest"].clone(),
+				userid_locked: args.db["userid_locked"].clone(),
 				userid_masterkeyid: args.db["userid_masterkeyid"].clone(),
 				userid_password: args.db["userid_password"].clone(),
+				userid_pendingapproval: args.db["userid_pendingapproval"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
 			},
+			interrupt: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let config = &self.services.server.config;
+		let stale_devices_enabled = config.stale_device_days > 0;
+		let renewals_enabled = config.account_validity.period_days > 0;
+
+		// devices are first checked a full interval after startup, so those in use
+		// had a chance to be seen
+		let period = stale_devices::STALE_DEVICE_INTERVAL;
+		let start = Instant::now()
+			.checked_add(period)
+			.unwrap_or_else(Instant::now);
+		let mut stale_devices = interval_at(start, period);
+		stale_devices.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		let mut renewals = interval(account_validity::RENEWAL_INTERVAL);
+		renewals.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = stale_devices.tick(), if stale_devices_enabled => {
+					tokio::select! {
+						() = self.interrupt.notified() => break,
+						() = self.services.maintenance.defer("stale device cleanup") => (),
+					}
+
+					match self.delete_stale_devices().await {
+						| Ok(0) => {},
+						| Ok(deleted) => info!("Deleted {deleted} stale devices"),
+						| Err(e) => warn!("Failed to delete stale devices: {e}"),
+					}
+				},
+				_ = renewals.tick(), if renewals_enabled => {
+					match self.send_renewal_emails().await {
+						| Ok(0) => {},
+						| Ok(sent) => info!("Sent {sent} account renewal emails"),
+						| Err(e) => warn!("Failed to send account renewal emails: {e}"),
+					}
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -123,9 +240,17 @@ pub async fn is_admin(&self, user_id: &UserId) -> bool {
 	/// Create a new user account on this homeserver.
 	#[inline]
 	pub fn create(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
-		self.set_password(user_id, password)
+		self.set_password(user_id, password)?;
+		self.created.fetch_add(1, Ordering::Relaxed);
+
+		Ok(())
 	}
 
+	/// Number of users created since startup. A list of users derived from
+	/// all users is current as long as this doesn't change.
+	#[inline]
+	pub fn created_count(&self) -> u64 { self.created.load(Ordering::Relaxed) }
+
 	/// Deactivate account
 	pub async fn deactivate_account(&self, user_id: &UserId) -> Result<()> {
 		// Remove all associated devices
@@ -159,6 +284,50 @@ pub async fn is_deactivated(&self, user_id: &UserId) -> Result<bool> {
 			.await
 	}
 
+	/// Mark an account as awaiting approval by an admin, or approve it
+	/// (MSC3866).
+	pub fn set_pending_approval(&self, user_id: &UserId, pending: bool) {
+		if pending {
+			self.db.userid_pendingapproval.insert(user_id, []);
+		} else {
+			self.db.userid_pendingapproval.remove(user_id);
+		}
+	}
+
+	/// Check if account is awaiting approval by an admin
+	pub async fn is_pending_approval(&self, user_id: &UserId) -> bool {
+		self.db.userid_pendingapproval.get(user_id).await.is_ok()
+	}
+
+	/// Returns the accounts awaiting approval by an admin
+	pub fn list_pending_approval(&self) -> impl Stream<Item = &UserId> + Send {
+		self.db.userid_pendingapproval.keys().ignore_err()
+	}
+
+	/// Lock an account, or unlock it (MSC3939). Locked users keep their
+	/// sessions but can't use them until they are unlocked.
+	pub fn set_locked(&self, user_id: &UserId, locked: bool) {
+		if locked {
+			self.db.userid_locked.insert(user_id, []);
+		} else {
+			self.db.userid_locked.remove(user_id);
+		}
+	}
+
+	/// Check if account is locked
+	pub async fn is_locked(&self, user_id: &UserId) -> bool {
+		self.db.userid_locked.get(user_id).await.is_ok()
+	}
+
+	/// Mark an account as a guest account. Guests may only use a subset of
+	/// the client API.
+	pub fn mark_as_guest(&self, user_id: &UserId) { self.db.userid_guest.insert(user_id, []); }
+
+	/// Check if account is a guest account
+	pub async fn is_guest(&self, user_id: &UserId) -> bool {
+		self.db.userid_guest.get(user_id).await.is_ok()
+	}
+
 	/// Check if account is active, infallible
 	pub async fn is_active(&self, user_id: &UserId) -> bool {
 		!self.is_deactivated(user_id).await.unwrap_or(true)
@@ -223,6 +392,12 @@ pub fn set_password(&self, user_id: &UserId, password: Option<&str>) -> Result<(
 		Ok(())
 	}
 
+	/// Set a password hash produced elsewhere, such as one imported from
+	/// another homeserver; Argon2 and bcrypt hashes are verified at login.
+	pub fn set_password_hash(&self, user_id: &UserId, password_hash: &str) {
+		self.db.userid_password.insert(user_id, password_hash);
+	}
+
 	/// Returns the displayname of a user on this homeserver.
 	pub async fn displayname(&self, user_id: &UserId) -> Result<String> {
 		self.db.userid_displayname.get(user_id).await.deserialized()
@@ -285,15 +460,19 @@ pub async fn create_device(
 		}
 
 		let key = (user_id, device_id);
+		let now = MilliSecondsSinceUnixEpoch::now();
 		let val = Device {
 			device_id: device_id.into(),
 			display_name: initial_device_display_name,
 			last_seen_ip: client_ip,
-			last_seen_ts: Some(MilliSecondsSinceUnixEpoch::now()),
+			last_seen_ts: Some(now),
 		};
 
 		increment(&self.db.userid_devicelistversion, user_id.as_bytes());
 		self.db.userdeviceid_metadata.put(key, Json(val));
+		self.db
+			.userdeviceid_createdts
+			.put(key, u64::from(now.get()));
 		self.set_token(user_id, device_id, token).await
 	}
 
@@ -321,6 +500,8 @@ pub async fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) {
 		increment(&self.db.userid_devicelistversion, user_id.as_bytes());
 
 		self.db.userdeviceid_metadata.del(userdeviceid);
+		self.db.userdeviceid_createdts.del(userdeviceid);
+		self.db.userdeviceid_lastseen.del(userdeviceid);
 		self.mark_device_key_update(user_id).await;
 	}
 
@@ -673,6 +854,23 @@ fn keys_changed_user_or_room<'a>(
 			.map(|((_, count), user_id): KeyVal<'_>| (user_id, count))
 	}
 
+	/// Removes markers of device list changes made before the count `before`.
+	/// Returns the number removed.
+	pub async fn prune_keys_changed(&self, before: u64) -> usize {
+		type Key<'a> = (&'a str, u64);
+
+		self.db
+			.keychangeid_userid
+			.keys()
+			.ignore_err()
+			.ready_filter(|&(_, count): &Key<'_>| count < before)
+			.ready_fold(0_usize, |pruned, key: Key<'_>| {
+				self.db.keychangeid_userid.del(key);
+				pruned.saturating_add(1)
+			})
+			.await
+	}
+
 	pub async fn mark_device_key_update(&self, user_id: &UserId) {
 		let count = self.services.globals.next_count().unwrap();
 
@@ -764,6 +962,11 @@ pub async fn add_to_device_event(
 		event_type: &str,
 		content: serde_json::Value,
 	) {
+		// Users never receive to-device events from users they ignore
+		if self.user_is_ignored(sender, target_user_id).await {
+			return;
+		}
+
 		let count = self.services.globals.next_count().unwrap();
 
 		let key = (target_user_id, target_device_id, count);
@@ -839,17 +1042,95 @@ pub async fn update_device_metadata(
 		Ok(())
 	}
 
+	/// Record a request made by a device as its last seen time and IP. The
+	/// time is only written when it is older than a few minutes or the IP
+	/// changed, so not every request costs a write.
+	pub async fn update_device_last_seen(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		ip: Option<IpAddr>,
+	) {
+		let key = (user_id, device_id);
+		let last_seen: Option<LastSeen> = self
+			.db
+			.userdeviceid_lastseen
+			.qry(&key)
+			.await
+			.deserialized()
+			.ok();
+
+		let now = MilliSecondsSinceUnixEpoch::now();
+		let ip = ip.map(|ip| ip.to_string());
+		let unchanged = last_seen.as_ref().is_some_and(|seen| {
+			u64::from(now.get()).saturating_sub(seen.ts.get().into()) < LAST_SEEN_RESOLUTION_MS
+				&& (ip.is_none() || seen.ip == ip)
+		});
+
+		if unchanged || self.db.userdeviceid_metadata.qry(&key).await.is_err() {
+			return;
+		}
+
+		let ip = ip.or_else(|| last_seen.and_then(|seen| seen.ip));
+		self.db
+			.userdeviceid_lastseen
+			.put(key, Json(LastSeen { ts: now, ip }));
+	}
+
+	/// The device with the last time and IP it was seen at, which are kept
+	/// apart from the rest of its metadata.
+	async fn with_last_seen(&self, user_id: &UserId, mut device: Device) -> Device {
+		let Ok(seen) = self
+			.db
+			.userdeviceid_lastseen
+			.qry(&(user_id, &device.device_id))
+			.await
+			.deserialized::<LastSeen>()
+		else {
+			return device;
+		};
+
+		if device.last_seen_ts.is_none_or(|ts| ts <= seen.ts) {
+			device.last_seen_ts = Some(seen.ts);
+			if seen.ip.is_some() {
+				device.last_seen_ip = seen.ip;
+			}
+		}
+
+		device
+	}
+
 	/// Get device metadata.
 	pub async fn get_device_metadata(
 		&self,
 		user_id: &UserId,
 		device_id: &DeviceId,
 	) -> Result<Device> {
-		self.db
+		let device = self
+			.db
 			.userdeviceid_metadata
 			.qry(&(user_id, device_id))
 			.await
-			.deserialized()
+			.deserialized()?;
+
+		Ok(self.with_last_seen(user_id, device).await)
+	}
+
+	/// Get the time a device was created. Devices created before this was
+	/// recorded have none.
+	pub async fn get_device_created(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+	) -> Result<MilliSecondsSinceUnixEpoch> {
+		let created: u64 = self
+			.db
+			.userdeviceid_createdts
+			.qry(&(user_id, device_id))
+			.await
+			.deserialized()?;
+
+		Ok(MilliSecondsSinceUnixEpoch(UInt::new_saturating(created)))
 	}
 
 	pub async fn get_devicelist_version(&self, user_id: &UserId) -> Result<u64> {
@@ -870,6 +1151,7 @@ pub fn all_devices_metadata<'a>(
 			.stream_prefix(&key)
 			.ignore_err()
 			.map(|(_, val): (Ignore, Device)| val)
+			.then(move |device| self.with_last_seen(user_id, device))
 	}
 
 	/// Creates a new sync filter. Returns the filter id.
@@ -1125,3 +1407,18 @@ fn increment(db: &Arc<Map>, key: &[u8]) {
 	let new = utils::increment(old.ok().as_deref());
 	db.insert(key, new);
 }
+
+/// Error returned to accounts awaiting approval. Ruma has no variant for
+/// `M_USER_AWAITING_APPROVAL` (MSC3866) so the code is deserialized into
+/// a custom error kind.
+#[must_use]
+pub fn awaiting_approval_error() -> Error {
+	let kind = serde_json::from_value(json!({ "errcode": "M_USER_AWAITING_APPROVAL" }))
+		.unwrap_or_else(|_| ErrorKind::forbidden());
+
+	Error::Request(
+		kind,
+		"Your account is awaiting approval by an administrator.".into(),
+		StatusCode::FORBIDDEN,
+	)
+}
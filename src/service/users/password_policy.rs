@@ -0,0 +1,164 @@
+use std::{collections::HashSet, fs};
+
+use conduwuit::{Err, Result, config::PasswordPolicyConfig, err, implement};
+use ruma::UserId;
+use serde_json::{Value as JsonValue, json};
+
+/// Capability the password policy is advertised with (MSC2000).
+pub const PASSWORD_POLICY_CAPABILITY: &str = "org.matrix.msc2000.password_policy";
+
+/// Commonly used passwords rejected when `deny_common` is enabled.
+const COMMON_PASSWORDS: &[&str] = &[
+	"000000",
+	"111111",
+	"112233",
+	"121212",
+	"123123",
+	"123321",
+	"1234",
+	"12345",
+	"123456",
+	"1234567",
+	"12345678",
+	"123456789",
+	"1234567890",
+	"123qwe",
+	"1q2w3e",
+	"1q2w3e4r",
+	"1q2w3e4r5t",
+	"654321",
+	"666666",
+	"696969",
+	"7777777",
+	"987654321",
+	"aa123456",
+	"abc123",
+	"access",
+	"admin",
+	"admin123",
+	"baseball",
+	"batman",
+	"charlie",
+	"dragon",
+	"football",
+	"freedom",
+	"hello",
+	"iloveyou",
+	"letmein",
+	"login",
+	"master",
+	"matrix",
+	"monkey",
+	"mustang",
+	"passw0rd",
+	"password",
+	"password1",
+	"password123",
+	"princess",
+	"qazwsx",
+	"qwerty",
+	"qwerty123",
+	"qwertyuiop",
+	"shadow",
+	"solo",
+	"starwars",
+	"sunshine",
+	"superman",
+	"trustno1",
+	"welcome",
+	"whatever",
+	"zaq12wsx",
+];
+
+/// Load the passwords rejected by the policy.
+pub(super) fn denylist(config: &PasswordPolicyConfig) -> Result<HashSet<String>> {
+	let mut denylist = HashSet::new();
+	if !config.enable {
+		return Ok(denylist);
+	}
+
+	if config.deny_common {
+		denylist.extend(COMMON_PASSWORDS.iter().map(ToString::to_string));
+	}
+
+	if let Some(path) = &config.denylist_file {
+		let file = fs::read_to_string(path).map_err(|e| {
+			err!(Config("password_policy.denylist_file", "Failed to read {path:?}: {e}"))
+		})?;
+
+		denylist.extend(
+			file.lines()
+				.map(str::trim)
+				.filter(|line| !line.is_empty())
+				.map(str::to_lowercase),
+		);
+	}
+
+	Ok(denylist)
+}
+
+/// Check a new password of `user_id` against the password policy.
+#[implement(super::Service)]
+pub fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result {
+	let config = &self.services.server.config.password_policy;
+	if !config.enable {
+		return Ok(());
+	}
+
+	if password.chars().count() < config.minimum_length {
+		return Err!(Request(WeakPassword(
+			"Password must be at least {} characters long.",
+			config.minimum_length
+		)));
+	}
+
+	if config.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+		return Err!(Request(WeakPassword("Password must contain a digit.")));
+	}
+
+	if config.require_symbol && password.chars().all(char::is_alphanumeric) {
+		return Err!(Request(WeakPassword("Password must contain a symbol.")));
+	}
+
+	if config.require_lowercase && !password.chars().any(char::is_lowercase) {
+		return Err!(Request(WeakPassword("Password must contain a lowercase letter.")));
+	}
+
+	if config.require_uppercase && !password.chars().any(char::is_uppercase) {
+		return Err!(Request(WeakPassword("Password must contain an uppercase letter.")));
+	}
+
+	if self.password_denylist.contains(&password.to_lowercase()) {
+		return Err!(Request(WeakPassword("Password is too common.")));
+	}
+
+	if let Some(minimum_score) = config.minimum_zxcvbn_score {
+		let inputs = [user_id.localpart(), user_id.server_name().as_str()];
+		let score = zxcvbn::zxcvbn(password, &inputs)
+			.map_err(|e| err!(Request(WeakPassword("Password was rejected: {e}"))))?
+			.score();
+
+		if score < minimum_score {
+			return Err!(Request(WeakPassword("Password is too easy to guess.")));
+		}
+	}
+
+	Ok(())
+}
+
+/// The password policy advertised to clients (MSC2000), if enabled.
+#[implement(super::Service)]
+#[must_use]
+pub fn password_policy(&self) -> Option<JsonValue> {
+	let config = &self.services.server.config.password_policy;
+
+	config.enable.then(|| {
+		json!({
+			"m.minimum_length": config.minimum_length,
+			"m.require_digit": config.require_digit,
+			"m.require_symbol": config.require_symbol,
+			"m.require_lowercase": config.require_lowercase,
+			"m.require_uppercase": config.require_uppercase,
+		})
+	})
+}
@@ -0,0 +1,90 @@
+//! Provisioning
+//!
+//! Accounts created on their first login through JWT, LDAP or SSO are subject
+//! to the same policy as registered ones: forbidden usernames, approval
+//! (MSC3866), account validity, `auto_join_rooms` and the admin room notice.
+
+use conduwuit::{Result, implement, info, utils};
+use ruma::{
+	UserId,
+	events::{
+		GlobalAccountDataEventType,
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		room::message::RoomMessageEventContent,
+	},
+	push,
+};
+
+/// Length of the unusable password set on provisioned users; an empty
+/// password marks a deactivated account
+const PASSWORD_LENGTH: usize = 32;
+
+/// Create a local user logging in through `source` for the first time, with
+/// an unusable password. Errors when the user has to be approved first, in
+/// which case the account is created pending approval.
+#[implement(super::Service)]
+pub async fn provision(
+	&self,
+	user_id: &UserId,
+	displayname: Option<String>,
+	source: &str,
+) -> Result {
+	self.check_forbidden_username(user_id)?;
+
+	info!(%user_id, "Registering user from {source}");
+	self.create(user_id, Some(&utils::random_string(PASSWORD_LENGTH)))?;
+	self.set_displayname(
+		user_id,
+		Some(displayname.unwrap_or_else(|| user_id.localpart().to_owned())),
+	);
+
+	self.services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(PushRulesEvent {
+				content: PushRulesEventContent {
+					global: push::Ruleset::server_default(user_id),
+				},
+			})?,
+		)
+		.await?;
+
+	if self.account_validity_enabled() {
+		self.start_account_validity(user_id);
+	}
+
+	let config = &self.services.server.config;
+	if config.registration_requires_approval {
+		self.set_pending_approval(user_id, true);
+		self.services
+			.admin
+			.send_message(RoomMessageEventContent::notice_markdown(format!(
+				"New user {user_id} registered on this server through {source} and is awaiting \
+				 approval. Approve them with `!admin users approve {user_id}` or reject them \
+				 with `!admin users deny {user_id}`."
+			)))
+			.await
+			.ok();
+
+		return Err(super::awaiting_approval_error());
+	}
+
+	if config.admin_room_notices {
+		self.services
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"New user \"{user_id}\" registered on this server through {source}"
+			)))
+			.await
+			.ok();
+	}
+
+	if !config.auto_join_rooms.is_empty() {
+		self.services.auto_join.join(user_id).await;
+	}
+
+	Ok(())
+}
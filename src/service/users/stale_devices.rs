@@ -0,0 +1,140 @@
+//! Stale device cleanup
+//!
+//! Devices which have not been seen for `stale_device_days` are deleted
+//! periodically as if they logged out: their access token is revoked and the
+//! device list of their user changes, so they drop out of key queries. Only
+//! time the server was running counts, so devices do not go stale while it
+//! is down.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use conduwuit::{Result, debug, implement, utils::millis_since_unix_epoch};
+use database::Json;
+use futures::StreamExt;
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedUserId, UserId, api::client::device::Device,
+	encryption::DeviceKeys, serde::Raw,
+};
+use serde::{Deserialize, Serialize};
+
+pub(super) const STALE_DEVICE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Last time and IP a device was seen at, kept apart from the rest of its
+/// metadata so recording them does not race with changes to the device.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(super) struct LastSeen {
+	pub(super) ts: MilliSecondsSinceUnixEpoch,
+	pub(super) ip: Option<String>,
+}
+
+/// Delete the devices of local users not seen for `stale_device_days`,
+/// except exempt ones. Returns the number of deleted devices.
+#[implement(super::Service)]
+pub async fn delete_stale_devices(&self) -> Result<usize> {
+	let days = self.services.server.config.stale_device_days;
+	if days == 0 {
+		return Ok(0);
+	}
+
+	let now = millis_since_unix_epoch();
+	let started = self
+		.services
+		.server
+		.started
+		.duration_since(UNIX_EPOCH)
+		.ok()
+		.and_then(|started| u64::try_from(started.as_millis()).ok())
+		.unwrap_or(now);
+
+	let users: Vec<OwnedUserId> = self.stream().map(ToOwned::to_owned).collect().await;
+
+	let mut deleted: usize = 0;
+	for user_id in users {
+		if self.stale_device_exempt_user(&user_id).await {
+			continue;
+		}
+
+		let devices: Vec<Device> = self.all_devices_metadata(&user_id).collect().await;
+		for device in devices {
+			let last_seen = device.last_seen_ts.map(|ts| ts.get().into());
+			if !is_stale(last_seen, started, now, days)
+				|| self.stale_device_exempt_dehydrated(&user_id, &device).await
+			{
+				continue;
+			}
+
+			debug!(%user_id, device_id = %device.device_id, "Deleting stale device");
+			self.remove_device(&user_id, &device.device_id).await;
+			deleted = deleted.saturating_add(1);
+		}
+	}
+
+	Ok(deleted)
+}
+
+/// Count every device as seen now. Devices were only marked seen when
+/// created or renamed before the last seen time was tracked, so devices in
+/// use would otherwise be deleted as stale.
+#[implement(super::Service)]
+pub async fn mark_all_devices_seen(&self) {
+	let now = MilliSecondsSinceUnixEpoch::now();
+	let users: Vec<OwnedUserId> = self.stream().map(ToOwned::to_owned).collect().await;
+	for user_id in users {
+		let devices: Vec<Device> = self.all_devices_metadata(&user_id).collect().await;
+		for device in devices {
+			self.db
+				.userdeviceid_lastseen
+				.put((&user_id, &device.device_id), Json(seen_at_migration(&device, now)));
+		}
+	}
+}
+
+#[implement(super::Service)]
+async fn stale_device_exempt_user(&self, user_id: &UserId) -> bool {
+	let config = &self.services.server.config;
+	if config.stale_device_exempt_users.is_match(user_id.as_str()) {
+		return true;
+	}
+
+	config.stale_device_exempt_appservice_users
+		&& self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.any(|info| info.is_user_match(user_id))
+}
+
+/// Whether the device is dehydrated (MSC3814) and exempt from the cleanup.
+#[implement(super::Service)]
+async fn stale_device_exempt_dehydrated(&self, user_id: &UserId, device: &Device) -> bool {
+	self.services.server.config.stale_device_exempt_dehydrated
+		&& self
+			.get_device_keys(user_id, &device.device_id)
+			.await
+			.is_ok_and(|keys| is_dehydrated(&keys))
+}
+
+/// Whether a device last seen at `last_seen` has not been seen for `days` of
+/// the server running since `started`, at `now`. Devices never seen are not.
+pub(super) fn is_stale(last_seen: Option<u64>, started: u64, now: u64, days: u64) -> bool {
+	last_seen.is_some_and(|last_seen| {
+		days > 0 && now.saturating_sub(last_seen.max(started)) >= days.saturating_mul(DAY_MS)
+	})
+}
+
+/// Whether the device keys mark the device as dehydrated.
+pub(super) fn is_dehydrated(keys: &Raw<DeviceKeys>) -> bool {
+	matches!(keys.get_field::<bool>("dehydrated"), Ok(Some(true)))
+}
+
+/// When the migration counts a device as seen, keeping its IP.
+pub(super) fn seen_at_migration(device: &Device, now: MilliSecondsSinceUnixEpoch) -> LastSeen {
+	LastSeen {
+		ts: device.last_seen_ts.map_or(now, |ts| ts.max(now)),
+		ip: device.last_seen_ip.clone(),
+	}
+}
@@ -0,0 +1,109 @@
+use conduwuit::config::InvitePolicy;
+use ruma::{
+	MilliSecondsSinceUnixEpoch, UInt, api::client::device::Device, device_id, serde::Raw, user_id,
+};
+use serde_json::json;
+
+use super::{
+	InvitePermission, InvitePermissionConfig, glob_matches,
+	stale_devices::{LastSeen, is_dehydrated, is_stale, seen_at_migration},
+};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[test]
+fn glob_matches_literals_exactly() {
+	assert!(glob_matches("@spammer:example.com", "@spammer:example.com"));
+	assert!(!glob_matches("@spammer:example.com", "@spammer:example.com.evil"));
+	assert!(!glob_matches("@spammer:example.com", "@spammerX:example.com"));
+	assert!(!glob_matches("example.com", "exampleXcom"));
+}
+
+#[test]
+fn glob_matches_wildcards() {
+	assert!(glob_matches("*.evil.org", "a.evil.org"));
+	assert!(glob_matches("*.evil.org", "a.b.evil.org"));
+	assert!(!glob_matches("*.evil.org", "evil.org"));
+	assert!(glob_matches("@bot?:example.com", "@bot1:example.com"));
+	assert!(!glob_matches("@bot?:example.com", "@bot12:example.com"));
+}
+
+#[test]
+fn invites_are_allowed_by_default() {
+	assert_eq!(InvitePolicy::default(), InvitePolicy::Allow);
+
+	let config = InvitePermissionConfig::default();
+	assert_eq!(config.permission(user_id!("@alice:remote.example")), InvitePermission::Allow);
+}
+
+#[test]
+fn invite_permission_precedence() {
+	let config = InvitePermissionConfig {
+		allowed_users: vec!["@friend:evil.org".to_owned()],
+		ignored_users: vec!["@*:noisy.example".to_owned()],
+		blocked_servers: vec!["*".to_owned()],
+		..Default::default()
+	};
+
+	assert_eq!(config.permission(user_id!("@friend:evil.org")), InvitePermission::Allow);
+	assert_eq!(config.permission(user_id!("@bot:noisy.example")), InvitePermission::Ignore);
+	assert_eq!(config.permission(user_id!("@spammer:evil.org")), InvitePermission::Block);
+}
+
+#[test]
+fn stale_devices_are_unseen_for_the_whole_period() {
+	let now = 100 * DAY_MS;
+	let started = 10 * DAY_MS;
+
+	assert!(is_stale(Some(50 * DAY_MS), started, now, 30));
+	assert!(!is_stale(Some(80 * DAY_MS), started, now, 30));
+	assert!(!is_stale(None, started, now, 30));
+	assert!(!is_stale(Some(0), started, now, 0));
+}
+
+#[test]
+fn stale_devices_only_count_uptime() {
+	let now = 100 * DAY_MS;
+
+	// last seen long ago, but the server only started recently
+	assert!(!is_stale(Some(DAY_MS), 80 * DAY_MS, now, 30));
+	assert!(is_stale(Some(DAY_MS), 70 * DAY_MS, now, 30));
+}
+
+#[test]
+fn dehydrated_devices_are_exempt() {
+	let keys = |keys: serde_json::Value| {
+		Raw::from_json(serde_json::value::to_raw_value(&keys).expect("device keys"))
+	};
+
+	assert!(is_dehydrated(&keys(json!({ "dehydrated": true }))));
+	assert!(!is_dehydrated(&keys(json!({ "dehydrated": false }))));
+	assert!(!is_dehydrated(&keys(json!({ "device_id": "ABCDEF" }))));
+}
+
+#[test]
+fn migration_marks_devices_seen() {
+	let at = |ms: u32| MilliSecondsSinceUnixEpoch(UInt::from(ms));
+	let device = |last_seen_ts, last_seen_ip: Option<&str>| {
+		let mut device = Device::new(device_id!("ABCDEF").to_owned());
+		device.last_seen_ts = last_seen_ts;
+		device.last_seen_ip = last_seen_ip.map(ToOwned::to_owned);
+		device
+	};
+
+	assert_eq!(seen_at_migration(&device(None, None), at(1000)), LastSeen {
+		ts: at(1000),
+		ip: None
+	});
+
+	assert_eq!(
+		seen_at_migration(&device(Some(at(10)), Some("192.0.2.1")), at(1000)),
+		LastSeen {
+			ts: at(1000),
+			ip: Some("192.0.2.1".to_owned())
+		}
+	);
+
+	// clocks going back do not move devices into the past
+	assert_eq!(seen_at_migration(&device(Some(at(2000)), None), at(1000)).ts, at(2000));
+}